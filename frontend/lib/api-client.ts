@@ -1,4 +1,4 @@
-import axios, { AxiosError, AxiosInstance } from 'axios';
+import axios, { AxiosError, AxiosInstance, InternalAxiosRequestConfig } from 'axios';
 
 export interface ApiClientError {
   code: string;
@@ -64,16 +64,57 @@ apiClient.interceptors.request.use((config) => {
   return config;
 });
 
+// Access tokens are short-lived, so one concurrent refresh is shared by every request that hit a 401.
+let pendingRefresh: Promise<string | null> | null = null;
+
+function refreshAccessToken(): Promise<string | null> {
+  const refreshToken = localStorage.getItem('refresh_token');
+  if (!refreshToken) return Promise.resolve(null);
+  if (pendingRefresh) return pendingRefresh;
+  pendingRefresh = axios
+    .post<{ token: string; refresh_token: string }>(
+      `${baseURL}/v1/auth/refresh`,
+      { refresh_token: refreshToken },
+      { timeout: 15000 },
+    )
+    .then(({ data }) => {
+      // Refresh tokens are single-use: the one just sent is revoked and this replaces it.
+      localStorage.setItem('token', data.token);
+      localStorage.setItem('refresh_token', data.refresh_token);
+      return data.token;
+    })
+    .catch(() => null)
+    .finally(() => {
+      pendingRefresh = null;
+    });
+  return pendingRefresh;
+}
+
+type RetriableConfig = InternalAxiosRequestConfig & { _refreshed?: boolean };
+
 apiClient.interceptors.response.use(
   (response) => response,
-  (error: AxiosError) => {
+  async (error: AxiosError) => {
     const normalized = normalizeError(error);
+    const isAuthError = normalized.status === 401 || normalized.code === 'UNAUTHORIZED';
+
+    const original = error.config as RetriableConfig | undefined;
+    if (typeof window !== 'undefined' && isAuthError && original && !original._refreshed) {
+      original._refreshed = true;
+      const token = await refreshAccessToken();
+      if (token) {
+        original.headers.Authorization = `Bearer ${token}`;
+        return apiClient(original);
+      }
+    }
 
-    // If the backend rejects a request due to auth, clear the stale token so the UI
-    // can prompt the user to login again (e.g., after a redeploy that rotates JWT_SECRET).
-    if (typeof window !== 'undefined' && (normalized.status === 401 || normalized.code === 'UNAUTHORIZED')) {
+    // If the backend rejects a request due to auth and the session can't be refreshed, clear the
+    // stale tokens so the UI can prompt the user to login again (e.g., after a redeploy that rotates
+    // JWT_SECRET).
+    if (typeof window !== 'undefined' && isAuthError) {
       try {
         localStorage.removeItem('token');
+        localStorage.removeItem('refresh_token');
         window.dispatchEvent(new Event('starkshield:auth:invalid'));
       } catch {
         // Ignore storage/event failures; the original error will still be surfaced.
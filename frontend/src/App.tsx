@@ -12,6 +12,8 @@ interface LoginResponse {
   success: boolean;
   token: string;
   expires_in_seconds: number;
+  refresh_token: string;
+  refresh_expires_in_seconds: number;
 }
 
 const REQUIRE_LOGIN = (import.meta.env.VITE_REQUIRE_LOGIN as string | undefined)?.toLowerCase() === 'true';
@@ -80,6 +82,7 @@ function App() {
         password,
       });
       localStorage.setItem('token', data.token);
+      localStorage.setItem('refresh_token', data.refresh_token);
       setToken(data.token);
       setPassword('');
     } catch (error) {
@@ -89,8 +92,18 @@ function App() {
     }
   };
 
-  const handleLogout = () => {
+  const handleLogout = async () => {
+    // Revoke the session server-side too, so a copied token stops working; log out locally regardless.
+    const refreshToken = localStorage.getItem('refresh_token');
+    if (localStorage.getItem('token')) {
+      try {
+        await apiClient.post('/v1/auth/logout', refreshToken ? { refresh_token: refreshToken } : {});
+      } catch {
+        // Already expired or revoked, or the solver is unreachable.
+      }
+    }
     localStorage.removeItem('token');
+    localStorage.removeItem('refresh_token');
     setToken(null);
     disconnect();
   };
@@ -439,13 +439,19 @@ export const TradeForm: React.FC = () => {
     setIsSubmitting(true);
     try {
       const submitWithProof = async (proof: any) => {
+        if (!account) {
+          throw new Error('Connect a wallet to sign the intent');
+        }
         await submitIntent({
           proof,
+          account,
           userAddress: address,
           tokenIn: tradeParams.tokenIn,
           tokenOut: tradeParams.tokenOut,
           amountIn: tradeParams.amountIn,
           minAmountOut: tradeParams.minAmountOut,
+          amountInBaseUnits: parseUnits(tradeParams.amountIn, tokenDecimals(tradeParams.tokenIn)).toString(),
+          minAmountOutBaseUnits: parseUnits(tradeParams.minAmountOut, tokenDecimals(tradeParams.tokenOut)).toString(),
           deadline: tradeParams.deadline,
         });
       };
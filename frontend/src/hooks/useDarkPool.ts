@@ -1,6 +1,6 @@
 import { useCallback } from 'react';
 import { apiClient } from '../../lib/api-client';
-import { keccak256, toUtf8Bytes } from 'ethers';
+import { stark, type AccountInterface, type TypedData } from 'starknet';
 
 type IntentStatusValue =
   | 'pending'
@@ -28,11 +28,16 @@ interface ProofOutput {
 
 interface SubmitIntentParams {
   proof: ProofOutput;
+  // Signs the intent; its address must be userAddress.
+  account: AccountInterface;
   userAddress: string;
   tokenIn: string;
   tokenOut: string;
   amountIn: string;
   minAmountOut: string;
+  // The same two amounts in base units (token decimals applied), as the solver hashes them.
+  amountInBaseUnits: string;
+  minAmountOutBaseUnits: string;
   deadline: number;
 }
 
@@ -72,6 +77,11 @@ interface PendingIntentResponse {
   settlement_tx_hash?: string | null;
 }
 
+interface PendingIntentsPage {
+  intents: PendingIntentResponse[];
+  next_cursor?: string | null;
+}
+
 function historyKey(address: string): string {
   return `starkshield:intents:${normalizeAddress(address)}`;
 }
@@ -111,21 +121,75 @@ function toUnixSeconds(iso: string): number {
   return Number.isNaN(millis) ? Math.floor(Date.now() / 1000) : Math.floor(millis / 1000);
 }
 
-export const useDarkPool = () => {
-  const buildIntentSignature = useCallback(
-    (userAddress: string, nullifier: string, nonce: number, deadline: number): string => {
-      const payload = `${userAddress.toLowerCase()}:${nullifier.toLowerCase()}:${nonce}:${deadline}`;
-      return keccak256(toUtf8Bytes(payload));
+// SNIP-12 (revision 0) message the solver checks against the account's is_valid_signature.
+// Must stay in step with intent_message_hash in solver/src/starknet.rs.
+function intentTypedData(message: {
+  user: string;
+  token_in: string;
+  token_out: string;
+  amount_in: string;
+  min_amount_out: string;
+  deadline: number;
+  nonce: number;
+  chain_id: string;
+  domain_separator: string;
+}): TypedData {
+  const felts = (...names: string[]) => names.map((name) => ({ name, type: 'felt' }));
+  return {
+    types: {
+      StarkNetDomain: felts('name', 'version', 'chainId'),
+      Intent: felts(
+        'user',
+        'token_in',
+        'token_out',
+        'amount_in',
+        'min_amount_out',
+        'deadline',
+        'nonce',
+        'chain_id',
+        'domain_separator'
+      ),
     },
-    []
-  );
+    primaryType: 'Intent',
+    domain: { name: 'StarkShield', version: '1', chainId: message.chain_id },
+    message,
+  };
+}
 
+export const useDarkPool = () => {
   const submitIntent = useCallback(async (params: SubmitIntentParams): Promise<void> => {
-    const { proof, userAddress, tokenIn, tokenOut, amountIn, minAmountOut, deadline } = params;
+    const {
+      proof,
+      account,
+      userAddress,
+      tokenIn,
+      tokenOut,
+      amountIn,
+      minAmountOut,
+      amountInBaseUnits,
+      minAmountOutBaseUnits,
+      deadline,
+    } = params;
     const nonce = getNextNonce(userAddress);
     const chainId = import.meta.env.VITE_CHAIN_ID ?? 'SN_SEPOLIA';
     const domainSeparator = import.meta.env.VITE_DOMAIN_SEPARATOR ?? 'starkshield-hackathon';
 
+    const signature = stark.formatSignature(
+      await account.signMessage(
+        intentTypedData({
+          user: userAddress,
+          token_in: tokenIn,
+          token_out: tokenOut,
+          amount_in: amountInBaseUnits,
+          min_amount_out: minAmountOutBaseUnits,
+          deadline,
+          nonce,
+          chain_id: chainId,
+          domain_separator: domainSeparator,
+        })
+      )
+    );
+
     const payload = {
       intent_hash: proof.intent_hash,
       nullifier: proof.nullifier,
@@ -155,12 +219,12 @@ export const useDarkPool = () => {
           minAmountOut,
         })
       ),
-      signature: buildIntentSignature(userAddress, proof.nullifier, nonce, deadline),
+      signature,
     };
 
     await apiClient.post('/v1/intents', payload);
     writeIntentHistory(userAddress, proof.nullifier);
-  }, [buildIntentSignature]);
+  }, []);
 
   const getIntentStatus = useCallback(async (nullifier: string): Promise<string> => {
     const { data } = await apiClient.get<IntentQueryResponse>(`/v1/intents/${nullifier}`);
@@ -193,10 +257,17 @@ export const useDarkPool = () => {
       // Ignore and try pending below.
     }
 
-    const { data } = await apiClient.get<PendingIntentResponse[]>('/v1/intents/pending', {
-      params: { user: userAddress },
-    });
-    return data
+    // The pending book is paged; walk it to the end, keeping this user's intents.
+    const pending: PendingIntentResponse[] = [];
+    let cursor: string | undefined;
+    do {
+      const { data } = await apiClient.get<PendingIntentsPage>('/v1/intents/pending', {
+        params: { user: userAddress, cursor, limit: 500 },
+      });
+      pending.push(...data.intents);
+      cursor = data.next_cursor ?? undefined;
+    } while (cursor);
+    return pending
       .map((intent) => ({
         nullifier: intent.nullifier,
         status: intent.status,
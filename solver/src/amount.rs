@@ -0,0 +1,194 @@
+use num_bigint::BigUint;
+use num_traits::{Num, Zero};
+use starknet::core::types::Felt;
+use std::fmt;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum AmountError {
+    #[error("amount is empty")]
+    Empty,
+    #[error("invalid amount: {0}")]
+    Invalid(String),
+    #[error("too many decimals (max {max}): {value}")]
+    TooManyDecimals { max: u32, value: String },
+    #[error("amount does not fit in a felt: {0}")]
+    Overflow(String),
+}
+
+/// A token amount in base units, parsed once from the string forms clients send.
+///
+/// Accepted inputs:
+/// - decimal token amounts such as `"10"`, `"0.01"` or `"007.50"`, scaled by `10^decimals`
+///   (at most `decimals` fractional digits, never truncated)
+/// - `0x` hex strings, taken as base units already
+///
+/// Negative, empty, exponent and leading-dot (`".5"`) forms are rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenAmount {
+    base_units: BigUint,
+    decimals: u32,
+}
+
+impl TokenAmount {
+    pub fn parse(value: &str, decimals: u32) -> Result<Self, AmountError> {
+        let v = value.trim();
+        if v.is_empty() {
+            return Err(AmountError::Empty);
+        }
+        let invalid = || AmountError::Invalid(v.to_string());
+
+        if let Some(hex) = v.strip_prefix("0x").or_else(|| v.strip_prefix("0X")) {
+            if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+            let base_units = BigUint::from_str_radix(hex, 16).map_err(|_| invalid())?;
+            return Ok(Self { base_units, decimals });
+        }
+
+        let (int_part, frac_part) = v.split_once('.').unwrap_or((v, ""));
+        if int_part.is_empty() || !int_part.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        if !frac_part.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        if frac_part.len() > decimals as usize {
+            return Err(AmountError::TooManyDecimals {
+                max: decimals,
+                value: v.to_string(),
+            });
+        }
+
+        let digits = format!("{}{:0<width$}", int_part, frac_part, width = decimals as usize);
+        let base_units = BigUint::from_str_radix(&digits, 10).map_err(|_| invalid())?;
+        Ok(Self { base_units, decimals })
+    }
+
+    /// `parse` at the decimals of `token` (see `token_decimals_for`), as intent amounts are read.
+    pub fn parse_for_token(value: &str, token: &str) -> Result<Self, AmountError> {
+        Self::parse(value, crate::starknet::token_decimals_for(token))
+    }
+
+    pub fn from_base_units(base_units: BigUint, decimals: u32) -> Self {
+        Self { base_units, decimals }
+    }
+
+    pub fn base_units(&self) -> &BigUint {
+        &self.base_units
+    }
+
+    pub fn into_base_units(self) -> BigUint {
+        self.base_units
+    }
+
+    /// Base units as a single felt, as the DarkPool `public_inputs` layout expects.
+    pub fn to_felt(&self) -> Result<Felt, AmountError> {
+        // felt252 holds anything below the Starknet prime (~2^251); real token amounts are far smaller.
+        if self.base_units.bits() > 251 {
+            return Err(AmountError::Overflow(self.base_units.to_str_radix(10)));
+        }
+        Felt::from_dec_str(&self.base_units.to_str_radix(10))
+            .map_err(|_| AmountError::Overflow(self.base_units.to_str_radix(10)))
+    }
+}
+
+/// Token units with trailing fractional zeros trimmed, e.g. `1500000` at 6 decimals is `1.5`.
+impl fmt::Display for TokenAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.base_units.to_str_radix(10);
+        let decimals = self.decimals as usize;
+        if decimals == 0 || self.base_units.is_zero() {
+            return f.write_str(&digits);
+        }
+        let padded = format!("{:0>width$}", digits, width = decimals + 1);
+        let (int_part, frac_part) = padded.split_at(padded.len() - decimals);
+        let frac_part = frac_part.trim_end_matches('0');
+        if frac_part.is_empty() {
+            write!(f, "{}", int_part)
+        } else {
+            write!(f, "{}.{}", int_part, frac_part)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base(value: &str, decimals: u32) -> BigUint {
+        TokenAmount::parse(value, decimals).expect("parse").into_base_units()
+    }
+
+    #[test]
+    fn parses_decimal_token_amounts() {
+        // 0.01 * 1e18 = 1e16
+        assert_eq!(base("0.01", 18), BigUint::from(10_000_000_000_000_000u64));
+        // 10 * 1e18
+        assert_eq!(base("10", 18), BigUint::from(10_000_000_000_000_000_000u128));
+        // 0.01 * 1e6 = 10000
+        assert_eq!(base("0.01", 6), BigUint::from(10_000u32));
+        // 10 * 1e6
+        assert_eq!(base("10", 6), BigUint::from(10_000_000u32));
+        assert_eq!(base(" 1.5 ", 6), BigUint::from(1_500_000u32));
+        assert_eq!(base("1.", 6), BigUint::from(1_000_000u32));
+        assert_eq!(base("0", 18), BigUint::from(0u8));
+    }
+
+    #[test]
+    fn accepts_leading_zeros() {
+        assert_eq!(base("007.50", 6), BigUint::from(7_500_000u32));
+        assert_eq!(base("0000", 6), BigUint::from(0u8));
+        assert_eq!(base("0.000001", 6), BigUint::from(1u8));
+    }
+
+    #[test]
+    fn enforces_max_fractional_digits() {
+        assert_eq!(base("1.123456", 6), BigUint::from(1_123_456u32));
+        assert_eq!(
+            TokenAmount::parse("1.1234567", 6),
+            Err(AmountError::TooManyDecimals {
+                max: 6,
+                value: "1.1234567".to_string()
+            })
+        );
+        assert!(TokenAmount::parse("1.5", 0).is_err());
+        assert_eq!(base("15", 0), BigUint::from(15u8));
+    }
+
+    #[test]
+    fn treats_hex_as_base_units() {
+        assert_eq!(base("0x2386f26fc10000", 18), BigUint::from(10_000_000_000_000_000u64));
+        assert_eq!(base("0X0a", 6), BigUint::from(10u8));
+        assert!(TokenAmount::parse("0x", 18).is_err());
+        assert!(TokenAmount::parse("0xzz", 18).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_amounts() {
+        for v in ["", "  ", "-1", ".5", "1.2.3", "1e18", "abc", "1,5", "+1"] {
+            assert!(TokenAmount::parse(v, 18).is_err(), "{:?} should be rejected", v);
+        }
+    }
+
+    #[test]
+    fn displays_in_token_units() {
+        let show = |v: &str, d: u32| TokenAmount::parse(v, d).expect("parse").to_string();
+        assert_eq!(show("007.50", 6), "7.5");
+        assert_eq!(show("0.000001", 6), "0.000001");
+        assert_eq!(show("10", 18), "10");
+        assert_eq!(show("0", 6), "0");
+        assert_eq!(show("0x3e8", 0), "1000");
+    }
+
+    #[test]
+    fn to_felt_round_trips_and_rejects_overflow() {
+        let amount = TokenAmount::parse("0.01", 18).expect("parse");
+        assert_eq!(amount.to_felt(), Ok(Felt::from_dec_str("10000000000000000").expect("felt")));
+        let huge = TokenAmount::parse(
+            "0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+            18,
+        )
+        .expect("parse");
+        assert!(matches!(huge.to_felt(), Err(AmountError::Overflow(_))));
+    }
+}
@@ -1,96 +1,152 @@
 use axum::{
-    extract::{Json, Path, Query, State},
-    http::{HeaderMap, StatusCode},
-    response::Json as JsonResponse,
+    extract::{DefaultBodyLimit, Json, Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    middleware,
+    response::{
+        sse::{self, KeepAlive, Sse},
+        IntoResponse, Json as JsonResponse, Response,
+    },
     routing::{get, post},
     Router,
 };
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
-use std::collections::HashMap;
+use tokio::sync::RwLock;
 use tower_http::cors::{Any, CorsLayer};
-use tracing::{debug, error, info, warn};
+use tracing::{error, info, warn};
 
 use crate::{
-    auth::{issue_token, verify_token},
-    config::{ApiConfig, Config},
-    matcher::IntentMatcher,
+    amount::TokenAmount,
+    auth::{JwtClaims, JwtKeyring, TokenType},
+    bundle::{BundleSigner, STATE_BUNDLE_VERSION},
+    config::{ApiConfig, Config, MatchingConfig, PrecheckMode},
+    events::{EventBus, SolverEvent},
+    matcher::{coarsen_quote, is_near_duplicate, parse_trigger_price, IntentMatcher, SearchMetrics, TRIGGER_PRICE_DECIMALS},
     models::*,
+    oracle::{usd_value, ConsensusPrice, CrossRate, OracleError, PragmaOracle, PriceCacheMetrics, SourcePrice},
+    rpc::RpcClient,
+    starknet::{
+        check_proof_input_bindings, decode_cairo_string, intent_message_hash, is_well_formed_signature, parse_u256_json,
+        proof_current_time, submit_intent_calldata, token_decimals_for, token_symbol_for,
+        OnchainIntentStatus, SettlementFeeEstimator,
+    },
     storage::RedisStorage,
-    storage::SolverStats,
+    storage::{ExportCursor, RateLimitDecision, SolverStats, StatsCache},
+    utils::{is_valid_match_id, log_address, log_nullifier, same_address},
+    webhook::is_valid_callback_url,
 };
 use serde::{Deserialize, Serialize};
 use starknet::core::types::Felt;
-use starknet::core::utils::{cairo_short_string_to_felt, get_selector_from_name};
+use starknet::core::utils::get_selector_from_name;
 use num_bigint::BigUint;
-use num_traits::{Num, ToPrimitive};
-use tokio::sync::{OnceCell, RwLock};
+use num_traits::{Num, ToPrimitive, Zero};
 
-const ACCESS_TOKEN_EXPIRES_SECONDS: u64 = 3600;
+const ACCESS_TOKEN_EXPIRES_SECONDS: u64 = 15 * 60;
+const REFRESH_TOKEN_EXPIRES_SECONDS: u64 = 7 * 24 * 3600;
+/// Liquidity walks every pending intent and prices each token, so serve it from a short cache.
+const LIQUIDITY_CACHE_TTL_SECONDS: u64 = 30;
+const USD_DECIMALS: u32 = 6;
+/// `Retry-After` on `BOOK_FULL`: roughly a few matching ticks, long enough for the book to drain a little.
+const BOOK_FULL_RETRY_AFTER_SECONDS: u64 = 5;
+/// How long a user's submit lock (see `admit_intent`) is held at most, and how long and how often
+/// another submission by the same user waits for it.
+const SUBMIT_LOCK_TTL_MS: u64 = 5_000;
+const SUBMIT_LOCK_WAIT_MS: u64 = 2_000;
+const SUBMIT_LOCK_POLL_MS: u64 = 25;
 type ApiResult<T> = std::result::Result<T, (StatusCode, JsonResponse<ErrorResponse>)>;
-
-#[derive(Clone, Debug)]
-struct CachedPragmaPrice {
-    expires_at: u64,
-    response: PragmaTwapResponse,
-}
+/// Like `ApiResult`, for handlers whose errors may carry headers (`Retry-After`).
+type HeaderedApiResult<T> = std::result::Result<T, Response>;
 
 #[derive(Clone)]
 pub struct AppState {
     storage: Arc<RedisStorage>,
+    /// `REDIS_READ_URL` replica (or `storage` itself) for the read-heavy queries; may lag `storage`.
+    read_storage: Arc<RedisStorage>,
     matcher: Arc<IntentMatcher>,
     start_time: u64,
     api_config: ApiConfig,
-    starknet_rpc: String,
-    pragma_summary_stats_address: Felt,
-    pragma_oracle_address: Arc<OnceCell<Felt>>,
-    pragma_price_cache: Arc<RwLock<HashMap<String, CachedPragmaPrice>>>,
+    matching_config: MatchingConfig,
+    jwt_keyring: Arc<parking_lot::RwLock<JwtKeyring>>,
+    rpc: RpcClient,
+    /// `rpc` with `PREFLIGHT_TIMEOUT_MS` instead of `RPC_TIMEOUT_MS`.
+    preflight_rpc: RpcClient,
+    oracle: Arc<PragmaOracle>,
+    liquidity_cache: Arc<RwLock<Option<LiquidityResponse>>>,
+    /// ERC-20 metadata by normalized address. It never changes on-chain, so entries do not expire.
+    token_metadata_cache: Arc<RwLock<HashMap<String, TokenMetadata>>>,
+    /// Decoded settlement receipts by transaction hash, with when they were cached. Receipts are
+    /// final, so entries do not expire; past `SETTLEMENT_EVENTS_CACHE_MAX` the oldest is evicted.
+    settlement_events_cache: Arc<RwLock<HashMap<String, (std::time::Instant, Vec<SettlementEvent>)>>>,
+    events: EventBus,
+    stats_cache: Arc<StatsCache>,
     dark_pool_address: Felt,
-    enforce_prechecks: bool,
-}
-
-pub fn create_router(storage: Arc<RedisStorage>, matcher: Arc<IntentMatcher>, config: Config) -> Router {
-    fn normalize_starknet_rpc_url(raw: &str) -> String {
-        // Many providers require an explicit JSON-RPC path (e.g. `/rpc/v0_8`).
-        // If the env is given as a bare host, default to v0_8 for Starknet Sepolia.
-        if let Ok(mut url) = reqwest::Url::parse(raw) {
-            let path = url.path();
-            if path.is_empty() || path == "/" {
-                url.set_path("/rpc/v0_8");
-                return url.to_string();
-            }
-        }
-        raw.to_string()
-    }
-
-    let pragma_summary_stats_address = std::env::var("PRAGMA_SUMMARY_STATS_ADDRESS")
-        .ok()
-        .filter(|s| !s.trim().is_empty())
-        .unwrap_or_else(|| {
-            // Pragma "Realized Volatility / TWAP" contract on Starknet Sepolia.
-            // Source: Pragma docs -> Advanced -> Overview -> Contract Addresses -> Sepolia Testnet.
-            "0x49eefafae944d07744d07cc72a5bf14728a6fb463c3eae5bca13552f5d455fd".to_string()
-        });
-    let pragma_summary_stats_address = Felt::from_hex(&pragma_summary_stats_address)
-        .expect("Invalid PRAGMA_SUMMARY_STATS_ADDRESS");
+    precheck_mode: PrecheckMode,
+    /// The matching loop's last tick (unix millis), shared with the matcher.
+    matching_loop_last_tick_ms: Arc<AtomicU64>,
+    /// `STATE_BUNDLE_KEY`; state export/import is off without it, and refused while it is invalid.
+    bundle_signer: Option<Result<BundleSigner, String>>,
+}
 
-    let starknet_rpc = normalize_starknet_rpc_url(&config.starknet_rpc);
+pub fn create_router(
+    storage: Arc<RedisStorage>,
+    read_storage: Arc<RedisStorage>,
+    matcher: Arc<IntentMatcher>,
+    oracle: Arc<PragmaOracle>,
+    events: EventBus,
+    config: Config,
+) -> Router {
+    let rpc = RpcClient::new(
+        config.starknet_rpc_url(),
+        config.debug_rpc_logging,
+        config.rpc_block_tag,
+        config.rpc_timeout_ms,
+    );
+    let preflight_rpc = rpc.with_timeout(config.preflight_timeout_ms);
     let dark_pool_address = Felt::from_hex(&config.dark_pool_address).expect("Invalid DARK_POOL_ADDRESS");
 
+    let start_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut jwt_keyring = JwtKeyring::new(config.api_config.jwt_secret.clone());
+    if let Some(previous) = &config.api_config.jwt_secret_previous {
+        jwt_keyring = jwt_keyring.with_previous(
+            previous.clone(),
+            start_time.saturating_add(config.api_config.jwt_rotation_grace_seconds),
+        );
+    }
+
+    let matching_loop_last_tick_ms = matcher.last_tick_ms();
+    let bundle_signer = config.state_bundle_key.as_deref().map(|key| {
+        BundleSigner::from_hex(key).map_err(|e| {
+            error!("Invalid STATE_BUNDLE_KEY, state export/import will be refused: {}", e);
+            e.to_string()
+        })
+    });
     let state = AppState {
         storage,
+        read_storage,
         matcher,
-        start_time: SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0),
+        start_time,
         api_config: config.api_config.clone(),
-        starknet_rpc,
-        pragma_summary_stats_address,
-        pragma_oracle_address: Arc::new(OnceCell::new()),
-        pragma_price_cache: Arc::new(RwLock::new(HashMap::new())),
+        matching_config: config.matching_config.clone(),
+        jwt_keyring: Arc::new(parking_lot::RwLock::new(jwt_keyring)),
+        rpc,
+        preflight_rpc,
+        oracle,
+        liquidity_cache: Arc::new(RwLock::new(None)),
+        token_metadata_cache: Arc::new(RwLock::new(HashMap::new())),
+        settlement_events_cache: Arc::new(RwLock::new(HashMap::new())),
+        events,
+        stats_cache: Arc::new(StatsCache::new(std::time::Duration::from_millis(
+            config.api_config.stats_cache_ttl_ms,
+        ))),
         dark_pool_address,
-        enforce_prechecks: config.enforce_prechecks,
+        precheck_mode: config.precheck_mode,
+        matching_loop_last_tick_ms,
+        bundle_signer,
     };
 
     let allow_any_origin = config.api_config.cors_origins.iter().any(|s| s.trim() == "*");
@@ -119,37 +175,208 @@ pub fn create_router(storage: Arc<RedisStorage>, matcher: Arc<IntentMatcher>, co
             .allow_headers(Any)
     };
 
+    // `/health` stays unversioned: it is the probe nginx and the orchestrator hit, not API surface.
     let public_routes = Router::new()
         .route("/v1/health", get(health_check))
+        .route("/v1/health/ready", get(readiness_check))
         .route("/v1/starknet-rpc", post(starknet_rpc_proxy))
         .route("/v1/prices/pragma/twap", get(pragma_twap))
+        .route("/v1/prices/pragma/cross", get(pragma_cross))
+        .route("/v1/stats/liquidity", get(get_liquidity))
+        .route("/v1/tokens/:address/metadata", get(get_token_metadata))
         .route("/health", get(health_check))
-        .route("/starknet-rpc", post(starknet_rpc_proxy))
-        .layer(cors_public);
+        .route("/health/ready", get(readiness_check))
+        .layer(cors_public.clone());
 
     let private_routes = Router::new()
         .route("/v1/auth/login", post(login))
+        .route("/v1/auth/refresh", post(refresh_token))
+        .route("/v1/auth/logout", post(logout))
         .route("/v1/intents", post(submit_intent))
+        .route(
+            "/v1/intents/batch",
+            post(submit_intent_batch).layer(DefaultBodyLimit::max(BATCH_BODY_LIMIT_BYTES)),
+        )
+        .route("/v1/intents/validate", post(validate_intent))
+        .route("/v1/book/quote", post(quote_book))
         .route("/v1/intents/:nullifier", get(query_intent))
         .route("/v1/intents/:nullifier/cancel", post(cancel_intent))
+        .route("/v1/intents/:nullifier/abandon", post(abandon_intent))
+        .route("/v1/intents/:nullifier/extend", post(extend_intent))
+        .route("/v1/intents/:nullifier/events", get(stream_intent_events))
+        .route("/v1/intents/:nullifier/match", get(query_intent_match))
         .route("/v1/matches/:match_id/confirm", post(confirm_match))
+        .route("/v1/matches/:match_id/report-tx", post(report_match_tx))
+        .route("/v1/matches/:match_id/cancel", post(cancel_match))
+        .route("/v1/matches/:match_id/events", get(get_match_events))
+        .route("/v1/matches/:match_id/estimate", get(estimate_match_settlement))
+        .route("/v1/matches/unsettled", get(list_unsettled_matches))
+        .route("/v1/matches/active", get(list_active_matches))
+        .route("/v1/matches/deadletter", get(list_deadletter_matches))
+        .route("/v1/matches/history", get(list_match_history))
+        .route("/v1/settlements", get(list_settlements))
+        .route("/v1/approvals/relay", post(relay_approval))
         .route("/v1/intents/by-user", get(get_intents_by_user))
+        .route("/v1/intents/by-nonce/:nonce/replace", post(replace_intent_by_nonce))
+        .route("/v1/users/:user/summary", get(get_user_summary))
+        .route("/v1/events/export", get(export_events))
         .route("/v1/intents/pending", get(get_pending_intents))
         .route("/v1/stats", get(get_stats))
-        .route("/auth/login", post(login))
-        .route("/intent", post(submit_intent))
-        .route("/intent/:nullifier", get(query_intent))
-        .route("/intents/by-user", get(get_intents_by_user))
-        .route("/intents/pending", get(get_pending_intents))
-        .route("/stats", get(get_stats))
-        .layer(cors_private);
-
-    Router::new()
-        .merge(public_routes)
-        .merge(private_routes)
+        .route("/v1/metrics", get(get_metrics))
+        .route("/v1/admin/jwt/rotate", post(rotate_jwt_secret))
+        .route("/v1/admin/preview-match", post(preview_match))
+        .route("/v1/admin/gas-budget/override", post(override_gas_budget))
+        .route("/v1/admin/intents/:nullifier/purge", post(purge_intent))
+        .route("/v1/admin/export", get(export_state))
+        .route(
+            "/v1/admin/import",
+            post(import_state).layer(DefaultBodyLimit::max(STATE_IMPORT_MAX_BYTES)),
+        )
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        .layer(cors_private.clone());
+
+    let mut router = Router::new().merge(public_routes).merge(private_routes);
+    if config.api_config.enable_legacy_routes {
+        // Deprecated unversioned aliases of `/v1` routes; see "Versioning" in docs/api.md.
+        let legacy_routes = Router::new()
+            .merge(
+                Router::new()
+                    .route("/starknet-rpc", post(starknet_rpc_proxy))
+                    .layer(cors_public),
+            )
+            .merge(
+                Router::new()
+                    .route("/auth/login", post(login))
+                    .route("/intent", post(submit_intent))
+                    .route("/intent/:nullifier", get(query_intent))
+                    .route("/intents/by-user", get(get_intents_by_user))
+                    .route("/intents/pending", get(get_pending_intents))
+                    .route("/stats", get(get_stats))
+                    .layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+                    .layer(cors_private),
+            )
+            .layer(middleware::map_response(mark_deprecated));
+        router = router.merge(legacy_routes);
+    }
+
+    router
+        .layer(middleware::map_response(add_api_version_header))
         .with_state(state)
 }
 
+const RATE_LIMIT_WINDOW_MS: u64 = 60_000;
+
+/// `RATE_LIMIT_RPM` over a sliding minute, for the private routes. Requests with a valid bearer
+/// token count against its subject, others against the client IP. Counts live in Redis so replicas
+/// share them; if Redis can't be reached, requests go through.
+async fn rate_limit(State(state): State<AppState>, mut request: axum::extract::Request, next: middleware::Next) -> Response {
+    let limit = state.api_config.rate_limit_requests_per_minute;
+    if limit == 0 {
+        return next.run(request).await;
+    }
+    let client = rate_limit_client(&state, &request);
+    request.extensions_mut().insert(RateLimitClient(client.clone()));
+    let now_ms = chrono::Utc::now().timestamp_millis().max(0) as u64;
+    let decision = match state.storage.hit_rate_limit(&client, RATE_LIMIT_WINDOW_MS, limit, now_ms).await {
+        Ok(decision) => decision,
+        Err(e) => {
+            warn!("Rate limit check failed for {}; letting the request through: {}", client, e);
+            return next.run(request).await;
+        }
+    };
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        let correlation_id = correlation_id_from_headers(request.headers());
+        let retry_after = decision.reset_at_ms.saturating_sub(now_ms).div_ceil(1000).max(1);
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(axum::http::header::RETRY_AFTER, retry_after.to_string())],
+            JsonResponse(error_response(
+                "RATE_LIMITED",
+                &format!("More than {} requests per minute; retry in {}s", limit, retry_after),
+                Some(correlation_id),
+            )),
+        )
+            .into_response()
+    };
+    add_rate_limit_headers(response.headers_mut(), &decision);
+    response
+}
+
+/// Who `rate_limit` counted a request against, for handlers that charge more than one hit.
+#[derive(Clone)]
+struct RateLimitClient(String);
+
+/// Charges `client` one more `RATE_LIMIT_RPM` hit, or returns the seconds until one is free. Fails
+/// open like `rate_limit`.
+async fn charge_rate_limit(state: &AppState, client: &RateLimitClient) -> Option<u64> {
+    let limit = state.api_config.rate_limit_requests_per_minute;
+    let now_ms = chrono::Utc::now().timestamp_millis().max(0) as u64;
+    match state.storage.hit_rate_limit(&client.0, RATE_LIMIT_WINDOW_MS, limit, now_ms).await {
+        Ok(decision) if !decision.allowed => Some(decision.reset_at_ms.saturating_sub(now_ms).div_ceil(1000).max(1)),
+        Ok(_) => None,
+        Err(e) => {
+            warn!("Rate limit check failed for {}; letting the request through: {}", client.0, e);
+            None
+        }
+    }
+}
+
+fn add_rate_limit_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    for (name, value) in [
+        ("x-ratelimit-limit", decision.limit as u64),
+        ("x-ratelimit-remaining", decision.remaining as u64),
+        ("x-ratelimit-reset", decision.reset_at_ms.div_ceil(1000)),
+    ] {
+        headers.insert(name, HeaderValue::from(value));
+    }
+}
+
+/// Who a request counts against: `sub:<subject>` for a valid bearer token, else `ip:<address>`.
+fn rate_limit_client(state: &AppState, request: &axum::extract::Request) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    if let Some(token) = bearer_token_from_headers(request.headers()) {
+        if let Ok(claims) = state.jwt_keyring.read().verify(token, now) {
+            return format!("sub:{}", claims.sub);
+        }
+    }
+    let forwarded = state
+        .api_config
+        .trust_proxy_headers
+        .then(|| request.headers().get("x-real-ip").and_then(|v| v.to_str().ok()))
+        .flatten()
+        .and_then(|v| v.trim().parse::<std::net::IpAddr>().ok());
+    let peer = request
+        .extensions()
+        .get::<axum::extract::ConnectInfo<std::net::SocketAddr>>()
+        .map(|info| info.0.ip());
+    match forwarded.or(peer) {
+        Some(ip) => format!("ip:{}", ip),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+/// Version of the response formats served; reported on every response as `x-api-version`.
+const API_VERSION: &str = "1";
+
+async fn add_api_version_header(mut response: axum::response::Response) -> axum::response::Response {
+    response
+        .headers_mut()
+        .insert("x-api-version", HeaderValue::from_static(API_VERSION));
+    response
+}
+
+async fn mark_deprecated(mut response: axum::response::Response) -> axum::response::Response {
+    response
+        .headers_mut()
+        .insert("deprecation", HeaderValue::from_static("true"));
+    response
+}
+
 #[derive(Debug, Deserialize)]
 struct PragmaTwapQuery {
     pair_id: String,
@@ -160,328 +387,102 @@ struct PragmaTwapQuery {
 struct PragmaTwapResponse {
     success: bool,
     source: String,
+    /// The oracle source whose price is the consensus (weighted median).
+    provider: String,
     pair_id: String,
     window_seconds: u64,
     start_time: u64,
     price_raw: String,
     decimals_raw: String,
+    /// Every configured source's answer; only present when more than one is configured.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    sources: Vec<SourcePrice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PragmaCrossQuery {
+    base: String,
+    quote: String,
+    window_seconds: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct PragmaCrossResponse {
+    success: bool,
+    #[serde(flatten)]
+    rate: CrossRate,
+}
+
+fn oracle_error(e: OracleError) -> (StatusCode, JsonResponse<ErrorResponse>) {
+    let (status, code) = match e {
+        OracleError::InvalidPairId(_) => (StatusCode::BAD_REQUEST, "INVALID_PAIR_ID"),
+        OracleError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_ERROR"),
+        OracleError::Upstream(_) => (StatusCode::BAD_GATEWAY, "PRAGMA_TWAP_ERROR"),
+    };
+    (status, JsonResponse(error_response(code, &e.to_string(), None)))
 }
 
 async fn pragma_twap(
     State(state): State<AppState>,
     Query(query): Query<PragmaTwapQuery>,
 ) -> ApiResult<JsonResponse<PragmaTwapResponse>> {
-    fn felt_hex(v: Felt) -> String {
-        format!("0x{:x}", v)
-    }
-
-    async fn jsonrpc_starknet_call(
-        rpc_url: &str,
-        contract_address: Felt,
-        selector: Felt,
-        calldata: Vec<Felt>,
-    ) -> Result<serde_json::Value, reqwest::Error> {
-        let payload = serde_json::json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "starknet_call",
-            "params": [
-                {
-                    "contract_address": format!("0x{:x}", contract_address),
-                    "entry_point_selector": format!("0x{:x}", selector),
-                    "calldata": calldata.into_iter().map(|v| format!("0x{:x}", v)).collect::<Vec<_>>(),
-                },
-                // Some RPC providers are strict about BlockId encoding. "latest" (string) is widely accepted.
-                "latest"
-            ]
-        });
-
-        reqwest::Client::new().post(rpc_url).json(&payload).send().await?.json().await
-    }
-
-    let now = chrono::Utc::now().timestamp().max(0) as u64;
-    let window_seconds = query.window_seconds.unwrap_or(3600).max(1).min(24 * 60 * 60);
-    let start_time = now.saturating_sub(window_seconds);
-
-    let pair_id = query.pair_id.trim().to_string();
-    if pair_id.is_empty() || pair_id.len() > 31 {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            JsonResponse(error_response(
-                "INVALID_PAIR_ID",
-                "pair_id is required and must be <= 31 chars",
-                None,
-            )),
-        ));
-    }
-
-    let pair_felt = cairo_short_string_to_felt(&pair_id).map_err(|_| {
-        (
-            StatusCode::BAD_REQUEST,
-            JsonResponse(error_response(
-                "INVALID_PAIR_ID",
-                "pair_id must be a Cairo short string",
-                None,
-            )),
-        )
-    })?;
-
-    // Serve cached response to avoid hammering the RPC/Pragma contracts (and spamming logs)
-    // when the frontend recalculates slippage frequently.
-    // Cache per (pair_id, window_seconds) for a short TTL.
-    let cache_key = format!("{}:{}", pair_id, window_seconds);
-    {
-        let cache = state.pragma_price_cache.read().await;
-        if let Some(entry) = cache.get(&cache_key) {
-            if now < entry.expires_at {
-                return Ok(JsonResponse(entry.response.clone()));
-            }
-        }
-    }
-
-    // Selector: calculate_twap
-    let selector = get_selector_from_name("calculate_twap").map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            JsonResponse(error_response(
-                "INTERNAL_ERROR",
-                "Failed to build selector",
-                None,
-            )),
-        )
-    })?;
-
-    // Send JSON-RPC directly to avoid client incompatibilities across providers.
-    // Some testnets may not have enough checkpoints for TWAP; in that case we fall back to Pragma's spot median.
-    let mut source = "pragma_twap".to_string();
-    let json = jsonrpc_starknet_call(
-        &state.starknet_rpc,
-        state.pragma_summary_stats_address,
-        selector,
-        vec![
-            // DataType::SpotEntry(pair_id)
-            Felt::ZERO,
-            pair_felt,
-            // AggregationMode::Median(())
-            Felt::ZERO,
-            Felt::from(window_seconds),
-            Felt::from(start_time),
-        ],
-    )
-    .await
-    .map_err(|e| {
-        error!("Pragma TWAP RPC request failed: {}", e);
-        (
-            StatusCode::BAD_GATEWAY,
-            JsonResponse(error_response(
-                "PRAGMA_TWAP_ERROR",
-                "Failed to reach Starknet RPC",
-                None,
-            )),
-        )
-    })?;
-
-    fn is_not_enough_data_error(payload: &serde_json::Value) -> bool {
-        // Pragma testnet TWAP often reverts with "Not enough data".
-        // Treat that as a normal "TWAP unavailable" situation and fall back without error-level logging.
-        payload
-            .get("error")
-            .and_then(|e| e.get("data"))
-            .and_then(|d| d.get("revert_error"))
-            .and_then(|re| re.get("error"))
-            .and_then(|v| v.as_str())
-            .map(|s| s.contains("Not enough data") || s.contains("0x4e6f7420656e6f7567682064617461"))
-            .unwrap_or(false)
-    }
-
-    // If the TWAP call errors (e.g., "Not enough data" on testnets), try spot median from the oracle contract.
-    let json = if json.get("error").is_some() {
-        if is_not_enough_data_error(&json) {
-            debug!("Pragma TWAP not available (Not enough data); falling back to spot median");
-        } else {
-            warn!("Pragma TWAP RPC returned error payload; falling back to spot median: {}", json);
-        }
-
-        let oracle_addr = *state
-            .pragma_oracle_address
-            .get_or_try_init(|| async {
-                // get_oracle_address() -> ContractAddress
-                let oracle_selector = get_selector_from_name("get_oracle_address").map_err(|_| {
-                    anyhow::anyhow!("Failed to build selector")
-                })?;
-                let oracle_addr_json = jsonrpc_starknet_call(
-                    &state.starknet_rpc,
-                    state.pragma_summary_stats_address,
-                    oracle_selector,
-                    vec![],
-                )
-                .await
-                .map_err(|e| anyhow::anyhow!("Pragma oracle address RPC request failed: {}", e))?;
-
-                let oracle_addr = oracle_addr_json
-                    .get("result")
-                    .and_then(|v| v.as_array())
-                    .and_then(|a| a.get(0))
-                    .and_then(|v| v.as_str())
-                    .ok_or_else(|| anyhow::anyhow!("Failed to resolve Pragma oracle address"))?;
-
-                let oracle_addr = Felt::from_hex(oracle_addr)
-                    .map_err(|_| anyhow::anyhow!("Failed to parse Pragma oracle address"))?;
-                Ok::<Felt, anyhow::Error>(oracle_addr)
-            })
-            .await
-            .map_err(|e| {
-                error!("{}", e);
-                (
-                    StatusCode::BAD_GATEWAY,
-                    JsonResponse(error_response(
-                        "PRAGMA_TWAP_ERROR",
-                        "Failed to resolve Pragma oracle address",
-                        None,
-                    )),
-                )
-            })?;
-
-        // get_data_median(DataType) -> PragmaPricesResponse
-        let spot_selector = get_selector_from_name("get_data_median").map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                JsonResponse(error_response(
-                    "INTERNAL_ERROR",
-                    "Failed to build selector",
-                    None,
-                )),
-            )
-        })?;
-
-        source = "pragma_spot_median".to_string();
-        jsonrpc_starknet_call(
-            &state.starknet_rpc,
-            oracle_addr,
-            spot_selector,
-            vec![
-                // DataType::SpotEntry(pair_id)
-                Felt::ZERO,
-                pair_felt,
-            ],
-        )
+    let ConsensusPrice { price, sources } = state
+        .oracle
+        .get_consensus_price(&query.pair_id, query.window_seconds)
         .await
-        .map_err(|e| {
-            error!("Pragma spot median RPC request failed: {}", e);
-            (
-                StatusCode::BAD_GATEWAY,
-                JsonResponse(error_response(
-                    "PRAGMA_TWAP_ERROR",
-                    "Failed to reach Starknet RPC",
-                    None,
-                )),
-            )
-        })?
-    } else {
-        json
-    };
-
-    let result = json
-        .get("result")
-        .and_then(|v| v.as_array())
-        .ok_or_else(|| {
-            (
-                StatusCode::BAD_GATEWAY,
-                JsonResponse(error_response(
-                    "PRAGMA_TWAP_ERROR",
-                    "TWAP response missing fields",
-                    None,
-                )),
-            )
-        })?;
-
-    if result.len() < 2 {
-        return Err((
-            StatusCode::BAD_GATEWAY,
-            JsonResponse(error_response(
-                "PRAGMA_TWAP_ERROR",
-                "TWAP response missing fields",
-                None,
-            )),
-        ));
-    }
+        .map_err(oracle_error)?;
 
-    let price_raw = result[0].as_str().unwrap_or_default().to_string();
-    let decimals_raw = result[1].as_str().unwrap_or_default().to_string();
-    if price_raw.is_empty() || decimals_raw.is_empty() {
-        return Err((
-            StatusCode::BAD_GATEWAY,
-            JsonResponse(error_response(
-                "PRAGMA_TWAP_ERROR",
-                "TWAP response missing fields",
-                None,
-            )),
-        ));
-    }
-
-    let resp = PragmaTwapResponse {
+    Ok(JsonResponse(PragmaTwapResponse {
         success: true,
-        source,
-        pair_id,
-        window_seconds,
-        start_time,
-        price_raw,
-        decimals_raw,
-    };
+        source: price.source,
+        provider: price.provider,
+        sources: if sources.len() > 1 { sources } else { Vec::new() },
+        pair_id: price.pair_id,
+        window_seconds: price.window_seconds,
+        start_time: price.start_time,
+        price_raw: price.price_raw,
+        decimals_raw: price.decimals_raw,
+    }))
+}
 
-    // Keep cache short to avoid stale prices while still reducing RPC pressure.
-    {
-        let ttl = 30u64;
-        let mut cache = state.pragma_price_cache.write().await;
-        cache.insert(
-            cache_key,
-            CachedPragmaPrice {
-                expires_at: now.saturating_add(ttl),
-                response: resp.clone(),
-            },
-        );
-    }
+async fn pragma_cross(
+    State(state): State<AppState>,
+    Query(query): Query<PragmaCrossQuery>,
+) -> ApiResult<JsonResponse<PragmaCrossResponse>> {
+    let rate = state
+        .oracle
+        .get_cross_rate(&query.base, &query.quote, query.window_seconds)
+        .await
+        .map_err(oracle_error)?;
 
-    Ok(JsonResponse(resp))
+    Ok(JsonResponse(PragmaCrossResponse { success: true, rate }))
 }
 
 async fn starknet_rpc_proxy(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(payload): Json<serde_json::Value>,
 ) -> Result<JsonResponse<serde_json::Value>, (StatusCode, JsonResponse<ErrorResponse>)> {
     // Allow browsers to call Starknet JSON-RPC without CORS issues by proxying through the solver.
     // We intentionally do not expose arbitrary URLs; only the configured STARKNET_RPC is used.
-    let client = reqwest::Client::new();
-    let resp = client
-        .post(&state.starknet_rpc)
-        .json(&payload)
-        .send()
+    let correlation_id = correlation_id_from_headers(&headers);
+    let (status, json) = state
+        .rpc
+        .send(&payload, Some(&correlation_id))
         .await
         .map_err(|e| {
             error!("RPC proxy request failed: {}", e);
+            let message = if e.is_decode() {
+                "Invalid response from Starknet RPC"
+            } else {
+                "Failed to reach Starknet RPC"
+            };
             (
                 StatusCode::BAD_GATEWAY,
-                JsonResponse(error_response(
-                    "RPC_PROXY_ERROR",
-                    "Failed to reach Starknet RPC",
-                    None,
-                )),
+                JsonResponse(error_response("RPC_PROXY_ERROR", message, None)),
             )
         })?;
 
-    let status = resp.status();
-    let json = resp.json::<serde_json::Value>().await.map_err(|e| {
-        error!("RPC proxy JSON decode failed: {}", e);
-        (
-            StatusCode::BAD_GATEWAY,
-            JsonResponse(error_response(
-                "RPC_PROXY_ERROR",
-                "Invalid response from Starknet RPC",
-                None,
-            )),
-        )
-    })?;
-
     if !status.is_success() {
         return Err((
             StatusCode::BAD_GATEWAY,
@@ -496,10 +497,76 @@ async fn starknet_rpc_proxy(
     Ok(JsonResponse(json))
 }
 
+/// Readiness: 503 while Redis is unreachable or the matching loop has stopped ticking. A paused
+/// settlement budget or a full book is reported but does not make the instance unready, since
+/// matching (and, for the budget, intake) keeps working.
+async fn readiness_check(State(state): State<AppState>) -> (StatusCode, JsonResponse<ReadinessResponse>) {
+    let redis = match state.storage.ping().await {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Readiness: Redis ping failed: {}", e);
+            false
+        }
+    };
+    let gas_budget = if redis {
+        state.matcher.gas_budget_status().await.unwrap_or_else(|e| {
+            warn!("Readiness: failed to read gas budget: {}", e);
+            None
+        })
+    } else {
+        None
+    };
+    let pending_intents = if redis {
+        match state.storage.pending_count().await {
+            Ok(pending) => Some(pending),
+            Err(e) => {
+                warn!("Readiness: failed to count pending intents: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let max_pending_intents = Some(state.api_config.max_pending_intents).filter(|cap| *cap > 0);
+    let book_full = matches!((pending_intents, max_pending_intents), (Some(n), Some(cap)) if n >= cap);
+
+    let last_tick_ms = state.matching_loop_last_tick_ms.load(Ordering::Relaxed);
+    let now_ms = chrono::Utc::now().timestamp_millis().max(0) as u64;
+    let matching_loop_last_tick_ms_ago = (last_tick_ms > 0).then(|| now_ms.saturating_sub(last_tick_ms));
+    let stale_after_ms = state.matching_config.matching_loop_stale_after_ms;
+    let matching_loop = stale_after_ms == 0
+        || matching_loop_last_tick_ms_ago.map_or(false, |ago| ago <= stale_after_ms);
+    if !matching_loop {
+        warn!(
+            "Readiness: matching loop last ticked {:?}ms ago (stale after {}ms)",
+            matching_loop_last_tick_ms_ago, stale_after_ms
+        );
+    }
+
+    let ready = redis && matching_loop;
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (
+        status,
+        JsonResponse(ReadinessResponse {
+            ready,
+            redis,
+            matching_loop,
+            matching_loop_last_tick_ms_ago,
+            settlement_paused: gas_budget.as_ref().map_or(false, |b| b.paused),
+            gas_budget,
+            pending_intents,
+            max_pending_intents,
+            book_full,
+            stale_oracle_feeds: state.matcher.stale_oracle_feeds(),
+        }),
+    )
+}
+
 async fn health_check(State(state): State<AppState>) -> JsonResponse<HealthResponse> {
-    let stats = state.storage.get_stats().await.unwrap_or(SolverStats {
+    let stats = state.stats_cache.get(&state.read_storage).await.unwrap_or(SolverStats {
         pending_intents: 0,
         matched_pairs: 0,
+        pair_issues: Vec::new(),
     });
     let uptime = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -519,6 +586,16 @@ async fn login(
     State(state): State<AppState>,
     Json(payload): Json<LoginRequest>,
 ) -> ApiResult<JsonResponse<LoginResponse>> {
+    if !sessions_configured(&state.api_config) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            JsonResponse(error_response(
+                "AUTH_DISABLED",
+                "Login needs AUTH_PASSWORD and JWT_SECRET to be set on this solver",
+                None,
+            )),
+        ));
+    }
     if payload.username != state.api_config.auth_username || payload.password != state.api_config.auth_password {
         return Err((
             StatusCode::UNAUTHORIZED,
@@ -530,142 +607,604 @@ async fn login(
         ));
     }
 
-    let token = issue_token(
-        &payload.username,
-        &state.api_config.jwt_secret,
-        (ACCESS_TOKEN_EXPIRES_SECONDS / 60) as i64,
-    )
-    .map_err(|e| {
-        error!("Failed to issue access token: {}", e);
+    let issue_failed = |e: anyhow::Error| {
+        error!("Failed to issue tokens: {}", e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             JsonResponse(error_response("AUTH_ERROR", "Failed to issue token", None)),
         )
-    })?;
+    };
+    let (token, (refresh_token, refresh_claims)) = {
+        let keyring = state.jwt_keyring.read();
+        let token = keyring
+            .issue(&payload.username, (ACCESS_TOKEN_EXPIRES_SECONDS / 60) as i64)
+            .map_err(issue_failed)?;
+        let refresh = keyring
+            .issue_refresh(&payload.username, (REFRESH_TOKEN_EXPIRES_SECONDS / 60) as i64)
+            .map_err(issue_failed)?;
+        (token, refresh)
+    };
+    state
+        .storage
+        .store_refresh_token(&refresh_claims.jti, &refresh_claims.sub, REFRESH_TOKEN_EXPIRES_SECONDS)
+        .await
+        .map_err(issue_failed)?;
 
     Ok(JsonResponse(LoginResponse {
         success: true,
         token,
         expires_in_seconds: ACCESS_TOKEN_EXPIRES_SECONDS,
+        refresh_token,
+        refresh_expires_in_seconds: REFRESH_TOKEN_EXPIRES_SECONDS,
     }))
 }
 
-async fn submit_intent(
+/// Trades a refresh token still on record for a new access token and a new refresh token. Refresh
+/// tokens are single-use: the one traded in is revoked first, so a stolen copy that is replayed
+/// after (or races) the owner's refresh gets `401`.
+async fn refresh_token(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(request): Json<SubmitIntentRequest>,
-) -> ApiResult<JsonResponse<SubmitIntentResponse>> {
+    Json(payload): Json<RefreshTokenRequest>,
+) -> ApiResult<JsonResponse<RefreshTokenResponse>> {
     let correlation_id = correlation_id_from_headers(&headers);
-    require_auth(&headers, &state, &correlation_id)?;
-
-    info!(
-        "Received intent submission from user {}, correlation_id={}",
-        request.public_inputs.user, correlation_id
-    );
+    let claims = verify_refresh_token(&state, &payload.refresh_token, &correlation_id).await?;
 
-    if request.proof_data.is_empty() {
-        return Err((
-            StatusCode::BAD_REQUEST,
+    let issue_failed = |e: anyhow::Error| {
+        error!("Failed to issue tokens: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
             JsonResponse(error_response(
-                "INVALID_PROOF",
-                "Invalid proof data (empty)",
-                Some(correlation_id),
+                "AUTH_ERROR",
+                "Failed to issue token",
+                Some(correlation_id.clone()),
             )),
-        ));
-    }
-    // Current Groth16 circuit uses nPublic=3 (VK IC length = 4).
-    // Older payloads may include additional business fields; accept either as long as
-    // minimum verifier-required public signals are present.
-    if !request.proof_public_inputs.is_empty() && request.proof_public_inputs.len() < 3 {
+        )
+    };
+    // Only one refresh wins the old token's id.
+    if !state.storage.revoke_refresh_token(&claims.jti).await.map_err(issue_failed)? {
         return Err((
-            StatusCode::BAD_REQUEST,
+            StatusCode::UNAUTHORIZED,
             JsonResponse(error_response(
-                "INVALID_PUBLIC_INPUTS",
-                "Invalid proof_public_inputs (expected at least 3 elements)",
-                Some(correlation_id),
+                "UNAUTHORIZED",
+                "Refresh token has been revoked",
+                Some(correlation_id.clone()),
             )),
         ));
     }
-    if !is_valid_signature(&request.signature) {
+    let (token, (refresh_token, refresh_claims)) = {
+        let keyring = state.jwt_keyring.read();
+        let token = keyring
+            .issue(&claims.sub, (ACCESS_TOKEN_EXPIRES_SECONDS / 60) as i64)
+            .map_err(issue_failed)?;
+        let refresh = keyring
+            .issue_refresh(&claims.sub, (REFRESH_TOKEN_EXPIRES_SECONDS / 60) as i64)
+            .map_err(issue_failed)?;
+        (token, refresh)
+    };
+    state
+        .storage
+        .store_refresh_token(&refresh_claims.jti, &refresh_claims.sub, REFRESH_TOKEN_EXPIRES_SECONDS)
+        .await
+        .map_err(issue_failed)?;
+
+    Ok(JsonResponse(RefreshTokenResponse {
+        success: true,
+        token,
+        expires_in_seconds: ACCESS_TOKEN_EXPIRES_SECONDS,
+        refresh_token,
+        refresh_expires_in_seconds: REFRESH_TOKEN_EXPIRES_SECONDS,
+    }))
+}
+
+/// Claims of `token` if it is a valid refresh token whose id is still on record.
+async fn verify_refresh_token(state: &AppState, token: &str, correlation_id: &str) -> ApiResult<JwtClaims> {
+    let unauthorized = |message: &str| {
+        (
+            StatusCode::UNAUTHORIZED,
+            JsonResponse(error_response("UNAUTHORIZED", message, Some(correlation_id.to_string()))),
+        )
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let claims = state
+        .jwt_keyring
+        .read()
+        .verify_typed(token, now, TokenType::Refresh)
+        .map_err(|_| unauthorized("Invalid or expired refresh token"))?;
+
+    let subject = state.storage.get_refresh_token_subject(&claims.jti).await.map_err(|e| {
+        error!("Failed to look up refresh token: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            JsonResponse(error_response(
+                "AUTH_ERROR",
+                "Failed to check the refresh token",
+                Some(correlation_id.to_string()),
+            )),
+        )
+    })?;
+    if subject.as_deref() != Some(claims.sub.as_str()) {
+        return Err(unauthorized("Refresh token has been revoked"));
+    }
+    Ok(claims)
+}
+
+/// Revokes the bearer token for the rest of its validity by putting its `jti` on the denylist
+/// `require_session` checks, along with the refresh token in the body, if any. Needs a token even
+/// with auth off, since there is one to revoke.
+async fn logout(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    payload: Option<Json<LogoutRequest>>,
+) -> ApiResult<JsonResponse<ActionResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    let claims = require_session(&headers, &state, &correlation_id).await?;
+    let payload = payload.map(|Json(p)| p).unwrap_or_default();
+    let refresh_claims = match payload.refresh_token.as_deref() {
+        Some(token) => Some(verify_refresh_token(&state, token, &correlation_id).await?),
+        None => None,
+    };
+    if refresh_claims.as_ref().is_some_and(|r| r.sub != claims.sub) {
         return Err((
-            StatusCode::BAD_REQUEST,
+            StatusCode::FORBIDDEN,
             JsonResponse(error_response(
-                "INVALID_SIGNATURE",
-                "Signature format is invalid",
+                "FORBIDDEN",
+                "The refresh token belongs to another session",
                 Some(correlation_id),
             )),
         ));
     }
-    if request.public_inputs.chain_id.trim().is_empty()
-        || request.public_inputs.domain_separator.trim().is_empty()
-    {
+    if claims.jti.is_empty() {
         return Err((
             StatusCode::BAD_REQUEST,
             JsonResponse(error_response(
-                "INVALID_INTENT_METADATA",
-                "chain_id and domain_separator are required",
+                "TOKEN_NOT_REVOCABLE",
+                "This token was issued without a jti and stays valid until it expires",
                 Some(correlation_id),
             )),
         ));
     }
 
-    let now = chrono::Utc::now().timestamp().max(0) as u64;
-    if request.public_inputs.deadline <= now {
-        return Err((
-            StatusCode::BAD_REQUEST,
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let remaining = (claims.exp as u64).saturating_sub(now);
+    let revoke_failed = |e: anyhow::Error| {
+        error!("Failed to revoke token: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
             JsonResponse(error_response(
-                "ERR_EXPIRED_INTENT",
-                "Intent already expired",
-                Some(correlation_id),
+                "AUTH_ERROR",
+                "Failed to log out",
+                Some(correlation_id.clone()),
             )),
-        ));
+        )
+    };
+    if let Some(refresh) = &refresh_claims {
+        state.storage.revoke_refresh_token(&refresh.jti).await.map_err(revoke_failed)?;
     }
+    state.storage.revoke_token(&claims.jti, remaining).await.map_err(revoke_failed)?;
+    info!("Session of {} logged out, correlation_id={}", log_address(&claims.sub), correlation_id);
 
-    if state.enforce_prechecks {
-        if let Err((status, body)) =
-            enforce_balance_allowance_precheck(&state, &request, &correlation_id).await
-        {
-            return Err((status, JsonResponse(body)));
+    Ok(JsonResponse(ActionResponse {
+        success: true,
+        correlation_id,
+        message: "Logged out; the token is no longer accepted".to_string(),
+        outputs: None,
+    }))
+}
+
+async fn rotate_jwt_secret(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<RotateJwtSecretRequest>,
+) -> ApiResult<JsonResponse<RotateJwtSecretResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    let subject = require_admin(&headers, &state, &correlation_id).await?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let grace_seconds = request
+        .grace_seconds
+        .unwrap_or(state.api_config.jwt_rotation_grace_seconds);
+
+    state
+        .jwt_keyring
+        .write()
+        .rotate(request.new_secret, grace_seconds, now)
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                JsonResponse(error_response(
+                    "INVALID_REQUEST",
+                    &e.to_string(),
+                    Some(correlation_id.clone()),
+                )),
+            )
+        })?;
+
+    warn!(
+        "JWT signing secret rotated by {}; previous secret accepted for {}s, correlation_id={}",
+        subject, grace_seconds, correlation_id
+    );
+
+    Ok(JsonResponse(RotateJwtSecretResponse {
+        success: true,
+        previous_valid_until: now.saturating_add(grace_seconds),
+        correlation_id,
+    }))
+}
+
+/// Admin: the `MatchedPair` the matcher would create for two stored intents, by the matching
+/// loop's rules. No intent changes and nothing is locked or published.
+async fn preview_match(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<PreviewMatchRequest>,
+) -> ApiResult<JsonResponse<PreviewMatchResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_admin(&headers, &state, &correlation_id).await?;
+    let reject = |status: StatusCode, code: &str, message: &str| {
+        (
+            status,
+            JsonResponse(error_response(code, message, Some(correlation_id.clone()))),
+        )
+    };
+
+    let mut intents = Vec::with_capacity(2);
+    for nullifier in [&request.nullifier_a, &request.nullifier_b] {
+        match state.storage.get_intent(nullifier).await {
+            Ok(Some(intent)) => intents.push(intent),
+            Ok(None) => {
+                return Err(reject(
+                    StatusCode::NOT_FOUND,
+                    "NOT_FOUND",
+                    &format!("Intent {} not found", nullifier),
+                ))
+            }
+            Err(e) => {
+                error!("Failed to load intent {} for match preview: {}", log_nullifier(nullifier), e);
+                return Err(reject(StatusCode::INTERNAL_SERVER_ERROR, "QUERY_ERROR", "Failed to load intent"));
+            }
         }
     }
+    let second = intents.pop().expect("two intents");
+    let first = intents.pop().expect("two intents");
 
-    if let Ok(Some(_)) = state.storage.get_intent(&request.nullifier).await {
-        return Err((
-            StatusCode::CONFLICT,
+    let pair = state.matcher.preview_match(first, second).await.map_err(|e| {
+        let msg = e.to_string();
+        let code = if msg.contains("INTENT_NOT_PENDING") {
+            "INTENT_NOT_PENDING"
+        } else {
+            "INCOMPATIBLE_INTENTS"
+        };
+        reject(StatusCode::CONFLICT, code, &msg)
+    })?;
+
+    Ok(JsonResponse(PreviewMatchResponse {
+        success: true,
+        pair,
+        correlation_id,
+    }))
+}
+
+/// Admin: lift today's `DAILY_GAS_BUDGET` pause. It comes back at the next UTC day only if that
+/// day's fees reach the budget too.
+async fn override_gas_budget(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ApiResult<JsonResponse<GasBudgetStatus>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    let subject = require_admin(&headers, &state, &correlation_id).await?;
+    let storage_error = |e: anyhow::Error| {
+        error!("Failed to override gas budget: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
             JsonResponse(error_response(
-                "DUPLICATE_INTENT",
-                "Intent already exists",
-                Some(correlation_id),
+                "STORAGE_ERROR",
+                "Failed to override gas budget",
+                Some(correlation_id.clone()),
             )),
-        ));
-    }
+        )
+    };
 
-    // Fail fast for invalid proofs by simulating DarkPool.submit_intent through RPC.
-    // This prevents invalid intents from entering the matching queue and getting stuck in `Matched`.
-    if let Err(reason) = preflight_verify_intent_proof(&state, &request).await {
-        warn!(
-            "Proof preflight verification failed: correlation_id={}, user={}, nullifier={}, reason={}",
-            correlation_id,
-            request.public_inputs.user,
-            request.nullifier,
-            reason
-        );
+    let Some(status) = state.matcher.gas_budget_status().await.map_err(storage_error)? else {
         return Err((
             StatusCode::BAD_REQUEST,
             JsonResponse(error_response(
-                "INVALID_PROOF",
-                &format!("Proof preflight verification failed: {}", reason),
+                "GAS_BUDGET_NOT_CONFIGURED",
+                "DAILY_GAS_BUDGET is not set",
                 Some(correlation_id),
             )),
         ));
+    };
+    state
+        .storage
+        .set_gas_budget_override(&status.day)
+        .await
+        .map_err(storage_error)?;
+    warn!(
+        "Daily gas budget override for {} by {} ({} of {} nano spent), correlation_id={}",
+        status.day, subject, status.spent_nano, status.budget_nano, correlation_id
+    );
+
+    Ok(JsonResponse(GasBudgetStatus {
+        overridden: true,
+        paused: false,
+        ..status
+    }))
+}
+
+async fn submit_intent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SubmitIntentRequest>,
+) -> HeaderedApiResult<JsonResponse<SubmitIntentResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await.map_err(IntoResponse::into_response)?;
+
+    info!(
+        "Received intent submission from user {}, correlation_id={}",
+        log_address(&request.public_inputs.user), correlation_id
+    );
+
+    enforce_book_capacity(&state, &correlation_id).await?;
+    authenticate_intent(&state, &request, &correlation_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    enforce_submit_interval(&state, &request.public_inputs.user, &correlation_id).await?;
+    let allow_duplicate = request.allow_duplicate;
+    let (intent, warnings) = vet_intent(&state, request, &correlation_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    admit_intent(&state, intent, warnings, allow_duplicate, correlation_id)
+        .await
+        .map(JsonResponse)
+        .map_err(IntoResponse::into_response)
+}
+
+/// `submit_intent` for several intents in one round trip. Each runs the same checks and is stored
+/// or refused on its own, in order; the answer lists every outcome by index. Only an oversized
+/// batch or a full book refuses the whole request. Each intent counts as a submission of its own:
+/// `MIN_SUBMIT_INTERVAL_MS`, `RATE_LIMIT_RPM` and `MAX_PENDING_INTENTS` all apply per intent, so a
+/// batch can't get past them.
+async fn submit_intent_batch(
+    State(state): State<AppState>,
+    rate_limit_client: Option<axum::Extension<RateLimitClient>>,
+    headers: HeaderMap,
+    Json(requests): Json<Vec<SubmitIntentRequest>>,
+) -> HeaderedApiResult<JsonResponse<BatchSubmitResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await.map_err(IntoResponse::into_response)?;
+    let reject = |code: &str, message: &str| {
+        (
+            StatusCode::BAD_REQUEST,
+            JsonResponse(error_response(code, message, Some(correlation_id.clone()))),
+        )
+            .into_response()
+    };
+    let cap = state.api_config.max_batch_intents;
+    if requests.is_empty() {
+        return Err(reject("INVALID_REQUEST", "The batch has no intents"));
+    }
+    if requests.len() > cap {
+        return Err(reject(
+            "BATCH_TOO_LARGE",
+            &format!("The batch has {} intents (max {})", requests.len(), cap),
+        ));
+    }
+    info!(
+        "Received batch of {} intents, correlation_id={}",
+        requests.len(),
+        correlation_id
+    );
+    enforce_book_capacity(&state, &correlation_id).await?;
+
+    let state = &state;
+    let correlation_id_ref = &correlation_id;
+    let rate_limit_client = rate_limit_client.as_ref().map(|axum::Extension(client)| client);
+    let results = submit_batch(requests, |index, request| async move {
+        let refusal = |code: &str, message: String| ErrorDetail { code: code.to_string(), message };
+        // The request itself was charged once by `rate_limit`.
+        if let (true, Some(client)) = (index > 0, rate_limit_client) {
+            if let Some(retry_after) = charge_rate_limit(state, client).await {
+                return Err(refusal(
+                    "RATE_LIMITED",
+                    format!(
+                        "More than {} requests per minute; retry in {}s",
+                        state.api_config.rate_limit_requests_per_minute, retry_after
+                    ),
+                ));
+            }
+        }
+        if book_is_full(state, correlation_id_ref).await {
+            return Err(refusal("BOOK_FULL", "The order book is full; retry later".to_string()));
+        }
+        authenticate_intent(state, &request, correlation_id_ref)
+            .await
+            .map_err(|(_, JsonResponse(body))| body.error_detail)?;
+        if let Some(remaining_ms) = claim_submit_interval(state, &request.public_inputs.user).await {
+            return Err(refusal(
+                "SUBMIT_TOO_FREQUENT",
+                format!(
+                    "Submissions must be at least {} ms apart; retry in {} ms",
+                    state.api_config.min_submit_interval_ms, remaining_ms
+                ),
+            ));
+        }
+        let allow_duplicate = request.allow_duplicate;
+        let (intent, warnings) = vet_intent(state, request, correlation_id_ref)
+            .await
+            .map_err(|(_, JsonResponse(body))| body.error_detail)?;
+        admit_intent(state, intent, warnings, allow_duplicate, correlation_id_ref.clone())
+            .await
+            .map_err(|(_, JsonResponse(body))| body.error_detail)
+    })
+    .await;
+
+    let accepted = results.iter().filter(|r| r.success).count();
+    Ok(JsonResponse(BatchSubmitResponse {
+        rejected: results.len() - accepted,
+        accepted,
+        results,
+        correlation_id,
+    }))
+}
+
+/// Runs `submit_one` over a batch in order, with its index, and collects one result per request.
+/// A nullifier repeated within the batch is refused as `DUPLICATE_INTENT` without reaching it.
+async fn submit_batch<F, Fut>(requests: Vec<SubmitIntentRequest>, mut submit_one: F) -> Vec<BatchSubmitResult>
+where
+    F: FnMut(usize, SubmitIntentRequest) -> Fut,
+    Fut: std::future::Future<Output = Result<SubmitIntentResponse, ErrorDetail>>,
+{
+    let repeats = repeated_nullifiers(&requests);
+    let mut results = Vec::with_capacity(requests.len());
+    for (index, request) in requests.into_iter().enumerate() {
+        let nullifier = request.nullifier.clone();
+        let outcome = match repeats[index] {
+            Some(first) => Err(ErrorDetail {
+                code: "DUPLICATE_INTENT".to_string(),
+                message: format!("Same nullifier as intent {} of this batch", first),
+            }),
+            None => submit_one(index, request).await,
+        };
+        results.push(match outcome {
+            Ok(accepted) => BatchSubmitResult {
+                index,
+                nullifier,
+                success: true,
+                intent_id: Some(accepted.intent_id),
+                status: Some(accepted.status),
+                compatible_counterparties: Some(accepted.compatible_counterparties),
+                warnings: accepted.warnings,
+                error: None,
+            },
+            Err(error) => BatchSubmitResult {
+                index,
+                nullifier,
+                success: false,
+                intent_id: None,
+                status: None,
+                compatible_counterparties: None,
+                warnings: Vec::new(),
+                error: Some(error),
+            },
+        });
+    }
+    results
+}
+
+/// For each request, the index of an earlier request in the batch with the same nullifier.
+fn repeated_nullifiers(requests: &[SubmitIntentRequest]) -> Vec<Option<usize>> {
+    let mut first_seen: HashMap<String, usize> = HashMap::new();
+    requests
+        .iter()
+        .enumerate()
+        .map(|(index, request)| {
+            let key = request.nullifier.trim().to_ascii_lowercase();
+            match first_seen.get(&key) {
+                Some(first) => Some(*first),
+                None => {
+                    first_seen.insert(key, index);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Every check a submission passes before it may be stored: `prepare_intent`, then the post-only
+/// guard. The near-duplicate guard runs in `admit_intent`, next to the store it protects.
+async fn vet_intent(
+    state: &AppState,
+    request: SubmitIntentRequest,
+    correlation_id: &str,
+) -> ApiResult<(Intent, Vec<ErrorDetail>)> {
+    let (intent, warnings) = prepare_intent(state, request, correlation_id).await?;
+    reject_crossing(state, &intent, correlation_id).await?;
+    Ok((intent, warnings))
+}
+
+/// Reserves the nonce of a vetted intent, stores it and announces it. Unless `allow_duplicate`,
+/// the near-duplicate check and the store run under the user's submit lock, so two near-identical
+/// submissions racing each other can't both pass the check.
+async fn admit_intent(
+    state: &AppState,
+    intent: Intent,
+    warnings: Vec<ErrorDetail>,
+    allow_duplicate: bool,
+    correlation_id: String,
+) -> ApiResult<SubmitIntentResponse> {
+    if allow_duplicate || state.api_config.near_duplicate_window_seconds == 0 {
+        return store_submission(state, intent, warnings, correlation_id).await;
+    }
+    let user = intent.public_inputs.user.clone();
+    let lock = acquire_submit_lock(state, &user, &correlation_id).await?;
+    let result = match reject_near_duplicate(state, &intent, &correlation_id).await {
+        Ok(()) => store_submission(state, intent, warnings, correlation_id).await,
+        Err(e) => Err(e),
+    };
+    if let Some(token) = lock {
+        if let Err(e) = state.storage.release_submit_lock(&user, &token).await {
+            warn!("Failed to release submit lock for {}: {}", log_address(&user), e);
+        }
+    }
+    result
+}
+
+/// Waits up to `SUBMIT_LOCK_WAIT_MS` for `user`'s submit lock. `None` when Redis can't be asked,
+/// which lets the submission through like the other spam brakes.
+async fn acquire_submit_lock(state: &AppState, user: &str, correlation_id: &str) -> ApiResult<Option<String>> {
+    let started = std::time::Instant::now();
+    loop {
+        match state.storage.try_acquire_submit_lock(user, SUBMIT_LOCK_TTL_MS).await {
+            Ok(Some(token)) => return Ok(Some(token)),
+            Ok(None) if started.elapsed() < std::time::Duration::from_millis(SUBMIT_LOCK_WAIT_MS) => {
+                tokio::time::sleep(std::time::Duration::from_millis(SUBMIT_LOCK_POLL_MS)).await;
+            }
+            Ok(None) => {
+                return Err((
+                    StatusCode::TOO_MANY_REQUESTS,
+                    JsonResponse(error_response(
+                        "SUBMIT_TOO_FREQUENT",
+                        "Another submission by this user is still being stored; retry shortly",
+                        Some(correlation_id.to_string()),
+                    )),
+                ))
+            }
+            Err(e) => {
+                warn!("Failed to take submit lock for {}: {}", log_address(user), e);
+                return Ok(None);
+            }
+        }
     }
+}
 
+/// Reserves the nonce of an admitted intent, stores it and announces it.
+async fn store_submission(
+    state: &AppState,
+    intent: Intent,
+    warnings: Vec<ErrorDetail>,
+    correlation_id: String,
+) -> ApiResult<SubmitIntentResponse> {
+    let nonce_ttl = state.api_config.nonce_mode.reservation_ttl(
+        intent.public_inputs.deadline,
+        chrono::Utc::now().timestamp().max(0) as u64,
+        state.api_config.nonce_strict_window_seconds,
+    );
     match state
         .storage
         .reserve_nonce(
-            &request.public_inputs.user,
-            request.public_inputs.nonce,
-            request.public_inputs.deadline,
+            &intent.public_inputs.user,
+            intent.public_inputs.nonce,
+            nonce_ttl,
         )
         .await
     {
@@ -693,42 +1232,6 @@ async fn submit_intent(
         Ok(true) => {}
     }
 
-    let encrypted_details = match base64::decode(&request.encrypted_details) {
-        Ok(data) => data,
-        Err(_) => {
-            return Err((
-                StatusCode::BAD_REQUEST,
-                JsonResponse(error_response(
-                    "INVALID_ENCODING",
-                    "Invalid encrypted details",
-                    Some(correlation_id),
-                )),
-            ));
-        }
-    };
-
-    let expires_at = chrono::DateTime::<chrono::Utc>::from_timestamp(request.public_inputs.deadline as i64, 0)
-        .ok_or_else(|| {
-            (
-                StatusCode::BAD_REQUEST,
-                JsonResponse(error_response(
-                    "INVALID_DEADLINE",
-                    "Invalid deadline timestamp",
-                    Some(correlation_id.clone()),
-                )),
-            )
-        })?;
-
-    let intent = Intent::new(
-        request.intent_hash,
-        request.nullifier.clone(),
-        request.proof_data,
-        request.proof_public_inputs,
-        request.public_inputs,
-        encrypted_details,
-        expires_at,
-    );
-
     if let Err(e) = state.storage.store_intent(&intent).await {
         error!("Failed to store intent: {}", e);
         return Err((
@@ -740,470 +1243,3348 @@ async fn submit_intent(
             )),
         ));
     }
+    state.events.publish(SolverEvent::Submitted {
+        nullifier: intent.nullifier.clone(),
+        user: intent.public_inputs.user.clone(),
+        token_in: intent.public_inputs.token_in.clone(),
+        token_out: intent.public_inputs.token_out.clone(),
+        timestamp: intent.created_at,
+    });
 
-    Ok(JsonResponse(SubmitIntentResponse {
-        intent_id: intent.id,
+    let compatible_counterparties = resting_counterparties(state, &intent).await;
+    let estimated_match_time = (compatible_counterparties > 0).then(|| {
+        let tick_seconds = state.matching_config.poll_interval_ms.div_ceil(1000).max(1);
+        format!("< {} seconds", tick_seconds)
+    });
+
+    Ok(SubmitIntentResponse {
+        intent_id: intent.id,
         status: intent.status,
-        estimated_match_time: Some("< 30 seconds".to_string()),
+        estimated_match_time,
+        compatible_counterparties,
+        warnings,
         correlation_id,
-    }))
+    })
 }
 
-async fn preflight_verify_intent_proof(
-    state: &AppState,
-    request: &SubmitIntentRequest,
-) -> Result<(), String> {
-    fn parse_felt_any(input: &str) -> Result<Felt, String> {
-        let v = input.trim();
-        if v.is_empty() {
-            return Err("empty felt".to_string());
-        }
-        if v.starts_with("0x") || v.starts_with("0X") {
-            Felt::from_hex(v).map_err(|e| e.to_string())
-        } else {
-            Felt::from_dec_str(v).map_err(|e| e.to_string())
+/// Opposite-side intents resting in the book that `intent` could match now; 0 if the book can't
+/// be read, since this only feeds the submit response's estimate.
+async fn resting_counterparties(state: &AppState, intent: &Intent) -> usize {
+    let opposite = state
+        .storage
+        .get_intents_by_pair(&intent.public_inputs.token_out, &intent.public_inputs.token_in)
+        .await;
+    match opposite {
+        Ok(candidates) => state.matcher.compatible_counterparties(intent, &candidates),
+        Err(e) => {
+            warn!("Failed to read counterparties for intent {}: {}", log_nullifier(&intent.nullifier), e);
+            0
         }
     }
-    fn parse_named_felt(name: &str, input: &str) -> Result<Felt, String> {
-        parse_felt_any(input).map_err(|e| {
-            let v = input.trim();
-            let preview = if v.len() > 96 {
-                format!("{}...", &v[..96])
-            } else {
-                v.to_string()
-            };
-            format!("{} parse error: {} (value={})", name, e, preview)
-        })
-    }
-
-    let selector = get_selector_from_name("submit_intent").map_err(|e| e.to_string())?;
-    let contract = state.dark_pool_address;
-
-    // IntentProof ABI:
-    // [intent_hash, nullifier, proof_data_len, ...proof_data, public_inputs_len, ...public_inputs]
-    let mut calldata: Vec<Felt> = Vec::new();
-    calldata.push(parse_named_felt("intent_hash", &request.intent_hash)?);
-    calldata.push(parse_named_felt("nullifier", &request.nullifier)?);
-    calldata.push(Felt::from(request.proof_data.len() as u64));
-    for (idx, p) in request.proof_data.iter().enumerate() {
-        calldata.push(parse_named_felt(&format!("proof_data[{}]", idx), p)?);
-    }
-    calldata.push(Felt::from(request.proof_public_inputs.len() as u64));
-    for (idx, p) in request.proof_public_inputs.iter().enumerate() {
-        calldata.push(parse_named_felt(&format!("proof_public_inputs[{}]", idx), p)?);
-    }
-
-    let payload = serde_json::json!({
-        "jsonrpc": "2.0",
-        "id": 1,
-        "method": "starknet_call",
-        "params": [
-            {
-                "contract_address": format!("0x{:x}", contract),
-                "entry_point_selector": format!("0x{:x}", selector),
-                "calldata": calldata.into_iter().map(|v| format!("0x{:x}", v)).collect::<Vec<_>>(),
-            },
-            "latest"
-        ]
-    });
-
-    let json: serde_json::Value = reqwest::Client::new()
-        .post(&state.starknet_rpc)
-        .json(&payload)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?
-        .json()
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if let Some(err) = json.get("error") {
-        let msg = err
-            .get("message")
-            .and_then(|v| v.as_str())
-            .map(ToString::to_string)
-            .unwrap_or_else(|| err.to_string());
-        return Err(msg);
-    }
-
-    Ok(())
 }
 
-async fn enforce_balance_allowance_precheck(
-    state: &AppState,
-    request: &SubmitIntentRequest,
-    correlation_id: &str,
-) -> Result<(), (StatusCode, ErrorResponse)> {
-    async fn jsonrpc_starknet_call(
-        rpc_url: &str,
-        contract_address: Felt,
-        selector: Felt,
-        calldata: Vec<Felt>,
-        block_tag: &'static str,
-    ) -> Result<serde_json::Value, reqwest::Error> {
-        let payload = serde_json::json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "starknet_call",
-            "params": [
-                {
-                    "contract_address": format!("0x{:x}", contract_address),
-                    "entry_point_selector": format!("0x{:x}", selector),
-                    "calldata": calldata.into_iter().map(|v| format!("0x{:x}", v)).collect::<Vec<_>>(),
-                },
-                // Use "pending" so approvals/balances reflect mempool state faster.
-                // This reduces "approve 2-3 times" UX issues due to provider propagation delays.
-                block_tag
-            ]
-        });
+/// Nonce-based order management: cancels the user's pending intent carrying `nonce` and stores
+/// the submitted intent (same user and nonce, new nullifier) in its place in one step.
+async fn replace_intent_by_nonce(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(nonce): Path<u64>,
+    Json(request): Json<SubmitIntentRequest>,
+) -> HeaderedApiResult<JsonResponse<ReplaceIntentResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_owner(&headers, &state, &request.public_inputs.user, &correlation_id).await
+        .map_err(IntoResponse::into_response)?;
+    let reject = |status: StatusCode, code: &str, message: &str| {
+        (
+            status,
+            JsonResponse(error_response(code, message, Some(correlation_id.clone()))),
+        )
+            .into_response()
+    };
 
-        reqwest::Client::new().post(rpc_url).json(&payload).send().await?.json().await
+    if request.public_inputs.nonce != nonce {
+        return Err(reject(
+            StatusCode::BAD_REQUEST,
+            "NONCE_MISMATCH",
+            &format!(
+                "public_inputs.nonce ({}) must equal the nonce being replaced ({})",
+                request.public_inputs.nonce, nonce
+            ),
+        ));
     }
 
-    async fn jsonrpc_starknet_call_best_effort(
-        rpc_url: &str,
-        contract_address: Felt,
-        selector: Felt,
-        calldata: Vec<Felt>,
-    ) -> Result<serde_json::Value, reqwest::Error> {
-        // Prefer "pending" so just-submitted approvals reflect faster.
-        // If a provider rejects the block tag (e.g., "Invalid params"), fall back to "latest".
-        let pending = jsonrpc_starknet_call(rpc_url, contract_address, selector, calldata.clone(), "pending").await?;
-        let msg = pending
-            .get("error")
-            .and_then(|e| e.get("message"))
-            .and_then(|m| m.as_str())
-            .unwrap_or("");
-        if msg.to_lowercase().contains("invalid params") || msg.contains("InvalidParams") {
-            return jsonrpc_starknet_call(rpc_url, contract_address, selector, calldata, "latest").await;
-        }
-        Ok(pending)
+    let existing = match state
+        .storage
+        .get_nullifier_for_nonce(&request.public_inputs.user, nonce)
+        .await
+    {
+        Ok(Some(nullifier)) => state.storage.get_intent(&nullifier).await,
+        Ok(None) => Ok(None),
+        Err(e) => Err(e),
     }
-
-    fn jsonrpc_error_message(json: &serde_json::Value) -> Option<String> {
-        let err = json.get("error")?;
-        // Common shape: { "code": ..., "message": "...", "data": ... }
-        if let Some(msg) = err.get("message").and_then(|v| v.as_str()) {
-            return Some(msg.to_string());
-        }
-        Some(err.to_string())
+    .map_err(|e| {
+        error!("Failed to look up intent by nonce: {}", e);
+        reject(StatusCode::INTERNAL_SERVER_ERROR, "QUERY_ERROR", "Failed to look up intent")
+    })?;
+    let Some(existing) = existing else {
+        return Err(reject(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            "No intent with this nonce for the user",
+        ));
+    };
+    if existing.status != IntentStatus::Pending {
+        return Err(reject(
+            StatusCode::CONFLICT,
+            "INTENT_NOT_PENDING",
+            "Only pending intents can be replaced",
+        ));
     }
 
-    fn parse_u256_like(value: &serde_json::Value) -> Option<(String, String)> {
-        // Support a few observed shapes across providers:
-        // 1) ["0xLOW", "0xHIGH"]
-        // 2) ["0xLOW"] (assume HIGH=0)
-        // 3) [{"low":"0x..","high":"0x.."}]
-        // 4) [{"balance":{"low":"0x..","high":"0x.."}}]
-        if let Some(arr) = value.as_array() {
-            if arr.is_empty() {
-                return None;
-            }
-            if arr.len() >= 2 && arr[0].is_string() && arr[1].is_string() {
-                return Some((arr[0].as_str()?.to_string(), arr[1].as_str()?.to_string()));
-            }
-            if arr.len() == 1 && arr[0].is_string() {
-                return Some((arr[0].as_str()?.to_string(), "0x0".to_string()));
-            }
-            if arr.len() >= 1 && arr[0].is_object() {
-                let obj = arr[0].as_object()?;
-                if let (Some(low), Some(high)) = (obj.get("low"), obj.get("high")) {
-                    return Some((low.as_str()?.to_string(), high.as_str()?.to_string()));
-                }
-                if let Some(balance) = obj.get("balance").and_then(|v| v.as_object()) {
-                    let low = balance.get("low")?.as_str()?.to_string();
-                    let high = balance.get("high")?.as_str()?.to_string();
-                    return Some((low, high));
-                }
-            }
-            return None;
+    authenticate_intent(&state, &request, &correlation_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    enforce_submit_interval(&state, &request.public_inputs.user, &correlation_id).await?;
+    let (intent, warnings) = prepare_intent(&state, request, &correlation_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    reject_crossing(&state, &intent, &correlation_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    match state.storage.replace_pending_intent(&existing, &intent).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err(reject(
+                StatusCode::CONFLICT,
+                "INTENT_NOT_PENDING",
+                "Intent was matched or cancelled before it could be replaced",
+            ));
         }
-        if let Some(obj) = value.as_object() {
-            if let (Some(low), Some(high)) = (obj.get("low"), obj.get("high")) {
-                return Some((low.as_str()?.to_string(), high.as_str()?.to_string()));
-            }
-            if let Some(balance) = obj.get("balance").and_then(|v| v.as_object()) {
-                let low = balance.get("low")?.as_str()?.to_string();
-                let high = balance.get("high")?.as_str()?.to_string();
-                return Some((low, high));
-            }
+        Err(e) => {
+            error!("Failed to replace intent {}: {}", log_nullifier(&existing.nullifier), e);
+            return Err(reject(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "STORAGE_ERROR",
+                "Failed to replace intent",
+            ));
         }
-        None
-    }
-
-    fn parse_u256_result(json: &serde_json::Value) -> Option<BigUint> {
-        let result = json.get("result")?;
-        let (low, high) = parse_u256_like(result)?;
-        let low = BigUint::from_str_radix(low.trim_start_matches("0x"), 16).ok()?;
-        let high = BigUint::from_str_radix(high.trim_start_matches("0x"), 16).ok()?;
-        Some(low + (high << 128u32))
     }
 
-    fn parse_felt_result(json: &serde_json::Value) -> Option<BigUint> {
-        let result = json.get("result")?.as_array()?;
-        let v = result.get(0)?.as_str()?;
-        BigUint::from_str_radix(v.trim_start_matches("0x"), 16).ok()
+    // The nonce stays reserved, now for the replacement's deadline.
+    let nonce_ttl = state.api_config.nonce_mode.reservation_ttl(
+        intent.public_inputs.deadline,
+        chrono::Utc::now().timestamp().max(0) as u64,
+        state.api_config.nonce_strict_window_seconds,
+    );
+    if let Err(e) = state
+        .storage
+        .extend_nonce_reservation(&intent.public_inputs.user, nonce, nonce_ttl)
+        .await
+    {
+        warn!("Failed to extend nonce reservation for {}: {}", log_nullifier(&intent.nullifier), e);
     }
 
-    fn parse_units_decimal(amount: &str, decimals: u32) -> Option<BigUint> {
-        let s = amount.trim();
-        if s.is_empty() {
-            return None;
-        }
-        if s.starts_with('-') {
-            return None;
-        }
+    let now = chrono::Utc::now();
+    state.events.publish(SolverEvent::Cancelled {
+        nullifier: existing.nullifier.clone(),
+        timestamp: now,
+    });
+    state.events.publish(SolverEvent::Submitted {
+        nullifier: intent.nullifier.clone(),
+        user: intent.public_inputs.user.clone(),
+        token_in: intent.public_inputs.token_in.clone(),
+        token_out: intent.public_inputs.token_out.clone(),
+        timestamp: intent.created_at,
+    });
+    info!(
+        "Replaced intent {} with {} (nonce {}), correlation_id={}",
+        log_nullifier(&existing.nullifier), log_nullifier(&intent.nullifier), nonce, correlation_id
+    );
 
-        let mut parts = s.splitn(2, '.');
-        let int_part = parts.next().unwrap_or("0").trim();
-        let frac_part = parts.next().unwrap_or("").trim();
+    Ok(JsonResponse(ReplaceIntentResponse {
+        intent_id: intent.id,
+        status: intent.status,
+        replaced_nullifier: existing.nullifier,
+        warnings,
+        correlation_id,
+    }))
+}
 
-        if !int_part.chars().all(|c| c.is_ascii_digit()) {
-            return None;
-        }
-        if !frac_part.chars().all(|c| c.is_ascii_digit()) {
-            return None;
-        }
+/// `MAX_PENDING_INTENTS`: refuses new intents with `503 BOOK_FULL` once the pending set is at the
+/// cap, before any proof work, so a backlog the matcher can't keep up with stops growing. Intents
+/// already pending keep matching. Replacements don't grow the book and skip this. Fails open.
+async fn enforce_book_capacity(state: &AppState, correlation_id: &str) -> HeaderedApiResult<()> {
+    if !book_is_full(state, correlation_id).await {
+        return Ok(());
+    }
+    Err((
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(axum::http::header::RETRY_AFTER, BOOK_FULL_RETRY_AFTER_SECONDS.to_string())],
+        JsonResponse(error_response(
+            "BOOK_FULL",
+            "The order book is full; retry later",
+            Some(correlation_id.to_string()),
+        )),
+    )
+        .into_response())
+}
 
-        let mut frac = frac_part.to_string();
-        if frac.len() > decimals as usize {
-            frac.truncate(decimals as usize);
-        } else {
-            while frac.len() < decimals as usize {
-                frac.push('0');
-            }
+/// Whether the pending set is at `MAX_PENDING_INTENTS`; `false` when uncapped or uncountable.
+async fn book_is_full(state: &AppState, correlation_id: &str) -> bool {
+    let cap = state.api_config.max_pending_intents;
+    if cap == 0 {
+        return false;
+    }
+    match state.storage.pending_count().await {
+        Ok(pending) if pending >= cap => {
+            warn!(
+                "Book full ({} pending, cap {}), refusing submission, correlation_id={}",
+                pending, cap, correlation_id
+            );
+            true
         }
-
-        let digits = format!("{}{}", if int_part.is_empty() { "0" } else { int_part }, frac);
-        let digits = digits.trim_start_matches('0');
-        if digits.is_empty() {
-            return Some(BigUint::from(0u8));
+        Ok(_) => false,
+        Err(e) => {
+            warn!("Failed to count pending intents: {}", e);
+            false
         }
-        BigUint::from_str_radix(digits, 10).ok()
     }
+}
 
-    let token_addr = Felt::from_hex(&request.public_inputs.token_in).map_err(|_| {
-        (
-            StatusCode::BAD_REQUEST,
-            error_response(
-                "INVALID_TOKEN",
-                "token_in must be a felt hex address",
-                Some(correlation_id.to_string()),
-            ),
-        )
-    })?;
-    let user_addr = Felt::from_hex(&request.public_inputs.user).map_err(|_| {
-        (
-            StatusCode::BAD_REQUEST,
-            error_response(
-                "INVALID_USER",
-                "user must be a felt hex address",
-                Some(correlation_id.to_string()),
+/// `409 NEAR_DUPLICATE_INTENT` when the user has a pending intent submitted within
+/// `NEAR_DUPLICATE_WINDOW_SECONDS` that `intent` nearly repeats. A book that can't be read lets the
+/// submission through, like the submit interval.
+async fn reject_near_duplicate(state: &AppState, intent: &Intent, correlation_id: &str) -> ApiResult<()> {
+    let window = state.api_config.near_duplicate_window_seconds;
+    if window == 0 {
+        return Ok(());
+    }
+    let resting = match state.storage.get_intents_by_user(&intent.public_inputs.user).await {
+        Ok(resting) => resting,
+        Err(e) => {
+            warn!("Failed to check near-duplicates for {}: {}", log_address(&intent.public_inputs.user), e);
+            return Ok(());
+        }
+    };
+    let since = chrono::Utc::now() - chrono::Duration::seconds(window.min(i64::MAX as u64) as i64);
+    let tolerance_bps = state.api_config.near_duplicate_tolerance_bps;
+    let Some(existing) = resting.iter().find(|r| {
+        r.status == IntentStatus::Pending
+            && r.created_at >= since
+            && r.nullifier != intent.nullifier
+            && is_near_duplicate(r, intent, tolerance_bps)
+    }) else {
+        return Ok(());
+    };
+    Err((
+        StatusCode::CONFLICT,
+        JsonResponse(error_response(
+            "NEAR_DUPLICATE_INTENT",
+            &format!(
+                "Near-duplicate of pending intent {} (nullifier {}) submitted {}s ago; set allow_duplicate to submit it anyway",
+                existing.id,
+                existing.nullifier,
+                (chrono::Utc::now() - existing.created_at).num_seconds().max(0)
             ),
-        )
-    })?;
-
-    let sel_decimals = get_selector_from_name("decimals").map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            error_response("INTERNAL_ERROR", "Failed to build selector", Some(correlation_id.to_string())),
-        )
-    })?;
-    let sel_balance = get_selector_from_name("balanceOf").map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            error_response("INTERNAL_ERROR", "Failed to build selector", Some(correlation_id.to_string())),
-        )
-    })?;
-    let sel_allowance = get_selector_from_name("allowance").map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            error_response("INTERNAL_ERROR", "Failed to build selector", Some(correlation_id.to_string())),
-        )
-    })?;
+            Some(correlation_id.to_string()),
+        )),
+    ))
+}
 
-    let decimals_json = jsonrpc_starknet_call_best_effort(&state.starknet_rpc, token_addr, sel_decimals, vec![])
+/// `409 WOULD_CROSS` for a `post_only` intent that could match a resting intent right now, by the
+/// matcher's own rules. Unlike the estimate in `resting_counterparties`, a book that can't be read
+/// refuses the intent: letting it through could make it the taker it asked not to be.
+async fn reject_crossing(state: &AppState, intent: &Intent, correlation_id: &str) -> ApiResult<()> {
+    if !intent.post_only {
+        return Ok(());
+    }
+    let opposite = state
+        .storage
+        .get_intents_by_pair(&intent.public_inputs.token_out, &intent.public_inputs.token_in)
         .await
         .map_err(|e| {
-            error!("Precheck decimals RPC failed: {}", e);
+            error!("Failed to read counterparties for post-only intent {}: {}", log_nullifier(&intent.nullifier), e);
             (
-                StatusCode::BAD_GATEWAY,
-                error_response(
-                    "PRECHECK_RPC_ERROR",
-                    "Failed to query token decimals",
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse(error_response(
+                    "QUERY_ERROR",
+                    "Failed to check the book for crossing intents",
                     Some(correlation_id.to_string()),
-                ),
+                )),
             )
         })?;
-    if let Some(msg) = jsonrpc_error_message(&decimals_json) {
-        error!("Precheck decimals JSON-RPC error: {}", msg);
-        return Err((
-            StatusCode::BAD_GATEWAY,
-            error_response(
-                "PRECHECK_RPC_ERROR",
-                "Failed to query token decimals",
-                Some(correlation_id.to_string()),
-            ),
-        ));
+    let crossing = state.matcher.compatible_counterparties(intent, &opposite);
+    if crossing == 0 {
+        return Ok(());
     }
-    let decimals = parse_felt_result(&decimals_json).ok_or_else(|| {
-        (
-            StatusCode::BAD_GATEWAY,
-            error_response(
-                "PRECHECK_RPC_ERROR",
-                "Token decimals response missing fields",
-                Some(correlation_id.to_string()),
-            ),
-        )
-    })?;
-    let decimals_u32: u32 = decimals.to_u32().unwrap_or(18);
-
-    let required = parse_units_decimal(&request.public_inputs.amount_in, decimals_u32).ok_or_else(|| {
-        (
-            StatusCode::BAD_REQUEST,
-            error_response(
-                "INVALID_AMOUNT",
-                "amount_in must be a non-negative decimal string",
-                Some(correlation_id.to_string()),
+    Err((
+        StatusCode::CONFLICT,
+        JsonResponse(error_response(
+            "WOULD_CROSS",
+            &format!(
+                "Post-only intent would match {} resting intent(s) immediately; adjust the price or drop post_only",
+                crossing
             ),
-        )
-    })?;
+            Some(correlation_id.to_string()),
+        )),
+    ))
+}
 
-    let bal_json = jsonrpc_starknet_call_best_effort(
-        &state.starknet_rpc,
-        token_addr,
-        sel_balance,
-        vec![user_addr],
-    )
-    .await
-    .map_err(|e| {
-        error!("Precheck balanceOf RPC failed: {}", e);
-        (
-            StatusCode::BAD_GATEWAY,
-            error_response(
-                "PRECHECK_RPC_ERROR",
-                "Failed to query token balance",
+/// `MIN_SUBMIT_INTERVAL_MS`: rejects a submission (new or replacement) with `SUBMIT_TOO_FREQUENT`
+/// and `Retry-After` when the same user submitted less than the interval ago. Runs after
+/// `authenticate_intent`, so only the user's own signed submissions start their interval, and
+/// before `prepare_intent`, so one that then fails the proof checks still does. Fails open: this is
+/// a spam brake, not a correctness check.
+async fn enforce_submit_interval(
+    state: &AppState,
+    user: &str,
+    correlation_id: &str,
+) -> HeaderedApiResult<()> {
+    let interval_ms = state.api_config.min_submit_interval_ms;
+    match claim_submit_interval(state, user).await {
+        None => Ok(()),
+        Some(remaining_ms) => Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            [(axum::http::header::RETRY_AFTER, remaining_ms.div_ceil(1000).to_string())],
+            JsonResponse(error_response(
+                "SUBMIT_TOO_FREQUENT",
+                &format!(
+                    "Submissions must be at least {} ms apart; retry in {} ms",
+                    interval_ms, remaining_ms
+                ),
                 Some(correlation_id.to_string()),
-            ),
+            )),
         )
-    })?;
-    if let Some(msg) = jsonrpc_error_message(&bal_json) {
-        error!("Precheck balanceOf JSON-RPC error: {}", msg);
-        return Err((
-            StatusCode::BAD_GATEWAY,
-            error_response(
-                "PRECHECK_RPC_ERROR",
-                "Failed to query token balance",
-                Some(correlation_id.to_string()),
-            ),
-        ));
+            .into_response()),
     }
-    let balance = parse_u256_result(&bal_json).ok_or_else(|| {
-        (
-            StatusCode::BAD_GATEWAY,
-            error_response(
-                "PRECHECK_RPC_ERROR",
-                "Token balance response missing fields",
-                Some(correlation_id.to_string()),
-            ),
-        )
-    })?;
+}
 
-    if balance < required {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            error_response(
-                "INSUFFICIENT_BALANCE",
-                "Insufficient token balance for amount_in",
-                Some(correlation_id.to_string()),
-            ),
-        ));
+/// Starts `user`'s `MIN_SUBMIT_INTERVAL_MS`, or returns how many ms of the running one are left.
+/// `None` when the interval is off or can't be checked.
+async fn claim_submit_interval(state: &AppState, user: &str) -> Option<u64> {
+    let interval_ms = state.api_config.min_submit_interval_ms;
+    if interval_ms == 0 {
+        return None;
     }
+    match state.storage.claim_submit_slot(user, interval_ms).await {
+        Ok(remaining_ms) => remaining_ms,
+        Err(e) => {
+            warn!("Failed to check submit interval for {}: {}", log_address(user), e);
+            None
+        }
+    }
+}
 
-    let allowance_json = jsonrpc_starknet_call_best_effort(
-        &state.starknet_rpc,
-        token_addr,
-        sel_allowance,
-        vec![user_addr, state.dark_pool_address],
-    )
-    .await
-    .map_err(|e| {
-        error!("Precheck allowance RPC failed: {}", e);
-        (
-            StatusCode::BAD_GATEWAY,
-            error_response(
-                "PRECHECK_RPC_ERROR",
-                "Failed to query token allowance",
-                Some(correlation_id.to_string()),
+/// Runs every `submit_intent` check without storing anything or reserving the nonce, and
+/// reports all failures together so a client can fix them in one round trip.
+async fn validate_intent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SubmitIntentRequest>,
+) -> ApiResult<JsonResponse<ValidateIntentResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    let mut errors = intent_shape_errors(&state, &request, now, &correlation_id);
+    let mut warnings = Vec::new();
+    if !errors.iter().any(|e| e.code == "INVALID_SIGNATURE") {
+        if let Err((_, JsonResponse(body))) =
+            verify_intent_signature(&state, &request.public_inputs, &request.signature, &correlation_id).await
+        {
+            errors.push(body.error_detail);
+        }
+    }
+    match run_precheck(&state, &request, &correlation_id).await {
+        Ok(warning) => warnings.extend(warning),
+        Err((_, body)) => errors.push(body.error_detail),
+    }
+    if let Some(conflict) = nullifier_conflict(&state, &request, &correlation_id).await {
+        errors.push(conflict);
+    }
+    if !state.api_config.allow_empty_proof && !request.proof_data.is_empty() {
+        if let Err(e) = preflight_verify_intent_proof(
+            &state,
+            &request.intent_hash,
+            &request.nullifier,
+            &request.proof_data,
+            &request.proof_public_inputs,
+            &correlation_id,
+        )
+        .await
+        {
+            let (_, JsonResponse(body)) = match e {
+                PreflightError::Timeout => preflight_timeout_error(&correlation_id),
+                PreflightError::Rejected(reason) => (
+                    StatusCode::BAD_REQUEST,
+                    JsonResponse(error_response(
+                        "INVALID_PROOF",
+                        &format!("Proof preflight verification failed: {}", reason),
+                        None,
+                    )),
+                ),
+            };
+            errors.push(body.error_detail);
+        }
+    }
+
+    if errors.is_empty() {
+        return Ok(JsonResponse(ValidateIntentResponse {
+            valid: true,
+            warnings,
+            correlation_id,
+        }));
+    }
+    let mut body = error_response(
+        "VALIDATION_FAILED",
+        &format!("Intent failed {} validation check(s)", errors.len()),
+        Some(correlation_id),
+    );
+    body.errors = errors;
+    Err((StatusCode::BAD_REQUEST, JsonResponse(body)))
+}
+
+/// `MAX_PROOF_DATA_LEN` / `MAX_PUBLIC_INPUTS_LEN`: both arrays end up in the preflight and
+/// settlement calldata, so oversized ones are refused before anything encodes them.
+fn proof_size_error(
+    config: &ApiConfig,
+    proof_data: &[String],
+    proof_public_inputs: &[String],
+) -> Option<(&'static str, String)> {
+    if proof_data.len() > config.max_proof_data_len {
+        return Some((
+            "PROOF_DATA_TOO_LARGE",
+            format!("proof_data has {} elements (max {})", proof_data.len(), config.max_proof_data_len),
+        ));
+    }
+    if proof_public_inputs.len() > config.max_public_inputs_len {
+        return Some((
+            "TOO_MANY_PUBLIC_INPUTS",
+            format!(
+                "proof_public_inputs has {} elements (max {})",
+                proof_public_inputs.len(),
+                config.max_public_inputs_len
             ),
+        ));
+    }
+    None
+}
+
+/// The request-shape checks of `prepare_intent` that need no RPC or storage, all of them rather
+/// than the first: proof and signature shape, metadata, amounts, deadline and activation time,
+/// proof freshness and bindings, and the encrypted details' encoding. In submission order.
+fn intent_shape_errors(
+    state: &AppState,
+    request: &SubmitIntentRequest,
+    now: u64,
+    correlation_id: &str,
+) -> Vec<ErrorDetail> {
+    let mut errors = Vec::new();
+    let mut fail = |code: &str, message: &str| {
+        errors.push(ErrorDetail {
+            code: code.to_string(),
+            message: message.to_string(),
+        })
+    };
+    if request.proof_data.is_empty() && !state.api_config.allow_empty_proof {
+        fail("INVALID_PROOF", "Invalid proof data (empty)");
+    }
+    // Current Groth16 circuit uses nPublic=3 (VK IC length = 4).
+    // Older payloads may include additional business fields; accept either as long as
+    // minimum verifier-required public signals are present.
+    if !request.proof_public_inputs.is_empty() && request.proof_public_inputs.len() < 3 {
+        fail("INVALID_PUBLIC_INPUTS", "Invalid proof_public_inputs (expected at least 3 elements)");
+    }
+    if let Some((code, message)) = proof_size_error(&state.api_config, &request.proof_data, &request.proof_public_inputs) {
+        fail(code, &message);
+    }
+    if !is_valid_signature(&request.signature) {
+        fail("INVALID_SIGNATURE", "Signature format is invalid");
+    }
+    if request.public_inputs.chain_id.trim().is_empty()
+        || request.public_inputs.domain_separator.trim().is_empty()
+    {
+        fail("INVALID_INTENT_METADATA", "chain_id and domain_separator are required");
+    } else if !is_accepted_domain_separator(
+        &state.api_config.accepted_domain_separators,
+        &request.public_inputs.domain_separator,
+    ) {
+        fail("INVALID_DOMAIN_SEPARATOR", "domain_separator is not one this solver accepts");
+    }
+
+    let amounts = [
+        ("amount_in", &request.public_inputs.amount_in, &request.public_inputs.token_in),
+        ("min_amount_out", &request.public_inputs.min_amount_out, &request.public_inputs.token_out),
+    ];
+    for (field, value, token) in amounts {
+        if let Err(e) = TokenAmount::parse(value, token_decimals_for(token)) {
+            fail("INVALID_AMOUNT", &format!("{}: {}", field, e));
+        }
+    }
+
+    if request.public_inputs.deadline <= now {
+        fail("ERR_EXPIRED_INTENT", "Intent already expired");
+    }
+    if let Some(activate_at) = request.activate_at {
+        if activate_at >= request.public_inputs.deadline {
+            fail("INVALID_ACTIVATION_TIME", "activate_at must be before deadline");
+        }
+    }
+    if request.max_slippage_bps.is_some_and(|bps| !(1..=10_000).contains(&bps)) {
+        fail("INVALID_SLIPPAGE", "max_slippage_bps must be between 1 and 10000");
+    }
+    match (&request.trigger_price, request.trigger_direction) {
+        (None, None) => {}
+        (Some(price), Some(_)) => {
+            if parse_trigger_price(price).is_none() {
+                fail(
+                    "INVALID_TRIGGER",
+                    &format!(
+                        "trigger_price must be a positive decimal price of token_in in token_out, at most {} decimals",
+                        TRIGGER_PRICE_DECIMALS
+                    ),
+                );
+            } else if request.post_only {
+                fail("INVALID_TRIGGER", "post_only intents cannot have a price trigger");
+            }
+        }
+        _ => fail("INVALID_TRIGGER", "trigger_price and trigger_direction must be given together"),
+    }
+    if let Some(url) = &request.callback_url {
+        if state.api_config.webhook_secret.is_none() {
+            fail("CALLBACKS_DISABLED", "This solver does not send settlement callbacks (no WEBHOOK_SECRET)");
+        } else if !is_valid_callback_url(url) {
+            fail("INVALID_CALLBACK_URL", "callback_url must be an absolute http(s) URL");
+        }
+    }
+
+    let checks = [
+        check_proof_freshness(state, &request.proof_public_inputs, now, correlation_id),
+        check_proof_bindings(
+            state,
+            &request.proof_public_inputs,
+            &request.intent_hash,
+            &request.nullifier,
+            correlation_id,
+        ),
+    ];
+    for (_, JsonResponse(body)) in checks.into_iter().filter_map(Result::err) {
+        errors.push(body.error_detail);
+    }
+
+    if base64::decode(&request.encrypted_details).is_err() {
+        errors.push(ErrorDetail {
+            code: "INVALID_ENCODING".to_string(),
+            message: "Invalid encrypted details".to_string(),
+        });
+    }
+    errors
+}
+
+/// `ACCEPTED_DOMAIN_SEPARATORS`: hex separators compare as felts, others exactly. An empty list
+/// accepts anything.
+fn is_accepted_domain_separator(accepted: &[String], separator: &str) -> bool {
+    accepted.is_empty() || accepted.iter().any(|a| same_address(a, separator))
+}
+
+/// Nullifiers are ZK nullifiers and unique across all users (DarkPool keys intents by them), so
+/// the same nullifier from another user is a replay, not a duplicate submission.
+async fn nullifier_conflict(
+    state: &AppState,
+    request: &SubmitIntentRequest,
+    correlation_id: &str,
+) -> Option<ErrorDetail> {
+    let existing = state.storage.get_intent(&request.nullifier).await.ok()??;
+    let (code, message) = if same_address(&existing.public_inputs.user, &request.public_inputs.user) {
+        ("DUPLICATE_INTENT", "Intent already exists")
+    } else {
+        warn!(
+            "Nullifier {} reused by {} (already held by another user), correlation_id={}",
+            log_nullifier(&request.nullifier), log_address(&request.public_inputs.user), correlation_id
+        );
+        ("NULLIFIER_REUSED", "Nullifier was already used by another intent")
+    };
+    Some(ErrorDetail {
+        code: code.to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// The checks that tie a submission to its user: request shape, proof freshness and bindings, and
+/// `verify_intent_signature`. Every submission path runs this first, then `prepare_intent`.
+async fn authenticate_intent(state: &AppState, request: &SubmitIntentRequest, correlation_id: &str) -> ApiResult<()> {
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    if let Some(first) = intent_shape_errors(state, request, now, correlation_id).into_iter().next() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            JsonResponse(error_response(&first.code, &first.message, Some(correlation_id.to_string()))),
+        ));
+    }
+    verify_intent_signature(state, &request.public_inputs, &request.signature, correlation_id).await
+}
+
+/// Everything `submit_intent` checks after `authenticate_intent` before an intent may rest in the
+/// book: prechecks, duplicates and the proof preflight. Nonce reservation and storage are left to
+/// the caller. Also returns the precheck warning under `PRECHECK_MODE=warn`.
+async fn prepare_intent(
+    state: &AppState,
+    request: SubmitIntentRequest,
+    correlation_id: &str,
+) -> ApiResult<(Intent, Vec<ErrorDetail>)> {
+    let skip_proof_verification = state.api_config.allow_empty_proof;
+
+    let warnings: Vec<ErrorDetail> = run_precheck(state, &request, correlation_id)
+        .await
+        .map_err(|(status, body)| (status, JsonResponse(body)))?
+        .into_iter()
+        .collect();
+
+    if let Some(conflict) = nullifier_conflict(state, &request, correlation_id).await {
+        return Err((
+            StatusCode::CONFLICT,
+            JsonResponse(error_response(&conflict.code, &conflict.message, Some(correlation_id.to_string()))),
+        ));
+    }
+
+    // Fail fast for invalid proofs by simulating DarkPool.submit_intent through RPC.
+    // This prevents invalid intents from entering the matching queue and getting stuck in `Matched`.
+    if skip_proof_verification {
+        warn!(
+            "ALLOW_EMPTY_PROOF: accepting intent without proof verification: correlation_id={}, nullifier={}",
+            correlation_id, log_nullifier(&request.nullifier)
+        );
+    } else if let Err(e) = preflight_verify_intent_proof(
+        state,
+        &request.intent_hash,
+        &request.nullifier,
+        &request.proof_data,
+        &request.proof_public_inputs,
+        correlation_id,
+    )
+    .await
+    {
+        let reason = match e {
+            PreflightError::Timeout => {
+                warn!(
+                    "Proof preflight timed out: correlation_id={}, nullifier={}",
+                    correlation_id, log_nullifier(&request.nullifier)
+                );
+                return Err(preflight_timeout_error(correlation_id));
+            }
+            PreflightError::Rejected(reason) => reason,
+        };
+        warn!(
+            "Proof preflight verification failed: correlation_id={}, user={}, nullifier={}, reason={}",
+            correlation_id,
+            log_address(&request.public_inputs.user),
+            log_nullifier(&request.nullifier),
+            reason
+        );
+        return Err((
+            StatusCode::BAD_REQUEST,
+            JsonResponse(error_response(
+                "INVALID_PROOF",
+                &format!("Proof preflight verification failed: {}", reason),
+                Some(correlation_id.to_string()),
+            )),
+        ));
+    }
+
+    let encrypted_details = match base64::decode(&request.encrypted_details) {
+        Ok(data) => data,
+        Err(_) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                JsonResponse(error_response(
+                    "INVALID_ENCODING",
+                    "Invalid encrypted details",
+                    Some(correlation_id.to_string()),
+                )),
+            ));
+        }
+    };
+
+    let expires_at = chrono::DateTime::<chrono::Utc>::from_timestamp(request.public_inputs.deadline as i64, 0)
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                JsonResponse(error_response(
+                    "INVALID_DEADLINE",
+                    "Invalid deadline timestamp",
+                    Some(correlation_id.to_string()),
+                )),
+            )
+        })?;
+
+    let activate_at = match request.activate_at {
+        Some(ts) => Some(
+            i64::try_from(ts)
+                .ok()
+                .and_then(|ts| chrono::DateTime::<chrono::Utc>::from_timestamp(ts, 0))
+                .ok_or_else(|| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        JsonResponse(error_response(
+                            "INVALID_ACTIVATION_TIME",
+                            "Invalid activate_at timestamp",
+                            Some(correlation_id.to_string()),
+                        )),
+                    )
+                })?,
+        ),
+        None => None,
+    };
+
+    let mut intent = Intent::new(
+        request.intent_hash,
+        request.nullifier.clone(),
+        request.proof_data,
+        request.proof_public_inputs,
+        request.public_inputs,
+        encrypted_details,
+        expires_at,
+    );
+    intent.activate_at = activate_at;
+    intent.allow_extension = request.allow_extension;
+    intent.max_slippage_bps = request.max_slippage_bps;
+    intent.callback_url = request.callback_url;
+    intent.post_only = request.post_only;
+    intent.trigger_price = request.trigger_price.map(|p| p.trim().to_string());
+    intent.trigger_direction = request.trigger_direction;
+    reject_untriggerable(state, &intent, correlation_id).await?;
+
+    Ok((intent, warnings))
+}
+
+/// `400 INVALID_TRIGGER` for a conditional intent whose pair Pragma has no price for: its trigger
+/// could never fire. Other oracle errors let it through; the matcher waits for a price.
+async fn reject_untriggerable(state: &AppState, intent: &Intent, correlation_id: &str) -> ApiResult<()> {
+    if intent.trigger_price.is_none() {
+        return Ok(());
+    }
+    let (token_in, token_out) = (&intent.public_inputs.token_in, &intent.public_inputs.token_out);
+    match state.oracle.get_cross_rate(token_in, token_out, None).await {
+        Err(OracleError::InvalidPairId(reason)) => Err((
+            StatusCode::BAD_REQUEST,
+            JsonResponse(error_response(
+                "INVALID_TRIGGER",
+                &format!("No oracle price for {}/{} to trigger on: {}", token_in, token_out, reason),
+                Some(correlation_id.to_string()),
+            )),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The balance/allowance precheck under `PRECHECK_MODE`: `Err` rejects the submission (`enforce`),
+/// while under `warn` a failure, RPC errors included, is logged and handed back as a warning.
+async fn run_precheck(
+    state: &AppState,
+    request: &SubmitIntentRequest,
+    correlation_id: &str,
+) -> Result<Option<ErrorDetail>, (StatusCode, ErrorResponse)> {
+    match state.precheck_mode {
+        PrecheckMode::Off => Ok(None),
+        PrecheckMode::Enforce => enforce_balance_allowance_precheck(state, request, correlation_id)
+            .await
+            .map(|()| None),
+        PrecheckMode::Warn => match enforce_balance_allowance_precheck(state, request, correlation_id).await {
+            Ok(()) => Ok(None),
+            Err((_, body)) => {
+                warn!(
+                    "Precheck failed for {} but accepting (PRECHECK_MODE=warn): {} {}, correlation_id={}",
+                    log_address(&request.public_inputs.user), body.error_detail.code, body.error_detail.message, correlation_id
+                );
+                Ok(Some(body.error_detail))
+            }
+        },
+    }
+}
+
+/// `currentTime` is client-provided; without a freshness bound an old valid proof could be replayed.
+fn check_proof_freshness(
+    state: &AppState,
+    proof_public_inputs: &[String],
+    now: u64,
+    correlation_id: &str,
+) -> ApiResult<()> {
+    let max_proof_age = state.api_config.max_proof_age_seconds;
+    if max_proof_age > 0 && !proof_public_inputs.is_empty() {
+        let Some(current_time) = proof_current_time(proof_public_inputs) else {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                JsonResponse(error_response(
+                    "INVALID_PUBLIC_INPUTS",
+                    "proof_public_inputs[2] (currentTime) must be a unix timestamp",
+                    Some(correlation_id.to_string()),
+                )),
+            ));
+        };
+        if now.saturating_sub(current_time) > max_proof_age {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                JsonResponse(error_response(
+                    "PROOF_TOO_OLD",
+                    &format!(
+                        "Proof currentTime is {}s old (max {}s); generate a fresh proof",
+                        now - current_time,
+                        max_proof_age
+                    ),
+                    Some(correlation_id.to_string()),
+                )),
+            ));
+        }
+        if current_time.saturating_sub(now) > max_proof_age {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                JsonResponse(error_response(
+                    "INVALID_PUBLIC_INPUTS",
+                    "Proof currentTime is ahead of solver time",
+                    Some(correlation_id.to_string()),
+                )),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Stops a proof for one intent being submitted under another's hash or nullifier by checking the
+/// configured `PROOF_PUBLIC_INPUT_BINDINGS` positions.
+fn check_proof_bindings(
+    state: &AppState,
+    proof_public_inputs: &[String],
+    intent_hash: &str,
+    nullifier: &str,
+    correlation_id: &str,
+) -> ApiResult<()> {
+    if proof_public_inputs.is_empty() {
+        return Ok(());
+    }
+    check_proof_input_bindings(
+        &state.api_config.proof_input_bindings,
+        proof_public_inputs,
+        intent_hash,
+        nullifier,
+    )
+    .map_err(|reason| {
+        (
+            StatusCode::BAD_REQUEST,
+            JsonResponse(error_response(
+                "PROOF_INPUT_MISMATCH",
+                &reason,
+                Some(correlation_id.to_string()),
+            )),
+        )
+    })
+}
+
+/// With `VERIFY_INTENT_SIGNATURES` on, asks the user's account contract whether `signature` signs
+/// `intent_message_hash(inputs)`: `is_valid_signature`, then the legacy `isValidSignature` when the
+/// first call fails. Accounts answer `'VALID'`, or `1` on older implementations; a revert, a missing
+/// account or any other answer rejects the intent with `ERR_INVALID_SIGNATURE`.
+async fn verify_intent_signature(
+    state: &AppState,
+    inputs: &PublicInputs,
+    signature: &SignatureInput,
+    correlation_id: &str,
+) -> ApiResult<()> {
+    if !state.api_config.verify_intent_signatures {
+        return Ok(());
+    }
+    let invalid = |message: &str| {
+        (
+            StatusCode::BAD_REQUEST,
+            JsonResponse(error_response("ERR_INVALID_SIGNATURE", message, Some(correlation_id.to_string()))),
+        )
+    };
+    let hash = intent_message_hash(inputs)
+        .map_err(|e| invalid(&format!("Public inputs can't be hashed for signing: {}", e)))?;
+    let account = Felt::from_hex(inputs.user.trim()).map_err(|_| invalid("user must be a hex address"))?;
+    let elements = signature
+        .elements()
+        .iter()
+        .map(|e| Felt::from_hex(e))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid("Signature elements must be felts"))?;
+    let mut calldata = vec![hash, Felt::from(elements.len() as u64)];
+    calldata.extend(elements);
+
+    let mut last_error = String::new();
+    for entrypoint in ["is_valid_signature", "isValidSignature"] {
+        let selector = get_selector_from_name(entrypoint).expect("valid entrypoint name");
+        let json = state
+            .rpc
+            .starknet_call(account, selector, calldata.clone(), Some(correlation_id))
+            .await
+            .map_err(|e| {
+                error!("Signature verification RPC failed: correlation_id={}, error={}", correlation_id, e);
+                (
+                    StatusCode::BAD_GATEWAY,
+                    JsonResponse(error_response(
+                        "RPC_ERROR",
+                        "Failed to reach the node to verify the signature",
+                        Some(correlation_id.to_string()),
+                    )),
+                )
+            })?;
+        if let Some(err) = json.get("error") {
+            last_error = err
+                .get("message")
+                .and_then(|v| v.as_str())
+                .map(ToString::to_string)
+                .unwrap_or_else(|| err.to_string());
+            continue;
+        }
+        let answer = json
+            .get("result")
+            .and_then(|r| r.as_array())
+            .and_then(|r| r.first())
+            .and_then(|v| v.as_str())
+            .and_then(|v| Felt::from_hex(v).ok());
+        let valid = Felt::from_hex("0x56414c4944").expect("'VALID'");
+        return match answer {
+            Some(answer) if answer == valid || answer == Felt::ONE => Ok(()),
+            _ => Err(invalid("The account did not accept the signature")),
+        };
+    }
+    warn!(
+        "Intent signature rejected: correlation_id={}, user={}, reason={}",
+        correlation_id, log_address(&inputs.user), last_error
+    );
+    Err(invalid("The account rejected the signature"))
+}
+
+/// Why the proof preflight did not pass.
+enum PreflightError {
+    /// No answer within `PREFLIGHT_TIMEOUT_MS`; says nothing about the proof.
+    Timeout,
+    Rejected(String),
+}
+
+async fn preflight_verify_intent_proof(
+    state: &AppState,
+    intent_hash: &str,
+    nullifier: &str,
+    proof_data: &[String],
+    proof_public_inputs: &[String],
+    correlation_id: &str,
+) -> Result<(), PreflightError> {
+    let selector =
+        get_selector_from_name("submit_intent").map_err(|e| PreflightError::Rejected(e.to_string()))?;
+    let contract = state.dark_pool_address;
+    let calldata = submit_intent_calldata(intent_hash, nullifier, proof_data, proof_public_inputs)
+        .map_err(PreflightError::Rejected)?;
+
+    let json = state
+        .preflight_rpc
+        .starknet_call(contract, selector, calldata, Some(correlation_id))
+        .await
+        .map_err(|e| {
+            if e.is_timeout() {
+                PreflightError::Timeout
+            } else {
+                PreflightError::Rejected(e.to_string())
+            }
+        })?;
+
+    if let Some(err) = json.get("error") {
+        let msg = err
+            .get("message")
+            .and_then(|v| v.as_str())
+            .map(ToString::to_string)
+            .unwrap_or_else(|| err.to_string());
+        return Err(PreflightError::Rejected(msg));
+    }
+
+    Ok(())
+}
+
+fn preflight_timeout_error(correlation_id: &str) -> (StatusCode, JsonResponse<ErrorResponse>) {
+    (
+        StatusCode::GATEWAY_TIMEOUT,
+        JsonResponse(error_response(
+            "PROOF_PREFLIGHT_TIMEOUT",
+            "Proof verification timed out; the proof was not rejected, retry later",
+            Some(correlation_id.to_string()),
+        )),
+    )
+}
+
+async fn enforce_balance_allowance_precheck(
+    state: &AppState,
+    request: &SubmitIntentRequest,
+    correlation_id: &str,
+) -> Result<(), (StatusCode, ErrorResponse)> {
+    fn jsonrpc_error_message(json: &serde_json::Value) -> Option<String> {
+        let err = json.get("error")?;
+        // Common shape: { "code": ..., "message": "...", "data": ... }
+        if let Some(msg) = err.get("message").and_then(|v| v.as_str()) {
+            return Some(msg.to_string());
+        }
+        Some(err.to_string())
+    }
+
+    fn parse_felt_result(json: &serde_json::Value) -> Option<BigUint> {
+        let result = json.get("result")?.as_array()?;
+        let v = result.get(0)?.as_str()?;
+        BigUint::from_str_radix(v.trim_start_matches("0x"), 16).ok()
+    }
+
+    let token_addr = Felt::from_hex(&request.public_inputs.token_in).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            error_response(
+                "INVALID_TOKEN",
+                "token_in must be a felt hex address",
+                Some(correlation_id.to_string()),
+            ),
+        )
+    })?;
+    let user_addr = Felt::from_hex(&request.public_inputs.user).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            error_response(
+                "INVALID_USER",
+                "user must be a felt hex address",
+                Some(correlation_id.to_string()),
+            ),
+        )
+    })?;
+
+    let sel_decimals = get_selector_from_name("decimals").map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            error_response("INTERNAL_ERROR", "Failed to build selector", Some(correlation_id.to_string())),
+        )
+    })?;
+    let sel_balance = get_selector_from_name("balanceOf").map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            error_response("INTERNAL_ERROR", "Failed to build selector", Some(correlation_id.to_string())),
+        )
+    })?;
+    let sel_allowance = get_selector_from_name("allowance").map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            error_response("INTERNAL_ERROR", "Failed to build selector", Some(correlation_id.to_string())),
+        )
+    })?;
+
+    // Metadata lookups (`GET /v1/tokens/:address/metadata`) already know the decimals.
+    let cached_decimals = state
+        .token_metadata_cache
+        .read()
+        .await
+        .get(&normalize_token(&request.public_inputs.token_in))
+        .map(|m| u32::from(m.decimals));
+    let decimals_u32: u32 = match cached_decimals {
+        Some(decimals) => decimals,
+        None => {
+            let decimals_json = state
+                .rpc
+                .starknet_call(token_addr, sel_decimals, vec![], Some(correlation_id))
+                .await
+                .map_err(|e| {
+                    error!("Precheck decimals RPC failed: {}", e);
+                    (
+                        StatusCode::BAD_GATEWAY,
+                        error_response(
+                            "PRECHECK_RPC_ERROR",
+                            "Failed to query token decimals",
+                            Some(correlation_id.to_string()),
+                        ),
+                    )
+                })?;
+            if let Some(msg) = jsonrpc_error_message(&decimals_json) {
+                error!("Precheck decimals JSON-RPC error: {}", msg);
+                return Err((
+                    StatusCode::BAD_GATEWAY,
+                    error_response(
+                        "PRECHECK_RPC_ERROR",
+                        "Failed to query token decimals",
+                        Some(correlation_id.to_string()),
+                    ),
+                ));
+            }
+            let decimals = parse_felt_result(&decimals_json).ok_or_else(|| {
+                (
+                    StatusCode::BAD_GATEWAY,
+                    error_response(
+                        "PRECHECK_RPC_ERROR",
+                        "Token decimals response missing fields",
+                        Some(correlation_id.to_string()),
+                    ),
+                )
+            })?;
+            decimals.to_u32().unwrap_or(18)
+        }
+    };
+
+    let required = TokenAmount::parse(&request.public_inputs.amount_in, decimals_u32).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            error_response(
+                "INVALID_AMOUNT",
+                &format!("amount_in: {}", e),
+                Some(correlation_id.to_string()),
+            ),
+        )
+    })?;
+
+    let bal_json = state
+        .rpc
+        .starknet_call(token_addr, sel_balance, vec![user_addr], Some(correlation_id))
+        .await
+        .map_err(|e| {
+            error!("Precheck balanceOf RPC failed: {}", e);
+            (
+                StatusCode::BAD_GATEWAY,
+                error_response(
+                    "PRECHECK_RPC_ERROR",
+                    "Failed to query token balance",
+                    Some(correlation_id.to_string()),
+                ),
+            )
+        })?;
+    if let Some(msg) = jsonrpc_error_message(&bal_json) {
+        error!("Precheck balanceOf JSON-RPC error: {}", msg);
+        return Err((
+            StatusCode::BAD_GATEWAY,
+            error_response(
+                "PRECHECK_RPC_ERROR",
+                "Failed to query token balance",
+                Some(correlation_id.to_string()),
+            ),
+        ));
+    }
+    let balance_result = bal_json.get("result").unwrap_or(&serde_json::Value::Null);
+    let balance = parse_u256_json(balance_result).map_err(|e| {
+        warn!("Precheck balance response not a u256: {}", e);
+        (
+            StatusCode::BAD_GATEWAY,
+            error_response(
+                "PRECHECK_RPC_ERROR",
+                "Token balance response missing fields",
+                Some(correlation_id.to_string()),
+            ),
+        )
+    })?;
+
+    if &balance < required.base_units() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            error_response(
+                "INSUFFICIENT_BALANCE",
+                &format!("Insufficient token balance for amount_in (required {})", required),
+                Some(correlation_id.to_string()),
+            ),
+        ));
+    }
+
+    let allowance_json = state
+        .rpc
+        .starknet_call(token_addr, sel_allowance, vec![user_addr, state.dark_pool_address], Some(correlation_id))
+        .await
+        .map_err(|e| {
+            error!("Precheck allowance RPC failed: {}", e);
+            (
+                StatusCode::BAD_GATEWAY,
+                error_response(
+                    "PRECHECK_RPC_ERROR",
+                    "Failed to query token allowance",
+                    Some(correlation_id.to_string()),
+                ),
+            )
+        })?;
+    if let Some(msg) = jsonrpc_error_message(&allowance_json) {
+        error!("Precheck allowance JSON-RPC error: {}", msg);
+        return Err((
+            StatusCode::BAD_GATEWAY,
+            error_response(
+                "PRECHECK_RPC_ERROR",
+                "Failed to query token allowance",
+                Some(correlation_id.to_string()),
+            ),
+        ));
+    }
+    let allowance_result = allowance_json.get("result").unwrap_or(&serde_json::Value::Null);
+    let allowance = parse_u256_json(allowance_result).map_err(|e| {
+        warn!("Precheck allowance response not a u256: {}", e);
+        (
+            StatusCode::BAD_GATEWAY,
+            error_response(
+                "PRECHECK_RPC_ERROR",
+                "Token allowance response missing fields",
+                Some(correlation_id.to_string()),
+            ),
+        )
+    })?;
+
+    if &allowance < required.base_units() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            error_response(
+                "INSUFFICIENT_ALLOWANCE",
+                &format!("Insufficient token allowance for amount_in (required {})", required),
+                Some(correlation_id.to_string()),
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn query_intent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(nullifier): Path<String>,
+) -> ApiResult<JsonResponse<QueryIntentResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+
+    match state.read_storage.get_intent(&nullifier).await {
+        Ok(Some(intent)) => {
+            let view = IntentView {
+                id: intent.id,
+                nullifier: intent.nullifier,
+                user: intent.public_inputs.user,
+                status: intent.status,
+                created_at: intent.created_at,
+                expires_at: intent.expires_at,
+                matched_with: intent.matched_with,
+                settlement_tx_hash: intent.settlement_tx_hash,
+                failure_reason: intent.failure_reason,
+                activate_at: intent.activate_at,
+                allow_extension: intent.allow_extension,
+                max_slippage_bps: intent.max_slippage_bps,
+                post_only: intent.post_only,
+                trigger_price: intent.trigger_price,
+                trigger_direction: intent.trigger_direction,
+                triggered_at: intent.triggered_at,
+                near_miss_count: intent.near_miss_count,
+                last_near_miss_at: intent.last_near_miss_at,
+            };
+            Ok(JsonResponse(QueryIntentResponse { intent: Some(view) }))
+        }
+        Ok(None) => Ok(JsonResponse(QueryIntentResponse { intent: None })),
+        Err(e) => {
+            error!("Failed to query intent: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse(error_response(
+                    "QUERY_ERROR",
+                    "Failed to query intent",
+                    Some(correlation_id),
+                )),
+            ))
+        }
+    }
+}
+
+/// Where `stream_intent_events` is: the next change to send (the snapshot at connect time, first),
+/// the last status sent, and the subscription, dropped once a terminal status went out.
+struct IntentEventsCursor {
+    next: Option<IntentStatusChange>,
+    last: Option<IntentStatus>,
+    changes: Option<tokio::sync::broadcast::Receiver<IntentStatusChange>>,
+}
+
+/// Server-sent `status` events for one intent: its status at connect time, then every transition
+/// this instance writes. The stream ends after a terminal status, so an intent that is already
+/// terminal gets one event.
+async fn stream_intent_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(nullifier): Path<String>,
+) -> ApiResult<Sse<impl futures::Stream<Item = Result<sse::Event, axum::Error>>>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+
+    // Subscribe before reading, so no transition falls between the snapshot and the stream.
+    let changes = state.storage.subscribe_status_changes();
+    let intent = match state.storage.get_intent(&nullifier).await {
+        Ok(Some(intent)) => intent,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                JsonResponse(error_response("NOT_FOUND", "Intent not found", Some(correlation_id))),
+            ))
+        }
+        Err(e) => {
+            error!("Failed to load intent {} for its event stream: {}", log_nullifier(&nullifier), e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse(error_response("QUERY_ERROR", "Failed to query intent", Some(correlation_id))),
+            ));
+        }
+    };
+
+    let storage = state.storage.clone();
+    let nullifier = intent.nullifier.clone();
+    let start = IntentEventsCursor {
+        next: Some(IntentStatusChange::of(&intent)),
+        last: None,
+        changes: Some(changes),
+    };
+    let stream = futures::stream::unfold(start, move |mut cursor| {
+        let storage = storage.clone();
+        let nullifier = nullifier.clone();
+        async move {
+            while cursor.next.is_none() {
+                let changes = cursor.changes.as_mut()?;
+                match changes.recv().await {
+                    Ok(change) if change.nullifier == nullifier => cursor.next = Some(change),
+                    Ok(_) => {}
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        // The skipped changes may include this intent's; catch up from storage.
+                        warn!("Event stream for {} skipped {} status changes", log_nullifier(&nullifier), skipped);
+                        match storage.get_intent(&nullifier).await {
+                            Ok(Some(intent)) => cursor.next = Some(IntentStatusChange::of(&intent)),
+                            Ok(None) => return None,
+                            Err(e) => {
+                                warn!("Event stream for {} could not re-read the intent: {}", log_nullifier(&nullifier), e);
+                                return None;
+                            }
+                        }
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+                }
+                if cursor.next.as_ref().is_some_and(|c| cursor.last.as_ref() == Some(&c.status)) {
+                    cursor.next = None;
+                }
+            }
+            let change = cursor.next.take()?;
+            if change.status.is_terminal() {
+                cursor.changes = None;
+            }
+            cursor.last = Some(change.status.clone());
+            Some((sse::Event::default().event("status").json_data(&change), cursor))
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+async fn query_intent_match(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(nullifier): Path<String>,
+) -> ApiResult<JsonResponse<QueryMatchResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+
+    let query_error = |e: anyhow::Error| {
+        error!("Failed to query match for intent {}: {}", log_nullifier(&nullifier), e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            JsonResponse(error_response(
+                "QUERY_ERROR",
+                "Failed to query match",
+                Some(correlation_id.clone()),
+            )),
+        )
+    };
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            JsonResponse(error_response(
+                "NOT_FOUND",
+                "No active match for this intent",
+                Some(correlation_id.clone()),
+            )),
+        )
+    };
+
+    let intent = state
+        .storage
+        .get_intent(&nullifier)
+        .await
+        .map_err(query_error)?
+        .ok_or_else(not_found)?;
+    let match_id = state
+        .storage
+        .get_match_id_for_nullifier(&nullifier)
+        .await
+        .map_err(query_error)?
+        .ok_or_else(not_found)?;
+    let pair = state
+        .storage
+        .get_matched_pair(&match_id)
+        .await
+        .map_err(query_error)?
+        .ok_or_else(not_found)?;
+    let retry_state = state
+        .storage
+        .get_match_retry_state(&match_id)
+        .await
+        .map_err(query_error)?;
+
+    let counterparty_nullifier = if pair.intent_a.nullifier == nullifier {
+        pair.intent_b.nullifier
+    } else {
+        pair.intent_a.nullifier
+    };
+
+    Ok(JsonResponse(QueryMatchResponse {
+        match_view: MatchView {
+            match_id: pair.id,
+            matched_at: pair.matched_at,
+            nullifier: intent.nullifier,
+            counterparty_nullifier,
+            token_in: intent.public_inputs.token_in,
+            token_out: intent.public_inputs.token_out,
+            status: intent.status,
+            settlement_tx_hash: intent.settlement_tx_hash,
+            settlement_failures: retry_state.as_ref().map(|s| s.failures).unwrap_or(0),
+            next_retry_at_unix: retry_state
+                .as_ref()
+                .map(|s| s.next_retry_at_unix)
+                .filter(|ts| *ts > 0),
+            deferred_reason: retry_state.and_then(|s| s.deferred_reason),
+        },
+    }))
+}
+
+async fn cancel_intent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(nullifier): Path<String>,
+) -> ApiResult<JsonResponse<CancelIntentResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+
+    let intent = state.storage.get_intent(&nullifier).await.map_err(|e| {
+        error!("Failed to fetch intent for cancel: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            JsonResponse(error_response(
+                "QUERY_ERROR",
+                "Failed to fetch intent",
+                Some(correlation_id.clone()),
+            )),
+        )
+    })?;
+
+    let intent = intent.ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            JsonResponse(error_response(
+                "NOT_FOUND",
+                "Intent not found",
+                Some(correlation_id.clone()),
+            )),
+        )
+    })?;
+
+    // Chain state wins: never cancel locally what the DarkPool already settled, and let an
+    // intent the user cancelled on-chain be cancelled here as well, even if it was matched.
+    let onchain_status = onchain_intent_status(&state, &nullifier, &correlation_id).await;
+    if onchain_status == Some(OnchainIntentStatus::Settled) {
+        return Err((
+            StatusCode::CONFLICT,
+            JsonResponse(error_response(
+                "INTENT_SETTLED_ONCHAIN",
+                "Intent is already settled on-chain and cannot be cancelled",
+                Some(correlation_id),
+            )),
+        ));
+    }
+    let cancellable = match intent.status {
+        IntentStatus::Pending => true,
+        IntentStatus::Matched => onchain_status == Some(OnchainIntentStatus::Cancelled),
+        _ => false,
+    };
+    if !cancellable {
+        return Err((
+            StatusCode::CONFLICT,
+            JsonResponse(error_response(
+                "INVALID_STATE",
+                "Only pending intents (or intents cancelled on-chain) can be cancelled",
+                Some(correlation_id),
+            )),
+        ));
+    }
+
+    state
+        .storage
+        .update_intent_status(&nullifier, IntentStatus::Cancelled, None, None)
+        .await
+        .map_err(|e| {
+            error!("Failed to cancel intent: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse(error_response(
+                    "STORAGE_ERROR",
+                    "Failed to cancel intent",
+                    Some(correlation_id.clone()),
+                )),
+            )
+        })?;
+    state.events.publish(SolverEvent::Cancelled {
+        nullifier: nullifier.clone(),
+        timestamp: chrono::Utc::now(),
+    });
+
+    // A matched intent cancelled on-chain can never settle; stop retrying its match and release
+    // the counterparty from it.
+    if intent.status == IntentStatus::Matched {
+        abandon_match_of_cancelled_intent(&state, &nullifier, intent.matched_with.as_deref()).await;
+    }
+
+    Ok(JsonResponse(CancelIntentResponse {
+        success: true,
+        correlation_id,
+        message: "Intent cancelled".to_string(),
+        onchain_status,
+    }))
+}
+
+async fn abandon_match_of_cancelled_intent(state: &AppState, nullifier: &str, counterparty: Option<&str>) {
+    const REASON: &str = "COUNTERPARTY_CANCELLED";
+    let now = chrono::Utc::now();
+    let match_id = match state.storage.get_match_id_for_nullifier(nullifier).await {
+        Ok(Some(match_id)) => {
+            match state.storage.deadletter_match(&match_id).await {
+                Ok(()) => state.events.publish(SolverEvent::MatchDeadlettered {
+                    match_id: match_id.clone(),
+                    reason: REASON.to_string(),
+                    timestamp: now,
+                }),
+                Err(e) => warn!("Failed to deadletter match {} of cancelled intent {}: {}", match_id, log_nullifier(nullifier), e),
+            }
+            Some(match_id)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            warn!("Failed to look up match of cancelled intent {}: {}", log_nullifier(nullifier), e);
+            None
+        }
+    };
+    if let Some(counterparty) = counterparty {
+        release_counterparty(state, counterparty, nullifier, match_id, REASON).await;
+    }
+}
+
+/// The counterparty of an intent that walked out of their match did nothing wrong: put it back in
+/// the book under `REQUEUE_FAILED_MATCHES`, otherwise fail it with `reason`.
+async fn release_counterparty(
+    state: &AppState,
+    counterparty: &str,
+    nullifier: &str,
+    match_id: Option<String>,
+    reason: &str,
+) {
+    let now = chrono::Utc::now();
+    if state.matching_config.requeue_failed_matches {
+        let cooldown_until =
+            now + chrono::Duration::seconds(state.matching_config.failed_pair_cooldown_seconds as i64);
+        match state.storage.requeue_intent(counterparty, nullifier, cooldown_until).await {
+            Ok(true) => state.events.publish(SolverEvent::Requeued {
+                nullifier: counterparty.to_string(),
+                match_id: match_id.unwrap_or_default(),
+                reason: reason.to_string(),
+                timestamp: now,
+            }),
+            Ok(false) => {}
+            Err(e) => warn!("Failed to requeue counterparty {}: {}", log_nullifier(counterparty), e),
+        }
+        return;
+    }
+    match state.storage.mark_intent_failed(counterparty, reason).await {
+        Ok(()) => state.events.publish(SolverEvent::Failed {
+            nullifier: counterparty.to_string(),
+            reason: reason.to_string(),
+            timestamp: now,
+        }),
+        Err(e) => warn!("Failed to mark counterparty {} failed: {}", log_nullifier(counterparty), e),
+    }
+}
+
+/// Lets the owner of an intent stuck in a failed or dead-lettered match walk away from it: the
+/// match is discarded and the intent cancelled, once the chain confirms it never settled.
+async fn abandon_intent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(nullifier): Path<String>,
+) -> ApiResult<JsonResponse<AbandonIntentResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    let reject = |status: StatusCode, code: &str, message: &str| {
+        (status, JsonResponse(error_response(code, message, Some(correlation_id.clone()))))
+    };
+    let storage_error = |e: anyhow::Error| {
+        error!("Failed to abandon intent {}: {}", log_nullifier(&nullifier), e);
+        reject(StatusCode::INTERNAL_SERVER_ERROR, "STORAGE_ERROR", "Failed to abandon intent")
+    };
+
+    let intent = state
+        .storage
+        .get_intent(&nullifier)
+        .await
+        .map_err(storage_error)?
+        .ok_or_else(|| reject(StatusCode::NOT_FOUND, "NOT_FOUND", "Intent not found"))?;
+    require_owner(&headers, &state, &intent.public_inputs.user, &correlation_id).await?;
+
+    match intent.status {
+        IntentStatus::Matched | IntentStatus::Failed => {}
+        IntentStatus::Pending => {
+            return Err(reject(
+                StatusCode::CONFLICT,
+                "INVALID_STATE",
+                "Intent is not matched; cancel it with POST /v1/intents/:nullifier/cancel",
+            ));
+        }
+        _ => {
+            return Err(reject(
+                StatusCode::CONFLICT,
+                "INVALID_STATE",
+                "Only matched or failed intents can be abandoned",
+            ));
+        }
+    }
+
+    // A match the retry loop is still working on isn't stuck. Dead-lettered matches have no
+    // nullifier index entry any more, so they are looked up in the deadletter set.
+    if intent.status == IntentStatus::Matched {
+        let active = state.storage.get_match_id_for_nullifier(&nullifier).await.map_err(storage_error)?;
+        if active.is_some() {
+            return Err(reject(
+                StatusCode::CONFLICT,
+                "MATCH_NOT_STUCK",
+                "The intent's match is still being settled; it can be abandoned once it fails",
+            ));
+        }
+    }
+    let stuck_match = state
+        .storage
+        .get_deadletter_matches()
+        .await
+        .map_err(storage_error)?
+        .into_iter()
+        .find(|pair| pair.intent_a.nullifier == nullifier || pair.intent_b.nullifier == nullifier);
+
+    let onchain_status = match onchain_intent_status(&state, &nullifier, &correlation_id).await {
+        Some(OnchainIntentStatus::Settled) => {
+            return Err(reject(
+                StatusCode::CONFLICT,
+                "INTENT_SETTLED_ONCHAIN",
+                "Intent is already settled on-chain and cannot be abandoned",
+            ));
+        }
+        Some(status) => status,
+        None => {
+            return Err(reject(
+                StatusCode::BAD_GATEWAY,
+                "RPC_ERROR",
+                "Could not confirm on-chain that the intent is unsettled; try again",
+            ));
+        }
+    };
+
+    // A sent settlement may still land; discarding the match now would orphan it.
+    if let Some(pair) = &stuck_match {
+        if state.storage.get_settling_marker(&pair.id).await.map_err(storage_error)?.is_some() {
+            return Err(reject(
+                StatusCode::CONFLICT,
+                "SETTLEMENT_IN_PROGRESS",
+                "A settlement transaction for this match was sent and hasn't resolved yet",
+            ));
+        }
+    }
+
+    // Hold the settlement lock while discarding, so a manual confirm can't slip in.
+    let lock = match &stuck_match {
+        Some(pair) => Some(
+            state
+                .storage
+                .try_acquire_match_lock(&pair.id, state.matching_config.settlement_lock_ttl_seconds)
+                .await
+                .map_err(storage_error)?
+                .ok_or_else(|| {
+                    reject(
+                        StatusCode::CONFLICT,
+                        "SETTLEMENT_IN_PROGRESS",
+                        "A settlement attempt for this match is in progress",
+                    )
+                })?,
+        ),
+        None => None,
+    };
+    let result = async {
+        if let Some(pair) = &stuck_match {
+            state.storage.discard_match(&pair.id).await?;
+        }
+        state
+            .storage
+            .update_intent_status(&nullifier, IntentStatus::Cancelled, None, None)
+            .await
+    }
+    .await;
+    if let (Some(pair), Some(token)) = (&stuck_match, &lock) {
+        if let Err(e) = state.storage.release_match_lock(&pair.id, token).await {
+            warn!("Failed to release settlement lock for match {}: {}", pair.id, e);
+        }
+    }
+    result.map_err(storage_error)?;
+
+    info!(
+        "Intent {} abandoned by its owner (match {:?}): correlation_id={}",
+        log_nullifier(&nullifier),
+        stuck_match.as_ref().map(|p| &p.id),
+        correlation_id
+    );
+    state.events.publish(SolverEvent::Cancelled {
+        nullifier: nullifier.clone(),
+        timestamp: chrono::Utc::now(),
+    });
+
+    let match_id = stuck_match.as_ref().map(|pair| pair.id.clone());
+    if let Some(pair) = &stuck_match {
+        let counterparty = if pair.intent_a.nullifier == nullifier {
+            &pair.intent_b.nullifier
+        } else {
+            &pair.intent_a.nullifier
+        };
+        let still_matched = matches!(
+            state.storage.get_intent(counterparty).await,
+            Ok(Some(ref i)) if i.status == IntentStatus::Matched
+        );
+        if still_matched {
+            let reason = "COUNTERPARTY_ABANDONED";
+            release_counterparty(&state, counterparty, &nullifier, match_id.clone(), reason).await;
+        }
+    }
+
+    Ok(JsonResponse(AbandonIntentResponse {
+        success: true,
+        nullifier,
+        match_id,
+        onchain_status,
+        message: "Intent cancelled; no settlement will be submitted for it".to_string(),
+        correlation_id,
+    }))
+}
+
+/// Lets either participant tear down their match, e.g. when its settlement is stuck: their own
+/// intent is cancelled and the counterparty released under the requeue policy. An admin session
+/// acting on a match it is no party to releases both intents. Nothing changes if either intent
+/// settled on-chain, or if the chain can't be read.
+async fn cancel_match(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(match_id): Path<String>,
+) -> ApiResult<JsonResponse<CancelMatchResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    let subject = require_auth(&headers, &state, &correlation_id).await?;
+    let reject = |status: StatusCode, code: &str, message: &str| {
+        (status, JsonResponse(error_response(code, message, Some(correlation_id.clone()))))
+    };
+    let storage_error = |e: anyhow::Error| {
+        error!("Failed to cancel match {}: {}", match_id, e);
+        reject(StatusCode::INTERNAL_SERVER_ERROR, "STORAGE_ERROR", "Failed to cancel match")
+    };
+
+    if !is_valid_match_id(&match_id) {
+        return Err(reject(
+            StatusCode::BAD_REQUEST,
+            "INVALID_MATCH_ID",
+            "match_id must be a UUID or a 0x-prefixed hash",
+        ));
+    }
+    // Dead-lettered matches keep their payload, so this finds stuck matches as well.
+    let pair = state
+        .storage
+        .get_matched_pair(&match_id)
+        .await
+        .map_err(storage_error)?
+        .ok_or_else(|| reject(StatusCode::NOT_FOUND, "NOT_FOUND", "Match not found"))?;
+
+    let owns_a = subject_owns_user(&state.api_config, &subject, &pair.intent_a.public_inputs.user);
+    let owns_b = subject_owns_user(&state.api_config, &subject, &pair.intent_b.public_inputs.user);
+    let (a, b) = (pair.intent_a.nullifier.as_str(), pair.intent_b.nullifier.as_str());
+    // Each released intent comes with the counterparty it is cooled down against.
+    let (cancelled, released) = match (owns_a, owns_b) {
+        (true, false) => (Some(a), vec![(b, a)]),
+        (false, true) => (Some(b), vec![(a, b)]),
+        // Only admin sessions (or auth being off) own both sides.
+        (true, true) => (None, vec![(a, b), (b, a)]),
+        (false, false) => {
+            return Err(reject(
+                StatusCode::FORBIDDEN,
+                "FORBIDDEN",
+                "Only a participant in the match can cancel it",
+            ));
+        }
+    };
+
+    for nullifier in [a, b] {
+        let intent = state.storage.get_intent(nullifier).await.map_err(storage_error)?;
+        if intent.is_some_and(|i| i.status == IntentStatus::Settled) {
+            return Err(reject(
+                StatusCode::CONFLICT,
+                "MATCH_ALREADY_SETTLED",
+                "The match has already settled and cannot be cancelled",
+            ));
+        }
+        match onchain_intent_status(&state, nullifier, &correlation_id).await {
+            Some(OnchainIntentStatus::Settled) => {
+                return Err(reject(
+                    StatusCode::CONFLICT,
+                    "INTENT_SETTLED_ONCHAIN",
+                    "An intent in the match is already settled on-chain; the match cannot be cancelled",
+                ));
+            }
+            Some(_) => {}
+            None => {
+                return Err(reject(
+                    StatusCode::BAD_GATEWAY,
+                    "RPC_ERROR",
+                    "Could not confirm on-chain that the match is unsettled; try again",
+                ));
+            }
+        }
+    }
+
+    // A sent settlement may still land; discarding the match now would orphan it.
+    if state.storage.get_settling_marker(&match_id).await.map_err(storage_error)?.is_some() {
+        return Err(reject(
+            StatusCode::CONFLICT,
+            "SETTLEMENT_IN_PROGRESS",
+            "A settlement transaction for this match was sent and hasn't resolved yet",
+        ));
+    }
+
+    // Hold the settlement lock while discarding, so neither the retry loop nor a manual confirm
+    // can slip in.
+    let lock = state
+        .storage
+        .try_acquire_match_lock(&match_id, state.matching_config.settlement_lock_ttl_seconds)
+        .await
+        .map_err(storage_error)?
+        .ok_or_else(|| {
+            reject(
+                StatusCode::CONFLICT,
+                "SETTLEMENT_IN_PROGRESS",
+                "A settlement attempt for this match is in progress",
+            )
+        })?;
+    let result = async {
+        // The match may have settled between the checks above and taking the lock.
+        if state.storage.get_matched_pair(&match_id).await?.is_none() {
+            return Ok(false);
+        }
+        state.storage.discard_match(&match_id).await?;
+        if let Some(nullifier) = cancelled {
+            state
+                .storage
+                .update_intent_status(nullifier, IntentStatus::Cancelled, None, None)
+                .await?;
+        }
+        Ok::<_, anyhow::Error>(true)
+    }
+    .await;
+    if let Err(e) = state.storage.release_match_lock(&match_id, &lock).await {
+        warn!("Failed to release settlement lock for match {}: {}", match_id, e);
+    }
+    if !result.map_err(storage_error)? {
+        return Err(reject(
+            StatusCode::CONFLICT,
+            "MATCH_ALREADY_SETTLED",
+            "The match has already settled and cannot be cancelled",
+        ));
+    }
+
+    info!(
+        "Match {} cancelled by {} (cancelled intent {:?}): correlation_id={}",
+        match_id,
+        subject,
+        cancelled.map(|n| log_nullifier(n).to_string()),
+        correlation_id
+    );
+    if let Some(nullifier) = cancelled {
+        state.events.publish(SolverEvent::Cancelled {
+            nullifier: nullifier.to_string(),
+            timestamp: chrono::Utc::now(),
+        });
+    }
+
+    let mut released_intents = Vec::new();
+    for (nullifier, counterparty) in released {
+        let still_matched = matches!(
+            state.storage.get_intent(nullifier).await,
+            Ok(Some(ref i)) if i.status == IntentStatus::Matched
+        );
+        if still_matched {
+            release_counterparty(&state, nullifier, counterparty, Some(match_id.clone()), "MATCH_CANCELLED").await;
+            released_intents.push(nullifier.to_string());
+        }
+    }
+
+    Ok(JsonResponse(CancelMatchResponse {
+        success: true,
+        cancelled: cancelled.map(str::to_string),
+        released: released_intents,
+        message: "Match cancelled; no settlement will be submitted for it".to_string(),
+        match_id,
+        correlation_id,
+    }))
+}
+
+/// Admin: hard-deletes an intent with everything indexed under it, including its match, e.g. for a
+/// deletion request. Refused while a settlement for it is in flight. The counterparty of a deleted
+/// match is released under the requeue policy.
+async fn purge_intent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(nullifier): Path<String>,
+) -> ApiResult<JsonResponse<PurgeIntentResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    let subject = require_admin(&headers, &state, &correlation_id).await?;
+    let reject = |status: StatusCode, code: &str, message: &str| {
+        (status, JsonResponse(error_response(code, message, Some(correlation_id.clone()))))
+    };
+    let storage_error = |e: anyhow::Error| {
+        error!("Failed to purge intent {}: {}", log_nullifier(&nullifier), e);
+        reject(StatusCode::INTERNAL_SERVER_ERROR, "STORAGE_ERROR", "Failed to purge intent")
+    };
+    let in_flight = || {
+        reject(
+            StatusCode::CONFLICT,
+            "SETTLEMENT_IN_PROGRESS",
+            "A settlement for this intent is in flight; try again once it has settled or failed",
+        )
+    };
+
+    let intent = state
+        .storage
+        .get_intent(&nullifier)
+        .await
+        .map_err(storage_error)?
+        .ok_or_else(|| reject(StatusCode::NOT_FOUND, "NOT_FOUND", "Intent not found"))?;
+    // A submitted but unrecorded settlement transaction.
+    if intent.status == IntentStatus::Matched && intent.settlement_tx_hash.is_some() {
+        return Err(in_flight());
+    }
+    let match_id = state
+        .storage
+        .get_match_id_for_nullifier(&nullifier)
+        .await
+        .map_err(storage_error)?;
+    let pair = match &match_id {
+        Some(id) => state.storage.get_matched_pair(id).await.map_err(storage_error)?,
+        None => None,
+    };
+    let lock = match &match_id {
+        Some(id) => Some(
+            state
+                .storage
+                .try_acquire_match_lock(id, state.matching_config.settlement_lock_ttl_seconds)
+                .await
+                .map_err(storage_error)?
+                .ok_or_else(in_flight)?,
+        ),
+        None => None,
+    };
+
+    // The lock only spans the send; a transaction still waiting for its receipt has a marker.
+    let settling = match &match_id {
+        Some(id) => state.storage.get_settling_marker(id).await.map(|marker| marker.is_some()),
+        None => Ok(false),
+    };
+    let result = match settling {
+        Ok(false) => state.storage.purge_intent(&intent, match_id.as_deref()).await.map_err(storage_error),
+        Ok(true) => Err(in_flight()),
+        Err(e) => Err(storage_error(e)),
+    };
+    if let (Some(id), Some(lock)) = (&match_id, &lock) {
+        if let Err(e) = state.storage.release_match_lock(id, lock).await {
+            warn!("Failed to release settlement lock for match {}: {}", id, e);
+        }
+    }
+    let removed = result?;
+    warn!(
+        "Intent {} purged by {} (match {:?}, {} keys touched), correlation_id={}",
+        log_nullifier(&nullifier),
+        subject,
+        match_id,
+        removed.len(),
+        correlation_id
+    );
+
+    let mut released = None;
+    if let Some(pair) = pair {
+        let counterparty = if pair.intent_a.nullifier == nullifier {
+            &pair.intent_b.nullifier
+        } else {
+            &pair.intent_a.nullifier
+        };
+        let still_matched = matches!(
+            state.storage.get_intent(counterparty).await,
+            Ok(Some(ref i)) if i.status == IntentStatus::Matched
+        );
+        if still_matched {
+            release_counterparty(&state, counterparty, &nullifier, match_id.clone(), "COUNTERPARTY_PURGED").await;
+            released = Some(counterparty.clone());
+        }
+    }
+
+    Ok(JsonResponse(PurgeIntentResponse {
+        success: true,
+        nullifier,
+        match_id,
+        removed,
+        released,
+        correlation_id,
+    }))
+}
+
+/// `DarkPool.get_intent_status` for `nullifier`, or `None` if the chain could not be read.
+async fn onchain_intent_status(
+    state: &AppState,
+    nullifier: &str,
+    correlation_id: &str,
+) -> Option<OnchainIntentStatus> {
+    let nullifier_felt = Felt::from_hex(nullifier.trim()).ok()?;
+    let selector = get_selector_from_name("get_intent_status").ok()?;
+    let json = match state
+        .rpc
+        .starknet_call(state.dark_pool_address, selector, vec![nullifier_felt], Some(correlation_id))
+        .await
+    {
+        Ok(json) => json,
+        Err(e) => {
+            warn!("get_intent_status RPC failed for {}: {}", log_nullifier(nullifier), e);
+            return None;
+        }
+    };
+    if let Some(err) = json.get("error") {
+        warn!("get_intent_status returned an error for {}: {}", log_nullifier(nullifier), err);
+        return None;
+    }
+    json.get("result")
+        .and_then(|v| v.as_array())
+        .and_then(|a| a.first())
+        .and_then(|v| v.as_str())
+        .and_then(|v| Felt::from_hex(v).ok())
+        .and_then(OnchainIntentStatus::from_felt)
+}
+
+async fn extend_intent(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(nullifier): Path<String>,
+    Json(request): Json<ExtendIntentRequest>,
+) -> ApiResult<JsonResponse<ActionResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+
+    let reject = |status: StatusCode, code: &str, message: &str| {
+        (
+            status,
+            JsonResponse(error_response(code, message, Some(correlation_id.clone()))),
+        )
+    };
+
+    let max_extension = state.matching_config.max_intent_extension_seconds;
+    if max_extension == 0 {
+        return Err(reject(
+            StatusCode::BAD_REQUEST,
+            "EXTENSION_NOT_ALLOWED",
+            "Intent extensions are disabled on this solver",
+        ));
+    }
+
+    let mut intent = state
+        .storage
+        .get_intent(&nullifier)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch intent for extension: {}", e);
+            reject(StatusCode::INTERNAL_SERVER_ERROR, "QUERY_ERROR", "Failed to fetch intent")
+        })?
+        .ok_or_else(|| reject(StatusCode::NOT_FOUND, "NOT_FOUND", "Intent not found"))?;
+
+    if !intent.is_open() {
+        return Err(reject(
+            StatusCode::CONFLICT,
+            "INVALID_STATE",
+            "Only pending, unexpired intents can be extended",
+        ));
+    }
+    if !intent.allow_extension {
+        return Err(reject(
+            StatusCode::BAD_REQUEST,
+            "EXTENSION_NOT_ALLOWED",
+            "Intent was not submitted with allow_extension",
+        ));
+    }
+
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    let window = state.matching_config.near_miss_window_seconds;
+    let current_deadline = intent.public_inputs.deadline;
+    if current_deadline.saturating_sub(now) > window {
+        return Err(reject(
+            StatusCode::CONFLICT,
+            "EXTENSION_TOO_EARLY",
+            &format!("Intents can only be extended within {}s of their deadline", window),
+        ));
+    }
+    let recent_near_miss = intent
+        .last_near_miss_at
+        .map_or(false, |at| now.saturating_sub(at.timestamp().max(0) as u64) <= window);
+    if !recent_near_miss {
+        return Err(reject(
+            StatusCode::CONFLICT,
+            "NO_RECENT_NEAR_MISS",
+            "Intent has no recent near-miss to justify an extension",
+        ));
+    }
+
+    let new_deadline = request.public_inputs.deadline;
+    if new_deadline <= current_deadline || new_deadline > current_deadline.saturating_add(max_extension) {
+        return Err(reject(
+            StatusCode::BAD_REQUEST,
+            "INVALID_DEADLINE",
+            &format!("deadline must be later than the current one by at most {}s", max_extension),
+        ));
+    }
+    let mut expected_inputs = intent.public_inputs.clone();
+    expected_inputs.deadline = new_deadline;
+    if request.public_inputs != expected_inputs {
+        return Err(reject(
+            StatusCode::BAD_REQUEST,
+            "INVALID_PUBLIC_INPUTS",
+            "Only the deadline may change when extending an intent",
+        ));
+    }
+
+    // The new deadline is part of the proven statement, so a fresh proof is required.
+    if request.proof_data.is_empty() || request.proof_public_inputs.len() < 3 {
+        return Err(reject(
+            StatusCode::BAD_REQUEST,
+            "INVALID_PROOF",
+            "A fresh proof with at least 3 public inputs is required",
+        ));
+    }
+    if let Some((code, message)) = proof_size_error(&state.api_config, &request.proof_data, &request.proof_public_inputs) {
+        return Err(reject(StatusCode::BAD_REQUEST, code, &message));
+    }
+    if !is_valid_signature(&request.signature) {
+        return Err(reject(StatusCode::BAD_REQUEST, "INVALID_SIGNATURE", "Signature format is invalid"));
+    }
+    verify_intent_signature(&state, &request.public_inputs, &request.signature, &correlation_id).await?;
+    check_proof_freshness(&state, &request.proof_public_inputs, now, &correlation_id)?;
+    check_proof_bindings(
+        &state,
+        &request.proof_public_inputs,
+        &request.intent_hash,
+        &intent.nullifier,
+        &correlation_id,
+    )?;
+
+    if let Err(e) = preflight_verify_intent_proof(
+        &state,
+        &request.intent_hash,
+        &intent.nullifier,
+        &request.proof_data,
+        &request.proof_public_inputs,
+        &correlation_id,
+    )
+    .await
+    {
+        let reason = match e {
+            PreflightError::Timeout => {
+                warn!(
+                    "Extension proof preflight timed out: correlation_id={}, nullifier={}",
+                    correlation_id, log_nullifier(&intent.nullifier)
+                );
+                return Err(preflight_timeout_error(&correlation_id));
+            }
+            PreflightError::Rejected(reason) => reason,
+        };
+        warn!(
+            "Extension proof preflight failed: correlation_id={}, nullifier={}, reason={}",
+            correlation_id, log_nullifier(&intent.nullifier), reason
+        );
+        return Err(reject(
+            StatusCode::BAD_REQUEST,
+            "INVALID_PROOF",
+            &format!("Proof preflight verification failed: {}", reason),
+        ));
+    }
+
+    let expires_at = chrono::DateTime::<chrono::Utc>::from_timestamp(new_deadline as i64, 0)
+        .ok_or_else(|| reject(StatusCode::BAD_REQUEST, "INVALID_DEADLINE", "Invalid deadline timestamp"))?;
+
+    let read_deadline = intent.public_inputs.deadline;
+    intent.intent_hash = request.intent_hash;
+    intent.proof_data = request.proof_data;
+    intent.proof_public_inputs = request.proof_public_inputs;
+    intent.public_inputs = request.public_inputs;
+    intent.expires_at = expires_at;
+
+    let extended = state.storage.store_extended_intent(&intent, read_deadline).await.map_err(|e| {
+        error!("Failed to store extended intent: {}", e);
+        reject(StatusCode::INTERNAL_SERVER_ERROR, "STORAGE_ERROR", "Failed to extend intent")
+    })?;
+    if !extended {
+        return Err(reject(
+            StatusCode::CONFLICT,
+            "INVALID_STATE",
+            "The intent was matched, cancelled or extended meanwhile; fetch it and retry",
+        ));
+    }
+
+    // Keep the nonce reserved for the new deadline too.
+    let nonce_ttl = state.api_config.nonce_mode.reservation_ttl(
+        new_deadline,
+        now,
+        state.api_config.nonce_strict_window_seconds,
+    );
+    if let Err(e) = state
+        .storage
+        .extend_nonce_reservation(&intent.public_inputs.user, intent.public_inputs.nonce, nonce_ttl)
+        .await
+    {
+        warn!("Failed to extend nonce reservation for {}: {}", log_nullifier(&intent.nullifier), e);
+    }
+
+    state.events.publish(SolverEvent::Extended {
+        nullifier: intent.nullifier.clone(),
+        deadline: new_deadline,
+        timestamp: chrono::Utc::now(),
+    });
+    info!(
+        "Extended intent {} deadline {} -> {}, near_misses={}, correlation_id={}",
+        log_nullifier(&intent.nullifier), current_deadline, new_deadline, intent.near_miss_count, correlation_id
+    );
+
+    Ok(JsonResponse(ActionResponse {
+        success: true,
+        correlation_id,
+        message: format!("Intent deadline extended to {}", new_deadline),
+        outputs: None,
+    }))
+}
+
+/// `202 Accepted` once the settlement is sent: the intents become `settled` when the transaction is
+/// accepted on L2, which the matching loop checks for every few seconds.
+async fn confirm_match(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(match_id): Path<String>,
+) -> ApiResult<(StatusCode, JsonResponse<ActionResponse>)> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    let subject = require_auth(&headers, &state, &correlation_id).await?;
+
+    if !is_valid_match_id(&match_id) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            JsonResponse(error_response(
+                "INVALID_MATCH_ID",
+                "match_id must be a UUID or a 0x-prefixed hash",
+                Some(correlation_id),
+            )),
+        ));
+    }
+    let pair = state.storage.get_matched_pair(&match_id).await.map_err(|e| {
+        error!("Failed to fetch match {}: {}", match_id, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            JsonResponse(error_response(
+                "QUERY_ERROR",
+                "Failed to fetch match",
+                Some(correlation_id.clone()),
+            )),
+        )
+    })?;
+    let Some(pair) = pair else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            JsonResponse(error_response(
+                "NOT_FOUND",
+                "Match not found",
+                Some(correlation_id),
+            )),
+        ));
+    };
+    let is_participant = [&pair.intent_a, &pair.intent_b]
+        .iter()
+        .any(|intent| subject_owns_user(&state.api_config, &subject, &intent.public_inputs.user));
+    if !is_participant {
+        return Err((
+            StatusCode::FORBIDDEN,
+            JsonResponse(error_response(
+                "FORBIDDEN",
+                "Only a participant in the match can confirm it",
+                Some(correlation_id),
+            )),
+        ));
+    }
+
+    // Show the caller what they will receive, and stop before paying for a settlement that would
+    // leave anyone short of their minimum. If the simulation itself can't run, settle regardless.
+    let outputs = match state.matcher.simulate_outputs(&pair).await {
+        Ok(outputs) => outputs,
+        Err(e) if e.to_string().contains("SETTLEMENT_SIMULATION_REVERTED") => {
+            warn!("Settlement simulation of match {} reverted: {}", match_id, e);
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                JsonResponse(error_response(
+                    "SETTLEMENT_SIMULATION_REVERTED",
+                    "Settlement simulation reverted; no transaction was submitted",
+                    Some(correlation_id),
+                )),
+            ));
+        }
+        Err(e) => {
+            warn!("Could not simulate settlement of match {}; confirming without outputs: {}", match_id, e);
+            None
+        }
+    };
+    let owns = |output: &SettlementOutput| subject_owns_user(&state.api_config, &subject, &output.user);
+    let shortfalls: Vec<ErrorDetail> = outputs
+        .iter()
+        .flatten()
+        .filter(|output| output.shortfall)
+        .map(|output| ErrorDetail {
+            code: "SETTLEMENT_SHORTFALL".to_string(),
+            // The counterparty's amounts stay private.
+            message: if owns(output) {
+                format!(
+                    "Intent {} would receive {} (minimum {})",
+                    output.nullifier, output.amount, output.min_amount_out
+                )
+            } else {
+                "The counterparty would receive less than its min_amount_out".to_string()
+            },
+        })
+        .collect();
+    if !shortfalls.is_empty() {
+        warn!("Not settling match {}: simulated outputs fall short of min_amount_out", match_id);
+        let mut body = error_response(
+            "SETTLEMENT_SHORTFALL",
+            "Settlement would pay out less than min_amount_out; no transaction was submitted",
+            Some(correlation_id),
+        );
+        body.errors = shortfalls;
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, JsonResponse(body)));
+    }
+    let outputs = outputs.map(|all| all.into_iter().filter(|output| owns(output)).collect());
+
+    state
+        .matcher
+        .settle_match_by_id(&match_id)
+        .await
+        .map_err(|e| {
+            let msg = e.to_string();
+            error!("Failed to settle match {}: {}", match_id, msg);
+
+            // Surface precheck failures as explicit, user-actionable errors.
+            let (status, code, user_message) = if msg.contains("Match not found") {
+                // Settled or removed since the lookup above.
+                (StatusCode::NOT_FOUND, "NOT_FOUND", "Match not found")
+            } else if msg.contains("SETTLEMENT_IN_PROGRESS") {
+                (
+                    StatusCode::CONFLICT,
+                    "SETTLEMENT_IN_PROGRESS",
+                    "Settlement already in progress for this match",
+                )
+            } else if msg.contains("MATCH_ALREADY_SETTLED") {
+                (
+                    StatusCode::CONFLICT,
+                    "MATCH_ALREADY_SETTLED",
+                    "Match is no longer awaiting settlement",
+                )
+            } else if msg.contains("DEADLINE_EXPIRED") {
+                (
+                    StatusCode::CONFLICT,
+                    "DEADLINE_EXPIRED",
+                    "An intent deadline has passed (or is about to); the match was failed",
+                )
+            } else if msg.contains("PARTIAL_FILL_NOT_SETTLEABLE") {
+                (
+                    StatusCode::CONFLICT,
+                    "PARTIAL_FILL_NOT_SETTLEABLE",
+                    "Partial fills can't be settled by the DarkPool contract",
+                )
+            } else if msg.contains("INSUFFICIENT_ALLOWANCE") {
+                (
+                    StatusCode::BAD_REQUEST,
+                    "INSUFFICIENT_ALLOWANCE",
+                    "Insufficient token allowance for settlement. Please approve the Dark Pool contract and try again.",
+                )
+            } else if msg.contains("INSUFFICIENT_BALANCE") {
+                (
+                    StatusCode::BAD_REQUEST,
+                    "INSUFFICIENT_BALANCE",
+                    "Insufficient token balance for settlement. Please top up and try again.",
+                )
+            } else if msg.contains("SETTLEMENT_SIMULATION_REVERTED") {
+                (
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "SETTLEMENT_SIMULATION_REVERTED",
+                    "Settlement simulation reverted; no transaction was submitted",
+                )
+            } else if msg.contains("SETTLEMENT_PAUSED") {
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "SETTLEMENT_PAUSED",
+                    "Settlement is paused for today (daily gas budget reached); the match stays queued",
+                )
+            } else if msg.contains("RELAYER_UNAVAILABLE") {
+                (
+                    StatusCode::BAD_GATEWAY,
+                    "RELAYER_UNAVAILABLE",
+                    "Settlement relayer is unavailable; try again shortly",
+                )
+            } else if msg.contains("RELAYER_REJECTED") {
+                (
+                    StatusCode::BAD_GATEWAY,
+                    "RELAYER_REJECTED",
+                    "Settlement relayer rejected the transaction",
+                )
+            } else {
+                (StatusCode::BAD_REQUEST, "SETTLEMENT_ERROR", "Failed to settle match")
+            };
+
+            (
+                status,
+                JsonResponse(error_response(code, user_message, Some(correlation_id.clone()))),
+            )
+        })?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        JsonResponse(ActionResponse {
+            success: true,
+            correlation_id,
+            message: "Match confirmed and settlement submitted; intents settle once it is accepted on L2".to_string(),
+            outputs,
+        }),
+    ))
+}
+
+/// Records a settlement the solver did not send (a user-settled match, or one submitted elsewhere)
+/// once its receipt shows the dark pool settled exactly this match. The chain is the authority, so
+/// any authenticated client may report it.
+async fn report_match_tx(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(match_id): Path<String>,
+    Json(request): Json<ReportTxRequest>,
+) -> ApiResult<JsonResponse<ActionResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+    let reject = |status: StatusCode, code: &str, message: &str| {
+        (
+            status,
+            JsonResponse(error_response(code, message, Some(correlation_id.clone()))),
+        )
+    };
+
+    if !is_valid_match_id(&match_id) {
+        return Err(reject(
+            StatusCode::BAD_REQUEST,
+            "INVALID_MATCH_ID",
+            "match_id must be a UUID or a 0x-prefixed hash",
+        ));
+    }
+    let tx_hash = Felt::from_hex(request.tx_hash.trim())
+        .map(|felt| format!("{:?}", felt))
+        .map_err(|_| reject(StatusCode::BAD_REQUEST, "INVALID_TX_HASH", "tx_hash must be a felt hex string"))?;
+
+    state
+        .matcher
+        .report_settlement_tx(&match_id, &tx_hash)
+        .await
+        .map_err(|e| {
+            let msg = e.to_string();
+            warn!("Rejected reported tx {} for match {}: {}", tx_hash, match_id, msg);
+            if msg.contains("Match not found") {
+                reject(StatusCode::NOT_FOUND, "NOT_FOUND", "Match not found")
+            } else if msg.contains("Starknet client not configured") {
+                reject(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "STARKNET_UNAVAILABLE",
+                    "On-chain access is not configured on this solver",
+                )
+            } else if msg.contains("TX_NOT_FOUND") {
+                reject(StatusCode::NOT_FOUND, "TX_NOT_FOUND", "The node has no receipt for this transaction yet")
+            } else if msg.contains("TX_REVERTED") {
+                reject(StatusCode::UNPROCESSABLE_ENTITY, "TX_REVERTED", "The transaction reverted")
+            } else if msg.contains("TX_NOT_SETTLEMENT_OF_MATCH") {
+                reject(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "TX_NOT_SETTLEMENT_OF_MATCH",
+                    "The transaction did not settle this match",
+                )
+            } else if msg.contains("MATCH_ALREADY_SETTLED") {
+                reject(
+                    StatusCode::CONFLICT,
+                    "MATCH_ALREADY_SETTLED",
+                    "Match is already recorded as settled by another transaction",
+                )
+            } else if msg.contains("SETTLEMENT_IN_PROGRESS") {
+                reject(
+                    StatusCode::CONFLICT,
+                    "SETTLEMENT_IN_PROGRESS",
+                    "Settlement already in progress for this match; report again shortly",
+                )
+            } else {
+                reject(StatusCode::BAD_GATEWAY, "RPC_ERROR", "Failed to verify the transaction")
+            }
+        })?;
+
+    Ok(JsonResponse(ActionResponse {
+        success: true,
+        correlation_id,
+        message: format!("Match recorded as settled by {}", tx_hash),
+        outputs: None,
+    }))
+}
+
+/// The decoded events of a settled match's transaction, found through the settlement history.
+/// Only the match's participants (or an admin) may read them.
+async fn get_match_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(match_id): Path<String>,
+) -> ApiResult<JsonResponse<MatchEventsResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    let subject = require_auth(&headers, &state, &correlation_id).await?;
+    let reject = |status: StatusCode, code: &str, message: &str| {
+        (status, JsonResponse(error_response(code, message, Some(correlation_id.clone()))))
+    };
+    let storage_error = |e: anyhow::Error| {
+        error!("Failed to look up settlement of match {}: {}", match_id, e);
+        reject(StatusCode::INTERNAL_SERVER_ERROR, "STORAGE_ERROR", "Failed to look up the settlement")
+    };
+
+    if !is_valid_match_id(&match_id) {
+        return Err(reject(
+            StatusCode::BAD_REQUEST,
+            "INVALID_MATCH_ID",
+            "match_id must be a UUID or a 0x-prefixed hash",
+        ));
+    }
+    let (record, users) = match state.storage.get_settlement(&match_id).await.map_err(storage_error)? {
+        Some((record, users)) => (Some(record), users),
+        None => match state.storage.get_matched_pair(&match_id).await.map_err(storage_error)? {
+            Some(pair) => (None, vec![pair.intent_a.public_inputs.user, pair.intent_b.public_inputs.user]),
+            None => {
+                return Err(reject(StatusCode::NOT_FOUND, "NOT_FOUND", "No settled match with this id in the settlement history"));
+            }
+        },
+    };
+    // Records without users predate their being kept; only an admin can read those.
+    let is_admin = sessions_configured(&state.api_config) && subject == state.api_config.auth_username;
+    if !is_admin && !users.iter().any(|user| subject_owns_user(&state.api_config, &subject, user)) {
+        return Err(reject(StatusCode::FORBIDDEN, "FORBIDDEN", "Only a participant in the match can read its events"));
+    }
+    let tx_hash = match record {
+        Some(SettlementRecord { outcome: SettlementOutcome::Settled, transaction_hash: Some(tx_hash), .. }) => tx_hash,
+        Some(SettlementRecord { outcome: SettlementOutcome::Failed, .. }) => {
+            return Err(reject(StatusCode::CONFLICT, "MATCH_NOT_SETTLED", "The match was given up on without settling"));
+        }
+        Some(_) => {
+            return Err(reject(StatusCode::NOT_FOUND, "TX_NOT_FOUND", "No transaction was recorded for this settlement"));
+        }
+        None => {
+            return Err(reject(StatusCode::CONFLICT, "MATCH_NOT_SETTLED", "The match has not settled yet"));
+        }
+    };
+
+    let respond = |events: Vec<SettlementEvent>| {
+        JsonResponse(MatchEventsResponse {
+            success: true,
+            match_id: match_id.clone(),
+            transaction_hash: tx_hash.clone(),
+            events,
+            correlation_id: correlation_id.clone(),
+        })
+    };
+    if let Some((_, events)) = state.settlement_events_cache.read().await.get(&tx_hash) {
+        return Ok(respond(events.clone()));
+    }
+
+    let client = state.matcher.starknet_client().ok_or_else(|| {
+        reject(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "STARKNET_UNAVAILABLE",
+            "On-chain access is not configured on this solver",
+        )
+    })?;
+    let events = match client.settlement_events(&tx_hash).await {
+        Ok(Some(events)) => events,
+        Ok(None) => {
+            return Err(reject(StatusCode::NOT_FOUND, "TX_NOT_FOUND", "The node has no receipt for this transaction yet"));
+        }
+        Err(e) if e.to_string().contains("TX_REVERTED") => {
+            return Err(reject(StatusCode::UNPROCESSABLE_ENTITY, "TX_REVERTED", "The transaction reverted"));
+        }
+        Err(e) => {
+            error!("Failed to read receipt {} for match {}: {}", tx_hash, match_id, e);
+            return Err(reject(StatusCode::BAD_GATEWAY, "RPC_ERROR", "Failed to read the settlement receipt"));
+        }
+    };
+    let mut cache = state.settlement_events_cache.write().await;
+    if cache.len() >= SETTLEMENT_EVENTS_CACHE_MAX && !cache.contains_key(&tx_hash) {
+        let oldest = cache.iter().min_by_key(|(_, (cached_at, _))| *cached_at).map(|(hash, _)| hash.clone());
+        if let Some(oldest) = oldest {
+            cache.remove(&oldest);
+        }
+    }
+    cache.insert(tx_hash.clone(), (std::time::Instant::now(), events.clone()));
+    Ok(respond(events))
+}
+
+async fn estimate_match_settlement(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(match_id): Path<String>,
+) -> ApiResult<JsonResponse<SettlementEstimateResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    let subject = require_auth(&headers, &state, &correlation_id).await?;
+    let reject = |status: StatusCode, code: &str, message: &str| {
+        (status, JsonResponse(error_response(code, message, Some(correlation_id.clone()))))
+    };
+
+    if !is_valid_match_id(&match_id) {
+        return Err(reject(
+            StatusCode::BAD_REQUEST,
+            "INVALID_MATCH_ID",
+            "match_id must be a UUID or a 0x-prefixed hash",
+        ));
+    }
+    let pair = state.read_storage.get_matched_pair(&match_id).await.map_err(|e| {
+        error!("Failed to fetch match {}: {}", match_id, e);
+        reject(StatusCode::INTERNAL_SERVER_ERROR, "QUERY_ERROR", "Failed to fetch match")
+    })?;
+    let Some(pair) = pair else {
+        return Err(reject(StatusCode::NOT_FOUND, "NOT_FOUND", "Match not found"));
+    };
+    let is_participant = [&pair.intent_a, &pair.intent_b]
+        .iter()
+        .any(|intent| subject_owns_user(&state.api_config, &subject, &intent.public_inputs.user));
+    if !is_participant {
+        return Err(reject(
+            StatusCode::FORBIDDEN,
+            "FORBIDDEN",
+            "Only a participant in the match can estimate its settlement",
+        ));
+    }
+    if pair.fill.is_some() {
+        return Err(reject(
+            StatusCode::CONFLICT,
+            "PARTIAL_FILL_NOT_SETTLEABLE",
+            "Partial fills can't be settled by the DarkPool contract",
+        ));
+    }
+    let client = state.matcher.starknet_client().ok_or_else(|| {
+        reject(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "STARKNET_UNAVAILABLE",
+            "On-chain access is not configured on this solver",
+        )
+    })?;
+    settlement_estimate_response(client.as_ref(), &pair, correlation_id).await
+}
+
+/// The `/v1/matches/:match_id/estimate` answer for `pair`, from `estimator`.
+async fn settlement_estimate_response(
+    estimator: &dyn SettlementFeeEstimator,
+    pair: &MatchedPair,
+    correlation_id: String,
+) -> ApiResult<JsonResponse<SettlementEstimateResponse>> {
+    match estimator.estimate_settlement_fee(pair).await {
+        Ok(estimate) => Ok(JsonResponse(SettlementEstimateResponse {
+            success: true,
+            match_id: pair.id.clone(),
+            overall_fee: estimate.overall_fee.to_string(),
+            fee_token: estimate.fee_token,
+            fee_token_address: estimate.fee_token.address().to_string(),
+            correlation_id,
+        })),
+        Err(e) => {
+            let msg = e.to_string();
+            let (status, code, message) = match msg.strip_prefix("SETTLEMENT_ESTIMATE_REVERTED ") {
+                // Only the classified code goes out; the raw reason can carry other users' amounts.
+                Some(revert) => {
+                    info!("Settlement estimate of match {} reverted: {}", pair.id, revert);
+                    let revert_code = revert
+                        .strip_prefix("code=")
+                        .and_then(|rest| rest.split_whitespace().next())
+                        .unwrap_or("UNKNOWN");
+                    (
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "SETTLEMENT_ESTIMATE_REVERTED",
+                        format!("Settlement would revert: code={}", revert_code),
+                    )
+                }
+                None => {
+                    warn!("Failed to estimate settlement fee of match {}: {}", pair.id, msg);
+                    (StatusCode::BAD_GATEWAY, "RPC_ERROR", "Failed to estimate the settlement fee".to_string())
+                }
+            };
+            Err((status, JsonResponse(error_response(code, &message, Some(correlation_id)))))
+        }
+    }
+}
+
+async fn relay_approval(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ApprovalRelayRequest>,
+) -> ApiResult<JsonResponse<ApprovalRelayResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+
+    let bad_request = |code: &str, message: &str| {
+        (
+            StatusCode::BAD_REQUEST,
+            JsonResponse(error_response(code, message, Some(correlation_id.clone()))),
+        )
+    };
+
+    // Only ever relay approvals for the dark pool; anything else would let the solver pay gas
+    // to grant allowances to arbitrary contracts.
+    match Felt::from_hex(request.spender.trim()) {
+        Ok(spender) if spender == state.dark_pool_address => {}
+        Ok(_) => {
+            return Err(bad_request(
+                "SPENDER_NOT_ALLOWED",
+                "Only approvals for the dark pool can be relayed",
+            ))
+        }
+        Err(_) => return Err(bad_request("INVALID_REQUEST", "spender must be a hex address")),
+    }
+    if Felt::from_hex(request.account.trim()).is_err() || Felt::from_hex(request.token.trim()).is_err() {
+        return Err(bad_request("INVALID_REQUEST", "account and token must be hex addresses"));
+    }
+    if request.signature.is_empty() {
+        return Err(bad_request("INVALID_SIGNATURE", "signature is required"));
+    }
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    if request.execute_before <= now || request.execute_after >= request.execute_before {
+        return Err(bad_request(
+            "INVALID_REQUEST",
+            "execute_before must be in the future and after execute_after",
+        ));
+    }
+
+    let client = state.matcher.starknet_client().ok_or_else(|| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            JsonResponse(error_response(
+                "RELAY_UNAVAILABLE",
+                "On-chain settlement is not configured on this solver",
+                Some(correlation_id.clone()),
+            )),
         )
     })?;
-    if let Some(msg) = jsonrpc_error_message(&allowance_json) {
-        error!("Precheck allowance JSON-RPC error: {}", msg);
+
+    info!(
+        "Relaying approval: account={}, token={}, correlation_id={}",
+        log_address(&request.account), request.token, correlation_id
+    );
+    match client.relay_approval(&request).await {
+        Ok(transaction_hash) => Ok(JsonResponse(ApprovalRelayResponse {
+            success: true,
+            transaction_hash,
+            correlation_id,
+        })),
+        Err(e) => {
+            let msg = e.to_string();
+            error!("Failed to relay approval for {}: {}", log_address(&request.account), msg);
+            // The detail can carry RPC internals; it stays in the log.
+            let (status, code, message) = if msg.contains("SETTLEMENT_SIMULATION_REVERTED") {
+                (StatusCode::UNPROCESSABLE_ENTITY, "APPROVAL_RELAY_REVERTED", "The approval would revert on-chain")
+            } else {
+                (StatusCode::BAD_GATEWAY, "APPROVAL_RELAY_ERROR", "Failed to relay the approval")
+            };
+            Err((status, JsonResponse(error_response(code, message, Some(correlation_id)))))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MatchListQuery {
+    limit: Option<usize>,
+    offset: Option<usize>,
+    /// `failures` (most first), `age` (oldest first) or `next_retry_at` (soonest first).
+    sort: Option<String>,
+}
+
+const DEFAULT_MATCH_LIST_LIMIT: usize = 50;
+const MAX_MATCH_LIST_LIMIT: usize = 500;
+
+async fn list_unsettled_matches(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<MatchListQuery>,
+) -> ApiResult<JsonResponse<MatchListResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+
+    let pairs = state.storage.get_unsettled_matches().await.map_err(|e| {
+        error!("Failed to get unsettled matches: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            JsonResponse(error_response(
+                "QUERY_ERROR",
+                "Failed to get unsettled matches",
+                Some(correlation_id.clone()),
+            )),
+        )
+    })?;
+
+    list_matches(&state, pairs, query, correlation_id).await
+}
+
+async fn list_deadletter_matches(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<MatchListQuery>,
+) -> ApiResult<JsonResponse<MatchListResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+
+    let pairs = state.storage.get_deadletter_matches().await.map_err(|e| {
+        error!("Failed to get deadletter matches: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            JsonResponse(error_response(
+                "QUERY_ERROR",
+                "Failed to get deadletter matches",
+                Some(correlation_id.clone()),
+            )),
+        )
+    })?;
+
+    list_matches(&state, pairs, query, correlation_id).await
+}
+
+/// Validated `sort` (falling back to `age`), `limit` and `offset` of a match listing.
+fn match_list_params(query: &MatchListQuery, correlation_id: &str) -> ApiResult<(String, usize, usize)> {
+    let sort = query.sort.as_deref().map(str::trim).unwrap_or("age");
+    if !matches!(sort, "failures" | "age" | "next_retry_at") {
         return Err((
-            StatusCode::BAD_GATEWAY,
-            error_response(
-                "PRECHECK_RPC_ERROR",
-                "Failed to query token allowance",
+            StatusCode::BAD_REQUEST,
+            JsonResponse(error_response(
+                "INVALID_REQUEST",
+                "sort must be one of: failures, age, next_retry_at",
                 Some(correlation_id.to_string()),
-            ),
+            )),
         ));
     }
-    let allowance = parse_u256_result(&allowance_json).ok_or_else(|| {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_MATCH_LIST_LIMIT)
+        .clamp(1, MAX_MATCH_LIST_LIMIT);
+    Ok((sort.to_string(), limit, query.offset.unwrap_or(0)))
+}
+
+/// Joins each pair with its retry state and sorts the result by `sort`.
+async fn match_status_entries(state: &AppState, pairs: &[MatchedPair], sort: &str) -> Vec<MatchStatusEntry> {
+    let mut entries = Vec::with_capacity(pairs.len());
+    for pair in pairs {
+        // Missing retry state just means the match has not failed yet.
+        let retry_state = state
+            .storage
+            .get_match_retry_state(&pair.id)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Failed to read retry state for match {}: {}", pair.id, e);
+                None
+            });
+        let (settlement_failures, next_retry_at_unix, last_error, terminal_reason, deferred_reason) =
+            match retry_state {
+                Some(s) => (
+                    s.failures,
+                    Some(s.next_retry_at_unix).filter(|ts| *ts > 0),
+                    s.last_error,
+                    s.terminal_reason,
+                    s.deferred_reason,
+                ),
+                None => (0, None, None, None, None),
+            };
+        entries.push(MatchStatusEntry {
+            match_id: pair.id.clone(),
+            matched_at: pair.matched_at,
+            intent_a_nullifier: pair.intent_a.nullifier.clone(),
+            intent_b_nullifier: pair.intent_b.nullifier.clone(),
+            token_a: pair.intent_a.public_inputs.token_in.clone(),
+            token_b: pair.intent_a.public_inputs.token_out.clone(),
+            settlement_failures,
+            next_retry_at_unix,
+            last_error,
+            terminal_reason,
+            deferred_reason,
+        });
+    }
+
+    // Ties (and the default) fall back to oldest first so pages are stable.
+    entries.sort_by(|a, b| {
+        let primary = match sort {
+            "failures" => b.settlement_failures.cmp(&a.settlement_failures),
+            // Entries without a scheduled retry are due now, so they sort first.
+            "next_retry_at" => a
+                .next_retry_at_unix
+                .unwrap_or(0)
+                .cmp(&b.next_retry_at_unix.unwrap_or(0)),
+            _ => std::cmp::Ordering::Equal,
+        };
+        primary
+            .then_with(|| a.matched_at.cmp(&b.matched_at))
+            .then_with(|| a.match_id.cmp(&b.match_id))
+    });
+    entries
+}
+
+/// Joins each pair with its retry state, then sorts and pages the result.
+async fn list_matches(
+    state: &AppState,
+    pairs: Vec<MatchedPair>,
+    query: MatchListQuery,
+    correlation_id: String,
+) -> ApiResult<JsonResponse<MatchListResponse>> {
+    let (sort, limit, offset) = match_list_params(&query, &correlation_id)?;
+    let entries = match_status_entries(state, &pairs, &sort).await;
+
+    let total = entries.len();
+    let matches = entries.into_iter().skip(offset).take(limit).collect();
+    Ok(JsonResponse(MatchListResponse {
+        matches,
+        total,
+        limit,
+        offset,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct MatchHistoryQuery {
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+/// The trade log: settled matches, newest first, with their tokens, amounts and transaction.
+async fn list_match_history(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<MatchHistoryQuery>,
+) -> ApiResult<JsonResponse<MatchHistoryResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_MATCH_LIST_LIMIT)
+        .clamp(1, MAX_MATCH_LIST_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    let (matches, total) = state.read_storage.get_match_history(offset, limit).await.map_err(|e| {
+        error!("Failed to get match history: {}", e);
         (
-            StatusCode::BAD_GATEWAY,
-            error_response(
-                "PRECHECK_RPC_ERROR",
-                "Token allowance response missing fields",
-                Some(correlation_id.to_string()),
-            ),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            JsonResponse(error_response(
+                "QUERY_ERROR",
+                "Failed to get match history",
+                Some(correlation_id.clone()),
+            )),
+        )
+    })?;
+    Ok(JsonResponse(MatchHistoryResponse {
+        matches,
+        total,
+        limit,
+        offset,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct SettlementListQuery {
+    /// Unix seconds, inclusive.
+    from: Option<u64>,
+    /// Unix seconds, exclusive. Defaults to now.
+    to: Option<u64>,
+    /// `token_a/token_b`, in either direction.
+    pair: Option<String>,
+    /// `settled` or `failed`.
+    status: Option<String>,
+    /// `auto`, `manual` or `reported`.
+    trigger: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    /// `desc` (newest first, the default) or `asc`.
+    order: Option<String>,
+}
+
+/// Admin: settlement outcomes from the history, filtered and paged. Unlike the per-user views this
+/// covers every match, failures included, for auditing settlement performance.
+async fn list_settlements(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<SettlementListQuery>,
+) -> ApiResult<JsonResponse<SettlementListResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_admin(&headers, &state, &correlation_id).await?;
+    let reject = |message: &str| {
+        (
+            StatusCode::BAD_REQUEST,
+            JsonResponse(error_response("INVALID_REQUEST", message, Some(correlation_id.clone()))),
+        )
+    };
+
+    let outcome = match query.status.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some("settled") => Some(SettlementOutcome::Settled),
+        Some("failed") => Some(SettlementOutcome::Failed),
+        Some(_) => return Err(reject("status must be one of: settled, failed")),
+    };
+    let trigger = match query.trigger.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some("auto") => Some(SettlementTrigger::Auto),
+        Some("manual") => Some(SettlementTrigger::Manual),
+        Some("reported") => Some(SettlementTrigger::Reported),
+        Some(_) => return Err(reject("trigger must be one of: auto, manual, reported")),
+    };
+    let pair = match query.pair.as_deref().map(str::trim).filter(|p| !p.is_empty()) {
+        None => None,
+        Some(pair) => match pair.split_once('/') {
+            Some((a, b)) if !a.trim().is_empty() && !b.trim().is_empty() => Some((a.trim(), b.trim())),
+            _ => return Err(reject("pair must be token_a/token_b")),
+        },
+    };
+    let newest_first = match query.order.as_deref().map(str::trim) {
+        None | Some("") | Some("desc") => true,
+        Some("asc") => false,
+        Some(_) => return Err(reject("order must be asc or desc")),
+    };
+    let from_ms = query.from.map_or(0, |from| (from as i64).saturating_mul(1000));
+    let to_ms = query
+        .to
+        .map_or_else(|| chrono::Utc::now().timestamp_millis() + 1, |to| (to as i64).saturating_mul(1000));
+    if to_ms <= from_ms {
+        return Err(reject("to must be after from"));
+    }
+
+    let mut records = state.storage.get_settlements(from_ms, to_ms).await.map_err(|e| {
+        error!("Failed to get settlement history: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            JsonResponse(error_response(
+                "QUERY_ERROR",
+                "Failed to get settlement history",
+                Some(correlation_id.clone()),
+            )),
+        )
+    })?;
+    records.retain(|r| {
+        outcome.map_or(true, |o| r.outcome == o)
+            && trigger.map_or(true, |t| r.trigger == t)
+            && pair.map_or(true, |(a, b)| {
+                (same_address(&r.token_a, a) && same_address(&r.token_b, b))
+                    || (same_address(&r.token_a, b) && same_address(&r.token_b, a))
+            })
+    });
+    if newest_first {
+        records.reverse();
+    }
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_MATCH_LIST_LIMIT)
+        .clamp(1, MAX_MATCH_LIST_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    let total = records.len();
+    let settlements = records.into_iter().skip(offset).take(limit).collect();
+    Ok(JsonResponse(SettlementListResponse {
+        settlements,
+        total,
+        limit,
+        offset,
+    }))
+}
+
+/// Operator view of every in-flight match: the unsettled listing plus both sides' public
+/// inputs and whether a settlement attempt currently holds the match lock.
+async fn list_active_matches(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<MatchListQuery>,
+) -> ApiResult<JsonResponse<ActiveMatchListResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_admin(&headers, &state, &correlation_id).await?;
+    let (sort, limit, offset) = match_list_params(&query, &correlation_id)?;
+
+    let pairs = state.storage.get_unsettled_matches().await.map_err(|e| {
+        error!("Failed to get active matches: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            JsonResponse(error_response(
+                "QUERY_ERROR",
+                "Failed to get active matches",
+                Some(correlation_id.clone()),
+            )),
         )
     })?;
+    let entries = match_status_entries(&state, &pairs, &sort).await;
+    let total = entries.len();
+
+    let mut pairs: std::collections::HashMap<String, MatchedPair> =
+        pairs.into_iter().map(|p| (p.id.clone(), p)).collect();
+    let mut matches = Vec::new();
+    // Lock state is only read for the returned page.
+    for status in entries.into_iter().skip(offset).take(limit) {
+        let Some(pair) = pairs.remove(&status.match_id) else { continue };
+        let lock_ttl_seconds = state
+            .storage
+            .get_match_lock_ttl(&pair.id)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Failed to read settlement lock for match {}: {}", pair.id, e);
+                None
+            });
+        matches.push(ActiveMatchEntry {
+            status,
+            intent_a: pair.intent_a.public_inputs,
+            intent_b: pair.intent_b.public_inputs,
+            settlement_locked: lock_ttl_seconds.is_some(),
+            lock_ttl_seconds,
+        });
+    }
+
+    Ok(JsonResponse(ActiveMatchListResponse {
+        matches,
+        total,
+        limit,
+        offset,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct PendingIntentsQuery {
+    /// `next_cursor` of the previous page; absent or `0` starts over.
+    cursor: Option<String>,
+    limit: Option<usize>,
+    user: Option<String>,
+}
+
+async fn get_pending_intents(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<PendingIntentsQuery>,
+) -> ApiResult<JsonResponse<PendingIntentsPage>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+
+    let cursor = match query.cursor.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+        None => 0,
+        Some(raw) => raw.parse::<u64>().map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                JsonResponse(error_response(
+                    "INVALID_REQUEST",
+                    "cursor must be a next_cursor returned by this endpoint",
+                    Some(correlation_id.clone()),
+                )),
+            )
+        })?,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_MATCH_LIST_LIMIT)
+        .clamp(1, MAX_MATCH_LIST_LIMIT);
+
+    match state.read_storage.get_pending_intents_page(cursor, limit).await {
+        Ok((intents, next)) => {
+            // Wallets / libraries sometimes return the same Starknet address with different
+            // zero-padding. Compare by felt value when possible to avoid false mismatches.
+            let user_filter_raw = query.user.as_deref().map(|v| v.trim().to_string());
+            let user_filter_felt = user_filter_raw
+                .as_deref()
+                .and_then(|v| (!v.trim().is_empty()).then_some(v))
+                .and_then(|v| Felt::from_hex(v).ok());
+            let user_filter_lc = user_filter_raw
+                .as_deref()
+                .map(|v| v.trim().to_lowercase())
+                .filter(|v| !v.is_empty());
+
+            let views: Vec<IntentView> = intents
+                .into_iter()
+                .filter(|intent| {
+                    if let Some(user_felt) = user_filter_felt {
+                        if let Ok(intent_user_felt) = Felt::from_hex(intent.public_inputs.user.trim()) {
+                            return intent_user_felt == user_felt;
+                        }
+                        // Fall back to string compare if parsing fails.
+                    }
+                    if let Some(ref user_lc) = user_filter_lc {
+                        return intent.public_inputs.user.trim().to_lowercase() == *user_lc;
+                    }
+                    true
+                })
+                .map(|intent| IntentView {
+                    id: intent.id,
+                    nullifier: intent.nullifier,
+                    user: intent.public_inputs.user,
+                    status: intent.status,
+                    created_at: intent.created_at,
+                    expires_at: intent.expires_at,
+                    matched_with: intent.matched_with,
+                    settlement_tx_hash: intent.settlement_tx_hash,
+                    failure_reason: intent.failure_reason,
+                    activate_at: intent.activate_at,
+                    allow_extension: intent.allow_extension,
+                    max_slippage_bps: intent.max_slippage_bps,
+                    post_only: intent.post_only,
+                    trigger_price: intent.trigger_price,
+                    trigger_direction: intent.trigger_direction,
+                    triggered_at: intent.triggered_at,
+                    near_miss_count: intent.near_miss_count,
+                    last_near_miss_at: intent.last_near_miss_at,
+                })
+                .collect();
+            Ok(JsonResponse(PendingIntentsPage {
+                intents: views,
+                next_cursor: (next != 0).then(|| next.to_string()),
+                limit,
+            }))
+        }
+        Err(e) => {
+            error!("Failed to get pending intents: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse(error_response(
+                    "QUERY_ERROR",
+                    "Failed to get pending intents",
+                    Some(correlation_id),
+                )),
+            ))
+        }
+    }
+}
+
+async fn get_intents_by_user(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<std::collections::HashMap<String, String>>,
+) -> ApiResult<JsonResponse<Vec<IntentView>>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+
+    let user = query
+        .get("user")
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                JsonResponse(error_response(
+                    "INVALID_REQUEST",
+                    "Missing user query parameter",
+                    Some(correlation_id.clone()),
+                )),
+            )
+        })?;
 
-    if allowance < required {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            error_response(
-                "INSUFFICIENT_ALLOWANCE",
-                "Insufficient token allowance for amount_in",
-                Some(correlation_id.to_string()),
-            ),
-        ));
+    match state.read_storage.get_intents_by_user(&user).await {
+        Ok(mut intents) => {
+            // Compatibility: older deployments may have intents in `intents:pending` but no per-user index.
+            // If the index is empty, fall back to scanning pending and filtering by user felt value.
+            if intents.is_empty() {
+                if let Ok(pending) = state.read_storage.get_pending_intents().await {
+                    let user_felt = Felt::from_hex(user.trim()).ok();
+                    intents = pending
+                        .into_iter()
+                        .filter(|intent| {
+                            if let (Some(a), Ok(b)) = (user_felt, Felt::from_hex(intent.public_inputs.user.trim())) {
+                                a == b
+                            } else {
+                                intent.public_inputs.user.trim().eq_ignore_ascii_case(user.trim())
+                            }
+                        })
+                        .collect();
+                }
+            }
+
+            let mut views: Vec<IntentView> = intents
+                .into_iter()
+                .map(|intent| IntentView {
+                    id: intent.id,
+                    nullifier: intent.nullifier,
+                    user: intent.public_inputs.user,
+                    status: intent.status,
+                    created_at: intent.created_at,
+                    expires_at: intent.expires_at,
+                    matched_with: intent.matched_with,
+                    settlement_tx_hash: intent.settlement_tx_hash,
+                    failure_reason: intent.failure_reason,
+                    activate_at: intent.activate_at,
+                    allow_extension: intent.allow_extension,
+                    max_slippage_bps: intent.max_slippage_bps,
+                    post_only: intent.post_only,
+                    trigger_price: intent.trigger_price,
+                    trigger_direction: intent.trigger_direction,
+                    triggered_at: intent.triggered_at,
+                    near_miss_count: intent.near_miss_count,
+                    last_near_miss_at: intent.last_near_miss_at,
+                })
+                .collect();
+            views.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            Ok(JsonResponse(views))
+        }
+        Err(e) => {
+            error!("Failed to get intents by user: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse(error_response(
+                    "QUERY_ERROR",
+                    "Failed to get intents",
+                    Some(correlation_id),
+                )),
+            ))
+        }
     }
+}
 
-    Ok(())
+#[derive(Debug, Serialize)]
+struct MetricsResponse {
+    pragma_price_cache: PriceCacheMetrics,
+    matcher_search: SearchMetrics,
+    /// Events the NATS publisher and event recorder skipped by falling behind the bus.
+    events_lagged: u64,
 }
 
-async fn query_intent(
+/// Internal counters for operators (admin only).
+async fn get_metrics(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Path(nullifier): Path<String>,
-) -> ApiResult<JsonResponse<QueryIntentResponse>> {
+) -> ApiResult<JsonResponse<MetricsResponse>> {
     let correlation_id = correlation_id_from_headers(&headers);
-    require_auth(&headers, &state, &correlation_id)?;
+    require_admin(&headers, &state, &correlation_id).await?;
+    Ok(JsonResponse(MetricsResponse {
+        pragma_price_cache: state.oracle.price_cache_metrics().await,
+        matcher_search: state.matcher.search_metrics(),
+        events_lagged: state.events.lagged_events(),
+    }))
+}
 
-    match state.storage.get_intent(&nullifier).await {
-        Ok(Some(intent)) => {
-            let view = IntentView {
-                id: intent.id,
-                nullifier: intent.nullifier,
-                user: intent.public_inputs.user,
-                status: intent.status,
-                created_at: intent.created_at,
-                expires_at: intent.expires_at,
-                matched_with: intent.matched_with,
-                settlement_tx_hash: intent.settlement_tx_hash,
-            };
-            Ok(JsonResponse(QueryIntentResponse { intent: Some(view) }))
-        }
-        Ok(None) => Ok(JsonResponse(QueryIntentResponse { intent: None })),
+async fn get_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ApiResult<JsonResponse<SolverStats>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+
+    match state.stats_cache.get(&state.read_storage).await {
+        Ok(stats) => Ok(JsonResponse(stats)),
         Err(e) => {
-            error!("Failed to query intent: {}", e);
+            error!("Failed to get stats: {}", e);
             Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
                 JsonResponse(error_response(
-                    "QUERY_ERROR",
-                    "Failed to query intent",
+                    "STATS_ERROR",
+                    "Failed to get statistics",
                     Some(correlation_id),
                 )),
             ))
@@ -1211,308 +4592,728 @@ async fn query_intent(
     }
 }
 
-async fn cancel_intent(
-    State(state): State<AppState>,
-    headers: HeaderMap,
-    Path(nullifier): Path<String>,
-) -> ApiResult<JsonResponse<ActionResponse>> {
-    let correlation_id = correlation_id_from_headers(&headers);
-    require_auth(&headers, &state, &correlation_id)?;
+async fn get_liquidity(State(state): State<AppState>) -> ApiResult<JsonResponse<LiquidityResponse>> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    {
+        let cache = state.liquidity_cache.read().await;
+        if let Some(cached) = cache.as_ref() {
+            if now < cached.computed_at.saturating_add(LIQUIDITY_CACHE_TTL_SECONDS) {
+                return Ok(JsonResponse(cached.clone()));
+            }
+        }
+    }
 
-    let intent = state.storage.get_intent(&nullifier).await.map_err(|e| {
-        error!("Failed to fetch intent for cancel: {}", e);
+    let intents = state.read_storage.get_pending_intents().await.map_err(|e| {
+        error!("Failed to load pending intents for liquidity: {}", e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            JsonResponse(error_response(
-                "QUERY_ERROR",
-                "Failed to fetch intent",
-                Some(correlation_id.clone()),
-            )),
+            JsonResponse(error_response("STATS_ERROR", "Failed to get liquidity", None)),
         )
     })?;
 
-    let intent = intent.ok_or_else(|| {
-        (
-            StatusCode::NOT_FOUND,
-            JsonResponse(error_response(
-                "NOT_FOUND",
-                "Intent not found",
-                Some(correlation_id.clone()),
-            )),
-        )
-    })?;
+    // token_in -> (intent count, summed amount_in in base units)
+    let mut by_token: BTreeMap<String, (usize, BigUint)> = BTreeMap::new();
+    let mut intent_count = 0;
+    for intent in &intents {
+        let token = normalize_token(&intent.public_inputs.token_in);
+        let amount = match TokenAmount::parse(&intent.public_inputs.amount_in, token_decimals_for(&token)) {
+            Ok(amount) => amount,
+            Err(e) => {
+                warn!("Skipping intent {} in liquidity: {}", log_nullifier(&intent.nullifier), e);
+                continue;
+            }
+        };
+        let entry = by_token.entry(token).or_insert_with(|| (0, BigUint::from(0u8)));
+        entry.0 += 1;
+        entry.1 += amount.into_base_units();
+        intent_count += 1;
+    }
 
-    if intent.status != IntentStatus::Pending {
-        return Err((
-            StatusCode::CONFLICT,
-            JsonResponse(error_response(
-                "INVALID_STATE",
-                "Only pending intents can be cancelled",
-                Some(correlation_id),
-            )),
-        ));
+    let mut total_usd = BigUint::from(0u8);
+    let mut tokens = Vec::with_capacity(by_token.len());
+    let mut unpriced_tokens = Vec::new();
+    for (token, (count, amount)) in by_token {
+        let decimals = token_decimals_for(&token);
+        let value = match state.oracle.get_cross_rate(&token, "USD", None).await {
+            Ok(rate) => usd_value(&amount, decimals, &rate, USD_DECIMALS),
+            Err(e) => {
+                warn!("No USD price for token {}; leaving it out of liquidity: {}", token, e);
+                None
+            }
+        };
+        match &value {
+            Some(v) => total_usd += v,
+            None => unpriced_tokens.push(token.clone()),
+        }
+        tokens.push(TokenLiquidity {
+            symbol: token_symbol_for(&token).map(ToString::to_string),
+            token,
+            intent_count: count,
+            amount: TokenAmount::from_base_units(amount, decimals).to_string(),
+            value_usd: value.map(|v| TokenAmount::from_base_units(v, USD_DECIMALS).to_string()),
+        });
     }
 
-    state
-        .storage
-        .update_intent_status(&nullifier, IntentStatus::Cancelled, None, None)
-        .await
-        .map_err(|e| {
-            error!("Failed to cancel intent: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                JsonResponse(error_response(
-                    "STORAGE_ERROR",
-                    "Failed to cancel intent",
-                    Some(correlation_id.clone()),
-                )),
-            )
-        })?;
+    let response = LiquidityResponse {
+        total_usd: TokenAmount::from_base_units(total_usd, USD_DECIMALS).to_string(),
+        intent_count,
+        tokens,
+        unpriced_tokens,
+        computed_at: now,
+    };
+    *state.liquidity_cache.write().await = Some(response.clone());
+    Ok(JsonResponse(response))
+}
 
-    Ok(JsonResponse(ActionResponse {
-        success: true,
+/// Digits of `average_price` in book quotes.
+const QUOTE_PRICE_DECIMALS: u32 = 18;
+
+/// Volume-weighted price the resting book would give for `amount_in` right now (see
+/// `IntentMatcher::quote`). Only totals are returned, never the intents behind them.
+async fn quote_book(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<BookQuoteRequest>,
+) -> ApiResult<JsonResponse<BookQuoteResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_auth(&headers, &state, &correlation_id).await?;
+    let reject = |status: StatusCode, code: &str, message: &str| {
+        (status, JsonResponse(error_response(code, message, Some(correlation_id.clone()))))
+    };
+
+    let token_in = normalize_token(request.token_in.trim());
+    let token_out = normalize_token(request.token_out.trim());
+    if same_address(&token_in, &token_out) {
+        return Err(reject(StatusCode::BAD_REQUEST, "INVALID_REQUEST", "token_in and token_out must differ"));
+    }
+    let (in_decimals, out_decimals) = (token_decimals_for(&token_in), token_decimals_for(&token_out));
+    let amount_in = TokenAmount::parse(request.amount_in.trim(), in_decimals)
+        .map_err(|e| reject(StatusCode::BAD_REQUEST, "INVALID_AMOUNT", &format!("amount_in: {}", e)))?
+        .into_base_units();
+    if amount_in.is_zero() {
+        return Err(reject(StatusCode::BAD_REQUEST, "INVALID_AMOUNT", "amount_in must be positive"));
+    }
+
+    let mut resting = state.read_storage.get_pending_intents().await.map_err(|e| {
+        error!("Failed to load the book for a quote: {}", e);
+        reject(StatusCode::INTERNAL_SERVER_ERROR, "QUERY_ERROR", "Failed to read the book")
+    })?;
+    resting.retain(|i| {
+        same_address(&i.public_inputs.token_in, &token_out) && same_address(&i.public_inputs.token_out, &token_in)
+    });
+    let walk = coarsen_quote(
+        state.matcher.quote(&amount_in, &resting),
+        state.api_config.quote_min_counterparties,
+        state.api_config.quote_significant_digits,
+    );
+
+    let average_price = (!walk.filled_in.is_zero()).then(|| {
+        let scaled = &walk.amount_out * BigUint::from(10u8).pow(in_decimals + QUOTE_PRICE_DECIMALS);
+        let denominator = &walk.filled_in * BigUint::from(10u8).pow(out_decimals);
+        TokenAmount::from_base_units(scaled / denominator, QUOTE_PRICE_DECIMALS).to_string()
+    });
+    let unfilled_in = &amount_in - &walk.filled_in;
+    Ok(JsonResponse(BookQuoteResponse {
+        amount_in: TokenAmount::from_base_units(amount_in, in_decimals).to_string(),
+        filled_in: TokenAmount::from_base_units(walk.filled_in, in_decimals).to_string(),
+        unfilled_in: TokenAmount::from_base_units(unfilled_in, in_decimals).to_string(),
+        amount_out: TokenAmount::from_base_units(walk.amount_out, out_decimals).to_string(),
+        average_price,
+        counterparties: walk.counterparties,
+        token_in,
+        token_out,
         correlation_id,
-        message: "Intent cancelled".to_string(),
     }))
 }
 
-async fn confirm_match(
+/// Caps the metadata cache; lookups are public, so arbitrary addresses must not grow it forever.
+const TOKEN_METADATA_CACHE_MAX: usize = 1024;
+/// Caps the settlement receipt cache, evicting the oldest receipt once full.
+const SETTLEMENT_EVENTS_CACHE_MAX: usize = 1024;
+
+/// ERC-20 `name`/`symbol`/`decimals` for any token, read in one batched `starknet_call`.
+async fn get_token_metadata(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Path(match_id): Path<String>,
-) -> ApiResult<JsonResponse<ActionResponse>> {
+    Path(address): Path<String>,
+) -> ApiResult<JsonResponse<TokenMetadata>> {
     let correlation_id = correlation_id_from_headers(&headers);
-    require_auth(&headers, &state, &correlation_id)?;
+    let reject = |status: StatusCode, code: &str, message: &str| {
+        (status, JsonResponse(error_response(code, message, Some(correlation_id.clone()))))
+    };
 
-    state
-        .matcher
-        .settle_match_by_id(&match_id)
+    let token = Felt::from_hex(address.trim())
+        .map_err(|_| reject(StatusCode::BAD_REQUEST, "INVALID_TOKEN", "address must be a felt hex address"))?;
+    let key = normalize_token(address.trim());
+    if let Some(metadata) = state.token_metadata_cache.read().await.get(&key) {
+        return Ok(JsonResponse(metadata.clone()));
+    }
+
+    let selectors = ["name", "symbol", "decimals"]
+        .into_iter()
+        .map(get_selector_from_name)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| reject(StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_ERROR", "Failed to build selector"))?;
+    let calls = selectors.into_iter().map(|selector| (token, selector, vec![])).collect();
+    let envelopes = state
+        .rpc
+        .starknet_call_batch(calls, Some(&correlation_id))
         .await
         .map_err(|e| {
-            let msg = e.to_string();
-            error!("Failed to settle match {}: {}", match_id, msg);
+            error!("Token metadata RPC failed for {}: {}", key, e);
+            reject(StatusCode::BAD_GATEWAY, "RPC_ERROR", "Failed to query token metadata")
+        })?;
 
-            // Surface precheck failures as explicit, user-actionable errors.
-            let (code, user_message) = if msg.contains("INSUFFICIENT_ALLOWANCE") {
-                (
-                    "INSUFFICIENT_ALLOWANCE",
-                    "Insufficient token allowance for settlement. Please approve the Dark Pool contract and try again.",
-                )
-            } else if msg.contains("INSUFFICIENT_BALANCE") {
-                (
-                    "INSUFFICIENT_BALANCE",
-                    "Insufficient token balance for settlement. Please top up and try again.",
-                )
-            } else {
-                ("SETTLEMENT_ERROR", "Failed to settle match")
-            };
+    // Every call reverting or returning garbage means the address is not an ERC-20.
+    let felts = |envelope: &serde_json::Value| -> Option<Vec<Felt>> {
+        envelope
+            .get("result")?
+            .as_array()?
+            .iter()
+            .map(|v| v.as_str().and_then(|s| Felt::from_hex(s).ok()))
+            .collect()
+    };
+    let not_a_token = || reject(StatusCode::NOT_FOUND, "NOT_A_TOKEN", "Address does not implement ERC-20 metadata");
+    let name = envelopes.first().and_then(felts).and_then(|r| decode_cairo_string(&r));
+    let symbol = envelopes.get(1).and_then(felts).and_then(|r| decode_cairo_string(&r));
+    let decimals = envelopes
+        .get(2)
+        .and_then(felts)
+        .and_then(|r| r.first().and_then(|d| BigUint::from_bytes_be(&d.to_bytes_be()).to_u8()));
+    let (Some(name), Some(symbol), Some(decimals)) = (name, symbol, decimals) else {
+        return Err(not_a_token());
+    };
 
-            (
-                StatusCode::BAD_REQUEST,
-                JsonResponse(error_response(code, user_message, Some(correlation_id.clone()))),
-            )
-        })?;
+    let metadata = TokenMetadata { address: key.clone(), name, symbol, decimals };
+    let mut cache = state.token_metadata_cache.write().await;
+    if cache.len() < TOKEN_METADATA_CACHE_MAX {
+        cache.insert(key, metadata.clone());
+    }
+    Ok(JsonResponse(metadata))
+}
 
-    Ok(JsonResponse(ActionResponse {
-        success: true,
-        correlation_id,
-        message: "Match confirmed and settlement submitted".to_string(),
+/// Token address in one canonical form, so differently padded spellings group together.
+fn normalize_token(token: &str) -> String {
+    Felt::from_hex(token)
+        .map(|f| format!("0x{:x}", f))
+        .unwrap_or_else(|_| token.to_string())
+}
+
+const PNL_NOTE: &str = "Estimate: each settled trade is valued at current oracle prices (not prices at \
+settlement), received amounts are the counterparty's gross amount_in before protocol fees, and only \
+intents the solver still stores are included.";
+
+/// Activity rollup for one user from the intents the solver still holds. Each settled intent is a
+/// trade: the user sent its `amount_in` of `token_in` and received the counterparty's `amount_in`.
+async fn get_user_summary(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(user): Path<String>,
+) -> ApiResult<JsonResponse<UserSummaryResponse>> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_owner(&headers, &state, &user, &correlation_id).await?;
+
+    let intents = state.read_storage.get_intents_by_user(&user).await.map_err(|e| {
+        error!("Failed to get intents for user summary: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            JsonResponse(error_response(
+                "QUERY_ERROR",
+                "Failed to get intents",
+                Some(correlation_id.clone()),
+            )),
+        )
+    })?;
+
+    // token -> (sent, received) in base units
+    let mut volume: BTreeMap<String, (BigUint, BigUint)> = BTreeMap::new();
+    let mut rates: std::collections::HashMap<String, Option<CrossRate>> = std::collections::HashMap::new();
+    let mut sent_usd = BigUint::from(0u8);
+    let mut received_usd = BigUint::from(0u8);
+    let mut total_trades = 0;
+    let mut unpriced_trades = 0;
+
+    for intent in intents.iter().filter(|i| i.status == IntentStatus::Settled) {
+        total_trades += 1;
+        let token_in = normalize_token(&intent.public_inputs.token_in);
+        let token_out = normalize_token(&intent.public_inputs.token_out);
+        let Ok(sent) = TokenAmount::parse(&intent.public_inputs.amount_in, token_decimals_for(&token_in)) else {
+            unpriced_trades += 1;
+            continue;
+        };
+        volume.entry(token_in.clone()).or_default().0 += sent.base_units();
+
+        let counterparty = match &intent.matched_with {
+            Some(nullifier) => state.read_storage.get_intent(nullifier).await.unwrap_or_else(|e| {
+                warn!("Failed to load counterparty {} for user summary: {}", log_nullifier(nullifier), e);
+                None
+            }),
+            None => None,
+        };
+        let received = counterparty.and_then(|c| {
+            TokenAmount::parse(&c.public_inputs.amount_in, token_decimals_for(&token_out)).ok()
+        });
+        let Some(received) = received else {
+            unpriced_trades += 1;
+            continue;
+        };
+        volume.entry(token_out.clone()).or_default().1 += received.base_units();
+
+        for token in [&token_in, &token_out] {
+            if !rates.contains_key(token.as_str()) {
+                let rate = match state.oracle.get_cross_rate(token, "USD", None).await {
+                    Ok(rate) => Some(rate),
+                    Err(e) => {
+                        warn!("No USD price for token {} in user summary: {}", token, e);
+                        None
+                    }
+                };
+                rates.insert(token.clone(), rate);
+            }
+        }
+        let value = |token: &str, amount: &TokenAmount| {
+            let rate = rates.get(token).and_then(|r| r.as_ref())?;
+            usd_value(amount.base_units(), token_decimals_for(token), rate, USD_DECIMALS)
+        };
+        match (value(&token_in, &sent), value(&token_out, &received)) {
+            (Some(sent_value), Some(received_value)) => {
+                sent_usd += sent_value;
+                received_usd += received_value;
+            }
+            _ => unpriced_trades += 1,
+        }
+    }
+
+    let realized_pnl_usd = if received_usd >= sent_usd {
+        TokenAmount::from_base_units(&received_usd - &sent_usd, USD_DECIMALS).to_string()
+    } else {
+        format!("-{}", TokenAmount::from_base_units(&sent_usd - &received_usd, USD_DECIMALS))
+    };
+    let volume = volume
+        .into_iter()
+        .map(|(token, (sent, received))| {
+            let decimals = token_decimals_for(&token);
+            TokenVolume {
+                symbol: token_symbol_for(&token).map(ToString::to_string),
+                sent: TokenAmount::from_base_units(sent, decimals).to_string(),
+                received: TokenAmount::from_base_units(received, decimals).to_string(),
+                token,
+            }
+        })
+        .collect();
+
+    Ok(JsonResponse(UserSummaryResponse {
+        user,
+        intent_count: intents.len(),
+        total_trades,
+        volume,
+        sent_usd: TokenAmount::from_base_units(sent_usd, USD_DECIMALS).to_string(),
+        received_usd: TokenAmount::from_base_units(received_usd, USD_DECIMALS).to_string(),
+        realized_pnl_usd,
+        pnl_is_estimate: true,
+        pnl_note: PNL_NOTE.to_string(),
+        unpriced_trades,
+        computed_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
     }))
 }
 
-async fn get_pending_intents(
-    State(state): State<AppState>,
-    headers: HeaderMap,
-    axum::extract::Query(query): axum::extract::Query<std::collections::HashMap<String, String>>,
-) -> ApiResult<JsonResponse<Vec<IntentView>>> {
-    let correlation_id = correlation_id_from_headers(&headers);
-    require_auth(&headers, &state, &correlation_id)?;
+const EVENT_EXPORT_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Deserialize)]
+struct EventExportQuery {
+    /// Unix seconds, inclusive.
+    from: u64,
+    /// Unix seconds, exclusive. Defaults to now.
+    to: Option<u64>,
+    format: Option<String>,
+    /// Comma-separated event names, e.g. `matched,settled`.
+    #[serde(rename = "type")]
+    event_type: Option<String>,
+    user: Option<String>,
+}
+
+/// Streams the recorded event log for `[from, to)` as NDJSON, one event per line in the live
+/// feed's shape. Pages through Redis as the client reads, so large ranges are never buffered.
+async fn export_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<EventExportQuery>,
+) -> ApiResult<axum::response::Response> {
+    let correlation_id = correlation_id_from_headers(&headers);
+    require_admin(&headers, &state, &correlation_id).await?;
+    let bad_request = |message: &str| {
+        (
+            StatusCode::BAD_REQUEST,
+            JsonResponse(error_response(
+                "INVALID_REQUEST",
+                message,
+                Some(correlation_id.clone()),
+            )),
+        )
+    };
+
+    if let Some(format) = &query.format {
+        if !format.trim().eq_ignore_ascii_case("ndjson") {
+            return Err(bad_request("format must be ndjson"));
+        }
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let to = query.to.unwrap_or(now + 1);
+    if to <= query.from {
+        return Err(bad_request("to must be after from"));
+    }
+    let types: Option<std::collections::HashSet<String>> = query.event_type.as_deref().map(|t| {
+        t.split(',')
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect()
+    });
+    let user = query.user.as_deref().map(normalize_token);
+    let to_ms = (to as i64).saturating_mul(1000);
+
+    // Cursor: (score to resume at, records at that score already sent).
+    let storage = state.storage.clone();
+    let start = Some(((query.from as i64).saturating_mul(1000), 0usize));
+    let stream = futures::stream::unfold(start, move |cursor| {
+        let storage = storage.clone();
+        let types = types.clone();
+        let user = user.clone();
+        async move {
+            let (from_ms, skip) = cursor?;
+            let page = match storage
+                .get_events_page(from_ms, to_ms, skip, EVENT_EXPORT_PAGE_SIZE)
+                .await
+            {
+                Ok(page) if page.is_empty() => return None,
+                Ok(page) => page,
+                Err(e) => {
+                    error!("Event export aborted: {}", e);
+                    let err = std::io::Error::new(std::io::ErrorKind::Other, e.to_string());
+                    return Some((Err(err), None));
+                }
+            };
 
-    match state.storage.get_pending_intents().await {
-        Ok(intents) => {
-            // Wallets / libraries sometimes return the same Starknet address with different
-            // zero-padding. Compare by felt value when possible to avoid false mismatches.
-            let user_filter_raw = query.get("user").map(|v| v.trim().to_string());
-            let user_filter_felt = user_filter_raw
-                .as_deref()
-                .and_then(|v| (!v.trim().is_empty()).then_some(v))
-                .and_then(|v| Felt::from_hex(v).ok());
-            let user_filter_lc = user_filter_raw
-                .as_deref()
-                .map(|v| v.trim().to_lowercase())
-                .filter(|v| !v.is_empty());
+            let next = if page.len() < EVENT_EXPORT_PAGE_SIZE {
+                None
+            } else {
+                let last = page[page.len() - 1].1;
+                let at_last = page.iter().rev().take_while(|(_, score)| *score == last).count();
+                if last == from_ms {
+                    Some((from_ms, skip + page.len()))
+                } else {
+                    Some((last, at_last))
+                }
+            };
 
-            let views: Vec<IntentView> = intents
-                .into_iter()
-                .filter(|intent| {
-                    if let Some(user_felt) = user_filter_felt {
-                        if let Ok(intent_user_felt) = Felt::from_hex(intent.public_inputs.user.trim()) {
-                            return intent_user_felt == user_felt;
-                        }
-                        // Fall back to string compare if parsing fails.
-                    }
-                    if let Some(ref user_lc) = user_filter_lc {
-                        return intent.public_inputs.user.trim().to_lowercase() == *user_lc;
+            let mut chunk = String::new();
+            for (record, _) in page {
+                let Some(record) = record else {
+                    continue;
+                };
+                if types.as_ref().is_some_and(|t| !t.contains(record.event.name())) {
+                    continue;
+                }
+                if let Some(user) = &user {
+                    if !record.users.iter().any(|u| normalize_token(u) == *user) {
+                        continue;
                     }
-                    true
-                })
-                .map(|intent| IntentView {
-                    id: intent.id,
-                    nullifier: intent.nullifier,
-                    user: intent.public_inputs.user,
-                    status: intent.status,
-                    created_at: intent.created_at,
-                    expires_at: intent.expires_at,
-                    matched_with: intent.matched_with,
-                    settlement_tx_hash: intent.settlement_tx_hash,
-                })
-                .collect();
-            Ok(JsonResponse(views))
-        }
-        Err(e) => {
-            error!("Failed to get pending intents: {}", e);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                JsonResponse(error_response(
-                    "QUERY_ERROR",
-                    "Failed to get pending intents",
-                    Some(correlation_id),
-                )),
-            ))
+                }
+                if let Ok(line) = serde_json::to_string(&record.event) {
+                    chunk.push_str(&line);
+                    chunk.push('\n');
+                }
+            }
+            Some((Ok::<_, std::io::Error>(chunk), next))
         }
+    });
+
+    Ok((
+        [(axum::http::header::CONTENT_TYPE, "application/x-ndjson")],
+        axum::body::Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+/// Redis is capped at 256MB (`redis.conf`), so no bundle of ours is larger.
+const STATE_IMPORT_MAX_BYTES: usize = 256 * 1024 * 1024;
+/// Body limit for `POST /v1/intents/batch`, whatever `MAX_BATCH_INTENTS` is: twice axum's default,
+/// which single submissions get, and plenty for a batch of typical proofs.
+const BATCH_BODY_LIMIT_BYTES: usize = 4 * 1024 * 1024;
+
+/// State transfer moves every proof and address in the book, so on top of the admin check it needs
+/// real authentication (`REQUIRE_AUTH`) and a `STATE_BUNDLE_KEY` to sign or verify bundles with.
+async fn require_state_transfer<'a>(
+    headers: &HeaderMap,
+    state: &'a AppState,
+    correlation_id: &str,
+) -> ApiResult<(String, &'a BundleSigner)> {
+    let subject = require_admin(headers, state, correlation_id).await?;
+    let disabled = |message: &str| {
+        (
+            StatusCode::FORBIDDEN,
+            JsonResponse(error_response(
+                "STATE_TRANSFER_DISABLED",
+                message,
+                Some(correlation_id.to_string()),
+            )),
+        )
+    };
+    if !state.api_config.require_auth {
+        return Err(disabled("State export/import requires REQUIRE_AUTH=true"));
+    }
+    match &state.bundle_signer {
+        Some(Ok(signer)) => Ok((subject, signer)),
+        Some(Err(_)) => Err(disabled("STATE_BUNDLE_KEY is invalid; see the solver log")),
+        None => Err(disabled("State export/import requires STATE_BUNDLE_KEY")),
     }
 }
 
-async fn get_intents_by_user(
+/// Every intent, match and nonce reservation as one signed NDJSON bundle for `POST /v1/admin/import`.
+/// Streamed one SCAN page at a time as the client reads, signed line by line; a failure mid-export
+/// cuts the body short before the signature line, so import rejects it.
+async fn export_state(
     State(state): State<AppState>,
     headers: HeaderMap,
-    axum::extract::Query(query): axum::extract::Query<std::collections::HashMap<String, String>>,
-) -> ApiResult<JsonResponse<Vec<IntentView>>> {
+) -> ApiResult<Response> {
     let correlation_id = correlation_id_from_headers(&headers);
-    require_auth(&headers, &state, &correlation_id)?;
+    let (subject, signer) = require_state_transfer(&headers, &state, &correlation_id).await?;
 
-    let user = query
-        .get("user")
-        .map(|v| v.trim().to_string())
-        .filter(|v| !v.is_empty())
-        .ok_or_else(|| {
+    let mut writer = signer.writer();
+    let header = writer
+        .line(&BundleLine::Header { version: STATE_BUNDLE_VERSION, exported_at: chrono::Utc::now() })
+        .map_err(|e| {
+            error!("State export failed to encode: {}", e);
             (
-                StatusCode::BAD_REQUEST,
+                StatusCode::INTERNAL_SERVER_ERROR,
                 JsonResponse(error_response(
-                    "INVALID_REQUEST",
-                    "Missing user query parameter",
+                    "EXPORT_ERROR",
+                    "Failed to export solver state",
                     Some(correlation_id.clone()),
                 )),
             )
         })?;
+    info!("State export by {} started", subject);
 
-    match state.storage.get_intents_by_user(&user).await {
-        Ok(mut intents) => {
-            // Compatibility: older deployments may have intents in `intents:pending` but no per-user index.
-            // If the index is empty, fall back to scanning pending and filtering by user felt value.
-            if intents.is_empty() {
-                if let Ok(pending) = state.storage.get_pending_intents().await {
-                    let user_felt = Felt::from_hex(user.trim()).ok();
-                    intents = pending
-                        .into_iter()
-                        .filter(|intent| {
-                            if let (Some(a), Ok(b)) = (user_felt, Felt::from_hex(intent.public_inputs.user.trim())) {
-                                a == b
-                            } else {
-                                intent.public_inputs.user.trim().eq_ignore_ascii_case(user.trim())
-                            }
-                        })
-                        .collect();
+    fn aborted(e: anyhow::Error) -> std::io::Error {
+        error!("State export aborted: {}", e);
+        std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+    }
+    // `None` cursor: every page is out, the signature line is next.
+    let storage = state.storage.clone();
+    let start = Some((Some(ExportCursor::default()), writer, header));
+    let stream = futures::stream::unfold(start, move |step| {
+        let storage = storage.clone();
+        async move {
+            let (cursor, mut writer, mut chunk) = step?;
+            let Some(cursor) = cursor else {
+                return Some((writer.finish().map(|signature| chunk + &signature).map_err(aborted), None));
+            };
+            let (records, next) = match storage.export_state_page(cursor).await {
+                Ok(page) => page,
+                Err(e) => return Some((Err(aborted(e)), None)),
+            };
+            for record in &records {
+                match writer.line(record) {
+                    Ok(line) => chunk.push_str(&line),
+                    Err(e) => return Some((Err(aborted(e)), None)),
                 }
             }
-
-            let mut views: Vec<IntentView> = intents
-                .into_iter()
-                .map(|intent| IntentView {
-                    id: intent.id,
-                    nullifier: intent.nullifier,
-                    user: intent.public_inputs.user,
-                    status: intent.status,
-                    created_at: intent.created_at,
-                    expires_at: intent.expires_at,
-                    matched_with: intent.matched_with,
-                    settlement_tx_hash: intent.settlement_tx_hash,
-                })
-                .collect();
-            views.sort_by(|a, b| b.created_at.cmp(&a.created_at));
-            Ok(JsonResponse(views))
-        }
-        Err(e) => {
-            error!("Failed to get intents by user: {}", e);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                JsonResponse(error_response(
-                    "QUERY_ERROR",
-                    "Failed to get intents",
-                    Some(correlation_id),
-                )),
-            ))
+            Some((Ok::<_, std::io::Error>(chunk), Some((next, writer, String::new()))))
         }
-    }
+    });
+
+    Ok((
+        [
+            (axum::http::header::CONTENT_TYPE, "application/x-ndjson"),
+            (axum::http::header::CONTENT_DISPOSITION, "attachment; filename=\"starkshield-state.ndjson\""),
+        ],
+        axum::body::Body::from_stream(stream),
+    )
+        .into_response())
 }
 
-async fn get_stats(
+/// Restores a bundle from `GET /v1/admin/export` after checking its signature. Existing records are
+/// kept, and index sets are rebuilt from the imported records.
+async fn import_state(
     State(state): State<AppState>,
     headers: HeaderMap,
-) -> ApiResult<JsonResponse<SolverStats>> {
+    body: String,
+) -> ApiResult<JsonResponse<ImportStateResponse>> {
     let correlation_id = correlation_id_from_headers(&headers);
-    require_auth(&headers, &state, &correlation_id)?;
+    let (subject, signer) = require_state_transfer(&headers, &state, &correlation_id).await?;
 
-    match state.storage.get_stats().await {
-        Ok(stats) => Ok(JsonResponse(stats)),
-        Err(e) => {
-            error!("Failed to get stats: {}", e);
-            Err((
+    let records = signer.decode(&body).map_err(|e| {
+        let msg = e.to_string();
+        warn!("Rejected state import by {}: {}", subject, msg);
+        let (code, message) = if msg.contains("BUNDLE_SIGNATURE_INVALID") {
+            ("BUNDLE_SIGNATURE_INVALID", "Bundle signature does not verify under STATE_BUNDLE_KEY")
+        } else if msg.contains("BUNDLE_VERSION_UNSUPPORTED") {
+            ("BUNDLE_VERSION_UNSUPPORTED", "Bundle was exported by an incompatible solver version")
+        } else {
+            ("BUNDLE_MALFORMED", "Body is not a state bundle")
+        };
+        (
+            StatusCode::BAD_REQUEST,
+            JsonResponse(error_response(code, message, Some(correlation_id.clone()))),
+        )
+    })?;
+
+    let max_pending = state.api_config.max_pending_intents;
+    let summary = state.storage.import_state(records, max_pending).await.map_err(|e| {
+        error!("State import by {} failed: {}", subject, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            JsonResponse(error_response(
+                "IMPORT_ERROR",
+                "State import failed part-way; records imported so far are kept, re-running it is safe",
+                Some(correlation_id.clone()),
+            )),
+        )
+    })?;
+    info!("State import by {}: {:?}", subject, summary);
+
+    Ok(JsonResponse(ImportStateResponse {
+        success: true,
+        summary,
+        correlation_id,
+    }))
+}
+
+/// Subject of requests without a session when `REQUIRE_AUTH=false`. It owns no user.
+const ANONYMOUS_SUBJECT: &str = "public";
+
+async fn require_auth(
+    headers: &HeaderMap,
+    state: &AppState,
+    correlation_id: &str,
+) -> ApiResult<String> {
+    // Allow turning auth off for demo deployments where the UI is public: requests without a
+    // bearer token pass as ANONYMOUS_SUBJECT, which admin and per-user checks still refuse.
+    if !state.api_config.require_auth
+        && (bearer_token_from_headers(headers).is_none() || !sessions_configured(&state.api_config))
+    {
+        return Ok(ANONYMOUS_SUBJECT.to_string());
+    }
+    Ok(require_session(headers, state, correlation_id).await?.sub)
+}
+
+/// Whether sessions exist at all: `AUTH_PASSWORD` and `JWT_SECRET` are both set. Only
+/// `REQUIRE_AUTH=false` deployments may leave them out, and then nobody can log in or act as admin.
+fn sessions_configured(config: &ApiConfig) -> bool {
+    !config.jwt_secret.trim().is_empty() && !config.auth_password.trim().is_empty()
+}
+
+/// The claims of the request's bearer token: signed by the keyring, unexpired and not logged out.
+/// The logout check is one Redis GET; if Redis can't answer, the request is refused.
+async fn require_session(
+    headers: &HeaderMap,
+    state: &AppState,
+    correlation_id: &str,
+) -> ApiResult<JwtClaims> {
+    let unauthorized = |message: &str| {
+        (
+            StatusCode::UNAUTHORIZED,
+            JsonResponse(error_response("UNAUTHORIZED", message, Some(correlation_id.to_string()))),
+        )
+    };
+    if !sessions_configured(&state.api_config) {
+        return Err(unauthorized("Sessions are not configured on this solver"));
+    }
+    let token = bearer_token_from_headers(headers).ok_or_else(|| unauthorized("Missing bearer token"))?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let claims = state
+        .jwt_keyring
+        .read()
+        .verify_typed(token, now, TokenType::Access)
+        .map_err(|_| unauthorized("Invalid or expired bearer token"))?;
+
+    if !claims.jti.is_empty() {
+        let revoked = state.storage.is_token_revoked(&claims.jti).await.map_err(|e| {
+            error!("Failed to check token revocation: {}", e);
+            (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 JsonResponse(error_response(
-                    "STATS_ERROR",
-                    "Failed to get statistics",
-                    Some(correlation_id),
+                    "AUTH_ERROR",
+                    "Failed to check the bearer token",
+                    Some(correlation_id.to_string()),
                 )),
-            ))
+            )
+        })?;
+        if revoked {
+            return Err(unauthorized("Bearer token has been logged out"));
         }
     }
+    Ok(claims)
 }
 
-fn require_auth(
+/// A session of the configured operator account (`AUTH_USERNAME`), whatever `REQUIRE_AUTH` says.
+/// Without `AUTH_PASSWORD` and `JWT_SECRET` nobody is admin.
+async fn require_admin(
     headers: &HeaderMap,
     state: &AppState,
     correlation_id: &str,
 ) -> ApiResult<String> {
-    // Allow turning auth off for demo deployments where the UI is public.
-    // When disabled, all protected endpoints are treated as publicly accessible.
-    if !state.api_config.require_auth {
-        return Ok("public".to_string());
-    }
-
-    let token = bearer_token_from_headers(headers).ok_or_else(|| {
-        (
-            StatusCode::UNAUTHORIZED,
+    let subject = require_session(headers, state, correlation_id).await?.sub;
+    if subject != state.api_config.auth_username {
+        return Err((
+            StatusCode::FORBIDDEN,
             JsonResponse(error_response(
-                "UNAUTHORIZED",
-                "Missing bearer token",
+                "FORBIDDEN",
+                "Admin access required",
                 Some(correlation_id.to_string()),
             )),
-        )
-    })?;
+        ));
+    }
+    Ok(subject)
+}
 
-    let claims = verify_token(token, &state.api_config.jwt_secret).map_err(|_| {
-        (
-            StatusCode::UNAUTHORIZED,
+/// Per-user endpoints: the admin account, or a session whose subject is the wallet address itself.
+async fn require_owner(
+    headers: &HeaderMap,
+    state: &AppState,
+    user: &str,
+    correlation_id: &str,
+) -> ApiResult<String> {
+    let subject = require_auth(headers, state, correlation_id).await?;
+    if !subject_owns_user(&state.api_config, &subject, user) {
+        return Err((
+            StatusCode::FORBIDDEN,
             JsonResponse(error_response(
-                "UNAUTHORIZED",
-                "Invalid or expired bearer token",
+                "FORBIDDEN",
+                "Only the account owner can access this resource",
                 Some(correlation_id.to_string()),
             )),
-        )
-    })?;
+        ));
+    }
+    Ok(subject)
+}
 
-    Ok(claims.sub)
+/// Admin sessions act for any user; otherwise the session subject must be the user's address.
+/// Anonymous callers (`REQUIRE_AUTH=false` without a session) own nothing.
+fn subject_owns_user(config: &ApiConfig, subject: &str, user: &str) -> bool {
+    if !config.require_auth && subject == ANONYMOUS_SUBJECT {
+        return false;
+    }
+    if sessions_configured(config) && subject == config.auth_username {
+        return true;
+    }
+    match (Felt::from_hex(subject.trim()), Felt::from_hex(user.trim())) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
 }
 
 fn bearer_token_from_headers(headers: &HeaderMap) -> Option<&str> {
@@ -1520,15 +5321,8 @@ fn bearer_token_from_headers(headers: &HeaderMap) -> Option<&str> {
     value.strip_prefix("Bearer ").map(str::trim)
 }
 
-fn is_valid_signature(signature: &str) -> bool {
-    let trimmed = signature.trim();
-    if !trimmed.starts_with("0x") || trimmed.len() < 66 {
-        return false;
-    }
-    trimmed
-        .trim_start_matches("0x")
-        .chars()
-        .all(|ch| ch.is_ascii_hexdigit())
+fn is_valid_signature(signature: &SignatureInput) -> bool {
+    is_well_formed_signature(&signature.elements(), signature.is_single_blob())
 }
 
 fn correlation_id_from_headers(headers: &HeaderMap) -> String {
@@ -1550,5 +5344,119 @@ fn error_response(code: &str, message: &str, correlation_id: Option<String>) ->
             message: message.to_string(),
         },
         correlation_id,
+        errors: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submission(nullifier: &str, nonce: u64) -> SubmitIntentRequest {
+        serde_json::from_value(serde_json::json!({
+            "intent_hash": "0x1",
+            "nullifier": nullifier,
+            "proof_data": [],
+            "public_inputs": {
+                "user": "0x0111",
+                "token_in": "0x049d",
+                "token_out": "0x053c",
+                "amount_in": "1",
+                "min_amount_out": "1",
+                "deadline": 1_900_000_000u64,
+                "nonce": nonce,
+                "chain_id": "SN_SEPOLIA",
+                "domain_separator": "starkshield-v1",
+                "version": 1
+            },
+            "encrypted_details": "",
+            "signature": "0x1,0x2"
+        }))
+        .expect("submission")
+    }
+
+    #[test]
+    fn batches_flag_a_nullifier_repeated_within_them() {
+        let batch = [submission("0xaa01", 1), submission("0xAA01", 2), submission("0xbb02", 3)];
+        assert_eq!(repeated_nullifiers(&batch), vec![None, Some(0), None]);
+    }
+
+    #[tokio::test]
+    async fn a_batch_stores_what_it_can_and_refuses_the_rest() {
+        let stored = std::sync::Mutex::new(std::collections::HashSet::from(["0xbb02".to_string()]));
+        let batch = vec![submission("0xaa01", 1), submission("0xbb02", 2), submission("0xAA01", 3)];
+        let results = submit_batch(batch, |_, request| {
+            let stored = &stored;
+            async move {
+                if !stored.lock().unwrap().insert(request.nullifier.clone()) {
+                    return Err(ErrorDetail {
+                        code: "DUPLICATE_INTENT".to_string(),
+                        message: "Intent already exists".to_string(),
+                    });
+                }
+                Ok(SubmitIntentResponse {
+                    intent_id: request.nullifier,
+                    status: IntentStatus::Pending,
+                    estimated_match_time: None,
+                    compatible_counterparties: 0,
+                    warnings: Vec::new(),
+                    correlation_id: "cid".to_string(),
+                })
+            }
+        })
+        .await;
+
+        let outcomes: Vec<(bool, Option<&str>)> = results
+            .iter()
+            .map(|r| (r.success, r.error.as_ref().map(|e| e.code.as_str())))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![(true, None), (false, Some("DUPLICATE_INTENT")), (false, Some("DUPLICATE_INTENT"))]
+        );
+        assert_eq!(results[0].intent_id.as_deref(), Some("0xaa01"));
+        assert_eq!(stored.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn settlement_estimates_report_the_fee_or_the_revert() {
+        use crate::config::FeeToken;
+        use crate::starknet::{MockSettlementFeeEstimator, SettlementFeeEstimate};
+
+        let intents = crate::fixture::parse_fixture(include_str!("../fixtures/matching_sample.json")).expect("fixture");
+        let data = SettlementData { ekubo_pool: "0x0".to_string(), sqrt_price_limit: "0".to_string(), surplus_distribution: None };
+        let pair = MatchedPair::new(intents[0].clone(), intents[1].clone(), data);
+
+        let mut estimator = MockSettlementFeeEstimator::new();
+        estimator.expect_estimate_settlement_fee().times(1).returning(|_| {
+            Ok(SettlementFeeEstimate { overall_fee: BigUint::from(1_250_000_000_000_000u64), fee_token: FeeToken::Strk })
+        });
+        let JsonResponse(response) = settlement_estimate_response(&estimator, &pair, "cid-1".to_string())
+            .await
+            .expect("estimate");
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            serde_json::json!({
+                "success": true,
+                "match_id": pair.id,
+                "overall_fee": "1250000000000000",
+                "fee_token": "strk",
+                "fee_token_address": FeeToken::Strk.address(),
+                "correlation_id": "cid-1"
+            })
+        );
+
+        let mut reverting = MockSettlementFeeEstimator::new();
+        reverting.expect_estimate_settlement_fee().times(1).returning(|_| {
+            Err(anyhow::anyhow!(
+                "SETTLEMENT_ESTIMATE_REVERTED code=INSUFFICIENT_ALLOWANCE reason=ERC20: insufficient allowance"
+            ))
+        });
+        let (status, JsonResponse(error)) = settlement_estimate_response(&reverting, &pair, "cid-2".to_string())
+            .await
+            .expect_err("revert");
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.code, "SETTLEMENT_ESTIMATE_REVERTED");
+        assert_eq!(error.error, "Settlement would revert: code=INSUFFICIENT_ALLOWANCE");
     }
 }
@@ -3,25 +3,55 @@ use chrono::{Duration, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 
+/// What a token may be used for. Access tokens authenticate requests; refresh tokens are only
+/// accepted by `/v1/auth/refresh`, to get a new access token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    /// Also what tokens issued before the claim existed are.
+    #[default]
+    Access,
+    Refresh,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JwtClaims {
     pub sub: String,
     pub iat: usize,
     pub exp: usize,
+    /// Token id, for the logout denylist. Tokens issued before it existed have none and can't be
+    /// revoked.
+    #[serde(default)]
+    pub jti: String,
+    #[serde(default)]
+    pub token_type: TokenType,
+}
+
+pub fn issue_token(
+    subject: &str,
+    jwt_secret: &str,
+    expires_minutes: i64,
+    token_type: TokenType,
+) -> Result<String> {
+    issue_claims(jwt_secret, &new_claims(subject, expires_minutes, token_type))
 }
 
-pub fn issue_token(subject: &str, jwt_secret: &str, expires_minutes: i64) -> Result<String> {
+fn new_claims(subject: &str, expires_minutes: i64, token_type: TokenType) -> JwtClaims {
     let now = Utc::now();
     let exp = now + Duration::minutes(expires_minutes);
-    let claims = JwtClaims {
+    JwtClaims {
         sub: subject.to_string(),
         iat: now.timestamp().max(0) as usize,
         exp: exp.timestamp().max(0) as usize,
-    };
+        jti: uuid::Uuid::new_v4().to_string(),
+        token_type,
+    }
+}
 
+fn issue_claims(jwt_secret: &str, claims: &JwtClaims) -> Result<String> {
     encode(
         &Header::default(),
-        &claims,
+        claims,
         &EncodingKey::from_secret(jwt_secret.as_bytes()),
     )
     .map_err(|e| anyhow!("failed to issue token: {}", e))
@@ -36,3 +66,155 @@ pub fn verify_token(token: &str, jwt_secret: &str) -> Result<JwtClaims> {
     .map_err(|e| anyhow!("invalid token: {}", e))?;
     Ok(token_data.claims)
 }
+
+/// Minimum length accepted for a rotated-in signing secret.
+pub const MIN_JWT_SECRET_LEN: usize = 32;
+
+#[derive(Debug, Clone)]
+struct RetiringSecret {
+    secret: String,
+    retire_at_unix: u64,
+}
+
+/// JWT signing secrets: tokens are always issued with `current`, and verified against `current`
+/// or, until its grace period ends, the `previous` secret. This allows rotation without logging
+/// everyone out.
+#[derive(Debug, Clone)]
+pub struct JwtKeyring {
+    current: String,
+    previous: Option<RetiringSecret>,
+}
+
+impl JwtKeyring {
+    pub fn new(current: String) -> Self {
+        Self { current, previous: None }
+    }
+
+    /// Also accept `previous` until `retire_at_unix` (e.g. `JWT_SECRET_PREVIOUS` after a restart-based rotation).
+    pub fn with_previous(mut self, previous: String, retire_at_unix: u64) -> Self {
+        self.previous = Some(RetiringSecret {
+            secret: previous,
+            retire_at_unix,
+        });
+        self
+    }
+
+    pub fn issue(&self, subject: &str, expires_minutes: i64) -> Result<String> {
+        issue_token(subject, &self.current, expires_minutes, TokenType::Access)
+    }
+
+    /// Issues a refresh token, returning its claims too so the caller can record the `jti`.
+    pub fn issue_refresh(&self, subject: &str, expires_minutes: i64) -> Result<(String, JwtClaims)> {
+        let claims = new_claims(subject, expires_minutes, TokenType::Refresh);
+        Ok((issue_claims(&self.current, &claims)?, claims))
+    }
+
+    pub fn verify(&self, token: &str, now_unix: u64) -> Result<JwtClaims> {
+        match verify_token(token, &self.current) {
+            Ok(claims) => Ok(claims),
+            Err(e) => match &self.previous {
+                Some(prev) if now_unix < prev.retire_at_unix => verify_token(token, &prev.secret),
+                _ => Err(e),
+            },
+        }
+    }
+
+    /// `verify`, also requiring the token to be of `token_type`, so a refresh token can't stand in
+    /// for an access token or the other way round.
+    pub fn verify_typed(&self, token: &str, now_unix: u64, token_type: TokenType) -> Result<JwtClaims> {
+        let claims = self.verify(token, now_unix)?;
+        if claims.token_type != token_type {
+            return Err(anyhow!("expected a {:?} token, got {:?}", token_type, claims.token_type));
+        }
+        Ok(claims)
+    }
+
+    /// Promotes `new_secret` to current. The old current stays valid for `grace_seconds`;
+    /// a still-retiring previous secret is dropped.
+    pub fn rotate(&mut self, new_secret: String, grace_seconds: u64, now_unix: u64) -> Result<()> {
+        if new_secret.len() < MIN_JWT_SECRET_LEN {
+            return Err(anyhow!("new secret must be at least {} characters", MIN_JWT_SECRET_LEN));
+        }
+        if new_secret == self.current {
+            return Err(anyhow!("new secret must differ from the current secret"));
+        }
+        let old = std::mem::replace(&mut self.current, new_secret);
+        self.previous = Some(RetiringSecret {
+            secret: old,
+            retire_at_unix: now_unix.saturating_add(grace_seconds),
+        });
+        Ok(())
+    }
+
+    /// When the previous secret stops being accepted, if one is still retiring at `now_unix`.
+    pub fn previous_retires_at(&self, now_unix: u64) -> Option<u64> {
+        self.previous
+            .as_ref()
+            .filter(|p| now_unix < p.retire_at_unix)
+            .map(|p| p.retire_at_unix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = "old-secret-old-secret-old-secret-0001";
+    const NEW: &str = "new-secret-new-secret-new-secret-0002";
+
+    #[test]
+    fn rotated_keyring_accepts_previous_secret_during_grace() {
+        let now = Utc::now().timestamp() as u64;
+        let mut keyring = JwtKeyring::new(OLD.to_string());
+        let old_token = keyring.issue("admin", 60).expect("issue");
+
+        keyring.rotate(NEW.to_string(), 600, now).expect("rotate");
+        let new_token = keyring.issue("admin", 60).expect("issue");
+
+        assert!(verify_token(&new_token, NEW).is_ok(), "new tokens use the new secret");
+        assert!(keyring.verify(&old_token, now).is_ok());
+        assert!(keyring.verify(&new_token, now).is_ok());
+        assert_eq!(keyring.previous_retires_at(now), Some(now + 600));
+
+        assert!(keyring.verify(&old_token, now + 600).is_err(), "previous secret retired after grace");
+        assert!(keyring.verify(&new_token, now + 600).is_ok());
+        assert_eq!(keyring.previous_retires_at(now + 600), None);
+    }
+
+    #[test]
+    fn issued_tokens_carry_a_unique_jti() {
+        let first = verify_token(&issue_token("admin", OLD, 60, TokenType::Access).expect("issue"), OLD).expect("verify");
+        let second = verify_token(&issue_token("admin", OLD, 60, TokenType::Access).expect("issue"), OLD).expect("verify");
+        assert!(uuid::Uuid::try_parse(&first.jti).is_ok());
+        assert_ne!(first.jti, second.jti);
+    }
+
+    #[test]
+    fn refresh_tokens_are_typed_and_expire() {
+        let keyring = JwtKeyring::new(OLD.to_string());
+        let now = Utc::now().timestamp() as u64;
+        let access = keyring.issue("admin", 15).expect("issue");
+        assert!(keyring.verify_typed(&access, now, TokenType::Access).is_ok());
+        assert!(keyring.verify_typed(&access, now, TokenType::Refresh).is_err(), "access tokens don't refresh");
+
+        let (refresh, issued) = keyring.issue_refresh("admin", 60).expect("issue");
+        let verified = keyring.verify_typed(&refresh, now, TokenType::Refresh).expect("verify");
+        assert_eq!(verified.jti, issued.jti);
+        assert!(keyring.verify_typed(&refresh, now, TokenType::Access).is_err(), "refresh tokens don't authenticate");
+
+        // Past `exp` plus jsonwebtoken's default 60s leeway.
+        let expired = issue_token("admin", OLD, -2, TokenType::Refresh).expect("issue");
+        assert!(keyring.verify_typed(&expired, now, TokenType::Refresh).is_err());
+
+        let legacy: JwtClaims = serde_json::from_str(r#"{"sub":"admin","iat":0,"exp":1}"#).unwrap();
+        assert_eq!(legacy.token_type, TokenType::Access);
+    }
+
+    #[test]
+    fn rotate_rejects_short_or_unchanged_secrets() {
+        let mut keyring = JwtKeyring::new(OLD.to_string());
+        assert!(keyring.rotate("short".to_string(), 600, 0).is_err());
+        assert!(keyring.rotate(OLD.to_string(), 600, 0).is_err());
+        assert!(keyring.previous_retires_at(0).is_none());
+    }
+}
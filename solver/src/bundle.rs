@@ -0,0 +1,147 @@
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use sha3::Sha3_256;
+
+use crate::models::BundleLine;
+
+/// Bumped whenever `BundleLine` changes incompatibly; import refuses other versions.
+pub const STATE_BUNDLE_VERSION: u32 = 1;
+
+type BundleMac = Hmac<Sha3_256>;
+
+/// Signs and verifies state bundles with `STATE_BUNDLE_KEY`. A bundle is newline-delimited
+/// `BundleLine` JSON, header first, closed by a signature line over everything before it.
+#[derive(Clone)]
+pub struct BundleSigner {
+    key: Vec<u8>,
+}
+
+impl BundleSigner {
+    pub fn from_hex(key: &str) -> Result<Self> {
+        let key = hex::decode(key.trim().trim_start_matches("0x"))
+            .map_err(|_| anyhow::anyhow!("STATE_BUNDLE_KEY must be hex"))?;
+        if key.len() < 32 {
+            return Err(anyhow::anyhow!("STATE_BUNDLE_KEY must be at least 32 bytes (64 hex digits)"));
+        }
+        Ok(Self { key })
+    }
+
+    fn mac(&self, signed: &[u8]) -> BundleMac {
+        let mut mac = BundleMac::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(signed);
+        mac
+    }
+
+    /// Signs a bundle line by line, for exports streamed as they are read.
+    pub fn writer(&self) -> BundleWriter {
+        BundleWriter { mac: self.mac(&[]) }
+    }
+
+    /// `lines` as ndjson with the signature line appended.
+    #[cfg(test)]
+    pub fn encode(&self, lines: &[BundleLine]) -> Result<String> {
+        let mut writer = self.writer();
+        let mut bundle = String::new();
+        for line in lines {
+            bundle.push_str(&writer.line(line)?);
+        }
+        bundle.push_str(&writer.finish()?);
+        Ok(bundle)
+    }
+
+    /// Checks the signature and version, then returns the records after the header.
+    pub fn decode(&self, bundle: &str) -> Result<Vec<BundleLine>> {
+        let trimmed = bundle.trim_end_matches(['\n', '\r']);
+        let (signed, signature) = match trimmed.rfind('\n') {
+            Some(at) => trimmed.split_at(at + 1),
+            None => return Err(anyhow::anyhow!("BUNDLE_MALFORMED: no signature line")),
+        };
+        let Ok(BundleLine::Signature { hmac }) = serde_json::from_str(signature) else {
+            return Err(anyhow::anyhow!("BUNDLE_MALFORMED: last line is not a signature"));
+        };
+        let tag = hex::decode(hmac).map_err(|_| anyhow::anyhow!("BUNDLE_SIGNATURE_INVALID"))?;
+        self.mac(signed.as_bytes())
+            .verify_slice(&tag)
+            .map_err(|_| anyhow::anyhow!("BUNDLE_SIGNATURE_INVALID"))?;
+
+        let mut lines = signed.lines().filter(|l| !l.trim().is_empty()).enumerate().map(|(n, l)| {
+            serde_json::from_str::<BundleLine>(l)
+                .map_err(|e| anyhow::anyhow!("BUNDLE_MALFORMED: line {}: {}", n + 1, e))
+        });
+        match lines.next().transpose()? {
+            Some(BundleLine::Header { version, .. }) if version == STATE_BUNDLE_VERSION => {}
+            Some(BundleLine::Header { version, .. }) => {
+                return Err(anyhow::anyhow!("BUNDLE_VERSION_UNSUPPORTED: {}", version));
+            }
+            _ => return Err(anyhow::anyhow!("BUNDLE_MALFORMED: first line is not a header")),
+        }
+        let records = lines.collect::<Result<Vec<_>>>()?;
+        if records
+            .iter()
+            .any(|l| matches!(l, BundleLine::Header { .. } | BundleLine::Signature { .. }))
+        {
+            return Err(anyhow::anyhow!("BUNDLE_MALFORMED: header or signature in the middle"));
+        }
+        Ok(records)
+    }
+}
+
+/// A bundle being written: every line passed through `line` is covered by the `finish` signature.
+pub struct BundleWriter {
+    mac: BundleMac,
+}
+
+impl BundleWriter {
+    /// `line` as one ndjson line, newline included.
+    pub fn line(&mut self, line: &BundleLine) -> Result<String> {
+        let mut json = serde_json::to_string(line)?;
+        json.push('\n');
+        self.mac.update(json.as_bytes());
+        Ok(json)
+    }
+
+    /// The closing signature line.
+    pub fn finish(self) -> Result<String> {
+        let hmac = hex::encode(self.mac.finalize().into_bytes());
+        let mut json = serde_json::to_string(&BundleLine::Signature { hmac })?;
+        json.push('\n');
+        Ok(json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle(signer: &BundleSigner) -> String {
+        signer
+            .encode(&[
+                BundleLine::Header { version: STATE_BUNDLE_VERSION, exported_at: chrono::Utc::now() },
+                BundleLine::NonceReservation { user: "0xabc".to_string(), nonce: 7, ttl_seconds: 60 },
+            ])
+            .expect("encode")
+    }
+
+    #[test]
+    fn signed_bundles_round_trip_and_reject_tampering() {
+        let signer = BundleSigner::from_hex(&"11".repeat(32)).expect("key");
+        let encoded = bundle(&signer);
+        let records = signer.decode(&encoded).expect("decode");
+        assert!(matches!(records.as_slice(), [BundleLine::NonceReservation { nonce: 7, .. }]));
+
+        let tampered = encoded.replace("\"nonce\":7", "\"nonce\":8");
+        assert!(signer.decode(&tampered).unwrap_err().to_string().contains("BUNDLE_SIGNATURE_INVALID"));
+
+        let other = BundleSigner::from_hex(&"22".repeat(32)).expect("key");
+        assert!(other.decode(&encoded).is_err());
+
+        let unsigned = encoded.lines().next().unwrap().to_string();
+        assert!(signer.decode(&unsigned).unwrap_err().to_string().contains("BUNDLE_MALFORMED"));
+    }
+
+    #[test]
+    fn bundle_keys_must_be_long_enough() {
+        assert!(BundleSigner::from_hex("abcd").is_err());
+        assert!(BundleSigner::from_hex("zz").is_err());
+    }
+}
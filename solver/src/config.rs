@@ -6,35 +6,581 @@ use std::env;
 pub struct Config {
     pub server_addr: String,
     pub redis_url: String,
+    /// Read replica for read-heavy API queries (pending lists, user queries, stats, intent lookups);
+    /// unset reads from `redis_url`.
+    pub redis_read_url: Option<String>,
     pub starknet_rpc: String,
     pub dark_pool_address: String,
-    pub solver_address: Option<String>,
-    pub solver_private_key: String,
-    pub auto_settle_onchain: bool,
+    /// Accounts settlements are sent from, from `SOLVER_ADDRESS`/`SOLVER_PRIVATE_KEY` or the
+    /// `SOLVER_ADDRESSES`/`SOLVER_PRIVATE_KEYS` lists. The first also signs relayed settlements.
+    /// Empty disables on-chain settlement.
+    pub solver_accounts: Vec<SolverAccountConfig>,
+    pub settle_mode: SettleMode,
     pub matching_config: MatchingConfig,
     pub api_config: ApiConfig,
-    pub enforce_prechecks: bool,
+    pub precheck_mode: PrecheckMode,
+    /// Simulate settlement txs before sending them. `None` = default by chain (on for mainnet).
+    pub simulate_before_settle: Option<bool>,
+    pub pragma_summary_stats_address: String,
+    /// Pins the Pragma oracle used for the spot fallback; unset resolves it via `get_oracle_address`.
+    pub pragma_oracle_address: Option<String>,
+    /// Max `(pair_id, window)` entries in the Pragma price cache (LRU beyond that); 0 disables it.
+    /// Each oracle source has its own cache of this size.
+    pub pragma_price_cache_max_entries: usize,
+    /// Weight of the primary Pragma source against `oracle_sources`.
+    pub pragma_weight: u32,
+    /// Further Pragma deployments to spread price reads over and cross-check against.
+    pub oracle_sources: Vec<OracleSourceConfig>,
+    /// A source this far (basis points) from the consensus price is flagged as deviating.
+    pub oracle_deviation_bps: u32,
+    /// SETTLEMENT_FEE_TOKEN: ETH settles with v1 invokes, STRK with v3 invokes.
+    pub solver_fee_token: FeeToken,
+    /// Log full outbound JSON-RPC requests/responses at debug level (verbose).
+    pub debug_rpc_logging: bool,
+    /// Shorten user addresses and hash nullifiers in log lines (`utils::log_address` / `log_nullifier`).
+    pub log_redact: bool,
+    /// Block tag for every raw `starknet_call` the solver makes (prechecks, preflight, status, Pragma).
+    pub rpc_block_tag: RpcBlockTag,
+    /// Per-request timeout for raw JSON-RPC calls (prechecks, status reads, Pragma, the proxy); 0 disables it.
+    pub rpc_timeout_ms: u64,
+    /// Timeout for the proof preflight simulation, which runs full verification and can take far
+    /// longer than other calls; 0 disables it.
+    pub preflight_timeout_ms: u64,
+    /// NATS server to publish lifecycle events to (`nats://host:4222`); unset disables publishing.
+    pub event_bus_url: Option<String>,
+    /// Events go to `<event_bus_subject>.<event>`.
+    pub event_bus_subject: String,
+    /// How long events stay in the Redis log behind `/v1/events/export`; 0 disables the log.
+    pub event_log_retention_seconds: u64,
+    /// 32-byte hex key for encrypting intent and match payloads in Redis; unset stores plaintext JSON.
+    pub storage_encryption_key: Option<String>,
+    /// Hex key (32+ bytes) that signs and verifies `/v1/admin/export` bundles; unset disables state transfer.
+    pub state_bundle_key: Option<String>,
+    /// zstd-compress intent and match payloads in Redis (proof calldata dominates their size).
+    pub compress_intents: bool,
+    /// Paymaster base URL (AVNU-style `build-typed-data` / `execute`); unset settles from the solver account.
+    pub settlement_relayer_url: Option<String>,
+    /// Sent as `api-key` to the relayer, for sponsored (gasless) settlement.
+    pub settlement_relayer_api_key: Option<String>,
+    /// `SETTLEMENT_RELAYER_MAX_FEE` in 10^-9 fee-token units: the largest fee transfer the solver
+    /// signs into a relayed settlement. Unset signs sponsored settlements only.
+    pub settlement_relayer_max_fee_nano: Option<u64>,
+    /// How often pending intents past `expires_at` are marked `Expired`; 0 disables the sweep.
+    pub expiry_sweep_interval_ms: u64,
+    /// How long intent records are kept after `expires_at` while the sweep is on, so the
+    /// `Expired` status can still be queried.
+    pub expired_intent_retention_seconds: u64,
+    /// Ekubo core contract that pair pools are resolved against; unset settles through the
+    /// placeholder pool address derived from the token addresses.
+    pub ekubo_core_address: Option<String>,
 }
 
+/// Ekubo's 0.05% fee tier: 0.0005 * 2^128.
+const DEFAULT_EKUBO_POOL_FEE: u128 = 170141183460469231731687303715884105;
+
+/// Token the solver account pays settlement fees in. ETH uses v1 invokes, STRK needs v3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeeToken {
+    Eth,
+    Strk,
+}
+
+impl FeeToken {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "eth" => Some(Self::Eth),
+            "strk" => Some(Self::Strk),
+            _ => None,
+        }
+    }
+
+    /// Fee token contract (same address on mainnet and Sepolia).
+    pub fn address(&self) -> &'static str {
+        match self {
+            Self::Eth => "0x049d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7",
+            Self::Strk => "0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d",
+        }
+    }
+}
+
+/// How long a `(user, nonce)` reservation blocks reuse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NonceMode {
+    /// Reserved until the intent's deadline; once it passes the nonce can be used again.
+    Deadline,
+    /// Reserved for at least `NONCE_STRICT_WINDOW_SECONDS`, whatever the deadline.
+    Strict,
+}
+
+impl NonceMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "deadline" => Some(Self::Deadline),
+            "strict" => Some(Self::Strict),
+            _ => None,
+        }
+    }
+
+    /// TTL for a reservation made at `now` for an intent expiring at `deadline` (never below 1s).
+    pub fn reservation_ttl(&self, deadline: u64, now: u64, strict_window_seconds: u64) -> u64 {
+        let until_deadline = deadline.saturating_sub(now);
+        let ttl = match self {
+            Self::Deadline => until_deadline,
+            Self::Strict => until_deadline.max(strict_window_seconds),
+        };
+        ttl.max(1)
+    }
+}
+
+/// Who triggers on-chain settlement of a new match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettleMode {
+    /// Settle as soon as a match is created; the retry loop re-attempts every unsettled match.
+    Auto,
+    /// Settle only from `POST /v1/matches/:id/confirm`; failures are not retried.
+    Manual,
+    /// Like `Manual`, but the retry loop re-attempts matches an operator has confirmed.
+    ManualWithRetry,
+}
+
+impl SettleMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().replace('-', "_").as_str() {
+            "auto" => Some(Self::Auto),
+            "manual" => Some(Self::Manual),
+            "manual_with_retry" => Some(Self::ManualWithRetry),
+            _ => None,
+        }
+    }
+
+    pub fn retries(&self) -> bool {
+        !matches!(self, Self::Manual)
+    }
+}
+
+/// What the balance/allowance precheck does to a submission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrecheckMode {
+    Off,
+    /// Run the precheck, but only log a failure and return it as a warning; settlement is the
+    /// final gate.
+    Warn,
+    /// Reject the submission when the precheck fails, including when the RPC can't be read.
+    Enforce,
+}
+
+impl PrecheckMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "off" => Some(Self::Off),
+            "warn" => Some(Self::Warn),
+            "enforce" => Some(Self::Enforce),
+            _ => None,
+        }
+    }
+}
+
+/// Which unsettled matches the retry loop attempts first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettlementOrder {
+    /// Earliest of the two intent deadlines first, so near-expiry matches don't lapse.
+    Urgency,
+    /// Largest surplus (amount in over the counterparty's minimum, both sides, counted in intent A's
+    /// input token) first.
+    Profit,
+}
+
+impl SettlementOrder {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "urgency" => Some(Self::Urgency),
+            "profit" => Some(Self::Profit),
+            _ => None,
+        }
+    }
+}
+
+/// Who receives a match's surplus (what each side gives beyond the counterparty's minimum).
+/// The maker is the intent that was in the book first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SurplusPolicy {
+    Maker,
+    Taker,
+    /// Half to each side.
+    Split,
+    /// Kept by the solver as its fee.
+    Solver,
+}
+
+impl SurplusPolicy {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "maker" => Some(Self::Maker),
+            "taker" => Some(Self::Taker),
+            "split" => Some(Self::Split),
+            "solver" => Some(Self::Solver),
+            _ => None,
+        }
+    }
+
+    /// `(maker, taker, solver)` shares of the surplus in basis points; they always sum to 10000.
+    pub fn shares_bps(self) -> (u16, u16, u16) {
+        match self {
+            Self::Maker => (10_000, 0, 0),
+            Self::Taker => (0, 10_000, 0),
+            Self::Split => (5_000, 5_000, 0),
+            Self::Solver => (0, 0, 10_000),
+        }
+    }
+}
+
+/// What the matcher does with a complementary token pair that has no Ekubo pool to settle through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoPoolPolicy {
+    /// Leave the pair unmatched; its intents stay in the book.
+    Skip,
+    /// Match it anyway with a zero `ekubo_pool`, so the contract nets the two sides directly.
+    Net,
+}
+
+impl NoPoolPolicy {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "skip" => Some(Self::Skip),
+            "net" => Some(Self::Net),
+            _ => None,
+        }
+    }
+}
+
+/// Block tag that read calls are made against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RpcBlockTag {
+    /// Includes transactions not yet in a block, so a just-sent `approve` is visible at once.
+    Pending,
+    Latest,
+    /// `pending`, retried at `latest` when the node rejects the tag (some providers have no pending block).
+    PendingThenLatest,
+}
+
+impl RpcBlockTag {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "pending" => Some(Self::Pending),
+            "latest" => Some(Self::Latest),
+            "pending_then_latest" => Some(Self::PendingThenLatest),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a whole-token amount such as `2.5` into 10^-9 units (at most nine decimals).
+fn parse_fee_token_nano(value: &str) -> Option<u64> {
+    let (whole, fraction) = value.trim().split_once('.').unwrap_or((value.trim(), ""));
+    if (whole.is_empty() && fraction.is_empty())
+        || fraction.len() > 9
+        || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let whole: u64 = if whole.is_empty() { 0 } else { whole.parse().ok()? };
+    let fraction: u64 = format!("{:0<9}", fraction).parse().ok()?;
+    whole.checked_mul(1_000_000_000)?.checked_add(fraction)
+}
+
+/// Request field a `proof_public_inputs` position must carry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProofInputField {
+    IntentHash,
+    Nullifier,
+}
+
+impl ProofInputField {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::IntentHash => "intent_hash",
+            Self::Nullifier => "nullifier",
+        }
+    }
+}
+
+/// `proof_public_inputs[position]` must equal the request's `field`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofInputBinding {
+    pub position: usize,
+    pub field: ProofInputField,
+}
+
+/// Garaga intent circuit layout: `[intentHash, nullifier, currentTime]`. `currentTime` is checked
+/// against `MAX_PROOF_AGE_SECONDS` instead.
+pub fn default_proof_input_bindings() -> Vec<ProofInputBinding> {
+    vec![
+        ProofInputBinding {
+            position: 0,
+            field: ProofInputField::IntentHash,
+        },
+        ProofInputBinding {
+            position: 1,
+            field: ProofInputField::Nullifier,
+        },
+    ]
+}
+
+/// Parses `PROOF_PUBLIC_INPUT_BINDINGS`, e.g. `0=intent_hash,1=nullifier`. `none` disables binding.
+fn parse_proof_input_bindings(value: &str) -> Result<Vec<ProofInputBinding>> {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(Vec::new());
+    }
+    let mut bindings: Vec<ProofInputBinding> = Vec::new();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let invalid = || {
+            anyhow::anyhow!(
+                "PROOF_PUBLIC_INPUT_BINDINGS entries must look like <position>=<intent_hash|nullifier> (got {:?})",
+                entry
+            )
+        };
+        let (position, field) = entry.split_once('=').ok_or_else(invalid)?;
+        let position: usize = position.trim().parse().map_err(|_| invalid())?;
+        let field = match field.trim().to_lowercase().as_str() {
+            "intent_hash" => ProofInputField::IntentHash,
+            "nullifier" => ProofInputField::Nullifier,
+            _ => return Err(invalid()),
+        };
+        if bindings.iter().any(|b| b.position == position) {
+            anyhow::bail!("PROOF_PUBLIC_INPUT_BINDINGS binds position {} twice", position);
+        }
+        bindings.push(ProofInputBinding { position, field });
+    }
+    Ok(bindings)
+}
+
+/// A solver account: its deployed address and the key that signs for it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SolverAccountConfig {
+    pub address: String,
+    pub private_key: String,
+}
+
+/// Pairs up the comma-separated `SOLVER_ADDRESSES` and `SOLVER_PRIVATE_KEYS`, in order.
+fn parse_solver_accounts(addresses: &str, private_keys: &str) -> Result<Vec<SolverAccountConfig>> {
+    let split = |value: &str| value.split(',').map(str::trim).map(str::to_string).collect::<Vec<_>>();
+    let (addresses, private_keys) = (split(addresses), split(private_keys));
+    if addresses.len() != private_keys.len() {
+        anyhow::bail!(
+            "SOLVER_ADDRESSES has {} entries but SOLVER_PRIVATE_KEYS has {}",
+            addresses.len(),
+            private_keys.len()
+        );
+    }
+    let mut accounts: Vec<SolverAccountConfig> = Vec::with_capacity(addresses.len());
+    for (address, private_key) in addresses.into_iter().zip(private_keys) {
+        if address.is_empty() || private_key.is_empty() {
+            anyhow::bail!("SOLVER_ADDRESSES and SOLVER_PRIVATE_KEYS may not have empty entries");
+        }
+        if accounts.iter().any(|a| a.address.eq_ignore_ascii_case(&address)) {
+            anyhow::bail!("SOLVER_ADDRESSES lists {} twice", address);
+        }
+        accounts.push(SolverAccountConfig { address, private_key });
+    }
+    Ok(accounts)
+}
+
+/// An extra Pragma deployment from `ORACLE_SOURCES`, queried next to the primary one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OracleSourceConfig {
+    pub name: String,
+    pub summary_stats_address: String,
+    pub weight: u32,
+    /// Defaults to the solver's Starknet RPC.
+    pub rpc_url: Option<String>,
+}
+
+/// Parses `ORACLE_SOURCES`, e.g. `backup=0x49ee...:2@https://rpc.example,alt=0x1234...`: a name,
+/// the summary stats address, an optional `:weight` (default 1) and an optional `@rpc_url`.
+fn parse_oracle_sources(value: &str) -> Result<Vec<OracleSourceConfig>> {
+    let mut sources: Vec<OracleSourceConfig> = Vec::new();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let invalid = || {
+            anyhow::anyhow!(
+                "ORACLE_SOURCES entries must look like <name>=<summary_stats_address>[:<weight>][@<rpc_url>] (got {:?})",
+                entry
+            )
+        };
+        let (name, rest) = entry.split_once('=').ok_or_else(invalid)?;
+        let (address, rpc_url) = match rest.split_once('@') {
+            Some((address, url)) if !url.trim().is_empty() => (address, Some(url.trim().to_string())),
+            Some(_) => return Err(invalid()),
+            None => (rest, None),
+        };
+        let (address, weight) = match address.split_once(':') {
+            Some((address, weight)) => (address, weight.trim().parse::<u32>().map_err(|_| invalid())?),
+            None => (address, 1),
+        };
+        let (name, address) = (name.trim(), address.trim());
+        if name.is_empty() || address.is_empty() || weight == 0 {
+            return Err(invalid());
+        }
+        if name == PRIMARY_ORACLE_SOURCE || sources.iter().any(|s| s.name == name) {
+            anyhow::bail!("ORACLE_SOURCES uses the source name {:?} twice", name);
+        }
+        sources.push(OracleSourceConfig {
+            name: name.to_string(),
+            summary_stats_address: address.to_string(),
+            weight,
+            rpc_url,
+        });
+    }
+    Ok(sources)
+}
+
+/// Name of the source configured by `PRAGMA_SUMMARY_STATS_ADDRESS` / `PRAGMA_ORACLE_ADDRESS`.
+pub const PRIMARY_ORACLE_SOURCE: &str = "pragma";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MatchingConfig {
+    /// Intents whose `amount_in` is worth less than this (Pragma USD price) sit out matching;
+    /// 0 disables the check.
     pub min_match_amount_usd: f64,
+    /// Furthest a match's implied price may sit from the Pragma reference; 0 disables the check.
     pub max_slippage_bps: u16,
+    /// Oracle prices whose feed last updated longer ago than this don't count for the slippage
+    /// band or `min_match_amount_usd`; 0 disables the check.
+    pub max_oracle_staleness_seconds: u64,
+    /// While a pair's price is stale, match it on amounts alone instead of pausing it.
+    pub stale_oracle_amount_matching: bool,
     pub match_timeout_seconds: u64,
     pub batch_size: usize,
     pub poll_interval_ms: u64,
+    /// `/health/ready` reports the matching loop stalled once its last tick is older than this;
+    /// 0 disables the check.
+    pub matching_loop_stale_after_ms: u64,
     pub max_invalid_proof_retries: u64,
+    pub settlement_lock_ttl_seconds: u64,
+    /// How long a sent settlement may stay pending on L2 before its match is failed; 0 waits for
+    /// as long as it takes.
+    pub confirm_timeout_seconds: u64,
+    /// How long a settling marker whose transaction the node can't find (or whose hash was never
+    /// recorded) is waited on before the match goes back to the retry loop.
+    pub settling_marker_grace_seconds: u64,
+    /// A pair that misses crossing by at most this share of the required amount is a near-miss; 0 disables tracking.
+    pub near_miss_tolerance_bps: u16,
+    /// Near-misses older than this don't justify an extension, and only intents expiring
+    /// within this window may be extended.
+    pub near_miss_window_seconds: u64,
+    /// How far an extension may push a deadline; 0 disables extensions.
+    pub max_intent_extension_seconds: u64,
+    /// Re-verify both proofs (batched `starknet_call`) right before creating a match.
+    pub verify_proofs_at_match: bool,
+    /// Don't submit a settlement unless both deadlines are at least this far past the latest block time.
+    pub settlement_deadline_buffer_seconds: u64,
+    /// Put both intents of a terminally failed match back in the book instead of failing them, for
+    /// failures that are about the pairing rather than either intent.
+    pub requeue_failed_matches: bool,
+    /// How long a requeued pair may not be matched with each other again.
+    pub failed_pair_cooldown_seconds: u64,
+    pub settlement_order: SettlementOrder,
+    /// Most matches the retry loop settles in one multicall transaction; 1 sends one per match.
+    pub settlement_batch_size: usize,
+    /// Surplus shares encoded into each match's settlement data; unset leaves the split to the
+    /// contract and keeps the original `SettlementData` layout.
+    pub surplus_policy: Option<SurplusPolicy>,
+    /// Check that each pair's Ekubo pool is deployed before matching, and what to do when it isn't;
+    /// unset skips the check.
+    pub no_pool_policy: Option<NoPoolPolicy>,
+    /// `DAILY_GAS_BUDGET` in 10^-9 fee-token units; settlement pauses for the rest of the UTC day
+    /// once today's fees reach it.
+    pub daily_gas_budget_nano: Option<u64>,
+    /// `SETTLE_GAS_CEILING`: above this L1 gas price (10^-9 fee token per gas, i.e. gwei for ETH),
+    /// the retry loop defers matches that aren't close to expiry.
+    pub settle_gas_ceiling_gwei: Option<u64>,
+    /// Matches whose earliest deadline is within this many seconds settle whatever the gas price.
+    pub settle_gas_urgent_seconds: u64,
+    /// How long settlement outcomes stay behind `/v1/settlements`; 0 disables the history.
+    pub settlement_history_retention_seconds: u64,
+    /// Settled matches kept in the `/v1/matches/history` trade log, newest first; 0 disables it.
+    pub match_history_max: usize,
+    /// Match the smaller of two mismatched intents completely and rest the rest of the larger one
+    /// as a residual intent. The DarkPool settles whole intents only, so these are never submitted.
+    pub partial_fills: bool,
+    /// Look for three-intent rings among tokens that found no two-party match in a pass. Rings are
+    /// reported only: the DarkPool settles pairs, so their intents stay pending.
+    pub ring_matching: bool,
+    /// Fee tier of the Ekubo pools matches settle through, as Ekubo's 0.128 fixed-point fraction.
+    pub ekubo_pool_fee: u128,
+    /// Tick spacing of those pools.
+    pub ekubo_tick_spacing: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiConfig {
     pub max_intent_size_bytes: usize,
+    /// Requests per minute each client may make to the private routes; 0 disables the limit.
     pub rate_limit_requests_per_minute: u32,
+    /// Behind a proxy: take the client IP from `X-Real-IP` instead of the peer address.
+    pub trust_proxy_headers: bool,
     pub cors_origins: Vec<String>,
     pub require_auth: bool,
     pub jwt_secret: String,
+    /// Secret being rotated out; still accepted for `jwt_rotation_grace_seconds` after startup.
+    pub jwt_secret_previous: Option<String>,
+    /// How long a rotated-out JWT secret keeps verifying tokens.
+    pub jwt_rotation_grace_seconds: u64,
     pub auth_username: String,
     pub auth_password: String,
+    /// Max age of the proof's `currentTime` public signal at submission; 0 disables the check.
+    pub max_proof_age_seconds: u64,
+    /// Most elements `proof_data` may have; longer proofs are rejected at intake.
+    pub max_proof_data_len: usize,
+    /// Most elements `proof_public_inputs` may have.
+    pub max_public_inputs_len: usize,
+    pub nonce_mode: NonceMode,
+    /// Minimum reservation TTL under `NonceMode::Strict`.
+    pub nonce_strict_window_seconds: u64,
+    /// How long `/health` and `/stats` reuse the pending/matched counts; 0 disables caching.
+    pub stats_cache_ttl_ms: u64,
+    /// `proof_public_inputs` positions that must equal request fields on submission.
+    pub proof_input_bindings: Vec<ProofInputBinding>,
+    /// Test networks only: accept empty/placeholder proofs and skip the proof preflight.
+    /// Startup refuses this on mainnet.
+    pub allow_empty_proof: bool,
+    /// Check each intent's signature with the user's account contract. Startup refuses turning this
+    /// off on mainnet.
+    pub verify_intent_signatures: bool,
+    /// Signs settlement callbacks to intents' `callback_url`; intents can't set one without it.
+    pub webhook_secret: Option<String>,
+    /// Serve the deprecated unversioned aliases (`/intent`, `/stats`, ...) next to `/v1`.
+    pub enable_legacy_routes: bool,
+    /// Minimum gap between one user's submissions (new or replacement intents); 0 disables it.
+    pub min_submit_interval_ms: u64,
+    /// A new intent matching one of the same user's pending intents submitted within this window
+    /// (same tokens, amounts within `near_duplicate_tolerance_bps`) is rejected; 0 disables the guard.
+    pub near_duplicate_window_seconds: u64,
+    pub near_duplicate_tolerance_bps: u16,
+    /// Global cap on `intents:pending`; new submissions get `BOOK_FULL` at the cap. 0 disables it.
+    pub max_pending_intents: u64,
+    /// Most intents one `POST /v1/intents/batch` may carry; longer batches are refused whole.
+    pub max_batch_intents: usize,
+    /// `domain_separator` values intents may carry; empty accepts any non-empty separator.
+    pub accepted_domain_separators: Vec<String>,
+    /// `/v1/book/quote` shows nothing for a fill spread over fewer resting intents, so a quote
+    /// can't size one intent.
+    pub quote_min_counterparties: usize,
+    /// Significant digits quote totals are rounded down to; 0 leaves them exact.
+    pub quote_significant_digits: u32,
+}
+
+fn parse_flag(value: &str) -> bool {
+    matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes" | "on")
 }
 
 impl Config {
@@ -44,7 +590,7 @@ impl Config {
 
         let require_auth = env::var("REQUIRE_AUTH")
             .ok()
-            .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+            .map(|v| parse_flag(&v))
             .unwrap_or(true);
 
         let jwt_secret = match env::var("JWT_SECRET") {
@@ -67,25 +613,130 @@ impl Config {
             _ => String::new(),
         };
 
+        // SETTLEMENT_FEE_TOKEN supersedes SOLVER_FEE_TOKEN, which still works when it is unset.
+        let solver_fee_token = match ["SETTLEMENT_FEE_TOKEN", "SOLVER_FEE_TOKEN"]
+            .into_iter()
+            .find_map(|name| env::var(name).ok().filter(|v| !v.trim().is_empty()).map(|v| (name, v)))
+        {
+            Some((name, v)) => FeeToken::parse(&v)
+                .ok_or_else(|| anyhow::anyhow!("{} must be one of: eth, strk (got {:?})", name, v))?,
+            None => FeeToken::Eth,
+        };
+
+        let nonce_mode = match env::var("NONCE_REPLAY_MODE") {
+            Ok(v) if !v.trim().is_empty() => NonceMode::parse(&v)
+                .ok_or_else(|| anyhow::anyhow!("NONCE_REPLAY_MODE must be one of: deadline, strict (got {:?})", v))?,
+            _ => NonceMode::Deadline,
+        };
+
+        // SETTLE_MODE supersedes the older AUTO_SETTLE_ONCHAIN flag, which still picks auto/manual.
+        let non_empty = |name: &str| env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let solver_accounts = match (non_empty("SOLVER_ADDRESSES"), non_empty("SOLVER_PRIVATE_KEYS")) {
+            (Some(addresses), Some(private_keys)) => parse_solver_accounts(&addresses, &private_keys)?,
+            (None, None) => {
+                let private_key = env::var("SOLVER_PRIVATE_KEY")
+                    .map_err(|_| anyhow::anyhow!("SOLVER_PRIVATE_KEY must be set"))?;
+                non_empty("SOLVER_ADDRESS")
+                    .map(|address| SolverAccountConfig { address: address.trim().to_string(), private_key })
+                    .into_iter()
+                    .collect()
+            }
+            _ => anyhow::bail!("SOLVER_ADDRESSES and SOLVER_PRIVATE_KEYS must be set together"),
+        };
+
+        let settle_mode = match env::var("SETTLE_MODE") {
+            Ok(v) if !v.trim().is_empty() => SettleMode::parse(&v).ok_or_else(|| {
+                anyhow::anyhow!("SETTLE_MODE must be one of: auto, manual, manual_with_retry (got {:?})", v)
+            })?,
+            _ => match env::var("AUTO_SETTLE_ONCHAIN") {
+                Ok(v) if parse_flag(&v) => SettleMode::Auto,
+                _ => SettleMode::Manual,
+            },
+        };
+
+        // PRECHECK_MODE supersedes the older ENFORCE_PRECHECKS flag, which still picks enforce/off.
+        let precheck_mode = match env::var("PRECHECK_MODE") {
+            Ok(v) if !v.trim().is_empty() => PrecheckMode::parse(&v)
+                .ok_or_else(|| anyhow::anyhow!("PRECHECK_MODE must be one of: off, warn, enforce (got {:?})", v))?,
+            _ => match env::var("ENFORCE_PRECHECKS") {
+                Ok(v) if parse_flag(&v) => PrecheckMode::Enforce,
+                _ => PrecheckMode::Off,
+            },
+        };
+
+        let daily_gas_budget_nano = match env::var("DAILY_GAS_BUDGET") {
+            Ok(v) if !v.trim().is_empty() => Some(parse_fee_token_nano(&v).ok_or_else(|| {
+                anyhow::anyhow!("DAILY_GAS_BUDGET must be a fee-token amount such as 2.5 (got {:?})", v)
+            })?),
+            _ => None,
+        };
+
+        let settlement_relayer_max_fee_nano = match env::var("SETTLEMENT_RELAYER_MAX_FEE") {
+            Ok(v) if !v.trim().is_empty() => Some(parse_fee_token_nano(&v).ok_or_else(|| {
+                anyhow::anyhow!("SETTLEMENT_RELAYER_MAX_FEE must be a fee-token amount such as 0.01 (got {:?})", v)
+            })?),
+            _ => None,
+        };
+
+        let settle_gas_ceiling_gwei = match env::var("SETTLE_GAS_CEILING") {
+            Ok(v) if !v.trim().is_empty() => Some(v.trim().parse::<u64>().map_err(|_| {
+                anyhow::anyhow!("SETTLE_GAS_CEILING must be a whole number of gwei (got {:?})", v)
+            })?),
+            _ => None,
+        };
+
+        let settlement_order = match env::var("SETTLEMENT_ORDER") {
+            Ok(v) if !v.trim().is_empty() => SettlementOrder::parse(&v)
+                .ok_or_else(|| anyhow::anyhow!("SETTLEMENT_ORDER must be one of: urgency, profit (got {:?})", v))?,
+            _ => SettlementOrder::Urgency,
+        };
+
+        let surplus_policy = match env::var("SURPLUS_POLICY") {
+            Ok(v) if !v.trim().is_empty() => Some(SurplusPolicy::parse(&v).ok_or_else(|| {
+                anyhow::anyhow!("SURPLUS_POLICY must be one of: maker, taker, split, solver (got {:?})", v)
+            })?),
+            _ => None,
+        };
+
+        let no_pool_policy = match env::var("NO_POOL_POLICY") {
+            Ok(v) if !v.trim().is_empty() => Some(NoPoolPolicy::parse(&v).ok_or_else(|| {
+                anyhow::anyhow!("NO_POOL_POLICY must be one of: skip, net (got {:?})", v)
+            })?),
+            _ => None,
+        };
+
+        let rpc_block_tag = match env::var("RPC_BLOCK_TAG") {
+            Ok(v) if !v.trim().is_empty() => RpcBlockTag::parse(&v).ok_or_else(|| {
+                anyhow::anyhow!("RPC_BLOCK_TAG must be one of: pending, latest, pending_then_latest (got {:?})", v)
+            })?,
+            _ => RpcBlockTag::PendingThenLatest,
+        };
+
+        let oracle_sources = match env::var("ORACLE_SOURCES") {
+            Ok(v) if !v.trim().is_empty() => parse_oracle_sources(&v)?,
+            _ => Vec::new(),
+        };
+
+        let proof_input_bindings = match env::var("PROOF_PUBLIC_INPUT_BINDINGS") {
+            Ok(v) if !v.trim().is_empty() => parse_proof_input_bindings(&v)?,
+            _ => default_proof_input_bindings(),
+        };
+
         Ok(Config {
             server_addr: env::var("SOLVER_ADDR")
                 .unwrap_or_else(|_| "0.0.0.0:8080".to_string()),
             redis_url: env::var("REDIS_URL")
                 .unwrap_or_else(|_| "redis://localhost:6379".to_string()),
+            redis_read_url: env::var("REDIS_READ_URL")
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
             starknet_rpc: env::var("STARKNET_RPC")
                 .unwrap_or_else(|_| "https://starknet-sepolia.public.blastapi.io/rpc/v0_8".to_string()),
             dark_pool_address: env::var("DARK_POOL_ADDRESS")
                 .map_err(|_| anyhow::anyhow!("DARK_POOL_ADDRESS must be set"))?,
-            solver_address: env::var("SOLVER_ADDRESS")
-                .ok()
-                .map(|v| v.trim().to_string())
-                .filter(|v| !v.is_empty()),
-            solver_private_key: env::var("SOLVER_PRIVATE_KEY")
-                .map_err(|_| anyhow::anyhow!("SOLVER_PRIVATE_KEY must be set"))?,
-            auto_settle_onchain: env::var("AUTO_SETTLE_ONCHAIN")
-                .ok()
-                .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes" | "on"))
-                .unwrap_or(false),
+            solver_accounts,
+            settle_mode,
             matching_config: MatchingConfig {
                 min_match_amount_usd: env::var("MIN_MATCH_AMOUNT_USD")
                     .ok()
@@ -95,6 +746,14 @@ impl Config {
                     .ok()
                     .and_then(|s| s.parse().ok())
                     .unwrap_or(50),
+                max_oracle_staleness_seconds: env::var("MAX_ORACLE_STALENESS_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(0),
+                stale_oracle_amount_matching: env::var("STALE_ORACLE_AMOUNT_MATCHING")
+                    .ok()
+                    .map(|v| parse_flag(&v))
+                    .unwrap_or(false),
                 match_timeout_seconds: env::var("MATCH_TIMEOUT_SECONDS")
                     .ok()
                     .and_then(|s| s.parse().ok())
@@ -107,10 +766,91 @@ impl Config {
                     .ok()
                     .and_then(|s| s.parse().ok())
                     .unwrap_or(1000),
+                matching_loop_stale_after_ms: env::var("MATCHING_LOOP_STALE_AFTER_MS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(60_000),
                 max_invalid_proof_retries: env::var("MAX_INVALID_PROOF_RETRIES")
                     .ok()
                     .and_then(|s| s.parse().ok())
                     .unwrap_or(5),
+                settlement_lock_ttl_seconds: env::var("SETTLEMENT_LOCK_TTL_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(120),
+                confirm_timeout_seconds: env::var("CONFIRM_TIMEOUT_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(90),
+                settling_marker_grace_seconds: env::var("SETTLING_MARKER_GRACE_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(600),
+                near_miss_tolerance_bps: env::var("NEAR_MISS_TOLERANCE_BPS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(100),
+                near_miss_window_seconds: env::var("NEAR_MISS_WINDOW_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(300),
+                max_intent_extension_seconds: env::var("MAX_INTENT_EXTENSION_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(0),
+                verify_proofs_at_match: env::var("VERIFY_PROOFS_AT_MATCH")
+                    .ok()
+                    .map(|v| parse_flag(&v))
+                    .unwrap_or(false),
+                settlement_deadline_buffer_seconds: env::var("SETTLEMENT_DEADLINE_BUFFER_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(30),
+                requeue_failed_matches: env::var("REQUEUE_FAILED_MATCHES")
+                    .ok()
+                    .map(|v| parse_flag(&v))
+                    .unwrap_or(false),
+                failed_pair_cooldown_seconds: env::var("FAILED_PAIR_COOLDOWN_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(600),
+                settlement_order,
+                settlement_batch_size: env::var("SETTLEMENT_BATCH_SIZE")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(1),
+                surplus_policy,
+                no_pool_policy,
+                daily_gas_budget_nano,
+                settle_gas_ceiling_gwei,
+                settle_gas_urgent_seconds: env::var("SETTLE_GAS_URGENT_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(900),
+                match_history_max: env::var("MATCH_HISTORY_MAX")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(10_000),
+                settlement_history_retention_seconds: env::var("SETTLEMENT_HISTORY_RETENTION_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(30 * 24 * 60 * 60), // 30 days
+                partial_fills: env::var("PARTIAL_FILLS")
+                    .ok()
+                    .map(|v| parse_flag(&v))
+                    .unwrap_or(false),
+                ring_matching: env::var("RING_MATCHING")
+                    .ok()
+                    .map(|v| parse_flag(&v))
+                    .unwrap_or(false),
+                ekubo_pool_fee: env::var("EKUBO_POOL_FEE")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(DEFAULT_EKUBO_POOL_FEE),
+                ekubo_tick_spacing: env::var("EKUBO_TICK_SPACING")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(1000), // 0.1%
             },
             api_config: ApiConfig {
                 max_intent_size_bytes: env::var("MAX_INTENT_SIZE_BYTES")
@@ -121,6 +861,10 @@ impl Config {
                     .ok()
                     .and_then(|s| s.parse().ok())
                     .unwrap_or(60),
+                trust_proxy_headers: env::var("TRUST_PROXY_HEADERS")
+                    .ok()
+                    .map(|v| parse_flag(&v))
+                    .unwrap_or(false),
                 cors_origins: env::var("CORS_ORIGINS")
                     .unwrap_or_else(|_| "http://localhost:5173".to_string())
                     .split(',')
@@ -128,14 +872,362 @@ impl Config {
                     .collect(),
                 require_auth,
                 jwt_secret,
+                jwt_secret_previous: env::var("JWT_SECRET_PREVIOUS")
+                    .ok()
+                    .filter(|v| !v.trim().is_empty()),
+                jwt_rotation_grace_seconds: env::var("JWT_ROTATION_GRACE_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(3600), // one access token lifetime
                 auth_username: env::var("AUTH_USERNAME")
                     .unwrap_or_else(|_| "admin".to_string()),
                 auth_password,
+                max_proof_age_seconds: env::var("MAX_PROOF_AGE_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(900),
+                max_proof_data_len: env::var("MAX_PROOF_DATA_LEN")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(4096),
+                max_public_inputs_len: env::var("MAX_PUBLIC_INPUTS_LEN")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(32),
+                nonce_mode,
+                nonce_strict_window_seconds: env::var("NONCE_STRICT_WINDOW_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(30 * 24 * 60 * 60), // 30 days
+                stats_cache_ttl_ms: env::var("STATS_CACHE_TTL_MS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(2000),
+                proof_input_bindings,
+                allow_empty_proof: env::var("ALLOW_EMPTY_PROOF")
+                    .ok()
+                    .map(|v| parse_flag(&v))
+                    .unwrap_or(false),
+                verify_intent_signatures: env::var("VERIFY_INTENT_SIGNATURES")
+                    .ok()
+                    .map(|v| parse_flag(&v))
+                    .unwrap_or(true),
+                webhook_secret: env::var("WEBHOOK_SECRET")
+                    .ok()
+                    .map(|v| v.trim().to_string())
+                    .filter(|v| !v.is_empty()),
+                enable_legacy_routes: env::var("ENABLE_LEGACY_ROUTES")
+                    .ok()
+                    .map(|v| parse_flag(&v))
+                    .unwrap_or(true),
+                min_submit_interval_ms: env::var("MIN_SUBMIT_INTERVAL_MS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(500),
+                near_duplicate_window_seconds: env::var("NEAR_DUPLICATE_WINDOW_SECONDS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(0),
+                near_duplicate_tolerance_bps: env::var("NEAR_DUPLICATE_TOLERANCE_BPS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(0),
+                max_pending_intents: env::var("MAX_PENDING_INTENTS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(0),
+                max_batch_intents: env::var("MAX_BATCH_INTENTS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(20),
+                accepted_domain_separators: env::var("ACCEPTED_DOMAIN_SEPARATORS")
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect(),
+                quote_min_counterparties: env::var("QUOTE_MIN_COUNTERPARTIES")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(3),
+                quote_significant_digits: env::var("QUOTE_SIGNIFICANT_DIGITS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(2),
             },
-            enforce_prechecks: env::var("ENFORCE_PRECHECKS")
+            precheck_mode,
+            simulate_before_settle: env::var("SIMULATE_BEFORE_SETTLE")
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+                .map(|v| parse_flag(&v)),
+            pragma_summary_stats_address: env::var("PRAGMA_SUMMARY_STATS_ADDRESS")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .unwrap_or_else(|| {
+                    // Pragma "Realized Volatility / TWAP" contract on Starknet Sepolia.
+                    // Source: Pragma docs -> Advanced -> Overview -> Contract Addresses -> Sepolia Testnet.
+                    "0x49eefafae944d07744d07cc72a5bf14728a6fb463c3eae5bca13552f5d455fd".to_string()
+                }),
+            pragma_oracle_address: env::var("PRAGMA_ORACLE_ADDRESS")
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+            pragma_price_cache_max_entries: env::var("PRAGMA_PRICE_CACHE_MAX_ENTRIES")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(256),
+            pragma_weight: env::var("PRAGMA_WEIGHT")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|w| *w > 0)
+                .unwrap_or(1),
+            oracle_sources,
+            oracle_deviation_bps: env::var("ORACLE_DEVIATION_BPS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(200),
+            solver_fee_token,
+            debug_rpc_logging: env::var("DEBUG_RPC_LOGGING")
+                .ok()
+                .map(|v| parse_flag(&v))
+                .unwrap_or(false),
+            log_redact: env::var("LOG_REDACT")
+                .ok()
+                .map(|v| parse_flag(&v))
+                .unwrap_or(false),
+            rpc_block_tag,
+            rpc_timeout_ms: env::var("RPC_TIMEOUT_MS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10_000),
+            preflight_timeout_ms: env::var("PREFLIGHT_TIMEOUT_MS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(30_000),
+            event_bus_url: env::var("EVENT_BUS_URL")
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+            event_bus_subject: env::var("EVENT_BUS_SUBJECT")
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+                .unwrap_or_else(|| "starkshield.events".to_string()),
+            event_log_retention_seconds: env::var("EVENT_LOG_RETENTION_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(7 * 24 * 60 * 60), // 7 days
+            storage_encryption_key: env::var("STORAGE_ENCRYPTION_KEY")
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+            state_bundle_key: env::var("STATE_BUNDLE_KEY")
                 .ok()
-                .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+            compress_intents: env::var("COMPRESS_INTENTS")
+                .ok()
+                .map(|v| parse_flag(&v))
                 .unwrap_or(false),
+            settlement_relayer_url: env::var("SETTLEMENT_RELAYER_URL")
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+            settlement_relayer_api_key: env::var("SETTLEMENT_RELAYER_API_KEY")
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
+            settlement_relayer_max_fee_nano,
+            expiry_sweep_interval_ms: env::var("EXPIRY_SWEEP_INTERVAL_MS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(30_000),
+            expired_intent_retention_seconds: env::var("EXPIRED_INTENT_RETENTION_SECONDS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(60 * 60), // 1 hour
+            ekubo_core_address: env::var("EKUBO_CORE_ADDRESS")
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty()),
         })
     }
+
+    /// `starknet_rpc` with an explicit JSON-RPC path.
+    pub fn starknet_rpc_url(&self) -> String {
+        // Many providers require an explicit JSON-RPC path (e.g. `/rpc/v0_8`).
+        // If the env is given as a bare host, default to v0_8 for Starknet Sepolia.
+        if let Ok(mut url) = reqwest::Url::parse(&self.starknet_rpc) {
+            let path = url.path();
+            if path.is_empty() || path == "/" {
+                url.set_path("/rpc/v0_8");
+                return url.to_string();
+            }
+        }
+        self.starknet_rpc.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+    const WINDOW: u64 = 30 * 24 * 60 * 60;
+
+    #[test]
+    fn deadline_mode_reserves_until_deadline() {
+        assert_eq!(NonceMode::Deadline.reservation_ttl(NOW + 600, NOW, WINDOW), 600);
+        // Already-past deadlines still get a minimal TTL rather than a non-expiring key.
+        assert_eq!(NonceMode::Deadline.reservation_ttl(NOW - 5, NOW, WINDOW), 1);
+    }
+
+    #[test]
+    fn strict_mode_outlives_short_deadlines() {
+        assert_eq!(NonceMode::Strict.reservation_ttl(NOW + 600, NOW, WINDOW), WINDOW);
+        assert_eq!(NonceMode::Strict.reservation_ttl(NOW + WINDOW * 2, NOW, WINDOW), WINDOW * 2);
+    }
+
+    #[test]
+    fn reuse_after_first_intent_expired() {
+        // First intent submitted at NOW with a 10 minute deadline; the same nonce is
+        // resubmitted an hour later with a later deadline.
+        let first_deadline = NOW + 600;
+        let resubmitted_at = NOW + 3600;
+        let still_reserved = |mode: NonceMode| NOW + mode.reservation_ttl(first_deadline, NOW, WINDOW) > resubmitted_at;
+
+        assert!(!still_reserved(NonceMode::Deadline), "deadline mode frees the nonce once the first intent expires");
+        assert!(still_reserved(NonceMode::Strict), "strict mode keeps the nonce burned");
+    }
+
+    #[test]
+    fn parses_nonce_mode() {
+        assert_eq!(NonceMode::parse(" Strict "), Some(NonceMode::Strict));
+        assert_eq!(NonceMode::parse("deadline"), Some(NonceMode::Deadline));
+        assert_eq!(NonceMode::parse("forever"), None);
+    }
+
+    #[test]
+    fn parses_settle_mode() {
+        assert_eq!(SettleMode::parse("auto"), Some(SettleMode::Auto));
+        assert_eq!(SettleMode::parse(" Manual "), Some(SettleMode::Manual));
+        assert_eq!(SettleMode::parse("manual_with_retry"), Some(SettleMode::ManualWithRetry));
+        assert_eq!(SettleMode::parse("manual-with-retry"), Some(SettleMode::ManualWithRetry));
+        assert_eq!(SettleMode::parse("never"), None);
+        assert!(SettleMode::Auto.retries());
+        assert!(!SettleMode::Manual.retries());
+        assert!(SettleMode::ManualWithRetry.retries());
+    }
+
+    #[test]
+    fn parses_fee_token_amounts_into_nano_units() {
+        assert_eq!(parse_fee_token_nano("2.5"), Some(2_500_000_000));
+        assert_eq!(parse_fee_token_nano(" 3 "), Some(3_000_000_000));
+        assert_eq!(parse_fee_token_nano(".000000001"), Some(1));
+        assert_eq!(parse_fee_token_nano("0.0000000001"), None);
+        assert_eq!(parse_fee_token_nano("-1"), None);
+        assert_eq!(parse_fee_token_nano("."), None);
+        assert_eq!(parse_fee_token_nano("1e9"), None);
+    }
+
+    #[test]
+    fn parses_precheck_mode() {
+        assert_eq!(PrecheckMode::parse("off"), Some(PrecheckMode::Off));
+        assert_eq!(PrecheckMode::parse(" Warn "), Some(PrecheckMode::Warn));
+        assert_eq!(PrecheckMode::parse("enforce"), Some(PrecheckMode::Enforce));
+        assert_eq!(PrecheckMode::parse("strict"), None);
+    }
+
+    #[test]
+    fn parses_settlement_order() {
+        assert_eq!(SettlementOrder::parse("urgency"), Some(SettlementOrder::Urgency));
+        assert_eq!(SettlementOrder::parse(" Profit "), Some(SettlementOrder::Profit));
+        assert_eq!(SettlementOrder::parse("fifo"), None);
+    }
+
+    #[test]
+    fn surplus_policies_parse_and_share_the_whole_surplus() {
+        assert_eq!(SurplusPolicy::parse(" Split "), Some(SurplusPolicy::Split));
+        assert_eq!(SurplusPolicy::parse("protocol"), None);
+        for policy in [SurplusPolicy::Maker, SurplusPolicy::Taker, SurplusPolicy::Split, SurplusPolicy::Solver] {
+            let (maker, taker, solver) = policy.shares_bps();
+            assert_eq!(u32::from(maker) + u32::from(taker) + u32::from(solver), 10_000);
+        }
+    }
+
+    #[test]
+    fn parses_no_pool_policy() {
+        assert_eq!(NoPoolPolicy::parse(" Skip "), Some(NoPoolPolicy::Skip));
+        assert_eq!(NoPoolPolicy::parse("net"), Some(NoPoolPolicy::Net));
+        assert_eq!(NoPoolPolicy::parse("route"), None);
+    }
+
+    #[test]
+    fn parses_rpc_block_tag() {
+        assert_eq!(RpcBlockTag::parse("pending"), Some(RpcBlockTag::Pending));
+        assert_eq!(RpcBlockTag::parse(" LATEST "), Some(RpcBlockTag::Latest));
+        assert_eq!(RpcBlockTag::parse("pending_then_latest"), Some(RpcBlockTag::PendingThenLatest));
+        assert_eq!(RpcBlockTag::parse("safe"), None);
+    }
+
+    #[test]
+    fn parses_proof_input_bindings() {
+        assert_eq!(
+            parse_proof_input_bindings("0=intent_hash, 1=Nullifier").expect("bindings"),
+            default_proof_input_bindings()
+        );
+        assert_eq!(
+            parse_proof_input_bindings("3=nullifier").expect("bindings"),
+            vec![ProofInputBinding {
+                position: 3,
+                field: ProofInputField::Nullifier
+            }]
+        );
+        assert!(parse_proof_input_bindings("none").expect("bindings").is_empty());
+        assert!(parse_proof_input_bindings("0=user").is_err());
+        assert!(parse_proof_input_bindings("x=nullifier").is_err());
+        assert!(parse_proof_input_bindings("0=intent_hash,0=nullifier").is_err());
+    }
+
+    #[test]
+    fn parses_oracle_sources() {
+        let sources =
+            parse_oracle_sources("backup=0xabc:3@https://user:pw@rpc.example/v1, alt = 0xdef").expect("sources");
+        assert_eq!(
+            sources,
+            vec![
+                OracleSourceConfig {
+                    name: "backup".to_string(),
+                    summary_stats_address: "0xabc".to_string(),
+                    weight: 3,
+                    rpc_url: Some("https://user:pw@rpc.example/v1".to_string()),
+                },
+                OracleSourceConfig {
+                    name: "alt".to_string(),
+                    summary_stats_address: "0xdef".to_string(),
+                    weight: 1,
+                    rpc_url: None,
+                },
+            ]
+        );
+        assert!(parse_oracle_sources("0xabc").is_err());
+        assert!(parse_oracle_sources("a=0xabc:0").is_err());
+        assert!(parse_oracle_sources("a=0xabc@").is_err());
+        assert!(parse_oracle_sources("a=0x1,a=0x2").is_err());
+        assert!(parse_oracle_sources("pragma=0x1").is_err());
+    }
+
+    #[test]
+    fn parses_solver_account_lists() {
+        let accounts = parse_solver_accounts("0x111, 0x222", "0xaaa,0xbbb ").expect("accounts");
+        assert_eq!(
+            accounts,
+            vec![
+                SolverAccountConfig { address: "0x111".to_string(), private_key: "0xaaa".to_string() },
+                SolverAccountConfig { address: "0x222".to_string(), private_key: "0xbbb".to_string() },
+            ]
+        );
+        assert!(parse_solver_accounts("0x111,0x222", "0xaaa").is_err(), "lengths must match");
+        assert!(parse_solver_accounts("0x111,", "0xaaa,0xbbb").is_err());
+        assert!(parse_solver_accounts("0x111,0X111", "0xaaa,0xbbb").is_err());
+    }
 }
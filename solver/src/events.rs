@@ -0,0 +1,287 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::storage::RedisStorage;
+
+/// Room for bursts while a slow subscriber (e.g. the bus publisher) catches up. Subscribers that
+/// fall further behind skip events rather than holding up the matcher.
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Intent lifecycle and match events, shaped like the documented WebSocket events
+/// (`{"event": "matched", "nullifier": ..., "timestamp": ...}`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SolverEvent {
+    Submitted {
+        nullifier: String,
+        user: String,
+        token_in: String,
+        token_out: String,
+        timestamp: DateTime<Utc>,
+    },
+    Matched {
+        nullifier: String,
+        matched_with: String,
+        match_id: String,
+        timestamp: DateTime<Utc>,
+    },
+    Settled {
+        nullifier: String,
+        transaction_hash: String,
+        timestamp: DateTime<Utc>,
+    },
+    Cancelled {
+        nullifier: String,
+        timestamp: DateTime<Utc>,
+    },
+    Extended {
+        nullifier: String,
+        deadline: u64,
+        timestamp: DateTime<Utc>,
+    },
+    Failed {
+        nullifier: String,
+        reason: String,
+        timestamp: DateTime<Utc>,
+    },
+    /// Back to pending after its match failed with `reason` (`REQUEUE_FAILED_MATCHES`).
+    Requeued {
+        nullifier: String,
+        match_id: String,
+        reason: String,
+        timestamp: DateTime<Utc>,
+    },
+    MatchCreated {
+        match_id: String,
+        intent_a: String,
+        intent_b: String,
+        timestamp: DateTime<Utc>,
+    },
+    MatchSettled {
+        match_id: String,
+        transaction_hash: String,
+        timestamp: DateTime<Utc>,
+    },
+    MatchDeadlettered {
+        match_id: String,
+        reason: String,
+        timestamp: DateTime<Utc>,
+    },
+}
+
+impl SolverEvent {
+    /// The `event` tag, used as the bus subject suffix.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Submitted { .. } => "submitted",
+            Self::Matched { .. } => "matched",
+            Self::Settled { .. } => "settled",
+            Self::Cancelled { .. } => "cancelled",
+            Self::Extended { .. } => "extended",
+            Self::Failed { .. } => "failed",
+            Self::Requeued { .. } => "requeued",
+            Self::MatchCreated { .. } => "match_created",
+            Self::MatchSettled { .. } => "match_settled",
+            Self::MatchDeadlettered { .. } => "match_deadlettered",
+        }
+    }
+
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            Self::Submitted { timestamp, .. }
+            | Self::Matched { timestamp, .. }
+            | Self::Settled { timestamp, .. }
+            | Self::Cancelled { timestamp, .. }
+            | Self::Extended { timestamp, .. }
+            | Self::Failed { timestamp, .. }
+            | Self::Requeued { timestamp, .. }
+            | Self::MatchCreated { timestamp, .. }
+            | Self::MatchSettled { timestamp, .. }
+            | Self::MatchDeadlettered { timestamp, .. } => *timestamp,
+        }
+    }
+
+    /// The match the event belongs to, if any.
+    pub fn match_id(&self) -> Option<&str> {
+        match self {
+            Self::Matched { match_id, .. }
+            | Self::Requeued { match_id, .. }
+            | Self::MatchCreated { match_id, .. }
+            | Self::MatchSettled { match_id, .. }
+            | Self::MatchDeadlettered { match_id, .. } => Some(match_id),
+            Self::Submitted { .. }
+            | Self::Settled { .. }
+            | Self::Cancelled { .. }
+            | Self::Extended { .. }
+            | Self::Failed { .. } => None,
+        }
+    }
+
+    /// Intents the event refers to directly. Match-only events (`match_settled`,
+    /// `match_deadlettered`) name none.
+    pub fn nullifiers(&self) -> Vec<&str> {
+        match self {
+            Self::Submitted { nullifier, .. }
+            | Self::Settled { nullifier, .. }
+            | Self::Cancelled { nullifier, .. }
+            | Self::Extended { nullifier, .. }
+            | Self::Failed { nullifier, .. }
+            | Self::Requeued { nullifier, .. } => vec![nullifier],
+            Self::Matched { nullifier, matched_with, .. } => vec![nullifier, matched_with],
+            Self::MatchCreated { intent_a, intent_b, .. } => vec![intent_a, intent_b],
+            Self::MatchSettled { .. } | Self::MatchDeadlettered { .. } => Vec::new(),
+        }
+    }
+}
+
+/// An event as kept in the export log, with the users it concerns resolved at record time so
+/// exports can filter by user after the intents themselves have expired.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub users: Vec<String>,
+    pub event: SolverEvent,
+}
+
+/// In-process fan-out of `SolverEvent`s. Publishing never blocks or fails; with no subscribers
+/// events are simply dropped.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<SolverEvent>,
+    /// Events the bus publisher and recorder skipped by falling behind, since start.
+    lagged: Arc<AtomicU64>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self { tx, lagged: Arc::new(AtomicU64::new(0)) }
+    }
+
+    /// Events skipped by lagging subscribers, for `GET /v1/metrics`.
+    pub fn lagged_events(&self) -> u64 {
+        self.lagged.load(Ordering::Relaxed)
+    }
+
+    pub fn publish(&self, event: SolverEvent) {
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SolverEvent> {
+        self.tx.subscribe()
+    }
+}
+
+/// Forwards every event to NATS as JSON on `<subject_prefix>.<event>`. Runs until the process
+/// exits. Connection problems are logged and never surface to the matcher or API.
+pub fn spawn_nats_publisher(events: &EventBus, url: String, subject_prefix: String) {
+    let mut rx = events.subscribe();
+    let lagged = events.lagged.clone();
+    tokio::spawn(async move {
+        let client = match async_nats::ConnectOptions::new()
+            .retry_on_initial_connect()
+            .connect(url.as_str())
+            .await
+        {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("Event bus disabled: failed to set up NATS client for {}: {}", url, e);
+                return;
+            }
+        };
+        info!("Publishing solver events to NATS subject prefix {}", subject_prefix);
+
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    lagged.fetch_add(skipped, Ordering::Relaxed);
+                    warn!("Event bus publisher lagged; dropped {} events", skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            let payload = match serde_json::to_vec(&event) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!("Failed to serialize {} event: {}", event.name(), e);
+                    continue;
+                }
+            };
+            let subject = format!("{}.{}", subject_prefix, event.name());
+            if let Err(e) = client.publish(subject, payload.into()).await {
+                warn!("Failed to publish {} event to NATS: {}", event.name(), e);
+            }
+        }
+    });
+}
+
+/// Appends every event to the Redis event log behind `GET /v1/events/export`, dropping entries
+/// older than `retention_seconds`.
+pub fn spawn_event_recorder(events: &EventBus, storage: Arc<RedisStorage>, retention_seconds: u64) {
+    let mut rx = events.subscribe();
+    let lagged = events.lagged.clone();
+    tokio::spawn(async move {
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    lagged.fetch_add(skipped, Ordering::Relaxed);
+                    warn!("Event recorder lagged; {} events missing from the export log", skipped);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+
+            let mut users = Vec::new();
+            if let SolverEvent::Submitted { user, .. } = &event {
+                users.push(user.clone());
+            } else {
+                for nullifier in event.nullifiers() {
+                    if let Ok(Some(intent)) = storage.get_intent(nullifier).await {
+                        users.push(intent.public_inputs.user);
+                    }
+                }
+            }
+            users.sort();
+            users.dedup();
+
+            let timestamp_ms = event.timestamp().timestamp_millis();
+            let record = EventRecord { users, event };
+            let retain_from_ms = Utc::now().timestamp_millis() - (retention_seconds as i64).saturating_mul(1000);
+            if let Err(e) = storage.record_event(timestamp_ms, &record, retain_from_ms).await {
+                warn!("Failed to record {} event: {}", record.event.name(), e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_serialize_with_event_tag() {
+        let timestamp = DateTime::parse_from_rfc3339("2024-01-01T12:01:00Z")
+            .expect("timestamp")
+            .with_timezone(&Utc);
+        let event = SolverEvent::Matched {
+            nullifier: "0xabc".to_string(),
+            matched_with: "0xdef".to_string(),
+            match_id: "m-1".to_string(),
+            timestamp,
+        };
+        let json = serde_json::to_value(&event).expect("json");
+        assert_eq!(json["event"], "matched");
+        assert_eq!(json["event"], event.name());
+        assert_eq!(json["matched_with"], "0xdef");
+        assert_eq!(json["timestamp"], "2024-01-01T12:01:00Z");
+
+        let back: SolverEvent = serde_json::from_value(json).expect("round trip");
+        assert_eq!(back.nullifiers(), vec!["0xabc", "0xdef"]);
+        assert_eq!(back.timestamp(), timestamp);
+    }
+}
@@ -0,0 +1,128 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::matcher::IntentMatcher;
+use crate::models::{Intent, MatchedPair};
+use crate::storage::RedisStorage;
+
+/// Reads a JSON array of intents, in the shape they are stored in Redis.
+pub fn load_fixture(path: &Path) -> Result<Vec<Intent>> {
+    let raw = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read fixture {}: {}", path.display(), e))?;
+    parse_fixture(&raw).map_err(|e| anyhow::anyhow!("Invalid fixture {}: {}", path.display(), e))
+}
+
+pub fn parse_fixture(raw: &str) -> Result<Vec<Intent>> {
+    Ok(serde_json::from_str(raw)?)
+}
+
+/// Shifts every timestamp so the newest intent was created at `now`, keeping the gaps between
+/// them. Captured intents have long expired otherwise, and expired intents never match.
+pub fn rebase_times(intents: &mut [Intent], now: DateTime<Utc>) {
+    let Some(newest) = intents.iter().map(|i| i.created_at).max() else {
+        return;
+    };
+    let shift = now - newest;
+    let shift_secs = shift.num_seconds();
+    let shift_time = |t: DateTime<Utc>| t + shift;
+    for intent in intents.iter_mut() {
+        intent.created_at = shift_time(intent.created_at);
+        intent.expires_at = shift_time(intent.expires_at);
+        intent.activate_at = intent.activate_at.map(shift_time);
+        intent.last_near_miss_at = intent.last_near_miss_at.map(shift_time);
+        for until in intent.failed_counterparties.values_mut() {
+            *until = shift_time(*until);
+        }
+        intent.public_inputs.deadline = (intent.public_inputs.deadline as i64)
+            .saturating_add(shift_secs)
+            .max(0) as u64;
+    }
+}
+
+/// Stores `intents`, runs one `match_batch` and returns the matches that pass created for them.
+/// Everything else pending in `storage` takes part in the pass too, so point it at a scratch
+/// database.
+pub async fn replay(
+    storage: &RedisStorage,
+    matcher: &IntentMatcher,
+    mut intents: Vec<Intent>,
+) -> Result<Vec<MatchedPair>> {
+    rebase_times(&mut intents, Utc::now() - Duration::seconds(1));
+    let mut existing = BTreeSet::new();
+    for intent in &intents {
+        if let Some(match_id) = storage.get_match_id_for_nullifier(&intent.nullifier).await? {
+            existing.insert(match_id);
+        }
+        storage.store_intent(intent).await?;
+    }
+
+    matcher.match_batch().await?;
+
+    let mut created = BTreeSet::new();
+    for intent in &intents {
+        if let Some(match_id) = storage.get_match_id_for_nullifier(&intent.nullifier).await? {
+            if !existing.contains(&match_id) {
+                created.insert(match_id);
+            }
+        }
+    }
+    let mut matches = Vec::with_capacity(created.len());
+    for match_id in created {
+        if let Some(pair) = storage.get_matched_pair(&match_id).await? {
+            matches.push(pair);
+        }
+    }
+    matches.sort_by(|a, b| a.intent_a.created_at.cmp(&b.intent_a.created_at));
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, SettleMode};
+    use crate::events::EventBus;
+
+    const SAMPLE: &str = include_str!("../fixtures/matching_sample.json");
+
+    #[test]
+    fn rebasing_keeps_gaps_and_moves_deadlines() {
+        let mut intents = parse_fixture(SAMPLE).expect("sample fixture");
+        assert_eq!(intents.len(), 3);
+        let gap = intents[2].created_at - intents[0].created_at;
+        let ttl = intents[0].expires_at - intents[0].created_at;
+        let now = Utc::now();
+        rebase_times(&mut intents, now);
+
+        assert_eq!(intents[2].created_at, now);
+        assert_eq!(intents[2].created_at - intents[0].created_at, gap);
+        assert_eq!(intents[0].expires_at - intents[0].created_at, ttl);
+        assert!(intents.iter().all(|i| i.public_inputs.deadline > now.timestamp() as u64));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn sample_fixture_matches_the_crossing_pair_only() {
+        let config = Config::from_env().expect("config");
+        let storage = crate::storage::scratch::scratch_storage().await;
+        let intents = parse_fixture(SAMPLE).expect("sample fixture");
+        storage.track_intents(&intents);
+        let matcher = IntentMatcher::new(
+            storage.storage.clone(),
+            config.matching_config,
+            None,
+            SettleMode::Manual,
+            EventBus::new(),
+            None,
+            None,
+        );
+
+        let matches = replay(&storage, &matcher, intents).await.expect("replay");
+        let pairs: Vec<(&str, &str)> = matches
+            .iter()
+            .map(|m| (m.intent_a.nullifier.as_str(), m.intent_b.nullifier.as_str()))
+            .collect();
+        assert_eq!(pairs, vec![("0xf1a1", "0xf1b1")]);
+    }
+}
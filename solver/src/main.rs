@@ -9,13 +9,25 @@ mod matcher;
 mod api;
 mod auth;
 mod starknet;
+mod oracle;
+mod rpc;
+mod amount;
+mod bundle;
+mod events;
+mod fixture;
+mod relayer;
 mod utils;
+mod webhook;
 
-use config::Config;
-use storage::RedisStorage;
+use config::{Config, SettleMode, PRIMARY_ORACLE_SOURCE};
+use storage::{RedisStorage, StorageCipher};
 use matcher::IntentMatcher;
 use api::create_router;
-use starknet::StarknetClient;
+use starknet::{IntentProofVerifier, StarknetClient};
+use oracle::{spawn_price_cache_purge, PragmaOracle, PragmaSource};
+use rpc::RpcClient;
+use relayer::{PaymasterRelayer, RelayerFeePolicy};
+use events::{spawn_event_recorder, spawn_nats_publisher, EventBus};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -28,38 +40,201 @@ async fn main() -> Result<()> {
 
     // Load configuration
     let config = Config::from_env()?;
+    utils::set_log_redaction(config.log_redact);
     info!("Configuration loaded successfully");
+    if config.log_redact {
+        info!("LOG_REDACT: user addresses and nullifiers are redacted in logs");
+    }
+
+    let unverified = [
+        ("ALLOW_EMPTY_PROOF=true", config.api_config.allow_empty_proof),
+        ("VERIFY_INTENT_SIGNATURES=false", !config.api_config.verify_intent_signatures),
+    ]
+    .into_iter()
+    .filter(|(_, on)| *on)
+    .map(|(flag, _)| flag)
+    .collect::<Vec<_>>();
+    if !unverified.is_empty() {
+        // Fail closed: without a confirmed non-mainnet chain id, do not start with verification off.
+        let rpc = RpcClient::new(
+            config.starknet_rpc_url(),
+            config.debug_rpc_logging,
+            config.rpc_block_tag,
+            config.rpc_timeout_ms,
+        );
+        let chain_id = rpc
+            .chain_id()
+            .await
+            .map_err(|e| anyhow::anyhow!("{} but the chain id could not be checked: {}", unverified.join(" and "), e))?;
+        if chain_id == ::starknet::core::chain_id::MAINNET {
+            return Err(anyhow::anyhow!("{} is not allowed on mainnet", unverified.join(" and ")));
+        }
+        if config.api_config.allow_empty_proof {
+            tracing::warn!("!!! ALLOW_EMPTY_PROOF=true: proof verification is DISABLED. Intents are accepted without valid proofs; use on test networks only !!!");
+        }
+        if !config.api_config.verify_intent_signatures {
+            tracing::warn!("!!! VERIFY_INTENT_SIGNATURES=false: intent signatures are only checked for format; use on test networks only !!!");
+        }
+    }
+
+    if config.api_config.accepted_domain_separators.is_empty() {
+        tracing::warn!("ACCEPTED_DOMAIN_SEPARATORS is unset: intents signed for any domain are accepted");
+    }
 
     // Initialize Redis storage
-    let storage = Arc::new(RedisStorage::new(&config.redis_url).await?);
+    let storage = Arc::new(open_storage(&config, &config.redis_url).await?);
+    if config.storage_encryption_key.is_some() {
+        info!("Encrypting intent and match payloads at rest");
+    }
+    if config.compress_intents {
+        info!("Compressing intent and match payloads (zstd)");
+    }
     info!("Connected to Redis");
+    let read_storage = match &config.redis_read_url {
+        Some(url) => {
+            let replica = Arc::new(open_storage(&config, url).await?.read_only());
+            info!("Serving read-heavy queries from the Redis replica");
+            replica
+        }
+        None => storage.clone(),
+    };
+
+    if let Some(path) = fixture_path_arg(std::env::args()) {
+        return replay_fixture(&config, storage, &path).await;
+    }
 
     // Initialize Starknet settlement client (requires a funded solver account).
     // If misconfigured, keep solver running (matching/status still works) and allow manual troubleshooting.
-    let starknet_client: Option<Arc<StarknetClient>> = if config.auto_settle_onchain {
-        match &config.solver_address {
-            Some(addr) => Some(Arc::new(StarknetClient::new(
+    let starknet_client: Option<Arc<StarknetClient>> = match config.solver_accounts.first() {
+        Some(primary) => {
+            let mut client = StarknetClient::new(
                 &config.starknet_rpc,
                 &config.dark_pool_address,
-                addr,
-                &config.solver_private_key,
-            ).await?)),
-            None => {
-                tracing::warn!("AUTO_SETTLE_ONCHAIN=true but SOLVER_ADDRESS is not set; auto settlement disabled");
-                None
+                &config.solver_accounts,
+                config.simulate_before_settle,
+                config.solver_fee_token,
+                config.debug_rpc_logging,
+                config.rpc_block_tag,
+            ).await?;
+            if let Some(url) = &config.settlement_relayer_url {
+                let relayer = PaymasterRelayer::new(
+                    url,
+                    config.settlement_relayer_api_key.clone(),
+                    &primary.address,
+                    &primary.private_key,
+                    &config.dark_pool_address,
+                    RelayerFeePolicy {
+                        fee_token: ::starknet::core::types::Felt::from_hex(config.solver_fee_token.address())?,
+                        // Both fee tokens have 18 decimals.
+                        max_fee: config.settlement_relayer_max_fee_nano.map(|nano| u128::from(nano) * 1_000_000_000),
+                    },
+                    config.debug_rpc_logging,
+                )?;
+                client = client.with_relayer(Arc::new(relayer));
+                info!("Settlements are submitted through relayer {}", url);
+            }
+            match &config.ekubo_core_address {
+                Some(core) => {
+                    client = client.with_ekubo_core(core)?;
+                    info!(
+                        "Resolving Ekubo pools through core {} (fee={}, tick_spacing={})",
+                        core, config.matching_config.ekubo_pool_fee, config.matching_config.ekubo_tick_spacing
+                    );
+                }
+                None => tracing::warn!("EKUBO_CORE_ADDRESS is not set; matches carry placeholder pool addresses"),
             }
+            Some(Arc::new(client))
         }
+        None => {
+            tracing::warn!("Neither SOLVER_ADDRESS nor SOLVER_ADDRESSES is set; on-chain settlement disabled");
+            None
+        }
+    };
+    info!("Settlement mode: {:?}", config.settle_mode);
+
+    let events = EventBus::new();
+    if let Some(url) = &config.event_bus_url {
+        spawn_nats_publisher(&events, url.clone(), config.event_bus_subject.clone());
+    }
+    if config.event_log_retention_seconds > 0 {
+        spawn_event_recorder(&events, storage.clone(), config.event_log_retention_seconds);
+    }
+
+    let proof_verifier = if config.matching_config.verify_proofs_at_match {
+        let dark_pool_address = ::starknet::core::types::Felt::from_hex(&config.dark_pool_address)
+            .map_err(|_| anyhow::anyhow!("Invalid DARK_POOL_ADDRESS"))?;
+        info!("Re-verifying intent proofs before each match");
+        Some(IntentProofVerifier::new(
+            // Same simulation as the submit-time preflight, so it gets the same headroom.
+            RpcClient::new(
+                config.starknet_rpc_url(),
+                config.debug_rpc_logging,
+                config.rpc_block_tag,
+                config.preflight_timeout_ms,
+            ),
+            dark_pool_address,
+        ))
     } else {
         None
     };
 
+    let pragma_summary_stats_address = ::starknet::core::types::Felt::from_hex(&config.pragma_summary_stats_address)
+        .map_err(|_| anyhow::anyhow!("Invalid PRAGMA_SUMMARY_STATS_ADDRESS"))?;
+    let pragma_oracle_address = config
+        .pragma_oracle_address
+        .as_deref()
+        .map(::starknet::core::types::Felt::from_hex)
+        .transpose()
+        .map_err(|_| anyhow::anyhow!("Invalid PRAGMA_ORACLE_ADDRESS"))?;
+    let oracle_rpc = |url: String| {
+        RpcClient::new(url, config.debug_rpc_logging, config.rpc_block_tag, config.rpc_timeout_ms)
+    };
+    let mut oracle_sources = vec![PragmaSource::new(
+        PRIMARY_ORACLE_SOURCE,
+        config.pragma_weight,
+        oracle_rpc(config.starknet_rpc_url()),
+        pragma_summary_stats_address,
+        pragma_oracle_address,
+        config.pragma_price_cache_max_entries,
+    )];
+    for source in &config.oracle_sources {
+        let address = ::starknet::core::types::Felt::from_hex(&source.summary_stats_address)
+            .map_err(|_| anyhow::anyhow!("Invalid summary stats address for oracle source {}", source.name))?;
+        oracle_sources.push(PragmaSource::new(
+            &source.name,
+            source.weight,
+            oracle_rpc(source.rpc_url.clone().unwrap_or_else(|| config.starknet_rpc_url())),
+            address,
+            None,
+            config.pragma_price_cache_max_entries,
+        ));
+    }
+    if config.matching_config.max_oracle_staleness_seconds > 0 {
+        oracle_sources = oracle_sources.into_iter().map(PragmaSource::with_freshness_probe).collect();
+        info!(
+            "Oracle prices older than {}s pause their pairs{}",
+            config.matching_config.max_oracle_staleness_seconds,
+            if config.matching_config.stale_oracle_amount_matching { " (amount-only matching allowed)" } else { "" }
+        );
+    }
+    let oracle = Arc::new(PragmaOracle::new(oracle_sources, config.oracle_deviation_bps));
+    spawn_price_cache_purge(oracle.clone());
+
     // Initialize intent matcher
-    let matcher = Arc::new(IntentMatcher::new(
+    let mut matcher = IntentMatcher::new(
         storage.clone(),
         config.matching_config.clone(),
         starknet_client,
-        config.auto_settle_onchain,
-    ));
+        config.settle_mode,
+        events.clone(),
+        proof_verifier,
+        Some(oracle.clone()),
+    );
+    if let Some(secret) = &config.api_config.webhook_secret {
+        matcher = matcher.with_webhooks(webhook::WebhookNotifier::new(secret)?);
+        info!("Settlement callbacks enabled");
+    }
+    let matcher = Arc::new(matcher);
     info!("Intent matcher initialized");
 
     // Start background matching task
@@ -67,14 +242,17 @@ async fn main() -> Result<()> {
     tokio::spawn(async move {
         matcher_clone.run_matching_loop().await;
     });
+    if config.expiry_sweep_interval_ms > 0 {
+        spawn_expiry_sweep(storage.clone(), config.expiry_sweep_interval_ms);
+    }
 
     // Create and start API server
-    let app = create_router(storage, matcher, config.clone());
+    let app = create_router(storage, read_storage, matcher, oracle, events, config.clone());
     let listener = tokio::net::TcpListener::bind(&config.server_addr).await?;
     
     info!("Solver listening on {}", config.server_addr);
     
-    axum::serve(listener, app)
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
         .with_graceful_shutdown(shutdown_signal())
         .await?;
 
@@ -82,6 +260,74 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Storage on `url` with the payload encoding and retention settings from `config`, so a replica
+/// decodes what the primary wrote.
+async fn open_storage(config: &Config, url: &str) -> Result<RedisStorage> {
+    let mut storage = RedisStorage::new(url).await?;
+    if let Some(key) = &config.storage_encryption_key {
+        storage = storage.with_encryption(StorageCipher::from_hex(key)?);
+    }
+    if config.expiry_sweep_interval_ms > 0 {
+        storage = storage.with_expired_retention(config.expired_intent_retention_seconds);
+    }
+    Ok(storage.with_compression(config.compress_intents))
+}
+
+/// Marks pending intents past `expires_at` as `Expired` every `interval_ms`, so they stop
+/// reading as pending until their record's retention runs out.
+fn spawn_expiry_sweep(storage: Arc<RedisStorage>, interval_ms: u64) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(std::time::Duration::from_millis(interval_ms));
+        loop {
+            ticker.tick().await;
+            match storage.expire_pending_intents().await {
+                Ok(expired) if !expired.is_empty() => info!("Marked {} intents expired", expired.len()),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Expiry sweep failed: {}", e),
+            }
+        }
+    });
+}
+
+/// `--load-fixture <path>`: a dev mode that replays captured intents instead of serving.
+fn fixture_path_arg(mut args: impl Iterator<Item = String>) -> Option<std::path::PathBuf> {
+    while let Some(arg) = args.next() {
+        if let Some(path) = arg.strip_prefix("--load-fixture=") {
+            return Some(path.into());
+        }
+        if arg == "--load-fixture" {
+            return args.next().map(Into::into);
+        }
+    }
+    None
+}
+
+/// Stores the fixture's intents, runs one matching pass without settling, prints the matches
+/// it produced as JSON and exits. Refuses a `REDIS_URL` that already holds intents, so a
+/// mistyped URL can't mix fixtures into a live book.
+async fn replay_fixture(config: &Config, storage: Arc<RedisStorage>, path: &std::path::Path) -> Result<()> {
+    let intents = fixture::load_fixture(path)?;
+    if storage.holds_intents().await? {
+        return Err(anyhow::anyhow!(
+            "REDIS_URL already holds intents; --load-fixture needs an empty scratch database (FLUSHDB it first)"
+        ));
+    }
+    tracing::warn!("Replaying {} fixture intents into REDIS_URL", intents.len());
+    let matcher = IntentMatcher::new(
+        storage.clone(),
+        config.matching_config.clone(),
+        None,
+        SettleMode::Manual,
+        EventBus::new(),
+        None,
+        None,
+    );
+    let matches = fixture::replay(&storage, &matcher, intents).await?;
+    info!("Fixture replay produced {} matches", matches.len());
+    println!("{}", serde_json::to_string_pretty(&matches)?);
+    Ok(())
+}
+
 async fn shutdown_signal() {
     let ctrl_c = async {
         tokio::signal::ctrl_c()
@@ -1,20 +1,140 @@
 use anyhow::Result;
+use futures::future::BoxFuture;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tracing::{info, debug, warn, error};
-use tokio::time::{interval, Duration};
+use std::collections::{HashMap, HashSet};
+use tokio::time::{interval, Duration, Instant};
 use num_bigint::BigUint;
+use num_traits::{FromPrimitive, ToPrimitive, Zero};
+use serde::Serialize;
 
-use crate::config::MatchingConfig;
-use crate::models::{Intent, IntentStatus, MatchedPair, SettlementData};
-use crate::storage::RedisStorage;
-use crate::starknet::StarknetClient;
-use crate::starknet::{parse_amount_to_base_units, token_decimals_for};
+use crate::config::{MatchingConfig, NoPoolPolicy, SettleMode, SettlementOrder, SurplusPolicy};
+use crate::events::{EventBus, SolverEvent};
+use crate::models::{
+    GasBudgetStatus, Intent, IntentStatus, MatchedGroup, MatchedPair, PartialFill, SettlementData,
+    SettlementOutcome, SettlementOutput, SettlementRecord, SettlementTrigger, StaleOracleFeed, SurplusDistribution,
+    TriggerDirection,
+};
+use crate::oracle::{usd_value, CrossRate, OracleError, PragmaOracle};
+use crate::storage::{RedisStorage, SettlingMarker};
+use crate::starknet::{
+    felt_from_hex, settles_pair, transfers_to, IntentProofVerifier, SettlementReceipts, StarknetClient, TxOutcome,
+};
+use crate::webhook::{SettlementCallback, WebhookNotifier};
+use starknet::core::types::Event;
+use crate::starknet::token_decimals_for;
+use crate::amount::TokenAmount;
+use crate::utils::{keccak256, log_nullifier, same_address};
+
+/// How often in-flight settlements are checked for a receipt.
+const CONFIRM_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Retry-state `deferred_reason` while gas is above `SETTLE_GAS_CEILING`.
+const GAS_DEFERRAL_REASON: &str = "GAS_PRICE_ABOVE_CEILING";
+
+/// How long a stale rate stays in `/health/ready` after the last pass that asked for it.
+const STALE_FEED_REPORT_TTL_SECONDS: i64 = 300;
+
+/// How long a pool lookup is trusted before the pair is checked again.
+const POOL_CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// Resolved Ekubo pools kept in memory; pairs past this are resolved on every match.
+const EKUBO_POOL_CACHE_MAX: usize = 1024;
+
+/// Decimal places a `trigger_price` may have; it is compared at this scale.
+pub const TRIGGER_PRICE_DECIMALS: u32 = 18;
+
+/// How a token pair's matches settle, per `NO_POOL_POLICY`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PoolRoute {
+    Ekubo,
+    /// No pool; settle with a zero `ekubo_pool` so the contract nets the sides directly.
+    Net,
+    /// Don't match the pair (no pool under `skip`, or the lookup failed this round).
+    Unmatchable,
+}
+
+/// What `match_batch` settles once per token pair before pairing its intents.
+struct PairTerms {
+    /// Settle without a pool (`NO_POOL_POLICY=net` and none deployed).
+    net: bool,
+    /// Pragma price of `token_a` in `token_b` for the slippage band; `None` applies no band.
+    reference: Option<(BigUint, u32)>,
+}
+
+/// Counterparty search work: for one token pair, one `match_batch` pass, or summed over passes.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct SearchStats {
+    /// Pending intents considered (per pair: both sides' books).
+    pub scanned: u64,
+    /// Compatibility checks between an intent and a candidate counterparty.
+    pub comparisons: u64,
+    pub matches: u64,
+    pub elapsed_ms: u64,
+}
+
+impl SearchStats {
+    /// Comparisons spent per match created; `None` until something matched.
+    pub fn comparisons_per_match(&self) -> Option<f64> {
+        (self.matches > 0).then(|| self.comparisons as f64 / self.matches as f64)
+    }
+
+    fn add(&mut self, other: &SearchStats) {
+        self.scanned += other.scanned;
+        self.comparisons += other.comparisons;
+        self.matches += other.matches;
+        self.elapsed_ms += other.elapsed_ms;
+    }
+}
+
+/// Matcher counters for `GET /v1/metrics`. Passes with nothing pending are not counted.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SearchMetrics {
+    pub passes: u64,
+    pub totals: SearchStats,
+    pub comparisons_per_match: Option<f64>,
+    pub last_pass: Option<SearchStats>,
+    /// Distinct three-intent rings found under `RING_MATCHING`. A ring found again on later passes
+    /// is counted once, while it stays in the book.
+    pub rings_found: u64,
+}
+
+/// Outcome of re-verifying a candidate pair's proofs before matching.
+enum ProofCheck {
+    Valid,
+    /// At least one proof no longer verifies; those intents were marked failed.
+    Invalid { counterparty_failed: bool },
+    /// The node gave no conclusive answer; leave both intents pending for the next round.
+    Unavailable,
+}
 
 pub struct IntentMatcher {
     storage: Arc<RedisStorage>,
     config: MatchingConfig,
     starknet: Option<Arc<StarknetClient>>,
-    auto_settle_onchain: bool,
+    settle_mode: SettleMode,
+    events: EventBus,
+    /// Set when `VERIFY_PROOFS_AT_MATCH` is on.
+    proof_verifier: Option<IntentProofVerifier>,
+    /// Reference prices for the `max_slippage_bps` band; without one the band is not applied.
+    oracle: Option<Arc<PragmaOracle>>,
+    /// Token (lowercased) -> USD rate, refreshed each pass for the tokens in the book. A token
+    /// missing here has no price feed.
+    usd_prices: std::sync::Mutex<HashMap<String, CrossRate>>,
+    /// Unix millis of the matching loop's latest iteration; 0 until it starts.
+    last_tick_ms: Arc<AtomicU64>,
+    /// Whether each token pair (lowercased, in sorted order) has a deployed pool, and when we checked.
+    pool_cache: std::sync::Mutex<HashMap<(String, String), (Instant, bool)>>,
+    /// Ekubo pools resolved through `EKUBO_CORE_ADDRESS`, by token pair (lowercased, in sorted order).
+    ekubo_pools: std::sync::Mutex<HashMap<(String, String), String>>,
+    search_metrics: std::sync::Mutex<SearchMetrics>,
+    /// Rings the latest pass found, by sorted nullifiers, so a ring is reported once while it stays.
+    reported_rings: std::sync::Mutex<HashSet<Vec<String>>>,
+    /// Rates found older than `max_oracle_staleness_seconds`, by pair id; dropped once fresh again.
+    stale_feeds: std::sync::Mutex<HashMap<String, StaleOracleFeed>>,
+    /// Settlement callbacks to intents' `callback_url`, with `WEBHOOK_SECRET` set.
+    webhooks: Option<WebhookNotifier>,
 }
 
 impl IntentMatcher {
@@ -35,10 +155,9 @@ impl IntentMatcher {
         // Note: proof_public_inputs contains SNARK-native signals (intentHash,
         // nullifier, currentTime) — NOT business fields — so we always derive
         // amounts from the human-readable public_inputs fields.
-        let in_decimals = token_decimals_for(&intent.public_inputs.token_in);
-        let out_decimals = token_decimals_for(&intent.public_inputs.token_out);
-        let amount_in = parse_amount_to_base_units(&intent.public_inputs.amount_in, in_decimals).ok()?;
-        let min_out = parse_amount_to_base_units(&intent.public_inputs.min_amount_out, out_decimals).ok()?;
+        let inputs = &intent.public_inputs;
+        let amount_in = TokenAmount::parse_for_token(&inputs.amount_in, &inputs.token_in).ok()?.into_base_units();
+        let min_out = TokenAmount::parse_for_token(&inputs.min_amount_out, &inputs.token_out).ok()?.into_base_units();
         Some((amount_in, min_out))
     }
 
@@ -46,15 +165,72 @@ impl IntentMatcher {
         storage: Arc<RedisStorage>,
         config: MatchingConfig,
         starknet: Option<Arc<StarknetClient>>,
-        auto_settle_onchain: bool,
+        settle_mode: SettleMode,
+        events: EventBus,
+        proof_verifier: Option<IntentProofVerifier>,
+        oracle: Option<Arc<PragmaOracle>>,
     ) -> Self {
-        Self { storage, config, starknet, auto_settle_onchain }
+        Self {
+            storage,
+            config,
+            starknet,
+            settle_mode,
+            events,
+            proof_verifier,
+            oracle,
+            usd_prices: std::sync::Mutex::new(HashMap::new()),
+            last_tick_ms: Arc::new(AtomicU64::new(0)),
+            pool_cache: std::sync::Mutex::new(HashMap::new()),
+            ekubo_pools: std::sync::Mutex::new(HashMap::new()),
+            search_metrics: std::sync::Mutex::new(SearchMetrics::default()),
+            reported_rings: std::sync::Mutex::new(HashSet::new()),
+            stale_feeds: std::sync::Mutex::new(HashMap::new()),
+            webhooks: None,
+        }
+    }
+
+    pub fn with_webhooks(mut self, notifier: WebhookNotifier) -> Self {
+        self.webhooks = Some(notifier);
+        self
+    }
+
+    /// Counterparty search counters since start, with the ratio computed over the totals.
+    pub fn search_metrics(&self) -> SearchMetrics {
+        let mut metrics = self.search_metrics.lock().expect("search metrics lock").clone();
+        metrics.comparisons_per_match = metrics.totals.comparisons_per_match();
+        metrics
+    }
+
+    fn record_search(&self, pass: SearchStats) {
+        let mut metrics = self.search_metrics.lock().expect("search metrics lock");
+        metrics.passes += 1;
+        metrics.totals.add(&pass);
+        metrics.last_pass = Some(pass);
+    }
+
+    /// Shared handle on the matching loop's last tick (unix millis), for liveness checks.
+    pub fn last_tick_ms(&self) -> Arc<AtomicU64> {
+        self.last_tick_ms.clone()
+    }
+
+    /// Records that the matching loop is making progress. Stamped at the top of each tick and
+    /// again after each token pair, settlement and reconciled marker, so a long tick isn't stale.
+    fn stamp_liveness(&self) {
+        let now_ms = chrono::Utc::now().timestamp_millis().max(0) as u64;
+        self.last_tick_ms.store(now_ms, Ordering::Relaxed);
+    }
+
+    /// The settlement client, when on-chain settlement is configured.
+    pub fn starknet_client(&self) -> Option<Arc<StarknetClient>> {
+        self.starknet.clone()
     }
 
     /// Main matching loop - runs continuously
     pub async fn run_matching_loop(&self) {
         let mut ticker = interval(Duration::from_millis(self.config.poll_interval_ms));
         let settle_every_ticks: u64 = (10_000u64 / self.config.poll_interval_ms.max(1)).max(1);
+        let confirm_every_ticks: u64 =
+            (CONFIRM_POLL_INTERVAL.as_millis() as u64 / self.config.poll_interval_ms.max(1)).max(1);
         let mut ticks: u64 = 0;
         
         info!("Starting intent matching loop");
@@ -62,6 +238,14 @@ impl IntentMatcher {
         loop {
             ticker.tick().await;
             ticks = ticks.wrapping_add(1);
+            self.stamp_liveness();
+
+            // First thing after a restart, then every few seconds: sent settlements are confirmed here.
+            if ticks == 1 || ticks % confirm_every_ticks == 0 {
+                if let Err(e) = self.reconcile_settling_matches().await {
+                    warn!("Error reconciling in-flight settlements: {}", e);
+                }
+            }
             
             if let Err(e) = self.match_batch().await {
                 error!("Error in matching batch: {}", e);
@@ -69,7 +253,7 @@ impl IntentMatcher {
 
             // Retry settlement for already-matched pairs (e.g., allowance hasn't propagated yet).
             // Throttle to avoid hammering the RPC provider every poll tick.
-            if self.auto_settle_onchain && (ticks % settle_every_ticks == 0) {
+            if self.settle_mode.retries() && (ticks % settle_every_ticks == 0) {
                 if let Err(e) = self.retry_unsettled_matches().await {
                     warn!("Error retrying unsettled matches: {}", e);
                 }
@@ -78,7 +262,7 @@ impl IntentMatcher {
     }
 
     /// Process a batch of intents for matching
-    async fn match_batch(&self) -> Result<()> {
+    pub async fn match_batch(&self) -> Result<()> {
         let mut pending = self.storage.get_pending_intents().await?;
         pending.sort_by(|a, b| {
             a.created_at
@@ -91,6 +275,16 @@ impl IntentMatcher {
         }
         
         debug!("Processing {} pending intents", pending.len());
+        self.refresh_usd_prices(&pending).await;
+        self.fire_triggers(&mut pending).await;
+        let started = Instant::now();
+        let mut pass = SearchStats {
+            scanned: pending.len() as u64,
+            ..SearchStats::default()
+        };
+        // What the two-party search claimed this pass; ring discovery only looks at the rest.
+        let mut matched_nullifiers = HashSet::new();
+        let mut matched_tokens = HashSet::new();
         
         // Group intents by token pair
         let mut pairs: Vec<(String, String)> = pending
@@ -106,13 +300,19 @@ impl IntentMatcher {
         // the best compatible counterparty (highest surplus, then earliest created_at)
         // is selected.
         for (token_a, token_b) in pairs {
+            self.stamp_liveness();
             // Look for complementary pairs (A->B and B->A)
             let mut intents_a = self.storage.get_intents_by_pair(&token_a, &token_b).await?;
             let mut intents_b = self.storage.get_intents_by_pair(&token_b, &token_a).await?;
+            intents_a.retain(|i| self.meets_min_notional(i));
+            intents_b.retain(|i| self.meets_min_notional(i));
 
             if intents_a.is_empty() || intents_b.is_empty() {
                 continue;
             }
+            let Ok(terms) = self.pair_terms(&token_a, &token_b, intents_a.iter().chain(&intents_b)).await else {
+                continue;
+            };
 
             intents_a.sort_by(|a, b| {
                 a.created_at
@@ -125,7 +325,12 @@ impl IntentMatcher {
                     .then_with(|| a.nullifier.cmp(&b.nullifier))
             });
 
-            let mut used_b = std::collections::HashSet::new();
+            let pair_started = Instant::now();
+            let mut pair_stats = SearchStats {
+                scanned: (intents_a.len() + intents_b.len()) as u64,
+                ..SearchStats::default()
+            };
+            let mut used_b = HashSet::new();
 
             // Try to find compatible matches
             for intent_a in &intents_a {
@@ -135,50 +340,483 @@ impl IntentMatcher {
                 let best = intents_b
                     .iter()
                     .enumerate()
-                    .filter(|(idx, b)| !used_b.contains(idx) && self.are_compatible(intent_a, b))
+                    .filter(|(idx, b)| {
+                        if used_b.contains(idx) {
+                            return false;
+                        }
+                        pair_stats.comparisons += 1;
+                        self.pairs_in_full(intent_a, b, &terms)
+                    })
                     .max_by(|(_, b1), (_, b2)| {
                         self.compatibility_surplus(intent_a, b1)
                             .partial_cmp(&self.compatibility_surplus(intent_a, b2))
                             .unwrap_or(std::cmp::Ordering::Equal)
                             .then_with(|| b2.created_at.cmp(&b1.created_at))
                             .then_with(|| b2.nullifier.cmp(&b1.nullifier))
-                    });
+                    })
+                    .map(|(idx, b)| (idx, b, None));
+                // No counterparty crosses in full: fall back to the earliest one that crosses in part.
+                let best = best.or_else(|| {
+                    if !self.config.partial_fills {
+                        return None;
+                    }
+                    intents_b.iter().enumerate().find_map(|(idx, b)| {
+                        if used_b.contains(&idx) {
+                            return None;
+                        }
+                        pair_stats.comparisons += 1;
+                        self.plan_partial_fill(intent_a, b, terms.reference.as_ref()).map(|fill| (idx, b, Some(fill)))
+                    })
+                });
 
-                if let Some((idx, intent_b)) = best {
-                    match self.create_match(intent_a.clone(), intent_b.clone()).await {
-                        Ok(_) => {
+                if let Some((idx, intent_b, fill)) = best {
+                    match self.proofs_still_valid(intent_a, intent_b).await {
+                        ProofCheck::Valid => {}
+                        ProofCheck::Invalid { counterparty_failed } => {
+                            if counterparty_failed {
+                                used_b.insert(idx);
+                            }
+                            // If intent_a is still valid it gets a new counterparty next round.
+                            continue;
+                        }
+                        ProofCheck::Unavailable => continue,
+                    }
+                    let partial = fill.is_some();
+                    match self.create_match(intent_a.clone(), intent_b.clone(), terms.net, fill).await {
+                        Ok(false) => {
+                            // The other side (or this one) was matched or cancelled since the book was read.
+                            debug!("Skipped {} <-> {}: already taken", log_nullifier(&intent_a.nullifier), log_nullifier(&intent_b.nullifier));
+                            used_b.insert(idx);
+                        }
+                        Ok(true) => {
                             used_b.insert(idx);
+                            pair_stats.matches += 1;
+                            matched_nullifiers.insert(intent_a.nullifier.clone());
+                            matched_nullifiers.insert(intent_b.nullifier.clone());
+                            matched_tokens.insert(token_a.clone());
+                            matched_tokens.insert(token_b.clone());
                             info!(
-                                "Matched intents {} <-> {}",
-                                intent_a.nullifier,
-                                intent_b.nullifier
+                                "Matched intents {} <-> {}{}",
+                                log_nullifier(&intent_a.nullifier),
+                                log_nullifier(&intent_b.nullifier),
+                                if partial { " (partial fill)" } else { "" }
                             );
                         }
                         Err(e) => {
                             warn!("Failed to create match: {}", e);
                         }
                     }
+                } else if self.config.near_miss_tolerance_bps > 0 {
+                    for intent_b in intents_b.iter().filter(|b| self.is_near_miss(intent_a, b)) {
+                        debug!("Near-miss {} <-> {}", log_nullifier(&intent_a.nullifier), log_nullifier(&intent_b.nullifier));
+                        for (intent, counterparty) in [(intent_a, intent_b), (intent_b, intent_a)] {
+                            if let Err(e) = self.storage.record_near_miss(intent, &counterparty.nullifier).await {
+                                warn!("Failed to record near-miss for {}: {}", log_nullifier(&intent.nullifier), e);
+                            }
+                        }
+                    }
+                }
+            }
+
+            pair_stats.elapsed_ms = pair_started.elapsed().as_millis() as u64;
+            debug!(
+                "Searched pair {}->{}: scanned={}, comparisons={}, matches={}, elapsed_ms={}",
+                token_a, token_b, pair_stats.scanned, pair_stats.comparisons, pair_stats.matches, pair_stats.elapsed_ms
+            );
+            pass.comparisons += pair_stats.comparisons;
+            pass.matches += pair_stats.matches;
+        }
+
+        if self.config.ring_matching {
+            let leftover: Vec<Intent> = pending
+                .into_iter()
+                .filter(|i| {
+                    !matched_nullifiers.contains(&i.nullifier)
+                        && !matched_tokens.contains(&i.public_inputs.token_in)
+                        && !matched_tokens.contains(&i.public_inputs.token_out)
+                        && self.meets_min_notional(i)
+                })
+                .collect();
+            let rings = self.find_ring_matches(&leftover);
+            let new = newly_found_rings(&mut self.reported_rings.lock().expect("reported rings lock"), &rings);
+            for ring in &rings {
+                let members: Vec<String> = ring.intents.iter().map(|i| log_nullifier(&i.nullifier).to_string()).collect();
+                if new.contains(&ring.id.as_str()) {
+                    info!("Ring match {} found: {} (not settleable, intents stay pending)", ring.id, members.join(" -> "));
+                } else {
+                    debug!("Ring match {} still pending: {}", ring.id, members.join(" -> "));
                 }
             }
+            self.search_metrics.lock().expect("search metrics lock").rings_found += new.len() as u64;
+        }
+
+        pass.elapsed_ms = started.elapsed().as_millis() as u64;
+        let efficiency = pass
+            .comparisons_per_match()
+            .map(|r| format!("{:.1}", r))
+            .unwrap_or_else(|| "n/a".to_string());
+        if pass.matches > 0 {
+            info!(
+                "Match pass: scanned={}, comparisons={}, matches={}, comparisons_per_match={}, elapsed_ms={}",
+                pass.scanned, pass.comparisons, pass.matches, efficiency, pass.elapsed_ms
+            );
+        } else {
+            debug!(
+                "Match pass: scanned={}, comparisons={}, matches=0, elapsed_ms={}",
+                pass.scanned, pass.comparisons, pass.elapsed_ms
+            );
         }
+        self.record_search(pass);
         
         Ok(())
     }
 
-    /// Check if two intents are compatible for matching
-    fn are_compatible(&self, a: &Intent, b: &Intent) -> bool {
-        // Same user cannot match with themselves
-        if a.public_inputs.user == b.public_inputs.user {
+    /// How many of `candidates` could match `intent` right now, by the rules `match_batch` uses.
+    pub fn compatible_counterparties(&self, intent: &Intent, candidates: &[Intent]) -> usize {
+        candidates
+            .iter()
+            .filter(|c| c.can_match() && self.are_compatible(intent, c))
+            .count()
+    }
+
+    /// `walk_book` for `amount_in` against the `resting` intents that could match now, oldest first
+    /// among equal prices, as the matcher breaks ties. `resting` offer the quoted `token_out` for
+    /// its `token_in`.
+    pub fn quote(&self, amount_in: &BigUint, resting: &[Intent]) -> BookWalk {
+        let mut resting: Vec<&Intent> = resting.iter().filter(|i| i.can_match()).collect();
+        resting.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.nullifier.cmp(&b.nullifier)));
+        let levels: Vec<(BigUint, BigUint)> = resting
+            .into_iter()
+            .filter_map(|intent| Self::amounts_in_base_units(intent))
+            .collect();
+        walk_book(amount_in, &levels, self.config.partial_fills)
+    }
+
+    /// Three-intent rings among `pending` (see `MatchedGroup`), taken greedily in `pending` order
+    /// with each intent in at most one ring. Same per-pair rules as two-party matching, minus the
+    /// price band: there is no single reference price around a ring.
+    pub fn find_ring_matches(&self, pending: &[Intent]) -> Vec<MatchedGroup> {
+        find_rings(pending)
+    }
+
+    fn min_notional_enforced(&self) -> bool {
+        self.oracle.is_some() && self.config.min_match_amount_usd > 0.0
+    }
+
+    /// Fetches the USD rate of every token offered in `pending` for `min_match_amount_usd`.
+    /// The oracle caches the feeds, so this is cheap between cache expiries.
+    async fn refresh_usd_prices(&self, pending: &[Intent]) {
+        let Some(oracle) = self.oracle.as_ref().filter(|_| self.min_notional_enforced()) else {
+            return;
+        };
+        let mut tokens: Vec<String> = pending
+            .iter()
+            .map(|i| i.public_inputs.token_in.to_ascii_lowercase())
+            .collect();
+        tokens.sort();
+        tokens.dedup();
+        for token in tokens {
+            let rate = oracle.get_cross_rate(&token, "USD", None).await;
+            let mut prices = self.usd_prices.lock().expect("usd prices lock");
+            match rate {
+                Ok(rate) => {
+                    prices.insert(token, rate);
+                }
+                Err(e) => {
+                    debug!("No USD price for {}, its intents are ineligible this pass: {}", token, e);
+                    prices.remove(&token);
+                }
+            }
+        }
+    }
+
+    /// Marks the conditional intents in `pending` whose trigger the oracle price has reached, here
+    /// and in storage, so they match from this pass on. Each pair's rate is read once; without a
+    /// price, or with a stale one, its intents keep waiting.
+    async fn fire_triggers(&self, pending: &mut [Intent]) {
+        let Some(oracle) = self.oracle.as_ref() else {
+            return;
+        };
+        let mut rates: HashMap<(String, String), Option<CrossRate>> = HashMap::new();
+        let now = chrono::Utc::now();
+        for intent in pending.iter_mut().filter(|i| !i.is_triggered()) {
+            let (Some(trigger), Some(direction)) = (
+                intent.trigger_price.as_deref().and_then(parse_trigger_price),
+                intent.trigger_direction,
+            ) else {
+                continue;
+            };
+            let pair = (
+                intent.public_inputs.token_in.to_ascii_lowercase(),
+                intent.public_inputs.token_out.to_ascii_lowercase(),
+            );
+            let rate = match rates.get(&pair) {
+                Some(rate) => rate.clone(),
+                None => {
+                    let rate = match oracle.get_cross_rate(&pair.0, &pair.1, None).await {
+                        Ok(rate) if self.is_stale(&rate) => None,
+                        Ok(rate) => Some(rate),
+                        Err(e) => {
+                            debug!("No price for {}->{}, its triggers wait: {}", pair.0, pair.1, e);
+                            None
+                        }
+                    };
+                    rates.insert(pair, rate.clone());
+                    rate
+                }
+            };
+            let Some(rate) = rate else {
+                continue;
+            };
+            let Some(price) = BigUint::parse_bytes(rate.price_raw.as_bytes(), 10) else {
+                continue;
+            };
+            if !trigger_met(&price, rate.decimals, &trigger, direction) {
+                continue;
+            }
+            match self.storage.mark_intent_triggered(&intent.nullifier, now).await {
+                Ok(true) => {
+                    info!(
+                        "Intent {} triggered: {} is {} {}",
+                        log_nullifier(&intent.nullifier),
+                        rate.pair_id,
+                        direction.name(),
+                        intent.trigger_price.as_deref().unwrap_or_default()
+                    );
+                    intent.triggered_at = Some(now);
+                }
+                Ok(false) => {}
+                Err(e) => warn!("Failed to mark intent {} triggered: {}", log_nullifier(&intent.nullifier), e),
+            }
+        }
+    }
+
+    /// Whether `rate` is past `MAX_ORACLE_STALENESS_SECONDS`, keeping the stale-feed report current.
+    fn is_stale(&self, rate: &CrossRate) -> bool {
+        let max_age = self.config.max_oracle_staleness_seconds;
+        if max_age == 0 {
             return false;
         }
-        
-        // Tokens must be complementary
-        if a.public_inputs.token_in != b.public_inputs.token_out
-            || a.public_inputs.token_out != b.public_inputs.token_in
-        {
+        let now = chrono::Utc::now();
+        let stale = rate.is_stale(now.timestamp().max(0) as u64, max_age);
+        let mut feeds = self.stale_feeds.lock().expect("stale feeds lock");
+        if !stale {
+            if feeds.remove(&rate.pair_id).is_some() {
+                info!("Oracle price for {} is fresh again", rate.pair_id);
+            }
             return false;
         }
-        
+        let last_updated = rate.last_updated();
+        let feed = StaleOracleFeed { pair_id: rate.pair_id.clone(), last_updated, checked_at: now };
+        if feeds.insert(rate.pair_id.clone(), feed).is_none() {
+            warn!(
+                "Oracle price for {} is stale (last updated {:?}, limit {}s); {}",
+                rate.pair_id,
+                last_updated,
+                max_age,
+                if self.config.stale_oracle_amount_matching { "matching on amounts alone" } else { "pausing its pairs" }
+            );
+        }
+        true
+    }
+
+    /// Stale feeds seen by the most recent passes, for `/health/ready`. Rates that no pass has
+    /// asked for in `STALE_FEED_REPORT_TTL_SECONDS` are forgotten.
+    pub fn stale_oracle_feeds(&self) -> Vec<StaleOracleFeed> {
+        let cutoff = chrono::Utc::now() - chrono::Duration::seconds(STALE_FEED_REPORT_TTL_SECONDS);
+        let mut feeds = self.stale_feeds.lock().expect("stale feeds lock");
+        feeds.retain(|_, feed| feed.checked_at >= cutoff);
+        let mut report: Vec<StaleOracleFeed> = feeds.values().cloned().collect();
+        report.sort_by(|a, b| a.pair_id.cmp(&b.pair_id));
+        report
+    }
+
+    /// USD value of the intent's `amount_in` at the last fetched price, or `None` when the token
+    /// has no price feed (or the amount doesn't parse).
+    pub fn notional_usd(&self, intent: &Intent) -> Option<f64> {
+        let (amount_in, _) = Self::amounts_in_base_units(intent)?;
+        let prices = self.usd_prices.lock().expect("usd prices lock");
+        let rate = prices.get(&intent.public_inputs.token_in.to_ascii_lowercase())?;
+        let micro_usd = usd_value(&amount_in, token_decimals_for(&intent.public_inputs.token_in), rate, 6)?;
+        Some(micro_usd.to_f64()? / 1e6)
+    }
+
+    /// Whether the intent is worth at least `min_match_amount_usd`. Compared in base units so large
+    /// amounts don't lose precision; intents without a USD price are ineligible. So are intents
+    /// whose price is stale, unless `stale_oracle_amount_matching` lets them skip the check.
+    fn meets_min_notional(&self, intent: &Intent) -> bool {
+        if !self.min_notional_enforced() {
+            return true;
+        }
+        let rate = self
+            .usd_prices
+            .lock()
+            .expect("usd prices lock")
+            .get(&intent.public_inputs.token_in.to_ascii_lowercase())
+            .cloned();
+        if rate.as_ref().is_some_and(|rate| self.is_stale(rate)) {
+            if !self.config.stale_oracle_amount_matching {
+                debug!("Intent {} has a stale USD price; skipping", log_nullifier(&intent.nullifier));
+            }
+            return self.config.stale_oracle_amount_matching;
+        }
+        let eligible = Self::amounts_in_base_units(intent).is_some_and(|(amount_in, _)| {
+            rate.as_ref().is_some_and(|rate| {
+                worth_at_least(
+                    &amount_in,
+                    token_decimals_for(&intent.public_inputs.token_in),
+                    rate,
+                    self.config.min_match_amount_usd,
+                )
+            })
+        });
+        if !eligible {
+            match self.notional_usd(intent) {
+                Some(usd) => debug!(
+                    "Intent {} is worth {:.2} USD, below MIN_MATCH_AMOUNT_USD ({}); skipping",
+                    log_nullifier(&intent.nullifier), usd, self.config.min_match_amount_usd
+                ),
+                None => debug!("Intent {} has no USD price; skipping", log_nullifier(&intent.nullifier)),
+            }
+        }
+        eligible
+    }
+
+    /// How `token_a` -> `token_b` matches settle this pass and the price they are held to, given
+    /// the intents on both sides. Errors with the reason when the pair sits out: no pool under
+    /// `NO_POOL_POLICY=skip` (or the lookup failed), or no usable reference price.
+    async fn pair_terms<'i>(
+        &self,
+        token_a: &str,
+        token_b: &str,
+        intents: impl IntoIterator<Item = &'i Intent>,
+    ) -> Result<PairTerms, &'static str> {
+        let net = match self.pool_route(token_a, token_b).await {
+            PoolRoute::Ekubo => false,
+            PoolRoute::Net => true,
+            PoolRoute::Unmatchable => return Err("no pool to settle the pair through"),
+        };
+        let wants_band =
+            self.config.max_slippage_bps > 0 || intents.into_iter().any(|i| i.max_slippage_bps.is_some());
+        match self.slippage_reference(token_a, token_b, wants_band).await {
+            Ok(reference) => Ok(PairTerms { net, reference }),
+            Err(e) => {
+                warn!("No reference price for {}->{}, skipping the pair this round: {}", token_a, token_b, e);
+                Err("no reference price for the slippage band")
+            }
+        }
+    }
+
+    /// Whether `a` takes `b` as its counterparty in full: they cross and the price sits in the band.
+    fn pairs_in_full(&self, a: &Intent, b: &Intent, terms: &PairTerms) -> bool {
+        self.are_compatible(a, b) && self.within_price_band(a, b, terms.reference.as_ref())
+    }
+
+    /// Consensus Pragma price of `token_a` in `token_b` (scaled by `10^decimals`) to hold matches
+    /// to, or `None` when no intent wants a band, there is no oracle, or Pragma has no feed for
+    /// either token.
+    /// A stale price is an error (the pair sits out), or `None` under `stale_oracle_amount_matching`.
+    async fn slippage_reference(
+        &self,
+        token_a: &str,
+        token_b: &str,
+        wanted: bool,
+    ) -> Result<Option<(BigUint, u32)>, OracleError> {
+        let Some(oracle) = self.oracle.as_ref().filter(|_| wanted) else {
+            return Ok(None);
+        };
+        match oracle.get_consensus_cross_rate(token_a, token_b, None).await {
+            Ok(rate) if self.is_stale(&rate) => {
+                if self.config.stale_oracle_amount_matching {
+                    Ok(None)
+                } else {
+                    Err(OracleError::Upstream("Oracle price is stale"))
+                }
+            }
+            Ok(rate) => {
+                let price = BigUint::parse_bytes(rate.price_raw.as_bytes(), 10)
+                    .ok_or(OracleError::Internal("Unparseable cross rate"))?;
+                Ok(Some((price, rate.decimals)))
+            }
+            Err(OracleError::InvalidPairId(reason)) => {
+                debug!("No slippage band for {}->{}: {}", token_a, token_b, reason);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// `a` gives its `amount_in` of token A for `b`'s `amount_in` of token B; the price that
+    /// implies has to sit within the strictest slippage limit of `reference`.
+    fn within_price_band(&self, a: &Intent, b: &Intent, reference: Option<&(BigUint, u32)>) -> bool {
+        match (Self::amounts_in_base_units(a), Self::amounts_in_base_units(b)) {
+            (Some((a_in, _)), Some((b_in, _))) => self.price_band_allows(a, b, &a_in, &b_in, reference),
+            _ => reference.is_none(),
+        }
+    }
+
+    /// Like `within_price_band`, for `a` giving `a_gives` and `b` giving `b_gives` (base units).
+    fn price_band_allows(
+        &self,
+        a: &Intent,
+        b: &Intent,
+        a_gives: &BigUint,
+        b_gives: &BigUint,
+        reference: Option<&(BigUint, u32)>,
+    ) -> bool {
+        let (Some((price, price_decimals)), Some(max_bps)) = (
+            reference,
+            effective_slippage_bps(self.config.max_slippage_bps, a.max_slippage_bps, b.max_slippage_bps),
+        ) else {
+            return true;
+        };
+        let inside = within_slippage(
+            a_gives,
+            token_decimals_for(&a.public_inputs.token_in),
+            b_gives,
+            token_decimals_for(&b.public_inputs.token_in),
+            price,
+            *price_decimals,
+            max_bps,
+        );
+        if !inside {
+            debug!(
+                "Slippage band rejects {} <-> {}: implied price is more than {} bps from Pragma",
+                log_nullifier(&a.nullifier), log_nullifier(&b.nullifier), max_bps
+            );
+        }
+        inside
+    }
+
+    /// When `a` and `b` don't cross in full but one of them covers the other's whole order, the
+    /// fill that trades the smaller side completely. Same checks as `are_compatible` otherwise.
+    fn plan_partial_fill(&self, a: &Intent, b: &Intent, reference: Option<&(BigUint, u32)>) -> Option<PartialFill> {
+        if !self.is_pairable(a, b) || self.are_compatible(a, b) {
+            return None;
+        }
+        let (a_in, a_min_out) = Self::amounts_in_base_units(a)?;
+        let (b_in, b_min_out) = Self::amounts_in_base_units(b)?;
+        let (large, fill) = if let Some(fill) = partial_fill_amounts(&a_in, &a_min_out, &b_in, &b_min_out) {
+            if !self.price_band_allows(a, b, &fill.amount_in, &b_in, reference) {
+                return None;
+            }
+            (a, fill)
+        } else {
+            let fill = partial_fill_amounts(&b_in, &b_min_out, &a_in, &a_min_out)?;
+            if !self.price_band_allows(a, b, &a_in, &fill.amount_in, reference) {
+                return None;
+            }
+            (b, fill)
+        };
+        Some(partial_fill(large, fill))
+    }
+
+    /// Check if two intents are compatible for matching
+    fn are_compatible(&self, a: &Intent, b: &Intent) -> bool {
+        if !self.is_pairable(a, b) {
+            return false;
+        }
+
         // Check amount compatibility in base units.
         // A's input should satisfy B's minimum output, and vice versa.
         let (amount_a_in, min_a_out) = match Self::amounts_in_base_units(a) {
@@ -191,7 +829,30 @@ impl IntentMatcher {
         };
         
         // Both sides must be satisfied
-        if amount_a_in < min_b_out || amount_b_in < min_a_out {
+        amount_a_in >= min_b_out && amount_b_in >= min_a_out
+    }
+
+    /// Everything `are_compatible` checks except the amounts.
+    fn is_pairable(&self, a: &Intent, b: &Intent) -> bool {
+        // Same user cannot match with themselves
+        if a.public_inputs.user == b.public_inputs.user {
+            return false;
+        }
+
+        // Scheduled (good-after-time) intents sit out until they activate.
+        if !a.is_active() || !b.is_active() {
+            return false;
+        }
+
+        // A pair that just failed to settle waits out its cooldown before being tried again.
+        if a.failed_recently_with(&b.nullifier) || b.failed_recently_with(&a.nullifier) {
+            return false;
+        }
+        
+        // Tokens must be complementary
+        if a.public_inputs.token_in != b.public_inputs.token_out
+            || a.public_inputs.token_out != b.public_inputs.token_in
+        {
             return false;
         }
         
@@ -208,6 +869,30 @@ impl IntentMatcher {
         true
     }
 
+    /// Would-be counterparties (complementary tokens, both active) whose amounts fall short of
+    /// crossing on at least one side, but by no more than `near_miss_tolerance_bps`.
+    fn is_near_miss(&self, a: &Intent, b: &Intent) -> bool {
+        if a.public_inputs.user == b.public_inputs.user || !b.can_match() || !a.is_active() {
+            return false;
+        }
+        if a.public_inputs.token_in != b.public_inputs.token_out
+            || a.public_inputs.token_out != b.public_inputs.token_in
+        {
+            return false;
+        }
+        let (Some((amount_a_in, min_a_out)), Some((amount_b_in, min_b_out))) =
+            (Self::amounts_in_base_units(a), Self::amounts_in_base_units(b))
+        else {
+            return false;
+        };
+
+        let crosses = amount_a_in >= min_b_out && amount_b_in >= min_a_out;
+        let bps = self.config.near_miss_tolerance_bps;
+        !crosses
+            && within_tolerance(&amount_a_in, &min_b_out, bps)
+            && within_tolerance(&amount_b_in, &min_a_out, bps)
+    }
+
     fn compatibility_surplus(&self, a: &Intent, b: &Intent) -> f64 {
         // Calculate surplus using base units, convert to f64 for ranking only.
         let (amount_a_in, min_a_out) = Self::amounts_in_base_units(a).unwrap_or_default();
@@ -230,43 +915,186 @@ impl IntentMatcher {
         total_surplus.to_string().parse::<f64>().unwrap_or(0.0)
     }
 
-    /// Create a match between two compatible intents
-    async fn create_match(&self, intent_a: Intent, intent_b: Intent) -> Result<()> {
+    /// With `VERIFY_PROOFS_AT_MATCH`, re-simulates both intents' `submit_intent` in one batched
+    /// call: one revert reason per intent whose proof no longer verifies. Empty without the setting.
+    async fn proof_reverts(&self, intent_a: &Intent, intent_b: &Intent) -> Result<Vec<Option<String>>> {
+        match &self.proof_verifier {
+            Some(verifier) => verifier.verify(&[intent_a, intent_b]).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// `proof_reverts` for a pair about to be matched. Intents whose proofs no longer verify are
+    /// failed with `PROOF_INVALID_AT_MATCH`.
+    async fn proofs_still_valid(&self, intent_a: &Intent, intent_b: &Intent) -> ProofCheck {
+        let results = match self.proof_reverts(intent_a, intent_b).await {
+            Ok(results) => results,
+            Err(e) => {
+                warn!(
+                    "Skipping match {} <-> {} this round; proof re-verification unavailable: {}",
+                    log_nullifier(&intent_a.nullifier), log_nullifier(&intent_b.nullifier), e
+                );
+                return ProofCheck::Unavailable;
+            }
+        };
+
+        let mut any_invalid = false;
+        let mut counterparty_failed = false;
+        for (intent, result) in [intent_a, intent_b].into_iter().zip(results) {
+            let Some(revert) = result else { continue };
+            any_invalid = true;
+            counterparty_failed |= intent.nullifier == intent_b.nullifier;
+            warn!("Proof for intent {} no longer verifies: {}", log_nullifier(&intent.nullifier), revert);
+            let reason = format!("PROOF_INVALID_AT_MATCH: {}", revert);
+            match self.storage.mark_intent_failed(&intent.nullifier, &reason).await {
+                Ok(()) => self.events.publish(SolverEvent::Failed {
+                    nullifier: intent.nullifier.clone(),
+                    reason,
+                    timestamp: chrono::Utc::now(),
+                }),
+                Err(e) => warn!("Failed to mark intent {} failed: {}", log_nullifier(&intent.nullifier), e),
+            }
+        }
+        if any_invalid {
+            ProofCheck::Invalid { counterparty_failed }
+        } else {
+            ProofCheck::Valid
+        }
+    }
+
+    /// The pair `create_match` would store for two intents, by the rules `match_batch` applies:
+    /// minimum notional, pool route, slippage band, partial fills and the proof re-check. Errors
+    /// when the matching loop would not pair them: either can't match right now
+    /// (`INTENT_NOT_PENDING`) or it would pass them over (`INCOMPATIBLE_INTENTS`, with why).
+    pub async fn preview_match(&self, first: Intent, second: Intent) -> Result<MatchedPair> {
+        let refuse = |why: &str| anyhow::anyhow!("INCOMPATIBLE_INTENTS: {}", why);
+        if !first.can_match() || !second.can_match() {
+            return Err(anyhow::anyhow!("INTENT_NOT_PENDING: both intents must be pending, active and unexpired"));
+        }
+        // `match_batch` walks token pairs in sorted order, so the side selling the smaller
+        // (token_in, token_out) is the one that picks its counterparty and becomes `intent_a`.
+        let side = |i: &Intent| (i.public_inputs.token_in.clone(), i.public_inputs.token_out.clone());
+        let (intent_a, intent_b) = if side(&first) <= side(&second) {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        if !self.is_pairable(&intent_a, &intent_b) {
+            return Err(refuse("the intents would not be paired"));
+        }
+
+        let both = [intent_a.clone(), intent_b.clone()];
+        self.refresh_usd_prices(&both).await;
+        if !both.iter().all(|i| self.meets_min_notional(i)) {
+            return Err(refuse("an intent is below MIN_MATCH_AMOUNT_USD"));
+        }
+        let (token_a, token_b) = side(&intent_a);
+        let terms = self.pair_terms(&token_a, &token_b, &both).await.map_err(refuse)?;
+        let fill = if self.pairs_in_full(&intent_a, &intent_b, &terms) {
+            None
+        } else if self.config.partial_fills {
+            let fill = self.plan_partial_fill(&intent_a, &intent_b, terms.reference.as_ref());
+            Some(fill.ok_or_else(|| refuse("the intents don't cross within the slippage band, even in part"))?)
+        } else if self.are_compatible(&intent_a, &intent_b) {
+            return Err(refuse("the implied price is outside the slippage band"));
+        } else {
+            return Err(refuse("the intents would not be paired"));
+        };
+
+        let reverts = self.proof_reverts(&intent_a, &intent_b).await.map_err(|e| {
+            refuse(&format!("proof re-verification unavailable: {}", e))
+        })?;
+        for (intent, revert) in both.iter().zip(reverts) {
+            if let Some(revert) = revert {
+                return Err(refuse(&format!("the proof of {} no longer verifies: {}", intent.nullifier, revert)));
+            }
+        }
+
+        let mut pair = self.build_match(intent_a, intent_b, terms.net).await;
+        pair.fill = fill;
+        Ok(pair)
+    }
+
+    /// `net` settles without a pool: a zero `ekubo_pool` has the contract swap the two sides directly.
+    async fn build_match(&self, intent_a: Intent, intent_b: Intent, net: bool) -> MatchedPair {
+        let (ekubo_pool, sqrt_price_limit) = if net {
+            (format!("0x{:064x}", 0), "0".to_string())
+        } else {
+            (
+                self.pool_address(&intent_a.public_inputs.token_in, &intent_a.public_inputs.token_out).await,
+                self.sqrt_price_limit_for(&intent_a, &intent_b).await,
+            )
+        };
+        let settlement_data = SettlementData {
+            ekubo_pool,
+            sqrt_price_limit,
+            surplus_distribution: self
+                .config
+                .surplus_policy
+                .map(|policy| surplus_distribution(policy, intent_a.created_at, intent_b.created_at)),
+        };
+        let mut pair = MatchedPair::new(intent_a, intent_b, settlement_data);
+        pair.expected_profit = expected_profit(&pair);
+        pair
+    }
+
+    /// Create a match between two compatible intents.
+    /// Returns false when either intent was taken after the book was read; nothing is stored then.
+    async fn create_match(&self, intent_a: Intent, intent_b: Intent, net: bool, fill: Option<PartialFill>) -> Result<bool> {
         // Verify both intents are still pending
         if !intent_a.can_match() || !intent_b.can_match() {
             return Err(anyhow::anyhow!("One or more intents no longer pending"));
         }
         
-        // Create settlement data
-        let settlement_data = SettlementData {
-            ekubo_pool: self.get_pool_address(&intent_a.public_inputs.token_in, &intent_a.public_inputs.token_out),
-            sqrt_price_limit: "0".to_string(), // TODO: Calculate from current price
-        };
-        
-        let matched_pair = MatchedPair::new(intent_a.clone(), intent_b.clone(), settlement_data);
-        
-        // Store the match
-        self.storage.store_matched_pair(&matched_pair).await?;
-        
-        // Update intent statuses
-        self.storage.update_intent_status(
-            &intent_a.nullifier,
-            IntentStatus::Matched,
-            Some(intent_b.nullifier.clone()),
-            None,
-        ).await?;
+        let mut matched_pair = self.build_match(intent_a.clone(), intent_b.clone(), net).await;
+        matched_pair.fill = fill;
         
-        self.storage.update_intent_status(
-            &intent_b.nullifier,
-            IntentStatus::Matched,
-            Some(intent_a.nullifier.clone()),
-            None,
-        ).await?;
+        // Store the match and flip both intents to Matched in one step, unless either moved on.
+        if !self.storage.try_commit_match(&matched_pair).await? {
+            return Ok(false);
+        }
+
+        let now = chrono::Utc::now();
+        self.events.publish(SolverEvent::MatchCreated {
+            match_id: matched_pair.id.clone(),
+            intent_a: intent_a.nullifier.clone(),
+            intent_b: intent_b.nullifier.clone(),
+            timestamp: now,
+        });
+        for (nullifier, matched_with) in [(&intent_a.nullifier, &intent_b.nullifier), (&intent_b.nullifier, &intent_a.nullifier)] {
+            self.events.publish(SolverEvent::Matched {
+                nullifier: nullifier.clone(),
+                matched_with: matched_with.clone(),
+                match_id: matched_pair.id.clone(),
+                timestamp: now,
+            });
+        }
+
+        if let Some(fill) = &matched_pair.fill {
+            let parent = if fill.nullifier == intent_a.nullifier { &intent_a } else { &intent_b };
+            let residual = residual_intent(parent, fill);
+            self.storage.store_intent(&residual).await?;
+            info!(
+                "Partial fill of {} in match {}: {} filled, residual {} rests with {}",
+                log_nullifier(&parent.nullifier), matched_pair.id, fill.amount_in, log_nullifier(&residual.nullifier), fill.residual_amount_in
+            );
+            self.events.publish(SolverEvent::Submitted {
+                nullifier: residual.nullifier.clone(),
+                user: residual.public_inputs.user.clone(),
+                token_in: residual.public_inputs.token_in.clone(),
+                token_out: residual.public_inputs.token_out.clone(),
+                timestamp: now,
+            });
+            // The DarkPool settles whole intents against their proofs; nothing to submit.
+            return Ok(true);
+        }
 
         // Auto-settle on-chain immediately after match creation.
         // This requires the solver account to be configured and funded.
-        if self.auto_settle_onchain {
-            match self.settle_match(matched_pair.clone()).await {
+        if self.settle_mode == SettleMode::Auto && self.settlement_paused().await {
+            info!("Match {} queued: daily gas budget reached", matched_pair.id);
+        } else if self.settle_mode == SettleMode::Auto {
+            match self.settle_match(matched_pair.clone(), SettlementTrigger::Auto).await {
                 Ok(()) => info!("Auto-settled match {} on-chain", matched_pair.id),
                 Err(e) => {
                     error!("Auto-settlement failed for match {}: {}", matched_pair.id, e);
@@ -275,7 +1103,7 @@ impl IntentMatcher {
             }
         }
 
-        Ok(())
+        Ok(true)
     }
 
     /// Settle a match by id (called by confirm endpoint).
@@ -285,58 +1113,491 @@ impl IntentMatcher {
             .get_matched_pair(match_id)
             .await?
             .ok_or_else(|| anyhow::anyhow!("Match not found: {}", match_id))?;
-        self.settle_match(pair).await
+        // Under `manual_with_retry` this is what opts the match in to the retry loop.
+        if let Err(e) = self.storage.mark_match_confirmed(match_id).await {
+            warn!("Failed to record confirmation for match {}: {}", match_id, e);
+        }
+        if self.settlement_paused().await {
+            return Err(anyhow::anyhow!("SETTLEMENT_PAUSED: daily gas budget reached (match {})", match_id));
+        }
+        self.settle_match(pair, SettlementTrigger::Manual).await
     }
 
-    /// Settle a matched pair on-chain
-    async fn settle_match(&self, pair: MatchedPair) -> Result<()> {
-        info!(
-            "Settling match {}: {} <-> {}",
-            pair.id,
-            pair.intent_a.nullifier,
-            pair.intent_b.nullifier
-        );
-        
-        if let Some(client) = &self.starknet {
-            // Avoid submitting a tx that is guaranteed to revert due to missing approvals/balances.
-            if let Err(reason) = self.precheck_settlement(client, &pair).await {
-                if Self::is_precheck_rpc_unavailable(&reason) {
-                    warn!(
-                        "Settlement precheck unavailable for match {} ({}); proceeding with on-chain attempt",
-                        pair.id, reason
-                    );
-                } else {
-                    return Err(anyhow::anyhow!(reason));
-                }
-            }
-            let tx_hash = client.settle_match(&pair).await?;
-            self.storage.update_intent_status(
-                &pair.intent_a.nullifier,
-                IntentStatus::Settled,
-                Some(pair.intent_b.nullifier.clone()),
-                Some(tx_hash.clone()),
-            ).await?;
-            self.storage.update_intent_status(
-                &pair.intent_b.nullifier,
-                IntentStatus::Settled,
-                Some(pair.intent_a.nullifier.clone()),
-                Some(tx_hash),
-            ).await?;
-            // Remove from the "matched" set so the retry loop doesn't keep attempting it.
-            self.storage.mark_match_settled(&pair.id).await?;
-            // If this was previously failing (e.g., allowance propagation), clear backoff state.
-            let _ = self.storage.clear_match_retry_state(&pair.id).await;
-            info!("Match {} settled successfully", pair.id);
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("Starknet client not configured"))
-        }
+    /// Simulates settling `pair` and decodes what each participant would receive. `None` when there
+    /// is nothing to simulate with: no Starknet client, the relayer, or a partial fill.
+    pub async fn simulate_outputs(&self, pair: &MatchedPair) -> Result<Option<Vec<SettlementOutput>>> {
+        let Some(client) = self.starknet.as_ref().filter(|_| pair.fill.is_none()) else {
+            return Ok(None);
+        };
+        let Some(events) = client.simulate_settlement(pair).await? else {
+            return Ok(None);
+        };
+        settlement_outputs(pair, &events).map(Some)
     }
 
-    /// Get pool address from token pair
-    fn get_pool_address(&self, token_a: &str, token_b: &str) -> String {
-        // In production, this would query Ekubo factory
-        // For now, return a deterministic mock address
+    /// Reconciles a settlement someone else sent: checks on-chain that `tx_hash` settled exactly this
+    /// match and records it as if the solver had submitted it. Reporting the recorded hash again is
+    /// a no-op.
+    pub async fn report_settlement_tx(&self, match_id: &str, tx_hash: &str) -> Result<()> {
+        let client = self
+            .starknet
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Starknet client not configured"))?;
+        let pair = self
+            .storage
+            .get_matched_pair(match_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Match not found: {}", match_id))?;
+
+        let pairs = client
+            .settled_nullifier_pairs(tx_hash)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("TX_NOT_FOUND tx={}", tx_hash))?;
+        if !settles_pair(&pairs, &pair.intent_a.nullifier, &pair.intent_b.nullifier)? {
+            return Err(anyhow::anyhow!("TX_NOT_SETTLEMENT_OF_MATCH match={} tx={}", match_id, tx_hash));
+        }
+
+        let lock_token = self
+            .storage
+            .try_acquire_match_lock(&pair.id, self.config.settlement_lock_ttl_seconds)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("SETTLEMENT_IN_PROGRESS match={}", pair.id))?;
+        let result = async {
+            let a = self.storage.get_intent(&pair.intent_a.nullifier).await?;
+            let recorded = a.as_ref().and_then(|i| i.settlement_tx_hash.as_deref());
+            if let Some(recorded) = recorded {
+                if same_address(recorded, tx_hash) {
+                    return Ok(());
+                }
+                return Err(anyhow::anyhow!("MATCH_ALREADY_SETTLED match={} tx={}", pair.id, recorded));
+            }
+            info!("Recording reported settlement of match {}: {}", pair.id, tx_hash);
+            self.record_settlement(&pair, tx_hash.to_string(), SettlementTrigger::Reported).await?;
+            // The solver hears of this transaction only now, so both callbacks go out together.
+            self.send_submitted_callbacks(&pair, tx_hash);
+            match client.transaction_outcome(tx_hash).await {
+                Ok(TxOutcome::Confirmed { block_number, events }) => {
+                    self.send_confirmed_callbacks(&pair, tx_hash, block_number, &events)
+                }
+                Ok(_) => warn!("Reported settlement {} is not accepted on L2 yet; confirmed callbacks not sent", tx_hash),
+                Err(e) => warn!("Could not read the receipt of reported settlement {}: {}", tx_hash, e),
+            }
+            Ok(())
+        }
+        .await;
+        if let Err(e) = self.storage.release_match_lock(&pair.id, &lock_token).await {
+            warn!("Failed to release settlement lock for match {}: {}", pair.id, e);
+        }
+        result
+    }
+
+    /// Settle a matched pair on-chain
+    async fn settle_match(&self, pair: MatchedPair, trigger: SettlementTrigger) -> Result<()> {
+        ensure_settleable(&pair)?;
+        info!(
+            "Settling match {}: {} <-> {}",
+            pair.id,
+            log_nullifier(&pair.intent_a.nullifier),
+            log_nullifier(&pair.intent_b.nullifier)
+        );
+        
+        if let Some(client) = &self.starknet {
+            // Auto-settle, the retry loop and a manual confirm can all target the same match.
+            // Only the holder of the per-match lock may submit; everyone else backs off.
+            let lock_token = self
+                .storage
+                .try_acquire_match_lock(&pair.id, self.config.settlement_lock_ttl_seconds)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("SETTLEMENT_IN_PROGRESS match={}", pair.id))?;
+
+            let result = self.settle_match_locked(client, &pair, trigger).await;
+            self.stamp_liveness();
+
+            if let Err(e) = self.storage.release_match_lock(&pair.id, &lock_token).await {
+                warn!("Failed to release settlement lock for match {}: {}", pair.id, e);
+            }
+            result
+        } else {
+            Err(anyhow::anyhow!("Starknet client not configured"))
+        }
+    }
+
+    /// Settlement body; the caller must hold the per-match settlement lock.
+    async fn settle_match_locked(
+        &self,
+        client: &Arc<StarknetClient>,
+        pair: &MatchedPair,
+        trigger: SettlementTrigger,
+    ) -> Result<()> {
+        self.prepare_settlement(client, pair, trigger).await?;
+        let pairs = std::slice::from_ref(pair);
+        let tx_hash = self.submit_settlement(client, pairs, trigger).await?;
+        self.confirm_settlement(client, pairs, tx_hash)
+            .await
+            .pop()
+            .unwrap_or(Ok(()))
+    }
+
+    /// Checks that `pair` is still worth sending: nobody settled it meanwhile, its deadlines leave
+    /// room, and the precheck passes. The caller must hold the per-match settlement lock.
+    async fn prepare_settlement(
+        &self,
+        client: &Arc<StarknetClient>,
+        pair: &MatchedPair,
+        trigger: SettlementTrigger,
+    ) -> Result<()> {
+        // The previous lock holder may have settled this match while we were waiting.
+        let a = self.storage.get_intent(&pair.intent_a.nullifier).await?;
+        let b = self.storage.get_intent(&pair.intent_b.nullifier).await?;
+        let still_matched = |i: &Option<Intent>| {
+            i.as_ref()
+                .map(|i| i.status == IntentStatus::Matched && i.settlement_tx_hash.is_none())
+                .unwrap_or(false)
+        };
+        if !still_matched(&a) || !still_matched(&b) {
+            return Err(anyhow::anyhow!("MATCH_ALREADY_SETTLED match={}", pair.id));
+        }
+        // A transaction sent earlier, perhaps by a process that has since restarted, may still land;
+        // `reconcile_settling_matches` decides what happened to it.
+        if let Some(marker) = self.storage.get_settling_marker(&pair.id).await? {
+            return Err(anyhow::anyhow!(
+                "SETTLEMENT_IN_PROGRESS match={} tx={}",
+                pair.id,
+                marker.tx_hash.as_deref().unwrap_or("unknown")
+            ));
+        }
+
+        // A match that sat in backoff can outlive its deadlines, and the contract would revert.
+        let earliest_deadline = [&a, &b]
+            .into_iter()
+            .flatten()
+            .map(|i| i.public_inputs.deadline)
+            .min()
+            .unwrap_or(0);
+        match client.block_timestamp().await {
+            Ok(block_time)
+                if earliest_deadline < block_time.saturating_add(self.config.settlement_deadline_buffer_seconds) =>
+            {
+                warn!(
+                    "Skipping settlement of match {}: earliest deadline {} is within {}s of block time {}",
+                    pair.id, earliest_deadline, self.config.settlement_deadline_buffer_seconds, block_time
+                );
+                self.fail_match(pair, "DEADLINE_EXPIRED", trigger).await;
+                return Err(anyhow::anyhow!(
+                    "DEADLINE_EXPIRED match={} deadline={} block_time={}",
+                    pair.id,
+                    earliest_deadline,
+                    block_time
+                ));
+            }
+            Ok(_) => {}
+            Err(e) => warn!(
+                "Could not read block time before settling match {} ({}); skipping deadline check",
+                pair.id, e
+            ),
+        }
+
+        // Avoid submitting a tx that is guaranteed to revert due to missing approvals/balances.
+        if let Err(reason) = self.precheck_settlement(client, pair).await {
+            if Self::is_precheck_rpc_unavailable(&reason) {
+                warn!(
+                    "Settlement precheck unavailable for match {} ({}); proceeding with on-chain attempt",
+                    pair.id, reason
+                );
+            } else {
+                return Err(anyhow::anyhow!(reason));
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks `pairs` settling and sends them in one transaction, a multicall when there are
+    /// several. From the solver account the markers get the tx hash before it is sent, so a crash
+    /// mid-send still leaves reconcile something to look up. On failure nothing went out and the
+    /// markers are cleared again.
+    async fn submit_settlement(
+        &self,
+        client: &Arc<StarknetClient>,
+        pairs: &[MatchedPair],
+        trigger: SettlementTrigger,
+    ) -> Result<String> {
+        // Every on-chain path ends here; partial fills must never reach the DarkPool.
+        for pair in pairs {
+            ensure_settleable(pair)?;
+        }
+        self.check_gas_budget(client, pairs).await?;
+        // Durable across restarts: until the marker is cleared, nothing sends this match again.
+        let started_at_unix = chrono::Utc::now().timestamp().max(0) as u64;
+        let mut sent = Ok(());
+        for pair in pairs {
+            sent = self
+                .storage
+                .mark_match_settling(&pair.id, &SettlingMarker { started_at_unix, tx_hash: None, trigger })
+                .await;
+            if sent.is_err() {
+                break;
+            }
+        }
+        let record_hash = |tx_hash: String| -> BoxFuture<'_, Result<()>> {
+            Box::pin(async move {
+                let marker = SettlingMarker { started_at_unix, tx_hash: Some(tx_hash), trigger };
+                for pair in pairs {
+                    self.storage.mark_match_settling(&pair.id, &marker).await?;
+                }
+                Ok(())
+            })
+        };
+        let sent = match (sent, pairs) {
+            (Err(e), _) => Err(e),
+            (Ok(()), [pair]) => client.settle_match(pair, &record_hash).await,
+            (Ok(()), _) => client.settle_matches(pairs, &record_hash).await,
+        };
+        match sent {
+            Ok(tx_hash) => {
+                // Already recorded from the solver account; the relayer only reports its hash now.
+                let marker = SettlingMarker { started_at_unix, tx_hash: Some(tx_hash.clone()), trigger };
+                for pair in pairs {
+                    if let Err(e) = self.storage.mark_match_settling(&pair.id, &marker).await {
+                        warn!("Failed to record settlement tx {} for match {}: {}", tx_hash, pair.id, e);
+                    }
+                    self.send_submitted_callbacks(pair, &tx_hash);
+                }
+                Ok(tx_hash)
+            }
+            Err(e) => {
+                for pair in pairs {
+                    if let Err(clear_err) = self.storage.clear_settling_marker(&pair.id).await {
+                        warn!("Failed to clear settling marker for match {}: {}", pair.id, clear_err);
+                    }
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Hands `tx_hash`, which `submit_settlement` sent, to `reconcile_settling_matches` without
+    /// waiting for it: the settling markers now carry the hash, and the intents become settled once
+    /// it is accepted on L2, or fail if it reverted. One result per pair.
+    async fn confirm_settlement(
+        &self,
+        client: &Arc<StarknetClient>,
+        pairs: &[MatchedPair],
+        tx_hash: String,
+    ) -> Vec<Result<()>> {
+        let budget_nano = self.config.daily_gas_budget_nano;
+        let history = self.config.settlement_history_retention_seconds > 0;
+        if budget_nano.is_some() || history {
+            let match_ids = if history { pairs.iter().map(|p| p.id.clone()).collect() } else { Vec::new() };
+            spawn_fee_tracking(self.storage.clone(), client.clone(), tx_hash.clone(), match_ids, budget_nano);
+        }
+        for pair in pairs {
+            info!("Settlement {} of match {} sent; awaiting acceptance on L2", tx_hash, pair.id);
+        }
+        pairs.iter().map(|_| Ok(())).collect()
+    }
+
+    /// Settles `pairs` in one multicall transaction. A single failing call reverts the whole
+    /// batch, so when the batch can't be sent each pair is settled on its own instead, and the
+    /// broken one fails by itself. One result per pair, in order.
+    async fn settle_batch(&self, client: &Arc<StarknetClient>, pairs: &[MatchedPair]) -> Vec<Result<()>> {
+        let trigger = SettlementTrigger::Auto;
+        let mut results: Vec<Option<Result<()>>> = pairs.iter().map(|_| None).collect();
+        let mut locks = Vec::with_capacity(pairs.len());
+        let mut ready = Vec::with_capacity(pairs.len());
+        for (i, pair) in pairs.iter().enumerate() {
+            match self
+                .storage
+                .try_acquire_match_lock(&pair.id, self.config.settlement_lock_ttl_seconds)
+                .await
+            {
+                Ok(Some(token)) => locks.push((i, token)),
+                Ok(None) => {
+                    results[i] = Some(Err(anyhow::anyhow!("SETTLEMENT_IN_PROGRESS match={}", pair.id)));
+                    continue;
+                }
+                Err(e) => {
+                    results[i] = Some(Err(e));
+                    continue;
+                }
+            }
+            match self.prepare_settlement(client, pair, trigger).await {
+                Ok(()) => ready.push(pair.clone()),
+                Err(e) => results[i] = Some(Err(e)),
+            }
+            self.stamp_liveness();
+        }
+
+        let batch_result = if ready.len() > 1 {
+            let sent = self.submit_settlement(client, &ready, trigger).await;
+            self.stamp_liveness();
+            match sent {
+                Ok(tx_hash) => Some(self.confirm_settlement(client, &ready, tx_hash).await),
+                Err(e) => {
+                    warn!("Batch settlement of {} matches failed; settling them one by one: {}", ready.len(), e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let ready_results = match batch_result {
+            Some(results) => results,
+            None => {
+                let mut results = Vec::with_capacity(ready.len());
+                for pair in &ready {
+                    let pair = std::slice::from_ref(pair);
+                    results.push(match self.submit_settlement(client, pair, trigger).await {
+                        Ok(tx_hash) => self.confirm_settlement(client, pair, tx_hash).await.pop().unwrap_or(Ok(())),
+                        Err(e) => Err(e),
+                    });
+                    self.stamp_liveness();
+                }
+                results
+            }
+        };
+        let mut ready_results = ready_results.into_iter();
+        for slot in results.iter_mut().filter(|slot| slot.is_none()) {
+            *slot = ready_results.next();
+        }
+
+        for (i, token) in locks {
+            if let Err(e) = self.storage.release_match_lock(&pairs[i].id, &token).await {
+                warn!("Failed to release settlement lock for match {}: {}", pairs[i].id, e);
+            }
+        }
+        results
+            .into_iter()
+            .map(|r| r.unwrap_or_else(|| Err(anyhow::anyhow!("settlement not attempted"))))
+            .collect()
+    }
+
+    /// Adds an outcome to the settlement history behind `/v1/settlements`, unless it is disabled.
+    async fn record_history(&self, pair: &MatchedPair, record: SettlementRecord) {
+        let retention_seconds = self.config.settlement_history_retention_seconds;
+        if retention_seconds == 0 {
+            return;
+        }
+        let retain_from_ms = chrono::Utc::now().timestamp_millis() - (retention_seconds as i64).saturating_mul(1000);
+        if let Err(e) = self.storage.record_settlement(pair, &record, retain_from_ms).await {
+            warn!("Failed to record settlement history for match {}: {}", record.match_id, e);
+        }
+    }
+
+    /// Marks both intents settled by `tx_hash`, retires the match and publishes the events.
+    async fn record_settlement(&self, pair: &MatchedPair, tx_hash: String, trigger: SettlementTrigger) -> Result<()> {
+        self.storage.update_intent_status(
+            &pair.intent_a.nullifier,
+            IntentStatus::Settled,
+            Some(pair.intent_b.nullifier.clone()),
+            Some(tx_hash.clone()),
+        ).await?;
+        self.storage.update_intent_status(
+            &pair.intent_b.nullifier,
+            IntentStatus::Settled,
+            Some(pair.intent_a.nullifier.clone()),
+            Some(tx_hash.clone()),
+        ).await?;
+        // Remove from the "matched" set so the retry loop doesn't keep attempting it.
+        self.storage.mark_match_settled(&pair.id).await?;
+        // If this was previously failing (e.g., allowance propagation), clear backoff state.
+        let _ = self.storage.clear_match_retry_state(&pair.id).await;
+        if self.config.match_history_max > 0 {
+            if let Err(e) = self.storage.store_settled_match(pair, &tx_hash, self.config.match_history_max).await {
+                warn!("Failed to add match {} to the trade log: {}", pair.id, e);
+            }
+        }
+        self.record_history(pair, SettlementRecord::new(
+            pair,
+            SettlementOutcome::Settled,
+            trigger,
+            Some(tx_hash.clone()),
+            None,
+        ))
+        .await;
+        if let Err(e) = self.storage.publish_settlement_event(pair, &tx_hash).await {
+            warn!("Failed to publish the settlement of match {} to the settlement feed: {}", pair.id, e);
+        }
+
+        let now = chrono::Utc::now();
+        for nullifier in [&pair.intent_a.nullifier, &pair.intent_b.nullifier] {
+            self.events.publish(SolverEvent::Settled {
+                nullifier: nullifier.clone(),
+                transaction_hash: tx_hash.clone(),
+                timestamp: now,
+            });
+        }
+        self.events.publish(SolverEvent::MatchSettled {
+            match_id: pair.id.clone(),
+            transaction_hash: tx_hash.clone(),
+            timestamp: now,
+        });
+        info!("Match {} settled successfully", pair.id);
+        Ok(())
+    }
+
+    /// `settlement_submitted` to every intent of `pair` with a `callback_url`, once `tx_hash` is sent.
+    /// Nothing without `WEBHOOK_SECRET`.
+    fn send_submitted_callbacks(&self, pair: &MatchedPair, tx_hash: &str) {
+        let Some(notifier) = &self.webhooks else {
+            return;
+        };
+        let now = chrono::Utc::now();
+        for intent in [&pair.intent_a, &pair.intent_b] {
+            if let Some(url) = &intent.callback_url {
+                notifier.send(
+                    url.clone(),
+                    SettlementCallback::SettlementSubmitted {
+                        nullifier: intent.nullifier.clone(),
+                        match_id: pair.id.clone(),
+                        transaction_hash: tx_hash.to_string(),
+                        timestamp: now,
+                    },
+                );
+            }
+        }
+    }
+
+    /// `settlement_confirmed` to every intent of `pair` with a `callback_url`, from the receipt of
+    /// `tx_hash` accepted in `block_number`, with what that intent received. Nothing without
+    /// `WEBHOOK_SECRET`.
+    fn send_confirmed_callbacks(&self, pair: &MatchedPair, tx_hash: &str, block_number: u64, events: &[Event]) {
+        let Some(notifier) = &self.webhooks else {
+            return;
+        };
+        let intents = [&pair.intent_a, &pair.intent_b];
+        if intents.iter().all(|i| i.callback_url.is_none()) {
+            return;
+        }
+        let outputs = match settlement_outputs(pair, events) {
+            Ok(outputs) => outputs,
+            Err(e) => {
+                warn!("Could not decode the outputs of settlement {}: {}", tx_hash, e);
+                return;
+            }
+        };
+        let now = chrono::Utc::now();
+        for intent in intents {
+            if let Some(url) = &intent.callback_url {
+                // The counterparty's amounts are theirs to see, not this callback's.
+                let own = outputs.iter().filter(|o| o.nullifier == intent.nullifier).cloned().collect();
+                notifier.send(
+                    url.clone(),
+                    SettlementCallback::SettlementConfirmed {
+                        nullifier: intent.nullifier.clone(),
+                        match_id: pair.id.clone(),
+                        transaction_hash: tx_hash.to_string(),
+                        block_number,
+                        outputs: own,
+                        timestamp: now,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Placeholder pool address for a token pair, used when the pair's Ekubo pool can't be resolved.
+    fn get_pool_address(&self, token_a: &str, token_b: &str) -> String {
+        // Deterministic mock, not a real pool.
         let parse = |token: &str| -> u64 {
             let raw = token.strip_prefix("0x").unwrap_or(token);
             let part = &raw[..raw.len().min(8)];
@@ -348,16 +1609,417 @@ impl IntentMatcher {
         )
     }
 
+    /// The pair's pool from the Ekubo core at the configured fee tier, cached once resolved.
+    async fn resolve_pool(&self, client: &StarknetClient, token_a: &str, token_b: &str) -> Result<String> {
+        let (a, b) = (token_a.to_lowercase(), token_b.to_lowercase());
+        let key = if a <= b { (a, b) } else { (b, a) };
+        if let Some(pool) = self.ekubo_pools.lock().unwrap().get(&key) {
+            return Ok(pool.clone());
+        }
+        let pool = client
+            .resolve_ekubo_pool(token_a, token_b, self.config.ekubo_pool_fee, self.config.ekubo_tick_spacing)
+            .await?;
+        let pool = format!("0x{:x}", pool);
+        let mut pools = self.ekubo_pools.lock().unwrap();
+        if pools.len() < EKUBO_POOL_CACHE_MAX {
+            pools.insert(key, pool.clone());
+        }
+        Ok(pool)
+    }
+
+    /// `ekubo_pool` for a match on the pair: the resolved pool with `EKUBO_CORE_ADDRESS` set, else
+    /// (or when resolution fails) the `get_pool_address` placeholder.
+    async fn pool_address(&self, token_a: &str, token_b: &str) -> String {
+        if let Some(client) = self.starknet.as_ref().filter(|c| c.resolves_ekubo_pools()) {
+            match self.resolve_pool(client, token_a, token_b).await {
+                Ok(pool) => return pool,
+                Err(e) => warn!(
+                    "Could not resolve the Ekubo pool for {}/{}, using the placeholder address: {}",
+                    token_a, token_b, e
+                ),
+            }
+        }
+        self.get_pool_address(token_a, token_b)
+    }
+
+    /// `sqrt_price_limit` for a match: the pool's current price moved by the match's slippage limit
+    /// against a swap in `intent_a`'s direction. "0" (no limit) without a slippage limit, without
+    /// `EKUBO_CORE_ADDRESS`, or when the price can't be read.
+    async fn sqrt_price_limit_for(&self, intent_a: &Intent, intent_b: &Intent) -> String {
+        let Some(max_bps) =
+            effective_slippage_bps(self.config.max_slippage_bps, intent_a.max_slippage_bps, intent_b.max_slippage_bps)
+        else {
+            return "0".to_string();
+        };
+        let Some(client) = self.starknet.as_ref().filter(|c| c.resolves_ekubo_pools()) else {
+            return "0".to_string();
+        };
+        let (token_in, token_out) = (&intent_a.public_inputs.token_in, &intent_a.public_inputs.token_out);
+        let sqrt_ratio = match client
+            .get_pool_sqrt_price(token_in, token_out, self.config.ekubo_pool_fee, self.config.ekubo_tick_spacing)
+            .await
+        {
+            Ok(ratio) if !ratio.is_zero() => ratio,
+            Ok(_) => return "0".to_string(),
+            Err(e) => {
+                warn!("Could not read the Ekubo price for {}/{}, settling without a price limit: {}", token_in, token_out, e);
+                return "0".to_string();
+            }
+        };
+        let sells_token0 = match (felt_from_hex(token_in), felt_from_hex(token_out)) {
+            (Ok(a), Ok(b)) => a < b,
+            _ => token_in.to_lowercase() < token_out.to_lowercase(),
+        };
+        sqrt_price_limit(&sqrt_ratio, sells_token0, max_bps).to_string()
+    }
+
+    /// Whether the pair has a pool to settle through: an initialized Ekubo pool at the configured
+    /// tier with `EKUBO_CORE_ADDRESS` set, otherwise a contract at the placeholder address.
+    async fn has_pool(&self, client: &StarknetClient, token_a: &str, token_b: &str) -> Result<(bool, String)> {
+        if !client.resolves_ekubo_pools() {
+            let pool = self.get_pool_address(token_a, token_b);
+            return Ok((client.is_deployed(&pool).await?, pool));
+        }
+        match self.resolve_pool(client, token_a, token_b).await {
+            Ok(pool) => Ok((true, pool)),
+            Err(e) if e.to_string().contains("EKUBO_POOL_NOT_INITIALIZED") => {
+                Ok((false, format!("fee={} tick_spacing={}", self.config.ekubo_pool_fee, self.config.ekubo_tick_spacing)))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// With `NO_POOL_POLICY` set, checks (and caches) whether the pair's pool is deployed before it
+    /// is matched, recording pairs without one in the stats' `pair_issues`.
+    async fn pool_route(&self, token_a: &str, token_b: &str) -> PoolRoute {
+        let (Some(policy), Some(client)) = (self.config.no_pool_policy, &self.starknet) else {
+            return PoolRoute::Ekubo;
+        };
+        let (a, b) = (token_a.to_lowercase(), token_b.to_lowercase());
+        let key = if a <= b { (a, b) } else { (b, a) };
+        let cached = self
+            .pool_cache
+            .lock()
+            .unwrap()
+            .get(&key)
+            .filter(|(checked_at, _)| checked_at.elapsed() < POOL_CACHE_TTL)
+            .map(|(_, exists)| *exists);
+
+        let exists = match cached {
+            Some(exists) => exists,
+            None => {
+                let (exists, pool) = match self.has_pool(client, token_a, token_b).await {
+                    Ok(found) => found,
+                    Err(e) => {
+                        warn!("Skipping pair {}/{} this round; pool lookup failed: {}", token_a, token_b, e);
+                        return PoolRoute::Unmatchable;
+                    }
+                };
+                self.pool_cache.lock().unwrap().insert(key, (Instant::now(), exists));
+                let reason = match (exists, policy) {
+                    (true, _) => None,
+                    (false, NoPoolPolicy::Skip) => Some("NO_POOL"),
+                    (false, NoPoolPolicy::Net) => Some("NO_POOL_NETTED"),
+                };
+                if let Some(reason) = reason {
+                    info!("No Ekubo pool for {}/{} at {} ({})", token_a, token_b, pool, reason);
+                }
+                if let Err(e) = self.storage.set_pair_issue(token_a, token_b, reason).await {
+                    warn!("Failed to record pool status for {}/{}: {}", token_a, token_b, e);
+                }
+                exists
+            }
+        };
+        match (exists, policy) {
+            (true, _) => PoolRoute::Ekubo,
+            (false, NoPoolPolicy::Skip) => PoolRoute::Unmatchable,
+            (false, NoPoolPolicy::Net) => PoolRoute::Net,
+        }
+    }
+
+    /// Today's spend against `DAILY_GAS_BUDGET`, or `None` when no budget is configured.
+    pub async fn gas_budget_status(&self) -> Result<Option<GasBudgetStatus>> {
+        let Some(budget_nano) = self.config.daily_gas_budget_nano else {
+            return Ok(None);
+        };
+        let day = utc_day(chrono::Utc::now());
+        let spent_nano = self.storage.get_gas_spend(&day).await?;
+        let overridden = self.storage.is_gas_budget_overridden(&day).await?;
+        Ok(Some(GasBudgetStatus {
+            paused: spent_nano >= budget_nano && !overridden,
+            day,
+            spent_nano,
+            budget_nano,
+            overridden,
+        }))
+    }
+
+    /// Refuses to send `pairs` when their estimated fee would take today's spend past
+    /// `DAILY_GAS_BUDGET`. A fee that can't be estimated counts as nothing, and an unreadable spend
+    /// lets the send go ahead, as in `settlement_paused`.
+    async fn check_gas_budget(&self, client: &StarknetClient, pairs: &[MatchedPair]) -> Result<()> {
+        let status = match self.gas_budget_status().await {
+            Ok(Some(status)) if !status.overridden => status,
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                warn!("Failed to read daily gas spend: {}", e);
+                return Ok(());
+            }
+        };
+        let mut estimated_nano = 0u64;
+        for pair in pairs {
+            match client.estimate_settlement_fee(pair).await {
+                Ok(estimate) => estimated_nano = estimated_nano.saturating_add(fee_to_nano(&estimate.overall_fee)),
+                Err(e) => debug!("No fee estimate for match {} to check the gas budget: {}", pair.id, e),
+            }
+        }
+        if within_gas_budget(status.spent_nano, estimated_nano, status.budget_nano) {
+            return Ok(());
+        }
+        Err(anyhow::anyhow!(
+            "SETTLEMENT_PAUSED: daily gas budget would be exceeded ({} spent + {} estimated of {} nano on {})",
+            status.spent_nano,
+            estimated_nano,
+            status.budget_nano,
+            status.day
+        ))
+    }
+
+    /// Settlement by the solver (auto, retries, confirm) waits while today's budget is used up.
+    /// If the spend can't be read, settlement goes ahead rather than stalling on Redis.
+    async fn settlement_paused(&self) -> bool {
+        match self.gas_budget_status().await {
+            Ok(status) => status.map_or(false, |s| s.paused),
+            Err(e) => {
+                warn!("Failed to read daily gas spend: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Gives up on a match: deadletters it and moves both intents to `Failed` with `reason`. With
+    /// `REQUEUE_FAILED_MATCHES`, pairing failures put both intents back in the book instead, each
+    /// barred from the other for `FAILED_PAIR_COOLDOWN_SECONDS`.
+    async fn fail_match(&self, pair: &MatchedPair, reason: &str, trigger: SettlementTrigger) {
+        let _ = self.storage.mark_match_retry_terminal(&pair.id, reason).await;
+        let _ = self.storage.deadletter_match(&pair.id).await;
+        self.record_history(pair, SettlementRecord::new(
+            pair,
+            SettlementOutcome::Failed,
+            trigger,
+            None,
+            Some(reason.to_string()),
+        ))
+        .await;
+        if let Err(e) = self.storage.publish_failure_event(pair, reason).await {
+            warn!("Failed to publish the failure of match {} to the settlement feed: {}", pair.id, e);
+        }
+        let now = chrono::Utc::now();
+        self.events.publish(SolverEvent::MatchDeadlettered {
+            match_id: pair.id.clone(),
+            reason: reason.to_string(),
+            timestamp: now,
+        });
+        let requeue = self.config.requeue_failed_matches && requeues_intents(reason);
+        let cooldown_until = now + chrono::Duration::seconds(self.config.failed_pair_cooldown_seconds as i64);
+        for (nullifier, counterparty) in [
+            (&pair.intent_a.nullifier, &pair.intent_b.nullifier),
+            (&pair.intent_b.nullifier, &pair.intent_a.nullifier),
+        ] {
+            if requeue {
+                match self.storage.requeue_intent(nullifier, counterparty, cooldown_until).await {
+                    Ok(true) => {
+                        self.events.publish(SolverEvent::Requeued {
+                            nullifier: nullifier.clone(),
+                            match_id: pair.id.clone(),
+                            reason: reason.to_string(),
+                            timestamp: now,
+                        });
+                        continue;
+                    }
+                    // No longer `Matched` (e.g. cancelled meanwhile); leave it as it is.
+                    Ok(false) => continue,
+                    Err(e) => warn!("Failed to requeue intent {} for match {}: {}", log_nullifier(nullifier), pair.id, e),
+                }
+            }
+            match self.storage.mark_intent_failed(nullifier, reason).await {
+                Ok(()) => self.events.publish(SolverEvent::Failed {
+                    nullifier: nullifier.clone(),
+                    reason: reason.to_string(),
+                    timestamp: now,
+                }),
+                Err(e) => warn!("Failed to mark intent {} failed for match {}: {}", log_nullifier(nullifier), pair.id, e),
+            }
+        }
+    }
+
+    /// Resolves settling markers: every sent settlement, and any left by a send whose outcome was
+    /// never seen because the process restarted. A confirmed transaction is recorded as the match's
+    /// settlement and a reverted one fails the match, and so does one still pending after
+    /// `CONFIRM_TIMEOUT_SECONDS`. One the node still can't find after `SETTLING_MARKER_GRACE_SECONDS`
+    /// clears the marker so the retry loop can send the match again. Before re-queueing, both intents are checked on-chain: a match
+    /// the chain already settled keeps its marker until the transaction is reported
+    /// (`POST /v1/matches/:match_id/report-tx`).
+    async fn reconcile_settling_matches(&self) -> Result<()> {
+        let Some(client) = self.starknet.clone() else {
+            return Ok(());
+        };
+        for match_id in self.storage.settling_match_ids().await? {
+            let lock_token = match self
+                .storage
+                .try_acquire_match_lock(&match_id, self.config.settlement_lock_ttl_seconds)
+                .await
+            {
+                Ok(Some(token)) => token,
+                // Held by a settlement being sent right now; its own outcome handling covers it.
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Failed to lock match {} for reconciliation: {}", match_id, e);
+                    continue;
+                }
+            };
+            if let Err(e) = self.reconcile_settling_match(client.as_ref(), &match_id).await {
+                warn!("Could not reconcile in-flight settlement of match {}: {}", match_id, e);
+            }
+            self.stamp_liveness();
+            if let Err(e) = self.storage.release_match_lock(&match_id, &lock_token).await {
+                warn!("Failed to release settlement lock for match {}: {}", match_id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// One marker of `reconcile_settling_matches`; the caller holds the match lock.
+    async fn reconcile_settling_match(&self, receipts: &dyn SettlementReceipts, match_id: &str) -> Result<()> {
+        let Some(marker) = self.storage.get_settling_marker(match_id).await? else {
+            return Ok(());
+        };
+        let Some(pair) = self.storage.get_matched_pair(match_id).await? else {
+            // Settled or discarded since; nothing is waiting on the marker.
+            return self.storage.clear_settling_marker(match_id).await;
+        };
+        let outcome = match &marker.tx_hash {
+            Some(tx_hash) => receipts.transaction_outcome(tx_hash).await?,
+            None => TxOutcome::Unknown,
+        };
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        let waited = now.saturating_sub(marker.started_at_unix);
+        let tx = marker.tx_hash.as_deref().unwrap_or("unknown");
+        let timeout = self.config.confirm_timeout_seconds;
+        match outcome {
+            TxOutcome::Confirmed { block_number, events } => {
+                let tx_hash = marker.tx_hash.clone().unwrap_or_default();
+                let a = self.storage.get_intent(&pair.intent_a.nullifier).await?;
+                if a.and_then(|i| i.settlement_tx_hash).is_some() {
+                    // Recorded before the restart; only the match cleanup was missed.
+                    info!("Match {} was already recorded settled by {}; retiring it", match_id, tx_hash);
+                    return self.storage.mark_match_settled(match_id).await;
+                }
+                info!("In-flight settlement of match {} confirmed: {}", match_id, tx_hash);
+                self.record_settlement(&pair, tx_hash.clone(), marker.trigger).await?;
+                self.send_confirmed_callbacks(&pair, &tx_hash, block_number, &events);
+                return Ok(());
+            }
+            TxOutcome::Pending if timeout == 0 || waited < timeout => {
+                debug!("Settlement {} of match {} is still pending ({}s); waiting", tx, match_id, waited);
+                return Ok(());
+            }
+            TxOutcome::Pending => {
+                warn!("Settlement {} of match {} still pending after {}s; failing the match", tx, match_id, waited);
+                let _ = self
+                    .storage
+                    .record_match_error(match_id, &format!("CONFIRM_TIMEOUT tx={} waited={}s", tx, waited))
+                    .await;
+                self.fail_match(&pair, "CONFIRM_TIMEOUT", marker.trigger).await;
+                return self.storage.clear_settling_marker(match_id).await;
+            }
+            TxOutcome::Unknown if waited < self.config.settling_marker_grace_seconds => {
+                debug!("Settlement {} of match {} not found yet ({}s); waiting", tx, match_id, waited);
+                return Ok(());
+            }
+            TxOutcome::Reverted(reason) => {
+                warn!("Settlement {} of match {} reverted: {}", tx, match_id, reason);
+                let _ = self
+                    .storage
+                    .record_match_error(match_id, &format!("TX_REVERTED tx={} reason={}", tx, reason))
+                    .await;
+                self.fail_match(&pair, &format!("TX_REVERTED: {}", reason), marker.trigger).await;
+                return self.storage.clear_settling_marker(match_id).await;
+            }
+            TxOutcome::Unknown => {
+                warn!("Settlement {} of match {} not found after {}s; treating it as dropped", tx, match_id, waited);
+            }
+        }
+
+        for nullifier in [&pair.intent_a.nullifier, &pair.intent_b.nullifier] {
+            if receipts.is_intent_settled(nullifier).await? {
+                error!(
+                    "Match {} is settled on-chain by a transaction other than {}; report it to record the settlement",
+                    match_id, tx
+                );
+                return Ok(());
+            }
+        }
+        info!("Returning match {} to the retry loop", match_id);
+        self.storage.clear_settling_marker(match_id).await
+    }
+
     async fn retry_unsettled_matches(&self) -> Result<()> {
         if self.starknet.is_none() {
             return Ok(());
         }
+        if self.settlement_paused().await {
+            debug!("Skipping settlement retries: daily gas budget reached");
+            return Ok(());
+        }
 
-        let pairs = self.storage.get_unsettled_matches().await?;
+        let mut pairs = self.storage.get_unsettled_matches().await?;
+        pairs.retain(|pair| pair.fill.is_none());
+        if self.settle_mode == SettleMode::ManualWithRetry {
+            let mut confirmed = Vec::with_capacity(pairs.len());
+            for pair in pairs {
+                if self.storage.is_match_confirmed(&pair.id).await? {
+                    confirmed.push(pair);
+                }
+            }
+            pairs = confirmed;
+        }
         if pairs.is_empty() {
             return Ok(());
         }
 
+        // SETTLEMENT_ORDER; deadlines break profit ties and the match id keeps the order stable.
+        let earliest_deadline = |pair: &MatchedPair| {
+            pair.intent_a
+                .public_inputs
+                .deadline
+                .min(pair.intent_b.public_inputs.deadline)
+        };
+        match self.config.settlement_order {
+            SettlementOrder::Urgency => pairs.sort_by(|a, b| {
+                earliest_deadline(a)
+                    .cmp(&earliest_deadline(b))
+                    .then_with(|| a.id.cmp(&b.id))
+            }),
+            SettlementOrder::Profit => {
+                let mut ranked: Vec<(BigUint, MatchedPair)> = pairs
+                    .into_iter()
+                    .map(|pair| {
+                        let (amount_a_in, min_a_out) = Self::amounts_in_base_units(&pair.intent_a).unwrap_or_default();
+                        let (amount_b_in, min_b_out) = Self::amounts_in_base_units(&pair.intent_b).unwrap_or_default();
+                        (match_surplus(&amount_a_in, &min_a_out, &amount_b_in, &min_b_out), pair)
+                    })
+                    .collect();
+                ranked.sort_by(|(surplus_a, a), (surplus_b, b)| {
+                    surplus_b
+                        .cmp(surplus_a)
+                        .then_with(|| earliest_deadline(a).cmp(&earliest_deadline(b)))
+                        .then_with(|| a.id.cmp(&b.id))
+                });
+                pairs = ranked.into_iter().map(|(_, pair)| pair).collect();
+            }
+        }
+
         debug!("Retrying settlement for {} matched pairs", pairs.len());
 
         let now = std::time::SystemTime::now()
@@ -365,6 +2027,75 @@ impl IntentMatcher {
             .unwrap()
             .as_secs();
 
+        let gas_too_high = self.gas_above_ceiling().await;
+
+        let mut eligible = Vec::with_capacity(pairs.len());
+        for pair in pairs {
+            let mut deferred = false;
+            if let Ok(Some(state)) = self.storage.get_match_retry_state(&pair.id).await {
+                if state.terminal {
+                    debug!("Skipping retry for match {} (terminal retry state)", pair.id);
+                    continue;
+                }
+                if state.next_retry_at_unix > now {
+                    debug!(
+                        "Skipping retry for match {} until {} (failures={})",
+                        pair.id, state.next_retry_at_unix, state.failures
+                    );
+                    continue;
+                }
+                deferred = state.deferred_reason.is_some();
+            }
+
+            // Expensive gas only holds back matches with time to spare; near-expiry ones go anyway.
+            let urgent = earliest_deadline(&pair) <= now.saturating_add(self.config.settle_gas_urgent_seconds);
+            if gas_too_high && !urgent {
+                debug!("Deferring settlement of match {}: gas price above SETTLE_GAS_CEILING", pair.id);
+                if !deferred {
+                    let _ = self.storage.set_match_deferral(&pair.id, Some(GAS_DEFERRAL_REASON)).await;
+                }
+                continue;
+            }
+            if deferred {
+                let _ = self.storage.set_match_deferral(&pair.id, None).await;
+            }
+
+            eligible.push(pair);
+        }
+
+        // SETTLEMENT_BATCH_SIZE: several matches per transaction where the client can batch.
+        let batch_size = match &self.starknet {
+            Some(client) if client.batches_settlements() => self.config.settlement_batch_size.max(1),
+            _ => 1,
+        };
+        for batch in settlement_batches(eligible, batch_size) {
+            // `settle_match` already runs the precheck, so this is safe to attempt.
+            let results = match (&self.starknet, batch.as_slice()) {
+                (Some(client), [_, _, ..]) => self.settle_batch(client, &batch).await,
+                _ => {
+                    let mut results = Vec::with_capacity(batch.len());
+                    for pair in &batch {
+                        results.push(self.settle_match(pair.clone(), SettlementTrigger::Auto).await);
+                    }
+                    results
+                }
+            };
+            for (pair, result) in batch.iter().zip(results) {
+                match result {
+                    Ok(()) => {
+                        let _ = self.storage.clear_match_retry_state(&pair.id).await;
+                    }
+                    Err(e) => self.note_retry_failure(pair, e.to_string(), now).await,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Retry bookkeeping for a failed settlement attempt of `pair`: fail it for good, back off, or
+    /// leave it for the next pass, depending on `msg`.
+    async fn note_retry_failure(&self, pair: &MatchedPair, msg: String, now: u64) {
         let is_funding_error = |msg: &str| {
             msg.contains("INSUFFICIENT_BALANCE") || msg.contains("INSUFFICIENT_ALLOWANCE")
         };
@@ -373,6 +2104,13 @@ impl IntentMatcher {
                 || msg.contains("INVALID_PROOF")
                 || msg.contains("INVALID_PROOFS")
         };
+        // Simulated reverts that retrying cannot fix: an intent is no longer pending on-chain
+        // (settled/cancelled elsewhere) or the pair fails the contract's compatibility checks.
+        let is_terminal_revert = |msg: &str| -> Option<&'static str> {
+            ["INTENT_NOT_PENDING", "INCOMPATIBLE_INTENTS"]
+                .into_iter()
+                .find(|code| msg.contains(code))
+        };
 
         // Backoff after 3 consecutive failures:
         // 3 -> 5m, 4 -> 10m, 5 -> 20m ... capped at 1h.
@@ -389,72 +2127,79 @@ impl IntentMatcher {
             (60u64).saturating_mul(1u64 << exp).min(3600)
         };
 
-        for pair in pairs {
-            if let Ok(Some(state)) = self.storage.get_match_retry_state(&pair.id).await {
-                if state.terminal {
-                    debug!("Skipping retry for match {} (terminal retry state)", pair.id);
-                    continue;
-                }
-                if state.next_retry_at_unix > now {
-                    debug!(
-                        "Skipping retry for match {} until {} (failures={})",
-                        pair.id, state.next_retry_at_unix, state.failures
-                    );
-                    continue;
-                }
-            }
+        if msg.contains("DEADLINE_EXPIRED") {
+            // `settle_match` already failed the match.
+            return;
+        }
+        if let Some(code) = is_terminal_revert(&msg) {
+            self.fail_match(pair, code, SettlementTrigger::Auto).await;
+            warn!("Stopped retrying match {}: settlement simulation reverted with {}", pair.id, code);
+            return;
+        }
+        // Common case: allowances haven't updated yet. Keep it in the set for the next retry.
+        if is_funding_error(&msg) || is_invalid_proof_error(&msg) {
+            let current_failures = self
+                .storage
+                .get_match_retry_state(&pair.id)
+                .await
+                .ok()
+                .flatten()
+                .map(|s| s.failures)
+                .unwrap_or(0);
+            let next_failures = current_failures + 1;
 
-            // `settle_match` already runs the precheck, so this is safe to attempt.
-            if let Err(e) = self.settle_match(pair.clone()).await {
-                // Common case: allowances haven't updated yet. Keep it in the set for the next retry.
-                let msg = e.to_string();
-                if is_funding_error(&msg) || is_invalid_proof_error(&msg) {
-                    let current_failures = self
-                        .storage
-                        .get_match_retry_state(&pair.id)
-                        .await
-                        .ok()
-                        .flatten()
-                        .map(|s| s.failures)
-                        .unwrap_or(0);
-                    let next_failures = current_failures + 1;
-
-                    if is_invalid_proof_error(&msg)
-                        && next_failures >= self.config.max_invalid_proof_retries
-                    {
-                        let _ = self
-                            .storage
-                            .mark_match_retry_terminal(&pair.id, "INVALID_PROOFS")
-                            .await;
-                        let _ = self.storage.mark_match_settled(&pair.id).await;
-                        warn!(
-                            "Stopped retrying match {} after {} invalid-proof failures",
-                            pair.id, next_failures
-                        );
-                        continue;
-                    }
+            if is_invalid_proof_error(&msg)
+                && next_failures >= self.config.max_invalid_proof_retries
+            {
+                self.fail_match(pair, "INVALID_PROOFS", SettlementTrigger::Auto).await;
+                warn!(
+                    "Stopped retrying match {} after {} invalid-proof failures",
+                    pair.id, next_failures
+                );
+                return;
+            }
 
-                    let backoff = if is_invalid_proof_error(&msg) {
-                        compute_invalid_proof_backoff_secs(next_failures)
-                    } else {
-                        compute_backoff_secs(next_failures)
-                    };
-                    let next_retry_at_unix = now.saturating_add(backoff);
-                    let _ = self.storage.bump_match_retry_state(&pair.id, next_retry_at_unix).await;
-                    if backoff > 0 {
-                        debug!(
-                            "Backoff enabled for match {} after {} failures; next retry in {}s",
-                            pair.id, next_failures, backoff
-                        );
-                    }
-                }
-                debug!("Retry settlement skipped/failed: {}", msg);
+            let backoff = if is_invalid_proof_error(&msg) {
+                compute_invalid_proof_backoff_secs(next_failures)
             } else {
-                let _ = self.storage.clear_match_retry_state(&pair.id).await;
+                compute_backoff_secs(next_failures)
+            };
+            let next_retry_at_unix = now.saturating_add(backoff);
+            let _ = self
+                .storage
+                .bump_match_retry_state(&pair.id, next_retry_at_unix, &msg)
+                .await;
+            if backoff > 0 {
+                debug!(
+                    "Backoff enabled for match {} after {} failures; next retry in {}s",
+                    pair.id, next_failures, backoff
+                );
             }
+        } else if !msg.contains("SETTLEMENT_IN_PROGRESS") {
+            let _ = self.storage.record_match_error(&pair.id, &msg).await;
         }
+        debug!("Retry settlement skipped/failed: {}", msg);
+    }
 
-        Ok(())
+    /// Whether the L1 gas price is above `SETTLE_GAS_CEILING`. Unset ceiling or an unreadable
+    /// price never defers.
+    async fn gas_above_ceiling(&self) -> bool {
+        let (Some(ceiling_gwei), Some(client)) = (self.config.settle_gas_ceiling_gwei, &self.starknet) else {
+            return false;
+        };
+        match client.l1_gas_price().await {
+            Ok(price) => {
+                let ceiling = BigUint::from(ceiling_gwei) * BigUint::from(1_000_000_000u64);
+                if price > ceiling {
+                    debug!("L1 gas price {} is above SETTLE_GAS_CEILING ({} gwei)", price, ceiling_gwei);
+                }
+                price > ceiling
+            }
+            Err(e) => {
+                warn!("Could not read gas price; not deferring settlements: {}", e);
+                false
+            }
+        }
     }
 
     async fn precheck_settlement(&self, client: &Arc<StarknetClient>, pair: &MatchedPair) -> Result<(), String> {
@@ -467,8 +2212,8 @@ impl IntentMatcher {
 
         let a_decimals = token_decimals_for(&a.token_in);
         let b_decimals = token_decimals_for(&b.token_in);
-        let a_required = parse_amount_to_base_units(&a.amount_in, a_decimals).map_err(|e| e.to_string())?;
-        let b_required = parse_amount_to_base_units(&b.amount_in, b_decimals).map_err(|e| e.to_string())?;
+        let a_required = TokenAmount::parse(&a.amount_in, a_decimals).map_err(|e| e.to_string())?.into_base_units();
+        let b_required = TokenAmount::parse(&b.amount_in, b_decimals).map_err(|e| e.to_string())?.into_base_units();
 
         let a_bal = client.erc20_balance_of(&a.token_in, &a.user).await.map_err(|e| e.to_string())?;
         let a_allow = client.erc20_allowance(&a.token_in, &a.user, spender).await.map_err(|e| e.to_string())?;
@@ -503,3 +2248,1018 @@ impl IntentMatcher {
         Ok(())
     }
 }
+
+/// How long to wait for a settlement receipt before the fee goes uncounted.
+const GAS_RECEIPT_POLL_ATTEMPTS: u32 = 40;
+const GAS_RECEIPT_POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+fn utc_day(now: chrono::DateTime<chrono::Utc>) -> String {
+    now.format("%Y-%m-%d").to_string()
+}
+
+/// A fee in fee-token base units (wei / fri) as 10^-9 units, rounded up.
+fn fee_to_nano(fee: &BigUint) -> u64 {
+    let nano: BigUint = (fee + 999_999_999u32) / 1_000_000_000u32;
+    nano.to_u64().unwrap_or(u64::MAX)
+}
+
+/// Whether a settlement estimated at `estimated_nano` still fits today's budget.
+fn within_gas_budget(spent_nano: u64, estimated_nano: u64, budget_nano: u64) -> bool {
+    spent_nano.saturating_add(estimated_nano) <= budget_nano
+}
+
+/// What each participant of `pair` receives in `events` (a simulation or a receipt), in token units.
+fn settlement_outputs(pair: &MatchedPair, events: &[Event]) -> Result<Vec<SettlementOutput>> {
+    [&pair.intent_a, &pair.intent_b]
+        .into_iter()
+        .map(|intent| {
+            let inputs = &intent.public_inputs;
+            let received = transfers_to(events, felt_from_hex(&inputs.token_out)?, felt_from_hex(&inputs.user)?);
+            let (_, min_out) = IntentMatcher::amounts_in_base_units(intent)
+                .ok_or_else(|| anyhow::anyhow!("INVALID_AMOUNT: intent {}", intent.nullifier))?;
+            let decimals = token_decimals_for(&inputs.token_out);
+            Ok(SettlementOutput {
+                nullifier: intent.nullifier.clone(),
+                user: inputs.user.clone(),
+                token: inputs.token_out.clone(),
+                shortfall: received < min_out,
+                amount: TokenAmount::from_base_units(received, decimals).to_string(),
+                min_amount_out: TokenAmount::from_base_units(min_out, decimals).to_string(),
+            })
+        })
+        .collect()
+}
+
+/// `match_surplus` of `pair` in token units of intent A's `token_in`; 0 when an amount doesn't parse.
+fn expected_profit(pair: &MatchedPair) -> f64 {
+    let (Some((amount_a_in, min_a_out)), Some((amount_b_in, min_b_out))) = (
+        IntentMatcher::amounts_in_base_units(&pair.intent_a),
+        IntentMatcher::amounts_in_base_units(&pair.intent_b),
+    ) else {
+        return 0.0;
+    };
+    let surplus = match_surplus(&amount_a_in, &min_a_out, &amount_b_in, &min_b_out);
+    let decimals = token_decimals_for(&pair.intent_a.public_inputs.token_in);
+    TokenAmount::from_base_units(surplus, decimals).to_string().parse().unwrap_or(0.0)
+}
+
+/// A match's surplus in base units of intent A's input token: what A gives beyond B's minimum, plus
+/// what B gives beyond A's minimum converted at the match's own rate (`amount_a_in / amount_b_in`).
+fn match_surplus(amount_a_in: &BigUint, min_a_out: &BigUint, amount_b_in: &BigUint, min_b_out: &BigUint) -> BigUint {
+    let zero = BigUint::from(0u32);
+    let surplus_a = if amount_a_in > min_b_out { amount_a_in - min_b_out } else { zero.clone() };
+    let surplus_b = if amount_b_in > min_a_out { amount_b_in - min_a_out } else { zero.clone() };
+    if *amount_b_in == zero {
+        return surplus_a;
+    }
+    surplus_a + surplus_b * amount_a_in / amount_b_in
+}
+
+/// Groups `pairs` into multicall batches of up to `size`, keeping their order as far as possible.
+/// Matches that share a user go in different batches: their approvals and balances are prechecked
+/// one match at a time, and the receipt's transfers couldn't be told apart.
+fn settlement_batches(pairs: Vec<MatchedPair>, size: usize) -> Vec<Vec<MatchedPair>> {
+    let users = |pair: &MatchedPair| [pair.intent_a.public_inputs.user.clone(), pair.intent_b.public_inputs.user.clone()];
+    let mut batches: Vec<Vec<MatchedPair>> = Vec::new();
+    for pair in pairs {
+        let shares_user = |batch: &Vec<MatchedPair>| {
+            batch
+                .iter()
+                .flat_map(users)
+                .any(|u| users(&pair).iter().any(|v| same_address(&u, v)))
+        };
+        match batches.iter_mut().find(|b| b.len() < size.max(1) && !shares_user(b)) {
+            Some(batch) => batch.push(pair),
+            None => batches.push(vec![pair]),
+        }
+    }
+    batches
+}
+
+/// Waits for the receipt of settlement `tx_hash`, then adds its fee to today's `DAILY_GAS_BUDGET`
+/// spend and/or to the settlement history records of `match_ids`. Runs off the settlement path so
+/// settling never waits for inclusion.
+fn spawn_fee_tracking(
+    storage: Arc<RedisStorage>,
+    client: Arc<StarknetClient>,
+    tx_hash: String,
+    match_ids: Vec<String>,
+    budget_nano: Option<u64>,
+) {
+    tokio::spawn(async move {
+        for _ in 0..GAS_RECEIPT_POLL_ATTEMPTS {
+            tokio::time::sleep(GAS_RECEIPT_POLL_INTERVAL).await;
+            let fee = match client.transaction_fee(&tx_hash).await {
+                Ok(Some(fee)) => fee,
+                Ok(None) => continue,
+                Err(e) => {
+                    debug!("Receipt for {} not available yet: {}", tx_hash, e);
+                    continue;
+                }
+            };
+            // A multicall's fee is shared evenly between the matches it settled.
+            let share = &fee / BigUint::from(match_ids.len().max(1));
+            for match_id in &match_ids {
+                if let Err(e) = storage.set_settlement_fee(match_id, &share.to_string()).await {
+                    warn!("Failed to record fee of settlement {} in history: {}", tx_hash, e);
+                }
+            }
+            let Some(budget_nano) = budget_nano else {
+                return;
+            };
+            let day = utc_day(chrono::Utc::now());
+            match storage.add_gas_spend(&day, fee_to_nano(&fee)).await {
+                Ok(total) if total >= budget_nano => warn!(
+                    "Daily gas budget reached ({} of {} nano on {}); settlement paused until UTC rollover",
+                    total, budget_nano, day
+                ),
+                Ok(total) => debug!("Settlement {} fee {}; {} of {} nano spent on {}", tx_hash, fee, total, budget_nano, day),
+                Err(e) => warn!("Failed to record fee of settlement {}: {}", tx_hash, e),
+            }
+            return;
+        }
+        warn!("No receipt for settlement {}; its fee is not recorded", tx_hash);
+    });
+}
+
+/// Resolves `policy`'s maker/taker shares onto the pair's sides; the earlier intent is the maker
+/// (`intent_a` on a tie).
+fn surplus_distribution(
+    policy: SurplusPolicy,
+    a_created_at: chrono::DateTime<chrono::Utc>,
+    b_created_at: chrono::DateTime<chrono::Utc>,
+) -> SurplusDistribution {
+    let (maker_bps, taker_bps, solver_bps) = policy.shares_bps();
+    let (intent_a_bps, intent_b_bps) = if a_created_at <= b_created_at {
+        (maker_bps, taker_bps)
+    } else {
+        (taker_bps, maker_bps)
+    };
+    SurplusDistribution {
+        policy,
+        intent_a_bps,
+        intent_b_bps,
+        solver_bps,
+    }
+}
+
+/// How much of a taker's `amount_in` the book would take, what it would pay out for it and how many
+/// resting intents that takes. Base units.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BookWalk {
+    pub filled_in: BigUint,
+    pub amount_out: BigUint,
+    pub counterparties: usize,
+}
+
+/// Fills `amount_in` from `levels`, each a resting intent's `(amount_in, min_amount_out)`: what it
+/// offers of the taker's output token and what it asks of the taker's input token. Best price for
+/// the taker first, ties in the given order. A whole level is taken while the rest of `amount_in`
+/// covers its ask, and levels asking more are skipped; with `partial_fills` the first such level
+/// is instead filled pro rata (rounded down) and the walk ends there.
+pub fn walk_book(amount_in: &BigUint, levels: &[(BigUint, BigUint)], partial_fills: bool) -> BookWalk {
+    let mut levels: Vec<&(BigUint, BigUint)> = levels.iter().filter(|(_, ask)| *ask > BigUint::zero()).collect();
+    // offer_a / ask_a > offer_b / ask_b, compared without dividing.
+    levels.sort_by(|(offer_a, ask_a), (offer_b, ask_b)| (offer_b * ask_a).cmp(&(offer_a * ask_b)));
+
+    let mut walk = BookWalk::default();
+    let mut remaining = amount_in.clone();
+    for (offer, ask) in levels {
+        if remaining.is_zero() {
+            break;
+        }
+        if *ask <= remaining {
+            remaining -= ask;
+            walk.filled_in += ask;
+            walk.amount_out += offer;
+            walk.counterparties += 1;
+        } else if partial_fills {
+            let out = offer * &remaining / ask;
+            if !out.is_zero() {
+                walk.filled_in += &remaining;
+                walk.amount_out += out;
+                walk.counterparties += 1;
+            }
+            break;
+        }
+    }
+    walk
+}
+
+/// `walk` as a quote may show it: empty unless it spreads over at least `min_counterparties`
+/// resting intents, so no quote sizes a single one, and with both totals rounded down to
+/// `significant_digits` (0 = exact).
+pub fn coarsen_quote(walk: BookWalk, min_counterparties: usize, significant_digits: u32) -> BookWalk {
+    if walk.counterparties < min_counterparties.max(1) {
+        return BookWalk::default();
+    }
+    BookWalk {
+        filled_in: round_down_significant(&walk.filled_in, significant_digits),
+        amount_out: round_down_significant(&walk.amount_out, significant_digits),
+        counterparties: walk.counterparties,
+    }
+}
+
+fn round_down_significant(value: &BigUint, digits: u32) -> BigUint {
+    let len = value.to_str_radix(10).len() as u32;
+    if digits == 0 || len <= digits {
+        return value.clone();
+    }
+    let unit = BigUint::from(10u8).pow(len - digits);
+    value / &unit * unit
+}
+
+/// Whether a match that failed with `reason` may put its intents back in the book. Not when the
+/// failure is about one of the intents itself: no longer pending on-chain, a bad proof, a deadline
+/// too close to settle, or a settlement that reverted on-chain. Nor when the settlement was still
+/// pending at `CONFIRM_TIMEOUT_SECONDS`, since it may yet land.
+fn requeues_intents(reason: &str) -> bool {
+    !["INTENT_NOT_PENDING", "INVALID_PROOFS", "DEADLINE_EXPIRED", "CONFIRM_TIMEOUT"].contains(&reason)
+        && !reason.starts_with("TX_REVERTED")
+}
+
+/// `trigger_price` scaled by `10^TRIGGER_PRICE_DECIMALS`, or `None` unless it is a positive decimal.
+pub fn parse_trigger_price(price: &str) -> Option<BigUint> {
+    let price = price.trim();
+    if price.starts_with("0x") || price.starts_with("0X") {
+        return None;
+    }
+    TokenAmount::parse(price, TRIGGER_PRICE_DECIMALS)
+        .ok()
+        .map(|p| p.base_units().clone())
+        .filter(|p| !p.is_zero())
+}
+
+/// The oracle price `price_raw / 10^price_decimals` has reached `trigger` (from
+/// `parse_trigger_price`) in `direction`. Cross-multiplied, so the comparison is exact.
+pub fn trigger_met(price_raw: &BigUint, price_decimals: u32, trigger: &BigUint, direction: TriggerDirection) -> bool {
+    let price = price_raw * BigUint::from(10u32).pow(TRIGGER_PRICE_DECIMALS);
+    let trigger = trigger * BigUint::from(10u32).pow(price_decimals);
+    match direction {
+        TriggerDirection::Above => price >= trigger,
+        TriggerDirection::Below => price <= trigger,
+    }
+}
+
+/// `base_units` of a token with `decimals`, at `rate` (token/USD), is worth at least `min_usd`.
+/// Only the threshold goes through a float, rounded up to a whole micro-dollar.
+fn worth_at_least(base_units: &BigUint, decimals: u32, rate: &CrossRate, min_usd: f64) -> bool {
+    let min_micro_usd = BigUint::from_f64((min_usd * 1e6).ceil()).unwrap_or_default();
+    usd_value(base_units, decimals, rate, 6).is_some_and(|value| value >= min_micro_usd)
+}
+
+/// `PARTIAL_FILL_NOT_SETTLEABLE` for a partial fill: the DarkPool settles whole intents against
+/// their proofs, so these matches stay off-chain until the contract can settle part of one.
+fn ensure_settleable(pair: &MatchedPair) -> Result<()> {
+    if pair.fill.is_some() {
+        return Err(anyhow::anyhow!(
+            "PARTIAL_FILL_NOT_SETTLEABLE: the DarkPool settles whole intents only (match {})",
+            pair.id
+        ));
+    }
+    Ok(())
+}
+
+/// Base units of a partial fill: the larger order trades `amount_in` (exactly what the smaller
+/// one asks for) and keeps `residual_in`, each side of its `min_amount_out` scaled pro rata.
+#[derive(Debug, PartialEq, Eq)]
+struct FillAmounts {
+    amount_in: BigUint,
+    min_amount_out: BigUint,
+    residual_in: BigUint,
+    residual_min_out: BigUint,
+}
+
+/// `None` unless the larger order offers more than the smaller one wants and the smaller one pays
+/// at least the larger one's limit price for it. Minimums round up, in the larger order's favour.
+fn partial_fill_amounts(
+    large_in: &BigUint,
+    large_min_out: &BigUint,
+    small_in: &BigUint,
+    small_min_out: &BigUint,
+) -> Option<FillAmounts> {
+    if small_min_out.is_zero() || large_in <= small_min_out {
+        return None;
+    }
+    let pro_rata = |part: &BigUint| (large_min_out * part + large_in - 1u8) / large_in;
+    let amount_in = small_min_out.clone();
+    let min_amount_out = pro_rata(&amount_in);
+    if *small_in < min_amount_out {
+        return None;
+    }
+    let residual_in = large_in - &amount_in;
+    let residual_min_out = pro_rata(&residual_in);
+    Some(FillAmounts {
+        amount_in,
+        min_amount_out,
+        residual_in,
+        residual_min_out,
+    })
+}
+
+/// `fill` of `large` in `large`'s token units, with the residual's nullifier.
+fn partial_fill(large: &Intent, fill: FillAmounts) -> PartialFill {
+    let in_decimals = token_decimals_for(&large.public_inputs.token_in);
+    let out_decimals = token_decimals_for(&large.public_inputs.token_out);
+    let units = |amount: BigUint, decimals: u32| TokenAmount::from_base_units(amount, decimals).to_string();
+    PartialFill {
+        nullifier: large.nullifier.clone(),
+        residual_nullifier: residual_nullifier(&large.nullifier, &fill.residual_in),
+        amount_in: units(fill.amount_in, in_decimals),
+        min_amount_out: units(fill.min_amount_out, out_decimals),
+        residual_amount_in: units(fill.residual_in, in_decimals),
+        residual_min_amount_out: units(fill.residual_min_out, out_decimals),
+    }
+}
+
+/// Deterministic nullifier for the residual of `parent` keeping `residual_in` base units: keccak256
+/// of both, masked to 250 bits so it is a valid felt.
+fn residual_nullifier(parent: &str, residual_in: &BigUint) -> String {
+    let mut hash = keccak256(format!("{}:residual:{}", parent.trim().to_ascii_lowercase(), residual_in).as_bytes());
+    hash[0] &= 0x03;
+    format!("0x{}", hex::encode(hash))
+}
+
+/// The pending remainder of `parent` after `fill`: same user, tokens, deadline and expiry, with the
+/// residual amounts. It carries the parent's proof, which doesn't cover the new amounts.
+fn residual_intent(parent: &Intent, fill: &PartialFill) -> Intent {
+    let mut public_inputs = parent.public_inputs.clone();
+    public_inputs.amount_in = fill.residual_amount_in.clone();
+    public_inputs.min_amount_out = fill.residual_min_amount_out.clone();
+    let mut residual = Intent::new(
+        parent.intent_hash.clone(),
+        fill.residual_nullifier.clone(),
+        parent.proof_data.clone(),
+        parent.proof_public_inputs.clone(),
+        public_inputs,
+        parent.encrypted_details.clone(),
+        parent.expires_at,
+    );
+    residual.activate_at = parent.activate_at;
+    residual.max_slippage_bps = parent.max_slippage_bps;
+    residual.trigger_price = parent.trigger_price.clone();
+    residual.trigger_direction = parent.trigger_direction;
+    residual.triggered_at = parent.triggered_at;
+    residual.residual_of = Some(parent.nullifier.clone());
+    residual
+}
+
+/// Ids of the `rings` not in `reported`, which then holds exactly `rings`, so a ring that leaves the
+/// book and comes back later is reported again.
+fn newly_found_rings<'a>(reported: &mut HashSet<Vec<String>>, rings: &'a [MatchedGroup]) -> Vec<&'a str> {
+    let mut current = HashSet::with_capacity(rings.len());
+    let mut new = Vec::new();
+    for ring in rings {
+        let mut members: Vec<String> = ring.intents.iter().map(|i| i.nullifier.clone()).collect();
+        members.sort();
+        if !reported.contains(&members) {
+            new.push(ring.id.as_str());
+        }
+        current.insert(members);
+    }
+    *reported = current;
+    new
+}
+
+/// See `IntentMatcher::find_ring_matches`. For an intent giving X for Y, rings are closed through
+/// one giving Y for some Z and one giving Z for X.
+fn find_rings(pending: &[Intent]) -> Vec<MatchedGroup> {
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    let candidates: Vec<(&Intent, BigUint, BigUint)> = pending
+        .iter()
+        .filter(|i| {
+            i.can_match()
+                && i.public_inputs.deadline >= now
+                && i.public_inputs.token_in != i.public_inputs.token_out
+        })
+        .filter_map(|i| IntentMatcher::amounts_in_base_units(i).map(|(amount_in, min_out)| (i, amount_in, min_out)))
+        .collect();
+    let mut by_token_in: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (idx, (intent, _, _)) in candidates.iter().enumerate() {
+        by_token_in.entry(intent.public_inputs.token_in.as_str()).or_default().push(idx);
+    }
+    // `to` may pay `from`: different users, no cooldown between them, and `to` gives enough.
+    let pays = |from: usize, to: usize| {
+        let ((a, _, a_min_out), (b, b_in, _)) = (&candidates[from], &candidates[to]);
+        a.public_inputs.user != b.public_inputs.user
+            && !a.failed_recently_with(&b.nullifier)
+            && !b.failed_recently_with(&a.nullifier)
+            && b_in >= a_min_out
+    };
+
+    let mut used = HashSet::new();
+    let mut groups = Vec::new();
+    for a in 0..candidates.len() {
+        if used.contains(&a) {
+            continue;
+        }
+        let (x, y) = (
+            candidates[a].0.public_inputs.token_in.as_str(),
+            candidates[a].0.public_inputs.token_out.as_str(),
+        );
+        let ring = by_token_in.get(y).into_iter().flatten().find_map(|&b| {
+            let z = candidates[b].0.public_inputs.token_out.as_str();
+            if used.contains(&b) || z == x || z == y || !pays(a, b) {
+                return None;
+            }
+            let c = by_token_in.get(z).into_iter().flatten().copied().find(|&c| {
+                !used.contains(&c) && candidates[c].0.public_inputs.token_out == x && pays(b, c) && pays(c, a)
+            })?;
+            Some((b, c))
+        });
+        if let Some((b, c)) = ring {
+            used.extend([a, b, c]);
+            groups.push(MatchedGroup::new(vec![
+                candidates[a].0.clone(),
+                candidates[b].0.clone(),
+                candidates[c].0.clone(),
+            ]));
+        }
+    }
+    groups
+}
+
+/// The tightest of the global limit (0 = off) and the two intents' own limits, which can only
+/// tighten it; `None` when there is no limit at all.
+fn effective_slippage_bps(global: u16, a: Option<u16>, b: Option<u16>) -> Option<u16> {
+    [(global > 0).then_some(global), a, b].into_iter().flatten().min()
+}
+
+/// Ekubo's bounds on `sqrt_ratio`; a limit outside them is refused by the core.
+const EKUBO_MIN_SQRT_RATIO: u128 = 18447191164202170524;
+const EKUBO_MAX_SQRT_RATIO: &str = "6277100250585753475930931601400621808602321654880405518632";
+
+/// Price limit for a swap through an Ekubo pool at `sqrt_ratio`, `max_bps` away from it.
+///
+/// Ekubo prices a pool as token1 per token0 (base units), with token0 the smaller address, and
+/// stores sqrt(price) * 2^128. Selling token0 pushes the price down, so the limit sits below the
+/// current ratio; selling token1 pushes it up. The bps apply to the price, not its square root:
+/// the limit is sqrt(ratio^2 * (10000 -/+ bps) / 10000), clamped to the range the core accepts.
+fn sqrt_price_limit(sqrt_ratio: &BigUint, sells_token0: bool, max_bps: u16) -> BigUint {
+    let squared = sqrt_ratio * sqrt_ratio;
+    let scale = if sells_token0 {
+        10_000u32.saturating_sub(max_bps as u32)
+    } else {
+        10_000 + max_bps as u32
+    };
+    let limit = (squared * scale / 10_000u32).sqrt();
+    let min = BigUint::from(EKUBO_MIN_SQRT_RATIO);
+    let max: BigUint = EKUBO_MAX_SQRT_RATIO.parse().expect("valid constant");
+    limit.clamp(min, max)
+}
+
+/// Trading `amount_a` of token A for `amount_b` of token B (base units, with the tokens'
+/// decimals) implies a price of A in B within `max_bps` of `reference`, either way.
+fn within_slippage(
+    amount_a: &BigUint,
+    a_decimals: u32,
+    amount_b: &BigUint,
+    b_decimals: u32,
+    reference: &BigUint,
+    reference_decimals: u32,
+    max_bps: u16,
+) -> bool {
+    if amount_a.is_zero() {
+        return false;
+    }
+    let ten = BigUint::from(10u8);
+    let implied = amount_b * ten.pow(a_decimals + reference_decimals) / (amount_a * ten.pow(b_decimals));
+    let deviation = if implied >= *reference { &implied - reference } else { reference - &implied };
+    deviation * 10_000u32 <= reference * u32::from(max_bps)
+}
+
+/// Same user and token pair, with both amounts within `tolerance_bps` of each other (either way).
+/// Amounts that don't parse never count as duplicates.
+pub fn is_near_duplicate(a: &Intent, b: &Intent, tolerance_bps: u16) -> bool {
+    let (x, y) = (&a.public_inputs, &b.public_inputs);
+    if !same_address(&x.user, &y.user)
+        || !same_address(&x.token_in, &y.token_in)
+        || !same_address(&x.token_out, &y.token_out)
+    {
+        return false;
+    }
+    let (Some((a_in, a_min_out)), Some((b_in, b_min_out))) =
+        (IntentMatcher::amounts_in_base_units(a), IntentMatcher::amounts_in_base_units(b))
+    else {
+        return false;
+    };
+    let close = |p: &BigUint, q: &BigUint| within_tolerance(p.min(q), p.max(q), tolerance_bps);
+    close(&a_in, &b_in) && close(&a_min_out, &b_min_out)
+}
+
+/// `amount` covers `required`, or falls short by at most `tolerance_bps` of `required`.
+fn within_tolerance(amount: &BigUint, required: &BigUint, tolerance_bps: u16) -> bool {
+    if amount >= required {
+        return true;
+    }
+    (required - amount) * 10_000u32 <= required * u32::from(tolerance_bps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::scratch::{scratch_storage, Scratch};
+
+    #[test]
+    fn quotes_walk_the_best_prices_first() {
+        let levels = [
+            (BigUint::from(10u8), BigUint::from(10u8)),
+            (BigUint::from(30u8), BigUint::from(10u8)),
+            (BigUint::from(20u8), BigUint::from(10u8)),
+        ];
+        let whole = walk_book(&BigUint::from(25u8), &levels, false);
+        assert_eq!(
+            whole,
+            BookWalk { filled_in: BigUint::from(20u8), amount_out: BigUint::from(50u8), counterparties: 2 }
+        );
+        let partial = walk_book(&BigUint::from(25u8), &levels, true);
+        assert_eq!(
+            partial,
+            BookWalk { filled_in: BigUint::from(25u8), amount_out: BigUint::from(55u8), counterparties: 3 }
+        );
+        assert_eq!(walk_book(&BigUint::from(5u8), &levels, false), BookWalk::default());
+        assert_eq!(walk_book(&BigUint::from(100u8), &[], true), BookWalk::default());
+    }
+
+    #[test]
+    fn quotes_need_enough_counterparties_and_are_rounded() {
+        let walk = |filled_in: u64, amount_out: u64, counterparties| BookWalk {
+            filled_in: BigUint::from(filled_in),
+            amount_out: BigUint::from(amount_out),
+            counterparties,
+        };
+        // One or two resting intents would give their sizes away.
+        assert_eq!(coarsen_quote(walk(20, 50, 2), 3, 2), BookWalk::default());
+        assert_eq!(coarsen_quote(walk(2_345, 6_789, 3), 3, 2), walk(2_300, 6_700, 3));
+        assert_eq!(coarsen_quote(walk(2_345, 6_789, 3), 3, 0), walk(2_345, 6_789, 3));
+        assert_eq!(coarsen_quote(walk(7, 12, 4), 3, 2), walk(7, 12, 4));
+    }
+
+    #[test]
+    fn triggers_fire_at_the_threshold_and_hold_intents_back_until_then() {
+        // 2500 USDC per ETH, as a Pragma cross rate with 8 decimals.
+        let price = BigUint::from(2500u32) * BigUint::from(10u8).pow(8);
+        let at = |p: &str| parse_trigger_price(p).expect("trigger price");
+        assert!(trigger_met(&price, 8, &at("2500"), TriggerDirection::Above));
+        assert!(trigger_met(&price, 8, &at("2500"), TriggerDirection::Below));
+        assert!(trigger_met(&price, 8, &at("2499.99999999"), TriggerDirection::Above));
+        assert!(!trigger_met(&price, 8, &at("2500.000000000000000001"), TriggerDirection::Above));
+        assert!(!trigger_met(&price, 8, &at("2400"), TriggerDirection::Below));
+        for bad in ["0", "0.0", "-1", "0x10", "abc", "1.0000000000000000001"] {
+            assert!(parse_trigger_price(bad).is_none(), "{}", bad);
+        }
+
+        let mut intent = crate::fixture::parse_fixture(include_str!("../fixtures/matching_sample.json"))
+            .expect("fixture")
+            .remove(0);
+        assert!(intent.is_triggered());
+        intent.trigger_price = Some("2500".to_string());
+        intent.trigger_direction = Some(TriggerDirection::Below);
+        assert!(!intent.is_triggered() && !intent.is_active());
+        intent.triggered_at = Some(chrono::Utc::now());
+        assert!(intent.is_triggered() && intent.is_active());
+    }
+
+    #[test]
+    fn within_tolerance_allows_small_shortfalls_only() {
+        let required = BigUint::from(1_000_000u32);
+        // 1% (100 bps) short is still within a 100 bps tolerance, 1.0001% is not.
+        assert!(within_tolerance(&BigUint::from(990_000u32), &required, 100));
+        assert!(!within_tolerance(&BigUint::from(989_999u32), &required, 100));
+        assert!(within_tolerance(&BigUint::from(1_200_000u32), &required, 0));
+        assert!(!within_tolerance(&BigUint::from(999_999u32), &required, 0));
+    }
+
+    #[test]
+    fn near_duplicates_need_the_same_pair_and_close_amounts() {
+        let intents = crate::fixture::parse_fixture(include_str!("../fixtures/matching_sample.json")).expect("fixture");
+        let original = &intents[0];
+        let mut again = original.clone();
+        again.nullifier = "0xf1a2".to_string();
+        again.public_inputs.min_amount_out = "2001".to_string();
+        // 2001 vs 2000 is 5 bps apart.
+        assert!(is_near_duplicate(original, &again, 5));
+        assert!(!is_near_duplicate(original, &again, 4));
+        assert!(!is_near_duplicate(original, &again, 0));
+
+        again.public_inputs.min_amount_out = original.public_inputs.min_amount_out.clone();
+        assert!(is_near_duplicate(original, &again, 0));
+        again.public_inputs.user = "0x0999".to_string();
+        assert!(!is_near_duplicate(original, &again, 10_000));
+        assert!(!is_near_duplicate(original, &intents[1], 10_000));
+    }
+
+    #[test]
+    fn slippage_band_is_measured_against_the_reference_price() {
+        // 1 ETH (18 decimals) at a reference of 2500 USDC (6 decimals), band 50 bps = 2487.5..2512.5.
+        let eth = BigUint::from(10u8).pow(18);
+        let reference = BigUint::from(2500u32) * BigUint::from(10u8).pow(18);
+        let usdc = |units: u64| BigUint::from(units);
+        assert!(within_slippage(&eth, 18, &usdc(2_512_500_000), 6, &reference, 18, 50));
+        assert!(within_slippage(&eth, 18, &usdc(2_487_500_000), 6, &reference, 18, 50));
+        assert!(!within_slippage(&eth, 18, &usdc(2_512_600_000), 6, &reference, 18, 50));
+        assert!(!within_slippage(&eth, 18, &usdc(2_487_400_000), 6, &reference, 18, 50));
+        assert!(!within_slippage(&BigUint::from(0u8), 18, &usdc(2_500_000_000), 6, &reference, 18, 50));
+    }
+
+    #[test]
+    fn intent_slippage_limits_only_tighten_the_global() {
+        assert_eq!(effective_slippage_bps(50, None, None), Some(50));
+        assert_eq!(effective_slippage_bps(50, Some(20), None), Some(20));
+        assert_eq!(effective_slippage_bps(50, Some(200), Some(30)), Some(30));
+        assert_eq!(effective_slippage_bps(50, Some(200), None), Some(50));
+        assert_eq!(effective_slippage_bps(0, None, Some(75)), Some(75));
+        assert_eq!(effective_slippage_bps(0, None, None), None);
+    }
+
+    #[test]
+    fn sqrt_price_limits_move_against_each_swap_direction() {
+        // Price 1 (sqrt_ratio = 2^128) and a 100 bps limit: the price may fall to 0.99 selling
+        // token0 and rise to 1.01 selling token1.
+        let one = BigUint::from(1u8) << 128u32;
+        let squared = &one * &one;
+        let down = sqrt_price_limit(&one, true, 100);
+        assert!(down < one);
+        assert!(&down * &down * 10_000u32 <= &squared * 9_900u32);
+        assert!((&down + 1u8) * (&down + 1u8) * 10_000u32 > &squared * 9_900u32);
+        let up = sqrt_price_limit(&one, false, 100);
+        assert!(up > one);
+        assert!(&up * &up * 10_000u32 <= &squared * 10_100u32);
+        assert!((&up + 1u8) * (&up + 1u8) * 10_000u32 > &squared * 10_100u32);
+
+        assert_eq!(sqrt_price_limit(&one, true, 0), one);
+        assert_eq!(sqrt_price_limit(&one, true, 10_000), BigUint::from(EKUBO_MIN_SQRT_RATIO));
+        let near_max: BigUint = EKUBO_MAX_SQRT_RATIO.parse().unwrap();
+        assert_eq!(sqrt_price_limit(&near_max, false, 500), near_max);
+    }
+
+    #[test]
+    fn partial_fill_of_ten_against_three_leaves_seven() {
+        let units = |n: u32| BigUint::from(n);
+        let fill = partial_fill_amounts(&units(10), &units(10), &units(3), &units(3)).expect("fill");
+        assert_eq!(
+            fill,
+            FillAmounts {
+                amount_in: units(3),
+                min_amount_out: units(3),
+                residual_in: units(7),
+                residual_min_out: units(7),
+            }
+        );
+        // The smaller side can't be the one partially filled, and it has to pay the limit price.
+        assert!(partial_fill_amounts(&units(3), &units(3), &units(10), &units(10)).is_none());
+        assert!(partial_fill_amounts(&units(10), &units(10), &units(2), &units(3)).is_none());
+        // Pro-rata minimums round up for the larger order.
+        let uneven = partial_fill_amounts(&units(10), &units(5), &units(2), &units(3)).expect("fill");
+        assert_eq!((uneven.min_amount_out, uneven.residual_min_out), (units(2), units(4)));
+    }
+
+    /// ETH -> USDC -> STRK -> ETH, one intent per leg, each paying exactly what the next wants.
+    fn ring(amounts: [(&str, &str); 3]) -> Vec<Intent> {
+        let template = crate::fixture::parse_fixture(include_str!("../fixtures/matching_sample.json"))
+            .expect("fixture")
+            .remove(0);
+        let eth = "0x049d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7";
+        let usdc = "0x053c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8";
+        let strk = "0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d";
+        let legs = [(eth, usdc), (usdc, strk), (strk, eth)];
+        let mut intents: Vec<Intent> = legs
+            .iter()
+            .zip(amounts)
+            .enumerate()
+            .map(|(n, ((token_in, token_out), (amount_in, min_amount_out)))| {
+                let mut intent = template.clone();
+                intent.nullifier = format!("0xe{}", n);
+                intent.public_inputs.user = format!("0x0{}", n + 1);
+                intent.public_inputs.token_in = token_in.to_string();
+                intent.public_inputs.token_out = token_out.to_string();
+                intent.public_inputs.amount_in = amount_in.to_string();
+                intent.public_inputs.min_amount_out = min_amount_out.to_string();
+                intent
+            })
+            .collect();
+        crate::fixture::rebase_times(&mut intents, chrono::Utc::now());
+        intents
+    }
+
+    #[test]
+    fn three_cycle_is_found_only_when_every_leg_is_covered() {
+        // 1 ETH for 2000 USDC, 2000 USDC for 4000 STRK, 4000 STRK for 1 ETH.
+        let solvable = ring([("1", "2000"), ("2000", "4000"), ("4000", "1")]);
+        let groups = find_rings(&solvable);
+        assert_eq!(groups.len(), 1);
+        let order: Vec<&str> = groups[0].intents.iter().map(|i| i.nullifier.as_str()).collect();
+        assert_eq!(order, vec!["0xe0", "0xe1", "0xe2"]);
+
+        // The STRK leg gives 3999 where the USDC leg wants 4000.
+        let unsolvable = ring([("1", "2000"), ("2000", "4000"), ("3999", "1")]);
+        assert!(find_rings(&unsolvable).is_empty());
+
+        let mut same_user = solvable.clone();
+        same_user[2].public_inputs.user = same_user[0].public_inputs.user.clone();
+        assert!(find_rings(&same_user).is_empty());
+    }
+
+    #[test]
+    fn a_ring_is_reported_once_while_it_stays_in_the_book() {
+        let solvable = ring([("1", "2000"), ("2000", "4000"), ("4000", "1")]);
+        let mut reported = HashSet::new();
+        let first = find_rings(&solvable);
+        assert_eq!(newly_found_rings(&mut reported, &first), vec![first[0].id.as_str()]);
+        // Found again next pass, under a new id and starting elsewhere: not new.
+        let mut rotated = solvable.clone();
+        rotated.rotate_left(1);
+        let again = find_rings(&rotated);
+        assert!(newly_found_rings(&mut reported, &again).is_empty());
+        // Gone for a pass, then back.
+        assert!(newly_found_rings(&mut reported, &[]).is_empty());
+        let back = find_rings(&solvable);
+        assert_eq!(newly_found_rings(&mut reported, &back).len(), 1);
+    }
+
+    #[test]
+    fn partial_fill_of_ten_by_three_rests_seven_with_the_same_user_and_deadline() {
+        let intents = crate::fixture::parse_fixture(include_str!("../fixtures/matching_sample.json")).expect("fixture");
+        // Sells 10 ETH for at least 10 USDC; the counterparty only wants 3 ETH, for 3 USDC.
+        let mut large = intents[0].clone();
+        large.public_inputs.amount_in = "10".to_string();
+        large.public_inputs.min_amount_out = "10".to_string();
+        let (large_in, large_min_out) = IntentMatcher::amounts_in_base_units(&large).expect("amounts");
+        let usdc = BigUint::from(10u32).pow(6);
+        let eth = BigUint::from(10u32).pow(18);
+        let fill = partial_fill_amounts(&large_in, &large_min_out, &(&usdc * 3u8), &(&eth * 3u8)).expect("fill");
+        let fill = partial_fill(&large, fill);
+        assert_eq!((fill.amount_in.as_str(), fill.min_amount_out.as_str()), ("3", "3"));
+        assert_eq!((fill.residual_amount_in.as_str(), fill.residual_min_amount_out.as_str()), ("7", "7"));
+
+        let residual = residual_intent(&large, &fill);
+        assert_eq!(residual.public_inputs.user, large.public_inputs.user);
+        assert_eq!(residual.public_inputs.deadline, large.public_inputs.deadline);
+        assert_eq!(residual.expires_at, large.expires_at);
+        assert_eq!((residual.public_inputs.amount_in.as_str(), residual.public_inputs.min_amount_out.as_str()), ("7", "7"));
+        assert_eq!(residual.status, IntentStatus::Pending);
+        assert_eq!(residual.residual_of.as_deref(), Some(large.nullifier.as_str()));
+        assert_ne!(residual.nullifier, large.nullifier);
+        assert_eq!(residual.nullifier, residual_nullifier(&large.nullifier, &(&eth * 7u8)));
+        assert!(starknet::core::types::Felt::from_hex(&residual.nullifier).is_ok());
+
+        let data = SettlementData { ekubo_pool: "0x0".to_string(), sqrt_price_limit: "0".to_string(), surplus_distribution: None };
+        let mut pair = MatchedPair::new(large.clone(), intents[1].clone(), data);
+        assert!(ensure_settleable(&pair).is_ok());
+        pair.fill = Some(fill);
+        assert!(ensure_settleable(&pair).unwrap_err().to_string().contains("PARTIAL_FILL_NOT_SETTLEABLE"));
+    }
+
+    #[test]
+    fn settlement_batches_keep_users_apart_and_respect_the_size() {
+        let intents = crate::fixture::parse_fixture(include_str!("../fixtures/matching_sample.json")).expect("fixture");
+        let pair = |id: &str, user_a: &str, user_b: &str| {
+            let (mut a, mut b) = (intents[0].clone(), intents[1].clone());
+            a.public_inputs.user = user_a.to_string();
+            b.public_inputs.user = user_b.to_string();
+            let data = SettlementData { ekubo_pool: "0x0".to_string(), sqrt_price_limit: "0".to_string(), surplus_distribution: None };
+            let mut pair = MatchedPair::new(a, b, data);
+            pair.id = id.to_string();
+            pair
+        };
+        let pairs = vec![
+            pair("m1", "0x01", "0x02"),
+            pair("m2", "0x0003", "0x04"),
+            pair("m3", "0x1", "0x05"),
+            pair("m4", "0x06", "0x07"),
+        ];
+        let ids = |batches: Vec<Vec<MatchedPair>>| -> Vec<Vec<String>> {
+            batches.into_iter().map(|b| b.into_iter().map(|p| p.id).collect()).collect()
+        };
+
+        // m3 shares 0x1 with m1, so it waits for the next batch.
+        assert_eq!(ids(settlement_batches(pairs.clone(), 3)), vec![vec!["m1", "m2", "m4"], vec!["m3"]]);
+        assert_eq!(ids(settlement_batches(pairs.clone(), 2)), vec![vec!["m1", "m2"], vec!["m3", "m4"]]);
+        assert_eq!(settlement_batches(pairs, 1).len(), 4);
+    }
+
+    #[test]
+    fn settlement_outputs_read_each_side_from_the_transfers() {
+        use starknet::core::types::Felt;
+        use starknet::core::utils::get_selector_from_name;
+
+        let intents = crate::fixture::parse_fixture(include_str!("../fixtures/matching_sample.json")).expect("fixture");
+        let data = SettlementData { ekubo_pool: "0x0".to_string(), sqrt_price_limit: "0".to_string(), surplus_distribution: None };
+        let pair = MatchedPair::new(intents[0].clone(), intents[1].clone(), data);
+        let felt = |hex: &str| felt_from_hex(hex).unwrap();
+        let (eth, usdc) = (felt(&pair.intent_b.public_inputs.token_out), felt(&pair.intent_a.public_inputs.token_out));
+        let transfer = |token: Felt, to: &str, amount: u128| Event {
+            from_address: token,
+            keys: vec![get_selector_from_name("Transfer").unwrap(), Felt::from(0x900u16), felt(to)],
+            data: vec![Felt::from(amount), Felt::ZERO],
+        };
+        // a (0x0111) gets 2050 USDC; b (0x0222) 0.98 ETH against a 0.99 minimum.
+        let events = vec![
+            transfer(usdc, "0x0111", 2_050_000_000),
+            transfer(eth, "0x0222", 980_000_000_000_000_000),
+            transfer(eth, "0x0111", 5),
+        ];
+
+        let outputs = settlement_outputs(&pair, &events).expect("outputs");
+        let summary: Vec<(&str, &str, bool)> =
+            outputs.iter().map(|o| (o.nullifier.as_str(), o.amount.as_str(), o.shortfall)).collect();
+        assert_eq!(summary, vec![("0xf1a1", "2050", false), ("0xf1b1", "0.98", true)]);
+        assert_eq!(outputs[1].min_amount_out, "0.99");
+    }
+
+    fn usd_rate(price_raw: &str, decimals: u32) -> CrossRate {
+        CrossRate {
+            pair_id: "ETH/USD".to_string(),
+            window_seconds: 3600,
+            price_raw: price_raw.to_string(),
+            decimals,
+            legs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn minimum_notional_is_checked_in_base_units() {
+        // ETH at 2500.00000000 USD: 0.04 ETH is exactly 100 USD.
+        let eth = usd_rate("250000000000", 8);
+        let wei = |n: u128| BigUint::from(n);
+        assert!(worth_at_least(&wei(40_000_000_000_000_000), 18, &eth, 100.0));
+        assert!(!worth_at_least(&wei(39_999_999_999_999_999), 18, &eth, 100.0));
+        // 10^19 - 1 micro-dollars rounds to 10^19 as an f64, but still falls short.
+        let usdc = usd_rate("100000000", 8);
+        let big = BigUint::from(10_000_000_000_000_000_000u64);
+        assert!(worth_at_least(&big, 6, &usdc, 1e13));
+        assert!(!worth_at_least(&(&big - 1u8), 6, &usdc, 1e13));
+        assert!(!worth_at_least(&wei(1), 18, &usd_rate("not a number", 8), 0.0));
+    }
+
+    #[test]
+    fn search_efficiency_needs_a_match() {
+        let mut totals = SearchStats::default();
+        assert_eq!(totals.comparisons_per_match(), None);
+        totals.add(&SearchStats { scanned: 10, comparisons: 12, matches: 0, elapsed_ms: 3 });
+        totals.add(&SearchStats { scanned: 10, comparisons: 18, matches: 2, elapsed_ms: 4 });
+        assert_eq!((totals.scanned, totals.matches, totals.elapsed_ms), (20, 2, 7));
+        assert_eq!(totals.comparisons_per_match(), Some(15.0));
+    }
+
+    #[test]
+    fn fees_round_up_to_nano_units() {
+        assert_eq!(fee_to_nano(&BigUint::from(0u32)), 0);
+        assert_eq!(fee_to_nano(&BigUint::from(1u32)), 1);
+        assert_eq!(fee_to_nano(&BigUint::from(2_000_000_000u64)), 2);
+        assert_eq!(fee_to_nano(&BigUint::from(2_000_000_001u64)), 3);
+    }
+
+    #[test]
+    fn a_settlement_must_fit_the_rest_of_the_gas_budget() {
+        assert!(within_gas_budget(60, 40, 100));
+        assert!(!within_gas_budget(61, 40, 100));
+        assert!(!within_gas_budget(0, 101, 100));
+        assert!(!within_gas_budget(u64::MAX, 1, 100));
+    }
+
+    #[test]
+    fn match_surplus_is_counted_in_intent_a_input_token() {
+        let n = |v: u64| BigUint::from(v);
+        // A gives 1000 for at least 2; B gives 3 (worth 333 of A's token) for at least 900.
+        assert_eq!(match_surplus(&n(1000), &n(2), &n(3), &n(900)), n(100 + 333));
+        // Neither side gives more than the other's minimum.
+        assert_eq!(match_surplus(&n(900), &n(3), &n(3), &n(900)), n(0));
+        assert_eq!(match_surplus(&n(1000), &n(0), &n(0), &n(900)), n(100));
+    }
+
+    #[test]
+    fn expected_profit_is_the_surplus_in_intent_a_input_token() {
+        let intents = crate::fixture::parse_fixture(include_str!("../fixtures/matching_sample.json")).expect("fixture");
+        let data = SettlementData { ekubo_pool: "0x0".to_string(), sqrt_price_limit: "0".to_string(), surplus_distribution: None };
+        // 1 ETH for at least 2000 USDC against 2100 USDC for at least 0.99 ETH: 0.01 ETH over B's
+        // minimum, plus 100 USDC over A's, which is 1/21 ETH at the match's rate.
+        let pair = MatchedPair::new(intents[0].clone(), intents[1].clone(), data);
+        assert!((expected_profit(&pair) - (0.01 + 1.0 / 21.0)).abs() < 1e-12);
+
+        let mut unparseable = pair.clone();
+        unparseable.intent_b.public_inputs.amount_in = "lots".to_string();
+        assert_eq!(expected_profit(&unparseable), 0.0);
+    }
+
+    #[test]
+    fn surplus_goes_to_the_earlier_intent_as_maker() {
+        let earlier = chrono::Utc::now();
+        let later = earlier + chrono::Duration::seconds(5);
+        let maker_first = surplus_distribution(SurplusPolicy::Maker, earlier, later);
+        assert_eq!((maker_first.intent_a_bps, maker_first.intent_b_bps, maker_first.solver_bps), (10_000, 0, 0));
+        let maker_second = surplus_distribution(SurplusPolicy::Maker, later, earlier);
+        assert_eq!((maker_second.intent_a_bps, maker_second.intent_b_bps), (0, 10_000));
+        let solver = surplus_distribution(SurplusPolicy::Solver, later, earlier);
+        assert_eq!((solver.intent_a_bps, solver.intent_b_bps, solver.solver_bps), (0, 0, 10_000));
+    }
+
+    #[test]
+    fn only_pairing_failures_requeue_intents() {
+        assert!(requeues_intents("INCOMPATIBLE_INTENTS"));
+        assert!(requeues_intents("INSUFFICIENT_ALLOWANCE"));
+        assert!(!requeues_intents("INTENT_NOT_PENDING"));
+        assert!(!requeues_intents("INVALID_PROOFS"));
+        assert!(!requeues_intents("DEADLINE_EXPIRED"));
+        assert!(!requeues_intents("TX_REVERTED: Error in the called contract"));
+        assert!(!requeues_intents("CONFIRM_TIMEOUT"));
+    }
+
+    /// A matcher on the scratch Redis with a committed match whose settlement `tx_hash` was sent.
+    async fn sent_settlement(tx_hash: &str) -> (Scratch, IntentMatcher, MatchedPair) {
+        let config = crate::config::Config::from_env().expect("config");
+        let storage = scratch_storage().await;
+        let (a, b) = (storage.fixture_intent(0), storage.fixture_intent(1));
+        for intent in [&a, &b] {
+            storage.store_intent(intent).await.expect("store");
+        }
+        let data = SettlementData { ekubo_pool: "0x0".to_string(), sqrt_price_limit: "0".to_string(), surplus_distribution: None };
+        let pair = MatchedPair::new(a, b, data);
+        storage.track_match(&pair);
+        assert!(storage.try_commit_match(&pair).await.expect("commit"));
+        let marker = SettlingMarker {
+            started_at_unix: chrono::Utc::now().timestamp() as u64,
+            tx_hash: Some(tx_hash.to_string()),
+            trigger: SettlementTrigger::Auto,
+        };
+        storage.mark_match_settling(&pair.id, &marker).await.expect("mark");
+        let matcher = IntentMatcher::new(
+            storage.storage.clone(),
+            config.matching_config,
+            None,
+            SettleMode::Manual,
+            EventBus::new(),
+            None,
+            None,
+        );
+        (storage, matcher, pair)
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn accepted_receipt_settles_both_intents() {
+        let (_scratch, matcher, pair) = sent_settlement("0x5a1e").await;
+        let mut receipts = crate::starknet::MockSettlementReceipts::new();
+        receipts
+            .expect_transaction_outcome()
+            .withf(|tx_hash| tx_hash == "0x5a1e")
+            .returning(|_| Ok(TxOutcome::Confirmed { block_number: 812_345, events: Vec::new() }));
+
+        matcher.reconcile_settling_match(&receipts, &pair.id).await.expect("reconcile");
+
+        for nullifier in [&pair.intent_a.nullifier, &pair.intent_b.nullifier] {
+            let intent = matcher.storage.get_intent(nullifier).await.expect("get").expect("intent");
+            assert_eq!(intent.status, IntentStatus::Settled);
+            assert_eq!(intent.settlement_tx_hash.as_deref(), Some("0x5a1e"));
+        }
+        assert_eq!(matcher.storage.get_settling_marker(&pair.id).await.expect("marker"), None);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn reverted_receipt_fails_both_intents_with_the_reason() {
+        let (_scratch, matcher, pair) = sent_settlement("0xbad").await;
+        let mut receipts = crate::starknet::MockSettlementReceipts::new();
+        receipts
+            .expect_transaction_outcome()
+            .returning(|_| Ok(TxOutcome::Reverted("Error in the called contract: 'Invalid proofs'".to_string())));
+
+        matcher.reconcile_settling_match(&receipts, &pair.id).await.expect("reconcile");
+
+        for nullifier in [&pair.intent_a.nullifier, &pair.intent_b.nullifier] {
+            let intent = matcher.storage.get_intent(nullifier).await.expect("get").expect("intent");
+            assert_eq!(intent.status, IntentStatus::Failed);
+            assert_eq!(
+                intent.failure_reason.as_deref(),
+                Some("TX_REVERTED: Error in the called contract: 'Invalid proofs'")
+            );
+            assert_eq!(intent.settlement_tx_hash, None);
+        }
+        assert_eq!(matcher.storage.get_settling_marker(&pair.id).await.expect("marker"), None);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn settlement_pending_past_the_confirm_timeout_fails_the_match() {
+        let (_scratch, mut matcher, pair) = sent_settlement("0x5a1e").await;
+        let mut receipts = crate::starknet::MockSettlementReceipts::new();
+        receipts.expect_transaction_outcome().returning(|_| Ok(TxOutcome::Pending));
+
+        matcher.config.confirm_timeout_seconds = 3600;
+        matcher.reconcile_settling_match(&receipts, &pair.id).await.expect("reconcile");
+        let intent = matcher.storage.get_intent(&pair.intent_a.nullifier).await.expect("get").expect("intent");
+        assert_eq!(intent.status, IntentStatus::Matched);
+        assert!(matcher.storage.get_settling_marker(&pair.id).await.expect("marker").is_some());
+
+        // Sent "an hour ago": past the timeout.
+        let mut marker = matcher.storage.get_settling_marker(&pair.id).await.expect("marker").expect("set");
+        marker.started_at_unix -= 3600;
+        matcher.storage.mark_match_settling(&pair.id, &marker).await.expect("mark");
+        matcher.reconcile_settling_match(&receipts, &pair.id).await.expect("reconcile");
+        for nullifier in [&pair.intent_a.nullifier, &pair.intent_b.nullifier] {
+            let intent = matcher.storage.get_intent(nullifier).await.expect("get").expect("intent");
+            assert_eq!(intent.status, IntentStatus::Failed);
+            assert_eq!(intent.failure_reason.as_deref(), Some("CONFIRM_TIMEOUT"));
+        }
+    }
+}
@@ -1,7 +1,12 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
+use crate::amount::TokenAmount;
+use crate::config::{FeeToken, SurplusPolicy};
+use crate::starknet::OnchainIntentStatus;
+
 /// Represents an encrypted trade intent submitted by a user
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Intent {
@@ -20,10 +25,49 @@ pub struct Intent {
     pub expires_at: DateTime<Utc>,
     pub matched_with: Option<String>,
     pub settlement_tx_hash: Option<String>,
+    /// Why settlement was given up on; set when the intent moves to `Failed`.
+    #[serde(default)]
+    pub failure_reason: Option<String>,
+    /// Good-after-time: the intent stays pending but is not matched before this instant.
+    #[serde(default)]
+    pub activate_at: Option<DateTime<Utc>>,
+    /// Opted in to deadline extensions (each one needs a fresh proof and signature).
+    #[serde(default)]
+    pub allow_extension: bool,
+    /// The user's own slippage limit; the matcher applies the stricter of it and `MAX_SLIPPAGE_BPS`.
+    #[serde(default)]
+    pub max_slippage_bps: Option<u16>,
+    /// Set on the residual of a partial fill: the nullifier of the intent it was split from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub residual_of: Option<String>,
+    /// Distinct counterparties that came within `NEAR_MISS_TOLERANCE_BPS` of crossing.
+    #[serde(default)]
+    pub near_miss_count: u32,
+    #[serde(default)]
+    pub last_near_miss_at: Option<DateTime<Utc>>,
+    /// Counterparties whose match with this intent failed to settle, each mapped to the end of
+    /// its `FAILED_PAIR_COOLDOWN_SECONDS` window. Only written when a failed match is requeued.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub failed_counterparties: BTreeMap<String, DateTime<Utc>>,
+    /// Where the settlement callbacks for this intent go.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+    /// Submitted post-only: it was refused if it crossed a resting intent, so it only ever rests.
+    #[serde(default)]
+    pub post_only: bool,
+    /// Conditional intent: not matched until the oracle price of `token_in` in `token_out` (whole
+    /// tokens, decimal) has crossed this in `trigger_direction`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger_price: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger_direction: Option<TriggerDirection>,
+    /// When the matcher first saw the trigger condition met; the intent matches normally from then on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub triggered_at: Option<DateTime<Utc>>,
 }
 
 /// Public inputs that are visible without decrypting the intent
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct PublicInputs {
     pub user: String,
     pub token_in: String,
@@ -48,6 +92,56 @@ pub enum IntentStatus {
     Failed,
 }
 
+/// Which way the oracle price has to move for a conditional intent to trigger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerDirection {
+    /// At or above `trigger_price` (limit-trigger / take-profit).
+    Above,
+    /// At or below `trigger_price` (stop).
+    Below,
+}
+
+impl TriggerDirection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Above => "above",
+            Self::Below => "below",
+        }
+    }
+}
+
+impl IntentStatus {
+    /// No further transitions: `settled`, `cancelled`, `expired` or `failed`.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Settled | Self::Cancelled | Self::Expired | Self::Failed)
+    }
+}
+
+/// An intent's status as written by `RedisStorage`, streamed by `GET /v1/intents/:nullifier/events`.
+#[derive(Debug, Clone, Serialize)]
+pub struct IntentStatusChange {
+    pub nullifier: String,
+    pub status: IntentStatus,
+    pub matched_with: Option<String>,
+    pub settlement_tx_hash: Option<String>,
+    pub failure_reason: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl IntentStatusChange {
+    pub fn of(intent: &Intent) -> Self {
+        Self {
+            nullifier: intent.nullifier.clone(),
+            status: intent.status.clone(),
+            matched_with: intent.matched_with.clone(),
+            settlement_tx_hash: intent.settlement_tx_hash.clone(),
+            failure_reason: intent.failure_reason.clone(),
+            timestamp: Utc::now(),
+        }
+    }
+}
+
 /// A matched pair of intents ready for settlement
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MatchedPair {
@@ -57,12 +151,237 @@ pub struct MatchedPair {
     pub matched_at: DateTime<Utc>,
     pub expected_profit: f64,
     pub settlement_data: SettlementData,
+    /// Set when only part of one intent was matched (`PARTIAL_FILLS`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fill: Option<PartialFill>,
+}
+
+/// A ring of intents that settle each other (`RING_MATCHING`): each intent's `token_out` is the
+/// next one's `token_in`, and the next one's `amount_in` covers it, wrapping around at the end.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchedGroup {
+    pub id: String,
+    pub intents: Vec<Intent>,
+    pub found_at: DateTime<Utc>,
+}
+
+impl MatchedGroup {
+    pub fn new(intents: Vec<Intent>) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            intents,
+            found_at: Utc::now(),
+        }
+    }
+}
+
+/// The larger side of a partial fill trades part of its `amount_in`; the other side fills
+/// completely and the remainder rests in the book as a residual intent. Amounts are token units.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartialFill {
+    /// The partially filled intent.
+    pub nullifier: String,
+    pub amount_in: String,
+    /// The share of the intent's `min_amount_out` the filled part has to cover.
+    pub min_amount_out: String,
+    pub residual_nullifier: String,
+    pub residual_amount_in: String,
+    pub residual_min_amount_out: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SettlementData {
     pub ekubo_pool: String,
     pub sqrt_price_limit: String,
+    /// Set under `SURPLUS_POLICY`, and then appended to the `settle_match` calldata.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub surplus_distribution: Option<SurplusDistribution>,
+}
+
+/// How the settlement splits a match's surplus, resolved onto the pair's `intent_a`/`intent_b`
+/// sides. Shares are basis points and sum to 10000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SurplusDistribution {
+    pub policy: SurplusPolicy,
+    pub intent_a_bps: u16,
+    pub intent_b_bps: u16,
+    pub solver_bps: u16,
+}
+
+/// Who started a settlement: the solver on its own (on match or from the retry loop), a
+/// `/confirm` call, or a `/report-tx` of a transaction sent elsewhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettlementTrigger {
+    Auto,
+    Manual,
+    Reported,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettlementOutcome {
+    Settled,
+    /// Given up on (deadlettered) with `failure_reason`.
+    Failed,
+}
+
+/// One settlement outcome in the history behind `GET /v1/settlements`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettlementRecord {
+    pub match_id: String,
+    /// `intent_a`'s `token_in` / `token_out`.
+    pub token_a: String,
+    pub token_b: String,
+    /// Each side's `amount_in`, in token base units.
+    pub amount_a: String,
+    pub amount_b: String,
+    pub outcome: SettlementOutcome,
+    pub trigger: SettlementTrigger,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_hash: Option<String>,
+    /// Actual fee in fee-token base units, once the receipt is in. Only for transactions the solver sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure_reason: Option<String>,
+    pub matched_at: DateTime<Utc>,
+    pub recorded_at: DateTime<Utc>,
+    /// From match creation to this outcome.
+    pub latency_ms: u64,
+}
+
+impl SettlementRecord {
+    pub fn new(
+        pair: &MatchedPair,
+        outcome: SettlementOutcome,
+        trigger: SettlementTrigger,
+        transaction_hash: Option<String>,
+        failure_reason: Option<String>,
+    ) -> Self {
+        let recorded_at = Utc::now();
+        Self {
+            match_id: pair.id.clone(),
+            token_a: pair.intent_a.public_inputs.token_in.clone(),
+            token_b: pair.intent_a.public_inputs.token_out.clone(),
+            amount_a: amount_in_base_units(&pair.intent_a),
+            amount_b: amount_in_base_units(&pair.intent_b),
+            outcome,
+            trigger,
+            transaction_hash,
+            fee: None,
+            failure_reason,
+            matched_at: pair.matched_at,
+            recorded_at,
+            latency_ms: (recorded_at - pair.matched_at).num_milliseconds().max(0) as u64,
+        }
+    }
+}
+
+/// What `events:settlement` subscribers receive when a match settles or is given up on (see
+/// `RedisStorage::publish_settlement_event` and `publish_failure_event`).
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SettlementFeedEvent {
+    Settled {
+        #[serde(flatten)]
+        pair: SettlementFeedMatch,
+        transaction_hash: String,
+        timestamp: DateTime<Utc>,
+    },
+    Failed {
+        #[serde(flatten)]
+        pair: SettlementFeedMatch,
+        reason: String,
+        timestamp: DateTime<Utc>,
+    },
+}
+
+/// The match a `SettlementFeedEvent` is about.
+#[derive(Debug, Clone, Serialize)]
+pub struct SettlementFeedMatch {
+    pub match_id: String,
+    pub nullifier_a: String,
+    pub nullifier_b: String,
+    /// `intent_a`'s `token_in` / `token_out`.
+    pub token_a: String,
+    pub token_b: String,
+    /// Each side's `amount_in`, in token base units.
+    pub amount_a: String,
+    pub amount_b: String,
+}
+
+impl SettlementFeedMatch {
+    pub fn of(pair: &MatchedPair) -> Self {
+        Self {
+            match_id: pair.id.clone(),
+            nullifier_a: pair.intent_a.nullifier.clone(),
+            nullifier_b: pair.intent_b.nullifier.clone(),
+            token_a: pair.intent_a.public_inputs.token_in.clone(),
+            token_b: pair.intent_a.public_inputs.token_out.clone(),
+            amount_a: amount_in_base_units(&pair.intent_a),
+            amount_b: amount_in_base_units(&pair.intent_b),
+        }
+    }
+}
+
+/// `intent`'s `amount_in` in base units of its `token_in`, as the matcher reads it. Stored intents
+/// passed that parse at submit; anything else is passed through as given.
+fn amount_in_base_units(intent: &Intent) -> String {
+    let inputs = &intent.public_inputs;
+    TokenAmount::parse_for_token(&inputs.amount_in, &inputs.token_in)
+        .map(|amount| amount.base_units().to_string())
+        .unwrap_or_else(|_| inputs.amount_in.clone())
+}
+
+/// A settled match in the `GET /v1/matches/history` trade log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchHistoryEntry {
+    pub match_id: String,
+    /// `intent_a`'s `token_in` / `token_out`.
+    pub token_a: String,
+    pub token_b: String,
+    /// What each side gave, in whole tokens; for a partially filled intent, the filled part.
+    pub amount_a: String,
+    pub amount_b: String,
+    pub transaction_hash: String,
+    pub matched_at: DateTime<Utc>,
+    pub settled_at: DateTime<Utc>,
+}
+
+impl MatchHistoryEntry {
+    pub fn new(pair: &MatchedPair, transaction_hash: String) -> Self {
+        let traded = |intent: &Intent| match &pair.fill {
+            Some(fill) if fill.nullifier == intent.nullifier => fill.amount_in.clone(),
+            _ => intent.public_inputs.amount_in.clone(),
+        };
+        Self {
+            match_id: pair.id.clone(),
+            token_a: pair.intent_a.public_inputs.token_in.clone(),
+            token_b: pair.intent_a.public_inputs.token_out.clone(),
+            amount_a: traded(&pair.intent_a),
+            amount_b: traded(&pair.intent_b),
+            transaction_hash,
+            matched_at: pair.matched_at,
+            settled_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MatchHistoryResponse {
+    pub matches: Vec<MatchHistoryEntry>,
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SettlementListResponse {
+    pub settlements: Vec<SettlementRecord>,
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
 }
 
 /// Request to submit a new intent
@@ -77,7 +396,68 @@ pub struct SubmitIntentRequest {
     pub proof_public_inputs: Vec<String>,
     pub public_inputs: PublicInputs,
     pub encrypted_details: String, // base64 encoded
-    pub signature: String,
+    pub signature: SignatureInput,
+    /// Unix seconds before which the intent must not be matched. Must be before `deadline`.
+    #[serde(default)]
+    pub activate_at: Option<u64>,
+    /// Allow `POST /v1/intents/:nullifier/extend` to push the deadline out after near-misses.
+    #[serde(default)]
+    pub allow_extension: bool,
+    /// Tightens the solver's `MAX_SLIPPAGE_BPS` for this intent (1-10000); it can't loosen it.
+    #[serde(default)]
+    pub max_slippage_bps: Option<u16>,
+    /// Skip the `NEAR_DUPLICATE_WINDOW_SECONDS` guard, for deliberately repeated quotes.
+    #[serde(default)]
+    pub allow_duplicate: bool,
+    /// http(s) URL for the signed `settlement_submitted`/`settlement_confirmed` callbacks. Needs
+    /// `WEBHOOK_SECRET` on the solver.
+    #[serde(default)]
+    pub callback_url: Option<String>,
+    /// Reject the intent with `WOULD_CROSS` instead of storing it if it could match a resting intent
+    /// right away.
+    #[serde(default)]
+    pub post_only: bool,
+    /// Only match once the oracle price of `token_in` in `token_out` reaches this (decimal, whole
+    /// tokens) in `trigger_direction`. Both or neither; not with `post_only`.
+    #[serde(default)]
+    pub trigger_price: Option<String>,
+    #[serde(default)]
+    pub trigger_direction: Option<TriggerDirection>,
+}
+
+/// A re-proven intent with a later deadline. Every public input other than `deadline` must
+/// match the resting intent; the nullifier is kept.
+#[derive(Debug, Deserialize)]
+pub struct ExtendIntentRequest {
+    pub intent_hash: String,
+    pub proof_data: Vec<String>,
+    pub proof_public_inputs: Vec<String>,
+    pub public_inputs: PublicInputs,
+    pub signature: SignatureInput,
+}
+
+/// A wallet signature: either a single hex string (legacy clients, or `"r,s"` comma-separated)
+/// or a JSON array of felts as Starknet accounts produce them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum SignatureInput {
+    Text(String),
+    Felts(Vec<String>),
+}
+
+impl SignatureInput {
+    /// Signature elements, splitting the comma-separated string form.
+    pub fn elements(&self) -> Vec<&str> {
+        match self {
+            SignatureInput::Text(text) => text.split(',').map(str::trim).collect(),
+            SignatureInput::Felts(felts) => felts.iter().map(|f| f.trim()).collect(),
+        }
+    }
+
+    /// Whether this came in as one opaque hex string rather than a list of felts.
+    pub fn is_single_blob(&self) -> bool {
+        matches!(self, SignatureInput::Text(text) if !text.contains(','))
+    }
 }
 
 /// Response for intent submission
@@ -85,7 +465,61 @@ pub struct SubmitIntentRequest {
 pub struct SubmitIntentResponse {
     pub intent_id: String,
     pub status: IntentStatus,
+    /// `null` when nothing resting could match the intent right now.
     pub estimated_match_time: Option<String>,
+    /// Resting opposite-side intents the new one could match on the next matching pass.
+    pub compatible_counterparties: usize,
+    /// Non-fatal problems, e.g. a failed precheck under `PRECHECK_MODE=warn`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ErrorDetail>,
+    pub correlation_id: String,
+}
+
+/// One entry of a batch submission's results, in request order.
+#[derive(Debug, Serialize)]
+pub struct BatchSubmitResult {
+    pub index: usize,
+    pub nullifier: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intent_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<IntentStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compatible_counterparties: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ErrorDetail>,
+    /// Why this intent was refused; the others are unaffected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorDetail>,
+}
+
+/// Response for `POST /v1/intents/batch`.
+#[derive(Debug, Serialize)]
+pub struct BatchSubmitResponse {
+    pub results: Vec<BatchSubmitResult>,
+    pub accepted: usize,
+    pub rejected: usize,
+    pub correlation_id: String,
+}
+
+/// Response for a dry-run validation that found nothing wrong.
+#[derive(Debug, Serialize)]
+pub struct ValidateIntentResponse {
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ErrorDetail>,
+    pub correlation_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplaceIntentResponse {
+    pub intent_id: String,
+    pub status: IntentStatus,
+    /// Nullifier of the intent that was cancelled in favour of this one.
+    pub replaced_nullifier: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ErrorDetail>,
     pub correlation_id: String,
 }
 
@@ -105,6 +539,143 @@ pub struct ActionResponse {
     pub success: bool,
     pub correlation_id: String,
     pub message: String,
+    /// Confirm Match: what the caller's intents receive, per the settlement simulation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outputs: Option<Vec<SettlementOutput>>,
+}
+
+/// What one participant receives in a settlement, simulated or from the receipt. Amounts are token
+/// units.
+#[derive(Debug, Clone, Serialize)]
+pub struct SettlementOutput {
+    pub nullifier: String,
+    pub user: String,
+    /// The intent's `token_out`.
+    pub token: String,
+    pub amount: String,
+    pub min_amount_out: String,
+    /// `amount` is below `min_amount_out`.
+    pub shortfall: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CancelIntentResponse {
+    pub success: bool,
+    pub correlation_id: String,
+    pub message: String,
+    /// DarkPool status seen before cancelling; `None` if the chain could not be read.
+    pub onchain_status: Option<OnchainIntentStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AbandonIntentResponse {
+    pub success: bool,
+    pub nullifier: String,
+    /// The dead-lettered match that was discarded, if the intent still had one.
+    pub match_id: Option<String>,
+    /// DarkPool status that confirmed the intent never settled.
+    pub onchain_status: OnchainIntentStatus,
+    pub message: String,
+    pub correlation_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CancelMatchResponse {
+    pub success: bool,
+    pub match_id: String,
+    /// The caller's intent, now `cancelled`. `None` when an admin tore the match down.
+    pub cancelled: Option<String>,
+    /// Intents handed back under the requeue policy: back to `pending` or `failed`.
+    pub released: Vec<String>,
+    pub message: String,
+    pub correlation_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PurgeIntentResponse {
+    pub success: bool,
+    pub nullifier: String,
+    /// The match the intent was in, now deleted too.
+    pub match_id: Option<String>,
+    /// Redis keys an entry was removed from.
+    pub removed: Vec<String>,
+    /// The counterparty, handed back under the requeue policy.
+    pub released: Option<String>,
+    pub correlation_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SettlementEstimateResponse {
+    pub success: bool,
+    pub match_id: String,
+    /// Base units of `fee_token` (wei for ETH, fri for STRK), as a decimal string.
+    pub overall_fee: String,
+    pub fee_token: FeeToken,
+    pub fee_token_address: String,
+    pub correlation_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MatchEventsResponse {
+    pub success: bool,
+    pub match_id: String,
+    pub transaction_hash: String,
+    /// In emission order.
+    pub events: Vec<SettlementEvent>,
+    pub correlation_id: String,
+}
+
+/// One event from a settlement receipt. Addresses and nullifiers are 0x-hex, amounts are base
+/// units as decimal strings.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SettlementEvent {
+    IntentSettled {
+        contract: String,
+        nullifier_a: String,
+        nullifier_b: String,
+        token_in: String,
+        token_out: String,
+        amount_in: String,
+        amount_out: String,
+        timestamp: u64,
+    },
+    IntentSubmitted {
+        contract: String,
+        user: String,
+        nullifier: String,
+        timestamp: u64,
+    },
+    IntentCancelled {
+        contract: String,
+        user: String,
+        nullifier: String,
+        timestamp: u64,
+    },
+    ProofVerified {
+        contract: String,
+        nullifier: String,
+        verifier: String,
+        success: bool,
+    },
+    Transfer {
+        token: String,
+        from: String,
+        to: String,
+        amount: String,
+    },
+    Approval {
+        token: String,
+        owner: String,
+        spender: String,
+        amount: String,
+    },
+    /// A selector this solver doesn't know, raw.
+    Unknown {
+        contract: String,
+        keys: Vec<String>,
+        data: Vec<String>,
+    },
 }
 
 #[derive(Debug, Deserialize)]
@@ -116,8 +687,70 @@ pub struct LoginRequest {
 #[derive(Debug, Serialize)]
 pub struct LoginResponse {
     pub success: bool,
+    /// Access token, for the `Authorization` header.
     pub token: String,
     pub expires_in_seconds: u64,
+    /// Trades for a new access token at `/v1/auth/refresh`; not accepted anywhere else.
+    pub refresh_token: String,
+    pub refresh_expires_in_seconds: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefreshTokenResponse {
+    pub success: bool,
+    pub token: String,
+    pub expires_in_seconds: u64,
+    /// Replaces the refresh token that was traded in, which is now revoked.
+    pub refresh_token: String,
+    pub refresh_expires_in_seconds: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct LogoutRequest {
+    /// Also revoke this refresh token; it must belong to the same subject as the bearer token.
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RotateJwtSecretRequest {
+    pub new_secret: String,
+    /// Defaults to `JWT_ROTATION_GRACE_SECONDS`.
+    pub grace_seconds: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RotateJwtSecretResponse {
+    pub success: bool,
+    /// Unix time after which tokens signed with the old secret are rejected.
+    pub previous_valid_until: u64,
+    pub correlation_id: String,
+}
+
+/// A settlement transaction for a match that the solver did not send itself.
+#[derive(Debug, Deserialize)]
+pub struct ReportTxRequest {
+    pub tx_hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewMatchRequest {
+    pub nullifier_a: String,
+    pub nullifier_b: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreviewMatchResponse {
+    pub success: bool,
+    /// The pair as `create_match` would store it; its `id` is never stored.
+    #[serde(rename = "match")]
+    pub pair: MatchedPair,
+    pub correlation_id: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -130,6 +763,209 @@ pub struct IntentView {
     pub expires_at: DateTime<Utc>,
     pub matched_with: Option<String>,
     pub settlement_tx_hash: Option<String>,
+    pub failure_reason: Option<String>,
+    pub activate_at: Option<DateTime<Utc>>,
+    pub allow_extension: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_slippage_bps: Option<u16>,
+    pub post_only: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_price: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_direction: Option<TriggerDirection>,
+    /// Set once a conditional intent's trigger has fired.
+    pub triggered_at: Option<DateTime<Utc>>,
+    pub near_miss_count: u32,
+    pub last_near_miss_at: Option<DateTime<Utc>>,
+}
+
+/// A page of `GET /v1/intents/pending`. `next_cursor` is a decimal string (SSCAN cursors are u64)
+/// and is `None` once the walk is done.
+#[derive(Debug, Serialize)]
+pub struct PendingIntentsPage {
+    pub intents: Vec<IntentView>,
+    pub next_cursor: Option<String>,
+    pub limit: usize,
+}
+
+/// A participant's view of the match their intent belongs to.
+/// Deliberately omits the counterparty's user address, amounts and proof data.
+#[derive(Debug, Serialize)]
+pub struct MatchView {
+    pub match_id: String,
+    pub matched_at: DateTime<Utc>,
+    pub nullifier: String,
+    pub counterparty_nullifier: String,
+    pub token_in: String,
+    pub token_out: String,
+    pub status: IntentStatus,
+    pub settlement_tx_hash: Option<String>,
+    pub settlement_failures: u64,
+    pub next_retry_at_unix: Option<u64>,
+    /// Set while the retry loop holds settlement back, e.g. `GAS_PRICE_ABOVE_CEILING`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deferred_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueryMatchResponse {
+    #[serde(rename = "match")]
+    pub match_view: MatchView,
+}
+
+/// Operator view of a match awaiting settlement (or given up on), with its retry state.
+#[derive(Debug, Serialize)]
+pub struct MatchStatusEntry {
+    pub match_id: String,
+    pub matched_at: DateTime<Utc>,
+    pub intent_a_nullifier: String,
+    pub intent_b_nullifier: String,
+    pub token_a: String,
+    pub token_b: String,
+    pub settlement_failures: u64,
+    pub next_retry_at_unix: Option<u64>,
+    pub last_error: Option<String>,
+    pub terminal_reason: Option<String>,
+    pub deferred_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MatchListResponse {
+    pub matches: Vec<MatchStatusEntry>,
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+/// An in-flight match for the operator dashboard.
+#[derive(Debug, Serialize)]
+pub struct ActiveMatchEntry {
+    #[serde(flatten)]
+    pub status: MatchStatusEntry,
+    pub intent_a: PublicInputs,
+    pub intent_b: PublicInputs,
+    /// A settlement attempt holds `match:lock:<id>` right now.
+    pub settlement_locked: bool,
+    pub lock_ttl_seconds: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActiveMatchListResponse {
+    pub matches: Vec<ActiveMatchEntry>,
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
+}
+
+/// Resting liquidity for one `token_in` across all pending intents.
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenLiquidity {
+    pub token: String,
+    pub symbol: Option<String>,
+    pub intent_count: usize,
+    /// Sum of `amount_in`, in token units.
+    pub amount: String,
+    /// `None` when no price was available for this token.
+    pub value_usd: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LiquidityResponse {
+    /// Sum of `value_usd` over the priced tokens only.
+    pub total_usd: String,
+    pub intent_count: usize,
+    pub tokens: Vec<TokenLiquidity>,
+    /// Tokens left out of `total_usd` because the oracle had no price for them.
+    pub unpriced_tokens: Vec<String>,
+    pub computed_at: u64,
+}
+
+/// Body of `POST /v1/book/quote`; `amount_in` is in whole tokens, like an intent's.
+#[derive(Debug, Deserialize)]
+pub struct BookQuoteRequest {
+    pub token_in: String,
+    pub token_out: String,
+    pub amount_in: String,
+}
+
+/// What the resting book would give for `amount_in`, in aggregate only. Amounts are in whole
+/// tokens.
+#[derive(Debug, Serialize)]
+pub struct BookQuoteResponse {
+    pub token_in: String,
+    pub token_out: String,
+    pub amount_in: String,
+    pub filled_in: String,
+    pub unfilled_in: String,
+    pub amount_out: String,
+    /// `amount_out / filled_in`, in `token_out` per `token_in`; `null` when nothing fills.
+    pub average_price: Option<String>,
+    /// Resting intents the fill is spread over; each is a separate match.
+    pub counterparties: usize,
+    pub correlation_id: String,
+}
+
+/// On-chain ERC-20 metadata, as served by `GET /v1/tokens/:address/metadata`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenMetadata {
+    /// Normalized `0x` address.
+    pub address: String,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+/// Settled volume in one token for a user summary, in token units.
+#[derive(Debug, Serialize)]
+pub struct TokenVolume {
+    pub token: String,
+    pub symbol: Option<String>,
+    pub sent: String,
+    pub received: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserSummaryResponse {
+    pub user: String,
+    /// Intents of this user still held by the solver, in any status.
+    pub intent_count: usize,
+    pub total_trades: usize,
+    pub volume: Vec<TokenVolume>,
+    pub sent_usd: String,
+    pub received_usd: String,
+    /// `received_usd - sent_usd`, signed. An estimate: see `pnl_note`.
+    pub realized_pnl_usd: String,
+    pub pnl_is_estimate: bool,
+    pub pnl_note: String,
+    /// Trades left out of the USD figures (no oracle price or counterparty no longer stored).
+    pub unpriced_trades: usize,
+    pub computed_at: u64,
+}
+
+/// A user-signed SNIP-9 outside execution of `token.approve(spender, amount)`, relayed by the solver.
+#[derive(Debug, Deserialize)]
+pub struct ApprovalRelayRequest {
+    /// The user's account contract; it executes the approval, the solver only pays gas.
+    pub account: String,
+    pub token: String,
+    /// Must be the configured dark pool.
+    pub spender: String,
+    /// u256 in base units (decimal or `0x` hex).
+    pub amount: String,
+    /// Outside execution caller; defaults to `ANY_CALLER`.
+    #[serde(default)]
+    pub caller: Option<String>,
+    pub nonce: String,
+    pub execute_after: u64,
+    pub execute_before: u64,
+    pub signature: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApprovalRelayResponse {
+    pub success: bool,
+    pub transaction_hash: String,
+    pub correlation_id: String,
 }
 
 /// Health check response
@@ -142,6 +978,121 @@ pub struct HealthResponse {
     pub matched_pairs: usize,
 }
 
+/// `GET /health/ready`: whether this instance can serve traffic, plus operator-facing state that
+/// does not make it unready.
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub redis: bool,
+    /// The background matching loop ticked within `MATCHING_LOOP_STALE_AFTER_MS`.
+    pub matching_loop: bool,
+    /// `None` until the loop's first tick.
+    pub matching_loop_last_tick_ms_ago: Option<u64>,
+    /// Settlement is paused because `DAILY_GAS_BUDGET` is used up for today.
+    pub settlement_paused: bool,
+    /// Absent when no `DAILY_GAS_BUDGET` is configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_budget: Option<GasBudgetStatus>,
+    /// `None` while Redis is unreachable.
+    pub pending_intents: Option<u64>,
+    /// `MAX_PENDING_INTENTS`; absent when uncapped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_pending_intents: Option<u64>,
+    /// New submissions are refused with `BOOK_FULL`; matching carries on.
+    pub book_full: bool,
+    /// Feeds older than `MAX_ORACLE_STALENESS_SECONDS` at their last check; the pairs priced by them
+    /// are paused (or matched on amounts alone).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stale_oracle_feeds: Vec<StaleOracleFeed>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StaleOracleFeed {
+    /// The cross rate, e.g. `ETH/USDC` (slippage band) or `ETH/USD` (USD minimum).
+    pub pair_id: String,
+    /// Unix seconds of the oldest leg's last update; `None` when the oracle didn't say.
+    pub last_updated: Option<u64>,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// One line of a state bundle (`GET /v1/admin/export`, `POST /v1/admin/import`). Payloads are
+/// plaintext whatever `STORAGE_ENCRYPTION_KEY` says, so a bundle restores into any instance; index
+/// sets are not exported but rebuilt from the records.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BundleLine {
+    /// Always the first line.
+    Header {
+        version: u32,
+        exported_at: DateTime<Utc>,
+    },
+    Intent {
+        intent: Intent,
+        /// `None` when the key does not expire (matched and settled intents).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ttl_seconds: Option<u64>,
+    },
+    Match {
+        pair: MatchedPair,
+        /// In the retry set (`intents:matched`).
+        unsettled: bool,
+        deadletter: bool,
+        confirmed: bool,
+        /// Raw `match:retry:<id>` hash; empty when the match never failed.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        retry_state: BTreeMap<String, String>,
+        /// `None` when the retry state does not expire (deadlettered matches).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        retry_ttl_seconds: Option<u64>,
+    },
+    /// Anti-replay `(user, nonce)` reservation.
+    NonceReservation {
+        user: String,
+        nonce: u64,
+        ttl_seconds: u64,
+    },
+    /// Always the last line: HMAC-SHA3-256 under `STATE_BUNDLE_KEY` over every preceding line,
+    /// newlines included.
+    Signature {
+        hmac: String,
+    },
+}
+
+/// What `RedisStorage::import_state` restored. Records whose key already exists are skipped, never
+/// overwritten.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportSummary {
+    pub intents_imported: usize,
+    pub intents_skipped: usize,
+    /// Pending intents left out because the book was at `MAX_PENDING_INTENTS`.
+    pub intents_over_capacity: usize,
+    pub matches_imported: usize,
+    pub matches_skipped: usize,
+    pub nonce_reservations_imported: usize,
+    pub nonce_reservations_skipped: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportStateResponse {
+    pub success: bool,
+    #[serde(flatten)]
+    pub summary: ImportSummary,
+    pub correlation_id: String,
+}
+
+/// Settlement fees spent today (UTC) against `DAILY_GAS_BUDGET`, in 10^-9 units of the fee token
+/// (gwei for ETH, 10^9 fri for STRK).
+#[derive(Debug, Clone, Serialize)]
+pub struct GasBudgetStatus {
+    /// UTC day, `YYYY-MM-DD`.
+    pub day: String,
+    pub spent_nano: u64,
+    pub budget_nano: u64,
+    /// An admin lifted today's pause.
+    pub overridden: bool,
+    pub paused: bool,
+}
+
 /// Error response
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
@@ -150,6 +1101,9 @@ pub struct ErrorResponse {
     pub code: String,
     pub error_detail: ErrorDetail,
     pub correlation_id: Option<String>,
+    /// Every failure found, from `/v1/intents/validate`; `error_detail` is the first of them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ErrorDetail>,
 }
 
 #[derive(Debug, Serialize)]
@@ -182,6 +1136,19 @@ impl Intent {
             expires_at,
             matched_with: None,
             settlement_tx_hash: None,
+            failure_reason: None,
+            activate_at: None,
+            allow_extension: false,
+            max_slippage_bps: None,
+            residual_of: None,
+            near_miss_count: 0,
+            last_near_miss_at: None,
+            failed_counterparties: BTreeMap::new(),
+            callback_url: None,
+            post_only: false,
+            trigger_price: None,
+            trigger_direction: None,
+            triggered_at: None,
         }
     }
 
@@ -189,9 +1156,31 @@ impl Intent {
         Utc::now() > self.expires_at
     }
 
-    pub fn can_match(&self) -> bool {
+    /// Pending and not expired. Scheduled intents that are not active yet still count.
+    pub fn is_open(&self) -> bool {
         self.status == IntentStatus::Pending && !self.is_expired()
     }
+
+    /// Past its `activate_at`, and triggered if it is a conditional intent.
+    pub fn is_active(&self) -> bool {
+        self.activate_at.map_or(true, |at| Utc::now() >= at) && self.is_triggered()
+    }
+
+    /// Not conditional, or its trigger has fired.
+    pub fn is_triggered(&self) -> bool {
+        self.trigger_price.is_none() || self.triggered_at.is_some()
+    }
+
+    pub fn can_match(&self) -> bool {
+        self.is_open() && self.is_active()
+    }
+
+    /// A match with `counterparty` failed and its cooldown hasn't run out yet.
+    pub fn failed_recently_with(&self, counterparty: &str) -> bool {
+        self.failed_counterparties
+            .get(counterparty)
+            .map_or(false, |until| Utc::now() < *until)
+    }
 }
 
 impl MatchedPair {
@@ -199,10 +1188,12 @@ impl MatchedPair {
         Self {
             id: Uuid::new_v4().to_string(),
             matched_at: Utc::now(),
-            expected_profit: 0.0, // TODO: Calculate based on spread
+            // The matcher fills this in from the amounts (`expected_profit`).
+            expected_profit: 0.0,
             settlement_data,
             intent_a,
             intent_b,
+            fill: None,
         }
     }
 }
@@ -0,0 +1,974 @@
+use num_bigint::BigUint;
+use num_traits::{Num, ToPrimitive, Zero};
+use serde::Serialize;
+use starknet::core::types::Felt;
+use starknet::core::utils::{cairo_short_string_to_felt, get_selector_from_name};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{OnceCell, RwLock};
+use tracing::{debug, error, warn};
+
+use crate::rpc::RpcClient;
+use crate::starknet::token_symbol_for;
+
+/// Decimals used for derived cross rates (`price_raw` is scaled by `10^CROSS_RATE_DECIMALS`).
+pub const CROSS_RATE_DECIMALS: u32 = 18;
+
+/// Keep cache short to avoid stale prices while still reducing RPC pressure.
+const PRICE_CACHE_TTL_SECONDS: u64 = 30;
+
+/// Attempts at `get_oracle_address` per spot fallback; the delay doubles from the base each time.
+const ORACLE_ADDRESS_ATTEMPTS: u32 = 3;
+const ORACLE_ADDRESS_RETRY_BASE_MS: u64 = 200;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OracleError {
+    #[error("{0}")]
+    InvalidPairId(&'static str),
+    #[error("{0}")]
+    Internal(&'static str),
+    #[error("{0}")]
+    Upstream(&'static str),
+}
+
+/// A single Pragma price as returned by the TWAP (or spot median fallback) call.
+#[derive(Debug, Clone, Serialize)]
+pub struct PragmaPrice {
+    pub source: String,
+    /// The oracle source (`pragma`, or a name from `ORACLE_SOURCES`) that served the price.
+    pub provider: String,
+    pub pair_id: String,
+    pub window_seconds: u64,
+    pub start_time: u64,
+    pub price_raw: String,
+    pub decimals_raw: String,
+    /// Unix seconds of the feed's last update (`last_updated_timestamp` of the spot median). Known
+    /// for spot prices, and for TWAPs from a source with the freshness probe on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_updated: Option<u64>,
+}
+
+impl PragmaPrice {
+    /// Price as (integer mantissa, decimals).
+    pub fn scaled(&self) -> Option<(BigUint, u32)> {
+        let price = parse_felt_str(&self.price_raw)?;
+        let decimals = parse_felt_str(&self.decimals_raw)?.to_u32()?;
+        Some((price, decimals))
+    }
+}
+
+/// One source's answer in a `ConsensusPrice`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SourcePrice {
+    pub provider: String,
+    pub weight: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<PragmaPrice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Distance from the consensus price in basis points; `None` when the source gave no price.
+    pub deviation_bps: Option<u64>,
+    pub deviates: bool,
+}
+
+/// The weighted median of every source's price for a pair, with the per-source answers.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsensusPrice {
+    /// The answer of the source holding the weighted median.
+    pub price: PragmaPrice,
+    pub sources: Vec<SourcePrice>,
+}
+
+/// A price derived from two USD-quoted legs (e.g. ETH/USDC from ETH/USD and USDC/USD).
+#[derive(Debug, Clone, Serialize)]
+pub struct CrossRate {
+    pub pair_id: String,
+    pub window_seconds: u64,
+    /// Decimal integer string, scaled by `10^decimals`.
+    pub price_raw: String,
+    pub decimals: u32,
+    /// The Pragma prices the rate was derived from. A `USD` side contributes no leg.
+    pub legs: Vec<PragmaPrice>,
+}
+
+impl CrossRate {
+    /// Unix seconds of the oldest leg's last update; `None` when a leg doesn't say. A rate without
+    /// legs (`USD/USD`) never goes stale.
+    pub fn last_updated(&self) -> Option<u64> {
+        self.legs.iter().try_fold(u64::MAX, |oldest, leg| Some(oldest.min(leg.last_updated?)))
+    }
+
+    /// Some leg was last updated more than `max_age_seconds` before `now`, or can't tell.
+    pub fn is_stale(&self, now: u64, max_age_seconds: u64) -> bool {
+        self.last_updated().map_or(true, |at| now.saturating_sub(at) > max_age_seconds)
+    }
+}
+
+#[derive(Debug)]
+struct CachedPragmaPrice {
+    expires_at: u64,
+    price: PragmaPrice,
+    /// `PriceCache::clock` value at the last hit; the smallest one is evicted first.
+    last_used: AtomicU64,
+}
+
+/// Counters for `GET /v1/metrics`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PriceCacheMetrics {
+    pub entries: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+/// Price cache keyed by `(pair_id, window)`, capped at `capacity` entries with LRU eviction.
+/// Lookups only need `&self`, so hits are served under the read lock.
+struct PriceCache {
+    capacity: usize,
+    entries: HashMap<String, CachedPragmaPrice>,
+    clock: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl PriceCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            clock: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    fn get(&self, key: &str, now: u64) -> Option<PragmaPrice> {
+        match self.entries.get(key).filter(|entry| now < entry.expires_at) {
+            Some(entry) => {
+                let tick = self.clock.fetch_add(1, Ordering::Relaxed);
+                entry.last_used.store(tick, Ordering::Relaxed);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.price.clone())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, key: String, price: PragmaPrice, expires_at: u64, now: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            self.purge_expired(now);
+        }
+        while !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used.load(Ordering::Relaxed))
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        let tick = self.clock.fetch_add(1, Ordering::Relaxed);
+        self.entries.insert(
+            key,
+            CachedPragmaPrice { expires_at, price, last_used: AtomicU64::new(tick) },
+        );
+    }
+
+    /// Drops expired entries; returns how many were removed.
+    fn purge_expired(&mut self, now: u64) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| now < entry.expires_at);
+        before - self.entries.len()
+    }
+
+    fn metrics(&self) -> PriceCacheMetrics {
+        PriceCacheMetrics {
+            entries: self.entries.len(),
+            capacity: self.capacity,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// One Pragma deployment (a summary stats contract behind an RPC endpoint) with its own price cache.
+pub struct PragmaSource {
+    name: String,
+    weight: u32,
+    rpc: RpcClient,
+    summary_stats_address: Felt,
+    /// Spot-median oracle. Only a successful resolution is stored, so a failed one is retried on the
+    /// next fallback; `PRAGMA_ORACLE_ADDRESS` pre-fills it and skips resolution.
+    oracle_address: OnceCell<Felt>,
+    price_cache: RwLock<PriceCache>,
+    /// Also read the spot median's `last_updated_timestamp` for TWAPs, which don't carry one.
+    probe_freshness: bool,
+}
+
+impl PragmaSource {
+    /// `price_cache_capacity` caps the `(pair_id, window)` cache; 0 disables caching.
+    /// `oracle_address` pins the spot-median oracle instead of asking the summary stats contract.
+    pub fn new(
+        name: &str,
+        weight: u32,
+        rpc: RpcClient,
+        summary_stats_address: Felt,
+        oracle_address: Option<Felt>,
+        price_cache_capacity: usize,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            weight: weight.max(1),
+            rpc,
+            summary_stats_address,
+            oracle_address: OnceCell::new_with(oracle_address),
+            price_cache: RwLock::new(PriceCache::new(price_cache_capacity)),
+            probe_freshness: false,
+        }
+    }
+
+    /// For `MAX_ORACLE_STALENESS_SECONDS`: every TWAP costs one more call, for its update time.
+    pub fn with_freshness_probe(mut self) -> Self {
+        self.probe_freshness = true;
+        self
+    }
+
+    async fn jsonrpc_starknet_call(
+        &self,
+        contract_address: Felt,
+        selector: Felt,
+        calldata: Vec<Felt>,
+    ) -> Result<serde_json::Value, reqwest::Error> {
+        self.rpc
+            .starknet_call(contract_address, selector, calldata, None)
+            .await
+    }
+
+    /// The Pragma oracle behind the summary stats contract, resolved on first use.
+    async fn oracle_address(&self) -> Result<Felt, OracleError> {
+        self.oracle_address
+            .get_or_try_init(|| async {
+                let mut attempt = 1;
+                loop {
+                    match self.resolve_oracle_address().await {
+                        Ok(address) => return Ok(address),
+                        Err(e) if attempt < ORACLE_ADDRESS_ATTEMPTS => {
+                            let delay_ms = ORACLE_ADDRESS_RETRY_BASE_MS << (attempt - 1);
+                            warn!("{} (attempt {}); retrying in {}ms", e, attempt, delay_ms);
+                            tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+                            attempt += 1;
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            })
+            .await
+            .copied()
+            .map_err(|e| {
+                error!("{}", e);
+                OracleError::Upstream("Failed to resolve Pragma oracle address")
+            })
+    }
+
+    async fn resolve_oracle_address(&self) -> anyhow::Result<Felt> {
+        // get_oracle_address() -> ContractAddress
+        let selector = get_selector_from_name("get_oracle_address")
+            .map_err(|_| anyhow::anyhow!("Failed to build selector"))?;
+        let json = self
+            .jsonrpc_starknet_call(self.summary_stats_address, selector, vec![])
+            .await
+            .map_err(|e| anyhow::anyhow!("Pragma oracle address RPC request failed: {}", e))?;
+        parse_oracle_address(&json)
+            .ok_or_else(|| anyhow::anyhow!("Failed to resolve Pragma oracle address: {}", json))
+    }
+
+    /// Fetches `pair_id` (a Pragma pair such as `ETH/USD`) as a TWAP over `window_seconds`,
+    /// falling back to the oracle's spot median when the TWAP is unavailable.
+    pub async fn get_price(&self, pair_id: &str, window_seconds: Option<u64>) -> Result<PragmaPrice, OracleError> {
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        let window_seconds = window_seconds.unwrap_or(3600).max(1).min(24 * 60 * 60);
+        let start_time = now.saturating_sub(window_seconds);
+
+        let pair_id = pair_id.trim().to_string();
+        if pair_id.is_empty() || pair_id.len() > 31 {
+            return Err(OracleError::InvalidPairId("pair_id is required and must be <= 31 chars"));
+        }
+
+        let pair_felt = cairo_short_string_to_felt(&pair_id)
+            .map_err(|_| OracleError::InvalidPairId("pair_id must be a Cairo short string"))?;
+
+        // Serve cached response to avoid hammering the RPC/Pragma contracts (and spamming logs)
+        // when the frontend recalculates slippage frequently.
+        // Cache per (pair_id, window_seconds) for a short TTL.
+        let cache_key = format!("{}:{}", pair_id, window_seconds);
+        if let Some(price) = self.price_cache.read().await.get(&cache_key, now) {
+            return Ok(price);
+        }
+
+        // Selector: calculate_twap
+        let selector = get_selector_from_name("calculate_twap")
+            .map_err(|_| OracleError::Internal("Failed to build selector"))?;
+
+        // Send JSON-RPC directly to avoid client incompatibilities across providers.
+        // Some testnets may not have enough checkpoints for TWAP; in that case we fall back to Pragma's spot median.
+        let mut source = "pragma_twap".to_string();
+        let json = self
+            .jsonrpc_starknet_call(
+                self.summary_stats_address,
+                selector,
+                vec![
+                    // DataType::SpotEntry(pair_id)
+                    Felt::ZERO,
+                    pair_felt,
+                    // AggregationMode::Median(())
+                    Felt::ZERO,
+                    Felt::from(window_seconds),
+                    Felt::from(start_time),
+                ],
+            )
+            .await
+            .map_err(|e| {
+                error!("Pragma TWAP RPC request failed: {}", e);
+                OracleError::Upstream("Failed to reach Starknet RPC")
+            })?;
+
+        fn is_not_enough_data_error(payload: &serde_json::Value) -> bool {
+            // Pragma testnet TWAP often reverts with "Not enough data".
+            // Treat that as a normal "TWAP unavailable" situation and fall back without error-level logging.
+            payload
+                .get("error")
+                .and_then(|e| e.get("data"))
+                .and_then(|d| d.get("revert_error"))
+                .and_then(|re| re.get("error"))
+                .and_then(|v| v.as_str())
+                .map(|s| s.contains("Not enough data") || s.contains("0x4e6f7420656e6f7567682064617461"))
+                .unwrap_or(false)
+        }
+
+        // If the TWAP call errors (e.g., "Not enough data" on testnets), try spot median from the oracle contract.
+        let json = if json.get("error").is_some() {
+            if is_not_enough_data_error(&json) {
+                debug!("Pragma TWAP not available (Not enough data); falling back to spot median");
+            } else {
+                warn!("Pragma TWAP RPC returned error payload; falling back to spot median: {}", json);
+            }
+
+            let oracle_addr = self.oracle_address().await?;
+
+            // get_data_median(DataType) -> PragmaPricesResponse
+            let spot_selector = get_selector_from_name("get_data_median")
+                .map_err(|_| OracleError::Internal("Failed to build selector"))?;
+
+            source = "pragma_spot_median".to_string();
+            self.jsonrpc_starknet_call(
+                oracle_addr,
+                spot_selector,
+                vec![
+                    // DataType::SpotEntry(pair_id)
+                    Felt::ZERO,
+                    pair_felt,
+                ],
+            )
+            .await
+            .map_err(|e| {
+                error!("Pragma spot median RPC request failed: {}", e);
+                OracleError::Upstream("Failed to reach Starknet RPC")
+            })?
+        } else {
+            json
+        };
+
+        let result = json
+            .get("result")
+            .and_then(|v| v.as_array())
+            .ok_or(OracleError::Upstream("TWAP response missing fields"))?;
+
+        if result.len() < 2 {
+            return Err(OracleError::Upstream("TWAP response missing fields"));
+        }
+
+        let price_raw = result[0].as_str().unwrap_or_default().to_string();
+        let decimals_raw = result[1].as_str().unwrap_or_default().to_string();
+        if price_raw.is_empty() || decimals_raw.is_empty() {
+            return Err(OracleError::Upstream("TWAP response missing fields"));
+        }
+
+        let last_updated = if source == "pragma_spot_median" {
+            parse_last_updated(result)
+        } else if self.probe_freshness {
+            self.spot_last_updated(pair_felt).await
+        } else {
+            None
+        };
+        let price = PragmaPrice {
+            source,
+            provider: self.name.clone(),
+            pair_id,
+            window_seconds,
+            start_time,
+            price_raw,
+            decimals_raw,
+            last_updated,
+        };
+
+        self.price_cache.write().await.insert(
+            cache_key,
+            price.clone(),
+            now.saturating_add(PRICE_CACHE_TTL_SECONDS),
+            now,
+        );
+
+        Ok(price)
+    }
+
+    /// `last_updated_timestamp` of the spot median for `pair_felt`, or `None` if it can't be read.
+    async fn spot_last_updated(&self, pair_felt: Felt) -> Option<u64> {
+        let oracle_addr = self.oracle_address().await.ok()?;
+        let selector = get_selector_from_name("get_data_median").ok()?;
+        let json = match self
+            .jsonrpc_starknet_call(oracle_addr, selector, vec![Felt::ZERO, pair_felt])
+            .await
+        {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("Pragma freshness probe RPC request failed: {}", e);
+                return None;
+            }
+        };
+        let updated = json.get("result").and_then(|v| v.as_array()).and_then(|r| parse_last_updated(r));
+        if updated.is_none() {
+            warn!("Pragma freshness probe returned no update time: {}", json);
+        }
+        updated
+    }
+}
+
+/// `last_updated_timestamp` out of a `PragmaPricesResponse`
+/// (`price, decimals, last_updated_timestamp, num_sources_aggregated, ...`).
+fn parse_last_updated(result: &[serde_json::Value]) -> Option<u64> {
+    parse_felt_str(result.get(2)?.as_str()?)?.to_u64().filter(|t| *t > 0)
+}
+
+/// Pragma price reads over Starknet JSON-RPC, spread over one or more sources. Cheap to share
+/// behind an `Arc`; caches are internal, one per source.
+pub struct PragmaOracle {
+    sources: Vec<PragmaSource>,
+    /// Smooth weighted round-robin state: each source's current weight.
+    rotation: std::sync::Mutex<Vec<i64>>,
+    deviation_bps: u32,
+}
+
+impl PragmaOracle {
+    /// `sources` must not be empty; the first is the primary. `deviation_bps` is how far from the
+    /// consensus a source may be before it is flagged.
+    pub fn new(sources: Vec<PragmaSource>, deviation_bps: u32) -> Self {
+        assert!(!sources.is_empty(), "at least one oracle source is required");
+        Self {
+            rotation: std::sync::Mutex::new(vec![0; sources.len()]),
+            sources,
+            deviation_bps,
+        }
+    }
+
+    /// Cache counters summed over all sources.
+    pub async fn price_cache_metrics(&self) -> PriceCacheMetrics {
+        let mut total = PriceCacheMetrics {
+            entries: 0,
+            capacity: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        };
+        for source in &self.sources {
+            let metrics = source.price_cache.read().await.metrics();
+            total.entries += metrics.entries;
+            total.capacity += metrics.capacity;
+            total.hits += metrics.hits;
+            total.misses += metrics.misses;
+            total.evictions += metrics.evictions;
+        }
+        total
+    }
+
+    /// The source to ask first, by smooth weighted round-robin, followed by the others (heaviest
+    /// first) to fall back on.
+    fn rotation_order(&self) -> Vec<usize> {
+        let weights: Vec<u32> = self.sources.iter().map(|s| s.weight).collect();
+        let first = next_weighted(&mut self.rotation.lock().expect("oracle rotation lock"), &weights);
+        let mut rest: Vec<usize> = (0..self.sources.len()).filter(|i| *i != first).collect();
+        rest.sort_by(|a, b| weights[*b].cmp(&weights[*a]).then_with(|| a.cmp(b)));
+        std::iter::once(first).chain(rest).collect()
+    }
+
+    /// Fetches `pair_id` (a Pragma pair such as `ETH/USD`) as a TWAP over `window_seconds`, from
+    /// one source picked by weight, falling back to the others if it fails.
+    pub async fn get_price(&self, pair_id: &str, window_seconds: Option<u64>) -> Result<PragmaPrice, OracleError> {
+        let mut last_error = None;
+        for idx in self.rotation_order() {
+            let source = &self.sources[idx];
+            match source.get_price(pair_id, window_seconds).await {
+                Ok(price) => return Ok(price),
+                Err(e @ OracleError::InvalidPairId(_)) => return Err(e),
+                Err(e) => {
+                    if self.sources.len() > 1 {
+                        warn!("Oracle source {} failed for {}: {}; trying the next one", source.name, pair_id, e);
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or(OracleError::Internal("No oracle source configured")))
+    }
+
+    /// Asks every source for `pair_id` and returns the weighted median price with each source's
+    /// answer, flagging those more than `ORACLE_DEVIATION_BPS` from it.
+    pub async fn get_consensus_price(
+        &self,
+        pair_id: &str,
+        window_seconds: Option<u64>,
+    ) -> Result<ConsensusPrice, OracleError> {
+        let answers =
+            futures::future::join_all(self.sources.iter().map(|s| s.get_price(pair_id, window_seconds))).await;
+        if let Some(Err(OracleError::InvalidPairId(msg))) = answers.first() {
+            return Err(OracleError::InvalidPairId(*msg));
+        }
+
+        let values: Vec<Option<BigUint>> = answers
+            .iter()
+            .map(|a| a.as_ref().ok().and_then(normalized_price))
+            .collect();
+        let weighted: Vec<(usize, &BigUint, u32)> = values
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, v)| v.as_ref().map(|v| (idx, v, self.sources[idx].weight)))
+            .collect();
+        let Some(median_idx) = weighted_median(&weighted) else {
+            return Err(answers
+                .into_iter()
+                .find_map(Result::err)
+                .unwrap_or(OracleError::Upstream("TWAP response missing fields")));
+        };
+        let median = values[median_idx].clone().expect("median source has a price");
+
+        let mut price = None;
+        let mut sources = Vec::with_capacity(answers.len());
+        for (idx, answer) in answers.into_iter().enumerate() {
+            let source = &self.sources[idx];
+            let deviation_bps = values[idx].as_ref().and_then(|v| deviation_bps(v, &median));
+            let deviates = deviation_bps.map_or(false, |d| d > u64::from(self.deviation_bps));
+            if deviates {
+                warn!(
+                    "Oracle source {} deviates from consensus for {} by {} bps",
+                    source.name,
+                    pair_id,
+                    deviation_bps.unwrap_or_default()
+                );
+            }
+            let (answer, error) = match answer {
+                Ok(p) => (Some(p), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            if idx == median_idx {
+                price = answer.clone();
+            }
+            sources.push(SourcePrice {
+                provider: source.name.clone(),
+                weight: source.weight,
+                price: answer,
+                error,
+                deviation_bps,
+                deviates,
+            });
+        }
+        Ok(ConsensusPrice {
+            price: price.expect("median source answered"),
+            sources,
+        })
+    }
+
+    /// Derives `base/quote` from the `base/USD` and `quote/USD` Pragma feeds. Either side may be
+    /// a symbol (`ETH`) or a known token address; `USD` on either side needs no leg. Each leg is
+    /// served from (and stored in) the per-pair price cache.
+    pub async fn get_cross_rate(
+        &self,
+        base: &str,
+        quote: &str,
+        window_seconds: Option<u64>,
+    ) -> Result<CrossRate, OracleError> {
+        self.cross_rate(base, quote, window_seconds, false).await
+    }
+
+    /// Like `get_cross_rate`, with each leg the consensus of every source rather than one
+    /// source's answer. This is what matches are held to.
+    pub async fn get_consensus_cross_rate(
+        &self,
+        base: &str,
+        quote: &str,
+        window_seconds: Option<u64>,
+    ) -> Result<CrossRate, OracleError> {
+        self.cross_rate(base, quote, window_seconds, true).await
+    }
+
+    async fn cross_rate(
+        &self,
+        base: &str,
+        quote: &str,
+        window_seconds: Option<u64>,
+        consensus: bool,
+    ) -> Result<CrossRate, OracleError> {
+        let base = resolve_symbol(base).ok_or(OracleError::InvalidPairId("unknown base asset"))?;
+        let quote = resolve_symbol(quote).ok_or(OracleError::InvalidPairId("unknown quote asset"))?;
+
+        let base_leg = if base == "USD" {
+            None
+        } else {
+            Some(self.leg(&format!("{}/USD", base), window_seconds, consensus).await?)
+        };
+        let quote_leg = if quote == "USD" {
+            None
+        } else {
+            Some(self.leg(&format!("{}/USD", quote), window_seconds, consensus).await?)
+        };
+
+        fn usd_value(leg: Option<&PragmaPrice>) -> Result<(BigUint, u32), OracleError> {
+            match leg {
+                None => Ok((BigUint::from(1u8), 0)),
+                Some(price) => price
+                    .scaled()
+                    .ok_or(OracleError::Upstream("TWAP response missing fields")),
+            }
+        }
+        let (base_usd, base_decimals) = usd_value(base_leg.as_ref())?;
+        let (quote_usd, quote_decimals) = usd_value(quote_leg.as_ref())?;
+
+        let price = derive_cross_rate(&base_usd, base_decimals, &quote_usd, quote_decimals, CROSS_RATE_DECIMALS)
+            .ok_or(OracleError::Upstream("Oracle returned a zero quote price"))?;
+
+        let window_seconds = base_leg
+            .as_ref()
+            .or(quote_leg.as_ref())
+            .map(|p| p.window_seconds)
+            .unwrap_or_else(|| window_seconds.unwrap_or(3600));
+
+        Ok(CrossRate {
+            pair_id: format!("{}/{}", base, quote),
+            window_seconds,
+            price_raw: price.to_str_radix(10),
+            decimals: CROSS_RATE_DECIMALS,
+            legs: base_leg.into_iter().chain(quote_leg).collect(),
+        })
+    }
+
+    async fn leg(&self, pair_id: &str, window_seconds: Option<u64>, consensus: bool) -> Result<PragmaPrice, OracleError> {
+        if consensus {
+            Ok(self.get_consensus_price(pair_id, window_seconds).await?.price)
+        } else {
+            self.get_price(pair_id, window_seconds).await
+        }
+    }
+}
+
+/// Upper-cased Pragma symbol for a symbol or known token address.
+fn resolve_symbol(asset: &str) -> Option<String> {
+    let asset = asset.trim();
+    if asset.is_empty() {
+        return None;
+    }
+    if asset.starts_with("0x") || asset.starts_with("0X") {
+        return token_symbol_for(asset).map(ToString::to_string);
+    }
+    Some(asset.to_uppercase())
+}
+
+fn parse_felt_str(value: &str) -> Option<BigUint> {
+    let v = value.trim();
+    match v.strip_prefix("0x").or_else(|| v.strip_prefix("0X")) {
+        Some(hex) => BigUint::from_str_radix(hex, 16).ok(),
+        None => BigUint::from_str_radix(v, 10).ok(),
+    }
+}
+
+/// Purges expired prices every cache TTL, so entries for pairs nobody asks for again are freed.
+pub fn spawn_price_cache_purge(oracle: Arc<PragmaOracle>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(PRICE_CACHE_TTL_SECONDS));
+        loop {
+            ticker.tick().await;
+            let now = chrono::Utc::now().timestamp().max(0) as u64;
+            let mut purged = 0;
+            for source in &oracle.sources {
+                purged += source.price_cache.write().await.purge_expired(now);
+            }
+            if purged > 0 {
+                debug!("Purged {} expired Pragma prices", purged);
+            }
+        }
+    });
+}
+
+/// `(base_usd / 10^base_decimals) / (quote_usd / 10^quote_decimals)`, scaled by `10^out_decimals`.
+/// Integer arithmetic throughout; returns `None` for a zero quote price.
+pub fn derive_cross_rate(
+    base_usd: &BigUint,
+    base_decimals: u32,
+    quote_usd: &BigUint,
+    quote_decimals: u32,
+    out_decimals: u32,
+) -> Option<BigUint> {
+    if quote_usd.is_zero() {
+        return None;
+    }
+    let ten = BigUint::from(10u8);
+    let numerator = base_usd * ten.pow(quote_decimals + out_decimals);
+    let denominator = quote_usd * ten.pow(base_decimals);
+    Some(numerator / denominator)
+}
+
+/// Value of `base_units` (a token with `token_decimals`) at `rate` (token/USD), scaled by `10^out_decimals`.
+pub fn usd_value(base_units: &BigUint, token_decimals: u32, rate: &CrossRate, out_decimals: u32) -> Option<BigUint> {
+    let price = BigUint::from_str_radix(&rate.price_raw, 10).ok()?;
+    let ten = BigUint::from(10u8);
+    Some(base_units * price * ten.pow(out_decimals) / ten.pow(token_decimals + rate.decimals))
+}
+
+/// Decimals prices are brought to before sources are compared.
+const CONSENSUS_DECIMALS: u32 = 18;
+
+/// `price` scaled to `CONSENSUS_DECIMALS`, so sources reporting different decimals compare.
+fn normalized_price(price: &PragmaPrice) -> Option<BigUint> {
+    let (mantissa, decimals) = price.scaled()?;
+    let ten = BigUint::from(10u8);
+    Some(if decimals <= CONSENSUS_DECIMALS {
+        mantissa * ten.pow(CONSENSUS_DECIMALS - decimals)
+    } else {
+        mantissa / ten.pow(decimals - CONSENSUS_DECIMALS)
+    })
+}
+
+/// Index of the weighted median among `(index, value, weight)`: the smallest value at which the
+/// cumulative weight reaches half the total. `None` when empty.
+fn weighted_median(values: &[(usize, &BigUint, u32)]) -> Option<usize> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.1.cmp(b.1).then_with(|| a.0.cmp(&b.0)));
+    let total: u64 = sorted.iter().map(|(_, _, w)| u64::from(*w)).sum();
+    let mut cumulative = 0u64;
+    for (idx, _, weight) in sorted {
+        cumulative += u64::from(weight);
+        if cumulative * 2 >= total {
+            return Some(idx);
+        }
+    }
+    None
+}
+
+/// `|value - reference| / reference` in basis points; `None` for a zero reference.
+fn deviation_bps(value: &BigUint, reference: &BigUint) -> Option<u64> {
+    if reference.is_zero() {
+        return None;
+    }
+    let diff = if value > reference { value - reference } else { reference - value };
+    (diff * 10_000u32 / reference).to_u64().or(Some(u64::MAX))
+}
+
+/// Next source under smooth weighted round-robin: every source gains its weight, the largest
+/// current weight wins and pays back the total. Spreads picks evenly in proportion to weight.
+fn next_weighted(current: &mut [i64], weights: &[u32]) -> usize {
+    let total: i64 = weights.iter().map(|w| i64::from(*w)).sum();
+    let mut best = 0;
+    for (idx, weight) in weights.iter().enumerate() {
+        current[idx] += i64::from(*weight);
+        if current[idx] > current[best] {
+            best = idx;
+        }
+    }
+    current[best] -= total;
+    best
+}
+
+/// The address in a `get_oracle_address` envelope. An error envelope or a zero address (an
+/// unconfigured summary stats contract) resolves nothing.
+fn parse_oracle_address(json: &serde_json::Value) -> Option<Felt> {
+    let raw = json.get("result")?.as_array()?.first()?.as_str()?;
+    Felt::from_hex(raw).ok().filter(|address| *address != Felt::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derive_cross_rate_handles_mismatched_leg_decimals() {
+        // ETH/USD = 2500.00 (8 decimals), USDC/USD = 0.998 (6 decimals).
+        let eth = BigUint::from(250_000_000_000u64);
+        let usdc = BigUint::from(998_000u64);
+        let cross = derive_cross_rate(&eth, 8, &usdc, 6, 6).expect("cross");
+        // 2500 / 0.998 = 2505.010020...
+        assert_eq!(cross, BigUint::from(2_505_010_020u64));
+    }
+
+    #[test]
+    fn derive_cross_rate_against_usd_is_identity() {
+        let eth = BigUint::from(250_000_000_000u64);
+        let cross = derive_cross_rate(&eth, 8, &BigUint::from(1u8), 0, 8).expect("cross");
+        assert_eq!(cross, eth);
+    }
+
+    #[test]
+    fn derive_cross_rate_rejects_zero_quote() {
+        assert!(derive_cross_rate(&BigUint::from(1u8), 0, &BigUint::from(0u8), 0, 18).is_none());
+    }
+
+    #[test]
+    fn pragma_price_scaled_parses_hex_fields() {
+        let price = PragmaPrice {
+            source: "pragma_twap".to_string(),
+            provider: "pragma".to_string(),
+            pair_id: "ETH/USD".to_string(),
+            window_seconds: 3600,
+            start_time: 0,
+            price_raw: "0x3a35294400".to_string(),
+            decimals_raw: "0x8".to_string(),
+            last_updated: None,
+        };
+        assert_eq!(price.scaled(), Some((BigUint::from(250_000_000_000u64), 8)));
+    }
+
+    fn price(pair_id: &str) -> PragmaPrice {
+        PragmaPrice {
+            source: "pragma_twap".to_string(),
+            provider: "pragma".to_string(),
+            pair_id: pair_id.to_string(),
+            window_seconds: 3600,
+            start_time: 0,
+            price_raw: "0x1".to_string(),
+            decimals_raw: "0x8".to_string(),
+            last_updated: None,
+        }
+    }
+
+    #[test]
+    fn cross_rates_are_as_fresh_as_their_oldest_leg() {
+        let leg = |updated: Option<u64>| PragmaPrice { last_updated: updated, ..price("ETH/USD") };
+        let rate = |legs: Vec<PragmaPrice>| CrossRate {
+            pair_id: "ETH/USDC".to_string(),
+            window_seconds: 3600,
+            price_raw: "1".to_string(),
+            decimals: CROSS_RATE_DECIMALS,
+            legs,
+        };
+        let both = rate(vec![leg(Some(1_000)), leg(Some(900))]);
+        assert_eq!(both.last_updated(), Some(900));
+        assert!(!both.is_stale(1_000, 100));
+        assert!(both.is_stale(1_001, 100));
+        assert!(rate(vec![leg(Some(1_000)), leg(None)]).is_stale(1_000, 100), "unknown counts as stale");
+        assert!(!rate(vec![]).is_stale(u64::MAX, 0));
+
+        let spot = |fields: &[&str]| parse_last_updated(&fields.iter().map(|f| serde_json::json!(f)).collect::<Vec<_>>());
+        assert_eq!(spot(&["0x3a35294400", "0x8", "0x6592cd40", "0x5"]), Some(1_704_119_616));
+        assert_eq!(spot(&["0x3a35294400", "0x8", "0x0"]), None);
+        assert_eq!(spot(&["0x3a35294400", "0x8"]), None);
+    }
+
+    #[test]
+    fn weighted_round_robin_follows_weights() {
+        let mut current = vec![0; 3];
+        let picks: Vec<usize> = (0..6).map(|_| next_weighted(&mut current, &[3, 2, 1])).collect();
+        assert_eq!(picks.iter().filter(|i| **i == 0).count(), 3);
+        assert_eq!(picks.iter().filter(|i| **i == 1).count(), 2);
+        assert_eq!(picks.iter().filter(|i| **i == 2).count(), 1);
+        // Smooth: the heaviest source doesn't take its picks back to back.
+        assert_eq!(picks, vec![0, 1, 0, 2, 1, 0]);
+    }
+
+    #[test]
+    fn consensus_is_the_weighted_median_and_flags_outliers() {
+        let (low, mid, high) = (BigUint::from(99u8), BigUint::from(100u8), BigUint::from(150u8));
+        assert_eq!(weighted_median(&[(0, &high, 1), (1, &low, 1), (2, &mid, 1)]), Some(2));
+        // A heavy source pulls the median onto itself.
+        assert_eq!(weighted_median(&[(0, &high, 5), (1, &low, 1), (2, &mid, 1)]), Some(0));
+        assert_eq!(weighted_median(&[]), None);
+
+        assert_eq!(deviation_bps(&low, &mid), Some(100));
+        assert_eq!(deviation_bps(&high, &mid), Some(5000));
+        assert_eq!(deviation_bps(&mid, &BigUint::from(0u8)), None);
+
+        let mut eight = price("ETH/USD");
+        eight.price_raw = "0x2540be400".to_string(); // 100 with 8 decimals
+        let mut eighteen = price("ETH/USD");
+        eighteen.price_raw = "100000000000000000000".to_string();
+        eighteen.decimals_raw = "18".to_string();
+        assert_eq!(normalized_price(&eight), normalized_price(&eighteen));
+    }
+
+    #[test]
+    fn oracle_address_resolves_only_from_a_nonzero_result() {
+        let parse = |v: serde_json::Value| parse_oracle_address(&v);
+        assert_eq!(parse(serde_json::json!({ "result": ["0x2a"] })), Some(Felt::from(42u8)));
+        assert_eq!(parse(serde_json::json!({ "result": ["0x0"] })), None);
+        assert_eq!(parse(serde_json::json!({ "result": [] })), None);
+        assert_eq!(parse(serde_json::json!({ "error": { "message": "Contract not found" } })), None);
+    }
+
+    #[test]
+    fn price_cache_evicts_least_recently_used_at_capacity() {
+        let mut cache = PriceCache::new(2);
+        cache.insert("ETH/USD:3600".into(), price("ETH/USD"), 100, 0);
+        cache.insert("STRK/USD:3600".into(), price("STRK/USD"), 100, 0);
+        assert!(cache.get("ETH/USD:3600", 1).is_some());
+
+        cache.insert("USDC/USD:3600".into(), price("USDC/USD"), 100, 1);
+        assert!(cache.get("STRK/USD:3600", 2).is_none(), "least recently used entry is evicted");
+        assert!(cache.get("ETH/USD:3600", 2).is_some());
+        assert!(cache.get("USDC/USD:3600", 2).is_some());
+
+        let metrics = cache.metrics();
+        assert_eq!((metrics.entries, metrics.hits, metrics.misses, metrics.evictions), (2, 3, 1, 1));
+    }
+
+    #[test]
+    fn price_cache_prefers_purging_expired_entries() {
+        let mut cache = PriceCache::new(2);
+        cache.insert("ETH/USD:3600".into(), price("ETH/USD"), 10, 0);
+        cache.insert("STRK/USD:3600".into(), price("STRK/USD"), 100, 0);
+        assert!(cache.get("ETH/USD:3600", 20).is_none(), "expired entries are misses");
+
+        cache.insert("USDC/USD:3600".into(), price("USDC/USD"), 100, 20);
+        assert!(cache.get("STRK/USD:3600", 21).is_some());
+        assert_eq!(cache.metrics().evictions, 0);
+        assert_eq!(cache.purge_expired(200), 2);
+
+        let mut disabled = PriceCache::new(0);
+        disabled.insert("ETH/USD:3600".into(), price("ETH/USD"), 100, 0);
+        assert_eq!(disabled.metrics().entries, 0);
+    }
+
+    #[test]
+    fn usd_value_scales_token_and_rate_decimals() {
+        let rate = CrossRate {
+            pair_id: "ETH/USD".to_string(),
+            window_seconds: 3600,
+            // 2500.5 at 18 decimals
+            price_raw: "2500500000000000000000".to_string(),
+            decimals: CROSS_RATE_DECIMALS,
+            legs: vec![],
+        };
+        // 1.5 ETH (18 decimals) -> 3750.75 USD at 6 decimals.
+        let amount = BigUint::from(1_500_000_000_000_000_000u64);
+        assert_eq!(usd_value(&amount, 18, &rate, 6), Some(BigUint::from(3_750_750_000u64)));
+        assert_eq!(usd_value(&BigUint::from(0u8), 18, &rate, 6), Some(BigUint::from(0u8)));
+    }
+}
@@ -0,0 +1,332 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::Value;
+use starknet::core::types::{Felt, TypedData};
+use starknet::core::utils::get_selector_from_name;
+use starknet::signers::SigningKey;
+use tracing::{debug, info};
+
+use crate::starknet::{classify_revert_reason, felt_from_hex};
+
+/// Submits solver calls through an external service instead of sending (and paying for) the
+/// invoke from the solver account. Returns the transaction hash.
+///
+/// Errors use the same markers as direct sends, so the retry loop classifies them the same way:
+/// a revert the relayer reports becomes `SETTLEMENT_SIMULATION_REVERTED code=..`, and anything that
+/// says nothing about the call itself (transport, 5xx, bad responses) is `RELAYER_UNAVAILABLE`.
+#[async_trait]
+pub trait Relayer: Send + Sync {
+    async fn execute(&self, to: Felt, entrypoint: &str, calldata: &[Felt], label: &str) -> Result<String>;
+}
+
+/// AVNU-style paymaster: `POST {url}/build-typed-data` returns a SNIP-9 outside execution for the
+/// solver account as typed data, the solver signs its hash, and `POST {url}/execute` submits it.
+/// The solver account must support outside execution; the paymaster pays the gas.
+///
+/// The solver key signs only what it asked for: `settle_match` on the dark pool, plus at most one
+/// fee transfer in the fee token capped at `max_fee` (see `check_outside_execution`).
+pub struct PaymasterRelayer {
+    url: String,
+    api_key: Option<String>,
+    http: reqwest::Client,
+    account_address: Felt,
+    signing_key: SigningKey,
+    dark_pool: Felt,
+    fee_policy: RelayerFeePolicy,
+    debug_logging: bool,
+}
+
+/// The fee transfer a relayed outside execution may carry. Without `max_fee` only sponsored
+/// executions, with no fee call at all, are signed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelayerFeePolicy {
+    pub fee_token: Felt,
+    /// `SETTLEMENT_RELAYER_MAX_FEE`, in fee-token base units.
+    pub max_fee: Option<u128>,
+}
+
+impl PaymasterRelayer {
+    pub fn new(
+        url: &str,
+        api_key: Option<String>,
+        solver_address: &str,
+        private_key: &str,
+        dark_pool_address: &str,
+        fee_policy: RelayerFeePolicy,
+        debug_logging: bool,
+    ) -> Result<Self> {
+        reqwest::Url::parse(url)?;
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            api_key,
+            http: reqwest::Client::new(),
+            account_address: felt_from_hex(solver_address)?,
+            signing_key: SigningKey::from_secret_scalar(felt_from_hex(private_key)?),
+            dark_pool: felt_from_hex(dark_pool_address)?,
+            fee_policy,
+            debug_logging,
+        })
+    }
+
+    async fn post(&self, path: &str, body: &Value) -> Result<Value> {
+        let url = format!("{}/{}", self.url, path);
+        if self.debug_logging {
+            debug!("Relayer request url={} body={}", url, body);
+        }
+        let mut request = self.http.post(&url).json(body);
+        if let Some(key) = &self.api_key {
+            request = request.header("api-key", key);
+        }
+        let resp = request
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("RELAYER_UNAVAILABLE {}: {}", path, e))?;
+        let status = resp.status();
+        let body = resp
+            .json::<Value>()
+            .await
+            .map_err(|e| anyhow::anyhow!("RELAYER_UNAVAILABLE {} returned a non-JSON body ({}): {}", path, status, e))?;
+        if self.debug_logging {
+            debug!("Relayer response url={} status={} body={}", url, status, body);
+        }
+        if !status.is_success() {
+            return Err(relayer_error(status, &body));
+        }
+        Ok(body)
+    }
+}
+
+#[async_trait]
+impl Relayer for PaymasterRelayer {
+    async fn execute(&self, to: Felt, entrypoint: &str, calldata: &[Felt], label: &str) -> Result<String> {
+        if to != self.dark_pool || entrypoint != "settle_match" {
+            return Err(anyhow::anyhow!(
+                "RELAYER_REJECTED only settle_match on the dark pool is relayed (got {} on 0x{:x})",
+                entrypoint,
+                to
+            ));
+        }
+        let user_address = format!("0x{:x}", self.account_address);
+        let typed_data = self
+            .post(
+                "build-typed-data",
+                &serde_json::json!({
+                    "userAddress": user_address,
+                    "calls": [{
+                        "contractAddress": format!("0x{:x}", to),
+                        "entrypoint": entrypoint,
+                        "calldata": calldata.iter().map(|f| format!("0x{:x}", f)).collect::<Vec<_>>(),
+                    }],
+                }),
+            )
+            .await?;
+
+        let parsed: TypedData = serde_json::from_value(typed_data.clone())
+            .map_err(|e| anyhow::anyhow!("RELAYER_UNAVAILABLE build-typed-data returned invalid typed data: {}", e))?;
+        let expected = ExpectedCall { to, selector: get_selector_from_name(entrypoint)?, calldata };
+        check_outside_execution(&typed_data, &expected, &self.fee_policy)
+            .map_err(|e| anyhow::anyhow!("RELAYER_REJECTED refusing to sign typed data for {}: {}", label, e))?;
+        let hash = parsed
+            .message_hash(self.account_address)
+            .map_err(|e| anyhow::anyhow!("Failed to hash relayer typed data for {}: {}", label, e))?;
+        let signature = self
+            .signing_key
+            .sign(&hash)
+            .map_err(|e| anyhow::anyhow!("Failed to sign relayer typed data for {}: {}", label, e))?;
+
+        let body = self
+            .post(
+                "execute",
+                &serde_json::json!({
+                    "userAddress": user_address,
+                    "typedData": typed_data.to_string(),
+                    "signature": [format!("0x{:x}", signature.r), format!("0x{:x}", signature.s)],
+                }),
+            )
+            .await?;
+        let tx_hash = relayer_tx_hash(&body)?;
+        info!("Relayer accepted {}: {}", label, tx_hash);
+        Ok(tx_hash)
+    }
+}
+
+/// The one call the solver asked the relayer to execute.
+struct ExpectedCall<'a> {
+    to: Felt,
+    selector: Felt,
+    calldata: &'a [Felt],
+}
+
+/// Checks relayer typed data before it is signed: an `OutsideExecution` whose calls are exactly
+/// `expected`, optionally followed by one `transfer` of `policy.fee_token` of at most
+/// `policy.max_fee`. Both SNIP-12 revisions are accepted (`calls` / `Calls`, `to` / `To`, ...).
+fn check_outside_execution(typed_data: &Value, expected: &ExpectedCall<'_>, policy: &RelayerFeePolicy) -> Result<()> {
+    if typed_data.get("primaryType").and_then(Value::as_str) != Some("OutsideExecution") {
+        return Err(anyhow::anyhow!("primaryType is not OutsideExecution"));
+    }
+    let message = typed_data.get("message").ok_or_else(|| anyhow::anyhow!("typed data has no message"))?;
+    let calls = field(message, "calls")
+        .and_then(Value::as_array)
+        .ok_or_else(|| anyhow::anyhow!("message has no calls"))?;
+    let calls = calls.iter().map(parse_call).collect::<Result<Vec<_>>>()?;
+
+    let (first, rest) = calls.split_first().ok_or_else(|| anyhow::anyhow!("message has no calls"))?;
+    if first.0 != expected.to || first.1 != expected.selector || first.2 != expected.calldata {
+        return Err(anyhow::anyhow!("the first call is not the requested call"));
+    }
+    match rest {
+        [] => Ok(()),
+        [(to, selector, calldata)] => {
+            let max_fee = policy
+                .max_fee
+                .ok_or_else(|| anyhow::anyhow!("a fee call was added but SETTLEMENT_RELAYER_MAX_FEE is not set"))?;
+            if *to != policy.fee_token || *selector != get_selector_from_name("transfer")? {
+                return Err(anyhow::anyhow!("the extra call is not a fee-token transfer"));
+            }
+            let [_, low, high] = calldata.as_slice() else {
+                return Err(anyhow::anyhow!("the fee transfer has unexpected calldata"));
+            };
+            let amount = felt_u128(low)
+                .zip(felt_u128(high))
+                .filter(|&(_, high)| high == 0)
+                .map(|(low, _)| low)
+                .ok_or_else(|| anyhow::anyhow!("the fee transfer amount is out of range"))?;
+            if amount > max_fee {
+                return Err(anyhow::anyhow!("the fee transfer of {} exceeds the cap of {}", amount, max_fee));
+            }
+            Ok(())
+        }
+        _ => Err(anyhow::anyhow!("the message has {} calls besides the requested one", rest.len())),
+    }
+}
+
+/// `name` in a typed-data struct, matched regardless of case and of spaces vs underscores.
+fn field<'a>(object: &'a Value, name: &str) -> Option<&'a Value> {
+    object
+        .as_object()?
+        .iter()
+        .find(|(key, _)| key.to_ascii_lowercase().replace(' ', "_") == name)
+        .map(|(_, value)| value)
+}
+
+fn parse_call(call: &Value) -> Result<(Felt, Felt, Vec<Felt>)> {
+    let felt = |name: &str| {
+        field(call, name).and_then(typed_felt).ok_or_else(|| anyhow::anyhow!("call has no valid {}", name))
+    };
+    let calldata = field(call, "calldata")
+        .and_then(Value::as_array)
+        .ok_or_else(|| anyhow::anyhow!("call has no calldata"))?
+        .iter()
+        .map(|v| typed_felt(v).ok_or_else(|| anyhow::anyhow!("call has invalid calldata")))
+        .collect::<Result<Vec<_>>>()?;
+    Ok((felt("to")?, felt("selector")?, calldata))
+}
+
+/// A felt in typed data: a hex or decimal string, or a JSON number.
+fn typed_felt(value: &Value) -> Option<Felt> {
+    match value {
+        Value::String(s) if s.starts_with("0x") || s.starts_with("0X") => Felt::from_hex(s).ok(),
+        Value::String(s) => Felt::from_dec_str(s).ok(),
+        Value::Number(n) => n.as_u64().map(Felt::from),
+        _ => None,
+    }
+}
+
+fn felt_u128(value: &Felt) -> Option<u128> {
+    let bytes = value.to_bytes_be();
+    bytes[..16].iter().all(|b| *b == 0).then(|| u128::from_be_bytes(bytes[16..].try_into().unwrap()))
+}
+
+/// The transaction hash from an `execute` response (`transactionHash`, or `transaction_hash`).
+fn relayer_tx_hash(body: &Value) -> Result<String> {
+    let hash = body
+        .get("transactionHash")
+        .or_else(|| body.get("transaction_hash"))
+        .and_then(|v| v.as_str())
+        .and_then(|v| Felt::from_hex(v).ok())
+        .ok_or_else(|| anyhow::anyhow!("RELAYER_UNAVAILABLE execute response has no transaction hash: {}", body))?;
+    Ok(format!("{:?}", hash))
+}
+
+/// Maps a relayer error body (`{"messages": [..], "revertError": ".."}`) onto the retry markers.
+fn relayer_error(status: reqwest::StatusCode, body: &Value) -> anyhow::Error {
+    if let Some(reason) = body.get("revertError").and_then(|v| v.as_str()) {
+        return anyhow::anyhow!(
+            "SETTLEMENT_SIMULATION_REVERTED code={} reason={}",
+            classify_revert_reason(reason),
+            reason
+        );
+    }
+    let message = body
+        .get("messages")
+        .and_then(|v| v.as_array())
+        .map(|m| m.iter().filter_map(|v| v.as_str()).collect::<Vec<_>>().join("; "))
+        .filter(|m| !m.is_empty())
+        .unwrap_or_else(|| body.to_string());
+    if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        anyhow::anyhow!("RELAYER_UNAVAILABLE ({}): {}", status, message)
+    } else {
+        anyhow::anyhow!("RELAYER_REJECTED ({}): {}", status, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relayer_responses_map_onto_retry_markers() {
+        let hash = relayer_tx_hash(&serde_json::json!({ "transactionHash": "0x0abc" })).expect("hash");
+        assert_eq!(hash, format!("{:?}", Felt::from_hex("0xabc").unwrap()));
+        assert!(relayer_tx_hash(&serde_json::json!({})).is_err());
+
+        let reverted = relayer_error(
+            reqwest::StatusCode::BAD_REQUEST,
+            &serde_json::json!({ "messages": ["Execution reverted"], "revertError": "ERC20: insufficient allowance" }),
+        );
+        assert!(reverted.to_string().contains("SETTLEMENT_SIMULATION_REVERTED code=INSUFFICIENT_ALLOWANCE"));
+
+        let busy = relayer_error(
+            reqwest::StatusCode::SERVICE_UNAVAILABLE,
+            &serde_json::json!({ "messages": ["try later"] }),
+        );
+        assert!(busy.to_string().starts_with("RELAYER_UNAVAILABLE"));
+
+        let rejected = relayer_error(reqwest::StatusCode::BAD_REQUEST, &serde_json::json!({ "messages": ["bad calls"] }));
+        assert!(rejected.to_string().starts_with("RELAYER_REJECTED"));
+    }
+
+    #[test]
+    fn only_the_requested_call_and_a_capped_fee_are_signed() {
+        let (pool, token) = (Felt::from(0x900u16), Felt::from(0x70u8));
+        let calldata = [Felt::from(1u8), Felt::from(2u8)];
+        let expected = ExpectedCall { to: pool, selector: get_selector_from_name("settle_match").unwrap(), calldata: &calldata };
+        let policy = RelayerFeePolicy { fee_token: token, max_fee: Some(1_000) };
+        let settle = serde_json::json!({ "To": "0x900", "Selector": format!("0x{:x}", expected.selector), "Calldata": ["0x1", "2"] });
+        let fee = |amount: u64| {
+            serde_json::json!({
+                "To": "0x70",
+                "Selector": format!("0x{:x}", get_selector_from_name("transfer").unwrap()),
+                "Calldata": ["0x123", amount.to_string(), "0"]
+            })
+        };
+        let typed = |calls: Vec<Value>| {
+            serde_json::json!({ "primaryType": "OutsideExecution", "message": { "Caller": "0x1", "Calls": calls } })
+        };
+
+        assert!(check_outside_execution(&typed(vec![settle.clone()]), &expected, &policy).is_ok());
+        assert!(check_outside_execution(&typed(vec![settle.clone(), fee(1_000)]), &expected, &policy).is_ok());
+        assert!(check_outside_execution(&typed(vec![settle.clone(), fee(1_001)]), &expected, &policy).is_err());
+        let sponsored_only = RelayerFeePolicy { max_fee: None, ..policy };
+        assert!(check_outside_execution(&typed(vec![settle.clone(), fee(1)]), &expected, &sponsored_only).is_err());
+
+        let other_calldata = serde_json::json!({ "To": "0x900", "Selector": format!("0x{:x}", expected.selector), "Calldata": ["0x1"] });
+        assert!(check_outside_execution(&typed(vec![other_calldata]), &expected, &policy).is_err());
+        let drain = serde_json::json!({ "To": "0x71", "Selector": "0x1", "Calldata": [] });
+        assert!(check_outside_execution(&typed(vec![settle.clone(), drain]), &expected, &policy).is_err());
+        assert!(check_outside_execution(&typed(vec![settle.clone(), fee(1), fee(1)]), &expected, &policy).is_err());
+        let mut wrong_type = typed(vec![settle]);
+        wrong_type["primaryType"] = serde_json::json!("Transfer");
+        assert!(check_outside_execution(&wrong_type, &expected, &policy).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use serde_json::Value;
+use starknet::core::types::Felt;
+use std::time::Duration;
+use tracing::debug;
+
+use crate::config::RpcBlockTag;
+
+/// Raw Starknet JSON-RPC over HTTP, for the calls we send without starknet-rs
+/// (prechecks, proof preflight, Pragma reads, the browser proxy).
+///
+/// Every `starknet_call` goes out at the configured `RPC_BLOCK_TAG`, and every request is bounded
+/// by the client's timeout (`RPC_TIMEOUT_MS` unless derived with `with_timeout`).
+///
+/// With `DEBUG_RPC_LOGGING` on, every request and response is logged in full at debug level
+/// together with the endpoint URL and the caller's correlation id.
+#[derive(Clone)]
+pub struct RpcClient {
+    url: String,
+    http: reqwest::Client,
+    debug_logging: bool,
+    block_tag: RpcBlockTag,
+    timeout: Option<Duration>,
+}
+
+impl RpcClient {
+    /// A `timeout_ms` of 0 leaves requests unbounded.
+    pub fn new(url: String, debug_logging: bool, block_tag: RpcBlockTag, timeout_ms: u64) -> Self {
+        Self {
+            url,
+            http: reqwest::Client::new(),
+            debug_logging,
+            block_tag,
+            timeout: None,
+        }
+        .with_timeout(timeout_ms)
+    }
+
+    /// The same client (and connection pool) with a different per-request timeout; 0 disables it.
+    pub fn with_timeout(&self, timeout_ms: u64) -> Self {
+        Self {
+            timeout: (timeout_ms > 0).then(|| Duration::from_millis(timeout_ms)),
+            ..self.clone()
+        }
+    }
+
+    /// Posts a JSON-RPC payload as-is. Returns the HTTP status with the decoded body.
+    pub async fn send(
+        &self,
+        payload: &Value,
+        correlation_id: Option<&str>,
+    ) -> Result<(reqwest::StatusCode, Value), reqwest::Error> {
+        let correlation_id = correlation_id.unwrap_or("-");
+        if self.debug_logging {
+            debug!(
+                "RPC request url={} correlation_id={} payload={}",
+                self.url, correlation_id, payload
+            );
+        }
+
+        let result = async {
+            let mut request = self.http.post(&self.url).json(payload);
+            if let Some(timeout) = self.timeout {
+                request = request.timeout(timeout);
+            }
+            let resp = request.send().await?;
+            let status = resp.status();
+            Ok::<_, reqwest::Error>((status, resp.json::<Value>().await?))
+        }
+        .await;
+
+        if self.debug_logging {
+            match &result {
+                Ok((status, body)) => debug!(
+                    "RPC response url={} correlation_id={} status={} body={}",
+                    self.url, correlation_id, status, body
+                ),
+                Err(e) => debug!(
+                    "RPC response url={} correlation_id={} error={}",
+                    self.url, correlation_id, e
+                ),
+            }
+        }
+        result
+    }
+
+    /// Chain id reported by the node (`starknet_chainId`).
+    pub async fn chain_id(&self) -> anyhow::Result<Felt> {
+        let payload = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "starknet_chainId",
+            "params": []
+        });
+        let (_, body) = self.send(&payload, None).await?;
+        let chain_id = body
+            .get("result")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("starknet_chainId returned no result: {}", body))?;
+        Ok(Felt::from_hex(chain_id)?)
+    }
+
+    /// `starknet_call` at the configured block tag. Returns the JSON-RPC envelope,
+    /// so callers still inspect `error` themselves.
+    pub async fn starknet_call(
+        &self,
+        contract_address: Felt,
+        selector: Felt,
+        calldata: Vec<Felt>,
+        correlation_id: Option<&str>,
+    ) -> Result<Value, reqwest::Error> {
+        let payload = |tag: &str| call_payload(1, contract_address, selector, calldata.clone(), tag);
+        let body = self.send(&payload(self.first_tag()), correlation_id).await?.1;
+        if self.block_tag == RpcBlockTag::PendingThenLatest && rejects_block_tag(&body) {
+            return self.send(&payload("latest"), correlation_id).await.map(|(_, body)| body);
+        }
+        Ok(body)
+    }
+
+    /// Several `starknet_call`s in one JSON-RPC batch request. Envelopes come back in `calls`
+    /// order; a call the node did not answer gets an `error` envelope.
+    pub async fn starknet_call_batch(
+        &self,
+        calls: Vec<(Felt, Felt, Vec<Felt>)>,
+        correlation_id: Option<&str>,
+    ) -> Result<Vec<Value>, reqwest::Error> {
+        let envelopes = self.call_batch_at(calls.clone(), self.first_tag(), correlation_id).await?;
+        if self.block_tag == RpcBlockTag::PendingThenLatest && envelopes.iter().any(rejects_block_tag) {
+            return self.call_batch_at(calls, "latest", correlation_id).await;
+        }
+        Ok(envelopes)
+    }
+
+    fn first_tag(&self) -> &'static str {
+        match self.block_tag {
+            RpcBlockTag::Latest => "latest",
+            RpcBlockTag::Pending | RpcBlockTag::PendingThenLatest => "pending",
+        }
+    }
+
+    async fn call_batch_at(
+        &self,
+        calls: Vec<(Felt, Felt, Vec<Felt>)>,
+        block_tag: &str,
+        correlation_id: Option<&str>,
+    ) -> Result<Vec<Value>, reqwest::Error> {
+        let count = calls.len();
+        let payload = Value::Array(
+            calls
+                .into_iter()
+                .enumerate()
+                .map(|(id, (contract, selector, calldata))| {
+                    call_payload(id as u64, contract, selector, calldata, block_tag)
+                })
+                .collect(),
+        );
+        let (_, body) = self.send(&payload, correlation_id).await?;
+
+        // Batch responses may arrive in any order; pair them up by id.
+        let mut envelopes = vec![
+            serde_json::json!({ "error": { "message": "no response for call in batch" } });
+            count
+        ];
+        match body {
+            Value::Array(responses) => {
+                for response in responses {
+                    let id = response.get("id").and_then(|v| v.as_u64()).map(|v| v as usize);
+                    if let Some(slot) = id.and_then(|id| envelopes.get_mut(id)) {
+                        *slot = response;
+                    }
+                }
+            }
+            // Some nodes answer a rejected batch with a single error envelope.
+            other => envelopes.iter_mut().for_each(|slot| *slot = other.clone()),
+        }
+        Ok(envelopes)
+    }
+}
+
+/// Whether a node turned the block tag down (`Invalid params`), as providers without a pending
+/// block do, rather than the call itself failing.
+fn rejects_block_tag(envelope: &Value) -> bool {
+    let msg = envelope
+        .get("error")
+        .and_then(|e| e.get("message"))
+        .and_then(|m| m.as_str())
+        .unwrap_or("");
+    msg.to_lowercase().contains("invalid params") || msg.contains("InvalidParams")
+}
+
+fn call_payload(id: u64, contract_address: Felt, selector: Felt, calldata: Vec<Felt>, block_tag: &str) -> Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": "starknet_call",
+        "params": [
+            {
+                "contract_address": format!("0x{:x}", contract_address),
+                "entry_point_selector": format!("0x{:x}", selector),
+                "calldata": calldata.into_iter().map(|v| format!("0x{:x}", v)).collect::<Vec<_>>(),
+            },
+            // Some RPC providers are strict about BlockId encoding. A string tag is widely accepted.
+            block_tag
+        ]
+    })
+}
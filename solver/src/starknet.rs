@@ -1,23 +1,204 @@
 use starknet::{
-    accounts::{Account, ExecutionEncoding, SingleOwnerAccount},
-    core::types::{BlockId, BlockTag, Call, Felt, FunctionCall},
-    core::utils::get_selector_from_name,
-    providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider},
+    accounts::{Account, AccountError, ExecutionEncoding, SingleOwnerAccount},
+    core::types::{
+        BlockId, BlockTag, Call, ContractClass, Event, ExecuteInvocation, ExecutionResult, Felt,
+        FunctionCall, FunctionInvocation, InvokeTransactionResult, MaybePendingBlockWithTxHashes, ReceiptBlock,
+        SimulatedTransaction, StarknetError, TransactionFinalityStatus, TransactionTrace,
+    },
+    core::crypto::compute_hash_on_elements,
+    core::utils::{cairo_short_string_to_felt, get_selector_from_name, starknet_keccak},
+    providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider, ProviderError},
     signers::{LocalWallet, SigningKey},
 };
 use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use futures::future::BoxFuture;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::info;
+use tokio::sync::{Mutex, MutexGuard};
+use tracing::{debug, info, warn};
 
-use crate::models::MatchedPair;
+use crate::amount::TokenAmount;
+use crate::config::{FeeToken, ProofInputBinding, ProofInputField, RpcBlockTag, SolverAccountConfig};
+use crate::models::{ApprovalRelayRequest, Intent, MatchedPair, PublicInputs, SettlementEvent};
+use crate::relayer::Relayer;
+use crate::rpc::RpcClient;
+use crate::utils::log_address;
 use num_bigint::BigUint;
-use num_traits::Num;
+use num_traits::{Num, ToPrimitive};
 
-pub struct StarknetClient {
-    provider: Arc<JsonRpcClient<HttpTransport>>,
-    account: Arc<SingleOwnerAccount<Arc<JsonRpcClient<HttpTransport>>, LocalWallet>>,
-    dark_pool_address: Felt,
+/// `DarkPool::IntentStatus`; nullifiers the contract has never seen read as `Pending`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnchainIntentStatus {
+    Pending,
+    Settled,
+    Cancelled,
+    Expired,
+}
+
+impl OnchainIntentStatus {
+    /// Decodes the enum variant index returned by `get_intent_status`.
+    pub fn from_felt(value: Felt) -> Option<Self> {
+        [Self::Pending, Self::Settled, Self::Cancelled, Self::Expired]
+            .into_iter()
+            .zip(0u8..)
+            .find(|(_, index)| value == Felt::from(*index))
+            .map(|(status, _)| status)
+    }
+}
+
+/// Where a sent transaction stands, from the node's receipt for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxOutcome {
+    /// The node has no receipt (or record) of it: not propagated yet, or dropped.
+    Unknown,
+    /// Received or executed but not yet `ACCEPTED_ON_L2`.
+    Pending,
+    /// Accepted, with the block it landed in and the events it emitted.
+    Confirmed { block_number: u64, events: Vec<Event> },
+    Reverted(String),
+}
+
+/// The outcome a receipt shows: `Reverted` as soon as execution failed, `Confirmed` once it is in
+/// a block (`block_number`) and accepted on L2 (or L1), `Pending` before that.
+fn receipt_outcome(
+    execution: &ExecutionResult,
+    finality: &TransactionFinalityStatus,
+    block_number: Option<u64>,
+    events: &[Event],
+) -> TxOutcome {
+    if let ExecutionResult::Reverted { reason } = execution {
+        return TxOutcome::Reverted(reason.clone());
+    }
+    let accepted = matches!(
+        finality,
+        TransactionFinalityStatus::AcceptedOnL2 | TransactionFinalityStatus::AcceptedOnL1
+    );
+    match block_number {
+        Some(block_number) if accepted => TxOutcome::Confirmed { block_number, events: events.to_vec() },
+        _ => TxOutcome::Pending,
+    }
+}
+
+/// The invoke version settlements are sent as: fees in ETH go in v1 invokes, fees in STRK need v3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InvokeVersion {
+    V1,
+    V3,
+}
+
+impl InvokeVersion {
+    fn for_fee_token(fee_token: FeeToken) -> Self {
+        match fee_token {
+            FeeToken::Eth => Self::V1,
+            FeeToken::Strk => Self::V3,
+        }
+    }
+}
+
+/// Why `starknet_simulateTransactions` gave no trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SimulationError {
+    /// The node executed the transaction and it failed.
+    Reverted(String),
+    /// Anything else: transport, rate limits, node errors. Says nothing about the transaction.
+    Unavailable(String),
+}
+
+impl SimulationError {
+    fn of<S>(e: AccountError<S>) -> Self
+    where
+        AccountError<S>: std::fmt::Display,
+    {
+        match &e {
+            AccountError::Provider(ProviderError::StarknetError(err)) => match execution_revert_reason(err) {
+                Some(reason) => Self::Reverted(reason),
+                None => Self::Unavailable(e.to_string()),
+            },
+            _ => Self::Unavailable(e.to_string()),
+        }
+    }
+}
+
+/// Pause before simulating again after the node couldn't simulate at all.
+const SIMULATION_RETRY_DELAY_MS: u64 = 500;
+
+/// Margin on v3 resource bounds over the fee estimate, in percent. Gas use and the L1 gas price can
+/// both move between the estimate and inclusion.
+const V3_BOUNDS_MARGIN_PERCENT: u128 = 150;
+
+/// Called with the hash of a solver-account transaction once it is signed and before it is sent, so
+/// the caller can record it somewhere durable first. An error stops the send.
+pub type TxHashHook<'a> = &'a (dyn Fn(String) -> BoxFuture<'a, Result<()>> + Send + Sync);
+
+/// `max_fee` for a v1 invoke whose `starknet_estimateFee` came to `overall_fee`, raised by
+/// `V3_BOUNDS_MARGIN_PERCENT` like the v3 bounds.
+fn v1_max_fee(overall_fee: Felt) -> Result<Felt> {
+    let fee = BigUint::from_bytes_be(&overall_fee.to_bytes_be())
+        .to_u128()
+        .ok_or_else(|| anyhow::anyhow!("fee estimate value 0x{:x} does not fit in u128", overall_fee))?;
+    Ok(Felt::from(fee.saturating_mul(V3_BOUNDS_MARGIN_PERCENT).div_ceil(100)))
+}
+
+async fn record_tx_hash(on_hash: Option<TxHashHook<'_>>, tx_hash: Felt) -> Result<(), String> {
+    match on_hash {
+        Some(hook) => hook(format!("{:?}", tx_hash))
+            .await
+            .map_err(|e| format!("TX_HASH_NOT_RECORDED: {}", e)),
+        None => Ok(()),
+    }
+}
+
+/// L1 gas bounds `(max_amount, max_price_per_unit)` for a v3 invoke whose `starknet_estimateFee`
+/// came to `overall_fee` at `gas_price`: enough gas to pay the whole estimate (data gas included) at
+/// that price, and the price itself, each raised by `V3_BOUNDS_MARGIN_PERCENT`.
+fn v3_resource_bounds(overall_fee: Felt, gas_price: Felt) -> Result<(u64, u128)> {
+    let to_u128 = |f: Felt| {
+        BigUint::from_bytes_be(&f.to_bytes_be())
+            .to_u128()
+            .ok_or_else(|| anyhow::anyhow!("fee estimate value 0x{:x} does not fit in u128", f))
+    };
+    let (fee, price) = (to_u128(overall_fee)?, to_u128(gas_price)?);
+    if price == 0 {
+        return Err(anyhow::anyhow!("fee estimate has a zero gas price"));
+    }
+    let with_margin = |v: u128| v.saturating_mul(V3_BOUNDS_MARGIN_PERCENT).div_ceil(100);
+    let gas = u64::try_from(with_margin(fee.div_ceil(price)))
+        .map_err(|_| anyhow::anyhow!("fee estimate of {} at gas price {} needs more gas than fits in u64", fee, price))?;
+    Ok((gas, with_margin(price)))
+}
+
+/// What sending `settle_match` for a match would cost, from `starknet_estimateFee`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettlementFeeEstimate {
+    /// Base units of `fee_token`: wei for ETH, fri for STRK.
+    pub overall_fee: BigUint,
+    pub fee_token: FeeToken,
+}
+
+/// Settlement fee estimates for `GET /v1/matches/:match_id/estimate`; `StarknetClient` asks the node.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait SettlementFeeEstimator: Send + Sync {
+    /// Fails with `SETTLEMENT_ESTIMATE_REVERTED code=.. reason=..` when the settlement would revert.
+    async fn estimate_settlement_fee(&self, pair: &MatchedPair) -> Result<SettlementFeeEstimate>;
+}
+
+/// What reconciling a settling marker reads from the chain; `StarknetClient` asks the node.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait SettlementReceipts: Send + Sync {
+    async fn transaction_outcome(&self, tx_hash: &str) -> Result<TxOutcome>;
+    async fn is_intent_settled(&self, nullifier: &str) -> Result<bool>;
+}
+
+type SolverAccount = SingleOwnerAccount<Arc<JsonRpcClient<HttpTransport>>, LocalWallet>;
+
+/// One solver account of an `AccountPool`, with its own send lock and nonce cache.
+struct AccountSlot<A> {
+    account: A,
     // Ensure we never submit two txs concurrently from the same solver account, which can
     // lead to duplicate nonces (and NonceTooOld errors) under load.
     tx_mutex: Mutex<()>,
@@ -25,131 +206,621 @@ pub struct StarknetClient {
     next_nonce: Mutex<Option<Felt>>,
 }
 
+impl<A> AccountSlot<A> {
+    async fn mark_nonce_used(&self, used: Felt) {
+        let mut guard = self.next_nonce.lock().await;
+        *guard = Some(used + Felt::from(1u8));
+    }
+
+    async fn reset_nonce_cache(&self) {
+        let mut guard = self.next_nonce.lock().await;
+        *guard = None;
+    }
+
+    async fn seed_nonce_cache(&self, nonce: Felt) {
+        let mut guard = self.next_nonce.lock().await;
+        *guard = Some(nonce);
+    }
+
+    /// Re-syncs the nonce cache after a nonce-related rejection.
+    async fn resync_nonce_from_err(&self, msg: &str) {
+        if let Some(next) = parse_account_nonce_from_err(msg) {
+            // Seed cache to the reported account nonce (mempool-aware) and retry.
+            self.seed_nonce_cache(next).await;
+        } else {
+            self.reset_nonce_cache().await;
+        }
+    }
+}
+
+/// The solver accounts settlements go out from. Each sends one transaction at a time, so with
+/// several of them, settlements proceed in parallel up to the pool size.
+struct AccountPool<A> {
+    slots: Vec<AccountSlot<A>>,
+    // Where the next `acquire` starts looking, so idle accounts take turns.
+    next: AtomicUsize,
+}
+
+impl<A> AccountPool<A> {
+    /// `accounts` must not be empty; the first is the primary account.
+    fn new(accounts: Vec<A>) -> Self {
+        assert!(!accounts.is_empty(), "an account pool needs at least one account");
+        let slots = accounts
+            .into_iter()
+            .map(|account| AccountSlot { account, tx_mutex: Mutex::new(()), next_nonce: Mutex::new(None) })
+            .collect();
+        Self { slots, next: AtomicUsize::new(0) }
+    }
+
+    fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// An idle account, trying them in turn from where the previous call left off, and its send
+    /// lock. When every account is busy, waits for the one whose turn it is.
+    async fn acquire(&self) -> (&AccountSlot<A>, MutexGuard<'_, ()>) {
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % self.slots.len();
+        for offset in 0..self.slots.len() {
+            let slot = &self.slots[(start + offset) % self.slots.len()];
+            if let Ok(guard) = slot.tx_mutex.try_lock() {
+                return (slot, guard);
+            }
+        }
+        let slot = &self.slots[start];
+        (slot, slot.tx_mutex.lock().await)
+    }
+
+    /// The primary account and its send lock, for sends that must come from it.
+    async fn acquire_primary(&self) -> (&AccountSlot<A>, MutexGuard<'_, ()>) {
+        let slot = &self.slots[0];
+        (slot, slot.tx_mutex.lock().await)
+    }
+}
+
+pub struct StarknetClient {
+    provider: Arc<JsonRpcClient<HttpTransport>>,
+    // SOLVER_ADDRESSES / SOLVER_PRIVATE_KEYS, or the single SOLVER_ADDRESS.
+    accounts: AccountPool<SolverAccount>,
+    dark_pool_address: Felt,
+    // Run `starknet_simulateTransactions` right before each settlement send.
+    simulate_before_settle: bool,
+    fee_token: FeeToken,
+    rpc_url: String,
+    // DEBUG_RPC_LOGGING: log each provider request/response in full.
+    debug_rpc_logging: bool,
+    // RPC_BLOCK_TAG: block that contract reads and the deadline clock are taken from.
+    block_tag: RpcBlockTag,
+    // SETTLEMENT_RELAYER_URL: settlements go through this instead of the solver account.
+    relayer: Option<Arc<dyn Relayer>>,
+    // EKUBO_CORE_ADDRESS: core contract `resolve_ekubo_pool` reads pool prices from.
+    ekubo_core: Option<Felt>,
+}
+
 impl StarknetClient {
     pub async fn new(
         rpc_url: &str,
         dark_pool_address: &str,
-        solver_address: &str,
-        private_key: &str,
+        solver_accounts: &[SolverAccountConfig],
+        simulate_before_settle: Option<bool>,
+        fee_token: FeeToken,
+        debug_rpc_logging: bool,
+        block_tag: RpcBlockTag,
     ) -> Result<Self> {
         let provider = Arc::new(JsonRpcClient::new(HttpTransport::new(
             reqwest::Url::parse(rpc_url)?,
         )));
 
-        let signer = LocalWallet::from(SigningKey::from_secret_scalar(
-            felt_from_hex(private_key)?,
-        ));
-
         // Get chain ID
         let chain_id = provider.chain_id().await?;
 
-        // Use the deployed solver account address (must match SOLVER_PRIVATE_KEY).
-        let address = felt_from_hex(solver_address)?;
+        let dark_pool = felt_from_hex(dark_pool_address)?;
+
+        // Reverted settlements still burn fees, so simulate by default where fees are real.
+        let simulate_before_settle =
+            simulate_before_settle.unwrap_or(chain_id == starknet::core::chain_id::MAINNET);
+        info!("Settlement simulation before send: {}", simulate_before_settle);
 
-        let account = Arc::new(SingleOwnerAccount::new(
-            provider.clone(),
-            signer,
-            address,
-            chain_id,
-            ExecutionEncoding::New,
-        ));
+        if solver_accounts.is_empty() {
+            return Err(anyhow::anyhow!("At least one solver account is required"));
+        }
+        let mut accounts = Vec::with_capacity(solver_accounts.len());
+        for solver in solver_accounts {
+            let signer = LocalWallet::from(SigningKey::from_secret_scalar(
+                felt_from_hex(&solver.private_key)?,
+            ));
+            // Use the deployed solver account address (must match its private key).
+            let address = felt_from_hex(&solver.address)?;
 
-        let dark_pool = felt_from_hex(dark_pool_address)?;
+            // STRK fees need v3 invokes, which Cairo 0 account contracts cannot validate.
+            if fee_token == FeeToken::Strk {
+                match provider.get_class_at(BlockId::Tag(BlockTag::Latest), address).await {
+                    Ok(ContractClass::Legacy(_)) => {
+                        return Err(anyhow::anyhow!(
+                            "SETTLEMENT_FEE_TOKEN=strk requires v3 transactions, which the Cairo 0 solver account {} does not support",
+                            log_address(&solver.address)
+                        ));
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Could not check solver account class for v3 support: {}", e),
+                }
+            }
+            let fee_balance_call = FunctionCall {
+                contract_address: felt_from_hex(fee_token.address())?,
+                entry_point_selector: get_selector_from_name("balanceOf")?,
+                calldata: vec![address],
+            };
+            match provider.call(fee_balance_call, BlockId::Tag(BlockTag::Latest)).await {
+                Ok(result) if parse_u256_result(&result).map(|b| b == BigUint::from(0u8)).unwrap_or(false) => {
+                    warn!("Solver account {} holds no {:?} to pay settlement fees", log_address(&solver.address), fee_token);
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Could not read solver {:?} fee balance: {}", fee_token, e),
+            }
+
+            accounts.push(SingleOwnerAccount::new(
+                provider.clone(),
+                signer,
+                address,
+                chain_id,
+                ExecutionEncoding::New,
+            ));
+        }
+        // DarkPool `_assert_solver` accepts only its owner as caller, so other accounts would revert
+        // every settlement with 'Unauthorized solver'.
+        let owner = match provider
+            .get_storage_at(dark_pool, starknet_keccak(b"owner"), BlockId::Tag(BlockTag::Latest))
+            .await
+        {
+            Ok(owner) => Some(owner),
+            Err(e) => {
+                warn!("Could not read the DarkPool owner to check the solver accounts: {}", e);
+                None
+            }
+        };
+        let addresses: Vec<Felt> = accounts.iter().map(|a| a.address()).collect();
+        check_solver_accounts(owner, &addresses)?;
+        info!("Settling from {} solver account(s)", accounts.len());
+        info!("Settlement fee token: {:?}", fee_token);
 
         Ok(Self {
             provider,
-            account,
+            accounts: AccountPool::new(accounts),
             dark_pool_address: dark_pool,
-            tx_mutex: Mutex::new(()),
-            next_nonce: Mutex::new(None),
+            simulate_before_settle,
+            fee_token,
+            rpc_url: rpc_url.to_string(),
+            debug_rpc_logging,
+            block_tag,
+            relayer: None,
+            ekubo_core: None,
         })
     }
 
-    async fn nonce_for_send(&self) -> Result<Felt> {
+    /// Submit settlements through `relayer` instead of paying for them from the solver account.
+    /// Approval relays still go out directly.
+    pub fn with_relayer(mut self, relayer: Arc<dyn Relayer>) -> Self {
+        self.relayer = Some(relayer);
+        self
+    }
+
+    /// Resolve pair pools against the Ekubo core contract at `core`.
+    pub fn with_ekubo_core(mut self, core: &str) -> Result<Self> {
+        self.ekubo_core = Some(felt_from_hex(core)?);
+        Ok(self)
+    }
+
+    pub fn resolves_ekubo_pools(&self) -> bool {
+        self.ekubo_core.is_some()
+    }
+
+    /// Block to read at under RPC_BLOCK_TAG, plus the retry block for `pending_then_latest`.
+    fn read_blocks(&self) -> (BlockId, Option<BlockId>) {
+        let latest = BlockId::Tag(BlockTag::Latest);
+        let pending = BlockId::Tag(BlockTag::Pending);
+        match self.block_tag {
+            RpcBlockTag::Latest => (latest, None),
+            RpcBlockTag::Pending => (pending, None),
+            RpcBlockTag::PendingThenLatest => (pending, Some(latest)),
+        }
+    }
+
+    /// `starknet_call` at RPC_BLOCK_TAG, logged in full when DEBUG_RPC_LOGGING is on.
+    async fn call_contract(&self, call: FunctionCall) -> Result<Vec<Felt>> {
+        if self.debug_rpc_logging {
+            debug!("RPC request url={} method=starknet_call call={:?}", self.rpc_url, call);
+        }
+        let (block, fallback) = self.read_blocks();
+        let mut result = self.provider.call(&call, block).await;
+        // A revert is the node's answer; any other failure may be the block tag being refused.
+        if let (Err(e), Some(fallback)) = (&result, fallback) {
+            if !matches!(e, ProviderError::StarknetError(_)) {
+                result = self.provider.call(&call, fallback).await;
+            }
+        }
+        if self.debug_rpc_logging {
+            match &result {
+                Ok(felts) => debug!("RPC response url={} method=starknet_call result={:?}", self.rpc_url, felts),
+                Err(e) => debug!("RPC response url={} method=starknet_call error={}", self.rpc_url, e),
+            }
+        }
+        Ok(result?)
+    }
+
+    // v1 and v3 executions are distinct builder types, so pick the version per call.
+    async fn simulate_calls(
+        &self,
+        account: &SolverAccount,
+        calls: &[Call],
+        nonce: Felt,
+    ) -> Result<SimulatedTransaction, SimulationError> {
+        if self.debug_rpc_logging {
+            debug!(
+                "RPC request url={} method=starknet_simulateTransactions fee_token={:?} account=0x{:x} nonce=0x{:x} calls={:?}",
+                self.rpc_url, self.fee_token, account.address(), nonce, calls
+            );
+        }
+        let simulated = match InvokeVersion::for_fee_token(self.fee_token) {
+            InvokeVersion::V1 => account.execute_v1(calls.to_vec()).nonce(nonce).simulate(false, true).await,
+            InvokeVersion::V3 => account.execute_v3(calls.to_vec()).nonce(nonce).simulate(false, true).await,
+        };
+        if self.debug_rpc_logging {
+            match &simulated {
+                Ok(sim) => debug!(
+                    "RPC response url={} method=starknet_simulateTransactions trace={:?} fee_estimation={:?}",
+                    self.rpc_url, sim.transaction_trace, sim.fee_estimation
+                ),
+                Err(e) => debug!("RPC response url={} method=starknet_simulateTransactions error={}", self.rpc_url, e),
+            }
+        }
+        simulated.map_err(SimulationError::of)
+    }
+
+    async fn send_calls(
+        &self,
+        account: &SolverAccount,
+        calls: &[Call],
+        nonce: Felt,
+        on_hash: Option<TxHashHook<'_>>,
+    ) -> Result<InvokeTransactionResult, String> {
+        if self.debug_rpc_logging {
+            debug!(
+                "RPC request url={} method=starknet_addInvokeTransaction fee_token={:?} account=0x{:x} nonce=0x{:x} calls={:?}",
+                self.rpc_url, self.fee_token, account.address(), nonce, calls
+            );
+        }
+        let sent = match InvokeVersion::for_fee_token(self.fee_token) {
+            InvokeVersion::V1 => self.send_v1(account, calls, nonce, on_hash).await,
+            InvokeVersion::V3 => self.send_v3(account, calls, nonce, on_hash).await,
+        };
+        if self.debug_rpc_logging {
+            match &sent {
+                Ok(result) => debug!(
+                    "RPC response url={} method=starknet_addInvokeTransaction transaction_hash=0x{:x}",
+                    self.rpc_url, result.transaction_hash
+                ),
+                Err(e) => debug!("RPC response url={} method=starknet_addInvokeTransaction error={}", self.rpc_url, e),
+            }
+        }
+        sent
+    }
+
+    /// Sends a v1 invoke with `max_fee` from `starknet_estimateFee` (see `v1_max_fee`), fixed
+    /// before signing so the hash is known up front for `on_hash`.
+    async fn send_v1(
+        &self,
+        account: &SolverAccount,
+        calls: &[Call],
+        nonce: Felt,
+        on_hash: Option<TxHashHook<'_>>,
+    ) -> Result<InvokeTransactionResult, String> {
+        let execution = account.execute_v1(calls.to_vec()).nonce(nonce);
+        let estimate = execution.estimate_fee().await.map_err(|e| e.to_string())?;
+        let max_fee = v1_max_fee(estimate.overall_fee).map_err(|e| e.to_string())?;
+        let prepared = execution.max_fee(max_fee).prepared().map_err(|e| e.to_string())?;
+        record_tx_hash(on_hash, prepared.transaction_hash(false)).await?;
+        prepared.send().await.map_err(|e| e.to_string())
+    }
+
+    /// Sends a v3 invoke with explicit L1 gas bounds from `starknet_estimateFee` (see
+    /// `v3_resource_bounds`). A failed estimate fails the send, nonce errors included, so the
+    /// caller's nonce handling still applies.
+    async fn send_v3(
+        &self,
+        account: &SolverAccount,
+        calls: &[Call],
+        nonce: Felt,
+        on_hash: Option<TxHashHook<'_>>,
+    ) -> Result<InvokeTransactionResult, String> {
+        let execution = account.execute_v3(calls.to_vec()).nonce(nonce);
+        let estimate = execution.estimate_fee().await.map_err(|e| e.to_string())?;
+        let (gas, gas_price) =
+            v3_resource_bounds(estimate.overall_fee, estimate.gas_price).map_err(|e| e.to_string())?;
+        if self.debug_rpc_logging {
+            debug!(
+                "v3 resource bounds url={} estimate={:?} max_amount={} max_price_per_unit={}",
+                self.rpc_url, estimate, gas, gas_price
+            );
+        }
+        let prepared = execution.gas(gas).gas_price(gas_price).prepared().map_err(|e| e.to_string())?;
+        record_tx_hash(on_hash, prepared.transaction_hash(false)).await?;
+        prepared.send().await.map_err(|e| e.to_string())
+    }
+
+    async fn nonce_for_send(&self, slot: &AccountSlot<SolverAccount>) -> Result<Felt> {
         // We serialize tx submission via tx_mutex, so we can safely reuse a cached nonce.
         // Important: do not advance the cache until a tx is successfully submitted.
-        let mut guard = self.next_nonce.lock().await;
+        let mut guard = slot.next_nonce.lock().await;
         if let Some(n) = *guard {
             return Ok(n);
         }
 
-        // Use Latest since this starknet-rs version doesn't expose a Pending tag in BlockId/BlockTag.
+        // Always Latest, whatever RPC_BLOCK_TAG says: our own in-flight sends are covered by the
+        // cached nonce, and a pending nonce from a lagging node is worse than none.
         let onchain = self
             .provider
-            .get_nonce(BlockId::Tag(BlockTag::Latest), self.account.address())
+            .get_nonce(BlockId::Tag(BlockTag::Latest), slot.account.address())
             .await?;
         *guard = Some(onchain);
         Ok(onchain)
     }
 
-    async fn mark_nonce_used(&self, used: Felt) {
-        let mut guard = self.next_nonce.lock().await;
-        *guard = Some(used + Felt::from(1u8));
-    }
+    /// Settle a matched pair on-chain. From the solver account, `on_hash` sees the tx hash before it
+    /// is sent; the relayer only reports it afterwards, in the returned hash.
+    pub async fn settle_match(&self, pair: &MatchedPair, on_hash: TxHashHook<'_>) -> Result<String> {
+        info!(
+            "Settling match {} on Starknet",
+            pair.id
+        );
 
-    async fn reset_nonce_cache(&self) {
-        let mut guard = self.next_nonce.lock().await;
-        *guard = None;
+        let calldata = settle_match_calldata(pair)?;
+        let label = format!("match {}", pair.id);
+        let tx_hash = match &self.relayer {
+            Some(relayer) => {
+                relayer
+                    .execute(self.dark_pool_address, "settle_match", &calldata, &label)
+                    .await?
+            }
+            None => {
+                let call = Call {
+                    to: self.dark_pool_address,
+                    selector: get_selector_from_name("settle_match")?,
+                    calldata,
+                };
+                self.execute_from_solver(call, &label, on_hash).await?
+            }
+        };
+        info!("Match settled successfully. Transaction hash: {}", tx_hash);
+        Ok(tx_hash)
     }
 
-    async fn seed_nonce_cache(&self, nonce: Felt) {
-        let mut guard = self.next_nonce.lock().await;
-        *guard = Some(nonce);
+    /// Whether `settle_matches` can batch: only from the solver account, and only with
+    /// SIMULATE_BEFORE_SETTLE, so a batch that would revert is caught before it is sent.
+    pub fn batches_settlements(&self) -> bool {
+        self.relayer.is_none() && self.simulate_before_settle
     }
 
-    /// Settle a matched pair on-chain
-    pub async fn settle_match(&self, pair: &MatchedPair) -> Result<String> {
-        info!(
-            "Settling match {} on Starknet",
-            pair.id
-        );
+    /// Settles every pair in one multicall transaction from the solver account, with one nonce and
+    /// one fee. The calls succeed or revert together: one failing pair reverts the whole batch, and
+    /// callers should fall back to `settle_match` per pair when this fails.
+    pub async fn settle_matches(&self, pairs: &[MatchedPair], on_hash: TxHashHook<'_>) -> Result<String> {
+        if !self.batches_settlements() {
+            return Err(anyhow::anyhow!(
+                "BATCH_SETTLEMENT_UNSUPPORTED: needs the solver account and settlement simulation"
+            ));
+        }
+        let ids: Vec<&str> = pairs.iter().map(|p| p.id.as_str()).collect();
+        info!("Settling {} matches in one transaction: {}", pairs.len(), ids.join(", "));
+
+        let calls = settle_matches_calls(self.dark_pool_address, pairs)?;
+        let tx_hash = self
+            .execute_calls_from_solver(calls, &format!("matches {}", ids.join(", ")), on_hash)
+            .await?;
+        info!("Matches settled successfully. Transaction hash: {}", tx_hash);
+        Ok(tx_hash)
+    }
 
-        // Cairo ABI encoding for:
-        // settle_match(intent_a: IntentProof, intent_b: IntentProof, settlement_data: SettlementData)
-        //
-        // IntentProof = { intent_hash, nullifier, proof_data: Array<felt252>, public_inputs: Array<felt252> }
-        // SettlementData = { ekubo_pool: ContractAddress, sqrt_price_limit: u256(low, high) }
-        let mut calldata: Vec<Felt> = Vec::new();
-        append_intent_proof(&mut calldata, &pair.intent_a)?;
-        append_intent_proof(&mut calldata, &pair.intent_b)?;
-
-        // Settlement data
-        calldata.push(parse_felt_any(&pair.settlement_data.ekubo_pool)?);
-        let (low, high) = parse_u256_low_high(&pair.settlement_data.sqrt_price_limit)?;
-        calldata.push(low);
-        calldata.push(high);
+    /// Simulates `settle_match` for `pair` from the solver account and returns the events it would
+    /// emit, from every contract it touches. `None` when settlements go through the relayer, whose
+    /// account can't be simulated from here.
+    pub async fn simulate_settlement(&self, pair: &MatchedPair) -> Result<Option<Vec<Event>>> {
+        if self.relayer.is_some() {
+            return Ok(None);
+        }
+        let call = Call {
+            to: self.dark_pool_address,
+            selector: get_selector_from_name("settle_match")?,
+            calldata: settle_match_calldata(pair)?,
+        };
+        let (slot, nonce) = {
+            let (slot, _tx_guard) = self.accounts.acquire().await;
+            (slot, self.nonce_for_send(slot).await?)
+        };
+        let simulated = match self.simulate_calls(&slot.account, std::slice::from_ref(&call), nonce).await {
+            Ok(simulated) => simulated,
+            Err(SimulationError::Reverted(reason)) => {
+                return Err(anyhow::anyhow!(
+                    "SETTLEMENT_SIMULATION_REVERTED code={} reason={}",
+                    classify_revert_reason(&reason),
+                    reason
+                ))
+            }
+            Err(SimulationError::Unavailable(msg)) => return Err(anyhow::anyhow!("SETTLEMENT_SIMULATION_FAILED: {}", msg)),
+        };
+        if let Some(reason) = simulated_revert_reason(&simulated.transaction_trace) {
+            return Err(anyhow::anyhow!(
+                "SETTLEMENT_SIMULATION_REVERTED code={} reason={}",
+                classify_revert_reason(&reason),
+                reason
+            ));
+        }
+        Ok(Some(simulated_events(&simulated.transaction_trace)))
+    }
 
+    /// Estimates the fee of sending `settle_match` for `pair` from a solver account. Through the
+    /// relayer, this is what the solver account would pay to send it itself.
+    pub async fn estimate_settlement_fee(&self, pair: &MatchedPair) -> Result<SettlementFeeEstimate> {
         let call = Call {
             to: self.dark_pool_address,
             selector: get_selector_from_name("settle_match")?,
+            calldata: settle_match_calldata(pair)?,
+        };
+        // The primary account, whatever the pool size, so estimates never tie up the others.
+        let (slot, nonce) = {
+            let (slot, _tx_guard) = self.accounts.acquire_primary().await;
+            (slot, self.nonce_for_send(slot).await?)
+        };
+        if self.debug_rpc_logging {
+            debug!(
+                "RPC request url={} method=starknet_estimateFee fee_token={:?} account=0x{:x} nonce=0x{:x} call={:?}",
+                self.rpc_url, self.fee_token, slot.account.address(), nonce, call
+            );
+        }
+        let estimated = match InvokeVersion::for_fee_token(self.fee_token) {
+            InvokeVersion::V1 => slot.account.execute_v1(vec![call]).nonce(nonce).estimate_fee().await,
+            InvokeVersion::V3 => slot.account.execute_v3(vec![call]).nonce(nonce).estimate_fee().await,
+        };
+        if self.debug_rpc_logging {
+            match &estimated {
+                Ok(estimate) => debug!("RPC response url={} method=starknet_estimateFee estimate={:?}", self.rpc_url, estimate),
+                Err(e) => debug!("RPC response url={} method=starknet_estimateFee error={}", self.rpc_url, e),
+            }
+        }
+        match estimated {
+            Ok(estimate) => Ok(SettlementFeeEstimate {
+                overall_fee: BigUint::from_bytes_be(&estimate.overall_fee.to_bytes_be()),
+                fee_token: self.fee_token,
+            }),
+            Err(e) => {
+                let reason = match &e {
+                    AccountError::Provider(ProviderError::StarknetError(err)) => execution_revert_reason(err),
+                    _ => None,
+                };
+                Err(match reason {
+                    Some(reason) => anyhow::anyhow!(
+                        "SETTLEMENT_ESTIMATE_REVERTED code={} reason={}",
+                        classify_revert_reason(&reason),
+                        reason
+                    ),
+                    None => anyhow::anyhow!("SETTLEMENT_ESTIMATE_FAILED: {}", e),
+                })
+            }
+        }
+    }
+
+    /// Relays a user-signed approval of the dark pool through the user's account
+    /// (`execute_from_outside_v2`, SNIP-9). The spender is always `dark_pool_address`, so a
+    /// signature over any other spender fails the account's signature check.
+    pub async fn relay_approval(&self, approval: &ApprovalRelayRequest) -> Result<String> {
+        let account = felt_from_hex(&approval.account)?;
+        let caller = match approval.caller.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+            Some(caller) => felt_from_hex(caller)?,
+            None => cairo_short_string_to_felt("ANY_CALLER")?,
+        };
+        let (amount_low, amount_high) = parse_u256_low_high(&approval.amount)?;
+
+        // OutsideExecution { caller, nonce, execute_after, execute_before, calls: Span<Call> }
+        let mut calldata = vec![
+            caller,
+            parse_felt_any(&approval.nonce)?,
+            Felt::from(approval.execute_after),
+            Felt::from(approval.execute_before),
+            Felt::ONE,
+            felt_from_hex(&approval.token)?,
+            get_selector_from_name("approve")?,
+            Felt::from(3u8),
+            self.dark_pool_address,
+            amount_low,
+            amount_high,
+        ];
+        calldata.push(Felt::from(approval.signature.len() as u64));
+        for el in &approval.signature {
+            calldata.push(parse_felt_any(el)?);
+        }
+
+        let call = Call {
+            to: account,
+            selector: get_selector_from_name("execute_from_outside_v2")?,
             calldata,
         };
 
-        // Execute transaction (serialized to avoid nonce races).
-        let _tx_guard = self.tx_mutex.lock().await;
+        // The outside execution may name the solver as its caller, so send from the primary account.
+        let (slot, tx_guard) = self.accounts.acquire_primary().await;
+        let tx_hash = self
+            .execute_calls_from(slot, tx_guard, vec![call], &format!("approval relay for {}", approval.account), None)
+            .await?;
+        info!("Relayed approval for {}. Transaction hash: {}", log_address(&approval.account), tx_hash);
+        Ok(tx_hash)
+    }
+
+    /// Sends `call` from an idle solver account, simulating first when enabled. `label` names the
+    /// operation in logs.
+    async fn execute_from_solver(&self, call: Call, label: &str, on_hash: TxHashHook<'_>) -> Result<String> {
+        self.execute_calls_from_solver(vec![call], label, on_hash).await
+    }
+
+    /// `execute_from_solver` for several calls in one transaction.
+    async fn execute_calls_from_solver(&self, calls: Vec<Call>, label: &str, on_hash: TxHashHook<'_>) -> Result<String> {
+        let (slot, tx_guard) = self.accounts.acquire().await;
+        self.execute_calls_from(slot, tx_guard, calls, label, Some(on_hash)).await
+    }
+
+    /// Sends `calls` from `slot`. `_tx_guard` is the slot's send lock, held until this returns.
+    async fn execute_calls_from(
+        &self,
+        slot: &AccountSlot<SolverAccount>,
+        _tx_guard: MutexGuard<'_, ()>,
+        calls: Vec<Call>,
+        label: &str,
+        on_hash: Option<TxHashHook<'_>>,
+    ) -> Result<String> {
+        if self.accounts.len() > 1 {
+            debug!("Sending {} from solver account 0x{:x}", label, slot.account.address());
+        }
 
         // Retry on nonce desync (can happen if a previous tx was accepted but our cache is stale,
         // or if we optimistically cached a nonce and the provider rejected the tx).
         let mut last_err: Option<anyhow::Error> = None;
         for attempt in 0..3 {
-            let nonce = self.nonce_for_send().await?;
-            match self
-                .account
-                .execute(vec![call.clone()])
-                .nonce(nonce)
-                .send()
-                .await
-            {
+            let nonce = self.nonce_for_send(slot).await?;
+
+            // A plain `starknet_call` can't stand in for this: DarkPool.settle_match asserts the
+            // caller is the solver, so we simulate the signed invoke from the solver account instead.
+            // Any revert (proofs, deadlines, balances, pool state) is caught here without paying gas.
+            // A node that can't simulate at all is asked again, and after that the send fails
+            // without being counted as a revert.
+            if self.simulate_before_settle {
+                let revert_reason = match self.simulate_calls(&slot.account, &calls, nonce).await {
+                    Ok(simulated) => simulated_revert_reason(&simulated.transaction_trace),
+                    Err(SimulationError::Reverted(reason)) => Some(reason),
+                    Err(SimulationError::Unavailable(msg)) => {
+                        if is_nonce_error(&msg) {
+                            slot.resync_nonce_from_err(&msg).await;
+                        } else {
+                            warn!("Could not simulate {} (attempt {}): {}", label, attempt + 1, msg);
+                            if attempt + 1 < 3 {
+                                tokio::time::sleep(std::time::Duration::from_millis(SIMULATION_RETRY_DELAY_MS)).await;
+                            }
+                        }
+                        last_err = Some(anyhow::anyhow!("SETTLEMENT_SIMULATION_FAILED: {}", msg));
+                        continue;
+                    }
+                };
+                if let Some(reason) = revert_reason {
+                    warn!("Simulation reverted for {}: {}", label, reason);
+                    return Err(anyhow::anyhow!(
+                        "SETTLEMENT_SIMULATION_REVERTED code={} reason={}",
+                        classify_revert_reason(&reason),
+                        reason
+                    ));
+                }
+            }
+
+            match self.send_calls(&slot.account, &calls, nonce, on_hash).await {
                 Ok(result) => {
-                    info!(
-                        "Match settled successfully. Transaction hash: {:?}",
-                        result.transaction_hash
-                    );
-                    self.mark_nonce_used(nonce).await;
+                    slot.mark_nonce_used(nonce).await;
                     return Ok(format!("{:?}", result.transaction_hash));
                 }
-                Err(e) => {
-                    let msg = e.to_string();
+                Err(msg) => {
                     // Any error means our cached nonce might now be wrong (or the tx might have been
                     // accepted but we didn't observe it). Reset unless we can seed it from the error.
                     //
@@ -157,43 +828,182 @@ impl StarknetClient {
                     // - "NonceTooOld ..."
                     // - "InvalidTransactionNonce: ... account_nonce: Nonce(0x..)"
                     // - "Invalid transaction nonce ... Account nonce: 0x..; got: 0x.."
-                    if msg.contains("NonceTooOld")
-                        || msg.contains("InvalidTransactionNonce")
-                        || msg.contains("Invalid transaction nonce")
-                    {
-                        if let Some(next) = parse_account_nonce_from_err(&msg) {
-                            // Seed cache to the reported account nonce (mempool-aware) and retry.
-                            self.seed_nonce_cache(next).await;
-                        } else {
-                            self.reset_nonce_cache().await;
-                        }
+                    if is_nonce_error(&msg) {
+                        slot.resync_nonce_from_err(&msg).await;
                         last_err = Some(anyhow::anyhow!(msg.clone()));
                         if attempt + 1 < 3 {
                             continue;
                         }
                     }
-                    self.reset_nonce_cache().await;
+                    slot.reset_nonce_cache().await;
                     last_err = Some(anyhow::anyhow!(msg.clone()));
                     break;
                 }
             }
         }
 
-        Err(last_err.unwrap_or_else(|| anyhow::anyhow!("Failed to send settlement tx")))
+        Err(last_err.unwrap_or_else(|| anyhow::anyhow!("Failed to send tx for {}", label)))
     }
 
     /// Check if an intent has been settled on-chain
-    pub async fn is_intent_settled(&self, nullifier: &str) -> Result<bool> {
+    pub async fn get_intent_status(&self, nullifier: &str) -> Result<OnchainIntentStatus> {
         let call = FunctionCall {
             contract_address: self.dark_pool_address,
             entry_point_selector: get_selector_from_name("get_intent_status")?,
             calldata: vec![felt_from_hex(nullifier)?],
         };
 
-        let result = self.provider.call(call, BlockId::Tag(BlockTag::Latest)).await?;
+        let result = self.call_contract(call).await?;
+        result
+            .first()
+            .and_then(|v| OnchainIntentStatus::from_felt(*v))
+            .ok_or_else(|| anyhow::anyhow!("Unexpected get_intent_status result: {:?}", result))
+    }
+
+    pub async fn is_intent_settled(&self, nullifier: &str) -> Result<bool> {
+        Ok(self.get_intent_status(nullifier).await? == OnchainIntentStatus::Settled)
+    }
+
+    async fn read_block(&self) -> Result<MaybePendingBlockWithTxHashes> {
+        let (block, fallback) = self.read_blocks();
+        let mut result = self.provider.get_block_with_tx_hashes(block).await;
+        if let (Err(e), Some(fallback)) = (&result, fallback) {
+            if !matches!(e, ProviderError::StarknetError(_)) {
+                result = self.provider.get_block_with_tx_hashes(fallback).await;
+            }
+        }
+        Ok(result?)
+    }
+
+    /// Timestamp of the block at RPC_BLOCK_TAG, i.e. what the contract compares deadlines against.
+    pub async fn block_timestamp(&self) -> Result<u64> {
+        Ok(match self.read_block().await? {
+            MaybePendingBlockWithTxHashes::Block(block) => block.timestamp,
+            MaybePendingBlockWithTxHashes::PendingBlock(block) => block.timestamp,
+        })
+    }
+
+    /// L1 gas price of the block at RPC_BLOCK_TAG, in base units of the settlement fee token
+    /// (wei for ETH, fri for STRK).
+    pub async fn l1_gas_price(&self) -> Result<BigUint> {
+        let price = match self.read_block().await? {
+            MaybePendingBlockWithTxHashes::Block(block) => block.l1_gas_price,
+            MaybePendingBlockWithTxHashes::PendingBlock(block) => block.l1_gas_price,
+        };
+        let price = match self.fee_token {
+            FeeToken::Eth => price.price_in_wei,
+            FeeToken::Strk => price.price_in_fri,
+        };
+        Ok(BigUint::from_bytes_be(&price.to_bytes_be()))
+    }
+
+    /// Whether a contract is deployed at `address`, e.g. an Ekubo pool the matcher wants to route through.
+    pub async fn is_deployed(&self, address: &str) -> Result<bool> {
+        let address = parse_felt_any(address)?;
+        let (block, fallback) = self.read_blocks();
+        let mut result = self.provider.get_class_hash_at(block, address).await;
+        if let (Err(e), Some(fallback)) = (&result, fallback) {
+            if !matches!(e, ProviderError::StarknetError(_)) {
+                result = self.provider.get_class_hash_at(fallback, address).await;
+            }
+        }
+        match result {
+            Ok(_) => Ok(true),
+            Err(ProviderError::StarknetError(StarknetError::ContractNotFound)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Identifier of the initialized Ekubo pool for the pair at `fee` (0.128 fixed point) and
+    /// `tick_spacing`, without an extension. Ekubo pools have no address of their own, they live in
+    /// the core contract under their `PoolKey`, so the identifier is the Pedersen hash of that key.
+    /// Errors with `EKUBO_POOL_NOT_INITIALIZED` when the core has no price for the key.
+    pub async fn resolve_ekubo_pool(&self, token_a: &str, token_b: &str, fee: u128, tick_spacing: u32) -> Result<Felt> {
+        if self.get_pool_sqrt_price(token_a, token_b, fee, tick_spacing).await? == BigUint::from(0u8) {
+            return Err(anyhow::anyhow!(
+                "EKUBO_POOL_NOT_INITIALIZED: {}/{} fee={} tick_spacing={}",
+                token_a, token_b, fee, tick_spacing
+            ));
+        }
+        let key = ekubo_pool_key(parse_felt_any(token_a)?, parse_felt_any(token_b)?, fee, tick_spacing);
+        Ok(compute_hash_on_elements(&key))
+    }
+
+    /// Current `sqrt_ratio` of the pair's Ekubo pool: sqrt(token1 / token0), in base units, as a
+    /// 64.128 fixed-point number. Zero for a pool nobody has initialized.
+    pub async fn get_pool_sqrt_price(&self, token_a: &str, token_b: &str, fee: u128, tick_spacing: u32) -> Result<BigUint> {
+        let core = self
+            .ekubo_core
+            .ok_or_else(|| anyhow::anyhow!("EKUBO_CORE_ADDRESS is not set"))?;
+        let key = ekubo_pool_key(parse_felt_any(token_a)?, parse_felt_any(token_b)?, fee, tick_spacing);
+        let call = FunctionCall {
+            contract_address: core,
+            entry_point_selector: get_selector_from_name("get_pool_price")?,
+            calldata: key.to_vec(),
+        };
+        // `PoolPrice { sqrt_ratio: u256, tick: i129 }`; only the ratio is read.
+        let result = self.call_contract(call).await?;
+        parse_u256_result(result.get(..2).unwrap_or(&result))
+    }
+
+    /// Fee charged for `tx_hash` (`actual_fee.amount`, fee-token base units), or `None` while the
+    /// node has no receipt for it yet.
+    pub async fn transaction_fee(&self, tx_hash: &str) -> Result<Option<BigUint>> {
+        match self.provider.get_transaction_receipt(felt_from_hex(tx_hash)?).await {
+            Ok(receipt) => Ok(Some(BigUint::from_bytes_be(
+                &receipt.receipt.actual_fee().amount.to_bytes_be(),
+            ))),
+            Err(ProviderError::StarknetError(StarknetError::TransactionHashNotFound)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Nullifier pairs that `tx_hash` settled, read from the dark pool's `IntentSettled` events.
+    /// `None` while the node has no receipt; an error if the transaction reverted.
+    pub async fn settled_nullifier_pairs(&self, tx_hash: &str) -> Result<Option<Vec<(Felt, Felt)>>> {
+        match self.receipt_events(tx_hash).await? {
+            Some(events) => intent_settled_pairs(self.dark_pool_address, &events).map(Some),
+            None => Ok(None),
+        }
+    }
 
-        // Status 2 = Settled
-        Ok(!result.is_empty() && result[0] == Felt::from(2u8))
+    /// Every event `tx_hash` emitted, decoded where the selector is known (see `decode_events`).
+    /// `None` while the node has no receipt; an error if the transaction reverted.
+    pub async fn settlement_events(&self, tx_hash: &str) -> Result<Option<Vec<SettlementEvent>>> {
+        Ok(self
+            .receipt_events(tx_hash)
+            .await?
+            .map(|events| decode_events(self.dark_pool_address, &events)))
+    }
+
+    pub async fn transaction_outcome(&self, tx_hash: &str) -> Result<TxOutcome> {
+        let receipt = match self.provider.get_transaction_receipt(felt_from_hex(tx_hash)?).await {
+            Ok(receipt) => receipt,
+            Err(ProviderError::StarknetError(StarknetError::TransactionHashNotFound)) => return Ok(TxOutcome::Unknown),
+            Err(e) => return Err(e.into()),
+        };
+        let block_number = match receipt.block {
+            ReceiptBlock::Block { block_number, .. } => Some(block_number),
+            _ => None,
+        };
+        Ok(receipt_outcome(
+            receipt.receipt.execution_result(),
+            receipt.receipt.finality_status(),
+            block_number,
+            receipt.receipt.events(),
+        ))
+    }
+
+    async fn receipt_events(&self, tx_hash: &str) -> Result<Option<Vec<Event>>> {
+        let receipt = match self.provider.get_transaction_receipt(felt_from_hex(tx_hash)?).await {
+            Ok(receipt) => receipt.receipt,
+            Err(ProviderError::StarknetError(StarknetError::TransactionHashNotFound)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if let ExecutionResult::Reverted { reason } = receipt.execution_result() {
+            return Err(anyhow::anyhow!("TX_REVERTED tx={} reason={}", tx_hash, reason));
+        }
+        Ok(Some(receipt.events().to_vec()))
     }
 
     pub fn dark_pool_address(&self) -> Felt {
@@ -206,7 +1016,7 @@ impl StarknetClient {
             entry_point_selector: get_selector_from_name("balanceOf")?,
             calldata: vec![felt_from_hex(owner)?],
         };
-        let result = self.provider.call(call, BlockId::Tag(BlockTag::Latest)).await?;
+        let result = self.call_contract(call).await?;
         parse_u256_result(&result)
     }
 
@@ -216,11 +1026,188 @@ impl StarknetClient {
             entry_point_selector: get_selector_from_name("allowance")?,
             calldata: vec![felt_from_hex(owner)?, spender],
         };
-        let result = self.provider.call(call, BlockId::Tag(BlockTag::Latest)).await?;
+        let result = self.call_contract(call).await?;
         parse_u256_result(&result)
     }
 }
 
+fn is_nonce_error(msg: &str) -> bool {
+    msg.contains("NonceTooOld")
+        || msg.contains("InvalidTransactionNonce")
+        || msg.contains("Invalid transaction nonce")
+}
+
+/// Refuses solver accounts the DarkPool won't take settlements from: anything but its `owner`.
+/// An unknown owner (unreadable, or zero because the storage layout differs) passes a single
+/// account, but not a pool, which only works once the contract accepts more than one solver.
+fn check_solver_accounts(owner: Option<Felt>, addresses: &[Felt]) -> Result<()> {
+    match owner.filter(|owner| *owner != Felt::ZERO) {
+        Some(owner) => {
+            let refused: Vec<String> =
+                addresses.iter().filter(|a| **a != owner).map(|a| format!("0x{:x}", a)).collect();
+            if refused.is_empty() {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!(
+                    "DarkPool only accepts settlements from its owner 0x{:x}; refusing solver account(s) {}",
+                    owner,
+                    refused.join(", ")
+                ))
+            }
+        }
+        None if addresses.len() > 1 => Err(anyhow::anyhow!(
+            "Could not confirm that the DarkPool accepts settlements from all {} solver accounts",
+            addresses.len()
+        )),
+        None => Ok(()),
+    }
+}
+
+/// The revert behind a failed `starknet_estimateFee` or `starknet_simulateTransactions`, when the
+/// node says the transaction would revert rather than failing for some other reason.
+fn execution_revert_reason(err: &StarknetError) -> Option<String> {
+    match err {
+        StarknetError::TransactionExecutionError(data) => Some(format!("{:?}", data.execution_error)),
+        StarknetError::ContractError(data) => Some(format!("{:?}", data.revert_error)),
+        _ => None,
+    }
+}
+
+fn simulated_revert_reason(trace: &TransactionTrace) -> Option<String> {
+    match trace {
+        TransactionTrace::Invoke(invoke) => match &invoke.execute_invocation {
+            ExecuteInvocation::Reverted(reverted) => Some(reverted.revert_reason.clone()),
+            ExecuteInvocation::Success(_) => None,
+        },
+        _ => None,
+    }
+}
+
+/// Events of a successful simulated invoke, each tagged with the contract that emitted it.
+fn simulated_events(trace: &TransactionTrace) -> Vec<Event> {
+    fn collect(invocation: &FunctionInvocation, events: &mut Vec<Event>) {
+        events.extend(invocation.events.iter().map(|e| Event {
+            from_address: invocation.contract_address,
+            keys: e.keys.clone(),
+            data: e.data.clone(),
+        }));
+        for call in &invocation.calls {
+            collect(call, events);
+        }
+    }
+    let mut events = Vec::new();
+    if let TransactionTrace::Invoke(invoke) = trace {
+        if let ExecuteInvocation::Success(invocation) = &invoke.execute_invocation {
+            collect(invocation, &mut events);
+        }
+    }
+    events
+}
+
+/// Total ERC-20 `Transfer` of `token` to `recipient` in `events`. Cairo 1 tokens key `from` and
+/// `to` (`keys = [selector, from, to]`, `data = [low, high]`); older ones put everything in the
+/// data (`[from, to, low, high]`).
+pub fn transfers_to(events: &[Event], token: Felt, recipient: Felt) -> BigUint {
+    let Ok(selector) = get_selector_from_name("Transfer") else {
+        return BigUint::from(0u8);
+    };
+    let word = |f: &Felt| BigUint::from_bytes_be(&f.to_bytes_be());
+    events
+        .iter()
+        .filter(|e| e.from_address == token && e.keys.first() == Some(&selector))
+        .filter_map(|e| {
+            let (to, low, high) = match (e.keys.as_slice(), e.data.as_slice()) {
+                ([_, _, to], [low, high, ..]) => (to, low, high),
+                ([_], [_, to, low, high, ..]) => (to, low, high),
+                _ => return None,
+            };
+            (*to == recipient).then(|| word(low) + (word(high) << 128u32))
+        })
+        .sum()
+}
+
+#[async_trait]
+impl SettlementFeeEstimator for StarknetClient {
+    async fn estimate_settlement_fee(&self, pair: &MatchedPair) -> Result<SettlementFeeEstimate> {
+        StarknetClient::estimate_settlement_fee(self, pair).await
+    }
+}
+
+#[async_trait]
+impl SettlementReceipts for StarknetClient {
+    async fn transaction_outcome(&self, tx_hash: &str) -> Result<TxOutcome> {
+        StarknetClient::transaction_outcome(self, tx_hash).await
+    }
+
+    async fn is_intent_settled(&self, nullifier: &str) -> Result<bool> {
+        StarknetClient::is_intent_settled(self, nullifier).await
+    }
+}
+
+/// Cairo ABI encoding for:
+/// `settle_match(intent_a: IntentProof, intent_b: IntentProof, settlement_data: SettlementData)`
+///
+/// IntentProof = { intent_hash, nullifier, proof_data: Array<felt252>, public_inputs: Array<felt252> }
+/// SettlementData = { ekubo_pool: ContractAddress, sqrt_price_limit: u256(low, high) }
+///   with SURPLUS_POLICY: { ..., surplus_a_bps: u16, surplus_b_bps: u16, surplus_solver_bps: u16 }
+fn settle_match_calldata(pair: &MatchedPair) -> Result<Vec<Felt>> {
+    let mut calldata: Vec<Felt> = Vec::new();
+    append_intent_proof(&mut calldata, &pair.intent_a)?;
+    append_intent_proof(&mut calldata, &pair.intent_b)?;
+
+    // Settlement data
+    calldata.push(parse_felt_any(&pair.settlement_data.ekubo_pool)?);
+    let (low, high) = parse_u256_low_high(&pair.settlement_data.sqrt_price_limit)?;
+    calldata.push(low);
+    calldata.push(high);
+    if let Some(surplus) = &pair.settlement_data.surplus_distribution {
+        calldata.push(Felt::from(surplus.intent_a_bps));
+        calldata.push(Felt::from(surplus.intent_b_bps));
+        calldata.push(Felt::from(surplus.solver_bps));
+    }
+    Ok(calldata)
+}
+
+/// One `settle_match` call per pair, in order, for a multicall settlement.
+pub fn settle_matches_calls(dark_pool: Felt, pairs: &[MatchedPair]) -> Result<Vec<Call>> {
+    let selector = get_selector_from_name("settle_match")?;
+    pairs
+        .iter()
+        .map(|pair| {
+            Ok(Call {
+                to: dark_pool,
+                selector,
+                calldata: settle_match_calldata(pair)?,
+            })
+        })
+        .collect()
+}
+
+/// Maps a DarkPool / ERC-20 revert reason onto the error codes the matcher's retry policy understands.
+pub fn classify_revert_reason(reason: &str) -> &'static str {
+    let r = reason.to_ascii_lowercase();
+    if r.contains("invalid proof") {
+        "INVALID_PROOFS"
+    } else if r.contains("allowance") {
+        "INSUFFICIENT_ALLOWANCE"
+    } else if r.contains("insufficient balance")
+        || r.contains("exceeds balance")
+        || r.contains("u256_sub overflow")
+    {
+        "INSUFFICIENT_BALANCE"
+    } else if r.contains("not pending") || r.contains("intent already exists") {
+        "INTENT_NOT_PENDING"
+    } else if r.contains("amount mismatch") || r.contains("token mismatch") {
+        "INCOMPATIBLE_INTENTS"
+    } else if r.contains("contract is paused") {
+        "CONTRACT_PAUSED"
+    } else if r.contains("unauthorized solver") {
+        "UNAUTHORIZED_SOLVER"
+    } else {
+        "REVERTED"
+    }
+}
+
 fn parse_account_nonce_from_err(msg: &str) -> Option<Felt> {
     // Example (from logs):
     // InvalidTransactionNonce: "MempoolError(NonceTooOld { address: ..., tx_nonce: Nonce(0x10), account_nonce: Nonce(0x11) })"
@@ -254,7 +1241,121 @@ fn parse_account_nonce_from_invalid_nonce(msg: &str) -> Option<Felt> {
     Felt::from_hex(raw).ok()
 }
 
-fn felt_from_hex(value: &str) -> Result<Felt> {
+/// `(nullifier_a, nullifier_b)` of each `IntentSettled` the dark pool emitted. The event has no
+/// `#[key]` members, so both nullifiers lead its data.
+fn intent_settled_pairs(dark_pool: Felt, events: &[Event]) -> Result<Vec<(Felt, Felt)>> {
+    let selector = get_selector_from_name("IntentSettled")?;
+    Ok(events
+        .iter()
+        .filter(|e| e.from_address == dark_pool && e.keys.first() == Some(&selector))
+        .filter_map(|e| Some((*e.data.first()?, *e.data.get(1)?)))
+        .collect())
+}
+
+/// Decodes the dark pool's own events (none of their members are `#[key]`, so everything is in the
+/// data) and ERC-20 `Transfer`/`Approval` from any contract, in both layouts `transfers_to` reads.
+/// Anything else, or a known event too short for its layout, comes back as `Unknown`.
+pub fn decode_events(dark_pool: Felt, events: &[Event]) -> Vec<SettlementEvent> {
+    let selector = |name: &str| get_selector_from_name(name).ok();
+    let (settled, submitted, cancelled, verified, transfer, approval) = (
+        selector("IntentSettled"),
+        selector("IntentSubmitted"),
+        selector("IntentCancelled"),
+        selector("ProofVerified"),
+        selector("Transfer"),
+        selector("Approval"),
+    );
+    let hex = |f: &Felt| format!("0x{:x}", f);
+    let word = |f: &Felt| BigUint::from_bytes_be(&f.to_bytes_be());
+    let u256 = |low: &Felt, high: &Felt| (word(low) + (word(high) << 128u32)).to_string();
+    let timestamp = |f: &Felt| word(f).to_u64().unwrap_or(u64::MAX);
+
+    events
+        .iter()
+        .map(|e| {
+            let key = e.keys.first().copied();
+            let contract = hex(&e.from_address);
+            let decoded = match (e.keys.as_slice(), e.data.as_slice()) {
+                (_, [a, b, token_in, token_out, in_low, in_high, out_low, out_high, at, ..])
+                    if e.from_address == dark_pool && key == settled =>
+                {
+                    Some(SettlementEvent::IntentSettled {
+                        contract: contract.clone(),
+                        nullifier_a: hex(a),
+                        nullifier_b: hex(b),
+                        token_in: hex(token_in),
+                        token_out: hex(token_out),
+                        amount_in: u256(in_low, in_high),
+                        amount_out: u256(out_low, out_high),
+                        timestamp: timestamp(at),
+                    })
+                }
+                (_, [user, nullifier, at, ..]) if e.from_address == dark_pool && key == submitted => {
+                    Some(SettlementEvent::IntentSubmitted {
+                        contract: contract.clone(),
+                        user: hex(user),
+                        nullifier: hex(nullifier),
+                        timestamp: timestamp(at),
+                    })
+                }
+                (_, [user, nullifier, at, ..]) if e.from_address == dark_pool && key == cancelled => {
+                    Some(SettlementEvent::IntentCancelled {
+                        contract: contract.clone(),
+                        user: hex(user),
+                        nullifier: hex(nullifier),
+                        timestamp: timestamp(at),
+                    })
+                }
+                (_, [nullifier, verifier, success, ..]) if e.from_address == dark_pool && key == verified => {
+                    Some(SettlementEvent::ProofVerified {
+                        contract: contract.clone(),
+                        nullifier: hex(nullifier),
+                        verifier: hex(verifier),
+                        success: *success != Felt::ZERO,
+                    })
+                }
+                ([_, from, to], [low, high, ..]) | ([_], [from, to, low, high, ..]) if key == transfer => {
+                    Some(SettlementEvent::Transfer {
+                        token: contract.clone(),
+                        from: hex(from),
+                        to: hex(to),
+                        amount: u256(low, high),
+                    })
+                }
+                ([_, owner, spender], [low, high, ..]) | ([_], [owner, spender, low, high, ..]) if key == approval => {
+                    Some(SettlementEvent::Approval {
+                        token: contract.clone(),
+                        owner: hex(owner),
+                        spender: hex(spender),
+                        amount: u256(low, high),
+                    })
+                }
+                _ => None,
+            };
+            decoded.unwrap_or_else(|| SettlementEvent::Unknown {
+                contract,
+                keys: e.keys.iter().map(hex).collect(),
+                data: e.data.iter().map(hex).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Calldata for Ekubo's `PoolKey { token0, token1, fee, tick_spacing, extension }`: the tokens in the
+/// ascending order Ekubo requires, and no extension.
+pub fn ekubo_pool_key(token_a: Felt, token_b: Felt, fee: u128, tick_spacing: u32) -> [Felt; 5] {
+    let (token0, token1) = if token_a <= token_b { (token_a, token_b) } else { (token_b, token_a) };
+    [token0, token1, Felt::from(fee), Felt::from(tick_spacing), Felt::ZERO]
+}
+
+/// Whether `pairs` settles `nullifier_a` and `nullifier_b` together, in either order.
+pub fn settles_pair(pairs: &[(Felt, Felt)], nullifier_a: &str, nullifier_b: &str) -> Result<bool> {
+    let a = parse_felt_any(nullifier_a)?;
+    let b = parse_felt_any(nullifier_b)?;
+    Ok(pairs.iter().any(|&(x, y)| (x, y) == (a, b) || (x, y) == (b, a)))
+}
+
+pub(crate) fn felt_from_hex(value: &str) -> Result<Felt> {
     // starknet-rs moved from FieldElement -> Felt. Keep parsing centralized so future changes are localized.
     Ok(Felt::from_hex(value)?)
 }
@@ -278,6 +1379,88 @@ fn parse_felt_any(value: &str) -> Result<Felt> {
     Ok(Felt::from_dec_str(&n.to_str_radix(10))?)
 }
 
+/// SNIP-12 (revision 0) types of the message an intent's `signature` signs.
+const INTENT_DOMAIN_TYPE: &str = "StarkNetDomain(name:felt,version:felt,chainId:felt)";
+const INTENT_MESSAGE_TYPE: &str = "Intent(user:felt,token_in:felt,token_out:felt,amount_in:felt,min_amount_out:felt,deadline:felt,nonce:felt,chain_id:felt,domain_separator:felt)";
+
+/// Hash the user's account signs to authorize an intent: SNIP-12 revision 0 typed data with domain
+/// `{ name: "StarkShield", version: "1", chainId: chain_id }` and an `Intent` message of the public
+/// inputs, amounts in base units. Felt strings are read the way starknet.js reads them: `0x` hex or
+/// decimal digits are numbers (so `version: "1"` is `0x1`), anything else is a short string. This is
+/// the hash an account's `is_valid_signature` is asked about.
+pub fn intent_message_hash(inputs: &PublicInputs) -> Result<Felt> {
+    let text = |value: &str| -> Result<Felt> {
+        let value = value.trim();
+        if value.starts_with("0x") {
+            felt_from_hex(value)
+        } else if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+            Ok(Felt::from_dec_str(value)?)
+        } else {
+            Ok(cairo_short_string_to_felt(value)?)
+        }
+    };
+    let amount = |value: &str, token: &str| -> Result<Felt> {
+        let units = TokenAmount::parse(value, token_decimals_for(token))?.into_base_units();
+        Ok(Felt::from_dec_str(&units.to_str_radix(10))?)
+    };
+    let chain_id = text(&inputs.chain_id)?;
+    let user = felt_from_hex(&inputs.user)?;
+    let domain = compute_hash_on_elements(&[
+        starknet_keccak(INTENT_DOMAIN_TYPE.as_bytes()),
+        cairo_short_string_to_felt("StarkShield")?,
+        Felt::ONE,
+        chain_id,
+    ]);
+    let message = compute_hash_on_elements(&[
+        starknet_keccak(INTENT_MESSAGE_TYPE.as_bytes()),
+        user,
+        felt_from_hex(&inputs.token_in)?,
+        felt_from_hex(&inputs.token_out)?,
+        amount(&inputs.amount_in, &inputs.token_in)?,
+        amount(&inputs.min_amount_out, &inputs.token_out)?,
+        Felt::from(inputs.deadline),
+        Felt::from(inputs.nonce),
+        chain_id,
+        text(&inputs.domain_separator)?,
+    ]);
+    Ok(compute_hash_on_elements(&[
+        cairo_short_string_to_felt("StarkNet Message")?,
+        domain,
+        user,
+        message,
+    ]))
+}
+
+/// Shape check for intent signatures, run before the account is asked to verify one.
+///
+/// A single opaque blob (legacy clients) must be `0x` followed by an even number of hex digits,
+/// at least 64 of them. A multi-felt signature (`[r, s, ...]`) needs every element to be a
+/// `0x` felt below the field prime; felts are commonly unpadded, so odd lengths are fine there.
+pub fn is_well_formed_signature(elements: &[&str], single_blob: bool) -> bool {
+    fn hex_digits(value: &str) -> Option<&str> {
+        value
+            .strip_prefix("0x")
+            .filter(|digits| !digits.is_empty() && digits.chars().all(|ch| ch.is_ascii_hexdigit()))
+    }
+
+    if single_blob {
+        return match elements {
+            [blob] => hex_digits(blob).is_some_and(|digits| digits.len() >= 64 && digits.len() % 2 == 0),
+            _ => false,
+        };
+    }
+
+    if elements.is_empty() {
+        return false;
+    }
+    let prime = starknet_field_prime();
+    elements.iter().all(|element| {
+        hex_digits(element)
+            .and_then(|digits| BigUint::from_str_radix(digits, 16).ok())
+            .is_some_and(|value| value < prime)
+    })
+}
+
 fn starknet_field_prime() -> BigUint {
     // Starknet field prime:
     // p = 2^251 + 17 * 2^192 + 1
@@ -322,42 +1505,343 @@ mod tests {
     }
 
     #[test]
-    fn parse_amount_18_to_felt_converts_decimals() {
-        let f = parse_amount_18_to_felt("0.01").expect("parse");
-        // 0.01 * 1e18 = 1e16
+    fn only_execution_failures_count_as_simulated_reverts() {
+        let rate_limited = AccountError::<std::convert::Infallible>::Provider(ProviderError::RateLimited);
+        assert!(matches!(SimulationError::of(rate_limited), SimulationError::Unavailable(_)));
+        let not_found =
+            AccountError::<std::convert::Infallible>::Provider(ProviderError::StarknetError(StarknetError::ContractNotFound));
+        assert!(matches!(SimulationError::of(not_found), SimulationError::Unavailable(_)));
+    }
+
+    #[test]
+    fn classify_revert_reason_maps_darkpool_and_erc20_reasons() {
+        assert_eq!(classify_revert_reason("Failure reason: 0x496e76616c69642070726f6f6673 ('Invalid proofs')"), "INVALID_PROOFS");
+        assert_eq!(classify_revert_reason("ERC20: insufficient allowance"), "INSUFFICIENT_ALLOWANCE");
+        assert_eq!(classify_revert_reason("ERC20: insufficient balance"), "INSUFFICIENT_BALANCE");
+        assert_eq!(classify_revert_reason("'Intent A not pending'"), "INTENT_NOT_PENDING");
+        assert_eq!(classify_revert_reason("'Amount mismatch'"), "INCOMPATIBLE_INTENTS");
+        assert_eq!(classify_revert_reason("something else entirely"), "REVERTED");
+    }
+
+    #[test]
+    fn intent_settled_events_identify_the_settled_pair() {
+        let pool = Felt::from(0x900u16);
+        let settled = get_selector_from_name("IntentSettled").unwrap();
+        let event = |from: Felt, key: Felt, data: Vec<Felt>| Event { from_address: from, keys: vec![key], data };
+        let events = vec![
+            event(pool, settled, vec![Felt::from(0xau8), Felt::from(0xbu8), Felt::from(1u8)]),
+            // Same shape from another contract, and another event from the pool.
+            event(Felt::from(0x901u16), settled, vec![Felt::from(0xcu8), Felt::from(0xdu8)]),
+            event(pool, get_selector_from_name("IntentCancelled").unwrap(), vec![Felt::from(0xcu8), Felt::from(0xdu8)]),
+        ];
+        let pairs = intent_settled_pairs(pool, &events).expect("pairs");
+        assert_eq!(pairs, vec![(Felt::from(0xau8), Felt::from(0xbu8))]);
+        assert!(settles_pair(&pairs, "0xa", "0xb").unwrap());
+        assert!(settles_pair(&pairs, "0x0b", "0xa").unwrap());
+        assert!(!settles_pair(&pairs, "0xc", "0xd").unwrap());
+        assert!(!settles_pair(&pairs, "0xa", "0xc").unwrap());
+    }
+
+    #[test]
+    fn transfers_to_sums_both_event_layouts() {
+        let token = Felt::from(0x70u8);
+        let (user, other) = (Felt::from(0xau8), Felt::from(0xbu8));
+        let transfer = get_selector_from_name("Transfer").unwrap();
+        let event = |from: Felt, keys: Vec<Felt>, data: Vec<Felt>| Event { from_address: from, keys, data };
+        let events = vec![
+            // Cairo 1: from/to are keys.
+            event(token, vec![transfer, other, user], vec![Felt::from(5u8), Felt::from(0u8)]),
+            // Legacy: everything in data; the high word counts too.
+            event(token, vec![transfer], vec![other, user, Felt::from(1u8), Felt::from(1u8)]),
+            event(token, vec![transfer, user, other], vec![Felt::from(100u8), Felt::from(0u8)]),
+            event(Felt::from(0x71u8), vec![transfer, other, user], vec![Felt::from(100u8), Felt::from(0u8)]),
+        ];
+        let two_pow_128 = BigUint::from(1u8) << 128u32;
+        assert_eq!(transfers_to(&events, token, user), BigUint::from(6u8) + two_pow_128);
+        assert_eq!(transfers_to(&events, token, other), BigUint::from(100u8));
+        assert_eq!(transfers_to(&events, Felt::from(0x72u8), user), BigUint::from(0u8));
+    }
+
+    #[test]
+    fn receipts_map_to_outcomes() {
+        let succeeded = ExecutionResult::Succeeded;
+        let events = vec![Event { from_address: Felt::from(0x71u8), keys: vec![Felt::from(0x99u8)], data: vec![Felt::ONE] }];
+        assert_eq!(
+            receipt_outcome(&succeeded, &TransactionFinalityStatus::AcceptedOnL2, Some(812_345), &events),
+            TxOutcome::Confirmed { block_number: 812_345, events: events.clone() }
+        );
+        assert_eq!(
+            receipt_outcome(&succeeded, &TransactionFinalityStatus::AcceptedOnL1, Some(812_345), &[]),
+            TxOutcome::Confirmed { block_number: 812_345, events: Vec::new() }
+        );
+        // Executed in the pending block: not accepted yet, whatever the status says.
+        assert_eq!(
+            receipt_outcome(&succeeded, &TransactionFinalityStatus::AcceptedOnL2, None, &events),
+            TxOutcome::Pending
+        );
+
+        let reverted = ExecutionResult::Reverted { reason: "Error in the called contract: 'Invalid proofs'".to_string() };
+        assert_eq!(
+            receipt_outcome(&reverted, &TransactionFinalityStatus::AcceptedOnL2, Some(812_345), &[]),
+            TxOutcome::Reverted("Error in the called contract: 'Invalid proofs'".to_string())
+        );
         assert_eq!(
-            f,
-            Felt::from_dec_str("10000000000000000").expect("felt")
+            receipt_outcome(&reverted, &TransactionFinalityStatus::AcceptedOnL2, None, &[]),
+            TxOutcome::Reverted("Error in the called contract: 'Invalid proofs'".to_string())
+        );
+    }
+
+    #[test]
+    fn fee_tokens_pick_the_invoke_version_and_v3_bounds_cover_the_estimate() {
+        assert_eq!(InvokeVersion::for_fee_token(FeeToken::Eth), InvokeVersion::V1);
+        assert_eq!(InvokeVersion::for_fee_token(FeeToken::Strk), InvokeVersion::V3);
+
+        // Just under 2000 gas at 1 gwei rounds up to 2000, then both take the 1.5x margin.
+        let (gas, price) = v3_resource_bounds(Felt::from(1_999_999_999_999u64), Felt::from(1_000_000_000u64)).unwrap();
+        assert_eq!((gas, price), (3000, 1_500_000_000));
+        assert!(u128::from(gas) * price >= 1_999_999_999_999);
+
+        assert!(v3_resource_bounds(Felt::from(1u8), Felt::ZERO).is_err());
+        assert!(v3_resource_bounds(Felt::from(u128::MAX), Felt::ONE).is_err());
+    }
+
+    #[test]
+    fn only_the_dark_pool_owner_may_settle() {
+        let (owner, other) = (Felt::from(0x111u16), Felt::from(0x222u16));
+        assert!(check_solver_accounts(Some(owner), &[owner]).is_ok());
+        let refused = check_solver_accounts(Some(owner), &[owner, other]).unwrap_err().to_string();
+        assert!(refused.ends_with("refusing solver account(s) 0x222"));
+        assert!(check_solver_accounts(Some(owner), &[other]).is_err());
+
+        // Owner unknown: one account goes ahead as before, a pool can't be vouched for.
+        assert!(check_solver_accounts(None, &[other]).is_ok());
+        assert!(check_solver_accounts(Some(Felt::ZERO), &[other]).is_ok());
+        assert!(check_solver_accounts(None, &[owner, other]).is_err());
+    }
+
+    #[tokio::test]
+    async fn account_pool_hands_out_idle_accounts_in_turn() {
+        let pool = AccountPool::new(vec![1u8, 2, 3]);
+        let (first, held) = pool.acquire().await;
+        let (second, _) = pool.acquire().await;
+        assert_eq!((first.account, second.account), (1, 2));
+
+        let (third, _) = pool.acquire().await;
+        assert_eq!(third.account, 3);
+        // The turn is back at the first account, still busy, so the next idle one goes instead.
+        let (fourth, _) = pool.acquire().await;
+        assert_eq!(fourth.account, 2);
+        drop(held);
+
+        let (primary, _) = pool.acquire_primary().await;
+        assert_eq!(primary.account, 1);
+        assert_eq!(pool.len(), 3);
+    }
+
+    #[test]
+    fn multicall_settlement_has_one_settle_match_call_per_pair() {
+        use crate::models::SettlementData;
+
+        let intents = crate::fixture::parse_fixture(include_str!("../fixtures/matching_sample.json")).expect("fixture");
+        let data = || SettlementData { ekubo_pool: "0x0".to_string(), sqrt_price_limit: "0".to_string(), surplus_distribution: None };
+        let pairs = vec![
+            MatchedPair::new(intents[0].clone(), intents[1].clone(), data()),
+            MatchedPair::new(intents[2].clone(), intents[1].clone(), data()),
+        ];
+        let dark_pool = Felt::from(0x900u16);
+
+        let calls = settle_matches_calls(dark_pool, &pairs).expect("calls");
+        assert_eq!(calls.len(), 2);
+        for (call, pair) in calls.iter().zip(&pairs) {
+            assert_eq!(call.to, dark_pool);
+            assert_eq!(call.selector, get_selector_from_name("settle_match").unwrap());
+            assert_eq!(call.calldata, settle_match_calldata(pair).expect("calldata"));
+        }
+        assert_ne!(calls[0].calldata, calls[1].calldata);
+    }
+
+    #[test]
+    fn settlement_events_decode_pool_and_token_events() {
+        let (pool, token) = (Felt::from(0x900u16), Felt::from(0x70u8));
+        let (a, b, user) = (Felt::from(0xau8), Felt::from(0xbu8), Felt::from(0x11u8));
+        let selector = |name: &str| get_selector_from_name(name).unwrap();
+        let event = |from: Felt, keys: Vec<Felt>, data: Vec<Felt>| Event { from_address: from, keys, data };
+        let n = |v: u64| Felt::from(v);
+        let events = vec![
+            event(pool, vec![selector("IntentSettled")], vec![a, b, token, n(0x71), n(5), n(1), n(7), n(0), n(1_700_000_000)]),
+            event(token, vec![selector("Transfer"), pool, user], vec![n(7), n(0)]),
+            event(token, vec![selector("Approval")], vec![user, pool, n(3), n(0)]),
+            // An IntentSettled lookalike from another contract, and an event nobody knows.
+            event(n(0x901), vec![selector("IntentSettled")], vec![a, b]),
+            event(pool, vec![selector("Paused")], vec![]),
+        ];
+        let decoded = decode_events(pool, &events);
+        let json = serde_json::to_value(&decoded).expect("serialize");
+
+        assert_eq!(json[0]["event"], "intent_settled");
+        assert_eq!(json[0]["nullifier_a"], "0xa");
+        assert_eq!(json[0]["amount_in"], ((BigUint::from(1u8) << 128u32) + 5u8).to_string());
+        assert_eq!(json[0]["amount_out"], "7");
+        assert_eq!(json[0]["timestamp"], 1_700_000_000u64);
+        assert_eq!(json[1], serde_json::json!({ "event": "transfer", "token": "0x70", "from": "0x900", "to": "0x11", "amount": "7" }));
+        assert_eq!(json[2]["event"], "approval");
+        assert_eq!(json[2]["spender"], "0x900");
+        assert_eq!(json[3]["event"], "unknown");
+        assert_eq!(json[3]["data"], serde_json::json!(["0xa", "0xb"]));
+        assert_eq!(json[4]["event"], "unknown");
+    }
+
+    #[test]
+    fn intent_hash_matches_what_starknet_js_signs() {
+        // starknet.js `typedData.getMessageHash(intentTypedData(message), user)` (frontend
+        // useDarkPool.ts) for 1 ETH -> at least 2000 USDC, i.e. amounts 10^18 and 2 * 10^9.
+        let inputs = PublicInputs {
+            user: "0x064b48806902a367c8598f4f95c305e8c1a1acba5f082d294a43793113115691".to_string(),
+            token_in: "0x049d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7".to_string(),
+            token_out: "0x053c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8".to_string(),
+            amount_in: "1".to_string(),
+            min_amount_out: "2000".to_string(),
+            deadline: 1_704_114_000,
+            nonce: 1,
+            chain_id: "SN_SEPOLIA".to_string(),
+            domain_separator: "starkshield-hackathon".to_string(),
+            version: 1,
+        };
+        let hash = intent_message_hash(&inputs).expect("hash");
+        assert_eq!(hash, Felt::from_hex("0x2d74c434cf1ce6a64c363af17b038ad3b44d74b012323941add63558d08a42f").unwrap());
+
+        let tampered = [
+            PublicInputs { min_amount_out: "1".to_string(), ..inputs.clone() },
+            PublicInputs { nonce: inputs.nonce + 1, ..inputs.clone() },
+            PublicInputs { chain_id: "SN_MAIN".to_string(), ..inputs.clone() },
+            PublicInputs { domain_separator: "another-pool".to_string(), ..inputs.clone() },
+        ];
+        for other in &tampered {
+            assert_ne!(intent_message_hash(other).expect("hash"), hash);
+        }
+    }
+
+    #[test]
+    fn ekubo_pool_keys_sort_tokens_and_carry_the_tier() {
+        let (eth, usdc) = (Felt::from(0x49du32), Felt::from(0x53cu32));
+        let key = ekubo_pool_key(usdc, eth, 1 << 100, 200);
+        assert_eq!(key, ekubo_pool_key(eth, usdc, 1 << 100, 200));
+        assert_eq!(key, [eth, usdc, Felt::from(1u128 << 100), Felt::from(200u32), Felt::ZERO]);
+        assert_ne!(
+            compute_hash_on_elements(&key),
+            compute_hash_on_elements(&ekubo_pool_key(eth, usdc, 1 << 100, 1000))
         );
     }
 
     #[test]
-    fn parse_amount_18_to_felt_converts_integer_tokens() {
-        let f = parse_amount_18_to_felt("10").expect("parse");
-        // 10 * 1e18
+    fn onchain_intent_status_follows_contract_variant_order() {
+        assert_eq!(OnchainIntentStatus::from_felt(Felt::from(0u8)), Some(OnchainIntentStatus::Pending));
+        assert_eq!(OnchainIntentStatus::from_felt(Felt::from(1u8)), Some(OnchainIntentStatus::Settled));
+        assert_eq!(OnchainIntentStatus::from_felt(Felt::from(2u8)), Some(OnchainIntentStatus::Cancelled));
+        assert_eq!(OnchainIntentStatus::from_felt(Felt::from(3u8)), Some(OnchainIntentStatus::Expired));
+        assert_eq!(OnchainIntentStatus::from_felt(Felt::from(4u8)), None);
+    }
+
+    #[test]
+    fn u256_words_tolerate_short_and_prefixed_results() {
+        let w = |v: u128| BigUint::from(v);
+        let two_pow_128 = BigUint::from(1u8) << 128u32;
+        assert_eq!(u256_from_words(&[w(5), w(1)]).expect("u256"), w(5) + &two_pow_128);
+        assert_eq!(u256_from_words(&[w(5)]).expect("u256"), w(5));
+        assert_eq!(u256_from_words(&[w(0), w(5), w(1)]).expect("u256"), w(5) + &two_pow_128);
+        assert!(u256_from_words(&[]).is_err());
+        assert!(u256_from_words(&[w(1), w(5), w(1)]).is_err());
+        assert!(u256_from_words(&[w(0), w(0), w(0), w(0)]).is_err());
+        assert!(u256_from_words(&[two_pow_128.clone(), w(0)]).is_err());
+        assert!(u256_from_words(&[w(0), two_pow_128]).is_err());
+    }
+
+    #[test]
+    fn parse_u256_json_accepts_provider_shapes() {
+        let parse = |v: serde_json::Value| parse_u256_json(&v).ok();
+        let expected = Some(BigUint::from(0x10u8) + (BigUint::from(1u8) << 128u32));
+        assert_eq!(parse(serde_json::json!(["0x10", "0x1"])), expected);
+        assert_eq!(parse(serde_json::json!(["0x0", "0x10", "0x1"])), expected);
+        assert_eq!(parse(serde_json::json!(["16"])), Some(BigUint::from(16u8)));
+        assert_eq!(parse(serde_json::json!([{"low": "0x10", "high": "0x1"}])), expected);
+        assert_eq!(parse(serde_json::json!({"balance": {"low": "0x10", "high": "0x1"}})), expected);
+        assert_eq!(parse(serde_json::json!([{"balance": {"low": "0x10", "high": "0x1"}}])), expected);
+        assert_eq!(parse(serde_json::json!([])), None);
+        assert_eq!(parse(serde_json::json!(["0xzz"])), None);
+        assert_eq!(parse(serde_json::json!(null)), None);
+    }
+
+    #[test]
+    fn proof_input_bindings_compare_as_felts() {
+        let bindings = crate::config::default_proof_input_bindings();
+        let inputs = vec!["255".to_string(), "0x10".to_string(), "1704106800".to_string()];
+        assert_eq!(check_proof_input_bindings(&bindings, &inputs, "0xff", "16"), Ok(()));
+        assert_eq!(
+            check_proof_input_bindings(&bindings, &inputs, "0x10", "0xff"),
+            Err("proof_public_inputs[0] does not match intent_hash".to_string())
+        );
         assert_eq!(
-            f,
-            Felt::from_dec_str("10000000000000000000").expect("felt")
+            check_proof_input_bindings(&bindings, &inputs, "0xff", "0x11"),
+            Err("proof_public_inputs[1] does not match nullifier".to_string())
         );
+        assert_eq!(
+            check_proof_input_bindings(&bindings, &inputs[..1], "0xff", "0x10"),
+            Err("proof_public_inputs[1] is missing (must equal nullifier)".to_string())
+        );
+        assert!(check_proof_input_bindings(&bindings, &["".to_string(), "16".to_string()], "", "16").is_err());
+        assert_eq!(check_proof_input_bindings(&[], &inputs, "0x1", "0x2"), Ok(()));
     }
 
     #[test]
-    fn parse_amount_to_felt_converts_usdc_decimals() {
-        let f = parse_amount_to_felt("0.01", 6).expect("parse");
-        // 0.01 * 1e6 = 10000
-        assert_eq!(f, Felt::from_dec_str("10000").expect("felt"));
+    fn signature_shape_accepts_blobs_and_felt_lists() {
+        let blob = format!("0x{}", "ab".repeat(32));
+        assert!(is_well_formed_signature(&[&blob], true));
+        assert!(!is_well_formed_signature(&[&format!("{}c", blob)], true));
+        assert!(!is_well_formed_signature(&["0x1234"], true));
+        assert!(is_well_formed_signature(&["0x1", "0x7ab"], false));
+        assert!(!is_well_formed_signature(&["0x1", ""], false));
+        assert!(!is_well_formed_signature(&["0x1", "12"], false));
+        assert!(!is_well_formed_signature(&["0x1", "0xzz"], false));
+        // The field prime itself is out of range.
+        let prime = format!("0x{}", starknet_field_prime().to_str_radix(16));
+        assert!(!is_well_formed_signature(&["0x1", &prime], false));
+        assert!(!is_well_formed_signature(&[], false));
     }
 
     #[test]
-    fn parse_amount_to_felt_converts_usdc_integer_tokens() {
-        let f = parse_amount_to_felt("10", 6).expect("parse");
-        // 10 * 1e6
-        assert_eq!(f, Felt::from_dec_str("10000000").expect("felt"));
+    fn decodes_short_string_and_byte_array_names() {
+        let short = Felt::from_hex("0x55534443").unwrap(); // "USDC"
+        assert_eq!(decode_cairo_string(&[short]), Some("USDC".to_string()));
+
+        // "Starknet Token": no full words, 14 pending bytes.
+        let pending = Felt::from_bytes_be_slice(b"Starknet Token");
+        assert_eq!(
+            decode_cairo_string(&[Felt::from(0u8), pending, Felt::from(14u8)]),
+            Some("Starknet Token".to_string())
+        );
+
+        let long = "A token name longer than one word";
+        let (full, tail) = long.as_bytes().split_at(31);
+        let words = [
+            Felt::from(1u8),
+            Felt::from_bytes_be_slice(full),
+            Felt::from_bytes_be_slice(tail),
+            Felt::from(tail.len() as u8),
+        ];
+        assert_eq!(decode_cairo_string(&words), Some(long.to_string()));
+
+        assert_eq!(decode_cairo_string(&[]), None);
+        assert_eq!(decode_cairo_string(&[Felt::from(2u8), Felt::from(1u8), Felt::from(0u8)]), None);
     }
-}
 
-fn parse_amount_18_to_felt(value: &str) -> Result<Felt> {
-    parse_amount_to_felt(value, 18)
+    #[test]
+    fn proof_current_time_reads_third_public_signal() {
+        let inputs = |t: &str| vec!["0x1".to_string(), "0x2".to_string(), t.to_string()];
+        assert_eq!(proof_current_time(&inputs("1704106800")), Some(1_704_106_800));
+        assert_eq!(proof_current_time(&inputs("0x6592c430")), Some(1_704_117_296));
+        assert_eq!(proof_current_time(&inputs("0x10000000000000000")), None);
+        assert_eq!(proof_current_time(&["0x1".to_string(), "0x2".to_string()]), None);
+    }
 }
 
 fn normalize_hex_address(value: &str) -> String {
@@ -388,114 +1872,273 @@ fn token_decimals(token_address: &str) -> u32 {
 }
 
 fn parse_u256_result(result: &[Felt]) -> Result<BigUint> {
-    if result.len() < 2 {
-        return Err(anyhow::anyhow!("u256 response missing fields"));
-    }
-    // Starknet JSON-RPC and starknet-rs return u256 as [low, high]
-    let low = BigUint::from_str_radix(&format!("{:x}", result[0]), 16)?;
-    let high = BigUint::from_str_radix(&format!("{:x}", result[1]), 16)?;
-    Ok(low + (high << 128u32))
+    let words = result
+        .iter()
+        .map(|f| BigUint::from_str_radix(&format!("{:x}", f), 16))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    u256_from_words(&words)
 }
 
-fn parse_amount_to_felt(value: &str, decimals: u32) -> Result<Felt> {
-    // The frontend submits human-readable token amounts like "0.01" or "10".
-    // For on-chain settlement/circuit public inputs we need base units.
-    //
-    // Accept:
-    // - integer decimal strings (interpreted as whole tokens, converted to base units)
-    // - decimal strings with up to `decimals` fractional digits (interpreted as tokens, converted to base units)
-    // - hex strings `0x...` treated as integer base units
-    let v = value.trim();
-    if v.is_empty() {
-        return Ok(Felt::from(0u8));
-    }
-    if v.starts_with("0x") || v.starts_with("0X") {
-        // Already an integer amount.
-        return parse_felt_any(v);
-    }
-    if let Some((int_part, frac_part)) = v.split_once('.') {
-        if int_part.is_empty() || int_part.chars().any(|c| !c.is_ascii_digit()) {
-            return Err(anyhow::anyhow!("invalid decimal amount: {}", v));
-        }
-        if frac_part.chars().any(|c| !c.is_ascii_digit()) {
-            return Err(anyhow::anyhow!("invalid decimal amount: {}", v));
+/// Joins the words of a u256 call result. Starknet returns `[low, high]`; some providers and token
+/// implementations send a lone `[low]` for small values (high = 0) or put an Ok/variant
+/// discriminant (`0`) in front of either form. A two-word result is always read as `[low, high]`.
+/// Each limb must fit in 128 bits.
+pub fn u256_from_words(words: &[BigUint]) -> Result<BigUint> {
+    let (low, high) = match words {
+        [] => return Err(anyhow::anyhow!("u256 response missing fields")),
+        [low] => (low.clone(), BigUint::from(0u8)),
+        [low, high] => (low.clone(), high.clone()),
+        [flag, low, high] if flag.bits() == 0 => (low.clone(), high.clone()),
+        _ => {
+            return Err(anyhow::anyhow!(
+                "u256 response has unexpected shape ({} fields)",
+                words.len()
+            ))
         }
-        if frac_part.len() > decimals as usize {
-            return Err(anyhow::anyhow!("too many decimals (max {}): {}", decimals, v));
+    };
+    for (name, limb) in [("low", &low), ("high", &high)] {
+        if limb.bits() > 128 {
+            return Err(anyhow::anyhow!("u256 {} word exceeds 128 bits: {}", name, limb));
         }
+    }
+    Ok(low + (high << 128u32))
+}
 
-        let ten_pow: BigUint = BigUint::from(10u8).pow(decimals);
-        let int_n = BigUint::from_str_radix(int_part, 10)?;
-        let mut frac = frac_part.to_string();
-        while frac.len() < decimals as usize {
-            frac.push('0');
-        }
-        let frac_n = if frac.is_empty() {
-            BigUint::from(0u8)
-        } else {
-            BigUint::from_str_radix(&frac, 10)?
+/// `u256_from_words` over a raw JSON-RPC `result`: a list of felt strings, or `{"low", "high"}`
+/// (optionally wrapped as `{"balance": {...}}`, alone or as the first list element).
+pub fn parse_u256_json(result: &serde_json::Value) -> Result<BigUint> {
+    fn word(value: &serde_json::Value) -> Result<BigUint> {
+        let v = value
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("u256 word is not a string: {}", value))?
+            .trim();
+        let n = match v.strip_prefix("0x").or_else(|| v.strip_prefix("0X")) {
+            Some(hex) => BigUint::from_str_radix(hex, 16),
+            None => BigUint::from_str_radix(v, 10),
+        };
+        n.map_err(|_| anyhow::anyhow!("u256 word is not a number: {:?}", v))
+    }
+    fn from_object(obj: &serde_json::Map<String, serde_json::Value>) -> Result<BigUint> {
+        let obj = match obj.get("balance").and_then(|v| v.as_object()) {
+            Some(inner) => inner,
+            None => obj,
         };
-        let n = (int_n * ten_pow) + frac_n;
-        return Ok(Felt::from_dec_str(&n.to_str_radix(10))?);
+        match (obj.get("low"), obj.get("high")) {
+            (Some(low), Some(high)) => u256_from_words(&[word(low)?, word(high)?]),
+            _ => Err(anyhow::anyhow!("u256 response missing fields")),
+        }
     }
 
-    // No dot: integer tokens (whole tokens) -> base units
-    if v.chars().all(|c| c.is_ascii_digit()) {
-        let ten_pow: BigUint = BigUint::from(10u8).pow(decimals);
-        let int_n = BigUint::from_str_radix(v, 10)?;
-        let n = int_n * ten_pow;
-        return Ok(Felt::from_dec_str(&n.to_str_radix(10))?);
+    match result {
+        serde_json::Value::Array(items) => match items.first() {
+            Some(serde_json::Value::Object(obj)) => from_object(obj),
+            _ => u256_from_words(&items.iter().map(word).collect::<Result<Vec<_>>>()?),
+        },
+        serde_json::Value::Object(obj) => from_object(obj),
+        _ => Err(anyhow::anyhow!("u256 response missing fields")),
     }
+}
 
-    // Fall back to generic felt parsing for any other form.
-    parse_felt_any(v)
+/// Decodes an ERC-20 `name`/`symbol` result. Cairo 0 and older Cairo 1 tokens return a short string
+/// (one felt); OpenZeppelin Cairo 1 tokens return a `ByteArray`
+/// (`[full_word_count, words.., pending_word, pending_word_len]`, 31 bytes per full word).
+pub fn decode_cairo_string(result: &[Felt]) -> Option<String> {
+    let small = |f: &Felt| BigUint::from_bytes_be(&f.to_bytes_be()).to_usize();
+    let bytes = match result {
+        [short] => {
+            let raw = short.to_bytes_be();
+            let start = raw.iter().position(|b| *b != 0).unwrap_or(raw.len());
+            raw[start..].to_vec()
+        }
+        [count, rest @ ..] => {
+            let count = small(count)?;
+            if rest.len() != count.checked_add(2)? {
+                return None;
+            }
+            let pending_len = small(&rest[count + 1]).filter(|len| *len < 31)?;
+            let mut bytes = Vec::with_capacity(count * 31 + pending_len);
+            for word in &rest[..count] {
+                bytes.extend_from_slice(&word.to_bytes_be()[1..]);
+            }
+            bytes.extend_from_slice(&rest[count].to_bytes_be()[32 - pending_len..]);
+            bytes
+        }
+        [] => return None,
+    };
+    String::from_utf8(bytes).ok()
 }
 
 pub fn token_decimals_for(token_address: &str) -> u32 {
     token_decimals(token_address)
 }
 
-pub fn parse_amount_to_base_units(value: &str, decimals: u32) -> Result<BigUint> {
-    let v = value.trim();
-    if v.is_empty() {
-        return Ok(BigUint::from(0u8));
+/// Pragma symbol for the common Sepolia token addresses (see `token_decimals`).
+pub fn token_symbol_for(token_address: &str) -> Option<&'static str> {
+    let a = normalize_hex_address(token_address);
+    match a.as_str() {
+        "0x049d36570d4e46f48e99674bd3fcc84644ddd6b96f7c741b1562b82f9e004dc7" => Some("ETH"),
+        "0x04718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d" => Some("STRK"),
+        "0x053c91253bc9682c04929ca02ed00b3e423f6710d2ee7e0d5ebb06f3ecf368a8" => Some("USDC"),
+        "0x068f5c6a61780768455de69077e07e89787839bf8166decfbf92b645209c0fb8" => Some("USDT"),
+        _ => None,
+    }
+}
+
+/// The circuit's `currentTime` public signal: index 2 of `[intentHash, nullifier, currentTime, ...]`.
+/// `None` when missing or not a u64 timestamp.
+pub fn proof_current_time(proof_public_inputs: &[String]) -> Option<u64> {
+    let v = proof_public_inputs.get(2)?.trim();
+    let n = match v.strip_prefix("0x").or_else(|| v.strip_prefix("0X")) {
+        Some(hex) => BigUint::from_str_radix(hex, 16).ok()?,
+        None => BigUint::from_str_radix(v, 10).ok()?,
+    };
+    n.to_u64()
+}
+
+/// `DarkPool.submit_intent` calldata for an `IntentProof`:
+/// `[intent_hash, nullifier, proof_data_len, ...proof_data, public_inputs_len, ...public_inputs]`.
+/// Values must already be in the field; errors name the offending element.
+pub fn submit_intent_calldata(
+    intent_hash: &str,
+    nullifier: &str,
+    proof_data: &[String],
+    proof_public_inputs: &[String],
+) -> std::result::Result<Vec<Felt>, String> {
+    fn parse_named_felt(name: &str, input: &str) -> std::result::Result<Felt, String> {
+        let v = input.trim();
+        let parsed = if v.is_empty() {
+            Err("empty felt".to_string())
+        } else if v.starts_with("0x") || v.starts_with("0X") {
+            Felt::from_hex(v).map_err(|e| e.to_string())
+        } else {
+            Felt::from_dec_str(v).map_err(|e| e.to_string())
+        };
+        parsed.map_err(|e| {
+            let preview = if v.len() > 96 {
+                format!("{}...", &v[..96])
+            } else {
+                v.to_string()
+            };
+            format!("{} parse error: {} (value={})", name, e, preview)
+        })
     }
-    if v.starts_with("0x") || v.starts_with("0X") {
-        return BigUint::from_str_radix(v.trim_start_matches("0x").trim_start_matches("0X"), 16)
-            .map_err(|e| anyhow::anyhow!(e));
+
+    let mut calldata: Vec<Felt> = Vec::new();
+    calldata.push(parse_named_felt("intent_hash", intent_hash)?);
+    calldata.push(parse_named_felt("nullifier", nullifier)?);
+    calldata.push(Felt::from(proof_data.len() as u64));
+    for (idx, p) in proof_data.iter().enumerate() {
+        calldata.push(parse_named_felt(&format!("proof_data[{}]", idx), p)?);
     }
-    if let Some((int_part, frac_part)) = v.split_once('.') {
-        if int_part.is_empty() || int_part.chars().any(|c| !c.is_ascii_digit()) {
-            return Err(anyhow::anyhow!("invalid decimal amount: {}", v));
-        }
-        if frac_part.chars().any(|c| !c.is_ascii_digit()) {
-            return Err(anyhow::anyhow!("invalid decimal amount: {}", v));
+    calldata.push(Felt::from(proof_public_inputs.len() as u64));
+    for (idx, p) in proof_public_inputs.iter().enumerate() {
+        calldata.push(parse_named_felt(&format!("proof_public_inputs[{}]", idx), p)?);
+    }
+    Ok(calldata)
+}
+
+/// Re-checks stored intents' proofs right before matching by simulating `DarkPool.submit_intent`
+/// for all of them in one batched JSON-RPC request. This catches proofs that stopped verifying
+/// after submission, e.g. because the nullifier was spent or cancelled on-chain.
+#[derive(Clone)]
+pub struct IntentProofVerifier {
+    rpc: RpcClient,
+    dark_pool_address: Felt,
+}
+
+impl IntentProofVerifier {
+    pub fn new(rpc: RpcClient, dark_pool_address: Felt) -> Self {
+        Self {
+            rpc,
+            dark_pool_address,
         }
-        if frac_part.len() > decimals as usize {
-            return Err(anyhow::anyhow!("too many decimals (max {}): {}", decimals, v));
+    }
+
+    /// One entry per intent: `None` if its proof still verifies, otherwise the revert reason.
+    /// `Err` when the node gave no conclusive answer for some intent (transport errors, rate
+    /// limits); callers should retry later rather than treat the proof as invalid.
+    pub async fn verify(&self, intents: &[&Intent]) -> Result<Vec<Option<String>>> {
+        let selector = get_selector_from_name("submit_intent")?;
+        let mut calls = Vec::with_capacity(intents.len());
+        let mut results: Vec<Option<String>> = vec![None; intents.len()];
+        let mut call_slots = Vec::with_capacity(intents.len());
+        for (idx, intent) in intents.iter().enumerate() {
+            match submit_intent_calldata(
+                &intent.intent_hash,
+                &intent.nullifier,
+                &intent.proof_data,
+                &intent.proof_public_inputs,
+            ) {
+                Ok(calldata) => {
+                    calls.push((self.dark_pool_address, selector, calldata));
+                    call_slots.push(idx);
+                }
+                // Could never have verified on-chain either.
+                Err(reason) => results[idx] = Some(reason),
+            }
         }
 
-        let ten_pow: BigUint = BigUint::from(10u8).pow(decimals);
-        let int_n = BigUint::from_str_radix(int_part, 10)?;
-        let mut frac = frac_part.to_string();
-        while frac.len() < decimals as usize {
-            frac.push('0');
+        if !calls.is_empty() {
+            let envelopes = self.rpc.starknet_call_batch(calls, None).await?;
+            for (idx, envelope) in call_slots.into_iter().zip(envelopes) {
+                let Some(err) = envelope.get("error") else {
+                    continue;
+                };
+                // Revert reasons usually sit in `data`, not `message`.
+                let reason = err.to_string();
+                match classify_revert_reason(&reason) {
+                    "INVALID_PROOFS" | "INTENT_NOT_PENDING" => results[idx] = Some(reason),
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "proof check for {} inconclusive: {}",
+                            intents[idx].nullifier,
+                            reason
+                        ))
+                    }
+                }
+            }
         }
-        let frac_n = if frac.is_empty() {
-            BigUint::from(0u8)
-        } else {
-            BigUint::from_str_radix(&frac, 10)?
-        };
-        return Ok((int_n * ten_pow) + frac_n);
-    }
 
-    if v.chars().all(|c| c.is_ascii_digit()) {
-        let ten_pow: BigUint = BigUint::from(10u8).pow(decimals);
-        let int_n = BigUint::from_str_radix(v, 10)?;
-        return Ok(int_n * ten_pow);
+        Ok(results)
     }
+}
 
-    Err(anyhow::anyhow!("invalid amount: {}", v))
+/// Checks each bound `proof_public_inputs` position against the request field it stands for.
+/// Values are compared as felts (decimal or hex, reduced into the field like the frontend does).
+/// The error names the first offending position.
+pub fn check_proof_input_bindings(
+    bindings: &[ProofInputBinding],
+    proof_public_inputs: &[String],
+    intent_hash: &str,
+    nullifier: &str,
+) -> std::result::Result<(), String> {
+    for binding in bindings {
+        let name = binding.field.name();
+        let expected = match binding.field {
+            ProofInputField::IntentHash => intent_hash,
+            ProofInputField::Nullifier => nullifier,
+        };
+        let Some(actual) = proof_public_inputs.get(binding.position) else {
+            return Err(format!(
+                "proof_public_inputs[{}] is missing (must equal {})",
+                binding.position, name
+            ));
+        };
+        let parse = |v: &str| match v.trim() {
+            "" => None,
+            v => parse_felt_any(v).ok(),
+        };
+        let Some(expected) = parse(expected) else {
+            return Err(format!("{} is not a valid felt", name));
+        };
+        if parse(actual) != Some(expected) {
+            return Err(format!(
+                "proof_public_inputs[{}] does not match {}",
+                binding.position, name
+            ));
+        }
+    }
+    Ok(())
 }
 
 fn public_inputs_to_felts(inputs: &crate::models::PublicInputs) -> Result<Vec<Felt>> {
@@ -508,8 +2151,8 @@ fn public_inputs_to_felts(inputs: &crate::models::PublicInputs) -> Result<Vec<Fe
         parse_felt_any(&inputs.user)?,
         parse_felt_any(&inputs.token_in)?,
         parse_felt_any(&inputs.token_out)?,
-        parse_amount_to_felt(&inputs.amount_in, in_decimals)?,
-        parse_amount_to_felt(&inputs.min_amount_out, out_decimals)?,
+        TokenAmount::parse(&inputs.amount_in, in_decimals)?.to_felt()?,
+        TokenAmount::parse(&inputs.min_amount_out, out_decimals)?.to_felt()?,
         Felt::from(inputs.deadline),
     ])
 }
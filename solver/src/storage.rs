@@ -1,31 +1,330 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
 use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use redis::AsyncCommands;
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::{info, debug};
+use tokio::sync::{broadcast, RwLock};
+use tracing::{info, debug, warn};
 
-use crate::models::{Intent, IntentStatus, MatchedPair};
+use crate::events::EventRecord;
+use crate::models::{
+    BundleLine, ImportSummary, Intent, IntentStatus, IntentStatusChange, MatchHistoryEntry, MatchedPair,
+    SettlementFeedEvent, SettlementFeedMatch, SettlementRecord, SettlementTrigger,
+};
+use crate::utils::{log_address, log_nullifier};
+
+/// Deletes KEYS[1] only if it still holds ARGV[1] (owner-checked delete).
+const COMPARE_AND_DELETE_LUA: &str =
+    r#"if redis.call("GET", KEYS[1]) == ARGV[1] then return redis.call("DEL", KEYS[1]) else return 0 end"#;
+
+/// Overwrites KEYS[1] with ARGV[2], keeping its TTL, only if it still holds ARGV[1].
+const COMPARE_AND_SET_LUA: &str = r#"
+if redis.call("GET", KEYS[1]) ~= ARGV[1] then return 0 end
+redis.call("SET", KEYS[1], ARGV[2], "KEEPTTL")
+return 1
+"#;
+
+/// Rewrites the intent record KEYS[1] with ARGV[2], expiring at ARGV[3] (unix seconds) along with
+/// its near-miss record KEYS[2] and nonce index KEYS[3], only if KEYS[1] still holds ARGV[1].
+const EXTEND_INTENT_LUA: &str = r#"
+if redis.call("GET", KEYS[1]) ~= ARGV[1] then return 0 end
+redis.call("SET", KEYS[1], ARGV[2], "EXAT", ARGV[3])
+redis.call("EXPIREAT", KEYS[2], ARGV[3])
+redis.call("EXPIREAT", KEYS[3], ARGV[3])
+return 1
+"#;
+
+/// Puts the intent record KEYS[1] back in the pending set KEYS[2] as ARGV[2] (nullifier ARGV[3]),
+/// keeping its TTL, only if it still holds ARGV[1].
+const REQUEUE_INTENT_LUA: &str = r#"
+if redis.call("GET", KEYS[1]) ~= ARGV[1] then return 0 end
+redis.call("SET", KEYS[1], ARGV[2], "KEEPTTL")
+redis.call("SADD", KEYS[2], ARGV[3])
+return 1
+"#;
+
+/// Marks the intent record KEYS[1] expired as ARGV[2], keeping its TTL, and drops nullifier ARGV[3]
+/// from the pending set KEYS[2] and pair index KEYS[3], only if KEYS[1] still holds the pending
+/// payload ARGV[1] the sweep read. Returns 0, changing nothing, if the intent moved on meanwhile.
+const EXPIRE_INTENT_LUA: &str = r#"
+if redis.call("GET", KEYS[1]) ~= ARGV[1] then return 0 end
+redis.call("SET", KEYS[1], ARGV[2], "KEEPTTL")
+redis.call("SREM", KEYS[2], ARGV[3])
+redis.call("SREM", KEYS[3], ARGV[3])
+return 1
+"#;
+
+/// Cancels KEYS[1] and stores the replacement like `store_intent` does, but only while KEYS[1]
+/// still holds the pending payload the caller read (ARGV[6]); payloads may be encrypted, so the
+/// status is checked in Rust. KEYS: old intent, new intent, pending set, user index, pair index,
+/// nonce index. ARGV: cancelled old payload, old nullifier, new payload, new TTL, new nullifier,
+/// expected old payload.
+const REPLACE_PENDING_INTENT_LUA: &str = r#"
+if redis.call("GET", KEYS[1]) ~= ARGV[6] then return 0 end
+redis.call("SET", KEYS[1], ARGV[1], "KEEPTTL")
+redis.call("SREM", KEYS[3], ARGV[2])
+redis.call("SETEX", KEYS[2], ARGV[4], ARGV[3])
+redis.call("SADD", KEYS[3], ARGV[5])
+redis.call("SADD", KEYS[4], ARGV[5])
+redis.call("SADD", KEYS[5], ARGV[5])
+redis.call("SETEX", KEYS[6], ARGV[4], ARGV[5])
+return 1
+"#;
+
+/// Commits a new match: both intent records KEYS[1..2] must still hold the pending payloads ARGV[1..2]
+/// read before building it. Then they become ARGV[3..4] (matched), the pair ARGV[5] is stored at
+/// KEYS[3] and indexed (KEYS[4] set of matches, KEYS[6..7] by nullifier), and both nullifiers
+/// ARGV[7..8] leave the pending set KEYS[5]. Returns 0, changing nothing, if either intent moved on.
+const COMMIT_MATCH_LUA: &str = r#"
+if redis.call("GET", KEYS[1]) ~= ARGV[1] or redis.call("GET", KEYS[2]) ~= ARGV[2] then return 0 end
+redis.call("SET", KEYS[1], ARGV[3])
+redis.call("SET", KEYS[2], ARGV[4])
+redis.call("SET", KEYS[3], ARGV[5])
+redis.call("SADD", KEYS[4], ARGV[6])
+redis.call("SREM", KEYS[5], ARGV[7], ARGV[8])
+redis.call("SET", KEYS[6], ARGV[6])
+redis.call("SET", KEYS[7], ARGV[6])
+return 1
+"#;
+
+/// Claims the per-user submission slot KEYS[1] for ARGV[1] ms. Returns 0 when claimed, otherwise
+/// the slot's remaining TTL in ms.
+const CLAIM_SUBMIT_SLOT_LUA: &str = r#"
+if redis.call("SET", KEYS[1], "1", "NX", "PX", ARGV[1]) then return 0 end
+return math.max(redis.call("PTTL", KEYS[1]), 1)
+"#;
+
+/// Sliding-window limiter: KEYS[1] is a sorted set of request times. Drops entries older than
+/// ARGV[2] ms before ARGV[1] (now, ms) and records ARGV[4] at ARGV[1] if fewer than ARGV[3] remain.
+/// Returns {allowed (1/0), requests in the window, time of the oldest one}.
+const RATE_LIMIT_LUA: &str = r#"
+redis.call("ZREMRANGEBYSCORE", KEYS[1], "-inf", tonumber(ARGV[1]) - tonumber(ARGV[2]))
+local count = redis.call("ZCARD", KEYS[1])
+local allowed = 0
+if count < tonumber(ARGV[3]) then
+  redis.call("ZADD", KEYS[1], ARGV[1], ARGV[4])
+  count = count + 1
+  allowed = 1
+end
+redis.call("PEXPIRE", KEYS[1], ARGV[2])
+local oldest = redis.call("ZRANGE", KEYS[1], 0, 0, "WITHSCORES")
+return {allowed, count, tonumber(oldest[2] or ARGV[1])}
+"#;
 
 pub struct RedisStorage {
     connection: Arc<RwLock<redis::aio::ConnectionManager>>,
+    /// Set when `STORAGE_ENCRYPTION_KEY` is configured.
+    cipher: Option<StorageCipher>,
+    /// `COMPRESS_INTENTS`: zstd-compress intent and match payloads.
+    compress: bool,
+    /// How long intent records outlive `expires_at`, so the expiry sweep can mark them `Expired`.
+    expired_retention_seconds: u64,
+    /// Connected to a read replica: reads never repair indexes, since the replica refuses writes.
+    read_only: bool,
+    /// Every status this instance writes; see `subscribe_status_changes`.
+    status_changes: broadcast::Sender<IntentStatusChange>,
+}
+
+/// Status changes buffered per subscriber; one that falls further behind skips the oldest.
+const STATUS_CHANNEL_CAPACITY: usize = 1024;
+
+/// Marks a payload sealed by `StorageCipher`; anything else is read as plaintext JSON.
+const SEALED_PREFIX: &str = "enc:v1:";
+/// Marks an unencrypted zstd-compressed payload (`COMPRESS_INTENTS`), base64 after the prefix.
+const COMPRESSED_PREFIX: &str = "zst:v1:";
+/// zstd frame magic. JSON never starts with it, so a sealed payload's plaintext is compressed
+/// exactly when it does.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const ZSTD_LEVEL: i32 = 3;
+
+/// AES-256-GCM for intent and match payloads at rest. Each payload gets a random nonce and is
+/// bound to its Redis key as associated data, so a sealed value cannot be moved to another key.
+#[derive(Clone)]
+pub struct StorageCipher {
+    cipher: Aes256Gcm,
+}
+
+impl StorageCipher {
+    /// `key` is 32 bytes as hex (64 digits, optional `0x`).
+    pub fn from_hex(key: &str) -> Result<Self> {
+        let bytes = hex::decode(key.trim().trim_start_matches("0x"))
+            .map_err(|_| anyhow::anyhow!("STORAGE_ENCRYPTION_KEY must be hex"))?;
+        let cipher = Aes256Gcm::new_from_slice(&bytes)
+            .map_err(|_| anyhow::anyhow!("STORAGE_ENCRYPTION_KEY must be 32 bytes (64 hex digits)"))?;
+        Ok(Self { cipher })
+    }
+
+    fn seal(&self, key: &str, plaintext: &[u8]) -> Result<String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, Payload { msg: plaintext, aad: key.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("Failed to encrypt {}", key))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(format!("{}{}", SEALED_PREFIX, BASE64.encode(sealed)))
+    }
+
+    fn open(&self, key: &str, sealed: &str) -> Result<Vec<u8>> {
+        let bytes = BASE64.decode(sealed)?;
+        if bytes.len() < 12 {
+            return Err(anyhow::anyhow!("Encrypted payload for {} is truncated", key));
+        }
+        let (nonce, ciphertext) = bytes.split_at(12);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: key.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("Failed to decrypt {} (wrong STORAGE_ENCRYPTION_KEY?)", key))
+    }
+}
+
+/// Serializes a payload stored under `key`: zstd-compressed when `compress` is set and that comes
+/// out smaller, then sealed when a cipher is configured.
+fn encode_payload<T: Serialize>(
+    cipher: Option<&StorageCipher>,
+    compress: bool,
+    key: &str,
+    value: &T,
+) -> Result<String> {
+    let json = serde_json::to_vec(value)?;
+    let compressed = if compress {
+        Some(zstd::encode_all(json.as_slice(), ZSTD_LEVEL)?).filter(|c| c.len() < json.len())
+    } else {
+        None
+    };
+    match (cipher, compressed) {
+        (Some(cipher), compressed) => cipher.seal(key, compressed.as_deref().unwrap_or(&json)),
+        (None, Some(compressed)) => Ok(format!("{}{}", COMPRESSED_PREFIX, BASE64.encode(compressed))),
+        (None, None) => Ok(String::from_utf8(json)?),
+    }
+}
+
+/// Reads a payload stored under `key`. Plaintext written before encryption or compression was
+/// enabled is still accepted, and compressed payloads stay readable with compression off; sealed
+/// payloads need the cipher.
+fn decode_payload<T: DeserializeOwned>(cipher: Option<&StorageCipher>, key: &str, raw: &str) -> Result<T> {
+    let bytes = if let Some(sealed) = raw.strip_prefix(SEALED_PREFIX) {
+        Cow::Owned(
+            cipher
+                .ok_or_else(|| anyhow::anyhow!("{} is encrypted but STORAGE_ENCRYPTION_KEY is not set", key))?
+                .open(key, sealed)?,
+        )
+    } else if let Some(compressed) = raw.strip_prefix(COMPRESSED_PREFIX) {
+        Cow::Owned(BASE64.decode(compressed)?)
+    } else {
+        Cow::Borrowed(raw.as_bytes())
+    };
+    if bytes.starts_with(&ZSTD_MAGIC) {
+        let json = zstd::decode_all(bytes.as_ref())
+            .map_err(|e| anyhow::anyhow!("Failed to decompress {}: {}", key, e))?;
+        return Ok(serde_json::from_slice(&json)?);
+    }
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// `EventRecord`s, encoded like intent payloads, scored by event time in milliseconds.
+const EVENT_LOG_KEY: &str = "events:log";
+
+/// Matches the retry loop gave up on. Their `matched:<id>` payload and retry state are kept for triage.
+const DEADLETTER_KEY: &str = "matches:deadletter";
+/// Matches confirmed through the API, i.e. eligible for retries under `manual_with_retry`.
+const CONFIRMED_MATCHES_KEY: &str = "matches:confirmed";
+/// Matches with a settlement transaction whose outcome isn't known yet; markers live at
+/// `match:settling:<id>` and never expire.
+const SETTLING_MATCHES_KEY: &str = "matches:settling";
+/// Hash of `token_a/token_b` -> reason for pairs the matcher won't match.
+const PAIR_ISSUES_KEY: &str = "pairs:issues";
+/// Match ids scored by settlement-outcome time in milliseconds; records live at `settlement:<match_id>`.
+const SETTLEMENT_LOG_KEY: &str = "settlements:log";
+/// `MatchHistoryEntry` JSON tagged with the pair's nullifiers, newest first, trimmed to `MATCH_HISTORY_MAX`.
+const MATCH_HISTORY_KEY: &str = "matches:history";
+
+/// A trade-log entry or settlement record as stored, tagged with the pair's nullifiers so
+/// `purge_intent` can find everything recorded about an intent, and with its users so the record's
+/// owners are known after the pair itself is gone.
+#[derive(Serialize, Deserialize)]
+struct Tagged<T> {
+    #[serde(flatten)]
+    record: T,
+    #[serde(default)]
+    nullifiers: Vec<String>,
+    #[serde(default)]
+    users: Vec<String>,
+}
+
+impl<T> Tagged<T> {
+    fn new(record: T, pair: &MatchedPair) -> Self {
+        Self {
+            record,
+            nullifiers: vec![pair.intent_a.nullifier.clone(), pair.intent_b.nullifier.clone()],
+            users: vec![pair.intent_a.public_inputs.user.clone(), pair.intent_b.public_inputs.user.clone()],
+        }
+    }
+
+    fn names(&self, nullifier: &str) -> bool {
+        self.nullifiers.iter().any(|n| n.eq_ignore_ascii_case(nullifier))
+    }
+}
+
+/// Pub/sub channel for `SettlementFeedEvent` JSON, for off-chain indexers.
+const SETTLEMENT_EVENTS_CHANNEL: &str = "events:settlement";
+/// Per-UTC-day gas spend and override keys outlive their day by a day.
+const GAS_DAY_KEY_TTL_SECONDS: u64 = 2 * 24 * 60 * 60;
+
+/// Outcome of one `hit_rate_limit` call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    /// Requests the client can still make in the current window.
+    pub remaining: u32,
+    /// Unix millis at which the oldest counted request leaves the window.
+    pub reset_at_ms: u64,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Written before a settlement is sent and kept until its outcome is known, so a match whose
+/// transaction may be on its way isn't sent again, even after a restart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettlingMarker {
+    pub started_at_unix: u64,
+    /// Set once the node has accepted the transaction. A marker without one means the process
+    /// stopped between sending and hearing back.
+    pub tx_hash: Option<String>,
+    /// What started the send, for the outcome recorded once the receipt is in.
+    pub trigger: SettlementTrigger,
+}
+
+#[derive(Debug, Clone)]
 pub struct MatchRetryState {
     pub failures: u64,
     pub next_retry_at_unix: u64,
     pub terminal: bool,
+    pub terminal_reason: Option<String>,
+    pub last_error: Option<String>,
+    /// Why the retry loop is holding the match back without attempting it (e.g. gas above ceiling).
+    pub deferred_reason: Option<String>,
 }
 
 impl RedisStorage {
-    fn user_index_key(user: &str) -> String {
+    fn canonical_user(user: &str) -> String {
         // Canonicalize by felt value when possible (removes padding/casing differences).
         // Fall back to lowercase string to avoid losing the intent.
         if let Ok(felt) = starknet::core::types::Felt::from_hex(user.trim()) {
-            return format!("intents:user:0x{:x}", felt);
+            return format!("0x{:x}", felt);
         }
-        format!("intents:user:{}", user.trim().to_lowercase())
+        user.trim().to_lowercase()
+    }
+
+    fn user_index_key(user: &str) -> String {
+        format!("intents:user:{}", Self::canonical_user(user))
+    }
+
+    /// `(user, nonce)` -> nullifier of the intent that carries it.
+    fn nonce_index_key(user: &str, nonce: u64) -> String {
+        format!("intents:nonce:{}:{}", Self::canonical_user(user), nonce)
     }
 
     pub async fn new(redis_url: &str) -> Result<Self> {
@@ -36,9 +335,61 @@ impl RedisStorage {
         
         Ok(Self {
             connection: Arc::new(RwLock::new(connection)),
+            cipher: None,
+            compress: false,
+            expired_retention_seconds: 0,
+            read_only: false,
+            status_changes: broadcast::channel(STATUS_CHANNEL_CAPACITY).0,
         })
     }
 
+    /// Encrypts intent and match payloads from now on (index keys stay plaintext).
+    pub fn with_encryption(mut self, cipher: StorageCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Keeps intent records `seconds` past their `expires_at` (see `expire_pending_intents`).
+    pub fn with_expired_retention(mut self, seconds: u64) -> Self {
+        self.expired_retention_seconds = seconds;
+        self
+    }
+
+    /// For a replica connection; only the read methods may be used on it.
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Status changes written through this instance from now on (not other solver processes').
+    pub fn subscribe_status_changes(&self) -> broadcast::Receiver<IntentStatusChange> {
+        self.status_changes.subscribe()
+    }
+
+    fn publish_status(&self, intent: &Intent) {
+        // Errors only mean nobody is subscribed.
+        let _ = self.status_changes.send(IntentStatusChange::of(intent));
+    }
+
+    /// TTL for a freshly stored intent record and its nonce index entry.
+    fn intent_ttl(&self, intent: &Intent) -> u64 {
+        ((intent.expires_at - intent.created_at).num_seconds().max(1) as u64)
+            .saturating_add(self.expired_retention_seconds)
+    }
+
+    fn encode<T: Serialize>(&self, key: &str, value: &T) -> Result<String> {
+        encode_payload(self.cipher.as_ref(), self.compress, key, value)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, key: &str, raw: &str) -> Result<T> {
+        decode_payload(self.cipher.as_ref(), key, raw)
+    }
+
     fn match_retry_key(match_id: &str) -> String {
         format!("match:retry:{}", match_id)
     }
@@ -47,36 +398,33 @@ impl RedisStorage {
     pub async fn get_match_retry_state(&self, match_id: &str) -> Result<Option<MatchRetryState>> {
         let key = Self::match_retry_key(match_id);
         let mut conn = self.connection.write().await;
-        let failures: Option<u64> = redis::cmd("HGET")
-            .arg(&key)
-            .arg("failures")
-            .query_async(&mut *conn)
-            .await?;
-        let next_retry_at_unix: Option<u64> = redis::cmd("HGET")
+        let fields: HashMap<String, String> = redis::cmd("HGETALL")
             .arg(&key)
-            .arg("next_retry_at_unix")
             .query_async(&mut *conn)
             .await?;
 
-        if failures.is_none() && next_retry_at_unix.is_none() {
+        if fields.is_empty() {
             return Ok(None);
         }
 
-        let terminal: Option<u8> = redis::cmd("HGET")
-            .arg(&key)
-            .arg("terminal")
-            .query_async(&mut *conn)
-            .await?;
-
+        let number = |name: &str| fields.get(name).and_then(|v| v.parse::<u64>().ok());
         Ok(Some(MatchRetryState {
-            failures: failures.unwrap_or(0),
-            next_retry_at_unix: next_retry_at_unix.unwrap_or(0),
-            terminal: terminal.unwrap_or(0) == 1,
+            failures: number("failures").unwrap_or(0),
+            next_retry_at_unix: number("next_retry_at_unix").unwrap_or(0),
+            terminal: number("terminal").unwrap_or(0) == 1,
+            terminal_reason: fields.get("terminal_reason").cloned(),
+            last_error: fields.get("last_error").cloned(),
+            deferred_reason: fields.get("deferred_reason").cloned(),
         }))
     }
 
     /// Increments the failure counter and sets the next retry timestamp. Returns updated state.
-    pub async fn bump_match_retry_state(&self, match_id: &str, next_retry_at_unix: u64) -> Result<MatchRetryState> {
+    pub async fn bump_match_retry_state(
+        &self,
+        match_id: &str,
+        next_retry_at_unix: u64,
+        last_error: &str,
+    ) -> Result<MatchRetryState> {
         let key = Self::match_retry_key(match_id);
         let mut conn = self.connection.write().await;
 
@@ -91,12 +439,15 @@ impl RedisStorage {
             .arg(&key)
             .arg("next_retry_at_unix")
             .arg(next_retry_at_unix)
+            .arg("last_error")
+            .arg(last_error)
             .query_async::<_, ()>(&mut *conn)
             .await?;
         redis::cmd("HDEL")
             .arg(&key)
             .arg("terminal")
             .arg("terminal_reason")
+            .arg("deferred_reason")
             .query_async::<_, ()>(&mut *conn)
             .await?;
 
@@ -111,9 +462,59 @@ impl RedisStorage {
             failures: failures.max(0) as u64,
             next_retry_at_unix,
             terminal: false,
+            terminal_reason: None,
+            last_error: Some(last_error.to_string()),
+            deferred_reason: None,
         })
     }
 
+    /// Records the latest settlement error without counting it towards backoff.
+    pub async fn record_match_error(&self, match_id: &str, last_error: &str) -> Result<()> {
+        let key = Self::match_retry_key(match_id);
+        let mut conn = self.connection.write().await;
+        redis::cmd("HSET")
+            .arg(&key)
+            .arg("last_error")
+            .arg(last_error)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        let _ = redis::cmd("EXPIRE")
+            .arg(&key)
+            .arg(7 * 24 * 60 * 60) // 7 days
+            .query_async::<_, ()>(&mut *conn)
+            .await;
+        Ok(())
+    }
+
+    /// Records (or with `None` clears) why the retry loop deferred a match this cycle.
+    pub async fn set_match_deferral(&self, match_id: &str, reason: Option<&str>) -> Result<()> {
+        let key = Self::match_retry_key(match_id);
+        let mut conn = self.connection.write().await;
+        match reason {
+            Some(reason) => {
+                redis::cmd("HSET")
+                    .arg(&key)
+                    .arg("deferred_reason")
+                    .arg(reason)
+                    .query_async::<_, ()>(&mut *conn)
+                    .await?;
+                let _ = redis::cmd("EXPIRE")
+                    .arg(&key)
+                    .arg(7 * 24 * 60 * 60) // 7 days
+                    .query_async::<_, ()>(&mut *conn)
+                    .await;
+            }
+            None => {
+                redis::cmd("HDEL")
+                    .arg(&key)
+                    .arg("deferred_reason")
+                    .query_async::<_, ()>(&mut *conn)
+                    .await?
+            }
+        }
+        Ok(())
+    }
+
     /// Marks retry state as terminal (do not retry automatically anymore).
     pub async fn mark_match_retry_terminal(&self, match_id: &str, reason: &str) -> Result<MatchRetryState> {
         let key = Self::match_retry_key(match_id);
@@ -125,6 +526,11 @@ impl RedisStorage {
             .query_async(&mut *conn)
             .await?;
         let failures = failures.unwrap_or(0);
+        let last_error: Option<String> = redis::cmd("HGET")
+            .arg(&key)
+            .arg("last_error")
+            .query_async(&mut *conn)
+            .await?;
 
         redis::cmd("HSET")
             .arg(&key)
@@ -147,6 +553,9 @@ impl RedisStorage {
             failures,
             next_retry_at_unix: 0,
             terminal: true,
+            terminal_reason: Some(reason.to_string()),
+            last_error,
+            deferred_reason: None,
         })
     }
 
@@ -161,291 +570,1954 @@ impl RedisStorage {
         Ok(())
     }
 
-    /// Store a new intent
-    pub async fn store_intent(&self, intent: &Intent) -> Result<()> {
-        let key = format!("intent:{}", intent.nullifier);
-        let value = serde_json::to_string(intent)?;
-        
+    fn match_settling_key(match_id: &str) -> String {
+        format!("match:settling:{}", match_id)
+    }
+
+    /// Records that a settlement of `match_id` is being sent (or was sent, once `tx_hash` is set).
+    pub async fn mark_match_settling(&self, match_id: &str, marker: &SettlingMarker) -> Result<()> {
+        let key = Self::match_settling_key(match_id);
         let mut conn = self.connection.write().await;
-        
-        // Store intent with expiration
-        let ttl = (intent.expires_at - intent.created_at).num_seconds().max(1) as u64;
-        redis::cmd("SETEX")
+        redis::cmd("HSET")
             .arg(&key)
-            .arg(ttl)
-            .arg(&value)
+            .arg("started_at_unix")
+            .arg(marker.started_at_unix)
+            .arg("tx_hash")
+            .arg(marker.tx_hash.as_deref().unwrap_or(""))
+            .arg("trigger")
+            .arg(serde_json::to_string(&marker.trigger)?)
             .query_async::<_, ()>(&mut *conn)
             .await?;
-        
-        // Add to pending set
         redis::cmd("SADD")
-            .arg("intents:pending")
-            .arg(&intent.nullifier)
+            .arg(SETTLING_MATCHES_KEY)
+            .arg(match_id)
             .query_async::<_, ()>(&mut *conn)
             .await?;
+        Ok(())
+    }
 
-        // Index by user for status queries across devices/browsers.
-        let user_key = Self::user_index_key(&intent.public_inputs.user);
-        redis::cmd("SADD")
-            .arg(&user_key)
-            .arg(&intent.nullifier)
+    pub async fn get_settling_marker(&self, match_id: &str) -> Result<Option<SettlingMarker>> {
+        let key = Self::match_settling_key(match_id);
+        let mut conn = self.connection.write().await;
+        let fields: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(&key)
+            .query_async(&mut *conn)
+            .await?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(SettlingMarker {
+            started_at_unix: fields
+                .get("started_at_unix")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0),
+            tx_hash: fields.get("tx_hash").filter(|v| !v.is_empty()).cloned(),
+            // Markers written before the field existed came from the matcher's own sends.
+            trigger: fields
+                .get("trigger")
+                .and_then(|v| serde_json::from_str(v).ok())
+                .unwrap_or(SettlementTrigger::Auto),
+        }))
+    }
+
+    pub async fn clear_settling_marker(&self, match_id: &str) -> Result<()> {
+        let mut conn = self.connection.write().await;
+        redis::cmd("DEL")
+            .arg(Self::match_settling_key(match_id))
             .query_async::<_, ()>(&mut *conn)
             .await?;
-        
-        // Index by token pair
-        let pair_key = format!("intents:pair:{}:{}", intent.public_inputs.token_in, intent.public_inputs.token_out);
-        redis::cmd("SADD")
-            .arg(&pair_key)
-            .arg(&intent.nullifier)
+        redis::cmd("SREM")
+            .arg(SETTLING_MATCHES_KEY)
+            .arg(match_id)
             .query_async::<_, ()>(&mut *conn)
             .await?;
-        
-        debug!("Stored intent {} with TTL {}s", intent.nullifier, ttl);
         Ok(())
     }
 
-    /// Reserve (user, nonce) for anti-replay. Returns false if already used.
-    pub async fn reserve_nonce(
-        &self,
-        user: &str,
-        nonce: u64,
-        expires_at_unix: u64,
-    ) -> Result<bool> {
-        let key = format!("nonce:{}:{}", user, nonce);
-        let now = chrono::Utc::now().timestamp().max(0) as u64;
-        let ttl = expires_at_unix.saturating_sub(now).max(1);
+    /// Matches with a settling marker, for startup reconciliation.
+    pub async fn settling_match_ids(&self) -> Result<Vec<String>> {
+        let mut conn = self.connection.write().await;
+        let ids: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(SETTLING_MATCHES_KEY)
+            .query_async(&mut *conn)
+            .await?;
+        Ok(ids)
+    }
+
+    fn match_by_nullifier_key(nullifier: &str) -> String {
+        format!("match:by-nullifier:{}", nullifier)
+    }
+
+    fn match_lock_key(match_id: &str) -> String {
+        format!("match:lock:{}", match_id)
+    }
+
+    /// Takes the per-match settlement lock (`SET NX EX`). Returns the lock token when acquired,
+    /// or `None` when another settlement path (auto-settle, retry loop, manual confirm) holds it.
+    pub async fn try_acquire_match_lock(&self, match_id: &str, ttl_seconds: u64) -> Result<Option<String>> {
+        let key = Self::match_lock_key(match_id);
+        let token = uuid::Uuid::new_v4().to_string();
         let mut conn = self.connection.write().await;
         let response: Option<String> = redis::cmd("SET")
             .arg(&key)
-            .arg("1")
+            .arg(&token)
             .arg("NX")
             .arg("EX")
-            .arg(ttl)
+            .arg(ttl_seconds.max(1))
             .query_async(&mut *conn)
             .await?;
-        Ok(response.is_some())
+        Ok(response.map(|_| token))
     }
 
-    /// Get an intent by nullifier
-    pub async fn get_intent(&self, nullifier: &str) -> Result<Option<Intent>> {
-        let key = format!("intent:{}", nullifier);
+    /// Releases the settlement lock, but only if `token` still owns it. If our TTL lapsed and
+    /// another path re-acquired the lock, deleting it blindly would let a third path in.
+    pub async fn release_match_lock(&self, match_id: &str, token: &str) -> Result<()> {
+        let key = Self::match_lock_key(match_id);
         let mut conn = self.connection.write().await;
-        
-        let value: Option<String> = redis::cmd("GET")
+        redis::cmd("EVAL")
+            .arg(COMPARE_AND_DELETE_LUA)
+            .arg(1)
             .arg(&key)
-            .query_async(&mut *conn)
+            .arg(token)
+            .query_async::<_, i64>(&mut *conn)
             .await?;
-        
-        match value {
-            Some(json) => {
-                let intent: Intent = serde_json::from_str(&json)?;
-                Ok(Some(intent))
-            }
-            None => Ok(None),
-        }
+        Ok(())
     }
 
-    /// Get all pending intents
-    pub async fn get_pending_intents(&self) -> Result<Vec<Intent>> {
-        // Fetch nullifiers first, then resolve intents without holding the connection lock.
-        // Holding the lock and calling `self.get_intent()` would deadlock (nested lock acquire).
-        let nullifiers: Vec<String> = {
-            let mut conn = self.connection.write().await;
-            redis::cmd("SMEMBERS")
-                .arg("intents:pending")
-                .query_async(&mut *conn)
-                .await?
-        };
-
-        let mut intents = Vec::new();
-        for nullifier in nullifiers {
-            if let Some(intent) = self.get_intent(&nullifier).await? {
-                if intent.can_match() {
-                    intents.push(intent);
-                }
-            }
-        }
-        
-        Ok(intents)
+    /// Remaining TTL of the settlement lock for `match_id`, or `None` if no attempt holds it.
+    pub async fn get_match_lock_ttl(&self, match_id: &str) -> Result<Option<u64>> {
+        let mut conn = self.connection.write().await;
+        // -2 = no key, -1 = no expiry (never set by us, but report it as held).
+        let ttl: i64 = redis::cmd("TTL")
+            .arg(Self::match_lock_key(match_id))
+            .query_async(&mut *conn)
+            .await?;
+        Ok(match ttl {
+            -2 => None,
+            t => Some(t.max(0) as u64),
+        })
     }
 
-    /// Get pending intents for a specific token pair
-    pub async fn get_intents_by_pair(&self, token_in: &str, token_out: &str) -> Result<Vec<Intent>> {
-        let pair_key = format!("intents:pair:{}:{}", token_in, token_out);
-        let nullifiers: Vec<String> = {
-            let mut conn = self.connection.write().await;
-            redis::cmd("SMEMBERS")
-                .arg(&pair_key)
-                .query_async(&mut *conn)
-                .await?
-        };
-
-        let mut intents = Vec::new();
-        for nullifier in nullifiers {
-            if let Some(intent) = self.get_intent(&nullifier).await? {
-                if intent.can_match() {
-                    intents.push(intent);
-                }
-            }
-        }
-        
-        Ok(intents)
+    /// Appends an event to the export log and drops entries scored before `retain_from_ms`. Records
+    /// name users, so they are sealed under `STORAGE_ENCRYPTION_KEY` like intents.
+    pub async fn record_event(&self, timestamp_ms: i64, record: &EventRecord, retain_from_ms: i64) -> Result<()> {
+        let member = self.encode(EVENT_LOG_KEY, record)?;
+        let mut conn = self.connection.write().await;
+        redis::cmd("ZADD")
+            .arg(EVENT_LOG_KEY)
+            .arg(timestamp_ms)
+            .arg(member)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        redis::cmd("ZREMRANGEBYSCORE")
+            .arg(EVENT_LOG_KEY)
+            .arg("-inf")
+            .arg(format!("({}", retain_from_ms))
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(())
     }
 
-    /// Get intents for a specific user (all statuses)
-    pub async fn get_intents_by_user(&self, user: &str) -> Result<Vec<Intent>> {
-        let user_key = Self::user_index_key(user);
-        let nullifiers: Vec<String> = {
-            let mut conn = self.connection.write().await;
-            redis::cmd("SMEMBERS")
-                .arg(&user_key)
-                .query_async(&mut *conn)
-                .await?
-        };
+    /// Publishes on `events:settlement` that `pair` settled in `tx_hash`. Pub/sub keeps nothing, so
+    /// only subscribers listening at the time receive it.
+    pub async fn publish_settlement_event(&self, pair: &MatchedPair, tx_hash: &str) -> Result<()> {
+        self.publish_settlement_feed(&SettlementFeedEvent::Settled {
+            pair: SettlementFeedMatch::of(pair),
+            transaction_hash: tx_hash.to_string(),
+            timestamp: chrono::Utc::now(),
+        })
+        .await
+    }
 
-        let mut intents = Vec::new();
-        for nullifier in nullifiers {
-            if let Some(intent) = self.get_intent(&nullifier).await? {
-                intents.push(intent);
-            }
-        }
+    /// Publishes on `events:settlement` that `pair` was given up on with `reason`.
+    pub async fn publish_failure_event(&self, pair: &MatchedPair, reason: &str) -> Result<()> {
+        self.publish_settlement_feed(&SettlementFeedEvent::Failed {
+            pair: SettlementFeedMatch::of(pair),
+            reason: reason.to_string(),
+            timestamp: chrono::Utc::now(),
+        })
+        .await
+    }
 
-        Ok(intents)
+    async fn publish_settlement_feed(&self, event: &SettlementFeedEvent) -> Result<()> {
+        let mut conn = self.connection.write().await;
+        redis::cmd("PUBLISH")
+            .arg(SETTLEMENT_EVENTS_CHANNEL)
+            .arg(serde_json::to_string(event)?)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(())
     }
 
-    /// Update intent status
-    pub async fn update_intent_status(
+    /// Up to `count` event records scored `from_ms <= t < to_ms`, oldest first, with their scores.
+    /// `skip` drops that many leading records (used to resume after records sharing `from_ms`). A
+    /// record that can't be decoded is `None` but still counts, so paging stays aligned.
+    pub async fn get_events_page(
         &self,
-        nullifier: &str,
-        status: IntentStatus,
-        matched_with: Option<String>,
-        settlement_tx_hash: Option<String>,
-    ) -> Result<()> {
-        let mut intent = match self.get_intent(nullifier).await? {
-            Some(intent) => intent,
-            None => return Err(anyhow::anyhow!("Intent not found: {}", nullifier)),
-        };
-        
-        intent.status = status.clone();
-        intent.matched_with = matched_with;
-        intent.settlement_tx_hash = settlement_tx_hash;
-        
-        let key = format!("intent:{}", nullifier);
-        let value = serde_json::to_string(&intent)?;
-        
+        from_ms: i64,
+        to_ms: i64,
+        skip: usize,
+        count: usize,
+    ) -> Result<Vec<(Option<EventRecord>, i64)>> {
+        let mut conn = self.connection.write().await;
+        let records: Vec<(String, f64)> = redis::cmd("ZRANGEBYSCORE")
+            .arg(EVENT_LOG_KEY)
+            .arg(from_ms)
+            .arg(format!("({}", to_ms))
+            .arg("WITHSCORES")
+            .arg("LIMIT")
+            .arg(skip)
+            .arg(count)
+            .query_async(&mut *conn)
+            .await?;
+        Ok(records
+            .into_iter()
+            .map(|(raw, score)| {
+                let record = self
+                    .decode(EVENT_LOG_KEY, &raw)
+                    .map_err(|e| warn!("Skipping unreadable event log record: {}", e))
+                    .ok();
+                (record, score as i64)
+            })
+            .collect())
+    }
+
+    fn settlement_key(match_id: &str) -> String {
+        format!("settlement:{}", match_id)
+    }
+
+    /// Adds `pair`, settled by `tx_hash`, to the front of the trade log and trims it to `max_len`.
+    pub async fn store_settled_match(&self, pair: &MatchedPair, tx_hash: &str, max_len: usize) -> Result<()> {
+        let entry = Tagged::new(MatchHistoryEntry::new(pair, tx_hash.to_string()), pair);
+        let value = self.encode(MATCH_HISTORY_KEY, &entry)?;
+        let mut conn = self.connection.write().await;
+        redis::cmd("LPUSH")
+            .arg(MATCH_HISTORY_KEY)
+            .arg(value)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        redis::cmd("LTRIM")
+            .arg(MATCH_HISTORY_KEY)
+            .arg(0)
+            .arg(max_len.saturating_sub(1))
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// A page of the trade log, newest first, and its total length.
+    pub async fn get_match_history(&self, offset: usize, limit: usize) -> Result<(Vec<MatchHistoryEntry>, usize)> {
+        let mut conn = self.connection.write().await;
+        let total: usize = redis::cmd("LLEN")
+            .arg(MATCH_HISTORY_KEY)
+            .query_async(&mut *conn)
+            .await?;
+        if limit == 0 || offset >= total {
+            return Ok((Vec::new(), total));
+        }
+        let raw: Vec<String> = redis::cmd("LRANGE")
+            .arg(MATCH_HISTORY_KEY)
+            .arg(offset)
+            .arg(offset + limit - 1)
+            .query_async(&mut *conn)
+            .await?;
+        let entries = raw
+            .iter()
+            .filter_map(|entry| match self.decode::<Tagged<MatchHistoryEntry>>(MATCH_HISTORY_KEY, entry) {
+                Ok(entry) => Some(entry.record),
+                Err(e) => {
+                    warn!("Skipping unreadable trade log entry: {}", e);
+                    None
+                }
+            })
+            .collect();
+        Ok((entries, total))
+    }
+
+    /// Adds a settlement outcome to the history and drops records older than `retain_from_ms`.
+    /// A later outcome for the same match replaces the earlier one.
+    pub async fn record_settlement(&self, pair: &MatchedPair, record: &SettlementRecord, retain_from_ms: i64) -> Result<()> {
+        let key = Self::settlement_key(&record.match_id);
+        let value = self.encode(&key, &Tagged::new(record, pair))?;
         let mut conn = self.connection.write().await;
         redis::cmd("SET")
             .arg(&key)
-            .arg(&value)
+            .arg(value)
             .query_async::<_, ()>(&mut *conn)
             .await?;
-        
-        // Update pending set
-        if status == IntentStatus::Matched || status == IntentStatus::Settled {
-            redis::cmd("SREM")
-                .arg("intents:pending")
-                .arg(nullifier)
+        redis::cmd("ZADD")
+            .arg(SETTLEMENT_LOG_KEY)
+            .arg(record.recorded_at.timestamp_millis())
+            .arg(&record.match_id)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+
+        let expired: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+            .arg(SETTLEMENT_LOG_KEY)
+            .arg("-inf")
+            .arg(format!("({}", retain_from_ms))
+            .query_async(&mut *conn)
+            .await?;
+        if !expired.is_empty() {
+            let keys: Vec<String> = expired.iter().map(|id| Self::settlement_key(id)).collect();
+            redis::cmd("DEL")
+                .arg(keys)
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+            redis::cmd("ZREM")
+                .arg(SETTLEMENT_LOG_KEY)
+                .arg(expired)
                 .query_async::<_, ()>(&mut *conn)
                 .await?;
         }
-        
-        debug!("Updated intent {} status to {:?}", nullifier, status);
         Ok(())
     }
 
-    /// Store a matched pair
-    pub async fn store_matched_pair(&self, pair: &MatchedPair) -> Result<()> {
-        let key = format!("matched:{}", pair.id);
-        let value = serde_json::to_string(pair)?;
-        
+    /// Fills in the receipt fee of a recorded settlement; a no-op once the record has expired.
+    pub async fn set_settlement_fee(&self, match_id: &str, fee: &str) -> Result<()> {
         let mut conn = self.connection.write().await;
+        let key = Self::settlement_key(match_id);
+        let raw: Option<String> = redis::cmd("GET")
+            .arg(&key)
+            .query_async(&mut *conn)
+            .await?;
+        let Some(raw) = raw else {
+            return Ok(());
+        };
+        let mut record: Tagged<SettlementRecord> = self.decode(&key, &raw)?;
+        record.record.fee = Some(fee.to_string());
         redis::cmd("SET")
             .arg(&key)
+            .arg(self.encode(&key, &record)?)
+            .arg("XX")
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// The recorded outcome for `match_id`, while it is within the history's retention, with the
+    /// users of its two intents (empty for records stored before they were kept).
+    pub async fn get_settlement(&self, match_id: &str) -> Result<Option<(SettlementRecord, Vec<String>)>> {
+        let key = Self::settlement_key(match_id);
+        let mut conn = self.connection.write().await;
+        let raw: Option<String> = redis::cmd("GET")
+            .arg(&key)
+            .query_async(&mut *conn)
+            .await?;
+        raw.map(|raw| self.decode::<Tagged<SettlementRecord>>(&key, &raw).map(|t| (t.record, t.users)))
+            .transpose()
+    }
+
+    /// Settlement records with outcomes at `from_ms <= t < to_ms`, oldest first.
+    pub async fn get_settlements(&self, from_ms: i64, to_ms: i64) -> Result<Vec<SettlementRecord>> {
+        let mut conn = self.connection.write().await;
+        let match_ids: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+            .arg(SETTLEMENT_LOG_KEY)
+            .arg(from_ms)
+            .arg(format!("({}", to_ms))
+            .query_async(&mut *conn)
+            .await?;
+        if match_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let keys: Vec<String> = match_ids.iter().map(|id| Self::settlement_key(id)).collect();
+        let raw: Vec<Option<String>> = redis::cmd("MGET")
+            .arg(&keys)
+            .query_async(&mut *conn)
+            .await?;
+        let mut records = Vec::with_capacity(raw.len());
+        for ((match_id, key), raw) in match_ids.iter().zip(&keys).zip(raw) {
+            let Some(raw) = raw else { continue };
+            match self.decode::<Tagged<SettlementRecord>>(key, &raw) {
+                Ok(tagged) => records.push(tagged.record),
+                Err(e) => warn!("Skipping unreadable settlement record {}: {}", match_id, e),
+            }
+        }
+        Ok(records)
+    }
+
+    /// Store a new intent
+    pub async fn store_intent(&self, intent: &Intent) -> Result<()> {
+        let key = format!("intent:{}", intent.nullifier);
+        let value = self.encode(&key, intent)?;
+        
+        let mut conn = self.connection.write().await;
+        
+        // Store intent with expiration
+        let ttl = self.intent_ttl(intent);
+        redis::cmd("SETEX")
+            .arg(&key)
+            .arg(ttl)
             .arg(&value)
             .query_async::<_, ()>(&mut *conn)
             .await?;
-        
-        // Add to matched set
-        redis::cmd("SADD")
-            .arg("intents:matched")
-            .arg(&pair.id)
+        
+        // Add to pending set
+        redis::cmd("SADD")
+            .arg("intents:pending")
+            .arg(&intent.nullifier)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+
+        // Index by user for status queries across devices/browsers.
+        let user_key = Self::user_index_key(&intent.public_inputs.user);
+        redis::cmd("SADD")
+            .arg(&user_key)
+            .arg(&intent.nullifier)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        
+        // Index by token pair
+        let pair_key = format!("intents:pair:{}:{}", intent.public_inputs.token_in, intent.public_inputs.token_out);
+        redis::cmd("SADD")
+            .arg(&pair_key)
+            .arg(&intent.nullifier)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+
+        // Index by nonce for nonce-based replacement; lives as long as the intent.
+        redis::cmd("SETEX")
+            .arg(Self::nonce_index_key(&intent.public_inputs.user, intent.public_inputs.nonce))
+            .arg(ttl)
+            .arg(&intent.nullifier)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        
+        debug!("Stored intent {} with TTL {}s", log_nullifier(&intent.nullifier), ttl);
+        Ok(())
+    }
+
+    /// Nullifier of the stored intent carrying `(user, nonce)`, if any.
+    pub async fn get_nullifier_for_nonce(&self, user: &str, nonce: u64) -> Result<Option<String>> {
+        let mut conn = self.connection.write().await;
+        let nullifier: Option<String> = redis::cmd("GET")
+            .arg(Self::nonce_index_key(user, nonce))
+            .query_async(&mut *conn)
+            .await?;
+        Ok(nullifier)
+    }
+
+    /// Atomically cancels `old` and stores `new` in its place (same user and nonce). Returns
+    /// false, changing nothing, if `old` is gone or no longer pending (e.g. it was just matched).
+    pub async fn replace_pending_intent(&self, old: &Intent, new: &Intent) -> Result<bool> {
+        let old_key = format!("intent:{}", old.nullifier);
+        let new_key = format!("intent:{}", new.nullifier);
+        let ttl = self.intent_ttl(new);
+        let pair_key = format!("intents:pair:{}:{}", new.public_inputs.token_in, new.public_inputs.token_out);
+        let new_value = self.encode(&new_key, new)?;
+
+        let mut conn = self.connection.write().await;
+        let current: Option<String> = redis::cmd("GET")
+            .arg(&old_key)
+            .query_async(&mut *conn)
+            .await?;
+        let Some(current) = current else {
+            return Ok(false);
+        };
+        let mut cancelled: Intent = self.decode(&old_key, &current)?;
+        if cancelled.status != IntentStatus::Pending {
+            return Ok(false);
+        }
+        cancelled.status = IntentStatus::Cancelled;
+
+        let replaced: i64 = redis::cmd("EVAL")
+            .arg(REPLACE_PENDING_INTENT_LUA)
+            .arg(6)
+            .arg(&old_key)
+            .arg(&new_key)
+            .arg("intents:pending")
+            .arg(Self::user_index_key(&new.public_inputs.user))
+            .arg(pair_key)
+            .arg(Self::nonce_index_key(&new.public_inputs.user, new.public_inputs.nonce))
+            .arg(self.encode(&old_key, &cancelled)?)
+            .arg(&old.nullifier)
+            .arg(new_value)
+            .arg(ttl)
+            .arg(&new.nullifier)
+            .arg(&current)
+            .query_async(&mut *conn)
+            .await?;
+        if replaced == 1 {
+            self.publish_status(&cancelled);
+        }
+        Ok(replaced == 1)
+    }
+
+    /// Reserve (user, nonce) for anti-replay for `ttl_seconds`. Returns false if already reserved.
+    /// The TTL comes from `NonceMode::reservation_ttl`; once the key expires the nonce is reusable.
+    pub async fn reserve_nonce(
+        &self,
+        user: &str,
+        nonce: u64,
+        ttl_seconds: u64,
+    ) -> Result<bool> {
+        let key = format!("nonce:{}:{}", user, nonce);
+        let ttl = ttl_seconds.max(1);
+        let mut conn = self.connection.write().await;
+        let response: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg("1")
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl)
+            .query_async(&mut *conn)
+            .await?;
+        Ok(response.is_some())
+    }
+
+    /// Round-trips a `PING`, for readiness checks.
+    pub async fn ping(&self) -> Result<()> {
+        let mut conn = self.connection.write().await;
+        redis::cmd("PING").query_async::<_, String>(&mut *conn).await?;
+        Ok(())
+    }
+
+    /// Records why the matcher can't match a token pair (e.g. `NO_POOL`), or clears it with `None`.
+    /// Both directions of a pair share one entry.
+    pub async fn set_pair_issue(&self, token_a: &str, token_b: &str, reason: Option<&str>) -> Result<()> {
+        let field = pair_issue_field(token_a, token_b);
+        let mut conn = self.connection.write().await;
+        match reason {
+            Some(reason) => {
+                redis::cmd("HSET")
+                    .arg(PAIR_ISSUES_KEY)
+                    .arg(&field)
+                    .arg(reason)
+                    .query_async::<_, ()>(&mut *conn)
+                    .await?
+            }
+            None => {
+                redis::cmd("HDEL")
+                    .arg(PAIR_ISSUES_KEY)
+                    .arg(&field)
+                    .query_async::<_, ()>(&mut *conn)
+                    .await?
+            }
+        }
+        Ok(())
+    }
+
+    fn gas_spend_key(day: &str) -> String {
+        format!("gas:spent:{}", day)
+    }
+
+    fn gas_override_key(day: &str) -> String {
+        format!("gas:override:{}", day)
+    }
+
+    /// Adds a settlement fee (10^-9 fee-token units) to the UTC `day`'s total and returns the new
+    /// total. Day keys are kept for two days, long enough to outlive any rollover race.
+    pub async fn add_gas_spend(&self, day: &str, amount_nano: u64) -> Result<u64> {
+        let key = Self::gas_spend_key(day);
+        let mut conn = self.connection.write().await;
+        let total: u64 = redis::cmd("INCRBY")
+            .arg(&key)
+            .arg(amount_nano)
+            .query_async(&mut *conn)
+            .await?;
+        redis::cmd("EXPIRE")
+            .arg(&key)
+            .arg(GAS_DAY_KEY_TTL_SECONDS)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(total)
+    }
+
+    pub async fn get_gas_spend(&self, day: &str) -> Result<u64> {
+        let mut conn = self.connection.write().await;
+        let total: Option<u64> = redis::cmd("GET")
+            .arg(Self::gas_spend_key(day))
+            .query_async(&mut *conn)
+            .await?;
+        Ok(total.unwrap_or(0))
+    }
+
+    /// Lifts the `DAILY_GAS_BUDGET` pause for the rest of the UTC `day`.
+    pub async fn set_gas_budget_override(&self, day: &str) -> Result<()> {
+        let mut conn = self.connection.write().await;
+        redis::cmd("SET")
+            .arg(Self::gas_override_key(day))
+            .arg("1")
+            .arg("EX")
+            .arg(GAS_DAY_KEY_TTL_SECONDS)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn is_gas_budget_overridden(&self, day: &str) -> Result<bool> {
+        let mut conn = self.connection.write().await;
+        let exists: bool = redis::cmd("EXISTS")
+            .arg(Self::gas_override_key(day))
+            .query_async(&mut *conn)
+            .await?;
+        Ok(exists)
+    }
+
+    /// Takes `user`'s submit lock (`SET NX PX`), held while a submission is checked against the
+    /// user's resting intents and stored. Returns the lock token, or `None` while another holds it.
+    pub async fn try_acquire_submit_lock(&self, user: &str, ttl_ms: u64) -> Result<Option<String>> {
+        let token = uuid::Uuid::new_v4().to_string();
+        let mut conn = self.connection.write().await;
+        let response: Option<String> = redis::cmd("SET")
+            .arg(Self::submit_lock_key(user))
+            .arg(&token)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl_ms.max(1))
+            .query_async(&mut *conn)
+            .await?;
+        Ok(response.map(|_| token))
+    }
+
+    /// Releases `user`'s submit lock if `token` still owns it.
+    pub async fn release_submit_lock(&self, user: &str, token: &str) -> Result<()> {
+        let mut conn = self.connection.write().await;
+        redis::cmd("EVAL")
+            .arg(COMPARE_AND_DELETE_LUA)
+            .arg(1)
+            .arg(Self::submit_lock_key(user))
+            .arg(token)
+            .query_async::<_, i64>(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    fn submit_lock_key(user: &str) -> String {
+        format!("submit:lock:{}", Self::canonical_user(user))
+    }
+
+    fn submit_slot_key(user: &str) -> String {
+        format!("submit:last:{}", Self::canonical_user(user))
+    }
+
+    /// Enforces `MIN_SUBMIT_INTERVAL_MS` for `user`: `None` when the submission may proceed (and
+    /// starts the next interval), or `Some(ms)` until the user may submit again. Keyed by the
+    /// canonical address, so padding or case can't open a second slot.
+    pub async fn claim_submit_slot(&self, user: &str, interval_ms: u64) -> Result<Option<u64>> {
+        let key = Self::submit_slot_key(user);
+        let mut conn = self.connection.write().await;
+        let remaining_ms: i64 = redis::cmd("EVAL")
+            .arg(CLAIM_SUBMIT_SLOT_LUA)
+            .arg(1)
+            .arg(&key)
+            .arg(interval_ms.max(1))
+            .query_async(&mut *conn)
+            .await?;
+        Ok((remaining_ms > 0).then_some(remaining_ms as u64))
+    }
+
+    /// Denylists a logged-out token's `jti` for `ttl_seconds`, the rest of its validity.
+    pub async fn revoke_token(&self, jti: &str, ttl_seconds: u64) -> Result<()> {
+        let mut conn = self.connection.write().await;
+        redis::cmd("SET")
+            .arg(Self::revoked_token_key(jti))
+            .arg("1")
+            .arg("EX")
+            .arg(ttl_seconds.max(1))
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Whether `jti` was logged out; a single GET, as it runs on every authenticated request.
+    pub async fn is_token_revoked(&self, jti: &str) -> Result<bool> {
+        let mut conn = self.connection.write().await;
+        let value: Option<String> = redis::cmd("GET")
+            .arg(Self::revoked_token_key(jti))
+            .query_async(&mut *conn)
+            .await?;
+        Ok(value.is_some())
+    }
+
+    fn revoked_token_key(jti: &str) -> String {
+        format!("jwt:revoked:{}", jti)
+    }
+
+    /// Records an issued refresh token for `subject`; `/v1/auth/refresh` only honours ids on
+    /// record, so deleting one revokes the token.
+    pub async fn store_refresh_token(&self, jti: &str, subject: &str, ttl_seconds: u64) -> Result<()> {
+        let mut conn = self.connection.write().await;
+        redis::cmd("SET")
+            .arg(Self::refresh_token_key(jti))
+            .arg(subject)
+            .arg("EX")
+            .arg(ttl_seconds.max(1))
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// The subject a refresh token was issued to, if it is still on record.
+    pub async fn get_refresh_token_subject(&self, jti: &str) -> Result<Option<String>> {
+        let mut conn = self.connection.write().await;
+        let subject: Option<String> = redis::cmd("GET")
+            .arg(Self::refresh_token_key(jti))
+            .query_async(&mut *conn)
+            .await?;
+        Ok(subject)
+    }
+
+    /// Revokes a refresh token; false if it wasn't on record (already revoked or expired).
+    pub async fn revoke_refresh_token(&self, jti: &str) -> Result<bool> {
+        let mut conn = self.connection.write().await;
+        let removed: u64 = redis::cmd("DEL")
+            .arg(Self::refresh_token_key(jti))
+            .query_async(&mut *conn)
+            .await?;
+        Ok(removed > 0)
+    }
+
+    fn refresh_token_key(jti: &str) -> String {
+        format!("jwt:refresh:{}", jti)
+    }
+
+    /// Counts a request by `client` at `now_ms` against `limit` requests per `window_ms`, unless
+    /// the window is already full. Shared by every instance on this Redis.
+    pub async fn hit_rate_limit(&self, client: &str, window_ms: u64, limit: u32, now_ms: u64) -> Result<RateLimitDecision> {
+        let key = format!("ratelimit:{}", client);
+        let member = format!("{}:{}", now_ms, uuid::Uuid::new_v4());
+        let mut conn = self.connection.write().await;
+        let (allowed, count, oldest_ms): (i64, u64, u64) = redis::cmd("EVAL")
+            .arg(RATE_LIMIT_LUA)
+            .arg(1)
+            .arg(&key)
+            .arg(now_ms)
+            .arg(window_ms.max(1))
+            .arg(limit)
+            .arg(member)
+            .query_async(&mut *conn)
+            .await?;
+        Ok(RateLimitDecision {
+            allowed: allowed == 1,
+            limit,
+            remaining: u64::from(limit).saturating_sub(count) as u32,
+            reset_at_ms: oldest_ms.saturating_add(window_ms),
+        })
+    }
+
+    /// Get an intent by nullifier
+    pub async fn get_intent(&self, nullifier: &str) -> Result<Option<Intent>> {
+        let key = format!("intent:{}", nullifier);
+        let mut conn = self.connection.write().await;
+        
+        let value: Option<String> = redis::cmd("GET")
+            .arg(&key)
+            .query_async(&mut *conn)
+            .await?;
+        
+        match value {
+            Some(raw) => Ok(Some(self.decode(&key, &raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get all pending intents
+    pub async fn get_pending_intents(&self) -> Result<Vec<Intent>> {
+        // Fetch nullifiers first, then resolve intents without holding the connection lock.
+        // Holding the lock and calling `self.get_intent()` would deadlock (nested lock acquire).
+        let nullifiers: Vec<String> = {
+            let mut conn = self.connection.write().await;
+            redis::cmd("SMEMBERS")
+                .arg("intents:pending")
+                .query_async(&mut *conn)
+                .await?
+        };
+
+        let mut intents = Vec::new();
+        for nullifier in nullifiers {
+            if let Some(intent) = self.get_intent(&nullifier).await? {
+                if intent.is_open() {
+                    intents.push(intent);
+                }
+            }
+        }
+        
+        Ok(intents)
+    }
+
+    /// One page of `get_pending_intents`, walked with `SSCAN` from `cursor` (0 starts over): the open
+    /// intents among the next `limit` or so pending nullifiers, and the cursor to continue from, 0 once
+    /// the set is exhausted. `COUNT` is only a hint, so a page can run over `limit` (small sets come
+    /// back whole) or hold fewer intents. An intent that stays pending is returned at least once
+    /// over a full walk; one added or removed meanwhile may or may not be.
+    pub async fn get_pending_intents_page(&self, cursor: u64, limit: usize) -> Result<(Vec<Intent>, u64)> {
+        let mut nullifiers: Vec<String> = Vec::new();
+        let mut next = cursor;
+        loop {
+            let (after, batch): (u64, Vec<String>) = {
+                let mut conn = self.connection.write().await;
+                redis::cmd("SSCAN")
+                    .arg("intents:pending")
+                    .arg(next)
+                    .arg("COUNT")
+                    .arg(limit)
+                    .query_async(&mut *conn)
+                    .await?
+            };
+            nullifiers.extend(batch);
+            next = after;
+            if next == 0 || nullifiers.len() >= limit {
+                break;
+            }
+        }
+        // SSCAN may return a member more than once.
+        nullifiers.sort();
+        nullifiers.dedup();
+
+        let mut intents = Vec::new();
+        for nullifier in nullifiers {
+            if let Some(intent) = self.get_intent(&nullifier).await? {
+                if intent.is_open() {
+                    intents.push(intent);
+                }
+            }
+        }
+        Ok((intents, next))
+    }
+
+    /// Marks every pending intent past its `expires_at` as `Expired`, dropping it from the pending
+    /// and pair indexes, and drops pending entries whose record is already gone. Each intent is
+    /// marked only if its record is unchanged since it was read, so one matched meanwhile is left
+    /// alone. Returns the nullifiers marked.
+    pub async fn expire_pending_intents(&self) -> Result<Vec<String>> {
+        let nullifiers: Vec<String> = {
+            let mut conn = self.connection.write().await;
+            redis::cmd("SMEMBERS")
+                .arg("intents:pending")
+                .query_async(&mut *conn)
+                .await?
+        };
+
+        let mut expired = Vec::new();
+        for nullifier in nullifiers {
+            let key = format!("intent:{}", nullifier);
+            let mut conn = self.connection.write().await;
+            let raw: Option<String> = redis::cmd("GET").arg(&key).query_async(&mut *conn).await?;
+            let Some(raw) = raw else {
+                redis::cmd("SREM")
+                    .arg("intents:pending")
+                    .arg(&nullifier)
+                    .query_async::<_, ()>(&mut *conn)
+                    .await?;
+                continue;
+            };
+            let mut intent: Intent = self.decode(&key, &raw)?;
+            if intent.status != IntentStatus::Pending || !intent.is_expired() {
+                continue;
+            }
+
+            intent.status = IntentStatus::Expired;
+            intent.matched_with = None;
+            intent.settlement_tx_hash = None;
+            let marked: i64 = redis::cmd("EVAL")
+                .arg(EXPIRE_INTENT_LUA)
+                .arg(3)
+                .arg(&key)
+                .arg("intents:pending")
+                .arg(format!("intents:pair:{}:{}", intent.public_inputs.token_in, intent.public_inputs.token_out))
+                .arg(&raw)
+                .arg(self.encode(&key, &intent)?)
+                .arg(&nullifier)
+                .query_async(&mut *conn)
+                .await?;
+            drop(conn);
+            if marked == 1 {
+                debug!("Updated intent {} status to {:?}", log_nullifier(&nullifier), intent.status);
+                self.publish_status(&intent);
+                expired.push(nullifier);
+            }
+        }
+        Ok(expired)
+    }
+
+    /// Get pending intents for a specific token pair
+    pub async fn get_intents_by_pair(&self, token_in: &str, token_out: &str) -> Result<Vec<Intent>> {
+        let pair_key = format!("intents:pair:{}:{}", token_in, token_out);
+        let nullifiers: Vec<String> = {
+            let mut conn = self.connection.write().await;
+            redis::cmd("SMEMBERS")
+                .arg(&pair_key)
+                .query_async(&mut *conn)
+                .await?
+        };
+
+        let mut intents = Vec::new();
+        for nullifier in nullifiers {
+            if let Some(intent) = self.get_intent(&nullifier).await? {
+                if intent.is_open() {
+                    intents.push(intent);
+                }
+            }
+        }
+        
+        Ok(intents)
+    }
+
+    /// Get intents for a specific user (all statuses)
+    pub async fn get_intents_by_user(&self, user: &str) -> Result<Vec<Intent>> {
+        let user_key = Self::user_index_key(user);
+        let nullifiers: Vec<String> = {
+            let mut conn = self.connection.write().await;
+            redis::cmd("SMEMBERS")
+                .arg(&user_key)
+                .query_async(&mut *conn)
+                .await?
+        };
+
+        // The index is only a hint: entries whose intent expired, or that resolve to another user's
+        // intent (older deployments indexed unnormalized addresses), are dropped from the result
+        // and from the index.
+        let canonical = Self::canonical_user(user);
+        let mut intents = Vec::new();
+        let mut stale = Vec::new();
+        for nullifier in nullifiers {
+            match self.get_intent(&nullifier).await? {
+                Some(intent) if Self::canonical_user(&intent.public_inputs.user) == canonical => {
+                    intents.push(intent)
+                }
+                Some(intent) => {
+                    warn!(
+                        "User index of {} lists intent {} of another user {}; removing it",
+                        log_address(user), log_nullifier(&nullifier), log_address(&intent.public_inputs.user)
+                    );
+                    stale.push(nullifier);
+                }
+                None => stale.push(nullifier),
+            }
+        }
+
+        if !stale.is_empty() && !self.read_only {
+            let mut conn = self.connection.write().await;
+            redis::cmd("SREM")
+                .arg(&user_key)
+                .arg(&stale)
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+        }
+
+        Ok(intents)
+    }
+
+    /// Update intent status
+    pub async fn update_intent_status(
+        &self,
+        nullifier: &str,
+        status: IntentStatus,
+        matched_with: Option<String>,
+        settlement_tx_hash: Option<String>,
+    ) -> Result<()> {
+        let mut intent = match self.get_intent(nullifier).await? {
+            Some(intent) => intent,
+            None => return Err(anyhow::anyhow!("Intent not found: {}", nullifier)),
+        };
+        
+        intent.status = status.clone();
+        intent.matched_with = matched_with;
+        intent.settlement_tx_hash = settlement_tx_hash;
+        
+        let key = format!("intent:{}", nullifier);
+        let value = self.encode(&key, &intent)?;
+        
+        let mut conn = self.connection.write().await;
+        redis::cmd("SET")
+            .arg(&key)
+            .arg(&value)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        
+        // Update pending set
+        if status == IntentStatus::Matched || status == IntentStatus::Settled {
+            redis::cmd("SREM")
+                .arg("intents:pending")
+                .arg(nullifier)
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+        }
+        
+        debug!("Updated intent {} status to {:?}", log_nullifier(nullifier), status);
+        self.publish_status(&intent);
+        Ok(())
+    }
+
+    /// Moves an intent to the terminal `Failed` state, recording why settlement was given up on.
+    /// `matched_with` is kept so the failed match can still be looked up.
+    pub async fn mark_intent_failed(&self, nullifier: &str, reason: &str) -> Result<()> {
+        let mut intent = match self.get_intent(nullifier).await? {
+            Some(intent) => intent,
+            None => return Err(anyhow::anyhow!("Intent not found: {}", nullifier)),
+        };
+
+        intent.status = IntentStatus::Failed;
+        intent.failure_reason = Some(reason.to_string());
+
+        let key = format!("intent:{}", nullifier);
+        let value = self.encode(&key, &intent)?;
+
+        let mut conn = self.connection.write().await;
+        redis::cmd("SET")
+            .arg(&key)
+            .arg(&value)
+            .arg("KEEPTTL")
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        redis::cmd("SREM")
+            .arg("intents:pending")
+            .arg(nullifier)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+
+        debug!("Marked intent {} failed: {}", log_nullifier(nullifier), reason);
+        self.publish_status(&intent);
+        Ok(())
+    }
+
+    /// Puts an intent from a failed match back in the book (`Pending`, no `matched_with`) and keeps
+    /// `counterparty` in its failed set until `cooldown_until`, pruning lapsed entries. Returns
+    /// false, leaving the intent alone, unless it is still `Matched`; the record and the pending set
+    /// change together, and only over the record that was read.
+    pub async fn requeue_intent(
+        &self,
+        nullifier: &str,
+        counterparty: &str,
+        cooldown_until: chrono::DateTime<chrono::Utc>,
+    ) -> Result<bool> {
+        let key = format!("intent:{}", nullifier);
+        let mut conn = self.connection.write().await;
+        let raw: Option<String> = redis::cmd("GET").arg(&key).query_async(&mut *conn).await?;
+        let Some(raw) = raw else {
+            return Ok(false);
+        };
+        let mut intent: Intent = self.decode(&key, &raw)?;
+        if intent.status != IntentStatus::Matched {
+            return Ok(false);
+        }
+
+        let now = chrono::Utc::now();
+        intent.status = IntentStatus::Pending;
+        intent.matched_with = None;
+        intent.failed_counterparties.retain(|_, until| *until > now);
+        intent
+            .failed_counterparties
+            .insert(counterparty.to_string(), cooldown_until);
+
+        let requeued: i64 = redis::cmd("EVAL")
+            .arg(REQUEUE_INTENT_LUA)
+            .arg(2)
+            .arg(&key)
+            .arg("intents:pending")
+            .arg(&raw)
+            .arg(self.encode(&key, &intent)?)
+            .arg(nullifier)
+            .query_async(&mut *conn)
+            .await?;
+        if requeued != 1 {
+            return Ok(false);
+        }
+
+        debug!("Requeued intent {} (cooling down with {})", log_nullifier(nullifier), log_nullifier(counterparty));
+        self.publish_status(&intent);
+        Ok(true)
+    }
+
+    /// Records that `counterparty` nearly crossed with a pending intent. Counterparties are kept
+    /// per intent in `intent:nearmiss:<nullifier>` (field = counterparty, value = last seen), so
+    /// a pair seen on every matching tick counts once. The intent's summary fields are only
+    /// rewritten for a new counterparty or once `last_near_miss_at` is a minute old.
+    pub async fn record_near_miss(&self, intent: &Intent, counterparty: &str) -> Result<()> {
+        let now = chrono::Utc::now();
+        let key = format!("intent:nearmiss:{}", intent.nullifier);
+        let (is_new, count): (i64, u32) = {
+            let mut conn = self.connection.write().await;
+            let is_new: i64 = redis::cmd("HSET")
+                .arg(&key)
+                .arg(counterparty)
+                .arg(now.timestamp())
+                .query_async(&mut *conn)
+                .await?;
+            redis::cmd("EXPIREAT")
+                .arg(&key)
+                .arg(intent.expires_at.timestamp())
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+            let count: u32 = redis::cmd("HLEN").arg(&key).query_async(&mut *conn).await?;
+            (is_new, count)
+        };
+
+        let stale = intent
+            .last_near_miss_at
+            .map_or(true, |at| (now - at).num_seconds() >= 60);
+        if is_new == 0 && !stale {
+            return Ok(());
+        }
+
+        // Re-read so a concurrent status change is not overwritten.
+        let mut current = match self.get_intent(&intent.nullifier).await? {
+            Some(current) if current.status == IntentStatus::Pending => current,
+            _ => return Ok(()),
+        };
+        current.near_miss_count = count;
+        current.last_near_miss_at = Some(now);
+
+        let intent_key = format!("intent:{}", intent.nullifier);
+        let value = self.encode(&intent_key, &current)?;
+        let mut conn = self.connection.write().await;
+        redis::cmd("SET")
+            .arg(&intent_key)
+            .arg(&value)
+            .arg("KEEPTTL")
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Records that a conditional intent's trigger fired at `at`. Written only over the payload it
+    /// read, so a concurrent cancel or match wins; returns false then, or when the intent is no
+    /// longer pending or already triggered.
+    pub async fn mark_intent_triggered(&self, nullifier: &str, at: chrono::DateTime<chrono::Utc>) -> Result<bool> {
+        let key = format!("intent:{}", nullifier);
+        let mut conn = self.connection.write().await;
+        let raw: Option<String> = redis::cmd("GET").arg(&key).query_async(&mut *conn).await?;
+        let Some(raw) = raw else {
+            return Ok(false);
+        };
+        let mut intent: Intent = self.decode(&key, &raw)?;
+        if intent.status != IntentStatus::Pending || intent.triggered_at.is_some() {
+            return Ok(false);
+        }
+        intent.triggered_at = Some(at);
+        let updated: i64 = redis::cmd("EVAL")
+            .arg(COMPARE_AND_SET_LUA)
+            .arg(1)
+            .arg(&key)
+            .arg(&raw)
+            .arg(self.encode(&key, &intent)?)
+            .query_async(&mut *conn)
+            .await?;
+        Ok(updated == 1)
+    }
+
+    /// Rewrites an intent whose deadline was extended, moving its key (and near-miss record)
+    /// to expire at the new `expires_at`. Only replaces a still-pending record whose deadline is
+    /// still `read_deadline`, so a concurrent match, cancel or extension wins; returns false then.
+    pub async fn store_extended_intent(&self, intent: &Intent, read_deadline: u64) -> Result<bool> {
+        let key = format!("intent:{}", intent.nullifier);
+        let value = self.encode(&key, intent)?;
+        let expire_at = intent
+            .expires_at
+            .timestamp()
+            .saturating_add(self.expired_retention_seconds.min(i64::MAX as u64) as i64);
+
+        let mut conn = self.connection.write().await;
+        let raw: Option<String> = redis::cmd("GET").arg(&key).query_async(&mut *conn).await?;
+        let Some(raw) = raw else {
+            return Ok(false);
+        };
+        let current: Intent = self.decode(&key, &raw)?;
+        if current.status != IntentStatus::Pending || current.public_inputs.deadline != read_deadline {
+            return Ok(false);
+        }
+        let updated: i64 = redis::cmd("EVAL")
+            .arg(EXTEND_INTENT_LUA)
+            .arg(3)
+            .arg(&key)
+            .arg(format!("intent:nearmiss:{}", intent.nullifier))
+            .arg(Self::nonce_index_key(&intent.public_inputs.user, intent.public_inputs.nonce))
+            .arg(&raw)
+            .arg(&value)
+            .arg(expire_at)
+            .query_async(&mut *conn)
+            .await?;
+        if updated == 1 {
+            debug!("Extended intent {} to {}", log_nullifier(&intent.nullifier), intent.expires_at);
+        }
+        Ok(updated == 1)
+    }
+
+    /// Keeps an already-reserved `(user, nonce)` reserved for `ttl_seconds` from now.
+    pub async fn extend_nonce_reservation(&self, user: &str, nonce: u64, ttl_seconds: u64) -> Result<()> {
+        let key = format!("nonce:{}:{}", user, nonce);
+        let mut conn = self.connection.write().await;
+        redis::cmd("SET")
+            .arg(&key)
+            .arg("1")
+            .arg("EX")
+            .arg(ttl_seconds.max(1))
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Stores `pair` and moves both of its intents to `Matched` in one step, as long as both are
+    /// still pending as stored. Returns false, changing nothing, when either was taken in the
+    /// meantime (another matcher, a cancel) or is gone.
+    pub async fn try_commit_match(&self, pair: &MatchedPair) -> Result<bool> {
+        let (a, b) = (&pair.intent_a.nullifier, &pair.intent_b.nullifier);
+        let (key_a, key_b) = (format!("intent:{}", a), format!("intent:{}", b));
+        let pair_key = format!("matched:{}", pair.id);
+
+        let mut conn = self.connection.write().await;
+        let mut current = Vec::with_capacity(2);
+        let mut matched = Vec::with_capacity(2);
+        let mut updated = Vec::with_capacity(2);
+        for (key, counterparty) in [(&key_a, b), (&key_b, a)] {
+            let raw: Option<String> = redis::cmd("GET").arg(key).query_async(&mut *conn).await?;
+            let Some(raw) = raw else {
+                return Ok(false);
+            };
+            let mut intent: Intent = self.decode(key, &raw)?;
+            if intent.status != IntentStatus::Pending {
+                return Ok(false);
+            }
+            intent.status = IntentStatus::Matched;
+            intent.matched_with = Some(counterparty.clone());
+            intent.settlement_tx_hash = None;
+            matched.push(self.encode(key, &intent)?);
+            current.push(raw);
+            updated.push(intent);
+        }
+
+        let committed: i64 = redis::cmd("EVAL")
+            .arg(COMMIT_MATCH_LUA)
+            .arg(7)
+            .arg(&key_a)
+            .arg(&key_b)
+            .arg(&pair_key)
+            .arg("intents:matched")
+            .arg("intents:pending")
+            .arg(Self::match_by_nullifier_key(a))
+            .arg(Self::match_by_nullifier_key(b))
+            .arg(&current[0])
+            .arg(&current[1])
+            .arg(&matched[0])
+            .arg(&matched[1])
+            .arg(self.encode(&pair_key, pair)?)
+            .arg(&pair.id)
+            .arg(a)
+            .arg(b)
+            .query_async(&mut *conn)
+            .await?;
+        if committed == 1 {
+            debug!("Committed match {} ({} <-> {})", pair.id, a, b);
+            updated.iter().for_each(|intent| self.publish_status(intent));
+        }
+        Ok(committed == 1)
+    }
+
+    pub async fn get_matched_pair(&self, id: &str) -> Result<Option<MatchedPair>> {
+        let key = format!("matched:{}", id);
+        let mut conn = self.connection.write().await;
+        let value: Option<String> = redis::cmd("GET")
+            .arg(&key)
+            .query_async(&mut *conn)
+            .await?;
+        match value {
+            Some(raw) => Ok(Some(self.decode(&key, &raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the id of the match a nullifier currently participates in (if any).
+    pub async fn get_match_id_for_nullifier(&self, nullifier: &str) -> Result<Option<String>> {
+        let mut conn = self.connection.write().await;
+        let match_id: Option<String> = redis::cmd("GET")
+            .arg(Self::match_by_nullifier_key(nullifier))
+            .query_async(&mut *conn)
+            .await?;
+        Ok(match_id)
+    }
+
+    /// Get matched pairs awaiting settlement
+    pub async fn get_unsettled_matches(&self) -> Result<Vec<MatchedPair>> {
+        // Fetch matched pair ids without holding the lock, then resolve pair + intent status
+        // using the normal helpers (avoids nested lock deadlocks).
+        let pair_ids: Vec<String> = {
+            let mut conn = self.connection.write().await;
+            redis::cmd("SMEMBERS")
+                .arg("intents:matched")
+                .query_async(&mut *conn)
+                .await?
+        };
+
+        let mut pairs = Vec::new();
+        for id in pair_ids {
+            let Some(pair) = self.get_matched_pair(&id).await? else {
+                // Stale set member.
+                let _ = self.mark_match_settled(&id).await;
+                continue;
+            };
+
+            let a = self.get_intent(&pair.intent_a.nullifier).await?;
+            let b = self.get_intent(&pair.intent_b.nullifier).await?;
+
+            // Only retry when both sides are still in Matched state.
+            match (a, b) {
+                (Some(a), Some(b))
+                    if a.status == IntentStatus::Matched
+                        && b.status == IntentStatus::Matched
+                        && a.settlement_tx_hash.is_none()
+                        && b.settlement_tx_hash.is_none() =>
+                {
+                    pairs.push(pair);
+                }
+                _ => {
+                    // Already settled/cancelled/expired or missing: clean up the set member.
+                    let _ = self.mark_match_settled(&id).await;
+                }
+            }
+        }
+
+        Ok(pairs)
+    }
+
+    /// Records that an operator confirmed a match (`SETTLE_MODE=manual_with_retry` retries only these).
+    pub async fn mark_match_confirmed(&self, match_id: &str) -> Result<()> {
+        let mut conn = self.connection.write().await;
+        redis::cmd("SADD")
+            .arg(CONFIRMED_MATCHES_KEY)
+            .arg(match_id)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn is_match_confirmed(&self, match_id: &str) -> Result<bool> {
+        let mut conn = self.connection.write().await;
+        let confirmed: bool = redis::cmd("SISMEMBER")
+            .arg(CONFIRMED_MATCHES_KEY)
+            .arg(match_id)
+            .query_async(&mut *conn)
+            .await?;
+        Ok(confirmed)
+    }
+
+    pub async fn mark_match_settled(&self, match_id: &str) -> Result<()> {
+        let mut conn = self.connection.write().await;
+        let key = format!("matched:{}", match_id);
+
+        // Drop the participant index entries, but only where they still point at this match
+        // (an intent may already belong to a newer match).
+        let payload: Option<String> = redis::cmd("GET")
+            .arg(&key)
+            .query_async(&mut *conn)
+            .await?;
+        if let Some(pair) = payload.and_then(|raw| self.decode::<MatchedPair>(&key, &raw).ok()) {
+            for nullifier in [&pair.intent_a.nullifier, &pair.intent_b.nullifier] {
+                redis::cmd("EVAL")
+                    .arg(COMPARE_AND_DELETE_LUA)
+                    .arg(1)
+                    .arg(Self::match_by_nullifier_key(nullifier))
+                    .arg(match_id)
+                    .query_async::<_, i64>(&mut *conn)
+                    .await?;
+            }
+        }
+
+        redis::cmd("SREM")
+            .arg("intents:matched")
+            .arg(match_id)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        // Drop it from the deadletter set too, in case it was given up on earlier.
+        redis::cmd("SREM")
+            .arg(DEADLETTER_KEY)
+            .arg(match_id)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        redis::cmd("SREM")
+            .arg(CONFIRMED_MATCHES_KEY)
+            .arg(match_id)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        redis::cmd("SREM")
+            .arg(SETTLING_MATCHES_KEY)
+            .arg(match_id)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        // Also delete the matched pair payload (and any settling marker) to avoid stale "matched" views.
+        redis::cmd("DEL")
+            .arg(&key)
+            .arg(Self::match_settling_key(match_id))
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Forgets a match that will never settle: the same cleanup as `mark_match_settled`, plus its
+    /// retry state.
+    pub async fn discard_match(&self, match_id: &str) -> Result<()> {
+        self.mark_match_settled(match_id).await?;
+        self.clear_match_retry_state(match_id).await
+    }
+
+    /// Hard-deletes `intent`: its payload, near-miss record, pending/user/pair index entries, nonce
+    /// index and nonce reservation, plus `match_id`'s payload, retry state, settling marker and set
+    /// memberships and both participants' match index entries. Returns the keys something was
+    /// removed from. The caller holds `match_id`'s settlement lock; settlement records are kept.
+    pub async fn purge_intent(&self, intent: &Intent, match_id: Option<&str>) -> Result<Vec<String>> {
+        let nullifier = intent.nullifier.as_str();
+        let inputs = &intent.public_inputs;
+        let mut conn = self.connection.write().await;
+        let mut removed = Vec::new();
+
+        let mut deletes = vec![
+            format!("intent:{}", nullifier),
+            format!("intent:nearmiss:{}", nullifier),
+            format!("nonce:{}:{}", inputs.user, inputs.nonce),
+        ];
+        let mut members = vec![
+            ("intents:pending".to_string(), nullifier.to_string()),
+            (Self::user_index_key(&inputs.user), nullifier.to_string()),
+            (format!("intents:pair:{}:{}", inputs.token_in, inputs.token_out), nullifier.to_string()),
+        ];
+        // Index entries that may already point at something newer are only dropped if they still
+        // point here.
+        let mut owned = vec![(Self::nonce_index_key(&inputs.user, inputs.nonce), nullifier.to_string())];
+        if let Some(match_id) = match_id {
+            let key = format!("matched:{}", match_id);
+            let payload: Option<String> = redis::cmd("GET").arg(&key).query_async(&mut *conn).await?;
+            let mut participants = vec![nullifier.to_string()];
+            if let Some(pair) = payload.and_then(|raw| self.decode::<MatchedPair>(&key, &raw).ok()) {
+                participants = vec![pair.intent_a.nullifier, pair.intent_b.nullifier];
+            }
+            for participant in participants {
+                owned.push((Self::match_by_nullifier_key(&participant), match_id.to_string()));
+            }
+            deletes.push(key);
+            deletes.push(Self::match_retry_key(match_id));
+            deletes.push(Self::match_settling_key(match_id));
+            for set in ["intents:matched", DEADLETTER_KEY, CONFIRMED_MATCHES_KEY, SETTLING_MATCHES_KEY] {
+                members.push((set.to_string(), match_id.to_string()));
+            }
+        }
+
+        for key in deletes {
+            let n: i64 = redis::cmd("DEL").arg(&key).query_async(&mut *conn).await?;
+            if n > 0 {
+                removed.push(key);
+            }
+        }
+        for (set, member) in members {
+            let n: i64 = redis::cmd("SREM").arg(&set).arg(&member).query_async(&mut *conn).await?;
+            if n > 0 {
+                removed.push(set);
+            }
+        }
+        for (key, expected) in owned {
+            let n: i64 = redis::cmd("EVAL")
+                .arg(COMPARE_AND_DELETE_LUA)
+                .arg(1)
+                .arg(&key)
+                .arg(&expected)
+                .query_async(&mut *conn)
+                .await?;
+            if n > 0 {
+                removed.push(key);
+            }
+        }
+
+        // What was recorded about the intent after the fact: its settlement records, its trade-log
+        // entries and its exported events, including those of its matches.
+        let mut match_ids: Vec<String> = match_id.into_iter().map(str::to_string).collect();
+        let settled = self.scrub_settlement_records(&mut *conn, nullifier).await?;
+        for id in &settled {
+            removed.push(Self::settlement_key(id));
+        }
+        if !settled.is_empty() {
+            removed.push(SETTLEMENT_LOG_KEY.to_string());
+        }
+        match_ids.extend(settled);
+        let traded = self.scrub_match_history(&mut *conn, nullifier).await?;
+        if !traded.is_empty() {
+            removed.push(MATCH_HISTORY_KEY.to_string());
+        }
+        match_ids.extend(traded);
+        if self.scrub_event_log(&mut *conn, nullifier, &match_ids).await? > 0 {
+            removed.push(EVENT_LOG_KEY.to_string());
+        }
+        Ok(removed)
+    }
+
+    /// Drops every exported event naming `nullifier` or one of `match_ids`; returns how many were
+    /// dropped.
+    async fn scrub_event_log(
+        &self,
+        conn: &mut redis::aio::ConnectionManager,
+        nullifier: &str,
+        match_ids: &[String],
+    ) -> Result<usize> {
+        let mut doomed = Vec::new();
+        let mut cursor: u64 = 0;
+        loop {
+            // Replies alternate member and score.
+            let (next, page): (u64, Vec<String>) = redis::cmd("ZSCAN")
+                .arg(EVENT_LOG_KEY)
+                .arg(cursor)
+                .arg("COUNT")
+                .arg(500)
+                .query_async(&mut *conn)
+                .await?;
+            for raw in page.into_iter().step_by(2) {
+                let Ok(record) = self.decode::<EventRecord>(EVENT_LOG_KEY, &raw) else { continue };
+                let names_intent = record.event.nullifiers().iter().any(|n| n.eq_ignore_ascii_case(nullifier));
+                let names_match = record.event.match_id().is_some_and(|id| match_ids.iter().any(|m| m == id));
+                if names_intent || names_match {
+                    doomed.push(raw);
+                }
+            }
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        doomed.dedup();
+        for chunk in doomed.chunks(500) {
+            redis::cmd("ZREM")
+                .arg(EVENT_LOG_KEY)
+                .arg(chunk)
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+        }
+        Ok(doomed.len())
+    }
+
+    /// Deletes the settlement records of every match `nullifier` was in; returns their match ids.
+    async fn scrub_settlement_records(&self, conn: &mut redis::aio::ConnectionManager, nullifier: &str) -> Result<Vec<String>> {
+        let match_ids: Vec<String> = redis::cmd("ZRANGE")
+            .arg(SETTLEMENT_LOG_KEY)
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut *conn)
+            .await?;
+        let mut doomed = Vec::new();
+        for ids in match_ids.chunks(500) {
+            let keys: Vec<String> = ids.iter().map(|id| Self::settlement_key(id)).collect();
+            let raw: Vec<Option<String>> = redis::cmd("MGET").arg(&keys).query_async(&mut *conn).await?;
+            for ((match_id, key), raw) in ids.iter().zip(&keys).zip(raw) {
+                let Some(raw) = raw else { continue };
+                let Ok(tagged) = self.decode::<Tagged<SettlementRecord>>(key, &raw) else { continue };
+                if tagged.names(nullifier) {
+                    doomed.push(match_id.clone());
+                }
+            }
+        }
+        for match_id in &doomed {
+            redis::cmd("DEL")
+                .arg(Self::settlement_key(match_id))
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+            redis::cmd("ZREM")
+                .arg(SETTLEMENT_LOG_KEY)
+                .arg(match_id)
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+        }
+        Ok(doomed)
+    }
+
+    /// Drops every trade-log entry naming `nullifier`; returns their match ids.
+    async fn scrub_match_history(&self, conn: &mut redis::aio::ConnectionManager, nullifier: &str) -> Result<Vec<String>> {
+        let entries: Vec<String> = redis::cmd("LRANGE")
+            .arg(MATCH_HISTORY_KEY)
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut *conn)
+            .await?;
+        let mut dropped = Vec::new();
+        for raw in entries {
+            let Ok(tagged) = self.decode::<Tagged<MatchHistoryEntry>>(MATCH_HISTORY_KEY, &raw) else { continue };
+            if tagged.names(nullifier) {
+                redis::cmd("LREM")
+                    .arg(MATCH_HISTORY_KEY)
+                    .arg(0)
+                    .arg(&raw)
+                    .query_async::<_, ()>(&mut *conn)
+                    .await?;
+                dropped.push(tagged.record.match_id);
+            }
+        }
+        Ok(dropped)
+    }
+
+    /// Moves a match out of the retry set into the deadletter set. Unlike `mark_match_settled`, the
+    /// pair payload is kept and the retry state stops expiring, so operators can still inspect it.
+    pub async fn deadletter_match(&self, match_id: &str) -> Result<()> {
+        let mut conn = self.connection.write().await;
+        let key = format!("matched:{}", match_id);
+
+        let payload: Option<String> = redis::cmd("GET")
+            .arg(&key)
+            .query_async(&mut *conn)
+            .await?;
+        if let Some(pair) = payload.and_then(|raw| self.decode::<MatchedPair>(&key, &raw).ok()) {
+            for nullifier in [&pair.intent_a.nullifier, &pair.intent_b.nullifier] {
+                redis::cmd("EVAL")
+                    .arg(COMPARE_AND_DELETE_LUA)
+                    .arg(1)
+                    .arg(Self::match_by_nullifier_key(nullifier))
+                    .arg(match_id)
+                    .query_async::<_, i64>(&mut *conn)
+                    .await?;
+            }
+        }
+
+        redis::cmd("SREM")
+            .arg("intents:matched")
+            .arg(match_id)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        redis::cmd("SADD")
+            .arg(DEADLETTER_KEY)
+            .arg(match_id)
+            .query_async::<_, ()>(&mut *conn)
+            .await?;
+        redis::cmd("PERSIST")
+            .arg(Self::match_retry_key(match_id))
             .query_async::<_, ()>(&mut *conn)
             .await?;
-        
-        debug!("Stored matched pair {}", pair.id);
         Ok(())
     }
 
-    pub async fn get_matched_pair(&self, id: &str) -> Result<Option<MatchedPair>> {
-        let key = format!("matched:{}", id);
-        let mut conn = self.connection.write().await;
-        let value: Option<String> = redis::cmd("GET")
-            .arg(&key)
-            .query_async(&mut *conn)
-            .await?;
-        match value {
-            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
-            None => Ok(None),
-        }
-    }
-
-    /// Get matched pairs awaiting settlement
-    pub async fn get_unsettled_matches(&self) -> Result<Vec<MatchedPair>> {
-        // Fetch matched pair ids without holding the lock, then resolve pair + intent status
-        // using the normal helpers (avoids nested lock deadlocks).
+    /// Get matches the retry loop gave up on
+    pub async fn get_deadletter_matches(&self) -> Result<Vec<MatchedPair>> {
         let pair_ids: Vec<String> = {
             let mut conn = self.connection.write().await;
             redis::cmd("SMEMBERS")
-                .arg("intents:matched")
+                .arg(DEADLETTER_KEY)
                 .query_async(&mut *conn)
                 .await?
         };
 
         let mut pairs = Vec::new();
         for id in pair_ids {
-            let Some(pair) = self.get_matched_pair(&id).await? else {
-                // Stale set member.
-                let _ = self.mark_match_settled(&id).await;
-                continue;
+            match self.get_matched_pair(&id).await? {
+                Some(pair) => pairs.push(pair),
+                None => {
+                    // Stale set member.
+                    let mut conn = self.connection.write().await;
+                    let _ = redis::cmd("SREM")
+                        .arg(DEADLETTER_KEY)
+                        .arg(&id)
+                        .query_async::<_, ()>(&mut *conn)
+                        .await;
+                }
+            }
+        }
+
+        Ok(pairs)
+    }
+
+    /// Whether the database holds any intent record, stopping at the first one found.
+    pub async fn holds_intents(&self) -> Result<bool> {
+        let mut cursor: u64 = 0;
+        loop {
+            let (next, batch): (u64, Vec<String>) = {
+                let mut conn = self.connection.write().await;
+                redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg("intent:*")
+                    .arg("COUNT")
+                    .arg(500)
+                    .query_async(&mut *conn)
+                    .await?
             };
+            if !batch.is_empty() {
+                return Ok(true);
+            }
+            if next == 0 {
+                return Ok(false);
+            }
+            cursor = next;
+        }
+    }
 
-            let a = self.get_intent(&pair.intent_a.nullifier).await?;
-            let b = self.get_intent(&pair.intent_b.nullifier).await?;
+    #[cfg(test)]
+    async fn key_ttl(conn: &mut redis::aio::ConnectionManager, key: &str) -> Result<KeyTtl> {
+        let pttl_ms: i64 = redis::cmd("PTTL").arg(key).query_async(conn).await?;
+        Ok(KeyTtl::from_pttl(pttl_ms))
+    }
 
-            // Only retry when both sides are still in Matched state.
-            match (a, b) {
-                (Some(a), Some(b))
-                    if a.status == IntentStatus::Matched
-                        && b.status == IntentStatus::Matched
-                        && a.settlement_tx_hash.is_none()
-                        && b.settlement_tx_hash.is_none() =>
-                {
-                    pairs.push(pair);
+    /// One SCAN page of the state export as bundle records, and where the next page starts (`None`
+    /// once every intent, match with its set memberships and retry state, and nonce reservation has
+    /// been read). Payloads are decrypted. Near-miss records, locks, the event log, submit slots and
+    /// gas counters are transient and not exported. Redis keeps serving the solver between pages, so
+    /// the export is not a snapshot: a key written meanwhile may be missed or read twice (import
+    /// skips the repeat), and one removed meanwhile is left out.
+    pub async fn export_state_page(&self, cursor: ExportCursor) -> Result<(Vec<BundleLine>, Option<ExportCursor>)> {
+        let (next, keys): (u64, Vec<String>) = {
+            let mut conn = self.connection.write().await;
+            redis::cmd("SCAN")
+                .arg(cursor.scan)
+                .arg("MATCH")
+                .arg(EXPORT_PATTERNS[cursor.stage])
+                .arg("COUNT")
+                .arg(500)
+                .query_async(&mut *conn)
+                .await?
+        };
+
+        let mut records = Vec::new();
+        for key in keys {
+            if let Some(record) = self.export_record(cursor.stage, &key).await? {
+                records.push(record);
+            }
+        }
+
+        let next = match next {
+            0 if cursor.stage + 1 < EXPORT_PATTERNS.len() => Some(ExportCursor { stage: cursor.stage + 1, scan: 0 }),
+            0 => None,
+            scan => Some(ExportCursor { stage: cursor.stage, scan }),
+        };
+        Ok((records, next))
+    }
+
+    /// The bundle record for `key`, found under `EXPORT_PATTERNS[stage]`. Each key is read in one
+    /// MULTI so its payload and TTL agree; `None` if it is gone or not exported.
+    async fn export_record(&self, stage: usize, key: &str) -> Result<Option<BundleLine>> {
+        let mut conn = self.connection.write().await;
+        match stage {
+            0 => {
+                if key.starts_with("intent:nearmiss:") {
+                    return Ok(None);
                 }
-                _ => {
-                    // Already settled/cancelled/expired or missing: clean up the set member.
-                    let _ = self.mark_match_settled(&id).await;
+                let (raw, pttl): (Option<String>, i64) = redis::pipe()
+                    .atomic()
+                    .cmd("GET")
+                    .arg(key)
+                    .cmd("PTTL")
+                    .arg(key)
+                    .query_async(&mut *conn)
+                    .await?;
+                drop(conn);
+                let ttl = KeyTtl::from_pttl(pttl);
+                let Some(raw) = raw.filter(|_| ttl != KeyTtl::Missing) else {
+                    return Ok(None);
+                };
+                let intent: Intent = self.decode(key, &raw)?;
+                Ok(Some(BundleLine::Intent { intent, ttl_seconds: ttl.seconds() }))
+            }
+            1 => {
+                let id = key.strip_prefix("matched:").unwrap_or(key);
+                let retry_key = Self::match_retry_key(id);
+                #[allow(clippy::type_complexity)]
+                let (raw, unsettled, deadletter, confirmed, retry_pttl, retry_state): (
+                    Option<String>,
+                    bool,
+                    bool,
+                    bool,
+                    i64,
+                    std::collections::BTreeMap<String, String>,
+                ) = redis::pipe()
+                    .atomic()
+                    .cmd("GET")
+                    .arg(key)
+                    .cmd("SISMEMBER")
+                    .arg("intents:matched")
+                    .arg(id)
+                    .cmd("SISMEMBER")
+                    .arg(DEADLETTER_KEY)
+                    .arg(id)
+                    .cmd("SISMEMBER")
+                    .arg(CONFIRMED_MATCHES_KEY)
+                    .arg(id)
+                    .cmd("PTTL")
+                    .arg(&retry_key)
+                    .cmd("HGETALL")
+                    .arg(&retry_key)
+                    .query_async(&mut *conn)
+                    .await?;
+                drop(conn);
+                let Some(raw) = raw else {
+                    return Ok(None);
+                };
+                let pair: MatchedPair = self.decode(key, &raw)?;
+                Ok(Some(BundleLine::Match {
+                    pair,
+                    unsettled,
+                    deadletter,
+                    confirmed,
+                    retry_state,
+                    retry_ttl_seconds: KeyTtl::from_pttl(retry_pttl).seconds(),
+                }))
+            }
+            _ => {
+                let pttl: i64 = redis::cmd("PTTL").arg(key).query_async(&mut *conn).await?;
+                let Some((user, nonce)) = key
+                    .strip_prefix("nonce:")
+                    .and_then(|rest| rest.rsplit_once(':'))
+                    .and_then(|(user, nonce)| Some((user.to_string(), nonce.parse::<u64>().ok()?)))
+                else {
+                    return Ok(None);
+                };
+                // Reservations always expire; one without a TTL is not ours.
+                match KeyTtl::from_pttl(pttl) {
+                    KeyTtl::Seconds(ttl_seconds) => Ok(Some(BundleLine::NonceReservation { user, nonce, ttl_seconds })),
+                    KeyTtl::Missing | KeyTtl::Persistent => Ok(None),
                 }
             }
         }
+    }
 
-        Ok(pairs)
+    /// Restores state bundle records, rebuilding the pending, user, pair, nonce, matched,
+    /// by-nullifier, deadletter and confirmed indexes from them. Existing keys win: a record whose
+    /// key is already present is skipped, so importing twice is harmless. A pending intent is left out
+    /// once `intents:pending` holds `max_pending` (0 for no cap), checked before each one.
+    pub async fn import_state(&self, records: Vec<BundleLine>, max_pending: u64) -> Result<ImportSummary> {
+        let mut summary = ImportSummary::default();
+        for record in records {
+            match record {
+                BundleLine::Intent { intent, ttl_seconds } => {
+                    if max_pending > 0
+                        && intent.status == IntentStatus::Pending
+                        && self.pending_count().await? >= max_pending
+                    {
+                        summary.intents_over_capacity += 1;
+                        continue;
+                    }
+                    if self.import_intent(&intent, ttl_seconds).await? {
+                        summary.intents_imported += 1;
+                    } else {
+                        summary.intents_skipped += 1;
+                    }
+                }
+                BundleLine::Match {
+                    pair,
+                    unsettled,
+                    deadletter,
+                    confirmed,
+                    retry_state,
+                    retry_ttl_seconds,
+                } => {
+                    let key = format!("matched:{}", pair.id);
+                    let value = self.encode(&key, &pair)?;
+                    let mut conn = self.connection.write().await;
+                    let stored: Option<String> = redis::cmd("SET")
+                        .arg(&key)
+                        .arg(&value)
+                        .arg("NX")
+                        .query_async(&mut *conn)
+                        .await?;
+                    if stored.is_none() {
+                        summary.matches_skipped += 1;
+                        continue;
+                    }
+                    if unsettled {
+                        redis::cmd("SADD")
+                            .arg("intents:matched")
+                            .arg(&pair.id)
+                            .query_async::<_, ()>(&mut *conn)
+                            .await?;
+                        for nullifier in [&pair.intent_a.nullifier, &pair.intent_b.nullifier] {
+                            redis::cmd("SET")
+                                .arg(Self::match_by_nullifier_key(nullifier))
+                                .arg(&pair.id)
+                                .query_async::<_, ()>(&mut *conn)
+                                .await?;
+                        }
+                    }
+                    if deadletter {
+                        redis::cmd("SADD")
+                            .arg(DEADLETTER_KEY)
+                            .arg(&pair.id)
+                            .query_async::<_, ()>(&mut *conn)
+                            .await?;
+                    }
+                    if confirmed {
+                        redis::cmd("SADD")
+                            .arg(CONFIRMED_MATCHES_KEY)
+                            .arg(&pair.id)
+                            .query_async::<_, ()>(&mut *conn)
+                            .await?;
+                    }
+                    if !retry_state.is_empty() {
+                        let retry_key = Self::match_retry_key(&pair.id);
+                        let mut hset = redis::cmd("HSET");
+                        hset.arg(&retry_key);
+                        for (field, value) in &retry_state {
+                            hset.arg(field).arg(value);
+                        }
+                        hset.query_async::<_, ()>(&mut *conn).await?;
+                        if let Some(ttl) = retry_ttl_seconds {
+                            redis::cmd("EXPIRE")
+                                .arg(&retry_key)
+                                .arg(ttl.max(1))
+                                .query_async::<_, ()>(&mut *conn)
+                                .await?;
+                        }
+                    }
+                    summary.matches_imported += 1;
+                }
+                BundleLine::NonceReservation { user, nonce, ttl_seconds } => {
+                    if self.reserve_nonce(&user, nonce, ttl_seconds).await? {
+                        summary.nonce_reservations_imported += 1;
+                    } else {
+                        summary.nonce_reservations_skipped += 1;
+                    }
+                }
+                BundleLine::Header { .. } | BundleLine::Signature { .. } => {}
+            }
+        }
+        info!("Imported state: {:?}", summary);
+        Ok(summary)
     }
 
-    pub async fn mark_match_settled(&self, match_id: &str) -> Result<()> {
+    /// `store_intent` for a bundled intent: keeps its remaining TTL (or none) and only adds it to
+    /// the pending set if it is still pending. Returns false if the intent already exists.
+    async fn import_intent(&self, intent: &Intent, ttl_seconds: Option<u64>) -> Result<bool> {
+        let key = format!("intent:{}", intent.nullifier);
+        let value = self.encode(&key, intent)?;
         let mut conn = self.connection.write().await;
-        let key = format!("matched:{}", match_id);
-        redis::cmd("SREM")
-            .arg("intents:matched")
-            .arg(match_id)
+
+        let mut set = redis::cmd("SET");
+        set.arg(&key).arg(&value).arg("NX");
+        if let Some(ttl) = ttl_seconds {
+            set.arg("EX").arg(ttl.max(1));
+        }
+        let stored: Option<String> = set.query_async(&mut *conn).await?;
+        if stored.is_none() {
+            return Ok(false);
+        }
+
+        if intent.status == IntentStatus::Pending {
+            redis::cmd("SADD")
+                .arg("intents:pending")
+                .arg(&intent.nullifier)
+                .query_async::<_, ()>(&mut *conn)
+                .await?;
+        }
+        redis::cmd("SADD")
+            .arg(Self::user_index_key(&intent.public_inputs.user))
+            .arg(&intent.nullifier)
             .query_async::<_, ()>(&mut *conn)
             .await?;
-        // Also delete the matched pair payload to avoid stale "matched" views.
-        redis::cmd("DEL")
-            .arg(&key)
+        let pair_key = format!("intents:pair:{}:{}", intent.public_inputs.token_in, intent.public_inputs.token_out);
+        redis::cmd("SADD")
+            .arg(&pair_key)
+            .arg(&intent.nullifier)
             .query_async::<_, ()>(&mut *conn)
             .await?;
-        Ok(())
+
+        let mut nonce = redis::cmd("SET");
+        nonce
+            .arg(Self::nonce_index_key(&intent.public_inputs.user, intent.public_inputs.nonce))
+            .arg(&intent.nullifier)
+            .arg("NX");
+        if let Some(ttl) = ttl_seconds {
+            nonce.arg("EX").arg(ttl.max(1));
+        }
+        nonce.query_async::<_, ()>(&mut *conn).await?;
+        Ok(true)
+    }
+
+    /// Size of `intents:pending`, uncached (the stats cache may lag by `STATS_CACHE_TTL_MS`).
+    pub async fn pending_count(&self) -> Result<u64> {
+        let mut conn = self.connection.write().await;
+        let pending: u64 = redis::cmd("SCARD")
+            .arg("intents:pending")
+            .query_async(&mut *conn)
+            .await?;
+        Ok(pending)
     }
 
     /// Get solver statistics
@@ -462,15 +2534,688 @@ impl RedisStorage {
             .query_async(&mut *conn)
             .await?;
         
+        let issues: std::collections::BTreeMap<String, String> = redis::cmd("HGETALL")
+            .arg(PAIR_ISSUES_KEY)
+            .query_async(&mut *conn)
+            .await?;
+        let pair_issues = issues
+            .into_iter()
+            .filter_map(|(field, reason)| {
+                let (token_a, token_b) = field.split_once('/')?;
+                Some(PairIssue {
+                    token_a: token_a.to_string(),
+                    token_b: token_b.to_string(),
+                    reason,
+                })
+            })
+            .collect();
+
         Ok(SolverStats {
             pending_intents: pending as usize,
             matched_pairs: matched as usize,
+            pair_issues,
         })
     }
 }
 
-#[derive(Debug, Serialize)]
+/// Expiry of a key as `PTTL` reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyTtl {
+    Missing,
+    Persistent,
+    /// Remaining time in whole seconds, rounded up.
+    Seconds(u64),
+}
+
+impl KeyTtl {
+    fn from_pttl(pttl_ms: i64) -> Self {
+        match pttl_ms {
+            -2 => Self::Missing,
+            ms if ms < 0 => Self::Persistent,
+            ms => Self::Seconds((ms as u64 + 999) / 1000),
+        }
+    }
+
+    fn seconds(self) -> Option<u64> {
+        match self {
+            Self::Seconds(seconds) => Some(seconds),
+            Self::Missing | Self::Persistent => None,
+        }
+    }
+}
+
+/// Key patterns `export_state_page` scans, in export order: intents, matches, nonce reservations.
+const EXPORT_PATTERNS: [&str; 3] = ["intent:*", "matched:*", "nonce:*"];
+
+/// Where the next `export_state_page` starts: which of `EXPORT_PATTERNS` is being scanned and the
+/// SCAN cursor within it. The default starts a fresh export.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExportCursor {
+    stage: usize,
+    scan: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct SolverStats {
     pub pending_intents: usize,
     pub matched_pairs: usize,
+    /// Token pairs the matcher currently won't match, and why.
+    pub pair_issues: Vec<PairIssue>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PairIssue {
+    pub token_a: String,
+    pub token_b: String,
+    pub reason: String,
+}
+
+/// Hash field for a token pair, the same for either direction.
+fn pair_issue_field(token_a: &str, token_b: &str) -> String {
+    let (a, b) = (token_a.to_lowercase(), token_b.to_lowercase());
+    if a <= b {
+        format!("{}/{}", a, b)
+    } else {
+        format!("{}/{}", b, a)
+    }
+}
+
+/// Short-lived `SolverStats` shared by the health and stats endpoints. Refreshes happen under one
+/// lock, so a burst of polls arriving on an expired entry triggers a single Redis round-trip.
+pub struct StatsCache {
+    ttl: std::time::Duration,
+    entry: tokio::sync::Mutex<Option<(std::time::Instant, SolverStats)>>,
+}
+
+impl StatsCache {
+    /// A zero `ttl` disables caching.
+    pub fn new(ttl: std::time::Duration) -> Self {
+        Self {
+            ttl,
+            entry: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    pub async fn get(&self, storage: &RedisStorage) -> Result<SolverStats> {
+        self.get_or_refresh(|| storage.get_stats()).await
+    }
+
+    async fn get_or_refresh<F, Fut>(&self, refresh: F) -> Result<SolverStats>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<SolverStats>>,
+    {
+        if self.ttl.is_zero() {
+            return refresh().await;
+        }
+        let mut entry = self.entry.lock().await;
+        if let Some((at, stats)) = entry.as_ref() {
+            if at.elapsed() < self.ttl {
+                return Ok(stats.clone());
+            }
+        }
+        let stats = refresh().await?;
+        *entry = Some((std::time::Instant::now(), stats.clone()));
+        Ok(stats)
+    }
+}
+
+/// The scratch Redis that Redis-backed tests run against (`REDIS_URL`, by default DB 15 of a local
+/// server). They are `#[ignore]`d so a plain `cargo test` needs no server; CI runs them with
+/// `--include-ignored` against a Redis service.
+#[cfg(test)]
+pub(crate) mod scratch {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Storage on the scratch Redis that removes what the test stored there once it is dropped,
+    /// whether the test passed or not: every intent handed out or tracked, with its match, and
+    /// every tracked key.
+    pub struct Scratch {
+        pub storage: Arc<RedisStorage>,
+        url: String,
+        configure: fn(RedisStorage) -> RedisStorage,
+        intents: Mutex<Vec<Intent>>,
+        matches: Mutex<Vec<(String, [String; 2])>>,
+        keys: Mutex<Vec<String>>,
+    }
+
+    pub async fn scratch_storage() -> Scratch {
+        scratch_storage_with(|storage| storage).await
+    }
+
+    /// `scratch_storage` with `configure` applied, e.g. to turn on encryption.
+    pub async fn scratch_storage_with(configure: fn(RedisStorage) -> RedisStorage) -> Scratch {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379/15".to_string());
+        let storage = configure(RedisStorage::new(&url).await.expect("redis"));
+        Scratch {
+            storage: Arc::new(storage),
+            url,
+            configure,
+            intents: Mutex::default(),
+            matches: Mutex::default(),
+            keys: Mutex::default(),
+        }
+    }
+
+    impl Scratch {
+        /// Intent `index` of the sample fixture under a fresh nullifier, so tests sharing the
+        /// database never collide, with its times moved to now.
+        pub fn fixture_intent(&self, index: usize) -> Intent {
+            let mut intents =
+                crate::fixture::parse_fixture(include_str!("../fixtures/matching_sample.json")).expect("fixture");
+            crate::fixture::rebase_times(&mut intents, chrono::Utc::now());
+            let mut intent = intents.swap_remove(index);
+            intent.nullifier = format!("0x{}", uuid::Uuid::new_v4().simple());
+            self.track_intents(std::slice::from_ref(&intent));
+            intent
+        }
+
+        pub fn track_intents(&self, intents: &[Intent]) {
+            self.intents.lock().expect("scratch").extend_from_slice(intents);
+        }
+
+        /// For matches the index no longer leads to once they settle.
+        pub fn track_match(&self, pair: &MatchedPair) {
+            let nullifiers = [pair.intent_a.nullifier.clone(), pair.intent_b.nullifier.clone()];
+            self.matches.lock().expect("scratch").push((pair.id.clone(), nullifiers));
+        }
+
+        pub fn track_key(&self, key: impl Into<String>) {
+            self.keys.lock().expect("scratch").push(key.into());
+        }
+    }
+
+    impl std::ops::Deref for Scratch {
+        type Target = RedisStorage;
+
+        fn deref(&self) -> &RedisStorage {
+            &self.storage
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            fn take<T>(tracked: &mut Mutex<Vec<T>>) -> Vec<T> {
+                std::mem::take(tracked.get_mut().unwrap_or_else(|e| e.into_inner()))
+            }
+            let (url, configure) = (self.url.clone(), self.configure);
+            let (intents, matches, keys) = (take(&mut self.intents), take(&mut self.matches), take(&mut self.keys));
+            // The test's runtime may be unwinding, so clean up on a runtime of our own.
+            let cleanup = std::thread::spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+                runtime.block_on(async move {
+                    let storage = configure(RedisStorage::new(&url).await?);
+                    for intent in &intents {
+                        let tracked = matches.iter().find(|(_, n)| n.contains(&intent.nullifier));
+                        let match_id = match tracked {
+                            Some((id, _)) => Some(id.clone()),
+                            None => storage.get_match_id_for_nullifier(&intent.nullifier).await?,
+                        };
+                        storage.purge_intent(intent, match_id.as_deref()).await?;
+                    }
+                    let mut conn = storage.connection.write().await;
+                    for key in &keys {
+                        redis::cmd("DEL").arg(key).query_async::<_, ()>(&mut *conn).await?;
+                    }
+                    anyhow::Ok(())
+                })
+            });
+            if let Ok(Err(e)) = cleanup.join() {
+                eprintln!("scratch Redis cleanup failed: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::scratch::{scratch_storage, scratch_storage_with};
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn canonical_user_ignores_padding_and_case_only() {
+        let user = RedisStorage::canonical_user("0x0ABC");
+        assert_eq!(user, RedisStorage::canonical_user(" 0x00abc "));
+        assert_ne!(user, RedisStorage::canonical_user("0xabd"));
+        assert_eq!(RedisStorage::submit_slot_key("0xABC"), RedisStorage::submit_slot_key("0x0abc"));
+        assert_eq!(RedisStorage::submit_lock_key("0xABC"), RedisStorage::submit_lock_key("0x0abc"));
+        assert_eq!(RedisStorage::user_index_key("0xABC"), "intents:user:0xabc");
+    }
+
+    #[test]
+    fn settlement_feed_events_carry_the_match_and_its_outcome() {
+        use crate::models::SettlementData;
+
+        let intents = crate::fixture::parse_fixture(include_str!("../fixtures/matching_sample.json")).expect("fixture");
+        let data = SettlementData { ekubo_pool: "0x0".to_string(), sqrt_price_limit: "0".to_string(), surplus_distribution: None };
+        let pair = MatchedPair::new(intents[0].clone(), intents[1].clone(), data);
+        let timestamp = chrono::DateTime::parse_from_rfc3339("2026-01-02T03:04:05Z").unwrap().with_timezone(&chrono::Utc);
+        let expected = |outcome: serde_json::Value| {
+            let mut payload = serde_json::json!({
+                "match_id": pair.id,
+                "nullifier_a": pair.intent_a.nullifier,
+                "nullifier_b": pair.intent_b.nullifier,
+                "token_a": pair.intent_a.public_inputs.token_in,
+                "token_b": pair.intent_a.public_inputs.token_out,
+                // 1 ETH and 2100 USDC, in base units.
+                "amount_a": "1000000000000000000",
+                "amount_b": "2100000000",
+                "timestamp": "2026-01-02T03:04:05Z"
+            });
+            payload.as_object_mut().unwrap().extend(outcome.as_object().unwrap().clone());
+            payload
+        };
+
+        let settled = SettlementFeedEvent::Settled {
+            pair: SettlementFeedMatch::of(&pair),
+            transaction_hash: "0x5e1f".to_string(),
+            timestamp,
+        };
+        assert_eq!(
+            serde_json::to_value(&settled).unwrap(),
+            expected(serde_json::json!({ "event": "settled", "transaction_hash": "0x5e1f" }))
+        );
+        let failed = SettlementFeedEvent::Failed {
+            pair: SettlementFeedMatch::of(&pair),
+            reason: "TX_REVERTED: Invalid proofs".to_string(),
+            timestamp,
+        };
+        assert_eq!(
+            serde_json::to_value(&failed).unwrap(),
+            expected(serde_json::json!({ "event": "failed", "reason": "TX_REVERTED: Invalid proofs" }))
+        );
+    }
+
+    #[test]
+    fn sealed_payloads_round_trip_and_stay_bound_to_their_key() {
+        type Json = serde_json::Value;
+        let cipher = StorageCipher::from_hex(&"11".repeat(32)).expect("key");
+        let payload = serde_json::json!({ "status": "pending" });
+        let sealed = encode_payload(Some(&cipher), false, "intent:0x1", &payload).expect("seal");
+        assert!(sealed.starts_with(SEALED_PREFIX));
+        assert!(!sealed.contains("pending"));
+
+        let opened: Json = decode_payload(Some(&cipher), "intent:0x1", &sealed).expect("open");
+        assert_eq!(opened, payload);
+        assert!(decode_payload::<Json>(Some(&cipher), "intent:0x2", &sealed).is_err());
+        assert!(decode_payload::<Json>(None, "intent:0x1", &sealed).is_err());
+
+        let other = StorageCipher::from_hex(&"22".repeat(32)).expect("key");
+        assert!(decode_payload::<Json>(Some(&other), "intent:0x1", &sealed).is_err());
+    }
+
+    #[test]
+    fn plaintext_payloads_are_still_readable_with_encryption_on() {
+        let cipher = StorageCipher::from_hex(&format!("0x{}", "ab".repeat(32))).expect("key");
+        let payload = serde_json::json!({ "status": "matched" });
+        let plain = encode_payload(None, false, "intent:0x1", &payload).expect("json");
+        let read: serde_json::Value = decode_payload(Some(&cipher), "intent:0x1", &plain).expect("plaintext");
+        assert_eq!(read, payload);
+        assert!(StorageCipher::from_hex("abcd").is_err());
+        assert!(StorageCipher::from_hex("zz").is_err());
+    }
+
+    #[test]
+    fn compressed_payloads_round_trip_with_and_without_encryption() {
+        // Garaga-style Groth16 calldata: ~2,000 decimal felts, mostly 96-bit limbs.
+        let mut seed: u128 = 0x5eed;
+        let proof_data: Vec<String> = (0..2000)
+            .map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                ((seed >> 32) & ((1u128 << 96) - 1)).to_string()
+            })
+            .collect();
+        let payload = serde_json::json!({ "status": "pending", "proof_data": proof_data });
+        let plain = encode_payload(None, false, "intent:0x1", &payload).expect("json");
+
+        let compressed = encode_payload(None, true, "intent:0x1", &payload).expect("compress");
+        assert!(compressed.starts_with(COMPRESSED_PREFIX));
+        assert!(compressed.len() * 10 < plain.len() * 7, "{} vs {}", compressed.len(), plain.len());
+        let read: serde_json::Value = decode_payload(None, "intent:0x1", &compressed).expect("decompress");
+        assert_eq!(read, payload);
+
+        let cipher = StorageCipher::from_hex(&"33".repeat(32)).expect("key");
+        let sealed = encode_payload(Some(&cipher), true, "intent:0x1", &payload).expect("seal");
+        assert!(sealed.starts_with(SEALED_PREFIX));
+        assert!(sealed.len() < plain.len());
+        let opened: serde_json::Value = decode_payload(Some(&cipher), "intent:0x1", &sealed).expect("open");
+        assert_eq!(opened, payload);
+
+        // Tiny payloads that don't shrink are stored as plain JSON.
+        let small = serde_json::json!({ "a": 1 });
+        assert_eq!(encode_payload(None, true, "k", &small).expect("json"), r#"{"a":1}"#);
+    }
+
+    #[test]
+    fn pair_issue_field_ignores_direction_and_case() {
+        assert_eq!(pair_issue_field("0xB", "0xa"), "0xa/0xb");
+        assert_eq!(pair_issue_field("0xa", "0xB"), pair_issue_field("0xB", "0xa"));
+    }
+
+    fn stats(pending_intents: usize) -> SolverStats {
+        SolverStats {
+            pending_intents,
+            matched_pairs: 0,
+            pair_issues: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn rate_limit_rejects_the_request_past_the_limit() {
+        let storage = scratch_storage().await;
+        let client = format!("test:{}", uuid::Uuid::new_v4());
+        storage.track_key(format!("ratelimit:{}", client));
+        let (window, limit, start) = (60_000, 3, 1_700_000_000_000u64);
+
+        for n in 1..=limit {
+            let decision = storage.hit_rate_limit(&client, window, limit, start + u64::from(n)).await.expect("hit");
+            assert!(decision.allowed, "request {} is within the limit", n);
+            assert_eq!(decision.remaining, limit - n);
+        }
+        let rejected = storage.hit_rate_limit(&client, window, limit, start + 10).await.expect("hit");
+        assert!(!rejected.allowed, "request {} in the window is rejected", limit + 1);
+        assert_eq!((rejected.remaining, rejected.reset_at_ms), (0, start + 1 + window));
+        // Rejected requests don't count; once the first request leaves the window one more fits.
+        assert!(storage.hit_rate_limit(&client, window, limit, start + 1 + window).await.expect("hit").allowed);
+        assert!(!storage.hit_rate_limit(&client, window, limit, start + 2 + window).await.expect("hit").allowed);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn logged_out_tokens_are_denied_and_fresh_ones_are_not() {
+        let storage = scratch_storage().await;
+        let keyring = crate::auth::JwtKeyring::new("test-secret-test-secret-test-secret".to_string());
+        let now = chrono::Utc::now().timestamp() as u64;
+        let old = keyring.verify(&keyring.issue("user", 60).expect("issue"), now).expect("verify");
+        storage.revoke_token(&old.jti, 3600).await.expect("revoke");
+        storage.track_key(RedisStorage::revoked_token_key(&old.jti));
+        let fresh = keyring.verify(&keyring.issue("user", 60).expect("issue"), now).expect("verify");
+
+        assert!(storage.is_token_revoked(&old.jti).await.expect("check"));
+        assert!(!storage.is_token_revoked(&fresh.jti).await.expect("check"));
+        let mut conn = storage.connection.write().await;
+        let ttl = RedisStorage::key_ttl(&mut *conn, &RedisStorage::revoked_token_key(&old.jti)).await.expect("ttl");
+        assert!(matches!(ttl, KeyTtl::Seconds(s) if s <= 3600));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn refresh_tokens_stay_on_record_until_revoked() {
+        let storage = scratch_storage().await;
+        let keyring = crate::auth::JwtKeyring::new("test-secret-test-secret-test-secret".to_string());
+        let (_, claims) = keyring.issue_refresh("user", 60).expect("issue");
+        storage.store_refresh_token(&claims.jti, &claims.sub, 3600).await.expect("store");
+        storage.track_key(RedisStorage::refresh_token_key(&claims.jti));
+
+        assert_eq!(storage.get_refresh_token_subject(&claims.jti).await.expect("get").as_deref(), Some("user"));
+        assert!(storage.revoke_refresh_token(&claims.jti).await.expect("revoke"));
+        assert!(!storage.revoke_refresh_token(&claims.jti).await.expect("revoke"), "only revoked once");
+        assert_eq!(storage.get_refresh_token_subject(&claims.jti).await.expect("get"), None);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn settling_markers_last_until_the_match_is_retired() {
+        let storage = scratch_storage().await;
+        let match_id = uuid::Uuid::new_v4().to_string();
+        storage.track_key(RedisStorage::match_settling_key(&match_id));
+
+        let sending = SettlingMarker { started_at_unix: 1_700_000_000, tx_hash: None, trigger: SettlementTrigger::Manual };
+        storage.mark_match_settling(&match_id, &sending).await.expect("mark");
+        assert_eq!(storage.get_settling_marker(&match_id).await.expect("get"), Some(sending.clone()));
+        let sent = SettlingMarker {
+            tx_hash: Some("0x5a1e".to_string()),
+            ..sending
+        };
+        storage.mark_match_settling(&match_id, &sent).await.expect("mark");
+        assert_eq!(storage.get_settling_marker(&match_id).await.expect("get"), Some(sent));
+        assert!(storage.settling_match_ids().await.expect("ids").contains(&match_id));
+
+        storage.mark_match_settled(&match_id).await.expect("settle");
+        assert_eq!(storage.get_settling_marker(&match_id).await.expect("get"), None);
+        assert!(!storage.settling_match_ids().await.expect("ids").contains(&match_id));
+    }
+
+    #[tokio::test]
+    async fn stats_cache_shares_one_refresh_across_a_burst() {
+        let cache = Arc::new(StatsCache::new(Duration::from_secs(60)));
+        let refreshes = Arc::new(AtomicUsize::new(0));
+
+        let polls = (0..16).map(|_| {
+            let cache = cache.clone();
+            let refreshes = refreshes.clone();
+            tokio::spawn(async move {
+                cache
+                    .get_or_refresh(|| async move {
+                        refreshes.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        Ok(stats(7))
+                    })
+                    .await
+            })
+        });
+        for poll in futures::future::join_all(polls).await {
+            assert_eq!(poll.expect("join").expect("stats").pending_intents, 7);
+        }
+        assert_eq!(refreshes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn stats_cache_refreshes_after_ttl_and_when_disabled() {
+        let cache = StatsCache::new(Duration::from_millis(20));
+        assert_eq!(cache.get_or_refresh(|| async { Ok(stats(1)) }).await.expect("stats").pending_intents, 1);
+        assert_eq!(cache.get_or_refresh(|| async { Ok(stats(2)) }).await.expect("stats").pending_intents, 1);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(cache.get_or_refresh(|| async { Ok(stats(3)) }).await.expect("stats").pending_intents, 3);
+
+        let uncached = StatsCache::new(Duration::ZERO);
+        assert_eq!(uncached.get_or_refresh(|| async { Ok(stats(4)) }).await.expect("stats").pending_intents, 4);
+        assert_eq!(uncached.get_or_refresh(|| async { Ok(stats(5)) }).await.expect("stats").pending_intents, 5);
+    }
+
+    #[test]
+    fn pttl_maps_onto_key_ttl() {
+        assert_eq!(KeyTtl::from_pttl(-2), KeyTtl::Missing);
+        assert_eq!(KeyTtl::from_pttl(-1), KeyTtl::Persistent);
+        assert_eq!(KeyTtl::from_pttl(1), KeyTtl::Seconds(1));
+        assert_eq!(KeyTtl::from_pttl(60_000), KeyTtl::Seconds(60));
+        assert_eq!(KeyTtl::from_pttl(60_001), KeyTtl::Seconds(61));
+        assert_eq!(KeyTtl::Persistent.seconds(), None);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn overlapping_match_commits_only_let_one_through() {
+        let storage = scratch_storage().await;
+        let [shared, b, c] = [0, 0, 0].map(|index| storage.fixture_intent(index));
+        for intent in [&shared, &b, &c] {
+            storage.store_intent(intent).await.expect("store");
+        }
+        let settlement = || crate::models::SettlementData {
+            ekubo_pool: "0x0".to_string(),
+            sqrt_price_limit: "0x0".to_string(),
+            surplus_distribution: None,
+        };
+        let first = MatchedPair::new(shared.clone(), b.clone(), settlement());
+        let second = MatchedPair::new(shared.clone(), c.clone(), settlement());
+        storage.track_match(&first);
+        storage.track_match(&second);
+
+        let (one, two) = tokio::join!(storage.try_commit_match(&first), storage.try_commit_match(&second));
+        let (one, two) = (one.expect("commit"), two.expect("commit"));
+        assert!(one != two, "exactly one overlapping commit must win");
+
+        let (winner, loser) = if one { (&first, &c) } else { (&second, &b) };
+        let stored = storage.get_intent(&shared.nullifier).await.expect("read").expect("shared");
+        assert_eq!(stored.status, IntentStatus::Matched);
+        assert_eq!(stored.matched_with.as_deref(), Some(winner.intent_b.nullifier.as_str()));
+        let untouched = storage.get_intent(&loser.nullifier).await.expect("read").expect("loser");
+        assert_eq!(untouched.status, IntentStatus::Pending);
+        assert!(storage.get_matched_pair(&winner.id).await.expect("read").is_some());
+        let lost = if one { &second } else { &first };
+        assert!(storage.get_matched_pair(&lost.id).await.expect("read").is_none());
+        assert_eq!(
+            storage.get_match_id_for_nullifier(&shared.nullifier).await.expect("read").as_deref(),
+            Some(winner.id.as_str())
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn paging_pending_intents_yields_each_once() {
+        let storage = scratch_storage().await;
+        // Past the listpack size, so SSCAN really pages instead of returning the set whole.
+        let mut stored = std::collections::HashSet::new();
+        for _ in 0..300 {
+            let intent = storage.fixture_intent(0);
+            storage.store_intent(&intent).await.expect("store");
+            stored.insert(intent.nullifier);
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        let (mut cursor, mut pages) = (0, 0);
+        loop {
+            let (intents, next) = storage.get_pending_intents_page(cursor, 50).await.expect("page");
+            for intent in intents {
+                assert!(seen.insert(intent.nullifier.clone()), "{} returned twice", intent.nullifier);
+            }
+            pages += 1;
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        assert!(pages > 1);
+        assert!(stored.is_subset(&seen), "{} stored intents not returned", stored.difference(&seen).count());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn the_trade_log_is_sealed_and_reads_back() {
+        use crate::models::SettlementData;
+
+        let storage = scratch_storage_with(|storage| {
+            storage.with_encryption(StorageCipher::from_hex(&"22".repeat(32)).expect("key"))
+        })
+        .await;
+        let data = SettlementData { ekubo_pool: "0x0".to_string(), sqrt_price_limit: "0".to_string(), surplus_distribution: None };
+        let pair = MatchedPair::new(storage.fixture_intent(0), storage.fixture_intent(1), data);
+        storage.track_match(&pair);
+
+        storage.store_settled_match(&pair, "0x5e1f", 10).await.expect("store");
+        let raw: String = {
+            let mut conn = storage.connection.write().await;
+            redis::cmd("LINDEX").arg(MATCH_HISTORY_KEY).arg(0).query_async(&mut *conn).await.expect("lindex")
+        };
+        assert!(raw.starts_with(SEALED_PREFIX), "trade log entries are encrypted");
+        let (entries, total) = storage.get_match_history(0, 1).await.expect("history");
+        assert!(total >= 1);
+        assert_eq!(entries[0].match_id, pair.id);
+        assert_eq!(entries[0].transaction_hash, "0x5e1f");
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn requeueing_returns_a_matched_intent_to_the_book_once() {
+        let storage = scratch_storage().await;
+        let mut intent = storage.fixture_intent(0);
+        intent.status = IntentStatus::Matched;
+        storage.store_intent(&intent).await.expect("store");
+
+        let until = chrono::Utc::now() + chrono::Duration::minutes(5);
+        assert!(storage.requeue_intent(&intent.nullifier, "0xcafe", until).await.expect("requeue"));
+        assert!(!storage.requeue_intent(&intent.nullifier, "0xcafe", until).await.expect("requeue"), "already pending");
+        let stored = storage.get_intent(&intent.nullifier).await.expect("get").expect("stored");
+        assert_eq!(stored.status, IntentStatus::Pending);
+        assert!(stored.failed_counterparties.contains_key("0xcafe"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn extensions_only_replace_the_intent_they_read() {
+        let storage = scratch_storage().await;
+        let mut intent = storage.fixture_intent(0);
+        storage.store_intent(&intent).await.expect("store");
+
+        let read_deadline = intent.public_inputs.deadline;
+        let mut extended = intent.clone();
+        extended.public_inputs.deadline = read_deadline + 3600;
+        extended.expires_at = intent.expires_at + chrono::Duration::hours(1);
+        assert!(!storage.store_extended_intent(&extended, read_deadline + 1).await.expect("extend"));
+        assert!(storage.store_extended_intent(&extended, read_deadline).await.expect("extend"));
+        assert!(!storage.store_extended_intent(&extended, read_deadline).await.expect("extend"), "already extended");
+        let stored = storage.get_intent(&intent.nullifier).await.expect("get").expect("stored");
+        assert_eq!(stored.public_inputs.deadline, read_deadline + 3600);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn sweep_marks_intents_past_their_deadline_expired() {
+        let storage = scratch_storage_with(|storage| storage.with_expired_retention(60)).await;
+        let mut intent = storage.fixture_intent(0);
+        intent.expires_at = chrono::Utc::now() - chrono::Duration::minutes(1);
+        storage.store_intent(&intent).await.expect("store");
+
+        let expired = storage.expire_pending_intents().await.expect("sweep");
+        assert!(expired.contains(&intent.nullifier));
+        let stored = storage.get_intent(&intent.nullifier).await.expect("read").expect("kept for retention");
+        assert_eq!(stored.status, IntentStatus::Expired);
+
+        let mut conn = storage.connection.write().await;
+        let ttl = RedisStorage::key_ttl(&mut *conn, &format!("intent:{}", intent.nullifier)).await.expect("ttl");
+        assert!(matches!(ttl, KeyTtl::Seconds(_)));
+        let pair_key = format!("intents:pair:{}:{}", intent.public_inputs.token_in, intent.public_inputs.token_out);
+        for index in ["intents:pending", pair_key.as_str()] {
+            let member: bool = redis::cmd("SISMEMBER")
+                .arg(index)
+                .arg(&intent.nullifier)
+                .query_async(&mut *conn)
+                .await
+                .expect("sismember");
+            assert!(!member, "still in {}", index);
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a scratch Redis at REDIS_URL"]
+    async fn purging_scrubs_what_was_recorded_about_the_intent() {
+        use crate::events::SolverEvent;
+        use crate::models::{SettlementData, SettlementOutcome};
+
+        let storage = scratch_storage().await;
+        let data = SettlementData { ekubo_pool: "0x0".to_string(), sqrt_price_limit: "0".to_string(), surplus_distribution: None };
+        let pair = MatchedPair::new(storage.fixture_intent(0), storage.fixture_intent(1), data);
+        storage.track_match(&pair);
+        let now_ms = chrono::Utc::now().timestamp_millis();
+
+        storage.store_settled_match(&pair, "0x5e1f", 10).await.expect("trade log");
+        let record = SettlementRecord::new(&pair, SettlementOutcome::Settled, SettlementTrigger::Auto, Some("0x5e1f".to_string()), None);
+        storage.record_settlement(&pair, &record, 0).await.expect("settlement");
+        let event = SolverEvent::MatchSettled {
+            match_id: pair.id.clone(),
+            transaction_hash: "0x5e1f".to_string(),
+            timestamp: chrono::Utc::now(),
+        };
+        storage
+            .record_event(now_ms, &EventRecord { users: Vec::new(), event }, 0)
+            .await
+            .expect("event");
+
+        let removed = storage.purge_intent(&pair.intent_a, None).await.expect("purge");
+        for key in [EVENT_LOG_KEY, SETTLEMENT_LOG_KEY, MATCH_HISTORY_KEY] {
+            assert!(removed.iter().any(|k| k == key), "nothing removed from {}", key);
+        }
+        assert!(storage.get_settlement(&pair.id).await.expect("get").is_none());
+        let (entries, _) = storage.get_match_history(0, 10).await.expect("history");
+        assert!(entries.iter().all(|e| e.match_id != pair.id));
+        let events = storage.get_events_page(now_ms, now_ms + 1, 0, 1000).await.expect("events");
+        assert!(events
+            .iter()
+            .filter_map(|(record, _)| record.as_ref())
+            .all(|r| r.event.match_id() != Some(pair.id.as_str())));
+    }
 }
@@ -1,5 +1,10 @@
 use sha3::{Digest, Keccak256};
 use hex;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// `LOG_REDACT`: set once at startup, read by every `log_address` / `log_nullifier`.
+static REDACT_LOGS: AtomicBool = AtomicBool::new(false);
 
 /// Hash data using Keccak256
 pub fn keccak256(data: &[u8]) -> [u8; 32] {
@@ -19,12 +24,56 @@ pub fn hex_to_bytes(hex: &str) -> Result<Vec<u8>, hex::FromHexError> {
     hex::decode(hex)
 }
 
-/// Truncate address for display
+/// Truncate address for display. Counts chars, not bytes, so a malformed address can't split one.
 pub fn truncate_address(address: &str) -> String {
-    if address.len() <= 10 {
+    let len = address.chars().count();
+    if len <= 10 {
         return address.to_string();
     }
-    format!("{}...{}", &address[..6], &address[address.len()-4..])
+    let head: String = address.chars().take(6).collect();
+    let tail: String = address.chars().skip(len - 4).collect();
+    format!("{}...{}", head, tail)
+}
+
+pub fn set_log_redaction(on: bool) {
+    REDACT_LOGS.store(on, Ordering::Relaxed);
+}
+
+/// A user address or nullifier as it goes into a log line: in full, or redacted under `LOG_REDACT`.
+/// Formatting is deferred, so filtered-out debug lines cost nothing.
+pub enum LogRedacted<'a> {
+    Address(&'a str),
+    Nullifier(&'a str),
+}
+
+pub fn log_address(address: &str) -> LogRedacted<'_> {
+    LogRedacted::Address(address)
+}
+
+pub fn log_nullifier(nullifier: &str) -> LogRedacted<'_> {
+    LogRedacted::Nullifier(nullifier)
+}
+
+impl LogRedacted<'_> {
+    /// Redacted, an address keeps its ends (`truncate_address`) and a nullifier becomes `nf:` and the
+    /// first 12 hex digits of the keccak256 of its trimmed, lowercased form: stable across log lines,
+    /// and recomputable from a known nullifier, but not the nullifier itself.
+    fn render(&self, redact: bool) -> String {
+        match (self, redact) {
+            (Self::Address(value), false) | (Self::Nullifier(value), false) => value.to_string(),
+            (Self::Address(address), true) => truncate_address(address),
+            (Self::Nullifier(nullifier), true) => {
+                let hash = keccak256(nullifier.trim().to_ascii_lowercase().as_bytes());
+                format!("nf:{}", hex::encode(&hash[..6]))
+            }
+        }
+    }
+}
+
+impl fmt::Display for LogRedacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(REDACT_LOGS.load(Ordering::Relaxed)))
+    }
 }
 
 /// Format amount with decimals
@@ -45,6 +94,26 @@ pub fn generate_id() -> String {
     format!("0x{}", hex::encode(bytes))
 }
 
+/// Match ids are UUIDs (`MatchedPair::new`) or a `0x`-prefixed hash of up to 64 hex digits.
+pub fn is_valid_match_id(id: &str) -> bool {
+    if let Some(hex) = id.strip_prefix("0x") {
+        return !hex.is_empty() && hex.len() <= 64 && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    uuid::Uuid::try_parse(id).is_ok()
+}
+
+/// Two Starknet addresses are the same account: compared as felts, so case and zero-padding don't
+/// matter. Unparseable input only equals itself.
+pub fn same_address(a: &str, b: &str) -> bool {
+    match (
+        starknet::core::types::Felt::from_hex(a.trim()),
+        starknet::core::types::Felt::from_hex(b.trim()),
+    ) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a.trim() == b.trim(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -71,5 +140,43 @@ mod tests {
         let addr = "0x1234567890abcdef1234567890abcdef12345678";
         let truncated = truncate_address(addr);
         assert_eq!(truncated, "0x1234...5678");
+
+        assert_eq!(truncate_address("0xé€ab€cd€ef€"), "0xé€ab...€ef€");
+        assert_eq!(truncate_address("ééééééééé"), "ééééééééé");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_log_redaction() {
+        let addr = "0x1234567890abcdef1234567890abcdef12345678";
+        assert_eq!(log_address(addr).render(false), addr);
+        assert_eq!(log_address(addr).render(true), "0x1234...5678");
+
+        let redacted = log_nullifier("0xABC123").render(true);
+        assert!(redacted.starts_with("nf:"));
+        assert_eq!(redacted.len(), 15);
+        assert_eq!(redacted, log_nullifier(" 0xabc123").render(true));
+        assert_ne!(redacted, log_nullifier("0xabc124").render(true));
+        assert_eq!(log_nullifier("0xABC123").render(false), "0xABC123");
+    }
+
+    #[test]
+    fn test_is_valid_match_id() {
+        assert!(is_valid_match_id("8f14e45f-ceea-467f-a8f9-0c7d6b3e5f21"));
+        assert!(is_valid_match_id("0x5f2a"));
+        assert!(is_valid_match_id(&format!("0x{}", "ab".repeat(32))));
+        assert!(!is_valid_match_id(&format!("0x{}", "ab".repeat(33))));
+        assert!(!is_valid_match_id("0x"));
+        assert!(!is_valid_match_id("0xzz"));
+        assert!(!is_valid_match_id("not-a-match"));
+        assert!(!is_valid_match_id(""));
+        assert!(!is_valid_match_id("../intent:abc"));
+    }
+
+    #[test]
+    fn test_same_address() {
+        assert!(same_address("0x0ABC", "0xabc"));
+        assert!(same_address(" 0x00abc", "0xabc"));
+        assert!(!same_address("0xabc", "0xabd"));
+        assert!(!same_address("not-an-address", "0xabc"));
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha3::Sha3_256;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use tracing::{debug, warn};
+
+use crate::models::SettlementOutput;
+
+type CallbackMac = Hmac<Sha3_256>;
+
+/// Attempts per callback; each retry waits twice as long as the one before, starting at a second.
+const CALLBACK_ATTEMPTS: u32 = 4;
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What an intent's `callback_url` receives about its settlement. `settlement_submitted` comes when
+/// the transaction is sent; `settlement_confirmed` once the node reports it `ACCEPTED_ON_L2` (or
+/// later) and succeeded, with the amounts read from the receipt. Only the latter is final.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SettlementCallback {
+    SettlementSubmitted {
+        nullifier: String,
+        match_id: String,
+        transaction_hash: String,
+        timestamp: DateTime<Utc>,
+    },
+    SettlementConfirmed {
+        nullifier: String,
+        match_id: String,
+        transaction_hash: String,
+        block_number: u64,
+        /// What this intent received, from the `Transfer` events in the receipt.
+        outputs: Vec<SettlementOutput>,
+        timestamp: DateTime<Utc>,
+    },
+}
+
+/// Posts `SettlementCallback`s, signed with `WEBHOOK_SECRET`: `X-StarkShield-Timestamp` carries unix
+/// seconds and `X-StarkShield-Signature` the hex HMAC-SHA3-256 of `"<timestamp>.<body>"`.
+#[derive(Clone)]
+pub struct WebhookNotifier {
+    secret: Vec<u8>,
+    http: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(secret: &str) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(CALLBACK_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .dns_resolver(Arc::new(PublicResolver))
+            .build()?;
+        Ok(Self { secret: secret.as_bytes().to_vec(), http })
+    }
+
+    pub fn sign(&self, timestamp: i64, body: &str) -> String {
+        let mut mac = CallbackMac::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(format!("{}.{}", timestamp, body).as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// Delivers `callback` to `url` in the background, retrying failures and non-2xx answers.
+    pub fn send(&self, url: String, callback: SettlementCallback) {
+        let notifier = self.clone();
+        tokio::spawn(async move {
+            // Literal addresses skip the resolver; intents stored before they were refused still get here.
+            if !is_valid_callback_url(&url) {
+                warn!("Not sending a settlement callback to non-public {}", url);
+                return;
+            }
+            let body = match serde_json::to_string(&callback) {
+                Ok(body) => body,
+                Err(e) => {
+                    warn!("Failed to serialize settlement callback for {}: {}", url, e);
+                    return;
+                }
+            };
+            let mut backoff = Duration::from_secs(1);
+            for attempt in 1..=CALLBACK_ATTEMPTS {
+                let timestamp = Utc::now().timestamp();
+                let result = notifier
+                    .http
+                    .post(&url)
+                    .header("content-type", "application/json")
+                    .header("x-starkshield-timestamp", timestamp.to_string())
+                    .header("x-starkshield-signature", notifier.sign(timestamp, &body))
+                    .body(body.clone())
+                    .send()
+                    .await;
+                match result {
+                    Ok(response) if response.status().is_success() => {
+                        debug!("Settlement callback delivered to {}", url);
+                        return;
+                    }
+                    Ok(response) => debug!("Settlement callback to {} answered {} (attempt {})", url, response.status(), attempt),
+                    Err(e) => debug!("Settlement callback to {} failed (attempt {}): {}", url, attempt, e),
+                }
+                if attempt < CALLBACK_ATTEMPTS {
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+            }
+            warn!("Giving up on settlement callback to {} after {} attempts", url, CALLBACK_ATTEMPTS);
+        });
+    }
+}
+
+/// Resolves callback hosts to public addresses only, so a name pointing at the solver's own network
+/// fails to connect instead of reaching it. Checked on every connection, after DNS.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Not loopback, private, link-local, unique-local, unspecified, broadcast or multicast.
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            !(v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_multicast()
+                // 100.64.0.0/10, carrier-grade NAT.
+                || (v4.octets()[0] == 100 && v4.octets()[1] & 0xc0 == 64))
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_public_ip(IpAddr::V4(v4)),
+            None => {
+                let first = v6.segments()[0];
+                !(v6.is_loopback()
+                    || v6.is_unspecified()
+                    || v6.is_multicast()
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// `url` can take callbacks: an absolute http(s) URL whose host isn't `localhost` or a literal
+/// non-public address. Names are checked again against what they resolve to when called.
+pub fn is_valid_callback_url(url: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) => is_public_ip(ip),
+        Err(_) => {
+            let host = host.trim_end_matches('.').to_ascii_lowercase();
+            host != "localhost" && !host.ends_with(".localhost")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callbacks_are_signed_over_timestamp_and_body() {
+        let notifier = WebhookNotifier::new("whsec-test").expect("notifier");
+        let body = r#"{"event":"settlement_confirmed"}"#;
+        let signature = notifier.sign(1_700_000_000, body);
+        assert_eq!(signature.len(), 64);
+        assert_eq!(signature, notifier.sign(1_700_000_000, body));
+        assert_ne!(signature, notifier.sign(1_700_000_001, body));
+        assert_ne!(signature, notifier.sign(1_700_000_000, r#"{"event":"settlement_submitted"}"#));
+        assert_ne!(signature, WebhookNotifier::new("other").expect("notifier").sign(1_700_000_000, body));
+
+        let mut mac = CallbackMac::new_from_slice(b"whsec-test").unwrap();
+        mac.update(format!("1700000000.{}", body).as_bytes());
+        assert!(mac.verify_slice(&hex::decode(&signature).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn callback_urls_must_be_absolute_http() {
+        assert!(is_valid_callback_url("https://example.com/hooks/starkshield"));
+        assert!(is_valid_callback_url("http://203.0.113.7:9000/cb"));
+        assert!(!is_valid_callback_url("ftp://example.com/cb"));
+        assert!(!is_valid_callback_url("/relative/path"));
+        assert!(!is_valid_callback_url(""));
+    }
+
+    #[test]
+    fn callback_urls_must_not_point_inside() {
+        for url in [
+            "http://localhost:9000/cb",
+            "http://api.localhost/cb",
+            "http://127.0.0.1/cb",
+            "http://10.0.0.5/cb",
+            "http://192.168.1.1/cb",
+            "http://169.254.169.254/latest/meta-data",
+            "http://0.0.0.0/cb",
+            "http://[::1]/cb",
+            "http://[fd00::1]/cb",
+            "http://[fe80::1]/cb",
+            "http://[::ffff:127.0.0.1]/cb",
+        ] {
+            assert!(!is_valid_callback_url(url), "{}", url);
+        }
+        assert!(is_public_ip("2606:4700::1111".parse().unwrap()));
+        assert!(!is_public_ip("100.100.0.1".parse().unwrap()));
+    }
+}